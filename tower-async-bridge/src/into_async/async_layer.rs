@@ -1,30 +1,27 @@
 use crate::{AsyncServiceWrapper, ClassicServiceWrapper};
 
-/// AsyncLayerExt adds a method to _any_ [`tower_layer::Layer`] that
-/// wraps it in an [AsyncLayer] so that it can be used within a [`tower_async_layer::Layer`] environment.
+/// AsyncLayerExt adds a method to wrap a [`tower_layer::Layer`] in a [AsyncLayer],
+/// so that it can be used within a [`tower_async_layer::Layer`] environment.
+///
+/// The method is named differently from [`AsyncServiceExt::into_async`](crate::AsyncServiceExt::into_async),
+/// as this trait is implemented for any type, which would make calls on services ambiguous.
 ///
 /// [`tower_layer::Layer`]: https://docs.rs/tower-layer/*/tower_layer/trait.Layer.html
 /// [`tower_async_layer::Layer`]: https://docs.rs/tower-async-layer/*/tower_async_layer/trait.Layer.html
-pub trait AsyncLayerExt<S>: tower_layer::Layer<S> {
+pub trait AsyncLayerExt: Sized {
     /// Wrap a [`tower_layer::Layer`],
     /// so that it can be used within a [`tower_async_layer::Layer`] environment.
     ///
     /// [`tower_layer::Layer`]: https://docs.rs/tower-layer/*/tower_layer/trait.Layer.html
     /// [`tower_async_layer::Layer`]: https://docs.rs/tower-async-layer/*/tower_async_layer/trait.Layer.html
-    fn into_async(self) -> AsyncLayer<Self, S>
-    where
-        Self: Sized,
-    {
+    fn into_async_layer(self) -> AsyncLayer<Self> {
         AsyncLayer::new(self)
     }
 }
 
-impl<L, S> AsyncLayerExt<S> for L where L: tower_layer::Layer<S> + Sized {}
+impl<L> AsyncLayerExt for L {}
 
-impl<L, S> From<L> for AsyncLayer<L, S>
-where
-    L: tower_layer::Layer<S>,
-{
+impl<L> From<L> for AsyncLayer<L> {
     fn from(inner: L) -> Self {
         Self::new(inner)
     }
@@ -32,16 +29,18 @@ where
 
 /// A wrapper around a [`tower_layer::Layer`] that implements
 /// [`tower_async_layer::Layer`] and is the type returned
-/// by [AsyncLayerExt::into_async].
+/// by [AsyncLayerExt::into_async_layer].
+///
+/// The wrapper is not tied to a specific service type: like the wrapped layer,
+/// it can be used to wrap services of different types, e.g. when cloned.
 ///
 /// [`tower_layer::Layer`]: https://docs.rs/tower-layer/*/tower_layer/trait.Layer.html
 /// [`tower_async_layer::Layer`]: https://docs.rs/tower-async-layer/*/tower_async_layer/trait.Layer.html
-pub struct AsyncLayer<L, S> {
+pub struct AsyncLayer<L> {
     inner: L,
-    _marker: std::marker::PhantomData<S>,
 }
 
-impl<L, S> std::fmt::Debug for AsyncLayer<L, S>
+impl<L> std::fmt::Debug for AsyncLayer<L>
 where
     L: std::fmt::Debug,
 {
@@ -52,29 +51,25 @@ where
     }
 }
 
-impl<L, S> Clone for AsyncLayer<L, S>
+impl<L> Clone for AsyncLayer<L>
 where
     L: Clone,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
-            _marker: std::marker::PhantomData,
         }
     }
 }
 
-impl<L, S> AsyncLayer<L, S> {
+impl<L> AsyncLayer<L> {
     /// Create a new [AsyncLayer] wrapping `inner`.
     pub fn new(inner: L) -> Self {
-        Self {
-            inner,
-            _marker: std::marker::PhantomData,
-        }
+        Self { inner }
     }
 }
 
-impl<L, S> tower_async_layer::Layer<S> for AsyncLayer<L, S>
+impl<L, S> tower_async_layer::Layer<S> for AsyncLayer<L>
 where
     L: tower_layer::Layer<ClassicServiceWrapper<S>>,
 {
@@ -216,10 +211,27 @@ mod tests {
     async fn test_async_layer_in_async_tower_builder() {
         let service = tower_async::ServiceBuilder::new()
             .timeout(std::time::Duration::from_millis(200))
-            .layer(DelayLayer::new(std::time::Duration::from_millis(100)).into_async())
+            .layer(DelayLayer::new(std::time::Duration::from_millis(100)).into_async_layer())
             .service(AsyncEchoService);
 
         let response = service.oneshot("hello").await.unwrap();
         assert_eq!(response, "hello");
     }
+
+    #[tokio::test]
+    async fn test_async_layer_reused_for_different_services() {
+        use tower_async::Layer;
+
+        let layer = DelayLayer::new(std::time::Duration::from_millis(10)).into_async_layer();
+
+        let echo = layer.layer(AsyncEchoService);
+        let response = echo.oneshot("hello").await.unwrap();
+        assert_eq!(response, "hello");
+
+        let len = layer.layer(tower_async::service_fn(|req: &'static str| async move {
+            Ok::<_, Infallible>(req.len())
+        }));
+        let response = len.oneshot("hello").await.unwrap();
+        assert_eq!(response, 5);
+    }
 }
@@ -1,30 +1,27 @@
 use crate::{AsyncServiceWrapper, ClassicServiceWrapper};
 
-/// ClassicLayerExt adds a method to _any_ [`tower_async_layer::Layer`] that
-/// wraps it in a [ClassicLayer] so that it can be used within a [`tower_layer::Layer`] environment.
+/// ClassicLayerExt adds a method to wrap a [`tower_async_layer::Layer`] in a [ClassicLayer],
+/// so that it can be used within a [`tower_layer::Layer`] environment.
+///
+/// The method is named differently from [`ClassicServiceExt::into_classic`](crate::ClassicServiceExt::into_classic),
+/// as this trait is implemented for any type, which would make calls on services ambiguous.
 ///
 /// [`tower_async_layer::Layer`]: https://docs.rs/tower-async-layer/*/tower_async_layer/trait.Layer.html
 /// [`tower_layer::Layer`]: https://docs.rs/tower-layer/*/tower_layer/trait.Layer.html
-pub trait ClassicLayerExt<S>: tower_async_layer::Layer<S> {
+pub trait ClassicLayerExt: Sized {
     /// Wrap a [`tower_async_layer::Layer`],
     /// so that it can be used within a [`tower_layer::Layer`] environment.
     ///
     /// [`tower_async_layer::Layer`]: https://docs.rs/tower-async-layer/*/tower_async_layer/trait.Layer.html
     /// [`tower_layer::Layer`]: https://docs.rs/tower-layer/*/tower_layer/trait.Layer.html
-    fn into_classic(self) -> ClassicLayer<Self, S>
-    where
-        Self: Sized,
-    {
+    fn into_classic_layer(self) -> ClassicLayer<Self> {
         ClassicLayer::new(self)
     }
 }
 
-impl<L, S> ClassicLayerExt<S> for L where L: tower_async_layer::Layer<S> + Sized {}
+impl<L> ClassicLayerExt for L {}
 
-impl<L, S> From<L> for ClassicLayer<L, S>
-where
-    L: tower_async_layer::Layer<S>,
-{
+impl<L> From<L> for ClassicLayer<L> {
     fn from(inner: L) -> Self {
         Self::new(inner)
     }
@@ -32,16 +29,18 @@ where
 
 /// A wrapper around a [`tower_layer::Layer`] that implements
 /// [`tower_async_layer::Layer`] and is the type returned
-/// by [ClassicLayerExt::into_classic].
+/// by [ClassicLayerExt::into_classic_layer].
+///
+/// The wrapper is not tied to a specific service type: like the wrapped layer,
+/// it can be used to wrap services of different types, e.g. when cloned.
 ///
 /// [`tower_layer::Layer`]: https://docs.rs/tower-layer/*/tower_layer/trait.Layer.html
 /// [`tower_async_layer::Layer`]: https://docs.rs/tower-async-layer/*/tower_async_layer/trait.Layer.html
-pub struct ClassicLayer<L, S> {
+pub struct ClassicLayer<L> {
     inner: L,
-    _marker: std::marker::PhantomData<S>,
 }
 
-impl<L, S> std::fmt::Debug for ClassicLayer<L, S>
+impl<L> std::fmt::Debug for ClassicLayer<L>
 where
     L: std::fmt::Debug,
 {
@@ -52,29 +51,25 @@ where
     }
 }
 
-impl<L, S> Clone for ClassicLayer<L, S>
+impl<L> Clone for ClassicLayer<L>
 where
     L: Clone,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
-            _marker: std::marker::PhantomData,
         }
     }
 }
 
-impl<L, S> ClassicLayer<L, S> {
+impl<L> ClassicLayer<L> {
     /// Create a new [ClassicLayer] wrapping `inner`.
     pub fn new(inner: L) -> Self {
-        Self {
-            inner,
-            _marker: std::marker::PhantomData,
-        }
+        Self { inner }
     }
 }
 
-impl<L, S> tower_layer::Layer<S> for ClassicLayer<L, S>
+impl<L, S> tower_layer::Layer<S> for ClassicLayer<L>
 where
     L: tower_async_layer::Layer<AsyncServiceWrapper<S>>,
 {
@@ -169,10 +164,27 @@ mod tests {
     async fn test_classic_layer_in_classic_tower_builder() {
         let service = tower::ServiceBuilder::new()
             .rate_limit(1, std::time::Duration::from_millis(200))
-            .layer(AsyncDelayLayer::new(std::time::Duration::from_millis(100)).into_classic())
+            .layer(AsyncDelayLayer::new(std::time::Duration::from_millis(100)).into_classic_layer())
             .service(EchoService);
 
         let response = service.oneshot("hello").await.unwrap();
         assert_eq!(response, "hello");
     }
+
+    #[tokio::test]
+    async fn test_classic_layer_reused_for_different_services() {
+        use tower::Layer;
+
+        let layer = AsyncDelayLayer::new(std::time::Duration::from_millis(10)).into_classic_layer();
+
+        let echo = layer.layer(EchoService);
+        let response = echo.oneshot("hello").await.unwrap();
+        assert_eq!(response, "hello");
+
+        let len = layer.layer(tower::service_fn(|req: &'static str| async move {
+            Ok::<_, Infallible>(req.len())
+        }));
+        let response = len.oneshot("hello").await.unwrap();
+        assert_eq!(response, 5);
+    }
 }
@@ -1,4 +1,8 @@
 /// Service returned by [crate::ClassicServiceExt::into_classic].
+///
+/// Its futures are only `Send` with the `nightly` feature enabled, as a `Send` bound
+/// on the future returned by the wrapped service requires the unstable
+/// `return_type_notation` feature.
 #[derive(Debug)]
 pub struct ClassicServiceWrapper<S> {
     inner: Option<S>,
@@ -22,15 +26,21 @@ where
     }
 }
 
+// Return type notation is feature gated by the parser, so the `Send` implementation
+// lives in a module which is only loaded with the `nightly` feature enabled.
+#[cfg(feature = "nightly")]
+mod nightly;
+
+#[cfg(not(feature = "nightly"))]
 impl<S, Request> tower_service::Service<Request> for ClassicServiceWrapper<S>
 where
-    S: tower_async_service::Service<Request, call(): Send> + Send + 'static,
-    Request: Send + 'static,
+    S: tower_async_service::Service<Request> + 'static,
+    Request: 'static,
 {
     type Response = S::Response;
     type Error = S::Error;
     type Future = std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>,
+        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + 'static>,
     >;
 
     #[inline]
@@ -5,9 +5,8 @@
     unreachable_pub
 )]
 #![forbid(unsafe_code)]
-#![allow(incomplete_features)]
-#![feature(associated_type_bounds)]
-#![feature(return_type_notation)]
+#![cfg_attr(feature = "nightly", allow(incomplete_features))]
+#![cfg_attr(feature = "nightly", feature(return_type_notation))]
 // `rustdoc::broken_intra_doc_links` is checked on CI
 
 //! Tower Async Bridge traits and extensions.
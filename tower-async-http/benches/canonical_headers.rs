@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use http::{header, HeaderMap, HeaderName, HeaderValue};
+use tower_async_http::canonical_headers::CanonicalHeaders;
+
+fn request_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::HOST, HeaderValue::from_static("example.com"));
+    headers.insert(header::USER_AGENT, HeaderValue::from_static("bench/1.0"));
+    headers.insert(
+        header::ACCEPT,
+        HeaderValue::from_static("text/html,  application/xhtml+xml, application/xml;q=0.9"),
+    );
+    headers.insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static("gzip, deflate, br"),
+    );
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("1024"));
+    headers.append(header::COOKIE, HeaderValue::from_static("session=abc"));
+    headers.append(header::COOKIE, HeaderValue::from_static("theme=dark"));
+    for i in 0..8 {
+        headers.insert(
+            HeaderName::try_from(format!("x-custom-{i}")).unwrap(),
+            HeaderValue::from_static("  some value\twith   whitespace "),
+        );
+    }
+    headers
+}
+
+fn canonicalize(c: &mut Criterion) {
+    let headers = request_headers();
+
+    let all = CanonicalHeaders::new();
+    c.bench_function("canonicalize all headers", |b| {
+        b.iter(|| all.canonicalize(black_box(&headers)))
+    });
+
+    let only = CanonicalHeaders::new().only([header::HOST, header::CONTENT_TYPE]);
+    c.bench_function("canonicalize signed headers", |b| {
+        b.iter(|| only.canonicalize(black_box(&headers)))
+    });
+}
+
+criterion_group!(benches, canonicalize);
+criterion_main!(benches);
@@ -77,7 +77,7 @@ fn app() -> Router {
     // Build route service
     Router::new()
         .route("/", get(hello_world))
-        .layer(middleware.into_classic())
+        .layer(middleware.into_classic_layer())
 }
 
 #[tokio::main]
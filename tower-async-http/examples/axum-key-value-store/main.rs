@@ -85,7 +85,7 @@ fn app() -> Router {
         HeaderValue::from_static("application/octet-stream"),
     );
 
-    let router_layer = middleware.into_classic();
+    let router_layer = middleware.into_classic_layer();
 
     // Build route service
     Router::new()
@@ -0,0 +1,73 @@
+use super::Reservation;
+use crate::BoxError;
+use bytes::Buf;
+use futures_util::ready;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Request body for [`Accounting`].
+    ///
+    /// Every data frame read from the body is reserved in the quota of the tenant,
+    /// resulting in a [`QuotaExceeded`] error once that quota is exceeded.
+    ///
+    /// [`Accounting`]: super::Accounting
+    /// [`QuotaExceeded`]: super::QuotaExceeded
+    pub struct AccountedBody<B> {
+        #[pin]
+        inner: B,
+        reservation: Reservation,
+    }
+}
+
+impl<B> AccountedBody<B> {
+    pub(crate) fn new(inner: B, reservation: Reservation) -> Self {
+        Self { inner, reservation }
+    }
+
+    /// Returns the [`Reservation`] the body is accounted to.
+    pub fn reservation(&self) -> &Reservation {
+        &self.reservation
+    }
+}
+
+impl<B> Body for AccountedBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => return Poll::Ready(None),
+        };
+
+        if let Some(data) = frame.data_ref() {
+            if let Err(err) = this.reservation.try_grow(data.remaining()) {
+                return Poll::Ready(Some(Err(err.into())));
+            }
+        }
+
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
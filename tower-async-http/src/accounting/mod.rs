@@ -0,0 +1,254 @@
+//! Middleware that accounts the memory used by requests against per-tenant quotas.
+//!
+//! A [`QuotaTracker`] keeps track of the memory used by every tenant, such as a customer
+//! of a multi-tenant gateway, and the quota each of them is allowed to use. The
+//! [`Accounting`] middleware resolves the tenant of every request and:
+//!
+//! - rejects requests with a `Content-Length` exceeding the remaining quota of the tenant
+//!   with a `413 Payload Too Large` response, without calling the inner service;
+//! - wraps the request body in an [`AccountedBody`], which reserves every data frame read
+//!   from it and fails with a [`QuotaExceeded`] error once the quota is exceeded;
+//! - inserts the [`Reservation`] of the request into its extensions, such that handlers can
+//!   account for other memory used for the request, such as cached responses, or degrade
+//!   gracefully if [`Reservation::try_grow`] fails.
+//!
+//! The memory reserved for a request is released once both the request has been handled
+//! and its body has been dropped. This way a single tenant uploading large bodies cannot
+//! exhaust the memory of the process for all other tenants.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::{BodyExt, Full};
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::accounting::{AccountedBody, AccountingLayer, QuotaTracker};
+//!
+//! async fn handle(req: Request<AccountedBody<Full<Bytes>>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     // fails if buffering the body exceeds the quota of the tenant
+//!     let body = req.into_body().collect().await?.to_bytes();
+//!     # Ok(Response::new(Full::new(body)))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! // Allow every tenant to buffer up to 10 MiB at once.
+//! let tracker = QuotaTracker::new(10 * 1024 * 1024);
+//! tracker.set_quota("free-tier".to_owned(), 1024 * 1024);
+//!
+//! let svc = ServiceBuilder::new()
+//!     .layer(AccountingLayer::new(tracker, |req: &Request<Full<Bytes>>| {
+//!         req.headers()
+//!             .get("x-tenant-id")
+//!             .and_then(|value| value.to_str().ok())
+//!             .unwrap_or("anonymous")
+//!             .to_owned()
+//!     }))
+//!     .service_fn(handle);
+//!
+//! let request = Request::builder()
+//!     .header("x-tenant-id", "free-tier")
+//!     .header("content-length", "2000000")
+//!     .body(Full::default())
+//!     .unwrap();
+//!
+//! let response = svc.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{Request, Response, StatusCode};
+use std::hash::Hash;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+mod body;
+mod tracker;
+
+pub use self::{
+    body::AccountedBody,
+    tracker::{QuotaExceeded, QuotaTracker, Reservation},
+};
+
+/// Layer that applies the [`Accounting`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct AccountingLayer<K, F> {
+    tracker: QuotaTracker<K>,
+    tenant: F,
+}
+
+impl<K, F> AccountingLayer<K, F> {
+    /// Create a new [`AccountingLayer`].
+    ///
+    /// `tenant` is called for every request to determine the tenant it is accounted to.
+    pub fn new(tracker: QuotaTracker<K>, tenant: F) -> Self {
+        Self { tracker, tenant }
+    }
+}
+
+impl<S, K, F> Layer<S> for AccountingLayer<K, F>
+where
+    F: Clone,
+{
+    type Service = Accounting<S, K, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Accounting {
+            inner,
+            tracker: self.tracker.clone(),
+            tenant: self.tenant.clone(),
+        }
+    }
+}
+
+/// Middleware that accounts the memory used by requests against per-tenant quotas.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Accounting<S, K, F> {
+    inner: S,
+    tracker: QuotaTracker<K>,
+    tenant: F,
+}
+
+impl<S, K, F> Accounting<S, K, F> {
+    /// Create a new [`Accounting`].
+    ///
+    /// `tenant` is called for every request to determine the tenant it is accounted to.
+    pub fn new(inner: S, tracker: QuotaTracker<K>, tenant: F) -> Self {
+        Self {
+            inner,
+            tracker,
+            tenant,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `Accounting` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(tracker: QuotaTracker<K>, tenant: F) -> AccountingLayer<K, F> {
+        AccountingLayer::new(tracker, tenant)
+    }
+}
+
+impl<S, K, F, ReqBody, ResBody> Service<Request<ReqBody>> for Accounting<S, K, F>
+where
+    S: Service<Request<AccountedBody<ReqBody>>, Response = Response<ResBody>>,
+    K: Hash + Eq,
+    F: Fn(&Request<ReqBody>) -> K,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let reservation = self.tracker.reserve((self.tenant)(&req));
+
+        let content_length = req
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+        if matches!(content_length, Some(len) if len > reservation.remaining()) {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+            return Ok(res);
+        }
+
+        req.extensions_mut().insert(reservation.clone());
+        let req = req.map(|body| AccountedBody::new(body, reservation));
+
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use crate::BoxError;
+    use http_body_util::BodyExt;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    fn tenant(req: &Request<Body>) -> String {
+        req.uri().path().trim_start_matches('/').to_owned()
+    }
+
+    async fn collect(req: Request<AccountedBody<Body>>) -> Result<Response<Body>, BoxError> {
+        let reservation = req.extensions().get::<Reservation>().unwrap().clone();
+        let body = req.into_body().collect().await?.to_bytes();
+        assert_eq!(reservation.reserved(), body.len());
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn request(tenant: &str, body: &'static str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/{}", tenant))
+            .body(Body::from_stream(futures_util::stream::iter(
+                body.split_inclusive(' ')
+                    .map(|chunk| Ok::<_, BoxError>(bytes::Bytes::from_static(chunk.as_bytes())))
+                    .collect::<Vec<_>>(),
+            )))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn body_within_quota() {
+        let tracker = QuotaTracker::new(16);
+        let svc = ServiceBuilder::new()
+            .layer(AccountingLayer::new(tracker.clone(), tenant))
+            .service(service_fn(collect));
+
+        svc.call(request("a", "hello world")).await.unwrap();
+        svc.call(request("a", "hello world")).await.unwrap();
+        assert_eq!(tracker.used(&"a".to_owned()), 0);
+    }
+
+    #[tokio::test]
+    async fn body_exceeding_quota() {
+        let tracker = QuotaTracker::new(8);
+        let svc = ServiceBuilder::new()
+            .layer(AccountingLayer::new(tracker.clone(), tenant))
+            .service(service_fn(collect));
+
+        let err = svc.call(request("a", "hello world")).await.unwrap_err();
+        assert!(err.is::<QuotaExceeded>());
+        assert_eq!(tracker.used(&"a".to_owned()), 0);
+    }
+
+    #[tokio::test]
+    async fn content_length_exceeding_remaining_quota() {
+        let tracker = QuotaTracker::new(8);
+        let svc = ServiceBuilder::new()
+            .layer(AccountingLayer::new(tracker.clone(), tenant))
+            .service(service_fn(|_: Request<AccountedBody<Body>>| async {
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }));
+
+        let held = tracker.reserve("a".to_owned());
+        held.try_grow(4).unwrap();
+
+        let mut req = Request::builder()
+            .uri("/a")
+            .header("content-length", "5")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // other tenants are not affected
+        req = Request::builder()
+            .uri("/b")
+            .header("content-length", "5")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
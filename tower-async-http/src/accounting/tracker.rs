@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The number of tenants tracked before idle tenants are pruned for the first time.
+const MIN_PRUNE_AT: usize = 64;
+
+/// Tracks the memory used per tenant against a quota.
+///
+/// Cloning a [`QuotaTracker`] is cheap and all clones share the same state,
+/// such that a single tracker can be used by multiple services and caches.
+///
+/// Tenants are forgotten once they are idle, that is once they have no reservations
+/// left and use the default quota, such that tracking many short-lived tenants doesn't
+/// grow the tracker without bound. Tenants with a quota set by [`QuotaTracker::set_quota`]
+/// are kept.
+///
+/// See the [module docs](super) for more details.
+pub struct QuotaTracker<K> {
+    inner: Arc<Inner<K>>,
+}
+
+struct Inner<K> {
+    default_quota: usize,
+    tenants: Mutex<Tenants<K>>,
+}
+
+struct Tenants<K> {
+    usage: HashMap<K, Arc<TenantUsage>>,
+    /// The number of tenants at which idle tenants are pruned next.
+    prune_at: usize,
+}
+
+struct TenantUsage {
+    quota: AtomicUsize,
+    /// Whether the quota was set for this tenant, rather than being the default.
+    custom_quota: AtomicBool,
+    used: AtomicUsize,
+}
+
+impl<K> QuotaTracker<K>
+where
+    K: Hash + Eq,
+{
+    /// Create a new [`QuotaTracker`] allowing every tenant to use up to `default_quota` bytes.
+    pub fn new(default_quota: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                default_quota,
+                tenants: Mutex::new(Tenants {
+                    usage: HashMap::new(),
+                    prune_at: MIN_PRUNE_AT,
+                }),
+            }),
+        }
+    }
+
+    /// Set the quota in bytes of a single tenant, overriding the default quota.
+    ///
+    /// Memory already in use by the tenant is not affected,
+    /// even if it exceeds the new quota.
+    pub fn set_quota(&self, tenant: K, quota: usize) {
+        let usage = self.tenant(tenant);
+        usage.quota.store(quota, Ordering::Release);
+        usage.custom_quota.store(true, Ordering::Release);
+    }
+
+    /// Returns the number of bytes currently in use by the tenant.
+    pub fn used(&self, tenant: &K) -> usize {
+        self.inner
+            .tenants
+            .lock()
+            .unwrap()
+            .usage
+            .get(tenant)
+            .map_or(0, |usage| usage.used.load(Ordering::Acquire))
+    }
+
+    /// Returns the number of bytes the tenant can still reserve.
+    pub fn remaining(&self, tenant: &K) -> usize {
+        match self.inner.tenants.lock().unwrap().usage.get(tenant) {
+            Some(usage) => usage.remaining(),
+            None => self.inner.default_quota,
+        }
+    }
+
+    /// Create an empty [`Reservation`] for the tenant.
+    ///
+    /// Memory is reserved by growing the reservation,
+    /// and released once the reservation is dropped.
+    pub fn reserve(&self, tenant: K) -> Reservation {
+        Reservation {
+            inner: Arc::new(ReservationInner {
+                usage: self.tenant(tenant),
+                reserved: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    fn tenant(&self, tenant: K) -> Arc<TenantUsage> {
+        let mut tenants = self.inner.tenants.lock().unwrap();
+        if let Some(usage) = tenants.usage.get(&tenant) {
+            return usage.clone();
+        }
+
+        // pruning once the number of tenants doubled keeps it amortized constant
+        if tenants.usage.len() >= tenants.prune_at {
+            tenants.usage.retain(|_, usage| !usage.is_idle());
+            tenants.prune_at = (tenants.usage.len() * 2).max(MIN_PRUNE_AT);
+        }
+
+        let usage = Arc::new(TenantUsage {
+            quota: AtomicUsize::new(self.inner.default_quota),
+            custom_quota: AtomicBool::new(false),
+            used: AtomicUsize::new(0),
+        });
+        tenants.usage.insert(tenant, usage.clone());
+        usage
+    }
+}
+
+impl<K> Clone for QuotaTracker<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for QuotaTracker<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuotaTracker")
+            .field("default_quota", &self.inner.default_quota)
+            .finish()
+    }
+}
+
+impl TenantUsage {
+    /// Returns `true` if the tenant can be forgotten, as it would be tracked the same way
+    /// again, must be called with the tenants locked.
+    fn is_idle(self: &Arc<Self>) -> bool {
+        // reservations are only created with the tenants locked, such that a tenant
+        // without reservations can't get one while it is pruned
+        Arc::strong_count(self) == 1
+            && !self.custom_quota.load(Ordering::Acquire)
+            && self.used.load(Ordering::Acquire) == 0
+    }
+
+    fn remaining(&self) -> usize {
+        self.quota
+            .load(Ordering::Acquire)
+            .saturating_sub(self.used.load(Ordering::Acquire))
+    }
+
+    fn try_grow(&self, bytes: usize) -> Result<(), QuotaExceeded> {
+        let quota = self.quota.load(Ordering::Acquire);
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes).filter(|used| *used <= quota)
+            })
+            .map(|_| ())
+            .map_err(|_| QuotaExceeded { _priv: () })
+    }
+}
+
+/// Memory reserved for a tenant in a [`QuotaTracker`].
+///
+/// Cloning a [`Reservation`] is cheap and all clones share the same reserved memory,
+/// which is released once all clones are dropped.
+///
+/// The [`Accounting`] middleware inserts the reservation of a request into
+/// its extensions, such that handlers can account for memory they use for it,
+/// for example to cache a response.
+///
+/// [`Accounting`]: super::Accounting
+#[derive(Clone)]
+pub struct Reservation {
+    inner: Arc<ReservationInner>,
+}
+
+struct ReservationInner {
+    usage: Arc<TenantUsage>,
+    reserved: AtomicUsize,
+}
+
+impl Reservation {
+    /// Reserve `bytes` more, failing if that would exceed the quota of the tenant.
+    pub fn try_grow(&self, bytes: usize) -> Result<(), QuotaExceeded> {
+        self.inner.usage.try_grow(bytes)?;
+        self.inner.reserved.fetch_add(bytes, Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Returns the number of bytes reserved.
+    pub fn reserved(&self) -> usize {
+        self.inner.reserved.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of bytes the tenant can still reserve.
+    pub fn remaining(&self) -> usize {
+        self.inner.usage.remaining()
+    }
+}
+
+impl Drop for ReservationInner {
+    fn drop(&mut self) {
+        self.usage
+            .used
+            .fetch_sub(*self.reserved.get_mut(), Ordering::AcqRel);
+    }
+}
+
+impl fmt::Debug for Reservation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reservation")
+            .field("reserved", &self.reserved())
+            .finish()
+    }
+}
+
+/// Error returned when a [`Reservation`] would exceed the quota of its tenant.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    _priv: (),
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory quota exceeded")
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_are_released_on_drop() {
+        let tracker = QuotaTracker::new(100);
+
+        let a = tracker.reserve("a");
+        a.try_grow(60).unwrap();
+        let a2 = a.clone();
+        a2.try_grow(30).unwrap();
+        assert!(a.try_grow(20).is_err());
+        assert_eq!(tracker.used(&"a"), 90);
+        assert_eq!(tracker.remaining(&"a"), 10);
+
+        // tenants do not share quota
+        tracker.reserve("b").try_grow(100).unwrap();
+
+        drop(a);
+        assert_eq!(tracker.used(&"a"), 90);
+        drop(a2);
+        assert_eq!(tracker.used(&"a"), 0);
+    }
+
+    #[test]
+    fn tenant_quota_overrides_default() {
+        let tracker = QuotaTracker::new(100);
+        tracker.set_quota("a", 10);
+
+        let a = tracker.reserve("a");
+        assert!(a.try_grow(11).is_err());
+        a.try_grow(10).unwrap();
+        assert_eq!(a.remaining(), 0);
+        assert_eq!(tracker.remaining(&"b"), 100);
+    }
+
+    #[test]
+    fn idle_tenants_are_pruned() {
+        let tracker = QuotaTracker::new(100);
+        tracker.set_quota(0, 10);
+        let reservation = tracker.reserve(1);
+        reservation.try_grow(1).unwrap();
+
+        for tenant in 2..1000 {
+            tracker.reserve(tenant).try_grow(50).unwrap();
+        }
+
+        let tenants = tracker.inner.tenants.lock().unwrap();
+        assert!(tenants.usage.len() <= 2 * MIN_PRUNE_AT);
+        // tenants with a custom quota or reservations are kept
+        assert!(tenants.usage.contains_key(&0));
+        assert!(tenants.usage.contains_key(&1));
+    }
+}
@@ -44,13 +44,57 @@
 //! // Call the service.
 //! let response = service
 
+//!     .call(Request::new(Full::<Bytes>::default()))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Values that differ per request can be computed from the request with [`AddExtensionWith`],
+//! while values that are expensive to construct can be initialized asynchronously on first use
+//! with [`AddExtensionLazy`].
+//!
+//! ```
+//! use tower_async_http::add_extension::{AddExtensionLazyLayer, AddExtensionWithLayer};
+//! use tower_async::{Service, ServiceBuilder};
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::{sync::Arc, convert::Infallible};
+//!
+//! # struct Config;
+//! # async fn load_config() -> Config { Config }
+//! #[derive(Clone)]
+//! struct RequestPath(String);
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let path = req.extensions().get::<RequestPath>().unwrap();
+//!     let config = req.extensions().get::<Arc<Config>>().unwrap();
+//!
+//!     Ok(Response::new(Full::<Bytes>::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut service = ServiceBuilder::new()
+//!     // Compute a value for every request.
+//!     .layer(AddExtensionWithLayer::new(|req: &Request<Full<Bytes>>| {
+//!         RequestPath(req.uri().path().to_owned())
+//!     }))
+//!     // Load the config on the first request and share it with all requests.
+//!     .layer(AddExtensionLazyLayer::new(|| async { Arc::new(load_config().await) }))
+//!     .service_fn(handle);
+//!
+//! let response = service
 //!     .call(Request::new(Full::<Bytes>::default()))
 //!     .await?;
 //! # Ok(())
 //! # }
 //! ```
 
+use async_lock::OnceCell;
 use http::{Request, Response};
+use std::{fmt, future::Future, sync::Arc};
 use tower_async_layer::Layer;
 use tower_async_service::Service;
 
@@ -126,6 +170,237 @@ where
     }
 }
 
+/// [`Layer`] for adding a value computed from each request to its [request extensions].
+///
+/// See the [module docs](crate::add_extension) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+#[derive(Clone, Copy)]
+pub struct AddExtensionWithLayer<F> {
+    f: F,
+}
+
+impl<F> AddExtensionWithLayer<F> {
+    /// Create a new [`AddExtensionWithLayer`].
+    ///
+    /// `f` is called with every request to compute the value to add.
+    pub fn new(f: F) -> Self {
+        AddExtensionWithLayer { f }
+    }
+}
+
+impl<F> fmt::Debug for AddExtensionWithLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddExtensionWithLayer")
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F> Layer<S> for AddExtensionWithLayer<F>
+where
+    F: Clone,
+{
+    type Service = AddExtensionWith<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AddExtensionWith {
+            inner,
+            f: self.f.clone(),
+        }
+    }
+}
+
+/// Middleware for adding a value computed from each request to its [request extensions].
+///
+/// See the [module docs](crate::add_extension) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+#[derive(Clone, Copy)]
+pub struct AddExtensionWith<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S, F> AddExtensionWith<S, F> {
+    /// Create a new [`AddExtensionWith`].
+    ///
+    /// `f` is called with every request to compute the value to add.
+    pub fn new(inner: S, f: F) -> Self {
+        Self { inner, f }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `AddExtensionWith` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(f: F) -> AddExtensionWithLayer<F> {
+        AddExtensionWithLayer::new(f)
+    }
+}
+
+impl<S, F> fmt::Debug for AddExtensionWith<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddExtensionWith")
+            .field("inner", &self.inner)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<ResBody, ReqBody, S, F, T> Service<Request<ReqBody>> for AddExtensionWith<S, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    F: Fn(&Request<ReqBody>) -> T,
+    T: Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let value = (self.f)(&req);
+        req.extensions_mut().insert(value);
+        self.inner.call(req).await
+    }
+}
+
+/// [`Layer`] for adding a lazily initialized value to [request extensions].
+///
+/// The value is initialized by the first request and shared by all services
+/// produced by this layer.
+///
+/// See the [module docs](crate::add_extension) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+pub struct AddExtensionLazyLayer<T, F> {
+    lazy: Arc<Lazy<T, F>>,
+}
+
+struct Lazy<T, F> {
+    value: OnceCell<T>,
+    init: F,
+}
+
+impl<T, F> AddExtensionLazyLayer<T, F> {
+    /// Create a new [`AddExtensionLazyLayer`].
+    ///
+    /// `init` is called once, by the first request, to initialize the value.
+    pub fn new(init: F) -> Self {
+        AddExtensionLazyLayer {
+            lazy: Arc::new(Lazy {
+                value: OnceCell::new(),
+                init,
+            }),
+        }
+    }
+}
+
+impl<T, F> Clone for AddExtensionLazyLayer<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            lazy: self.lazy.clone(),
+        }
+    }
+}
+
+impl<T, F> fmt::Debug for AddExtensionLazyLayer<T, F>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddExtensionLazyLayer")
+            .field("value", &self.lazy.value.get())
+            .field("init", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, T, F> Layer<S> for AddExtensionLazyLayer<T, F> {
+    type Service = AddExtensionLazy<S, T, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AddExtensionLazy {
+            inner,
+            lazy: self.lazy.clone(),
+        }
+    }
+}
+
+/// Middleware for adding a lazily initialized value to [request extensions].
+///
+/// See the [module docs](crate::add_extension) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+pub struct AddExtensionLazy<S, T, F> {
+    inner: S,
+    lazy: Arc<Lazy<T, F>>,
+}
+
+impl<S, T, F> AddExtensionLazy<S, T, F> {
+    /// Create a new [`AddExtensionLazy`].
+    ///
+    /// `init` is called once, by the first request, to initialize the value.
+    pub fn new(inner: S, init: F) -> Self {
+        AddExtensionLazyLayer::new(init).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `AddExtensionLazy` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(init: F) -> AddExtensionLazyLayer<T, F> {
+        AddExtensionLazyLayer::new(init)
+    }
+}
+
+impl<S, T, F> Clone for AddExtensionLazy<S, T, F>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            lazy: self.lazy.clone(),
+        }
+    }
+}
+
+impl<S, T, F> fmt::Debug for AddExtensionLazy<S, T, F>
+where
+    S: fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddExtensionLazy")
+            .field("inner", &self.inner)
+            .field("value", &self.lazy.value.get())
+            .field("init", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<ResBody, ReqBody, S, T, F, Fut> Service<Request<ReqBody>> for AddExtensionLazy<S, T, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    T: Clone + Send + Sync + 'static,
+    F: Fn() -> Fut,
+    Fut: Future<Output = T>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let value = self.lazy.value.get_or_init(&self.lazy.init).await;
+        req.extensions_mut().insert(value.clone());
+        self.inner.call(req).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
@@ -134,7 +409,13 @@ mod tests {
     use crate::test_helpers::Body;
 
     use http::Response;
-    use std::{convert::Infallible, sync::Arc};
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
     use tower_async::{service_fn, ServiceBuilder, ServiceExt};
 
     struct State(i32);
@@ -158,4 +439,45 @@ mod tests {
 
         assert_eq!(1, res);
     }
+
+    #[tokio::test]
+    async fn with() {
+        let svc = ServiceBuilder::new()
+            .layer(AddExtensionWithLayer::new(|req: &Request<Body>| {
+                Arc::new(State(req.uri().path().len() as i32))
+            }))
+            .service(service_fn(|req: Request<Body>| async move {
+                let state = req.extensions().get::<Arc<State>>().unwrap();
+                Ok::<_, Infallible>(Response::new(state.0))
+            }));
+
+        let req = Request::builder().uri("/foo").body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap().into_body();
+
+        assert_eq!(4, res);
+    }
+
+    #[tokio::test]
+    async fn lazy() {
+        let inits = Arc::new(AtomicUsize::new(0));
+
+        let svc = ServiceBuilder::new()
+            .layer(AddExtensionLazyLayer::new({
+                let inits = inits.clone();
+                move || {
+                    let inits = inits.clone();
+                    async move { Arc::new(State(inits.fetch_add(1, Ordering::SeqCst) as i32 + 1)) }
+                }
+            }))
+            .service(service_fn(|req: Request<Body>| async move {
+                let state = req.extensions().get::<Arc<State>>().unwrap();
+                Ok::<_, Infallible>(Response::new(state.0))
+            }));
+
+        for _ in 0..2 {
+            let res = svc.call(Request::new(Body::empty())).await.unwrap();
+            assert_eq!(1, res.into_body());
+        }
+        assert_eq!(1, inits.load(Ordering::SeqCst));
+    }
 }
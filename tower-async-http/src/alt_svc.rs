@@ -0,0 +1,225 @@
+//! Middleware that advertises alternative services, such as an HTTP/3 endpoint.
+//!
+//! The [`AltSvc`] middleware adds an [`Alt-Svc`] header to responses, telling clients that
+//! the same origin can be reached through other protocols or endpoints. It is typically used
+//! to advertise an HTTP/3 (QUIC) endpoint on responses served over TCP, after which clients
+//! that support HTTP/3 switch to it for subsequent requests.
+//!
+//! Responses which already have an `Alt-Svc` header are left unchanged, as are responses to
+//! requests received over HTTP/3, where advertising the endpoint again is pointless.
+//!
+//! [`Alt-Svc`]: https://www.rfc-editor.org/rfc/rfc7838
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::alt_svc::AltSvcLayer;
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(AltSvcLayer::new().h3(443).max_age(Duration::from_secs(24 * 60 * 60)))
+//!     .service_fn(handle);
+//!
+//! let res = svc.call(Request::new(Full::default())).await?;
+//! assert_eq!(res.headers()[header::ALT_SVC], "h3=\":443\"; ma=86400");
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderValue, Request, Response, Version};
+use std::time::Duration;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`AltSvc`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct AltSvcLayer {
+    alternatives: Vec<String>,
+    max_age: Option<Duration>,
+    header: Option<HeaderValue>,
+}
+
+impl AltSvcLayer {
+    /// Create a new [`AltSvcLayer`], without any alternative services.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`AltSvcLayer`] which tells clients to forget all alternative services
+    /// previously advertised for the origin.
+    pub fn clear() -> Self {
+        Self {
+            header: Some(HeaderValue::from_static("clear")),
+            ..Self::default()
+        }
+    }
+
+    /// Advertise an HTTP/3 endpoint on the same host, at the given UDP port.
+    pub fn h3(self, port: u16) -> Self {
+        self.alternative("h3", &format!(":{port}"))
+    }
+
+    /// Advertise an alternative service, with the given [ALPN protocol ID] and authority.
+    ///
+    /// The authority is a `host:port` pair, where the host may be omitted to use the host
+    /// of the origin, such as `:443` or `alt.example.com:8443`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the protocol ID or authority contain characters which aren't allowed in
+    /// a header value.
+    ///
+    /// [ALPN protocol ID]: https://www.iana.org/assignments/tls-extensiontype-values/tls-extensiontype-values.xhtml#alpn-protocol-ids
+    pub fn alternative(mut self, protocol: &str, authority: &str) -> Self {
+        self.alternatives
+            .push(format!("{protocol}=\"{authority}\""));
+        self.update_header();
+        self
+    }
+
+    /// Set how long clients may cache the advertised alternative services.
+    ///
+    /// Defaults to 24 hours, as defined by the specification.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self.update_header();
+        self
+    }
+
+    fn update_header(&mut self) {
+        if self.alternatives.is_empty() {
+            return;
+        }
+        let parameters = self
+            .max_age
+            .map(|max_age| format!("; ma={}", max_age.as_secs()))
+            .unwrap_or_default();
+        let value = self
+            .alternatives
+            .iter()
+            .map(|alternative| format!("{alternative}{parameters}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.header = Some(HeaderValue::try_from(value).expect("invalid Alt-Svc header value"));
+    }
+}
+
+impl<S> Layer<S> for AltSvcLayer {
+    type Service = AltSvc<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AltSvc {
+            inner,
+            header: self.header.clone(),
+        }
+    }
+}
+
+/// Middleware that advertises alternative services.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct AltSvc<S> {
+    inner: S,
+    header: Option<HeaderValue>,
+}
+
+impl<S> AltSvc<S> {
+    /// Create a new [`AltSvc`] advertising an HTTP/3 endpoint at the given UDP port.
+    pub fn h3(inner: S, port: u16) -> Self {
+        AltSvcLayer::new().h3(port).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `AltSvc` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> AltSvcLayer {
+        AltSvcLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AltSvc<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let is_h3 = req.version() == Version::HTTP_3;
+        let mut res = self.inner.call(req).await?;
+        if let Some(header) = self.header.as_ref().filter(|_| !is_h3) {
+            res.headers_mut()
+                .entry(header::ALT_SVC)
+                .or_insert_with(|| header.clone());
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::service_fn;
+
+    async fn ok(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    #[tokio::test]
+    async fn advertises_alternative_services() {
+        let svc = AltSvcLayer::new()
+            .h3(443)
+            .alternative("h2", "alt.example.com:8443")
+            .max_age(Duration::from_secs(60))
+            .layer(service_fn(ok));
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(
+            res.headers()[header::ALT_SVC],
+            "h3=\":443\"; ma=60, h2=\"alt.example.com:8443\"; ma=60"
+        );
+
+        let req = Request::builder()
+            .version(Version::HTTP_3)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert!(!res.headers().contains_key(header::ALT_SVC));
+
+        let svc = AltSvc::h3(
+            service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(header::ALT_SVC, "clear")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            }),
+            443,
+        );
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()[header::ALT_SVC], "clear");
+
+        let svc = AltSvcLayer::new().layer(service_fn(ok));
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert!(!res.headers().contains_key(header::ALT_SVC));
+    }
+}
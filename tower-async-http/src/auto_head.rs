@@ -0,0 +1,246 @@
+//! Middleware that answers `HEAD` requests using the `GET` handler of a service.
+//!
+//! A `HEAD` request must be answered with the same headers as a `GET` request of the same
+//! resource, without a body. The [`AutoHead`] middleware lets services only implement `GET`:
+//! it passes `HEAD` requests to the inner service as `GET` requests, and replaces the body
+//! of the response with an empty one, keeping its status and headers.
+//!
+//! The `Content-Length` header of the `GET` response is kept. If the inner service didn't
+//! set it, but the length of the body is known, it is added, as a server would have done
+//! for the `GET` response. It is never added to `1xx`, `204 No Content` and
+//! `304 Not Modified` responses, which don't have a body.
+//!
+//! Note that the inner service still produces the body of the `GET` response, which is
+//! dropped without being read. Services for which producing the body is expensive should
+//! rather handle `HEAD` requests themselves.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Method, Request, Response, StatusCode};
+//! use http_body::Body as _;
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::auto_head::AutoHeadLayer;
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let res = if req.method() == Method::GET {
+//!         Response::new(Full::from("hello"))
+//!     } else {
+//!         Response::builder()
+//!             .status(StatusCode::METHOD_NOT_ALLOWED)
+//!             .body(Full::default())
+//!             .unwrap()
+//!     };
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(AutoHeadLayer::new())
+//!     .service_fn(handle);
+//!
+//! let req = Request::head("/").body(Full::default())?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//! assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+//! assert!(res.body().is_end_stream());
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::Body;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`AutoHead`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoHeadLayer {
+    _priv: (),
+}
+
+impl AutoHeadLayer {
+    /// Create a new [`AutoHeadLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for AutoHeadLayer {
+    type Service = AutoHead<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AutoHead::new(inner)
+    }
+}
+
+/// Middleware that answers `HEAD` requests by calling the inner service with a `GET`
+/// request and dropping the body of its response.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct AutoHead<S> {
+    inner: S,
+}
+
+impl<S> AutoHead<S> {
+    /// Create a new [`AutoHead`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `AutoHead` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> AutoHeadLayer {
+        AutoHeadLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AutoHead<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body + Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if req.method() != Method::HEAD {
+            return self.inner.call(req).await;
+        }
+
+        *req.method_mut() = Method::GET;
+        let mut res = self.inner.call(req).await?;
+
+        // responses without a body must not get a length, see RFC 9110 section 8.6
+        let status = res.status();
+        let bodiless = status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED;
+        let length = res.body().size_hint().exact();
+        let headers = res.headers_mut();
+        if let Some(length) = length.filter(|_| !bodiless) {
+            if !headers.contains_key(header::TRANSFER_ENCODING) {
+                headers
+                    .entry(header::CONTENT_LENGTH)
+                    .or_insert_with(|| HeaderValue::from(length));
+            }
+        }
+        *res.body_mut() = ResBody::default();
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use bytes::Bytes;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let res = match req.method() {
+            &Method::GET => Response::builder()
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from("hello"))
+                .unwrap(),
+            _ => Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Body::empty())
+                .unwrap(),
+        };
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn answers_head_with_get_headers() {
+        let svc = ServiceBuilder::new()
+            .layer(AutoHeadLayer::new())
+            .service_fn(handle);
+
+        let req = Request::head("/").body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+        assert!(res
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .is_empty());
+
+        // other requests are passed through
+        let req = Request::post("/").body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "hello");
+    }
+
+    #[tokio::test]
+    async fn skips_content_length_of_bodiless_responses() {
+        let svc = ServiceBuilder::new()
+            .layer(AutoHeadLayer::new())
+            .service_fn(|req: Request<Body>| async move {
+                let status = match req.uri().path() {
+                    "/no-content" => StatusCode::NO_CONTENT,
+                    _ => StatusCode::NOT_MODIFIED,
+                };
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            });
+
+        for path in ["/no-content", "/not-modified"] {
+            let req = Request::head(path).body(Body::empty()).unwrap();
+            let res = svc.call(req).await.unwrap();
+            assert!(
+                res.headers().get(header::CONTENT_LENGTH).is_none(),
+                "{path}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_content_length_of_streams() {
+        let svc = ServiceBuilder::new()
+            .layer(AutoHeadLayer::new())
+            .service_fn(|_: Request<Body>| async {
+                let stream = futures_util::stream::iter([Ok::<_, Infallible>(Bytes::from("hi"))]);
+                let res = Response::builder()
+                    .header(header::CONTENT_LENGTH, "2")
+                    .body(Body::from_stream(stream))
+                    .unwrap();
+                Ok::<_, Infallible>(res)
+            });
+
+        let req = Request::head("/").body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "2");
+        assert!(res
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+//! Middleware that converts backpressure errors into responses with a `Retry-After` header.
+//!
+//! The [`Limit`] middleware fails requests it rejects with a [`LimitReached`] error, which
+//! would otherwise be surfaced as a generic error by the server. The [`Backpressure`] middleware
+//! converts these errors into `429 Too Many Requests` responses, and [`Overloaded`] errors
+//! returned by the [`LoadShed`] middleware or by the inner service itself into
+//! `503 Service Unavailable` responses. Both statuses can be changed.
+//!
+//! The `Retry-After` header of these responses is computed by a [`RetryAfter`] implementation,
+//! which typically inspects the internal state of the limiter, such that clients back off
+//! until the limiter is expected to accept requests again, instead of retrying right away.
+//! A [`RatePolicy`] computes the delay until its current period ends.
+//! [`Overloaded`] errors can carry their own delay, which takes precedence.
+//!
+//! All other errors are passed through unchanged.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{limit::policy::ConcurrentPolicy, Service, ServiceBuilder};
+//! use tower_async_http::backpressure::BackpressureLayer;
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     // Ask rejected clients to retry after 2 seconds.
+//!     .layer(BackpressureLayer::new(Duration::from_secs(2)))
+//!     .limit(ConcurrentPolicy::new(100))
+//!     .service_fn(handle);
+//!
+//! let res = svc.call(Request::new(Full::default())).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Limit`]: tower_async::limit::Limit
+//! [`LoadShed`]: tower_async::load_shed::LoadShed
+//! [`LimitReached`]: tower_async::limit::policy::LimitReached
+//! [`RatePolicy`]: tower_async::limit::policy::RatePolicy
+
+use crate::BoxError;
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use std::time::Duration;
+use tower_async::limit::policy::{LimitReached, RatePolicy};
+
+#[doc(no_inline)]
+pub use tower_async::load_shed::Overloaded;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Trait used by [`Backpressure`] to compute the `Retry-After` delay of rejected requests.
+///
+/// Returning `None` omits the header.
+pub trait RetryAfter {
+    /// Returns the time after which the request is expected to be accepted.
+    fn retry_after(&self) -> Option<Duration>;
+}
+
+impl RetryAfter for Duration {
+    fn retry_after(&self) -> Option<Duration> {
+        Some(*self)
+    }
+}
+
+impl RetryAfter for Option<Duration> {
+    fn retry_after(&self) -> Option<Duration> {
+        *self
+    }
+}
+
+/// Retry once the current period of the rate has ended, see [`RatePolicy::available_after`].
+impl RetryAfter for RatePolicy {
+    fn retry_after(&self) -> Option<Duration> {
+        self.available_after()
+    }
+}
+
+impl<F> RetryAfter for F
+where
+    F: Fn() -> Option<Duration>,
+{
+    fn retry_after(&self) -> Option<Duration> {
+        self()
+    }
+}
+
+/// Layer that applies the [`Backpressure`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct BackpressureLayer<R> {
+    retry_after: R,
+    limit_reached_status: StatusCode,
+    overloaded_status: StatusCode,
+}
+
+impl<R> BackpressureLayer<R> {
+    /// Creates a new [`BackpressureLayer`] computing the `Retry-After` delay with `retry_after`.
+    pub fn new(retry_after: R) -> Self {
+        Self {
+            retry_after,
+            limit_reached_status: StatusCode::TOO_MANY_REQUESTS,
+            overloaded_status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Set the status code of responses for requests rejected with [`LimitReached`].
+    ///
+    /// Defaults to `429 Too Many Requests`.
+    ///
+    /// [`LimitReached`]: tower_async::limit::policy::LimitReached
+    pub fn limit_reached_status(mut self, status: StatusCode) -> Self {
+        self.limit_reached_status = status;
+        self
+    }
+
+    /// Set the status code of responses for requests rejected with [`Overloaded`].
+    ///
+    /// Defaults to `503 Service Unavailable`.
+    pub fn overloaded_status(mut self, status: StatusCode) -> Self {
+        self.overloaded_status = status;
+        self
+    }
+}
+
+impl<S, R> Layer<S> for BackpressureLayer<R>
+where
+    R: Clone,
+{
+    type Service = Backpressure<S, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Backpressure {
+            inner,
+            retry_after: self.retry_after.clone(),
+            limit_reached_status: self.limit_reached_status,
+            overloaded_status: self.overloaded_status,
+        }
+    }
+}
+
+/// Middleware that converts backpressure errors into responses with a `Retry-After` header.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Backpressure<S, R> {
+    inner: S,
+    retry_after: R,
+    limit_reached_status: StatusCode,
+    overloaded_status: StatusCode,
+}
+
+impl<S, R> Backpressure<S, R> {
+    /// Creates a new [`Backpressure`] computing the `Retry-After` delay with `retry_after`.
+    pub fn new(inner: S, retry_after: R) -> Self {
+        Self {
+            inner,
+            retry_after,
+            limit_reached_status: StatusCode::TOO_MANY_REQUESTS,
+            overloaded_status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Backpressure` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(retry_after: R) -> BackpressureLayer<R> {
+        BackpressureLayer::new(retry_after)
+    }
+
+    /// Set the status code of responses for requests rejected with [`LimitReached`].
+    ///
+    /// Defaults to `429 Too Many Requests`.
+    ///
+    /// [`LimitReached`]: tower_async::limit::policy::LimitReached
+    pub fn limit_reached_status(mut self, status: StatusCode) -> Self {
+        self.limit_reached_status = status;
+        self
+    }
+
+    /// Set the status code of responses for requests rejected with [`Overloaded`].
+    ///
+    /// Defaults to `503 Service Unavailable`.
+    pub fn overloaded_status(mut self, status: StatusCode) -> Self {
+        self.overloaded_status = status;
+        self
+    }
+}
+
+impl<S, R, ReqBody, ResBody> Service<Request<ReqBody>> for Backpressure<S, R>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    R: RetryAfter,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let err = match self.inner.call(req).await {
+            Ok(res) => return Ok(res),
+            Err(err) => err.into(),
+        };
+
+        let (status, retry_after) = if let Some(overloaded) = err.downcast_ref::<Overloaded>() {
+            let retry_after = overloaded
+                .retry_after_hint()
+                .or_else(|| self.retry_after.retry_after());
+            (self.overloaded_status, retry_after)
+        } else if err.is::<LimitReached>() {
+            (self.limit_reached_status, self.retry_after.retry_after())
+        } else {
+            return Err(err);
+        };
+
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = status;
+        if let Some(retry_after) = retry_after {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, delay_seconds(retry_after));
+        }
+        Ok(res)
+    }
+}
+
+/// Encode `delay` as whole seconds, rounding up such that clients don't retry too early.
+fn delay_seconds(delay: Duration) -> HeaderValue {
+    let mut secs = delay.as_secs();
+    if delay.subsec_nanos() > 0 {
+        secs += 1;
+    }
+    HeaderValue::from(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+    use tower_async::{limit::policy::ConcurrentPolicy, service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn limit_reached_is_converted_into_response() {
+        let millis = Arc::new(AtomicU64::new(1500));
+
+        let svc = ServiceBuilder::new()
+            .layer(BackpressureLayer::new({
+                let millis = millis.clone();
+                move || Some(Duration::from_millis(millis.load(Ordering::SeqCst)))
+            }))
+            .limit(ConcurrentPolicy::new(0))
+            .service(service_fn(|_: Request<Body>| async {
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }));
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["retry-after"], "2");
+
+        millis.store(3000, Ordering::SeqCst);
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()["retry-after"], "3");
+    }
+
+    #[tokio::test]
+    async fn overloaded_delay_takes_precedence() {
+        let svc = Backpressure::new(
+            service_fn(|req: Request<Body>| async move {
+                match req.uri().path() {
+                    "/hint" => Err::<Response<Body>, _>(
+                        Overloaded::retry_after(Duration::from_secs(10)).into(),
+                    ),
+                    "/overloaded" => Err(Overloaded::new().into()),
+                    _ => Err(BoxError::from("boom")),
+                }
+            }),
+            None::<Duration>,
+        );
+
+        let res = svc
+            .call(Request::get("/hint").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()["retry-after"], "10");
+
+        let res = svc
+            .call(Request::get("/overloaded").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(res.headers().get("retry-after").is_none());
+
+        let err = svc
+            .call(Request::get("/other").body(Body::empty()).unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_after_end_of_rate_period() {
+        let rate = RatePolicy::new(1, Duration::from_secs(10));
+        let svc = ServiceBuilder::new()
+            .layer(
+                BackpressureLayer::new(rate.clone())
+                    .overloaded_status(StatusCode::TOO_MANY_REQUESTS),
+            )
+            .load_shed(rate)
+            .service(service_fn(|_: Request<Body>| async {
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }));
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        tokio::time::advance(Duration::from_millis(2500)).await;
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["retry-after"], "8");
+    }
+}
@@ -0,0 +1,113 @@
+//! Sizes of response bodies.
+//!
+//! The [`Trace`] and [`Metrics`] middleware count the bytes of response bodies as they are
+//! sent, and report their [`BodySize`] once the body has ended, including for streaming
+//! responses without a `Content-Length` header.
+//!
+//! If the response was compressed by the [`Compression`] middleware, which inserts an
+//! [`UncompressedSize`] extension into compressed responses, the size before compression
+//! is reported as well, from which the [compression ratio] is derived. This requires the
+//! [`Compression`] middleware to be wrapped by the middleware reporting the size.
+//!
+//! [`Trace`]: crate::trace::Trace
+//! [`Metrics`]: crate::metrics::Metrics
+//! [`Compression`]: crate::compression::Compression
+//! [compression ratio]: BodySize::compression_ratio
+
+#[cfg(any(feature = "trace", feature = "metrics"))]
+use http::{Extensions, Method, StatusCode};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// The size of a response body that has been sent.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodySize {
+    bytes: u64,
+    uncompressed_bytes: Option<u64>,
+}
+
+impl BodySize {
+    /// Returns the number of bytes that were sent.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of bytes before compression, if the body was compressed.
+    pub fn uncompressed_bytes(&self) -> Option<u64> {
+        self.uncompressed_bytes
+    }
+
+    /// Returns the ratio of the size before compression to the size that was sent,
+    /// if the body was compressed and not empty.
+    ///
+    /// A ratio of `4.0` means the body was compressed to a quarter of its size.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let uncompressed_bytes = self.uncompressed_bytes?;
+        (self.bytes > 0).then(|| uncompressed_bytes as f64 / self.bytes as f64)
+    }
+}
+
+/// Response extension counting the bytes of a response body before compression.
+///
+/// Inserted by the [`Compression`] middleware into compressed responses.
+///
+/// [`Compression`]: crate::compression::Compression
+#[derive(Debug, Clone, Default)]
+pub struct UncompressedSize {
+    bytes: Arc<AtomicU64>,
+}
+
+impl UncompressedSize {
+    /// Returns the number of bytes of the body read before compression so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn add(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Returns whether the response never sends a body, see RFC 9110 section 6.4.1,
+/// such that servers drop its body without polling it.
+#[cfg(any(feature = "trace", feature = "metrics"))]
+pub(crate) fn is_bodiless(method: &Method, status: StatusCode) -> bool {
+    method == Method::HEAD
+        || status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+}
+
+/// Counts the bytes of a response body.
+#[cfg(any(feature = "trace", feature = "metrics"))]
+#[derive(Debug, Clone)]
+pub(crate) struct BodySizeCounter {
+    bytes: u64,
+    uncompressed: Option<UncompressedSize>,
+}
+
+#[cfg(any(feature = "trace", feature = "metrics"))]
+impl BodySizeCounter {
+    pub(crate) fn new(extensions: &Extensions) -> Self {
+        Self {
+            bytes: 0,
+            uncompressed: extensions.get::<UncompressedSize>().cloned(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    pub(crate) fn size(&self) -> BodySize {
+        BodySize {
+            bytes: self.bytes,
+            uncompressed_bytes: self.uncompressed.as_ref().map(UncompressedSize::bytes),
+        }
+    }
+}
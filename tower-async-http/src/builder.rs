@@ -41,6 +41,52 @@ use tower_async_layer::Stack;
 /// # service.call(Request::new(Full::<Bytes>::default())).await.unwrap();
 /// # }
 /// ```
+///
+/// Stacks of middleware can be type erased with [`ServiceBuilder::into_box_layer`],
+/// for example to select one of them at runtime:
+///
+/// ```rust
+/// use http::{Request, Response, header::HeaderName};
+/// use http_body_util::Full;
+/// use bytes::Bytes;
+/// use std::time::Duration;
+/// use tower_async::{
+///     util::{BoxLayer, BoxService},
+///     BoxError, Layer, ServiceBuilder, ServiceExt, Service,
+/// };
+/// use tower_async_http::ServiceBuilderExt;
+///
+/// type Req = Request<Full<Bytes>>;
+/// type Res = Response<Full<Bytes>>;
+/// type Handler = BoxService<Req, Res, BoxError>;
+///
+/// fn middleware(internal: bool) -> BoxLayer<Handler, Req, Res, BoxError> {
+///     if internal {
+///         ServiceBuilder::new()
+///             .propagate_header(HeaderName::from_static("x-request-id"))
+///             .into_box_layer()
+///     } else {
+///         ServiceBuilder::new()
+///             .timeout(Duration::from_secs(30))
+///             .sensitive_request_headers([http::header::AUTHORIZATION].into())
+///             .into_box_layer()
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let handler = tower_async::service_fn(|_: Req| async {
+///     Ok::<_, BoxError>(Response::new(Full::<Bytes>::default()))
+/// });
+/// let service = middleware(false).layer(handler.into_dyn());
+/// # service.call(Request::new(Full::<Bytes>::default())).await.unwrap();
+/// # }
+/// ```
+///
+/// The futures of the erased services aren't `Send`, such that they can't be served with
+/// `tower-async-hyper`. With the `nightly` feature of `tower-async` enabled,
+/// `ServiceBuilder::into_box_send_layer` erases the stack into a `BoxSendLayer` instead,
+/// whose services can be served.
 #[cfg(feature = "util")]
 // ^ work around rustdoc not inferring doc(cfg)s for cfg's from surrounding scopes
 pub trait ServiceBuilderExt<L>: crate::sealed::Sealed<L> + Sized {
@@ -67,6 +113,17 @@ pub trait ServiceBuilderExt<L>: crate::sealed::Sealed<L> + Sized {
         value: T,
     ) -> ServiceBuilder<Stack<crate::add_extension::AddExtensionLayer<T>, L>>;
 
+    /// Lazily provide a per-process singleton to [request extensions].
+    ///
+    /// See [`tower_async_http::di`] for more details.
+    ///
+    /// [`tower_async_http::di`]: crate::di
+    /// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+    #[cfg(feature = "di")]
+    fn provide<T, P>(self, provider: P) -> ServiceBuilder<Stack<crate::di::ProvideLayer<T, P>, L>>
+    where
+        P: crate::di::Provide<T>;
+
     /// Apply a transformation to the request body.
     ///
     /// See [`tower_async_http::map_request_body`] for more details.
@@ -359,6 +416,27 @@ pub trait ServiceBuilderExt<L>: crate::sealed::Sealed<L> + Sized {
         limit: usize,
     ) -> ServiceBuilder<Stack<crate::limit::RequestBodyLimitLayer, L>>;
 
+    /// Emit a tracing event for requests still in flight after `threshold`.
+    ///
+    /// See [`tower_async_http::slow_request`] for more details.
+    ///
+    /// [`tower_async_http::slow_request`]: crate::slow_request
+    #[cfg(feature = "slow-request")]
+    fn slow_request(
+        self,
+        threshold: std::time::Duration,
+    ) -> ServiceBuilder<
+        Stack<crate::slow_request::SlowRequestLayer<crate::slow_request::DefaultOnSlowRequest>, L>,
+    >;
+
+    /// Record metrics about requests using the given recorder.
+    ///
+    /// See [`tower_async_http::metrics`] for more details.
+    ///
+    /// [`tower_async_http::metrics`]: crate::metrics
+    #[cfg(feature = "metrics")]
+    fn metrics<R>(self, recorder: R) -> ServiceBuilder<Stack<crate::metrics::MetricsLayer<R>, L>>;
+
     /// Remove trailing slashes from paths.
     ///
     /// See [`tower_async_http::normalize_path`] for more details.
@@ -389,6 +467,14 @@ impl<L> ServiceBuilderExt<L> for ServiceBuilder<L> {
         self.layer(crate::add_extension::AddExtensionLayer::new(value))
     }
 
+    #[cfg(feature = "di")]
+    fn provide<T, P>(self, provider: P) -> ServiceBuilder<Stack<crate::di::ProvideLayer<T, P>, L>>
+    where
+        P: crate::di::Provide<T>,
+    {
+        self.layer(crate::di::ProvideLayer::new(provider))
+    }
+
     #[cfg(feature = "map-request-body")]
     fn map_request_body<F>(
         self,
@@ -594,6 +680,24 @@ impl<L> ServiceBuilderExt<L> for ServiceBuilder<L> {
         self.layer(crate::limit::RequestBodyLimitLayer::new(limit))
     }
 
+    #[cfg(feature = "slow-request")]
+    fn slow_request(
+        self,
+        threshold: std::time::Duration,
+    ) -> ServiceBuilder<
+        Stack<crate::slow_request::SlowRequestLayer<crate::slow_request::DefaultOnSlowRequest>, L>,
+    > {
+        self.layer(crate::slow_request::SlowRequestLayer::new(
+            threshold,
+            crate::slow_request::DefaultOnSlowRequest::new(),
+        ))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics<R>(self, recorder: R) -> ServiceBuilder<Stack<crate::metrics::MetricsLayer<R>, L>> {
+        self.layer(crate::metrics::MetricsLayer::new(recorder))
+    }
+
     #[cfg(feature = "normalize-path")]
     fn trim_trailing_slash(
         self,
@@ -0,0 +1,235 @@
+//! Stable byte representation of request headers.
+//!
+//! Middleware that signs requests or fingerprints them, e.g. to detect duplicate requests
+//! or to key a cache, need a representation of the headers of a request which does not
+//! depend on the order in which the headers were inserted, nor on insignificant whitespace
+//! in their values. [`CanonicalHeaders`] computes such a representation, in the form:
+//!
+//! ```text
+//! name:value\n
+//! other-name:first value,second value\n
+//! ```
+//!
+//! - header names are lowercase, as [`HeaderName`]s always are, and sorted;
+//! - multiple values of the same header are joined with a `,`, in the order they were
+//!   inserted, as this order may be significant;
+//! - leading and trailing whitespace of values is removed, and sequences of spaces and tabs
+//!   within values are replaced by a single space.
+//!
+//! The representation is only computed when asked for, using [`CanonicalHeaders::canonicalize`]
+//! or [`CanonicalHeadersExt`], so it does not cost anything for requests that are neither
+//! signed nor fingerprinted.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, Request};
+//! use tower_async_http::canonical_headers::{CanonicalHeaders, CanonicalHeadersExt};
+//!
+//! let a = Request::builder()
+//!     .header("x-tenant", "acme")
+//!     .header(header::ACCEPT, "text/html,  application/json")
+//!     .body(())
+//!     .unwrap();
+//! let b = Request::builder()
+//!     .header(header::ACCEPT, " text/html, application/json ")
+//!     .header("X-Tenant", "acme")
+//!     .body(())
+//!     .unwrap();
+//!
+//! assert_eq!(a.canonical_headers(), b.canonical_headers());
+//! assert_eq!(
+//!     &a.canonical_headers()[..],
+//!     b"accept:text/html, application/json\nx-tenant:acme\n",
+//! );
+//!
+//! // Only include the headers that matter, e.g. for a cache key.
+//! let key = CanonicalHeaders::new()
+//!     .only([header::ACCEPT, header::ACCEPT_LANGUAGE])
+//!     .canonicalize(a.headers());
+//! assert_eq!(&key[..], b"accept:text/html, application/json\naccept-language:\n");
+//! ```
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{request::Parts, HeaderMap, HeaderName, Request};
+
+/// Computes the canonical representation of headers.
+///
+/// By default all headers are included. See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct CanonicalHeaders {
+    only: Option<Vec<HeaderName>>,
+    exclude: Vec<HeaderName>,
+}
+
+impl CanonicalHeaders {
+    /// Create a new [`CanonicalHeaders`] including all headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only include the given headers.
+    ///
+    /// Headers that are missing are included with an empty value, such that removing
+    /// a header changes the representation.
+    pub fn only<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        let mut names: Vec<_> = names.into_iter().collect();
+        names.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+        names.dedup();
+        self.only = Some(names);
+        self
+    }
+
+    /// Exclude the given header, e.g. a header the representation is stored in.
+    pub fn exclude(mut self, name: HeaderName) -> Self {
+        self.exclude.push(name);
+        self
+    }
+
+    /// Compute the canonical representation of `headers`.
+    pub fn canonicalize(&self, headers: &HeaderMap) -> Bytes {
+        let mut buf = BytesMut::new();
+        match &self.only {
+            Some(names) => {
+                for name in names {
+                    if !self.exclude.contains(name) {
+                        write_header(&mut buf, name, headers);
+                    }
+                }
+            }
+            None => {
+                let mut names: Vec<_> = headers
+                    .keys()
+                    .filter(|name| !self.exclude.contains(name))
+                    .collect();
+                names.sort_unstable_by(|a, b| a.as_str().cmp(b.as_str()));
+                for name in names {
+                    write_header(&mut buf, name, headers);
+                }
+            }
+        }
+        buf.freeze()
+    }
+}
+
+fn write_header(buf: &mut BytesMut, name: &HeaderName, headers: &HeaderMap) {
+    buf.put_slice(name.as_str().as_bytes());
+    buf.put_u8(b':');
+    for (i, value) in headers.get_all(name).iter().enumerate() {
+        if i > 0 {
+            buf.put_u8(b',');
+        }
+        write_value(buf, value.as_bytes());
+    }
+    buf.put_u8(b'\n');
+}
+
+fn write_value(buf: &mut BytesMut, value: &[u8]) {
+    let mut words = value
+        .split(|b| *b == b' ' || *b == b'\t')
+        .filter(|word| !word.is_empty());
+    if let Some(word) = words.next() {
+        buf.put_slice(word);
+        for word in words {
+            buf.put_u8(b' ');
+            buf.put_slice(word);
+        }
+    }
+}
+
+/// Extension trait to compute the canonical representation of the headers of a request.
+pub trait CanonicalHeadersExt: sealed::Sealed {
+    /// Returns the canonical representation of all headers,
+    /// see the [module docs](self) for the format.
+    fn canonical_headers(&self) -> Bytes;
+}
+
+impl<B> CanonicalHeadersExt for Request<B> {
+    fn canonical_headers(&self) -> Bytes {
+        CanonicalHeaders::new().canonicalize(self.headers())
+    }
+}
+
+impl CanonicalHeadersExt for Parts {
+    fn canonical_headers(&self) -> Bytes {
+        CanonicalHeaders::new().canonicalize(&self.headers)
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl<B> Sealed for http::Request<B> {}
+    impl Sealed for http::request::Parts {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use http::{header, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn independent_of_order_and_whitespace() {
+        let a = headers(&[
+            ("x-b", "\t two  words "),
+            ("x-a", "1"),
+            ("x-c", "first"),
+            ("x-c", "second"),
+        ]);
+        let b = headers(&[
+            ("x-c", "first"),
+            ("x-a", " 1"),
+            ("x-b", "two \t words"),
+            ("x-c", "second "),
+        ]);
+
+        let canonical = CanonicalHeaders::new().canonicalize(&a);
+        assert_eq!(&canonical[..], b"x-a:1\nx-b:two words\nx-c:first,second\n");
+        assert_eq!(canonical, CanonicalHeaders::new().canonicalize(&b));
+    }
+
+    #[test]
+    fn order_of_values_is_significant() {
+        let a = headers(&[("x-c", "first"), ("x-c", "second")]);
+        let b = headers(&[("x-c", "second"), ("x-c", "first")]);
+
+        assert_ne!(
+            CanonicalHeaders::new().canonicalize(&a),
+            CanonicalHeaders::new().canonicalize(&b)
+        );
+    }
+
+    #[test]
+    fn only_and_exclude() {
+        let headers = headers(&[
+            ("host", "example.com"),
+            ("x-signature", "abc"),
+            ("content-type", "text/plain"),
+            ("x-empty", "   "),
+        ]);
+
+        let canonical = CanonicalHeaders::new()
+            .exclude(HeaderName::from_static("x-signature"))
+            .canonicalize(&headers);
+        assert_eq!(
+            &canonical[..],
+            b"content-type:text/plain\nhost:example.com\nx-empty:\n"
+        );
+
+        let canonical = CanonicalHeaders::new()
+            .only([header::HOST, header::ACCEPT, header::HOST])
+            .canonicalize(&headers);
+        assert_eq!(&canonical[..], b"accept:\nhost:example.com\n");
+    }
+}
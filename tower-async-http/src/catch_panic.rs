@@ -83,10 +83,48 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # gRPC
+//!
+//! gRPC clients expect errors to be reported with a `grpc-status` rather than with an HTTP
+//! status code. The [`PanicProfile`] of the middleware selects how panics are reported:
+//! by default, [`CatchPanicLayer::new`] answers gRPC requests, i.e. requests with an
+//! `application/grpc` content type, with a trailers-only response with the status
+//! `INTERNAL`, and all other requests with a `500 Internal Server Error` response.
+//!
+//! ```rust
+//! use http::{header, Request, Response};
+//! use std::convert::Infallible;
+//! use tower_async::{Service, ServiceBuilder, BoxError};
+//! use tower_async_http::catch_panic::CatchPanicLayer;
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     panic!("something went wrong...")
+//! }
+//!
+//! let svc = ServiceBuilder::new()
+//!     .layer(CatchPanicLayer::new())
+//!     .service_fn(handle);
+//!
+//! let request = Request::post("/helloworld.Greeter/SayHello")
+//!     .header(header::CONTENT_TYPE, "application/grpc")
+//!     .body(Full::<Bytes>::default())?;
+//! let response = svc.call(request).await?;
+//!
+//! assert_eq!(response.status(), 200);
+//! assert_eq!(response.headers()["grpc-status"], "13");
+//! #
+//! # Ok(())
+//! # }
+//! ```
 
 use bytes::Bytes;
 use futures_util::future::FutureExt;
-use http::{HeaderValue, Request, Response, StatusCode};
+use http::{header, HeaderValue, Request, Response, StatusCode};
 use http_body::Body;
 use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Full};
 use std::{any::Any, panic::AssertUnwindSafe};
@@ -102,24 +140,39 @@ use crate::BoxError;
 #[derive(Debug, Clone, Copy, Default)]
 pub struct CatchPanicLayer<T> {
     panic_handler: T,
+    profile: PanicProfile,
 }
 
 impl CatchPanicLayer<DefaultResponseForPanic> {
     /// Create a new `CatchPanicLayer` with the default panic handler.
+    ///
+    /// gRPC requests are detected automatically, see [`PanicProfile::Auto`].
     pub fn new() -> Self {
         CatchPanicLayer {
             panic_handler: DefaultResponseForPanic,
+            profile: PanicProfile::Auto,
         }
     }
 }
 
 impl<T> CatchPanicLayer<T> {
     /// Create a new `CatchPanicLayer` with a custom panic handler.
+    ///
+    /// The panic handler is used for all requests, see [`PanicProfile::Http`].
     pub fn custom(panic_handler: T) -> Self
     where
         T: ResponseForPanic,
     {
-        Self { panic_handler }
+        Self {
+            panic_handler,
+            profile: PanicProfile::Http,
+        }
+    }
+
+    /// Set the [`PanicProfile`] selecting how panics are reported.
+    pub fn profile(mut self, profile: PanicProfile) -> Self {
+        self.profile = profile;
+        self
     }
 }
 
@@ -133,6 +186,7 @@ where
         CatchPanic {
             inner,
             panic_handler: self.panic_handler.clone(),
+            profile: self.profile,
         }
     }
 }
@@ -144,14 +198,18 @@ where
 pub struct CatchPanic<S, T> {
     inner: S,
     panic_handler: T,
+    profile: PanicProfile,
 }
 
 impl<S> CatchPanic<S, DefaultResponseForPanic> {
     /// Create a new `CatchPanic` with the default panic handler.
+    ///
+    /// gRPC requests are detected automatically, see [`PanicProfile::Auto`].
     pub fn new(inner: S) -> Self {
         Self {
             inner,
             panic_handler: DefaultResponseForPanic,
+            profile: PanicProfile::Auto,
         }
     }
 }
@@ -160,6 +218,8 @@ impl<S, T> CatchPanic<S, T> {
     define_inner_service_accessors!();
 
     /// Create a new `CatchPanic` with a custom panic handler.
+    ///
+    /// The panic handler is used for all requests, see [`PanicProfile::Http`].
     pub fn custom(inner: S, panic_handler: T) -> Self
     where
         T: ResponseForPanic,
@@ -167,10 +227,104 @@ impl<S, T> CatchPanic<S, T> {
         Self {
             inner,
             panic_handler,
+            profile: PanicProfile::Http,
+        }
+    }
+
+    /// Set the [`PanicProfile`] selecting how panics are reported.
+    pub fn profile(mut self, profile: PanicProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    fn response_for_panic(
+        &self,
+        err: Box<dyn Any + Send + 'static>,
+        grpc: bool,
+    ) -> Response<UnsyncBoxBody<Bytes, BoxError>>
+    where
+        T: ResponseForPanic,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<BoxError>,
+    {
+        if grpc {
+            log_panic(&*err);
+            grpc_internal_response().map(|body| body.map_err(Into::into).boxed_unsync())
+        } else {
+            self.panic_handler
+                .response_for_panic(err)
+                .map(|body| body.map_err(Into::into).boxed_unsync())
+        }
+    }
+}
+
+/// Selects how [`CatchPanic`] reports panics to clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PanicProfile {
+    /// Answer gRPC requests, with an `application/grpc` content type, like [`PanicProfile::Grpc`]
+    /// and all other requests like [`PanicProfile::Http`].
+    ///
+    /// This is the default of [`CatchPanicLayer::new`].
+    #[default]
+    Auto,
+    /// Answer all requests with the response of the [`ResponseForPanic`] handler.
+    ///
+    /// This is the default of [`CatchPanicLayer::custom`].
+    Http,
+    /// Answer all requests with a trailers-only gRPC response, with a `200 OK` status,
+    /// an empty body and the `grpc-status` header set to `13` (`INTERNAL`).
+    ///
+    /// The [`ResponseForPanic`] handler is not used.
+    Grpc,
+}
+
+impl PanicProfile {
+    fn is_grpc<B>(&self, req: &Request<B>) -> bool {
+        match self {
+            PanicProfile::Auto => req
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|content_type| {
+                    content_type
+                        .strip_prefix("application/grpc")
+                        .is_some_and(|rest| {
+                            rest.is_empty() || rest.starts_with('+') || rest.starts_with(';')
+                        })
+                }),
+            PanicProfile::Http => false,
+            PanicProfile::Grpc => true,
         }
     }
 }
 
+fn grpc_internal_response() -> Response<Full<Bytes>> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const APPLICATION_GRPC: HeaderValue = HeaderValue::from_static("application/grpc");
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INTERNAL: HeaderValue = HeaderValue::from_static("13");
+    #[allow(clippy::declare_interior_mutable_const)]
+    const MESSAGE: HeaderValue = HeaderValue::from_static("Service%20panicked");
+
+    let mut res = Response::new(Full::default());
+    let headers = res.headers_mut();
+    headers.insert(header::CONTENT_TYPE, APPLICATION_GRPC);
+    headers.insert("grpc-status", INTERNAL);
+    headers.insert("grpc-message", MESSAGE);
+    res
+}
+
+fn log_panic(err: &(dyn Any + Send + 'static)) {
+    if let Some(s) = err.downcast_ref::<String>() {
+        tracing::error!("Service panicked: {}", s);
+    } else if let Some(s) = err.downcast_ref::<&str>() {
+        tracing::error!("Service panicked: {}", s);
+    } else {
+        tracing::error!("Service panicked but `CatchPanic` was unable to downcast the panic info");
+    };
+}
+
 impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for CatchPanic<S, T>
 where
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
@@ -184,24 +338,17 @@ where
     type Error = S::Error;
 
     async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let grpc = self.profile.is_grpc(&req);
         let future = match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(req))) {
             Ok(future) => future,
-            Err(panic_err) => {
-                return Ok(self
-                    .panic_handler
-                    .response_for_panic(panic_err)
-                    .map(|body| body.map_err(Into::into).boxed_unsync()))
-            }
+            Err(panic_err) => return Ok(self.response_for_panic(panic_err, grpc)),
         };
         match AssertUnwindSafe(future).catch_unwind().await {
             Ok(res) => match res {
                 Ok(res) => Ok(res.map(|body| body.map_err(Into::into).boxed_unsync())),
                 Err(err) => Err(err),
             },
-            Err(panic_err) => Ok(self
-                .panic_handler
-                .response_for_panic(panic_err)
-                .map(|body| body.map_err(Into::into).boxed_unsync())),
+            Err(panic_err) => Ok(self.response_for_panic(panic_err, grpc)),
         }
     }
 }
@@ -247,23 +394,14 @@ impl ResponseForPanic for DefaultResponseForPanic {
         &self,
         err: Box<dyn Any + Send + 'static>,
     ) -> Response<Self::ResponseBody> {
-        if let Some(s) = err.downcast_ref::<String>() {
-            tracing::error!("Service panicked: {}", s);
-        } else if let Some(s) = err.downcast_ref::<&str>() {
-            tracing::error!("Service panicked: {}", s);
-        } else {
-            tracing::error!(
-                "Service panicked but `CatchPanic` was unable to downcast the panic info"
-            );
-        };
+        log_panic(&*err);
 
         let mut res = Response::new(Full::from("Service panicked"));
         *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
 
         #[allow(clippy::declare_interior_mutable_const)]
         const TEXT_PLAIN: HeaderValue = HeaderValue::from_static("text/plain; charset=utf-8");
-        res.headers_mut()
-            .insert(http::header::CONTENT_TYPE, TEXT_PLAIN);
+        res.headers_mut().insert(header::CONTENT_TYPE, TEXT_PLAIN);
 
         res
     }
@@ -279,7 +417,7 @@ mod tests {
 
     use hyper::Response;
     use std::convert::Infallible;
-    use tower_async::{ServiceBuilder, ServiceExt};
+    use tower_async::{Service, ServiceBuilder, ServiceExt};
 
     #[tokio::test]
     async fn panic_before_returning_future() {
@@ -316,4 +454,67 @@ mod tests {
         let body = test_helpers::to_bytes(res).await.unwrap();
         assert_eq!(&body[..], b"Service panicked");
     }
+
+    #[tokio::test]
+    async fn panic_for_grpc_request() {
+        let svc = ServiceBuilder::new()
+            .layer(CatchPanicLayer::new())
+            .service_fn(|_: Request<Body>| async {
+                panic!("future panic");
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, "application/grpc+proto")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = svc.call(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/grpc");
+        assert_eq!(res.headers()["grpc-status"], "13");
+        let body = test_helpers::to_bytes(res).await.unwrap();
+        assert!(body.is_empty());
+
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, "application/grpc-web")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn panic_with_profile() {
+        let handler = |_: Box<dyn Any + Send + 'static>| {
+            let mut res = Response::new(Body::from("custom"));
+            *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            res
+        };
+        let grpc_request = || {
+            Request::builder()
+                .header(header::CONTENT_TYPE, "application/grpc")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let svc = CatchPanic::custom(
+            tower_async::service_fn(|_: Request<Body>| async {
+                panic!("future panic");
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }),
+            handler,
+        );
+        let res = svc.call(grpc_request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let svc = svc.profile(PanicProfile::Auto);
+        let res = svc.call(grpc_request()).await.unwrap();
+        assert_eq!(res.headers()["grpc-status"], "13");
+
+        let svc = svc.profile(PanicProfile::Grpc);
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()["grpc-status"], "13");
+    }
 }
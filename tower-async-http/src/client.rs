@@ -0,0 +1,359 @@
+//! Opinionated middleware stack for resilient HTTP clients.
+//!
+//! [`resilient_http_client`] wraps an HTTP client with the middleware most clients need,
+//! applied in the order in which they work correctly together. From the outermost to the
+//! innermost layer:
+//!
+//! 1. [`Trace`], such that the span of a request covers all redirects and retries;
+//! 2. [`Decompression`], which requests compressed responses once and decompresses the final
+//!    response;
+//! 3. [`FollowRedirect`], such that every hop of a redirect chain is retried on its own;
+//! 4. [`Retry`] with the [`RetryPolicy`] of this module, retrying idempotent requests that
+//!    failed or were answered with `502`, `503` or `504`, with exponential backoff between
+//!    attempts and a [`TpsBudget`] capping the share of retries;
+//! 5. [`Timeout`], which applies to every attempt separately.
+//!
+//! Connection pooling is left to the wrapped client, such as the pooled client of
+//! [`hyper-util`], which is reused for all requests sent through the stack.
+//!
+//! Request bodies are cloned to retry requests and follow redirects which preserve the
+//! request body, so the request body type has to implement [`Clone`], such as [`Full`].
+//! Streaming bodies have to be buffered first.
+//!
+//! [`Trace`]: crate::trace::Trace
+//! [`Decompression`]: crate::decompression::Decompression
+//! [`FollowRedirect`]: crate::follow_redirect::FollowRedirect
+//! [`Retry`]: tower_async::retry::Retry
+//! [`Timeout`]: tower_async::timeout::Timeout
+//! [`TpsBudget`]: tower_async::retry::budget::TpsBudget
+//! [`hyper-util`]: https://docs.rs/hyper-util
+//! [`Full`]: https://docs.rs/http-body-util/latest/http_body_util/struct.Full.html
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::{BodyExt, Full};
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{service_fn, Service};
+//! use tower_async_http::client::{resilient_http_client, ResilientClientConfig};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! # let http_client = service_fn(|req: Request<Full<Bytes>>| async move {
+//! #     Ok::<_, Infallible>(Response::new(Full::<Bytes>::from("hello")))
+//! # });
+//! let client = resilient_http_client(
+//!     http_client,
+//!     ResilientClientConfig::new()
+//!         .timeout(Duration::from_secs(10))
+//!         .max_retries(2),
+//! );
+//!
+//! let request = Request::get("https://example.com").body(Full::<Bytes>::default())?;
+//! let response = client.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! let body = response.into_body().collect().await?.to_bytes();
+//! assert_eq!(body, "hello");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    decompression::{Decompression, DecompressionLayer},
+    follow_redirect::{
+        policy::{clone_body_fn, And, CloneBodyFn, PolicyExt, Standard},
+        FollowRedirect, FollowRedirectLayer,
+    },
+    trace::{Trace, TraceLayer},
+};
+use http::{Method, Request, Response, StatusCode};
+use std::{sync::Arc, time::Duration};
+use tower_async::{
+    retry::{
+        budget::{Budget, TpsBudget},
+        Policy, Retry, RetryLayer,
+    },
+    timeout::{Timeout, TimeoutLayer},
+    util::{
+        backoff::{Backoff, ExponentialBackoff, ExponentialBackoffMaker, MakeBackoff},
+        rng::HasherRng,
+    },
+    BoxError, ServiceBuilder,
+};
+
+/// Client produced by [`resilient_http_client`].
+///
+/// See the [module docs](self) for more details.
+pub type ResilientClient<S, B> = Trace<
+    Decompression<
+        FollowRedirect<
+            Retry<RetryPolicy, Timeout<S>>,
+            And<Standard, CloneBodyFn<fn(&B) -> Option<B>>>,
+        >,
+    >,
+    SharedClassifier<ServerErrorsAsFailures>,
+>;
+
+/// Configuration of [`resilient_http_client`].
+#[derive(Debug, Clone)]
+pub struct ResilientClientConfig {
+    timeout: Duration,
+    max_retries: usize,
+    backoff: ExponentialBackoffMaker,
+    budget: Arc<TpsBudget>,
+}
+
+impl Default for ResilientClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_retries: 3,
+            backoff: ExponentialBackoffMaker::new(
+                Duration::from_millis(50),
+                Duration::from_secs(5),
+                0.5,
+                HasherRng::default(),
+            )
+            .expect("valid default backoff"),
+            budget: Arc::new(TpsBudget::new(Duration::from_secs(10), 10, 0.2)),
+        }
+    }
+}
+
+impl ResilientClientConfig {
+    /// Create a new [`ResilientClientConfig`] with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timeout of a single attempt of a request.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    ///
+    /// Defaults to 3.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the backoff between attempts of a request.
+    ///
+    /// Defaults to an exponential backoff from 50 milliseconds up to 5 seconds.
+    pub fn backoff(mut self, backoff: ExponentialBackoffMaker) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the budget which caps the number of retries, shared by all requests.
+    ///
+    /// Defaults to retrying 20% of the requests of the last 10 seconds,
+    /// on top of 10 retries per second.
+    pub fn retry_budget(mut self, budget: TpsBudget) -> Self {
+        self.budget = Arc::new(budget);
+        self
+    }
+}
+
+/// Wrap an HTTP client with the middleware of a resilient HTTP client.
+///
+/// See the [module docs](self) for more details.
+pub fn resilient_http_client<S, B>(
+    client: S,
+    config: ResilientClientConfig,
+) -> ResilientClient<S, B>
+where
+    S: Clone,
+    B: Clone,
+{
+    let clone_body: fn(&B) -> Option<B> = |body| Some(body.clone());
+
+    ServiceBuilder::new()
+        .layer(TraceLayer::new_for_http())
+        .layer(DecompressionLayer::new())
+        .layer(FollowRedirectLayer::with_policy(
+            Standard::default().and::<_, B, BoxError>(clone_body_fn(clone_body)),
+        ))
+        .layer(RetryLayer::new(RetryPolicy {
+            max_retries: config.max_retries,
+            backoff: config.backoff,
+            budget: config.budget,
+        }))
+        .layer(TimeoutLayer::new(config.timeout))
+        .service(client)
+}
+
+/// Retry [`Policy`] of [`resilient_http_client`].
+///
+/// Retries idempotent requests that failed, or were answered with `502 Bad Gateway`,
+/// `503 Service Unavailable` or `504 Gateway Timeout`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    backoff: ExponentialBackoffMaker,
+    budget: Arc<TpsBudget>,
+}
+
+/// Retry state of a request, carried by the clone of the request used for the next attempt.
+#[derive(Debug, Clone)]
+struct RetryState {
+    retries: usize,
+    backoff: ExponentialBackoff,
+}
+
+impl<B, ResBody, E> Policy<Request<B>, Response<ResBody>, E> for RetryPolicy
+where
+    B: Clone,
+{
+    async fn retry(&self, req: &mut Request<B>, result: &mut Result<Response<ResBody>, E>) -> bool {
+        let retry = match result {
+            Ok(res) => matches!(
+                res.status(),
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Err(_) => true,
+        };
+        if !retry {
+            self.budget.deposit();
+            return false;
+        }
+
+        let mut state = req
+            .extensions_mut()
+            .remove::<RetryState>()
+            .unwrap_or_else(|| RetryState {
+                retries: 0,
+                backoff: self.backoff.make_backoff(),
+            });
+        if state.retries >= self.max_retries || !self.budget.withdraw() {
+            return false;
+        }
+
+        state.retries += 1;
+        state.backoff.next_backoff().await;
+        req.extensions_mut().insert(state);
+        true
+    }
+
+    fn clone_request(&self, req: &Request<B>) -> Option<Request<B>> {
+        if !matches!(
+            *req.method(),
+            Method::GET
+                | Method::HEAD
+                | Method::OPTIONS
+                | Method::TRACE
+                | Method::PUT
+                | Method::DELETE
+        ) {
+            return None;
+        }
+
+        let mut clone = Request::new(req.body().clone());
+        *clone.method_mut() = req.method().clone();
+        *clone.uri_mut() = req.uri().clone();
+        *clone.version_mut() = req.version();
+        *clone.headers_mut() = req.headers().clone();
+        *clone.extensions_mut() = req.extensions().clone();
+        Some(clone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use bytes::Bytes;
+    use http::header;
+    use http_body_util::{BodyExt, Full};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower_async::{service_fn, Service};
+
+    fn config() -> ResilientClientConfig {
+        ResilientClientConfig::new().backoff(
+            ExponentialBackoffMaker::new(
+                Duration::from_millis(1),
+                Duration::from_millis(1),
+                0.0,
+                HasherRng::default(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn retries_follows_redirects_and_decompresses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let client = {
+            let calls = calls.clone();
+            service_fn(move |req: Request<Full<Bytes>>| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    assert!(req.headers().contains_key(header::ACCEPT_ENCODING));
+                    let res = match (call, req.uri().path()) {
+                        (0, "/old") => Response::builder()
+                            .status(StatusCode::PERMANENT_REDIRECT)
+                            .header(header::LOCATION, "http://example.com/new")
+                            .body(Body::empty()),
+                        (1, _) => Response::builder()
+                            .status(StatusCode::SERVICE_UNAVAILABLE)
+                            .body(Body::empty()),
+                        (2, "/new") => {
+                            assert_eq!(req.into_body().collect().await?.to_bytes(), "payload");
+                            Response::builder().body(Body::from("done"))
+                        }
+                        _ => unreachable!(),
+                    };
+                    Ok::<_, BoxError>(res.unwrap())
+                }
+            })
+        };
+
+        let client = resilient_http_client(client, config());
+        let res = client
+            .call(
+                Request::put("http://example.com/old")
+                    .body(Full::from("payload"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "done");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_non_idempotent_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let client = {
+            let calls = calls.clone();
+            service_fn(move |_: Request<Full<Bytes>>| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Err::<Response<Body>, BoxError>("connection reset".into()) }
+            })
+        };
+
+        let client = resilient_http_client(client, config().max_retries(5));
+        let req = || Request::builder().uri("/").body(Full::default()).unwrap();
+
+        let mut post = req();
+        *post.method_mut() = Method::POST;
+        assert!(client.call(post).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        assert!(client.call(req()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1 + 6);
+    }
+}
@@ -1,8 +1,9 @@
 #![allow(unused_imports)]
 
+use crate::body_size::UncompressedSize;
 use crate::compression::CompressionLevel;
 use crate::{
-    compression_utils::{AsyncReadBody, BodyIntoStream, DecorateAsyncRead, WrapBody},
+    compression_utils::{AsyncReadBody, BodyIntoStream, CodecParams, DecorateAsyncRead, WrapBody},
     BoxError,
 };
 #[cfg(feature = "compression-br")]
@@ -64,17 +65,64 @@ where
     }
 }
 
+pin_project! {
+    /// Counts the bytes of a body before it is compressed.
+    pub(crate) struct CountBody<B> {
+        #[pin]
+        inner: B,
+        size: UncompressedSize,
+    }
+}
+
+impl<B> CountBody<B> {
+    pub(crate) fn new(inner: B, size: UncompressedSize) -> Self {
+        Self { inner, size }
+    }
+}
+
+impl<B> Body for CountBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+        if let Some(data) = frame
+            .as_ref()
+            .and_then(|frame| frame.as_ref().ok())
+            .and_then(Frame::data_ref)
+        {
+            this.size.add(data.remaining());
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
 #[cfg(feature = "compression-gzip")]
-type GzipBody<B> = WrapBody<GzipEncoder<B>>;
+type GzipBody<B> = WrapBody<GzipEncoder<CountBody<B>>>;
 
 #[cfg(feature = "compression-deflate")]
-type DeflateBody<B> = WrapBody<ZlibEncoder<B>>;
+type DeflateBody<B> = WrapBody<ZlibEncoder<CountBody<B>>>;
 
 #[cfg(feature = "compression-br")]
-type BrotliBody<B> = WrapBody<BrotliEncoder<B>>;
+type BrotliBody<B> = WrapBody<BrotliEncoder<CountBody<B>>>;
 
 #[cfg(feature = "compression-zstd")]
-type ZstdBody<B> = WrapBody<ZstdEncoder<B>>;
+type ZstdBody<B> = WrapBody<ZstdEncoder<CountBody<B>>>;
 
 pin_project_cfg! {
     #[project = BodyInnerProj]
@@ -111,22 +159,22 @@ pin_project_cfg! {
 
 impl<B: Body> BodyInner<B> {
     #[cfg(feature = "compression-gzip")]
-    pub(crate) fn gzip(inner: WrapBody<GzipEncoder<B>>) -> Self {
+    pub(crate) fn gzip(inner: GzipBody<B>) -> Self {
         Self::Gzip { inner }
     }
 
     #[cfg(feature = "compression-deflate")]
-    pub(crate) fn deflate(inner: WrapBody<ZlibEncoder<B>>) -> Self {
+    pub(crate) fn deflate(inner: DeflateBody<B>) -> Self {
         Self::Deflate { inner }
     }
 
     #[cfg(feature = "compression-br")]
-    pub(crate) fn brotli(inner: WrapBody<BrotliEncoder<B>>) -> Self {
+    pub(crate) fn brotli(inner: BrotliBody<B>) -> Self {
         Self::Brotli { inner }
     }
 
     #[cfg(feature = "compression-zstd")]
-    pub(crate) fn zstd(inner: WrapBody<ZstdEncoder<B>>) -> Self {
+    pub(crate) fn zstd(inner: ZstdBody<B>) -> Self {
         Self::Zstd { inner }
     }
 
@@ -176,8 +224,8 @@ where
     type Input = AsyncReadBody<B>;
     type Output = GzipEncoder<Self::Input>;
 
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
-        GzipEncoder::with_quality(input, quality.into_async_compression())
+    fn apply(input: Self::Input, params: CodecParams) -> Self::Output {
+        GzipEncoder::with_quality(input, params.quality.into_async_compression())
     }
 
     fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
@@ -193,8 +241,8 @@ where
     type Input = AsyncReadBody<B>;
     type Output = ZlibEncoder<Self::Input>;
 
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
-        ZlibEncoder::with_quality(input, quality.into_async_compression())
+    fn apply(input: Self::Input, params: CodecParams) -> Self::Output {
+        ZlibEncoder::with_quality(input, params.quality.into_async_compression())
     }
 
     fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
@@ -210,17 +258,17 @@ where
     type Input = AsyncReadBody<B>;
     type Output = BrotliEncoder<Self::Input>;
 
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
+    fn apply(input: Self::Input, params: CodecParams) -> Self::Output {
         // The brotli crate used under the hood here has a default compression level of 11,
         // which is the max for brotli. This causes extremely slow compression times, so we
         // manually set a default of 4 here.
         //
         // This is the same default used by NGINX for on-the-fly brotli compression.
-        let level = match quality {
+        let level = match params.quality {
             CompressionLevel::Default => async_compression::Level::Precise(4),
             other => other.into_async_compression(),
         };
-        BrotliEncoder::with_quality(input, level)
+        BrotliEncoder::with_params(input, params.compression.brotli_params(level))
     }
 
     fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
@@ -236,8 +284,12 @@ where
     type Input = AsyncReadBody<B>;
     type Output = ZstdEncoder<Self::Input>;
 
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
-        ZstdEncoder::with_quality(input, quality.into_async_compression())
+    fn apply(input: Self::Input, params: CodecParams) -> Self::Output {
+        ZstdEncoder::with_quality_and_params(
+            input,
+            params.quality.into_async_compression(),
+            &params.compression.zstd_params(),
+        )
     }
 
     fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
@@ -0,0 +1,112 @@
+use super::CompressionLevel;
+use crate::content_encoding::Encoding;
+use http::Extensions;
+
+/// Response extension recording whether, and why, a response was compressed.
+///
+/// Inserted by [`Compression`] into every response, such that responses which weren't
+/// compressed can be diagnosed. With the `tracing` feature enabled, the decision is also
+/// emitted as a `DEBUG` event.
+///
+/// # Example
+///
+/// ```
+/// use http::{header::ACCEPT_ENCODING, Request, Response};
+/// use http_body_util::Full;
+/// use bytes::Bytes;
+/// use std::convert::Infallible;
+/// use tower_async::{service_fn, Service};
+/// use tower_async_http::compression::{Compression, CompressionDecision, CompressionOutcome};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let service = Compression::new(service_fn(|_: Request<Full<Bytes>>| async {
+///     Ok::<_, Infallible>(Response::new(Full::new(Bytes::from_static(b"tiny"))))
+/// }));
+///
+/// let request = Request::builder()
+///     .header(ACCEPT_ENCODING, "gzip")
+///     .body(Full::default())
+///     .unwrap();
+/// let response = service.call(request).await.unwrap();
+///
+/// // the default predicate doesn't compress small responses
+/// let decision = response.extensions().get::<CompressionDecision>().unwrap();
+/// assert_eq!(decision.outcome(), CompressionOutcome::PredicateRejected);
+/// assert!(!decision.is_compressed());
+/// # }
+/// ```
+///
+/// [`Compression`]: super::Compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionDecision {
+    encoding: Encoding,
+    outcome: CompressionOutcome,
+    level: CompressionLevel,
+}
+
+/// Whether a response was compressed, and if not, why.
+///
+/// See [`CompressionDecision::outcome`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionOutcome {
+    /// The response was compressed.
+    Compressed,
+    /// The `Accept-Encoding` header of the request didn't accept any of the enabled encodings.
+    NotAccepted,
+    /// The response already had a `Content-Encoding` header, and is never recompressed.
+    AlreadyEncoded,
+    /// The [`Predicate`] decided not to compress the response.
+    ///
+    /// [`Predicate`]: super::Predicate
+    PredicateRejected,
+}
+
+impl CompressionDecision {
+    pub(crate) fn new(
+        encoding: Encoding,
+        outcome: CompressionOutcome,
+        level: CompressionLevel,
+    ) -> Self {
+        Self {
+            encoding,
+            outcome,
+            level,
+        }
+    }
+
+    /// Returns the encoding negotiated from the `Accept-Encoding` header of the request.
+    ///
+    /// This is [`Encoding::Identity`] if none of the enabled encodings was accepted.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns whether the response was compressed, and if not, why.
+    pub fn outcome(&self) -> CompressionOutcome {
+        self.outcome
+    }
+
+    /// Returns the level the response was compressed with, if it was compressed.
+    pub fn level(&self) -> Option<CompressionLevel> {
+        self.is_compressed().then_some(self.level)
+    }
+
+    /// Returns `true` if the response was compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.outcome == CompressionOutcome::Compressed
+    }
+
+    /// Inserts the decision into the response extensions, and traces it.
+    pub(crate) fn record(self, extensions: &mut Extensions) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            encoding = %self.encoding,
+            outcome = ?self.outcome,
+            level = ?self.level(),
+            "compression decision"
+        );
+        extensions.insert(self);
+    }
+}
@@ -1,6 +1,6 @@
 use super::{Compression, Predicate};
 use crate::compression::predicate::DefaultPredicate;
-use crate::compression::CompressionLevel;
+use crate::compression::{CompressionLevel, CompressionOptions};
 use crate::compression_utils::AcceptEncoding;
 use tower_async_layer::Layer;
 
@@ -15,6 +15,7 @@ pub struct CompressionLayer<P = DefaultPredicate> {
     accept: AcceptEncoding,
     predicate: P,
     quality: CompressionLevel,
+    options: CompressionOptions,
 }
 
 impl<S, P> Layer<S> for CompressionLayer<P>
@@ -29,6 +30,7 @@ where
             accept: self.accept,
             predicate: self.predicate.clone(),
             quality: self.quality,
+            options: self.options,
         }
     }
 }
@@ -73,6 +75,14 @@ impl CompressionLayer {
         self
     }
 
+    /// Sets the advanced options of the codecs.
+    ///
+    /// See [`Compression::options`] for more details.
+    pub fn options(mut self, options: CompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Disables the gzip encoding.
     ///
     /// This method is available even if the `gzip` crate feature is disabled.
@@ -116,6 +126,7 @@ impl CompressionLayer {
             accept: self.accept,
             predicate,
             quality: self.quality,
+            options: self.options,
         }
     }
 }
@@ -70,6 +70,7 @@
 pub mod predicate;
 
 mod body;
+mod decision;
 mod layer;
 mod pin_project_cfg;
 mod service;
@@ -77,11 +78,12 @@ mod service;
 #[doc(inline)]
 pub use self::{
     body::CompressionBody,
+    decision::{CompressionDecision, CompressionOutcome},
     layer::CompressionLayer,
     predicate::{DefaultPredicate, Predicate},
     service::Compression,
 };
-pub use crate::compression_utils::CompressionLevel;
+pub use crate::compression_utils::{CompressionLevel, CompressionOptions};
 
 #[cfg(test)]
 mod tests {
@@ -145,6 +147,39 @@ mod tests {
         assert_eq!(trailers["foo"], "bar");
     }
 
+    #[tokio::test]
+    async fn records_decision() {
+        async fn decision<S, B>(svc: &S, accept_encoding: &'static str) -> CompressionDecision
+        where
+            S: Service<Request<Body>, Response = Response<B>, Error = Infallible>,
+        {
+            let req = Request::builder()
+                .header(ACCEPT_ENCODING, accept_encoding)
+                .body(Body::empty())
+                .unwrap();
+            let res = svc.call(req).await.unwrap();
+            *res.extensions().get::<CompressionDecision>().unwrap()
+        }
+
+        let svc = Compression::new(service_fn(handle)).quality(CompressionLevel::Best);
+
+        let compressed = decision(&svc.clone().compress_when(Always), "gzip").await;
+        assert_eq!(
+            compressed.encoding(),
+            crate::content_encoding::Encoding::Gzip
+        );
+        assert!(compressed.is_compressed());
+        assert_eq!(compressed.level(), Some(CompressionLevel::Best));
+
+        let not_accepted = decision(&svc.clone().compress_when(Always), "identity").await;
+        assert_eq!(not_accepted.outcome(), CompressionOutcome::NotAccepted);
+
+        let never = |_, _, _: &HeaderMap, _: &http::Extensions| false;
+        let rejected = decision(&svc.compress_when(never), "gzip").await;
+        assert_eq!(rejected.outcome(), CompressionOutcome::PredicateRejected);
+        assert_eq!(rejected.level(), None);
+    }
+
     #[tokio::test]
     async fn zstd_works() {
         let svc = service_fn(handle);
@@ -168,6 +203,40 @@ mod tests {
         assert_eq!(decompressed, "Hello, World!");
     }
 
+    #[tokio::test]
+    async fn zstd_options() {
+        let svc = Compression::new(service_fn(handle))
+            .compress_when(Always)
+            .options(CompressionOptions::new().zstd_long_distance_matching(true));
+
+        let req = Request::builder()
+            .header("accept-encoding", "zstd")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        let compressed_data = res.into_body().collect().await.unwrap().to_bytes();
+        let decompressed = zstd::stream::decode_all(std::io::Cursor::new(compressed_data)).unwrap();
+        assert_eq!(decompressed, b"Hello, World!");
+
+        // a larger window than decompressors accept by default, for this request only
+        let mut req = Request::builder()
+            .header("accept-encoding", "zstd")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(CompressionOptions::new().zstd_window_log(28));
+        let res = svc.call(req).await.unwrap();
+        let compressed_data = res.into_body().collect().await.unwrap().to_bytes();
+        assert!(zstd::stream::decode_all(std::io::Cursor::new(&compressed_data)).is_err());
+
+        let mut decoder =
+            zstd::stream::Decoder::new(std::io::Cursor::new(&compressed_data)).unwrap();
+        decoder.window_log_max(28).unwrap();
+        let mut decompressed = String::new();
+        decoder.read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, "Hello, World!");
+    }
+
     #[tokio::test]
     async fn no_recompress() {
         const DATA: &str = "Hello, World! I'm already compressed with br!";
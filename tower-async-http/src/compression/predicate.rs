@@ -194,6 +194,12 @@ impl NotForContentType {
     /// Predicate that wont compress gRPC responses.
     pub const GRPC: Self = Self::const_new("application/grpc");
 
+    /// Predicate that wont compress newline delimited JSON streams.
+    ///
+    /// Compressing such streams delays lines until the encoder produces output,
+    /// which is undesirable for long polling.
+    pub const NDJSON: Self = Self::const_new("application/x-ndjson");
+
     /// Predicate that wont compress images.
     pub const IMAGES: Self = Self {
         content_type: Str::Static("image/"),
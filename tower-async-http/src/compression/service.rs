@@ -1,8 +1,9 @@
-use super::body::BodyInner;
-use super::{CompressionBody, CompressionLayer};
+use super::body::{BodyInner, CountBody};
+use super::{CompressionBody, CompressionDecision, CompressionLayer, CompressionOutcome};
+use crate::body_size::UncompressedSize;
 use crate::compression::predicate::{DefaultPredicate, Predicate};
-use crate::compression::CompressionLevel;
-use crate::compression_utils::WrapBody;
+use crate::compression::{CompressionLevel, CompressionOptions};
+use crate::compression_utils::{CodecParams, WrapBody};
 use crate::{compression_utils::AcceptEncoding, content_encoding::Encoding};
 use http::{header, Request, Response};
 use http_body::Body;
@@ -20,6 +21,7 @@ pub struct Compression<S, P = DefaultPredicate> {
     pub(crate) accept: AcceptEncoding,
     pub(crate) predicate: P,
     pub(crate) quality: CompressionLevel,
+    pub(crate) options: CompressionOptions,
 }
 
 impl<S> Compression<S, DefaultPredicate> {
@@ -30,6 +32,7 @@ impl<S> Compression<S, DefaultPredicate> {
             accept: AcceptEncoding::default(),
             predicate: DefaultPredicate::default(),
             quality: CompressionLevel::default(),
+            options: CompressionOptions::default(),
         }
     }
 }
@@ -78,6 +81,30 @@ impl<S, P> Compression<S, P> {
         self
     }
 
+    /// Sets the advanced options of the codecs, such as the zstd window size.
+    ///
+    /// The options can be overridden for the response to a single request by inserting
+    /// [`CompressionOptions`] into the extensions of the request.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tower_async_http::compression::{Compression, CompressionOptions};
+    /// use tower_async::util::service_fn;
+    ///
+    /// let service = service_fn(|_: ()| async {
+    ///     Ok::<_, std::io::Error>(http::Response::new(()))
+    /// });
+    ///
+    /// // compress large exports using long-distance matching
+    /// let service = Compression::new(service)
+    ///     .options(CompressionOptions::new().zstd_long_distance_matching(true));
+    /// ```
+    pub fn options(mut self, options: CompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Disables the gzip encoding.
     ///
     /// This method is available even if the `gzip` crate feature is disabled.
@@ -154,6 +181,7 @@ impl<S, P> Compression<S, P> {
             accept: self.accept,
             predicate,
             quality: self.quality,
+            options: self.options,
         }
     }
 }
@@ -170,56 +198,78 @@ where
     #[allow(unreachable_code, unused_mut, unused_variables, unreachable_patterns)]
     async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
         let encoding = Encoding::from_headers(req.headers(), self.accept);
+        let params = CodecParams {
+            quality: self.quality,
+            compression: req
+                .extensions()
+                .get::<CompressionOptions>()
+                .copied()
+                .unwrap_or(self.options),
+            ..Default::default()
+        };
 
         let res = self.inner.call(req).await?;
 
         // never recompress responses that are already compressed
-        let should_compress = !res.headers().contains_key(header::CONTENT_ENCODING)
-            && self.predicate.should_compress(&res);
+        let outcome = if res.headers().contains_key(header::CONTENT_ENCODING) {
+            CompressionOutcome::AlreadyEncoded
+        } else if encoding == Encoding::Identity {
+            CompressionOutcome::NotAccepted
+        } else if !self.predicate.should_compress(&res) {
+            CompressionOutcome::PredicateRejected
+        } else {
+            CompressionOutcome::Compressed
+        };
+        let should_compress = outcome == CompressionOutcome::Compressed;
 
         let (mut parts, body) = res.into_parts();
 
+        let size = UncompressedSize::default();
         let body = match (should_compress, encoding) {
             // if compression is _not_ support or the client doesn't accept it
             (false, _) | (_, Encoding::Identity) => {
+                CompressionDecision::new(encoding, outcome, self.quality)
+                    .record(&mut parts.extensions);
                 return Ok(Response::from_parts(
                     parts,
                     CompressionBody::new(BodyInner::identity(body)),
-                ))
+                ));
             }
 
             #[cfg(feature = "compression-gzip")]
-            (_, Encoding::Gzip) => {
-                CompressionBody::new(BodyInner::gzip(WrapBody::new(body, self.quality)))
-            }
+            (_, Encoding::Gzip) => CompressionBody::new(BodyInner::gzip(WrapBody::with_params(
+                CountBody::new(body, size.clone()),
+                params,
+            ))),
             #[cfg(feature = "compression-deflate")]
-            (_, Encoding::Deflate) => {
-                CompressionBody::new(BodyInner::deflate(WrapBody::new(body, self.quality)))
-            }
+            (_, Encoding::Deflate) => CompressionBody::new(BodyInner::deflate(
+                WrapBody::with_params(CountBody::new(body, size.clone()), params),
+            )),
             #[cfg(feature = "compression-br")]
-            (_, Encoding::Brotli) => {
-                CompressionBody::new(BodyInner::brotli(WrapBody::new(body, self.quality)))
-            }
+            (_, Encoding::Brotli) => CompressionBody::new(BodyInner::brotli(
+                WrapBody::with_params(CountBody::new(body, size.clone()), params),
+            )),
             #[cfg(feature = "compression-zstd")]
-            (_, Encoding::Zstd) => {
-                CompressionBody::new(BodyInner::zstd(WrapBody::new(body, self.quality)))
-            }
-            #[cfg(feature = "fs")]
+            (_, Encoding::Zstd) => CompressionBody::new(BodyInner::zstd(WrapBody::with_params(
+                CountBody::new(body, size.clone()),
+                params,
+            ))),
             (true, _) => {
                 // This should never happen because the `AcceptEncoding` struct which is used to determine
                 // `self.encoding` will only enable the different compression algorithms if the
                 // corresponding crate feature has been enabled. This means
-                // Encoding::[Gzip|Brotli|Deflate] should be impossible at this point without the
+                // Encoding::[Gzip|Brotli|Deflate|Zstd] should be impossible at this point without the
                 // features enabled.
                 //
-                // The match arm is still required though because the `fs` feature uses the
-                // Encoding struct independently and requires no compression logic to be enabled.
-                // This means a combination of an individual compression feature and `fs` will fail
-                // to compile without this branch even though it will never be reached.
+                // The match arm is still required though because the `Encoding` enum is public and
+                // shared with other middleware, so all of its variants exist regardless of which
+                // compression features are enabled.
                 //
                 // To safeguard against refactors that changes this relationship or other bugs the
                 // server will return an uncompressed response instead of panicking since that could
                 // become a ddos attack vector.
+                CompressionDecision::new(encoding, CompressionOutcome::NotAccepted, self.quality)
+                    .record(&mut parts.extensions);
                 return Ok(Response::from_parts(
                     parts,
                     CompressionBody::new(BodyInner::identity(body)),
@@ -228,6 +278,8 @@ where
         };
 
         parts.headers.remove(header::CONTENT_LENGTH);
+        parts.extensions.insert(size);
+        CompressionDecision::new(encoding, outcome, self.quality).record(&mut parts.extensions);
 
         parts
             .headers
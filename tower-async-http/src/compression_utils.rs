@@ -139,7 +139,7 @@ pub(crate) trait DecorateAsyncRead {
     type Output: AsyncRead;
 
     /// Apply the decorator
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output;
+    fn apply(input: Self::Input, params: CodecParams) -> Self::Output;
 
     /// Get a pinned mutable reference to the original input.
     ///
@@ -161,6 +161,15 @@ pin_project! {
 impl<M: DecorateAsyncRead> WrapBody<M> {
     #[allow(dead_code)]
     pub(crate) fn new<B>(body: B, quality: CompressionLevel) -> Self
+    where
+        B: Body,
+        M: DecorateAsyncRead<Input = AsyncReadBody<B>>,
+    {
+        Self::with_params(body, quality.into())
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn with_params<B>(body: B, params: CodecParams) -> Self
     where
         B: Body,
         M: DecorateAsyncRead<Input = AsyncReadBody<B>>,
@@ -176,7 +185,7 @@ impl<M: DecorateAsyncRead> WrapBody<M> {
         let read = StreamReader::new(stream);
 
         // apply decorator to `AsyncRead` yielding another `AsyncRead`
-        let read = M::apply(read, quality);
+        let read = M::apply(read, params);
 
         Self {
             read,
@@ -414,6 +423,153 @@ pub enum CompressionLevel {
     Precise(u32),
 }
 
+/// Advanced options of the codecs used to compress responses,
+/// for interoperability with clients that support larger windows than the defaults.
+///
+/// Every option defaults to the default of the codec. The options are set for all responses
+/// using [`Compression::options`], or for the response to a single request by inserting them
+/// into the extensions of the request, which takes precedence.
+///
+/// [`Compression::options`]: crate::compression::Compression::options
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionOptions {
+    zstd_window_log: Option<u32>,
+    zstd_long_distance_matching: bool,
+    brotli_window_size: Option<u32>,
+}
+
+#[allow(dead_code)]
+impl CompressionOptions {
+    /// Creates new [`CompressionOptions`], using the defaults of the codecs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the base-2 logarithm of the zstd window size, clamped to `10..=30`,
+    /// such that the window is `2^window_log` bytes.
+    ///
+    /// Decompressors reject windows larger than `2^27` by default,
+    /// so only use larger windows for clients known to accept them.
+    pub fn zstd_window_log(mut self, window_log: u32) -> Self {
+        self.zstd_window_log = Some(window_log.clamp(10, 30));
+        self
+    }
+
+    /// Sets whether zstd uses long-distance matching, improving the compression of large
+    /// responses with repetitions far apart.
+    ///
+    /// This increases the window size to `2^27` bytes, unless set otherwise.
+    pub fn zstd_long_distance_matching(mut self, enable: bool) -> Self {
+        self.zstd_long_distance_matching = enable;
+        self
+    }
+
+    /// Sets the base-2 logarithm of the Brotli window size, its `lgwin` parameter, clamped
+    /// to `10..=24`, such that the window is `2^window_log - 16` bytes.
+    ///
+    /// The large windows of the Brotli large window extension are not supported.
+    pub fn brotli_window_size(mut self, window_log: u32) -> Self {
+        self.brotli_window_size = Some(window_log.clamp(10, 24));
+        self
+    }
+}
+
+/// Advanced options of the codecs used to decompress bodies,
+/// for interoperability with peers that use larger windows than the defaults.
+///
+/// Every option defaults to the default of the codec. The options are set for all bodies
+/// using the `options` method of the middleware, or for a single request by inserting them
+/// into the extensions of the request, which takes precedence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecompressionOptions {
+    zstd_window_log_max: Option<u32>,
+}
+
+#[allow(dead_code)]
+impl DecompressionOptions {
+    /// Creates new [`DecompressionOptions`], using the defaults of the codecs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the base-2 logarithm of the largest zstd window size accepted,
+    /// clamped to `10..=30`, such that windows up to `2^window_log_max` bytes are accepted.
+    ///
+    /// Defaults to `2^27` bytes, which is also the memory a single body can require
+    /// to be decompressed.
+    pub fn zstd_window_log_max(mut self, window_log_max: u32) -> Self {
+        self.zstd_window_log_max = Some(window_log_max.clamp(10, 30));
+        self
+    }
+}
+
+/// The parameters of the codec applied by a [`DecorateAsyncRead`].
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CodecParams {
+    pub(crate) quality: CompressionLevel,
+    pub(crate) compression: CompressionOptions,
+    pub(crate) decompression: DecompressionOptions,
+}
+
+impl From<CompressionLevel> for CodecParams {
+    fn from(quality: CompressionLevel) -> Self {
+        CodecParams {
+            quality,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<DecompressionOptions> for CodecParams {
+    fn from(decompression: DecompressionOptions) -> Self {
+        CodecParams {
+            decompression,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(feature = "compression-zstd")]
+impl CompressionOptions {
+    pub(crate) fn zstd_params(&self) -> Vec<async_compression::zstd::CParameter> {
+        use async_compression::zstd::CParameter;
+
+        let mut params = Vec::new();
+        if let Some(window_log) = self.zstd_window_log {
+            params.push(CParameter::window_log(window_log));
+        }
+        if self.zstd_long_distance_matching {
+            params.push(CParameter::enable_long_distance_matching(true));
+        }
+        params
+    }
+}
+
+#[cfg(feature = "compression-br")]
+impl CompressionOptions {
+    pub(crate) fn brotli_params(
+        &self,
+        level: async_compression::Level,
+    ) -> async_compression::brotli::EncoderParams {
+        let params = async_compression::brotli::EncoderParams::default().quality(level);
+        match self.brotli_window_size {
+            Some(window_size) => params.window_size(window_size as i32),
+            None => params,
+        }
+    }
+}
+
+#[cfg(feature = "decompression-zstd")]
+impl DecompressionOptions {
+    pub(crate) fn zstd_params(&self) -> Vec<async_compression::zstd::DParameter> {
+        self.zstd_window_log_max
+            .map(async_compression::zstd::DParameter::window_log_max)
+            .into_iter()
+            .collect()
+    }
+}
+
 #[cfg(any(
     feature = "compression-br",
     feature = "compression-gzip",
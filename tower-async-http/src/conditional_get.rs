@@ -0,0 +1,402 @@
+//! Middleware that answers conditional `GET` requests with `304 Not Modified`.
+//!
+//! Clients revalidate cached responses by sending the validators of their cached copy in the
+//! `If-None-Match` and `If-Modified-Since` headers. Checking those validators after a handler
+//! has rendered the full response is wasteful: the body is thrown away whenever the copy of
+//! the client is still fresh.
+//!
+//! The [`ConditionalGet`] middleware instead lets handlers declare the [`CacheValidators`] of
+//! the resource as soon as they are known, for example after loading the version of a record
+//! from a database, through the [`Revalidation`] request extension. [`Revalidation::set`]
+//! tells the handler whether the copy of the client is still fresh, in which case it can skip
+//! rendering the body altogether. The middleware then:
+//!
+//! - replaces successful responses with an empty `304 Not Modified` response, if the copy of
+//!   the client is still fresh;
+//! - adds the `ETag` and `Last-Modified` headers to all other responses, unless the handler
+//!   already set them.
+//!
+//! Only `GET` and `HEAD` requests are revalidated. Responses of handlers that don't set any
+//! validators are passed through unchanged.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::conditional_get::{CacheValidators, ConditionalGetLayer, Revalidation};
+//!
+//! # fn render_report(version: u64) -> String { format!("report v{}", version) }
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // Cheap: look up the current version of the report.
+//!     let version = 42;
+//!
+//!     let revalidation = req.extensions().get::<Revalidation>().unwrap();
+//!     let validators = CacheValidators::new().etag(format!("\"v{}\"", version).parse().unwrap());
+//!     if revalidation.set(validators) {
+//!         // The client has the current version, the body is discarded anyway.
+//!         return Ok(Response::new(Full::default()));
+//!     }
+//!
+//!     // Expensive: render the report.
+//!     Ok(Response::new(Full::from(render_report(version))))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(ConditionalGetLayer::new())
+//!     .service_fn(handle);
+//!
+//! let res = svc.call(Request::new(Full::default())).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//! assert_eq!(res.headers()[header::ETAG], "\"v42\"");
+//!
+//! let req = Request::get("/")
+//!     .header(header::IF_NONE_MATCH, "\"v42\"")
+//!     .body(Full::default())?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use httpdate::HttpDate;
+use std::{
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Validators identifying the current version of a resource.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheValidators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HttpDate>,
+}
+
+impl CacheValidators {
+    /// Create new, empty, [`CacheValidators`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the entity tag of the resource.
+    ///
+    /// The value must be a complete entity tag including the quotes,
+    /// such as `"v1"` or `W/"v1"`.
+    pub fn etag(mut self, etag: HeaderValue) -> Self {
+        self.etag = Some(etag);
+        self
+    }
+
+    /// Set the time the resource was last modified.
+    pub fn last_modified(mut self, last_modified: SystemTime) -> Self {
+        self.last_modified = Some(last_modified.into());
+        self
+    }
+
+    /// Returns the entity tag of the resource, if any.
+    pub fn get_etag(&self) -> Option<&HeaderValue> {
+        self.etag.as_ref()
+    }
+
+    /// Returns the time the resource was last modified, if any.
+    pub fn get_last_modified(&self) -> Option<SystemTime> {
+        self.last_modified.map(Into::into)
+    }
+}
+
+/// Request extension through which handlers set the [`CacheValidators`] of a resource.
+///
+/// Inserted by [`ConditionalGet`] into `GET` and `HEAD` requests.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct Revalidation {
+    if_none_match: Option<HeaderValue>,
+    if_modified_since: Option<HttpDate>,
+    validators: Arc<Mutex<Option<CacheValidators>>>,
+}
+
+impl Revalidation {
+    fn new(headers: &HeaderMap) -> Self {
+        Self {
+            if_none_match: headers.get(header::IF_NONE_MATCH).cloned(),
+            if_modified_since: headers
+                .get(header::IF_MODIFIED_SINCE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| httpdate::parse_http_date(value).ok())
+                .map(Into::into),
+            validators: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Set the validators of the resource.
+    ///
+    /// Returns `true` if the copy of the client is still fresh. The response is then replaced
+    /// by a `304 Not Modified` response, so the handler can skip rendering the body.
+    pub fn set(&self, validators: CacheValidators) -> bool {
+        let fresh = self.is_fresh(&validators);
+        *self.validators.lock().unwrap() = Some(validators);
+        fresh
+    }
+
+    fn take(&self) -> Option<CacheValidators> {
+        self.validators.lock().unwrap().take()
+    }
+
+    fn is_fresh(&self, validators: &CacheValidators) -> bool {
+        // If-Modified-Since is ignored if If-None-Match is present, see RFC 9110 section 13.1.3.
+        if let Some(if_none_match) = &self.if_none_match {
+            let Some(etag) = &validators.etag else {
+                return false;
+            };
+            return if_none_match
+                .to_str()
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .any(|tag| tag == "*" || weak_eq(tag, etag.as_bytes()))
+                })
+                .unwrap_or_default();
+        }
+
+        match (self.if_modified_since, validators.last_modified) {
+            (Some(if_modified_since), Some(last_modified)) => last_modified <= if_modified_since,
+            _ => false,
+        }
+    }
+}
+
+/// Weak comparison of two entity tags, see RFC 9110 section 8.8.3.2.
+fn weak_eq(a: &str, b: &[u8]) -> bool {
+    let a = a.strip_prefix("W/").unwrap_or(a).as_bytes();
+    let b = b.strip_prefix(b"W/").unwrap_or(b);
+    a == b
+}
+
+/// Layer that applies the [`ConditionalGet`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConditionalGetLayer {
+    _priv: (),
+}
+
+impl ConditionalGetLayer {
+    /// Create a new [`ConditionalGetLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for ConditionalGetLayer {
+    type Service = ConditionalGet<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConditionalGet::new(inner)
+    }
+}
+
+/// Middleware that answers conditional `GET` requests with `304 Not Modified`,
+/// using the [`CacheValidators`] set by the handler.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct ConditionalGet<S> {
+    inner: S,
+}
+
+impl<S> ConditionalGet<S> {
+    /// Create a new [`ConditionalGet`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ConditionalGet` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> ConditionalGetLayer {
+        ConditionalGetLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ConditionalGet<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return self.inner.call(req).await;
+        }
+
+        let revalidation = Revalidation::new(req.headers());
+        req.extensions_mut().insert(revalidation.clone());
+        let mut res = self.inner.call(req).await?;
+
+        let validators = match revalidation.take() {
+            Some(validators) => validators,
+            None => return Ok(res),
+        };
+
+        let headers = res.headers_mut();
+        if let Some(etag) = &validators.etag {
+            headers.entry(header::ETAG).or_insert_with(|| etag.clone());
+        }
+        if let Some(last_modified) = validators.last_modified {
+            headers
+                .entry(header::LAST_MODIFIED)
+                .or_insert_with(|| HeaderValue::from_str(&last_modified.to_string()).unwrap());
+        }
+
+        if !res.status().is_success() || !revalidation.is_fresh(&validators) {
+            return Ok(res);
+        }
+
+        // Only keep the headers a 304 response is meant to carry, see RFC 9110 section 15.4.5.
+        let mut not_modified = Response::new(ResBody::default());
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+        *not_modified.version_mut() = res.version();
+        for name in [
+            header::CACHE_CONTROL,
+            header::CONTENT_LOCATION,
+            header::DATE,
+            header::ETAG,
+            header::EXPIRES,
+            header::LAST_MODIFIED,
+            header::VARY,
+        ] {
+            for value in res.headers().get_all(&name) {
+                not_modified.headers_mut().append(&name, value.clone());
+            }
+        }
+        Ok(not_modified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http_body_util::BodyExt;
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use tower_async::ServiceBuilder;
+
+    fn last_modified() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    #[tokio::test]
+    async fn skips_rendering_fresh_responses() {
+        let renders = Arc::new(AtomicUsize::new(0));
+        let svc = ServiceBuilder::new()
+            .layer(ConditionalGetLayer::new())
+            .service_fn({
+                let renders = renders.clone();
+                move |req: Request<Body>| {
+                    let renders = renders.clone();
+                    async move {
+                        let revalidation = req.extensions().get::<Revalidation>().unwrap();
+                        let validators = CacheValidators::new()
+                            .etag(HeaderValue::from_static("W/\"v1\""))
+                            .last_modified(last_modified());
+                        if revalidation.set(validators) {
+                            let res = Response::builder()
+                                .header(header::CACHE_CONTROL, "no-cache")
+                                .header(header::CONTENT_TYPE, "text/plain")
+                                .body(Body::empty())
+                                .unwrap();
+                            return Ok::<_, Infallible>(res);
+                        }
+
+                        renders.fetch_add(1, Ordering::SeqCst);
+                        Ok(Response::new(Body::from("rendered")))
+                    }
+                }
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ETAG], "W/\"v1\"");
+        assert_eq!(
+            res.headers()[header::LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+
+        let req = Request::get("/")
+            .header(header::IF_NONE_MATCH, "\"v0\", \"v1\"")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], "W/\"v1\"");
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+        assert!(res.headers().get(header::CONTENT_TYPE).is_none());
+        assert!(res
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .is_empty());
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+
+        // If-Modified-Since is ignored when If-None-Match is present
+        let req = Request::get("/")
+            .header(header::IF_NONE_MATCH, "\"v2\"")
+            .header(header::IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+
+        let req = Request::get("/")
+            .header(header::IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn only_revalidates_get_and_head() {
+        let svc = ServiceBuilder::new()
+            .layer(ConditionalGetLayer::new())
+            .service_fn(|req: Request<Body>| async move {
+                let revalidated = req.extensions().get::<Revalidation>().is_some();
+                Ok::<_, Infallible>(Response::new(Body::from(revalidated.to_string())))
+            });
+
+        let req = Request::post("/")
+            .header(header::IF_NONE_MATCH, "*")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "false");
+    }
+}
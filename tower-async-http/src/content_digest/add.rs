@@ -0,0 +1,274 @@
+use super::{encode, Algorithm, Hasher, CONTENT_DIGEST};
+use bytes::{Buf, Bytes};
+use futures_core::ready;
+use http::{header, HeaderMap, HeaderValue, Request, Response};
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`AddContentDigest`] middleware.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct AddContentDigestLayer {
+    algorithm: Algorithm,
+}
+
+impl Default for AddContentDigestLayer {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::Sha256,
+        }
+    }
+}
+
+impl AddContentDigestLayer {
+    /// Create a new [`AddContentDigestLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the algorithm of the digest.
+    ///
+    /// Defaults to [`Algorithm::Sha256`].
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+}
+
+impl<S> Layer<S> for AddContentDigestLayer {
+    type Service = AddContentDigest<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AddContentDigest {
+            inner,
+            algorithm: self.algorithm,
+        }
+    }
+}
+
+/// Middleware that adds the digest of response bodies as `Content-Digest` trailer.
+///
+/// The `Trailer` header of the response announces the trailer, and its `Content-Length`
+/// header is removed, as trailers can only be sent with bodies of unknown length, such as
+/// chunked HTTP/1.1 bodies. Responses which already have a `Content-Digest` header are
+/// passed through unchanged.
+///
+/// See the [module docs](super) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct AddContentDigest<S> {
+    inner: S,
+    algorithm: Algorithm,
+}
+
+impl<S> AddContentDigest<S> {
+    /// Create a new [`AddContentDigest`].
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            algorithm: Algorithm::Sha256,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `AddContentDigest` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> AddContentDigestLayer {
+        AddContentDigestLayer::new()
+    }
+
+    /// Set the algorithm of the digest.
+    ///
+    /// Defaults to [`Algorithm::Sha256`].
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AddContentDigest<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ContentDigestBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let mut res = self.inner.call(req).await?;
+        if res.headers().contains_key(CONTENT_DIGEST) {
+            return Ok(res.map(|body| ContentDigestBody::new(body, None)));
+        }
+
+        // trailers can't be sent with a fixed length body, such that the length is dropped
+        res.headers_mut().remove(header::CONTENT_LENGTH);
+        res.headers_mut()
+            .append(header::TRAILER, HeaderValue::from_static("content-digest"));
+        let algorithm = self.algorithm;
+        Ok(res.map(|body| ContentDigestBody::new(body, Some(algorithm))))
+    }
+}
+
+pin_project! {
+    /// Response body for [`AddContentDigest`].
+    ///
+    /// Sends the digest of the content as `Content-Digest` trailer,
+    /// merged with the trailers of the inner body, if any.
+    pub struct ContentDigestBody<B> {
+        #[pin]
+        inner: B,
+        algorithm: Option<Algorithm>,
+        hasher: Option<Hasher>,
+    }
+}
+
+impl<B> ContentDigestBody<B> {
+    fn new(inner: B, algorithm: Option<Algorithm>) -> Self {
+        Self {
+            inner,
+            algorithm,
+            hasher: algorithm.map(Algorithm::hasher),
+        }
+    }
+}
+
+impl<B> fmt::Debug for ContentDigestBody<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentDigestBody")
+            .field("inner", &self.inner)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl<B> Body for ContentDigestBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                let frame = frame.map_data(|mut data| data.copy_to_bytes(data.remaining()));
+                match frame.into_trailers() {
+                    Ok(mut trailers) => {
+                        insert_digest(*this.algorithm, this.hasher, &mut trailers);
+                        Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+                    }
+                    Err(frame) => {
+                        if let (Some(hasher), Some(data)) = (this.hasher.as_mut(), frame.data_ref())
+                        {
+                            hasher.update(data);
+                        }
+                        Poll::Ready(Some(Ok(frame)))
+                    }
+                }
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err))),
+            None if this.hasher.is_some() => {
+                let mut trailers = HeaderMap::new();
+                insert_digest(*this.algorithm, this.hasher, &mut trailers);
+                Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // the digest still has to be sent once the inner body ended
+        self.hasher.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        let hint = self.inner.size_hint();
+        if self.hasher.is_none() {
+            return hint;
+        }
+        // an exact hint would make servers send a `Content-Length` and drop the trailer
+        let mut inexact = http_body::SizeHint::new();
+        inexact.set_lower(hint.lower());
+        inexact
+    }
+}
+
+fn insert_digest(
+    algorithm: Option<Algorithm>,
+    hasher: &mut Option<Hasher>,
+    trailers: &mut HeaderMap,
+) {
+    if let (Some(algorithm), Some(hasher)) = (algorithm, hasher.take()) {
+        trailers.insert(CONTENT_DIGEST, encode(algorithm, &hasher.finalize()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    #[tokio::test]
+    async fn adds_digest_trailer() {
+        let svc = ServiceBuilder::new()
+            .layer(AddContentDigestLayer::new().algorithm(Algorithm::Sha512))
+            .service_fn(|_: Request<Body>| async {
+                let res = Response::builder()
+                    .header(header::CONTENT_LENGTH, "19")
+                    .body(Body::from("{\"hello\": \"world\"}\n"))
+                    .unwrap();
+                Ok::<_, Infallible>(res)
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()["trailer"], "content-digest");
+        // the length is unknown, such that the trailer isn't dropped
+        assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(http_body::Body::size_hint(res.body()).exact(), None);
+
+        let collected = res.into_body().collect().await.unwrap();
+        assert_eq!(
+            collected.trailers().unwrap()["content-digest"],
+            "sha-512=:YMAam51Jz/jOATT6/zvHrLVgOYTGFy1d6GJiOHTohq4yP+pgk4vf2aCs\
+             yRZOtw8MjkM7iw7yZ/WkppmM44T3qg==:"
+        );
+        assert_eq!(collected.to_bytes(), "{\"hello\": \"world\"}\n");
+    }
+
+    #[tokio::test]
+    async fn existing_digest_is_kept() {
+        let svc = ServiceBuilder::new()
+            .layer(AddContentDigestLayer::new())
+            .service_fn(|_: Request<Body>| async {
+                let mut res = Response::new(Body::from("{}"));
+                res.headers_mut()
+                    .insert(CONTENT_DIGEST, encode(Algorithm::Sha256, b"precomputed"));
+                Ok::<_, Infallible>(res)
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert!(res.headers().get("trailer").is_none());
+
+        let collected = res.into_body().collect().await.unwrap();
+        assert!(collected.trailers().is_none());
+    }
+}
@@ -0,0 +1,245 @@
+//! Middleware that computes and verifies digests of message content.
+//!
+//! [RFC 9530] defines the `Content-Digest` header, which carries a checksum of the content
+//! of a message, such that the receiver can detect corruption of the content in transit.
+//! This module provides middleware to:
+//!
+//! - verify the digests of request bodies with [`VerifyContentDigest`], which accepts the
+//!   `Content-Digest` header as well as the legacy `Digest` header of [RFC 3230];
+//! - add the digest of response bodies with [`AddContentDigest`].
+//!
+//! SHA-256 and SHA-512 digests are supported, see [`Algorithm`].
+//!
+//! Both middleware compute the digest while the body is streamed, without buffering it.
+//! As a consequence, a mismatching request digest can only be detected once the whole body
+//! has been read: the request body then fails with a [`DigestMismatch`] error instead of
+//! ending, such that handlers don't act on corrupted content. Likewise, the digest of a
+//! response is only known once the whole body has been sent, so it is sent as a trailer.
+//! Trailers can't follow a body of fixed length, so the `Content-Length` of such responses
+//! is removed, which makes HTTP/1.1 servers send them chunked.
+//!
+//! Digests cover the content as sent over the wire, so these middleware should be placed
+//! outside of the [compression] and [decompression] middleware.
+//!
+//! [RFC 9530]: https://www.rfc-editor.org/rfc/rfc9530
+//! [RFC 3230]: https://www.rfc-editor.org/rfc/rfc3230
+//! [compression]: crate::compression
+//! [decompression]: crate::decompression
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::{BodyExt, Full};
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::content_digest::{
+//!     AddContentDigestLayer, DigestMismatch, VerifyContentDigestLayer,
+//! };
+//!
+//! async fn handle<B>(req: Request<B>) -> Result<Response<Full<Bytes>>, BoxError>
+//! where
+//!     B: http_body::Body,
+//!     B::Error: Into<BoxError>,
+//! {
+//!     // Reading the body fails if the digest doesn't match.
+//!     let body = req.into_body().collect().await.map_err(Into::into)?.to_bytes();
+//!     Ok(Response::new(Full::new(body)))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(AddContentDigestLayer::new())
+//!     .layer(VerifyContentDigestLayer::new())
+//!     .service_fn(handle);
+//!
+//! let req = Request::post("/")
+//!     .header("content-digest", "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:")
+//!     .body(Full::<Bytes>::from("{\"hello\": \"world\"}"))?;
+//! let res = svc.call(req).await?;
+//!
+//! let trailers = res.into_body().collect().await?.trailers().cloned().unwrap();
+//! assert_eq!(
+//!     trailers["content-digest"],
+//!     "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:",
+//! );
+//!
+//! let req = Request::post("/")
+//!     .header("content-digest", "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:")
+//!     .body(Full::<Bytes>::from("{\"hello\": \"corrupted\"}"))?;
+//! let err = svc.call(req).await.unwrap_err();
+//! assert!(err.is::<DigestMismatch>());
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::Engine as _;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+
+mod add;
+mod verify;
+
+pub use self::{
+    add::{AddContentDigest, AddContentDigestLayer, ContentDigestBody},
+    verify::{VerifyContentDigest, VerifyContentDigestLayer, VerifyDigestBody},
+};
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+const DIGEST: HeaderName = HeaderName::from_static("digest");
+
+/// Digest algorithms supported by the [`content_digest`](self) middleware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// SHA-256, `sha-256`.
+    Sha256,
+    /// SHA-512, `sha-512`.
+    Sha512,
+}
+
+impl Algorithm {
+    /// Returns the key of the algorithm in the `Content-Digest` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha-256",
+            Algorithm::Sha512 => "sha-512",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        if key.eq_ignore_ascii_case("sha-256") {
+            Some(Algorithm::Sha256)
+        } else if key.eq_ignore_ascii_case("sha-512") {
+            Some(Algorithm::Sha512)
+        } else {
+            None
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Encode a digest as `Content-Digest` header value.
+fn encode(algorithm: Algorithm, digest: &[u8]) -> HeaderValue {
+    let value = format!("{}=:{}:", algorithm, BASE64.encode(digest));
+    HeaderValue::try_from(value).expect("base64 is a valid header value")
+}
+
+/// Returns the strongest supported digest of the headers, if any.
+///
+/// The `Content-Digest` header takes precedence over the legacy `Digest` header.
+fn expected_digest(headers: &HeaderMap) -> Option<(Algorithm, Vec<u8>)> {
+    let content_digest = headers
+        .get_all(CONTENT_DIGEST)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|member| {
+            let (key, value) = member.trim().split_once('=')?;
+            let value = value.strip_prefix(':')?.strip_suffix(':')?;
+            Some((Algorithm::from_key(key)?, BASE64.decode(value).ok()?))
+        })
+        .max_by_key(|(algorithm, _)| *algorithm);
+    if content_digest.is_some() {
+        return content_digest;
+    }
+
+    headers
+        .get_all(DIGEST)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|member| {
+            let (key, value) = member.trim().split_once('=')?;
+            Some((Algorithm::from_key(key)?, BASE64.decode(value).ok()?))
+        })
+        .max_by_key(|(algorithm, _)| *algorithm)
+}
+
+/// Error returned by [`VerifyDigestBody`] if the digest of the content
+/// doesn't match the digest of the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DigestMismatch {
+    algorithm: Algorithm,
+}
+
+impl DigestMismatch {
+    /// Returns the algorithm of the mismatching digest.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+impl fmt::Display for DigestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "content does not match the {} digest", self.algorithm)
+    }
+}
+
+impl std::error::Error for DigestMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_expected_digest() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DIGEST, HeaderValue::from_static("SHA-256=AAAA"));
+        assert_eq!(
+            expected_digest(&headers),
+            Some((Algorithm::Sha256, vec![0, 0, 0]))
+        );
+
+        // unsupported algorithms are ignored and the strongest digest is preferred
+        headers.insert(
+            CONTENT_DIGEST,
+            HeaderValue::from_static("md5=:AAAA:, sha-512=:AQID:, sha-256=:AAAA:"),
+        );
+        assert_eq!(
+            expected_digest(&headers),
+            Some((Algorithm::Sha512, vec![1, 2, 3]))
+        );
+
+        headers.insert(CONTENT_DIGEST, HeaderValue::from_static("md5=:AAAA:"));
+        headers.remove(DIGEST);
+        assert_eq!(expected_digest(&headers), None);
+    }
+}
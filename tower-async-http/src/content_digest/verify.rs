@@ -0,0 +1,246 @@
+use super::{expected_digest, Algorithm, DigestMismatch, Hasher};
+use crate::BoxError;
+use bytes::{Buf, Bytes};
+use futures_core::ready;
+use http::{Request, Response, StatusCode};
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`VerifyContentDigest`] middleware.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyContentDigestLayer {
+    require: bool,
+}
+
+impl VerifyContentDigestLayer {
+    /// Create a new [`VerifyContentDigestLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject requests without a digest of a supported algorithm
+    /// with a `400 Bad Request` response.
+    ///
+    /// Defaults to `false`, passing such requests on without verification.
+    pub fn require(mut self, require: bool) -> Self {
+        self.require = require;
+        self
+    }
+}
+
+impl<S> Layer<S> for VerifyContentDigestLayer {
+    type Service = VerifyContentDigest<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyContentDigest {
+            inner,
+            require: self.require,
+        }
+    }
+}
+
+/// Middleware that verifies the digest of request bodies.
+///
+/// See the [module docs](super) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct VerifyContentDigest<S> {
+    inner: S,
+    require: bool,
+}
+
+impl<S> VerifyContentDigest<S> {
+    /// Create a new [`VerifyContentDigest`].
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            require: false,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `VerifyContentDigest` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> VerifyContentDigestLayer {
+        VerifyContentDigestLayer::new()
+    }
+
+    /// Reject requests without a digest of a supported algorithm
+    /// with a `400 Bad Request` response.
+    ///
+    /// Defaults to `false`, passing such requests on without verification.
+    pub fn require(mut self, require: bool) -> Self {
+        self.require = require;
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for VerifyContentDigest<S>
+where
+    S: Service<Request<VerifyDigestBody<ReqBody>>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let expected = expected_digest(req.headers());
+        if expected.is_none() && self.require {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(res);
+        }
+
+        let req = req.map(|body| VerifyDigestBody::new(body, expected));
+        self.inner.call(req).await
+    }
+}
+
+pin_project! {
+    /// Request body for [`VerifyContentDigest`].
+    ///
+    /// Fails with a [`DigestMismatch`] error instead of ending,
+    /// if the content doesn't match the digest of the request.
+    pub struct VerifyDigestBody<B> {
+        #[pin]
+        inner: B,
+        expected: Option<(Algorithm, Vec<u8>)>,
+        hasher: Option<Hasher>,
+    }
+}
+
+impl<B> VerifyDigestBody<B> {
+    fn new(inner: B, expected: Option<(Algorithm, Vec<u8>)>) -> Self {
+        let hasher = expected.as_ref().map(|(algorithm, _)| algorithm.hasher());
+        Self {
+            inner,
+            expected,
+            hasher,
+        }
+    }
+
+    /// Returns the algorithm of the digest that is verified, if any.
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.expected.as_ref().map(|(algorithm, _)| *algorithm)
+    }
+}
+
+impl<B> std::fmt::Debug for VerifyDigestBody<B>
+where
+    B: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VerifyDigestBody")
+            .field("inner", &self.inner)
+            .field("algorithm", &self.algorithm())
+            .finish()
+    }
+}
+
+impl<B> Body for VerifyDigestBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                let frame = frame.map_data(|mut data| data.copy_to_bytes(data.remaining()));
+                if let (Some(hasher), Some(data)) = (this.hasher.as_mut(), frame.data_ref()) {
+                    hasher.update(data);
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err.into()))),
+            None => {
+                let (hasher, (algorithm, expected)) = match (this.hasher.take(), this.expected) {
+                    (Some(hasher), Some(expected)) => (hasher, expected),
+                    _ => return Poll::Ready(None),
+                };
+                if hasher.finalize() == *expected {
+                    Poll::Ready(None)
+                } else {
+                    let algorithm = *algorithm;
+                    Poll::Ready(Some(Err(DigestMismatch { algorithm }.into())))
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // the end of the stream still has to be polled to verify the digest
+        self.hasher.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    async fn read(req: Request<VerifyDigestBody<Body>>) -> Result<Response<Body>, BoxError> {
+        let body = req.into_body().collect().await?.to_bytes();
+        Ok(Response::new(Body::from(body)))
+    }
+
+    #[tokio::test]
+    async fn verifies_sha512_digest() {
+        let svc = ServiceBuilder::new()
+            .layer(VerifyContentDigestLayer::new())
+            .service_fn(read);
+
+        let digest = "sha-512=:YMAam51Jz/jOATT6/zvHrLVgOYTGFy1d6GJiOHTohq4yP+pgk4vf2aCs\
+                      yRZOtw8MjkM7iw7yZ/WkppmM44T3qg==:";
+        let req = Request::post("/")
+            .header("content-digest", digest)
+            .body(Body::from("{\"hello\": \"world\"}\n"))
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{\"hello\": \"world\"}\n");
+
+        let req = Request::post("/")
+            .header("content-digest", digest)
+            .body(Body::from("{\"hello\": \"world\"}"))
+            .unwrap();
+        let err = svc.call(req).await.unwrap_err();
+        let err = err.downcast::<DigestMismatch>().unwrap();
+        assert_eq!(err.algorithm(), Algorithm::Sha512);
+    }
+
+    #[tokio::test]
+    async fn require_digest() {
+        let svc = ServiceBuilder::new()
+            .layer(VerifyContentDigestLayer::new().require(true))
+            .service_fn(|_: Request<VerifyDigestBody<Body>>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc.call(Request::new(Body::from("{}"))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
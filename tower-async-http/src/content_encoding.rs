@@ -1,3 +1,42 @@
+//! Negotiation of the content encoding of responses.
+//!
+//! [`Negotiator`] picks the encoding of a response based on the `Accept-Encoding`
+//! header of the request, as specified in [RFC 9110 section 12.5.3]. This is the same
+//! negotiation used by [`Compression`] and [`ServeDir`], exposed so that other
+//! middleware and services can make the same decision. It takes care of:
+//!
+//! - q-values, where encodings with `q=0` are never chosen;
+//! - the `*` wildcard, matching all encodings not listed explicitly;
+//! - `identity`, which is acceptable unless excluded by `identity;q=0`
+//!   or by `*;q=0` without `identity` being listed.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, HeaderMap, HeaderValue};
+//! use tower_async_http::content_encoding::{Encoding, Negotiator};
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert(
+//!     header::ACCEPT_ENCODING,
+//!     HeaderValue::from_static("gzip;q=0.5, br;q=0.8, identity;q=0"),
+//! );
+//!
+//! let negotiator = Negotiator::new();
+//! assert_eq!(negotiator.negotiate(&headers).preferred(), Some(Encoding::Brotli));
+//!
+//! // Without brotli support gzip is chosen, but never the excluded identity encoding.
+//! let accepted = negotiator.br(false).negotiate(&headers);
+//! assert_eq!(accepted.preferred(), Some(Encoding::Gzip));
+//! assert!(!accepted.is_acceptable(Encoding::Identity));
+//! ```
+//!
+//! [RFC 9110 section 12.5.3]: https://www.rfc-editor.org/rfc/rfc9110#section-12.5.3
+//! [`Compression`]: crate::compression::Compression
+//! [`ServeDir`]: crate::services::ServeDir
+
+use http::HeaderMap;
+
 pub(crate) trait SupportedEncodings: Copy {
     fn gzip(&self) -> bool;
     fn deflate(&self) -> bool;
@@ -5,32 +44,38 @@ pub(crate) trait SupportedEncodings: Copy {
     fn zstd(&self) -> bool;
 }
 
+/// A content encoding.
 // This enum's variants are ordered from least to most preferred.
-#[derive(Copy, Clone, Debug, Ord, PartialOrd, PartialEq, Eq)]
-pub(crate) enum Encoding {
-    #[allow(dead_code)]
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Hash, Ord, PartialOrd, PartialEq, Eq)]
+pub enum Encoding {
+    /// No encoding.
     Identity,
-    #[cfg(any(feature = "fs", feature = "compression-deflate"))]
+    /// The `deflate` encoding.
     Deflate,
-    #[cfg(any(feature = "fs", feature = "compression-gzip"))]
+    /// The `gzip` encoding.
     Gzip,
-    #[cfg(any(feature = "fs", feature = "compression-br"))]
+    /// The `br` (Brotli) encoding.
     Brotli,
-    #[cfg(any(feature = "fs", feature = "compression-zstd"))]
+    /// The `zstd` (Zstandard) encoding.
     Zstd,
 }
 
 impl Encoding {
-    #[allow(dead_code)]
-    fn to_str(self) -> &'static str {
+    const COMPRESSED: [Encoding; 4] = [
+        Encoding::Deflate,
+        Encoding::Gzip,
+        Encoding::Brotli,
+        Encoding::Zstd,
+    ];
+
+    /// Returns the name of the encoding, as used in the `Accept-Encoding`
+    /// and `Content-Encoding` headers.
+    pub fn as_str(self) -> &'static str {
         match self {
-            #[cfg(any(feature = "fs", feature = "compression-gzip"))]
             Encoding::Gzip => "gzip",
-            #[cfg(any(feature = "fs", feature = "compression-deflate"))]
             Encoding::Deflate => "deflate",
-            #[cfg(any(feature = "fs", feature = "compression-br"))]
             Encoding::Brotli => "br",
-            #[cfg(any(feature = "fs", feature = "compression-zstd"))]
             Encoding::Zstd => "zstd",
             Encoding::Identity => "identity",
         }
@@ -49,42 +94,24 @@ impl Encoding {
 
     #[allow(dead_code)]
     pub(crate) fn into_header_value(self) -> http::HeaderValue {
-        http::HeaderValue::from_static(self.to_str())
+        http::HeaderValue::from_static(self.as_str())
     }
 
-    #[cfg(any(
-        feature = "compression-gzip",
-        feature = "compression-br",
-        feature = "compression-deflate",
-        feature = "compression-zstd",
-        feature = "fs",
-    ))]
-    fn parse(s: &str, _supported_encoding: impl SupportedEncodings) -> Option<Encoding> {
-        #[cfg(any(feature = "fs", feature = "compression-gzip"))]
-        if s.eq_ignore_ascii_case("gzip") && _supported_encoding.gzip() {
-            return Some(Encoding::Gzip);
-        }
-
-        #[cfg(any(feature = "fs", feature = "compression-deflate"))]
-        if s.eq_ignore_ascii_case("deflate") && _supported_encoding.deflate() {
-            return Some(Encoding::Deflate);
-        }
-
-        #[cfg(any(feature = "fs", feature = "compression-br"))]
-        if s.eq_ignore_ascii_case("br") && _supported_encoding.br() {
-            return Some(Encoding::Brotli);
-        }
-
-        #[cfg(any(feature = "fs", feature = "compression-zstd"))]
-        if s.eq_ignore_ascii_case("zstd") && _supported_encoding.zstd() {
-            return Some(Encoding::Zstd);
-        }
-
-        if s.eq_ignore_ascii_case("identity") {
-            return Some(Encoding::Identity);
+    fn is_supported(self, supported_encoding: impl SupportedEncodings) -> bool {
+        match self {
+            Encoding::Gzip => supported_encoding.gzip(),
+            Encoding::Deflate => supported_encoding.deflate(),
+            Encoding::Brotli => supported_encoding.br(),
+            Encoding::Zstd => supported_encoding.zstd(),
+            Encoding::Identity => true,
         }
+    }
 
-        None
+    fn parse(s: &str, supported_encoding: impl SupportedEncodings) -> Option<Encoding> {
+        std::iter::once(Encoding::Identity)
+            .chain(Encoding::COMPRESSED)
+            .find(|encoding| s.eq_ignore_ascii_case(encoding.as_str()))
+            .filter(|encoding| encoding.is_supported(supported_encoding))
     }
 
     #[cfg(any(
@@ -93,50 +120,152 @@ impl Encoding {
         feature = "compression-zstd",
         feature = "compression-deflate",
     ))]
-    // based on https://github.com/http-rs/accept-encoding
     pub(crate) fn from_headers(
         headers: &http::HeaderMap,
         supported_encoding: impl SupportedEncodings,
     ) -> Self {
-        Encoding::preferred_encoding(&encodings(headers, supported_encoding))
+        encodings(headers, supported_encoding)
+            .preferred()
             .unwrap_or(Encoding::Identity)
     }
+}
 
-    #[cfg(any(
-        feature = "compression-gzip",
-        feature = "compression-br",
-        feature = "compression-zstd",
-        feature = "compression-deflate",
-        feature = "fs",
-    ))]
-    pub(crate) fn preferred_encoding(accepted_encodings: &[(Encoding, QValue)]) -> Option<Self> {
-        accepted_encodings
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Negotiates the content encoding of a response from the `Accept-Encoding` header.
+///
+/// All encodings are supported by default.
+///
+/// See the [module docs](self) for more details.
+#[derive(Copy, Clone, Debug)]
+pub struct Negotiator {
+    gzip: bool,
+    deflate: bool,
+    br: bool,
+    zstd: bool,
+}
+
+impl Default for Negotiator {
+    fn default() -> Self {
+        Self {
+            gzip: true,
+            deflate: true,
+            br: true,
+            zstd: true,
+        }
+    }
+}
+
+impl Negotiator {
+    /// Create a new [`Negotiator`] supporting all encodings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the gzip encoding is supported.
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.gzip = enable;
+        self
+    }
+
+    /// Sets whether the Deflate encoding is supported.
+    pub fn deflate(mut self, enable: bool) -> Self {
+        self.deflate = enable;
+        self
+    }
+
+    /// Sets whether the Brotli encoding is supported.
+    pub fn br(mut self, enable: bool) -> Self {
+        self.br = enable;
+        self
+    }
+
+    /// Sets whether the Zstd encoding is supported.
+    pub fn zstd(mut self, enable: bool) -> Self {
+        self.zstd = enable;
+        self
+    }
+
+    /// Negotiate the encodings accepted by the client, given the request headers.
+    pub fn negotiate(&self, headers: &HeaderMap) -> AcceptedEncodings {
+        encodings(headers, *self)
+    }
+}
+
+impl SupportedEncodings for Negotiator {
+    fn gzip(&self) -> bool {
+        self.gzip
+    }
+
+    fn deflate(&self) -> bool {
+        self.deflate
+    }
+
+    fn br(&self) -> bool {
+        self.br
+    }
+
+    fn zstd(&self) -> bool {
+        self.zstd
+    }
+}
+
+/// The supported encodings accepted by a client, as negotiated by a [`Negotiator`].
+#[derive(Clone, Debug, Default)]
+pub struct AcceptedEncodings {
+    // all accepted encodings except for identity
+    encodings: Vec<(Encoding, QValue)>,
+    // `None` if identity was not mentioned, in which case it is acceptable
+    // but less preferred than any of the explicitly accepted encodings
+    identity: Option<QValue>,
+}
+
+impl AcceptedEncodings {
+    /// Returns the most preferred acceptable encoding.
+    ///
+    /// Returns `None` if none of the encodings is acceptable,
+    /// including the `identity` encoding.
+    pub fn preferred(&self) -> Option<Encoding> {
+        self.encodings
             .iter()
+            .copied()
+            .chain(self.identity.map(|qvalue| (Encoding::Identity, qvalue)))
             .filter(|(_, qvalue)| qvalue.0 > 0)
-            .max_by_key(|(encoding, qvalue)| (qvalue, encoding))
-            .map(|(encoding, _)| *encoding)
+            .max_by_key(|(encoding, qvalue)| (*qvalue, *encoding))
+            .map(|(encoding, _)| encoding)
+            .or_else(|| self.identity.is_none().then_some(Encoding::Identity))
+    }
+
+    /// Returns `true` if the given encoding is acceptable.
+    pub fn is_acceptable(&self, encoding: Encoding) -> bool {
+        if encoding == Encoding::Identity {
+            return self.identity != Some(QValue(0));
+        }
+        self.encodings
+            .iter()
+            .any(|(accepted, qvalue)| *accepted == encoding && qvalue.0 > 0)
+    }
+
+    /// Mark the given encoding as no longer acceptable,
+    /// for example because it is not available for a response.
+    pub fn remove(&mut self, encoding: Encoding) {
+        if encoding == Encoding::Identity {
+            self.identity = Some(QValue(0));
+        } else {
+            self.encodings.retain(|(accepted, _)| *accepted != encoding);
+        }
     }
 }
 
 // Allowed q-values are numbers between 0 and 1 with at most 3 digits in the fractional part. They
 // are presented here as an unsigned integer between 0 and 1000.
-#[cfg(any(
-    feature = "compression-gzip",
-    feature = "compression-br",
-    feature = "compression-zstd",
-    feature = "compression-deflate",
-    feature = "fs",
-))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct QValue(u16);
 
-#[cfg(any(
-    feature = "compression-gzip",
-    feature = "compression-br",
-    feature = "compression-zstd",
-    feature = "compression-deflate",
-    feature = "fs",
-))]
 impl QValue {
     #[inline]
     fn one() -> Self {
@@ -202,19 +331,15 @@ impl QValue {
     }
 }
 
-#[cfg(any(
-    feature = "compression-gzip",
-    feature = "compression-br",
-    feature = "compression-zstd",
-    feature = "compression-deflate",
-    feature = "fs",
-))]
 // based on https://github.com/http-rs/accept-encoding
 pub(crate) fn encodings(
-    headers: &http::HeaderMap,
+    headers: &HeaderMap,
     supported_encoding: impl SupportedEncodings,
-) -> Vec<(Encoding, QValue)> {
-    headers
+) -> AcceptedEncodings {
+    let mut accepted = AcceptedEncodings::default();
+    let mut wildcard = None;
+
+    let directives = headers
         .get_all(http::header::ACCEPT_ENCODING)
         .iter()
         .filter_map(|hval| hval.to_str().ok())
@@ -222,10 +347,7 @@ pub(crate) fn encodings(
         .filter_map(|v| {
             let mut v = v.splitn(2, ';');
 
-            let encoding = match Encoding::parse(v.next().unwrap().trim(), supported_encoding) {
-                Some(encoding) => encoding,
-                None => return None, // ignore unknown encodings
-            };
+            let coding = v.next().unwrap().trim();
 
             let qval = if let Some(qval) = v.next() {
                 QValue::parse(qval.trim())?
@@ -233,9 +355,40 @@ pub(crate) fn encodings(
                 QValue::one()
             };
 
-            Some((encoding, qval))
-        })
-        .collect::<Vec<(Encoding, QValue)>>()
+            Some((coding, qval))
+        });
+
+    for (coding, qval) in directives {
+        if coding == "*" {
+            wildcard.get_or_insert(qval);
+            continue;
+        }
+
+        match Encoding::parse(coding, supported_encoding) {
+            Some(Encoding::Identity) => {
+                accepted.identity.get_or_insert(qval);
+            }
+            // the first occurrence of an encoding wins
+            Some(encoding) if !accepted.encodings.iter().any(|(e, _)| *e == encoding) => {
+                accepted.encodings.push((encoding, qval));
+            }
+            _ => (), // ignore duplicate and unknown encodings
+        }
+    }
+
+    // the wildcard matches all encodings that were not listed explicitly
+    if let Some(qval) = wildcard {
+        for encoding in Encoding::COMPRESSED {
+            if encoding.is_supported(supported_encoding)
+                && !accepted.encodings.iter().any(|(e, _)| *e == encoding)
+            {
+                accepted.encodings.push((encoding, qval));
+            }
+        }
+        accepted.identity.get_or_insert(qval);
+    }
+
+    accepted
 }
 
 #[cfg(all(
@@ -578,4 +731,85 @@ mod tests {
         let encoding = Encoding::from_headers(&headers, SupportedEncodingsAll);
         assert_eq!(Encoding::Identity, encoding);
     }
+
+    fn accept_encoding(value: &'static str) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.append(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static(value),
+        );
+        headers
+    }
+
+    #[test]
+    fn accept_encoding_header_with_identity_qvalue_zero() {
+        let accepted = Negotiator::new().negotiate(&accept_encoding("identity;q=0"));
+        assert_eq!(None, accepted.preferred());
+        assert!(!accepted.is_acceptable(Encoding::Identity));
+
+        let accepted = Negotiator::new().negotiate(&accept_encoding("gzip;q=0.5,identity;q=0"));
+        assert_eq!(Some(Encoding::Gzip), accepted.preferred());
+
+        let accepted = Negotiator::new()
+            .gzip(false)
+            .negotiate(&accept_encoding("gzip,identity;q=0"));
+        assert_eq!(None, accepted.preferred());
+    }
+
+    #[test]
+    fn accept_encoding_header_with_identity_preferred() {
+        let accepted = Negotiator::new().negotiate(&accept_encoding("gzip;q=0.5,identity"));
+        assert_eq!(Some(Encoding::Identity), accepted.preferred());
+
+        // identity is the least preferred for equal q-values
+        let accepted = Negotiator::new().negotiate(&accept_encoding("identity,gzip"));
+        assert_eq!(Some(Encoding::Gzip), accepted.preferred());
+    }
+
+    #[test]
+    fn accept_encoding_header_with_wildcard() {
+        let accepted = Negotiator::new().negotiate(&accept_encoding("*"));
+        assert_eq!(Some(Encoding::Zstd), accepted.preferred());
+
+        let accepted = Negotiator::new()
+            .zstd(false)
+            .negotiate(&accept_encoding("*"));
+        assert_eq!(Some(Encoding::Brotli), accepted.preferred());
+
+        let accepted = Negotiator::new().negotiate(&accept_encoding("*;q=0.5,gzip"));
+        assert_eq!(Some(Encoding::Gzip), accepted.preferred());
+
+        let accepted = Negotiator::new().negotiate(&accept_encoding("br;q=0,zstd;q=0,*"));
+        assert_eq!(Some(Encoding::Gzip), accepted.preferred());
+        assert!(!accepted.is_acceptable(Encoding::Brotli));
+    }
+
+    #[test]
+    fn accept_encoding_header_with_wildcard_qvalue_zero() {
+        let accepted = Negotiator::new().negotiate(&accept_encoding("*;q=0"));
+        assert_eq!(None, accepted.preferred());
+        assert!(!accepted.is_acceptable(Encoding::Identity));
+
+        let accepted = Negotiator::new().negotiate(&accept_encoding("*;q=0,identity"));
+        assert_eq!(Some(Encoding::Identity), accepted.preferred());
+
+        let accepted = Negotiator::new().negotiate(&accept_encoding("deflate,*;q=0"));
+        assert_eq!(Some(Encoding::Deflate), accepted.preferred());
+        assert!(!accepted.is_acceptable(Encoding::Identity));
+    }
+
+    #[test]
+    fn removed_encodings_are_no_longer_preferred() {
+        let mut accepted = Negotiator::new().negotiate(&accept_encoding("br,gzip;q=0.5"));
+        assert_eq!(Some(Encoding::Brotli), accepted.preferred());
+
+        accepted.remove(Encoding::Brotli);
+        assert_eq!(Some(Encoding::Gzip), accepted.preferred());
+
+        accepted.remove(Encoding::Gzip);
+        assert_eq!(Some(Encoding::Identity), accepted.preferred());
+
+        accepted.remove(Encoding::Identity);
+        assert_eq!(None, accepted.preferred());
+    }
 }
@@ -0,0 +1,746 @@
+use http::{header, HeaderMap, HeaderValue, Uri};
+use std::{
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The default maximum number of cookies stored for a single domain.
+const DEFAULT_MAX_COOKIES_PER_DOMAIN: usize = 50;
+
+/// The default maximum number of cookies stored in a jar.
+const DEFAULT_MAX_COOKIES: usize = 3000;
+
+/// A cookie stored in a [`CookieJar`].
+///
+/// Cookies are parsed from `Set-Cookie` headers following [RFC 6265], relative to the URI of
+/// the request the response answers, which provides the defaults for the domain and path of
+/// the cookie.
+///
+/// The [`Display`](fmt::Display) implementation formats the cookie as the value of a
+/// `Set-Cookie` header, with the expiry as an absolute `Expires` attribute, such that it can
+/// be persisted and parsed again later.
+///
+/// [RFC 6265]: https://www.rfc-editor.org/rfc/rfc6265
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+}
+
+impl Cookie {
+    /// Parse the value of a `Set-Cookie` header, received in response to a request to `uri`.
+    ///
+    /// Returns `None` if the header is malformed, if `uri` has no host, if the `Domain`
+    /// attribute doesn't match the host of `uri` or is a single label, such as `com`, other
+    /// than the host itself, or if a `Secure` cookie is set over an insecure connection.
+    pub fn parse(set_cookie: &str, uri: &Uri) -> Option<Self> {
+        Self::parse_at(set_cookie, uri, SystemTime::now())
+    }
+
+    fn parse_at(set_cookie: &str, uri: &Uri, now: SystemTime) -> Option<Self> {
+        let host = uri.host()?.to_ascii_lowercase();
+
+        let mut parts = set_cookie.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut max_age = None;
+        let mut expires = None;
+        let mut domain = None;
+        let mut path = None;
+        let mut secure = false;
+        let mut http_only = false;
+        for attribute in parts {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+            if key.eq_ignore_ascii_case("max-age") {
+                if let Some(delta) = parse_max_age(value) {
+                    max_age = Some(match u64::try_from(delta) {
+                        Ok(seconds) if seconds > 0 => now
+                            .checked_add(Duration::from_secs(seconds))
+                            .unwrap_or(far_future()),
+                        _ => UNIX_EPOCH,
+                    });
+                }
+            } else if key.eq_ignore_ascii_case("expires") {
+                if let Ok(date) = httpdate::parse_http_date(value) {
+                    expires = Some(date);
+                }
+            } else if key.eq_ignore_ascii_case("domain") {
+                let value = value.strip_prefix('.').unwrap_or(value);
+                if !value.is_empty() {
+                    domain = Some(value.to_ascii_lowercase());
+                }
+            } else if key.eq_ignore_ascii_case("path") {
+                path = value.starts_with('/').then(|| value.to_owned());
+            } else if key.eq_ignore_ascii_case("secure") {
+                secure = true;
+            } else if key.eq_ignore_ascii_case("httponly") {
+                http_only = true;
+            }
+        }
+
+        if secure && !is_secure(uri) {
+            return None;
+        }
+
+        let (domain, host_only) = match domain {
+            // a cookie for a top-level domain would be sent to every site within it
+            Some(domain) if domain == host => (domain, !host.contains('.')),
+            Some(domain) if domain.contains('.') && domain_match(&host, &domain) => (domain, false),
+            Some(_) => return None,
+            None => (host, true),
+        };
+
+        Some(Cookie {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain,
+            host_only,
+            path: path.unwrap_or_else(|| default_path(uri)),
+            // `Max-Age` takes precedence over `Expires`
+            expires: max_age.or(expires),
+            secure,
+            http_only,
+        })
+    }
+
+    /// Returns the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the domain of the cookie, which is the host that set the cookie if the
+    /// cookie is [host-only](Cookie::is_host_only).
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns `true` if the cookie is only sent to the host that set it,
+    /// and not to its subdomains, as it was set without a `Domain` attribute.
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
+    /// Returns the path of the cookie.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the time at which the cookie expires,
+    /// or `None` for a session cookie, which lives as long as the jar.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Returns `true` if the cookie outlives the jar, as it was set with an expiry.
+    pub fn is_persistent(&self) -> bool {
+        self.expires.is_some()
+    }
+
+    /// Returns `true` if the cookie is only sent over secure connections.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Returns `true` if the cookie was set with the `HttpOnly` attribute.
+    ///
+    /// Such cookies are still sent with requests, the attribute only hides the cookie from
+    /// scripts run by browsers.
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, secure: bool, host: &str, path: &str) -> bool {
+        (secure || !self.secure)
+            && if self.host_only {
+                host == self.domain
+            } else {
+                domain_match(host, &self.domain)
+            }
+            && path_match(path, &self.path)
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if !self.host_only {
+            write!(f, "; Domain={}", self.domain)?;
+        }
+        write!(f, "; Path={}", self.path)?;
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        Ok(())
+    }
+}
+
+/// A jar of cookies, shared by all its clones.
+///
+/// Cookies are replaced by later cookies with the same name, domain and path, and removed
+/// once they expire. The oldest cookies are evicted once a domain, or the jar, holds too many
+/// cookies.
+///
+/// Cookies for single-label domains, such as `com`, are rejected. The jar doesn't embed the
+/// public suffix list, to reject cookies for domains such as `co.uk` as well, a lookup of the
+/// list has to be given to [`CookieJar::public_suffix_fn`].
+///
+/// See the [module docs](super) for an example.
+#[derive(Clone)]
+pub struct CookieJar {
+    store: Arc<Mutex<Store>>,
+    on_change: Option<Arc<dyn Fn(&CookieJar) + Send + Sync>>,
+    public_suffix: Option<Arc<dyn Fn(&str) -> bool + Send + Sync>>,
+    limits: Limits,
+}
+
+/// The maximum number of cookies in the jar.
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    per_domain: usize,
+    total: usize,
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self {
+            store: Default::default(),
+            on_change: None,
+            public_suffix: None,
+            // the minimum capacity RFC 6265 asks user agents to provide
+            limits: Limits {
+                per_domain: DEFAULT_MAX_COOKIES_PER_DOMAIN,
+                total: DEFAULT_MAX_COOKIES,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Store {
+    /// The cookies, with the sequence number of their creation.
+    cookies: Vec<(u64, Cookie)>,
+    next: u64,
+}
+
+impl CookieJar {
+    /// Create a new empty [`CookieJar`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` with the jar every time the cookies set by a response changed it.
+    ///
+    /// This can be used to persist the cookies, which can be restored using
+    /// [`CookieJar::insert`]. Cookies inserted directly don't trigger `f`.
+    pub fn on_change<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&CookieJar) + Send + Sync + 'static,
+    {
+        self.on_change = Some(Arc::new(f));
+        self
+    }
+
+    /// Reject cookies for domains which are public suffixes according to `f`, such as
+    /// `co.uk`, as such cookies would be sent to every site registered under the suffix.
+    ///
+    /// `f` is called with the lowercase domain of cookies set with a `Domain` attribute, and
+    /// is typically backed by the [public suffix list]. Cookies for a public suffix which is
+    /// the host that set them are kept, but only sent to that host.
+    ///
+    /// [public suffix list]: https://publicsuffix.org
+    pub fn public_suffix_fn<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.public_suffix = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the maximum number of cookies stored for a single domain, evicting the oldest
+    /// cookies of the domain once exceeded.
+    ///
+    /// Defaults to 50.
+    pub fn max_cookies_per_domain(mut self, max: usize) -> Self {
+        self.limits.per_domain = max;
+        self
+    }
+
+    /// Set the maximum number of cookies stored in the jar, evicting the oldest cookies
+    /// once exceeded.
+    ///
+    /// Defaults to 3000.
+    pub fn max_cookies(mut self, max: usize) -> Self {
+        self.limits.total = max;
+        self
+    }
+
+    /// Insert a cookie into the jar, replacing the cookie with the same name, domain and
+    /// path, if any.
+    ///
+    /// Inserting an expired cookie removes the cookie it replaces.
+    pub fn insert(&self, cookie: Cookie) {
+        self.store
+            .lock()
+            .unwrap()
+            .insert(cookie, SystemTime::now(), self.limits);
+    }
+
+    /// Returns all the cookies in the jar which haven't expired.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        let store = self.store.lock().unwrap();
+        store
+            .cookies
+            .iter()
+            .filter(|(_, cookie)| !cookie.is_expired(now))
+            .map(|(_, cookie)| cookie.clone())
+            .collect()
+    }
+
+    /// Remove all the cookies from the jar.
+    pub fn clear(&self) {
+        self.store.lock().unwrap().cookies.clear();
+    }
+
+    /// Returns the value of the `Cookie` header for a request to `uri`,
+    /// or `None` if no cookies match the request.
+    ///
+    /// Cookies with longer paths are listed first, cookies with equal paths
+    /// in the order in which they were created.
+    pub fn cookie_header(&self, uri: &Uri) -> Option<HeaderValue> {
+        self.cookie_header_at(uri, SystemTime::now())
+    }
+
+    fn cookie_header_at(&self, uri: &Uri, now: SystemTime) -> Option<HeaderValue> {
+        let host = uri.host()?.to_ascii_lowercase();
+        let secure = is_secure(uri);
+        let path = uri.path();
+
+        let mut store = self.store.lock().unwrap();
+        store.cookies.retain(|(_, cookie)| !cookie.is_expired(now));
+        let mut cookies: Vec<_> = store
+            .cookies
+            .iter()
+            .filter(|(_, cookie)| cookie.matches(secure, &host, path))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        cookies.sort_by(|(a_seq, a), (b_seq, b)| {
+            b.path.len().cmp(&a.path.len()).then(a_seq.cmp(b_seq))
+        });
+
+        let header = cookies
+            .iter()
+            .map(|(_, cookie)| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&header).ok()
+    }
+
+    /// Store the cookies set by the `Set-Cookie` headers of a response
+    /// to a request to `uri`.
+    ///
+    /// Invalid cookies, and cookies for domains `uri` doesn't belong to, are ignored.
+    pub fn store_set_cookies(&self, uri: &Uri, headers: &HeaderMap) {
+        self.store_set_cookies_at(uri, headers, SystemTime::now())
+    }
+
+    fn store_set_cookies_at(&self, uri: &Uri, headers: &HeaderMap, now: SystemTime) {
+        let Some(host) = uri.host().map(str::to_ascii_lowercase) else {
+            return;
+        };
+        let mut changed = false;
+        {
+            let mut store = self.store.lock().unwrap();
+            for value in headers.get_all(header::SET_COOKIE) {
+                let cookie = value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| Cookie::parse_at(value, uri, now))
+                    .and_then(|cookie| self.check_public_suffix(cookie, &host));
+                if let Some(cookie) = cookie {
+                    changed |= store.insert(cookie, now, self.limits);
+                }
+            }
+        }
+
+        if changed {
+            if let Some(on_change) = &self.on_change {
+                on_change(self);
+            }
+        }
+    }
+
+    /// Reject cookies for a public suffix, unless they were set by the suffix itself, in
+    /// which case they are only sent to it, see RFC 6265 section 5.3 step 5.
+    fn check_public_suffix(&self, mut cookie: Cookie, host: &str) -> Option<Cookie> {
+        let Some(is_public_suffix) = &self.public_suffix else {
+            return Some(cookie);
+        };
+        if cookie.host_only || !is_public_suffix(&cookie.domain) {
+            return Some(cookie);
+        }
+        if cookie.domain != host {
+            return None;
+        }
+        cookie.host_only = true;
+        Some(cookie)
+    }
+}
+
+impl Store {
+    /// Returns `true` if the jar changed.
+    fn insert(&mut self, cookie: Cookie, now: SystemTime, limits: Limits) -> bool {
+        let existing = self.cookies.iter().position(|(_, stored)| {
+            stored.name == cookie.name
+                && stored.domain == cookie.domain
+                && stored.path == cookie.path
+        });
+        match existing {
+            Some(index) if cookie.is_expired(now) => {
+                self.cookies.remove(index);
+                true
+            }
+            Some(index) => {
+                // the replacement keeps the creation time of the cookie it replaces
+                let stored = &mut self.cookies[index].1;
+                let changed = *stored != cookie;
+                *stored = cookie;
+                changed
+            }
+            None if cookie.is_expired(now) => false,
+            None => {
+                self.evict(&cookie.domain, now, limits);
+                self.cookies.push((self.next, cookie));
+                self.next += 1;
+                true
+            }
+        }
+    }
+
+    /// Make room for a new cookie for `domain`, removing expired cookies first,
+    /// and the oldest cookies if that isn't enough.
+    fn evict(&mut self, domain: &str, now: SystemTime, limits: Limits) {
+        let for_domain = |cookies: &[(u64, Cookie)]| {
+            cookies
+                .iter()
+                .filter(|(_, cookie)| cookie.domain == domain)
+                .count()
+        };
+        if for_domain(&self.cookies) < limits.per_domain && self.cookies.len() < limits.total {
+            return;
+        }
+        self.cookies.retain(|(_, cookie)| !cookie.is_expired(now));
+
+        // the cookies are kept in the order in which they were created
+        let excess = (for_domain(&self.cookies) + 1).saturating_sub(limits.per_domain);
+        let mut evicted = 0;
+        self.cookies.retain(|(_, cookie)| {
+            let evict = evicted < excess && cookie.domain == domain;
+            evicted += usize::from(evict);
+            !evict
+        });
+        let excess = (self.cookies.len() + 1).saturating_sub(limits.total);
+        self.cookies.drain(..excess.min(self.cookies.len()));
+    }
+}
+
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("cookies", &self.store.lock().unwrap().cookies.len())
+            .field("on_change", &self.on_change.is_some())
+            .field("public_suffix", &self.public_suffix.is_some())
+            .field("max_cookies_per_domain", &self.limits.per_domain)
+            .field("max_cookies", &self.limits.total)
+            .finish()
+    }
+}
+
+/// Parse a `Max-Age` value, which is an optionally negative number of seconds.
+fn parse_max_age(value: &str) -> Option<i64> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // too large values are capped, rather than ignored
+    Some(value.parse().unwrap_or(if value.starts_with('-') {
+        i64::MIN
+    } else {
+        i64::MAX
+    }))
+}
+
+/// Returns `true` if requests to `uri` are sent over a secure connection.
+fn is_secure(uri: &Uri) -> bool {
+    matches!(uri.scheme_str(), Some("https" | "wss"))
+}
+
+fn far_future() -> SystemTime {
+    // the latest date which can be formatted as an HTTP date
+    UNIX_EPOCH + Duration::from_secs(253_402_300_799)
+}
+
+/// Returns `true` if `host` is `domain`, or a subdomain of it.
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = host.starts_with('[') || host.parse::<IpAddr>().is_ok();
+    !is_ip
+        && host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Returns `true` if the request path `path` is within the cookie path `cookie_path`.
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    match path.strip_prefix(cookie_path) {
+        Some(rest) => rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// The path of a cookie set without a `Path` attribute,
+/// which is the "directory" of the request path.
+fn default_path(uri: &Uri) -> String {
+    match uri.path().rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(index) => uri.path()[..index].to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(uri: &str) -> Uri {
+        uri.parse().unwrap()
+    }
+
+    fn now() -> SystemTime {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        UNIX_EPOCH + Duration::from_secs(784_111_777)
+    }
+
+    #[test]
+    fn parses_attributes() {
+        let cookie = Cookie::parse_at(
+            "sid = abc ; Domain=.Example.com; Path=/api; Secure; HttpOnly; \
+             Expires=Wed, 09 Jun 2021 10:18:14 GMT; Max-Age=60",
+            &uri("https://www.example.com/login"),
+            now(),
+        )
+        .unwrap();
+        assert_eq!(cookie.name(), "sid");
+        assert_eq!(cookie.value(), "abc");
+        assert_eq!(cookie.domain(), "example.com");
+        assert!(!cookie.is_host_only());
+        assert_eq!(cookie.path(), "/api");
+        assert!(cookie.is_secure());
+        assert!(cookie.is_http_only());
+        assert_eq!(cookie.expires(), Some(now() + Duration::from_secs(60)));
+        assert_eq!(
+            cookie.to_string(),
+            "sid=abc; Domain=example.com; Path=/api; \
+             Expires=Sun, 06 Nov 1994 08:50:37 GMT; Secure; HttpOnly"
+        );
+    }
+
+    #[test]
+    fn defaults_to_request() {
+        let uri = uri("http://Example.com/a/b/c?x=1");
+        let cookie = Cookie::parse_at("a=1; Path=relative", &uri, now()).unwrap();
+        assert_eq!(cookie.domain(), "example.com");
+        assert!(cookie.is_host_only());
+        assert_eq!(cookie.path(), "/a/b");
+        assert!(!cookie.is_persistent());
+        assert_eq!(cookie.to_string(), "a=1; Path=/a/b");
+
+        let cookie = Cookie::parse_at("a=1", &self::uri("http://example.com/a"), now()).unwrap();
+        assert_eq!(cookie.path(), "/");
+    }
+
+    #[test]
+    fn rejects_invalid_cookies() {
+        let uri = uri("http://www.example.com/");
+        for set_cookie in [
+            "no-value",
+            "=value",
+            "a=1; Domain=other.com",
+            "a=1; Domain=ww.example.com",
+            "a=1; Domain=sub.www.example.com",
+            "a=1; Domain=com",
+            // secure cookies can't be set over plain http
+            "a=1; Secure",
+        ] {
+            assert_eq!(
+                Cookie::parse_at(set_cookie, &uri, now()),
+                None,
+                "{set_cookie}"
+            );
+        }
+        assert_eq!(
+            Cookie::parse_at("a=1", &self::uri("/relative"), now()),
+            None
+        );
+        assert_eq!(
+            Cookie::parse_at("a=1; Domain=0.1", &self::uri("http://127.0.0.1/"), now()),
+            None
+        );
+
+        // single-label hosts can only set host-only cookies
+        let cookie = Cookie::parse_at(
+            "a=1; Domain=localhost",
+            &self::uri("http://localhost/"),
+            now(),
+        )
+        .unwrap();
+        assert!(cookie.is_host_only());
+    }
+
+    #[test]
+    fn rejects_cookies_for_public_suffixes() {
+        let jar = CookieJar::new().public_suffix_fn(|domain| domain == "co.uk");
+        let store = |url: &str, set_cookie: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::SET_COOKIE, HeaderValue::from_static(set_cookie));
+            jar.store_set_cookies_at(&uri(url), &headers, now());
+        };
+
+        store("http://shop.example.co.uk/", "a=1; Domain=co.uk");
+        store("http://shop.example.co.uk/", "b=2; Domain=example.co.uk");
+        // the suffix itself can only set host-only cookies
+        store("http://co.uk/", "c=3; Domain=co.uk");
+
+        let cookies = jar.cookies();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].name(), "b");
+        assert_eq!(cookies[1].name(), "c");
+        assert!(cookies[1].is_host_only());
+        assert_eq!(
+            jar.cookie_header_at(&uri("http://other.co.uk/"), now()),
+            None
+        );
+    }
+
+    #[test]
+    fn evicts_oldest_cookies() {
+        let jar = CookieJar::new().max_cookies_per_domain(2).max_cookies(3);
+        let store = |url: &str, set_cookie: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::SET_COOKIE, HeaderValue::from_static(set_cookie));
+            jar.store_set_cookies_at(&uri(url), &headers, now());
+        };
+
+        store("http://a.com/", "a1=1");
+        store("http://a.com/", "a2=2");
+        store("http://a.com/", "a3=3");
+        store("http://b.com/", "b1=1");
+        store("http://c.com/", "c1=1");
+
+        let names: Vec<_> = jar
+            .cookies()
+            .iter()
+            .map(|cookie| cookie.name().to_owned())
+            .collect();
+        assert_eq!(names, ["a3", "b1", "c1"]);
+    }
+
+    #[test]
+    fn matches_domain_and_path() {
+        let jar = CookieJar::new();
+        let mut headers = HeaderMap::new();
+        for set_cookie in [
+            "host=1",
+            "domain=2; Domain=example.com",
+            "path=3; Path=/docs",
+            "secure=4; Secure",
+        ] {
+            headers.append(header::SET_COOKIE, HeaderValue::from_static(set_cookie));
+        }
+        jar.store_set_cookies_at(&uri("https://example.com/"), &headers, now());
+
+        let header = |url: &str| {
+            jar.cookie_header_at(&uri(url), now())
+                .map(|value| value.to_str().unwrap().to_owned())
+        };
+        assert_eq!(
+            header("https://example.com/docs/intro").as_deref(),
+            Some("path=3; host=1; domain=2; secure=4")
+        );
+        assert_eq!(
+            header("http://example.com/docsx").as_deref(),
+            Some("host=1; domain=2")
+        );
+        assert_eq!(
+            header("http://api.example.com/").as_deref(),
+            Some("domain=2")
+        );
+        assert_eq!(header("http://example.org/"), None);
+    }
+
+    #[test]
+    fn replaces_and_expires_cookies() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let jar = CookieJar::new().on_change({
+            let changes = changes.clone();
+            move |jar| changes.lock().unwrap().push(jar.cookies().len())
+        });
+        let uri = uri("http://example.com/");
+        let store = |set_cookie: &'static str, now: SystemTime| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::SET_COOKIE, HeaderValue::from_static(set_cookie));
+            jar.store_set_cookies_at(&uri, &headers, now);
+        };
+
+        store("a=1; Max-Age=60", now());
+        store("b=2", now());
+        store("a=1; Max-Age=60", now());
+        store("a=3; Max-Age=60", now());
+        assert_eq!(
+            jar.cookie_header_at(&uri, now()).unwrap(),
+            "a=3; b=2",
+            "replacements keep their position"
+        );
+
+        let later = now() + Duration::from_secs(60);
+        assert_eq!(jar.cookie_header_at(&uri, later).unwrap(), "b=2");
+        store("b=2; Expires=Thu, 01 Jan 1970 00:00:00 GMT", later);
+        assert_eq!(jar.cookie_header_at(&uri, later), None);
+
+        // unchanged cookies don't trigger the hook
+        assert_eq!(changes.lock().unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,289 @@
+//! Middleware that stores cookies for HTTP clients.
+//!
+//! [`CookieStore`] keeps the cookies set by responses in a [`CookieJar`], and sends them back
+//! with later requests, as browsers do. This allows clients to keep sessions with servers which
+//! identify them using cookies, such as after logging in.
+//!
+//! Cookies are handled following [RFC 6265]:
+//!
+//! - cookies set without a `Domain` attribute are only sent to the host which set them, while
+//!   cookies with a `Domain` attribute are sent to that domain and its subdomains;
+//! - cookies are only sent for requests within their `Path`, which defaults to the "directory"
+//!   of the request which set them;
+//! - `Secure` cookies are only sent over `https`, and ignored when set over `http`;
+//! - cookies for single-label domains such as `com` are rejected, as are cookies for public
+//!   suffixes such as `co.uk`, given a lookup with [`CookieJar::public_suffix_fn`];
+//! - the oldest cookies are evicted once a domain, or the jar, holds too many cookies;
+//! - cookies are removed from the jar once they expire, as set by the `Max-Age` and `Expires`
+//!   attributes, while cookies without expiry live as long as the jar.
+//!
+//! Requests are matched against cookies using their URI, which has to be absolute, as is the
+//! case for requests sent by HTTP clients. The `Host` header is used for requests with a relative
+//! URI instead. To store the cookies set by every response of a redirect chain, the
+//! [`CookieStore`] has to be wrapped by the [`FollowRedirect`] middleware, rather than the other
+//! way around.
+//!
+//! The jar can be persisted using [`CookieJar::on_change`], which is called every time a
+//! response changes the jar, and restored using [`CookieJar::insert`].
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::cookie_store::{CookieJar, CookieStoreLayer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let http_client = tower_async::service_fn(|req: Request<Full<Bytes>>| async move {
+//! #     let mut res = Response::new(Full::<Bytes>::default());
+//! #     if req.uri().path() == "/login" {
+//! #         res.headers_mut().insert(header::SET_COOKIE, "session=1337; Path=/; Max-Age=3600".parse().unwrap());
+//! #     } else {
+//! #         assert_eq!(req.headers()[header::COOKIE], "session=1337");
+//! #     }
+//! #     Ok::<_, Infallible>(res)
+//! # });
+//! let jar = CookieJar::new().on_change(|jar| {
+//!     // save the cookies which outlive the jar, to restore them later
+//!     for cookie in jar.cookies().iter().filter(|cookie| cookie.is_persistent()) {
+//!         // ...
+//!         # let _ = cookie.to_string();
+//!     }
+//! });
+//!
+//! let client = ServiceBuilder::new()
+//!     .layer(CookieStoreLayer::new(jar.clone()))
+//!     .service(http_client);
+//!
+//! // the response to the login request sets a session cookie...
+//! let request = Request::post("https://example.com/login").body(Full::default())?;
+//! client.call(request).await?;
+//! assert_eq!(jar.cookies()[0].value(), "1337");
+//!
+//! // ...which is sent with later requests
+//! let request = Request::get("https://example.com/profile").body(Full::default())?;
+//! client.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [RFC 6265]: https://www.rfc-editor.org/rfc/rfc6265
+//! [`FollowRedirect`]: crate::follow_redirect::FollowRedirect
+
+use http::{header, uri::Scheme, HeaderValue, Request, Response, Uri};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+mod jar;
+
+pub use self::jar::{Cookie, CookieJar};
+
+/// Layer that applies the [`CookieStore`] middleware.
+///
+/// See the [module docs](crate::cookie_store) for more details.
+#[derive(Clone, Debug)]
+pub struct CookieStoreLayer {
+    jar: CookieJar,
+}
+
+impl CookieStoreLayer {
+    /// Create a new [`CookieStoreLayer`], storing cookies in `jar`.
+    pub fn new(jar: CookieJar) -> Self {
+        Self { jar }
+    }
+}
+
+impl<S> Layer<S> for CookieStoreLayer {
+    type Service = CookieStore<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CookieStore {
+            inner,
+            jar: self.jar.clone(),
+        }
+    }
+}
+
+/// Middleware that sends the cookies of a [`CookieJar`] with requests,
+/// and stores the cookies set by responses in it.
+///
+/// See the [module docs](crate::cookie_store) for more details.
+#[derive(Clone, Debug)]
+pub struct CookieStore<S> {
+    inner: S,
+    jar: CookieJar,
+}
+
+impl<S> CookieStore<S> {
+    /// Create a new [`CookieStore`], storing cookies in `jar`.
+    pub fn new(inner: S, jar: CookieJar) -> Self {
+        Self { inner, jar }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns the [`CookieJar`] cookies are stored in.
+    pub fn jar(&self) -> &CookieJar {
+        &self.jar
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `CookieStore` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(jar: CookieJar) -> CookieStoreLayer {
+        CookieStoreLayer::new(jar)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CookieStore<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let uri = request_uri(&req);
+
+        if let Some(cookies) = uri.as_ref().and_then(|uri| self.jar.cookie_header(uri)) {
+            // cookies set on the request itself are kept, and sent along those of the jar
+            let mut values: Vec<_> = req
+                .headers()
+                .get_all(header::COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            values.extend(cookies.to_str().ok());
+            if let Ok(value) = HeaderValue::from_str(&values.join("; ")) {
+                req.headers_mut().insert(header::COOKIE, value);
+            }
+        }
+
+        let res = self.inner.call(req).await?;
+
+        if let Some(uri) = &uri {
+            self.jar.store_set_cookies(uri, res.headers());
+        }
+        Ok(res)
+    }
+}
+
+/// The absolute URI of a request, using the `Host` header for relative URIs.
+fn request_uri<B>(req: &Request<B>) -> Option<Uri> {
+    if req.uri().host().is_some() {
+        return Some(req.uri().clone());
+    }
+
+    let host = req.headers().get(header::HOST)?.to_str().ok()?;
+    let mut parts = req.uri().clone().into_parts();
+    parts.scheme = Some(parts.scheme.unwrap_or(Scheme::HTTP));
+    parts.authority = Some(host.parse().ok()?);
+    if parts.path_and_query.is_none() {
+        parts.path_and_query = Some("/".parse().ok()?);
+    }
+    Uri::from_parts(parts).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    /// Responds with the `Cookie` header of the request,
+    /// setting the cookies listed in the `set` query parameter.
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let cookies = req
+            .headers()
+            .get(header::COOKIE)
+            .map(|value| value.to_str().unwrap().to_owned())
+            .unwrap_or_default();
+        let mut res = Response::new(Body::from(cookies));
+        if let Some(set) = req
+            .uri()
+            .query()
+            .and_then(|query| query.strip_prefix("set="))
+        {
+            for cookie in set.split(',') {
+                res.headers_mut().append(
+                    header::SET_COOKIE,
+                    cookie.replace('+', " ").parse().unwrap(),
+                );
+            }
+        }
+        Ok(res)
+    }
+
+    async fn cookies<S>(svc: &S, req: Request<Body>) -> String
+    where
+        S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+    {
+        let res = svc.call(req).await.unwrap();
+        crate::test_helpers::to_bytes(res.into_body())
+            .await
+            .map(|bytes| String::from_utf8(bytes.to_vec()).unwrap())
+            .unwrap()
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_stored_cookies() {
+        let jar = CookieJar::new();
+        let svc = ServiceBuilder::new()
+            .layer(CookieStoreLayer::new(jar.clone()))
+            .service_fn(handle);
+
+        let req =
+            get("https://example.com/login?set=session=1;+Path=/,lang=en;+Domain=example.com");
+        assert_eq!(cookies(&svc, req).await, "");
+        assert_eq!(jar.cookies().len(), 2);
+
+        assert_eq!(
+            cookies(&svc, get("https://example.com/profile")).await,
+            "session=1; lang=en"
+        );
+        assert_eq!(
+            cookies(&svc, get("https://api.example.com/")).await,
+            "lang=en"
+        );
+        assert_eq!(cookies(&svc, get("https://example.org/")).await, "");
+
+        // cookies of the request are kept
+        let req = Request::get("/")
+            .header(header::HOST, "example.com")
+            .header(header::COOKIE, "theme=dark")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(cookies(&svc, req).await, "theme=dark; session=1; lang=en");
+
+        // deleted by the server
+        let req = get("https://example.com/logout?set=session=;+Path=/;+Max-Age=0");
+        cookies(&svc, req).await;
+        assert_eq!(
+            cookies(&svc, get("https://example.com/profile")).await,
+            "lang=en"
+        );
+    }
+
+    #[tokio::test]
+    async fn shared_jar() {
+        let jar = CookieJar::new();
+        let login = CookieStore::new(service_fn(handle), jar.clone());
+        let profile = CookieStore::new(service_fn(handle), jar);
+
+        cookies(&login, get("http://example.com/?set=session=1")).await;
+        assert_eq!(
+            cookies(&profile, get("http://example.com/profile")).await,
+            "session=1"
+        );
+    }
+}
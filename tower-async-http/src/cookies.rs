@@ -0,0 +1,584 @@
+//! Middleware that manages the cookies of requests and responses for servers.
+//!
+//! [`CookieManager`] parses the `Cookie` headers of every request into a [`Cookies`] jar,
+//! which it inserts into the extensions of the request. Handlers read the cookies sent by the
+//! client from the jar, and [add](Cookies::add) or [remove](Cookies::remove) cookies,
+//! for which the middleware adds a `Set-Cookie` header to the response. This saves handlers
+//! from parsing and formatting the headers themselves.
+//!
+//! Cookies are validated when they are added to the jar, such that values derived from user
+//! input can't inject attributes into the `Set-Cookie` header: names have to be RFC 6265
+//! tokens, values may only contain cookie octets, the domain and path may not contain `;`,
+//! and cookies with `SameSite=None` have to be [secure](ServerCookie::secure). Values which
+//! may contain other characters, such as `;` or spaces, have to be encoded by the handler,
+//! for example percent-encoded.
+//!
+//! For clients, which keep the cookies set by responses to send them with later requests,
+//! see the [`cookie_store`] module instead.
+//!
+//! [`cookie_store`]: crate::cookie_store
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::cookies::{CookieManagerLayer, Cookies, SameSite, ServerCookie};
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let jar = req.extensions().get::<Cookies>().unwrap();
+//!     let visits = jar
+//!         .get("visits")
+//!         .and_then(|cookie| cookie.value().parse::<u64>().ok())
+//!         .unwrap_or(0);
+//!     jar.add(
+//!         ServerCookie::new("visits", (visits + 1).to_string())
+//!             .path("/")
+//!             .max_age(Duration::from_secs(3600))
+//!             .http_only(true)
+//!             .same_site(SameSite::Lax),
+//!     )
+//!     .unwrap();
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(CookieManagerLayer::new())
+//!     .service_fn(handle);
+//!
+//! let req = Request::get("/")
+//!     .header(header::COOKIE, "visits=41; theme=dark")
+//!     .body(Full::default())?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(
+//!     res.headers()[header::SET_COOKIE],
+//!     "visits=42; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax",
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderMap, HeaderValue, Request, Response};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The `SameSite` attribute of a [`ServerCookie`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only send the cookie with requests from the same site.
+    Strict,
+    /// Also send the cookie when navigating to the site from another one.
+    Lax,
+    /// Send the cookie with all requests, which requires the cookie to be [secure](ServerCookie::secure).
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SameSite::Strict => f.write_str("Strict"),
+            SameSite::Lax => f.write_str("Lax"),
+            SameSite::None => f.write_str("None"),
+        }
+    }
+}
+
+/// A cookie as seen by a server, either sent by the client, in which case only its name and
+/// value are known, or set by the server.
+///
+/// Not to be confused with the cookies kept by clients, see [`cookie_store::Cookie`].
+///
+/// [`cookie_store::Cookie`]: crate::cookie_store::Cookie
+///
+/// The [`Display`](fmt::Display) implementation formats the cookie as the value of a
+/// `Set-Cookie` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerCookie {
+    name: String,
+    value: String,
+    domain: Option<String>,
+    path: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl ServerCookie {
+    /// Create a new [`ServerCookie`] with the given name and value, and no attributes.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            domain: None,
+            path: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Set the `Domain` attribute, sending the cookie to the domain and its subdomains.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Set the `Path` attribute, sending the cookie with requests within the path.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Set the `Max-Age` attribute, after which the client removes the cookie.
+    ///
+    /// Cookies without it are removed when the client is closed.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Set the `Secure` attribute, only sending the cookie over `https`.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set the `HttpOnly` attribute, hiding the cookie from scripts.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Returns the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the `Domain` attribute, if set.
+    pub fn get_domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// Returns the `Path` attribute, if set.
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Returns the `Max-Age` attribute, if set.
+    pub fn get_max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Returns an error if the `Set-Cookie` header of the cookie would be malformed,
+    /// or could be misinterpreted by the client.
+    fn validate(&self) -> Result<(), InvalidCookie> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token) {
+            return Err(InvalidCookie::Name);
+        }
+        let value = self
+            .value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(&self.value);
+        if !value.bytes().all(is_cookie_octet) {
+            return Err(InvalidCookie::Value);
+        }
+        if !self.domain.as_deref().is_none_or(is_attribute_value) {
+            return Err(InvalidCookie::Domain);
+        }
+        if !self.path.as_deref().is_none_or(is_attribute_value) {
+            return Err(InvalidCookie::Path);
+        }
+        if self.same_site == Some(SameSite::None) && !self.secure {
+            return Err(InvalidCookie::InsecureSameSiteNone);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the cookie is set for the same name, domain and path,
+    /// in which case it replaces the other one at the client.
+    fn replaces(&self, other: &ServerCookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+impl fmt::Display for ServerCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether the byte is a `tchar` of RFC 9110, allowed in cookie names.
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Returns whether the byte is a `cookie-octet` of RFC 6265, allowed in cookie values.
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+/// Returns whether the value of a `Domain` or `Path` attribute is visible ASCII without `;`.
+fn is_attribute_value(value: &str) -> bool {
+    value.bytes().all(|b| matches!(b, 0x20..=0x7E) && b != b';')
+}
+
+/// Error returned by [`Cookies::add`] and [`Cookies::remove`] for cookies which can't be sent
+/// to the client as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidCookie {
+    /// The name is empty or isn't a token.
+    Name,
+    /// The value contains characters which aren't cookie octets, such as `;` or spaces.
+    Value,
+    /// The domain contains `;` or characters which aren't visible ASCII.
+    Domain,
+    /// The path contains `;` or characters which aren't visible ASCII.
+    Path,
+    /// The cookie has `SameSite=None`, but isn't secure.
+    InsecureSameSiteNone,
+}
+
+impl fmt::Display for InvalidCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidCookie::Name => f.write_str("invalid cookie name"),
+            InvalidCookie::Value => f.write_str("invalid cookie value"),
+            InvalidCookie::Domain => f.write_str("invalid cookie domain"),
+            InvalidCookie::Path => f.write_str("invalid cookie path"),
+            InvalidCookie::InsecureSameSiteNone => {
+                f.write_str("cookie with SameSite=None isn't secure")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidCookie {}
+
+/// The cookies of a request, inserted into its extensions by [`CookieManager`].
+///
+/// Changes to the jar are sent to the client as `Set-Cookie` headers of the response.
+/// All clones of the jar share the same cookies.
+///
+/// See the [module docs](self) for an example.
+#[derive(Clone, Default)]
+pub struct Cookies {
+    inner: Arc<Mutex<Jar>>,
+}
+
+#[derive(Default)]
+struct Jar {
+    cookies: Vec<ServerCookie>,
+    changes: Vec<ServerCookie>,
+}
+
+impl Cookies {
+    /// Create a new [`Cookies`] holding the cookies of the `Cookie` headers.
+    ///
+    /// Malformed cookies are ignored.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let cookies = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                let name = name.trim();
+                (!name.is_empty()).then(|| ServerCookie::new(name, value.trim().trim_matches('"')))
+            })
+            .collect();
+        Self {
+            inner: Arc::new(Mutex::new(Jar {
+                cookies,
+                changes: Vec::new(),
+            })),
+        }
+    }
+
+    /// Returns the cookie with the given name, including cookies added to the jar.
+    ///
+    /// Clients may send multiple cookies with the same name, for different paths,
+    /// in which case the first one is returned.
+    pub fn get(&self, name: &str) -> Option<ServerCookie> {
+        let jar = self.inner.lock().unwrap();
+        jar.cookies
+            .iter()
+            .find(|cookie| cookie.name == name)
+            .cloned()
+    }
+
+    /// Returns all cookies, including cookies added to the jar.
+    pub fn list(&self) -> Vec<ServerCookie> {
+        self.inner.lock().unwrap().cookies.clone()
+    }
+
+    /// Add a cookie, which is sent to the client with the response.
+    ///
+    /// Replaces the cookie with the same name, as well as earlier changes to the cookie
+    /// with the same name, domain and path. Fails for cookies which aren't valid, see the
+    /// [module docs](self), leaving the jar unchanged.
+    pub fn add(&self, cookie: ServerCookie) -> Result<(), InvalidCookie> {
+        cookie.validate()?;
+        let mut jar = self.inner.lock().unwrap();
+        jar.cookies.retain(|c| c.name != cookie.name);
+        jar.cookies.push(cookie.clone());
+        jar.changes.retain(|c| !c.replaces(&cookie));
+        jar.changes.push(cookie);
+        Ok(())
+    }
+
+    /// Remove a cookie, which tells the client to remove it with the response.
+    ///
+    /// The domain and path of the cookie have to match the ones it was set with.
+    /// Fails for cookies which aren't valid, see the [module docs](self), leaving the jar
+    /// unchanged.
+    pub fn remove(&self, cookie: ServerCookie) -> Result<(), InvalidCookie> {
+        let removal = ServerCookie {
+            value: String::new(),
+            max_age: Some(Duration::ZERO),
+            ..cookie
+        };
+        removal.validate()?;
+        let mut jar = self.inner.lock().unwrap();
+        jar.cookies.retain(|c| c.name != removal.name);
+        jar.changes.retain(|c| !c.replaces(&removal));
+        jar.changes.push(removal);
+        Ok(())
+    }
+
+    /// Returns the cookies which were added or removed, in the order they were changed.
+    pub fn changes(&self) -> Vec<ServerCookie> {
+        self.inner.lock().unwrap().changes.clone()
+    }
+}
+
+impl fmt::Debug for Cookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let jar = self.inner.lock().unwrap();
+        f.debug_struct("Cookies")
+            .field("cookies", &jar.cookies)
+            .field("changes", &jar.changes)
+            .finish()
+    }
+}
+
+/// Layer that applies the [`CookieManager`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CookieManagerLayer {
+    _priv: (),
+}
+
+impl CookieManagerLayer {
+    /// Create a new [`CookieManagerLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for CookieManagerLayer {
+    type Service = CookieManager<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CookieManager::new(inner)
+    }
+}
+
+/// Middleware that inserts the [`Cookies`] of a request into its extensions,
+/// and sends the changes to the jar as `Set-Cookie` headers of the response.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct CookieManager<S> {
+    inner: S,
+}
+
+impl<S> CookieManager<S> {
+    /// Create a new [`CookieManager`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `CookieManager` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> CookieManagerLayer {
+        CookieManagerLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CookieManager<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let jar = Cookies::from_headers(req.headers());
+        req.extensions_mut().insert(jar.clone());
+
+        let mut res = self.inner.call(req).await?;
+
+        let headers = res.headers_mut();
+        for cookie in jar.changes() {
+            if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                headers.append(header::SET_COOKIE, value);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    #[test]
+    fn parses_cookie_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::COOKIE, HeaderValue::from_static("a=1; b=\"2\""));
+        headers.append(
+            header::COOKIE,
+            HeaderValue::from_static("malformed; c = 3;"),
+        );
+
+        let jar = Cookies::from_headers(&headers);
+        let cookies: Vec<_> = jar
+            .list()
+            .iter()
+            .map(|cookie| format!("{}", cookie))
+            .collect();
+        assert_eq!(cookies, ["a=1", "b=2", "c=3"]);
+        assert_eq!(jar.get("b").unwrap().value(), "2");
+        assert!(jar.get("malformed").is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_changes_as_set_cookie_headers() {
+        let svc = ServiceBuilder::new()
+            .layer(CookieManagerLayer::new())
+            .service_fn(|req: Request<Body>| async move {
+                let jar = req.extensions().get::<Cookies>().unwrap();
+                assert_eq!(jar.get("session").unwrap().value(), "old");
+
+                jar.add(ServerCookie::new("session", "first").path("/"))
+                    .unwrap();
+                jar.add(ServerCookie::new("session", "new").path("/").secure(true))
+                    .unwrap();
+                jar.remove(ServerCookie::new("tracking", "").domain("example.com"))
+                    .unwrap();
+                assert_eq!(jar.get("session").unwrap().value(), "new");
+                assert!(jar.get("tracking").is_none());
+
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let req = Request::builder()
+            .header(header::COOKIE, "session=old; tracking=1")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+
+        let set_cookies: Vec<_> = res.headers().get_all(header::SET_COOKIE).iter().collect();
+        assert_eq!(
+            set_cookies,
+            [
+                "session=new; Path=/; Secure",
+                "tracking=; Domain=example.com; Max-Age=0"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cookies() {
+        let jar = Cookies::default();
+        let cases = [
+            (ServerCookie::new("a b", "1"), InvalidCookie::Name),
+            (ServerCookie::new("", "1"), InvalidCookie::Name),
+            (
+                ServerCookie::new("id", "x; Domain=evil.example; Path=/"),
+                InvalidCookie::Value,
+            ),
+            (ServerCookie::new("id", "a\"b"), InvalidCookie::Value),
+            (
+                ServerCookie::new("id", "1").domain("example.com; Secure"),
+                InvalidCookie::Domain,
+            ),
+            (
+                ServerCookie::new("id", "1").path("/;HttpOnly"),
+                InvalidCookie::Path,
+            ),
+            (
+                ServerCookie::new("id", "1").same_site(SameSite::None),
+                InvalidCookie::InsecureSameSiteNone,
+            ),
+        ];
+        for (cookie, err) in cases {
+            assert_eq!(jar.add(cookie).unwrap_err(), err);
+        }
+        assert!(jar.changes().is_empty());
+
+        jar.add(
+            ServerCookie::new("id", "\"quoted\"")
+                .same_site(SameSite::None)
+                .secure(true),
+        )
+        .unwrap();
+        assert_eq!(
+            jar.changes()[0].to_string(),
+            "id=\"quoted\"; Secure; SameSite=None"
+        );
+    }
+}
@@ -4,6 +4,7 @@ use http::{
     header::{self, HeaderName, HeaderValue},
     request::Parts as RequestParts,
 };
+use tower_async::dynamic::Dynamic;
 
 use super::{Any, WILDCARD};
 
@@ -58,6 +59,18 @@ impl AllowOrigin {
         }
     }
 
+    /// Set multiple allowed origins, which can be updated while the service is running
+    ///
+    /// Updates of the list apply to subsequent requests. Unlike [`AllowOrigin::list`], a
+    /// wildcard (`*`) in the list is not rejected, but never matches the origin of a request.
+    ///
+    /// See [`CorsLayer::allow_origin`] for more details.
+    ///
+    /// [`CorsLayer::allow_origin`]: super::CorsLayer::allow_origin
+    pub fn dynamic(origins: Dynamic<Vec<HeaderValue>>) -> Self {
+        Self(OriginInner::Dynamic(origins))
+    }
+
     /// Set the allowed origins from a predicate
     ///
     /// See [`CorsLayer::allow_origin`] for more details.
@@ -95,6 +108,7 @@ impl AllowOrigin {
         let allow_origin = match &self.0 {
             OriginInner::Const(v) => v.clone(),
             OriginInner::List(l) => origin.filter(|o| l.contains(o))?.clone(),
+            OriginInner::Dynamic(l) => origin.filter(|o| l.load().contains(o))?.clone(),
             OriginInner::Predicate(c) => origin.filter(|origin| c(origin, parts))?.clone(),
         };
 
@@ -107,6 +121,7 @@ impl fmt::Debug for AllowOrigin {
         match &self.0 {
             OriginInner::Const(inner) => f.debug_tuple("Const").field(inner).finish(),
             OriginInner::List(inner) => f.debug_tuple("List").field(inner).finish(),
+            OriginInner::Dynamic(inner) => f.debug_tuple("Dynamic").field(inner).finish(),
             OriginInner::Predicate(_) => f.debug_tuple("Predicate").finish(),
         }
     }
@@ -137,10 +152,17 @@ impl From<Vec<HeaderValue>> for AllowOrigin {
     }
 }
 
+impl From<Dynamic<Vec<HeaderValue>>> for AllowOrigin {
+    fn from(origins: Dynamic<Vec<HeaderValue>>) -> Self {
+        Self::dynamic(origins)
+    }
+}
+
 #[derive(Clone)]
 enum OriginInner {
     Const(HeaderValue),
     List(Vec<HeaderValue>),
+    Dynamic(Dynamic<Vec<HeaderValue>>),
     Predicate(
         Arc<dyn for<'a> Fn(&'a HeaderValue, &'a RequestParts) -> bool + Send + Sync + 'static>,
     ),
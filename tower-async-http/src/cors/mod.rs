@@ -50,9 +50,10 @@
 use bytes::{BufMut, BytesMut};
 use http::{
     header::{self, HeaderName},
+    request::Parts as RequestParts,
     HeaderMap, HeaderValue, Method, Request, Response,
 };
-use std::{array, mem};
+use std::{array, borrow::Cow, future::Future, mem};
 use tower_async_layer::Layer;
 use tower_async_service::Service;
 
@@ -63,8 +64,11 @@ mod allow_origin;
 mod allow_private_network;
 mod expose_headers;
 mod max_age;
+mod policy;
 mod vary;
 
+use self::policy::PolicyFn;
+
 pub use self::{
     allow_credentials::AllowCredentials, allow_headers::AllowHeaders, allow_methods::AllowMethods,
     allow_origin::AllowOrigin, allow_private_network::AllowPrivateNetwork,
@@ -87,6 +91,7 @@ pub struct CorsLayer {
     expose_headers: ExposeHeaders,
     max_age: MaxAge,
     vary: Vary,
+    policy_fn: Option<PolicyFn>,
 }
 
 #[allow(clippy::declare_interior_mutable_const)]
@@ -110,6 +115,7 @@ impl CorsLayer {
             expose_headers: Default::default(),
             max_age: Default::default(),
             vary: Default::default(),
+            policy_fn: None,
         }
     }
 
@@ -314,6 +320,20 @@ impl CorsLayer {
     /// ));
     /// ```
     ///
+    /// Or a list of origins which can be updated while the service is running,
+    /// such as from a configuration watcher
+    ///
+    /// ```
+    /// use tower_async::dynamic::Dynamic;
+    /// use tower_async_http::cors::CorsLayer;
+    ///
+    /// let origins = Dynamic::new(vec!["http://example.com".parse().unwrap()]);
+    /// let layer = CorsLayer::new().allow_origin(origins.clone());
+    ///
+    /// // applies to subsequent requests
+    /// origins.set(vec!["http://api.example.com".parse().unwrap()]);
+    /// ```
+    ///
     /// Note that multiple calls to this method will override any previous
     /// calls.
     ///
@@ -389,6 +409,50 @@ impl CorsLayer {
         self.vary = headers.into();
         self
     }
+
+    /// Look up the CORS policy of every request with an `Origin` header using an async callback,
+    /// such as from a database or a cache of the allowed origins of every tenant.
+    ///
+    /// The callback returns the policy to apply to the request, configured like any other
+    /// `CorsLayer`, or `None` to deny the origin, in which case no CORS headers are sent.
+    /// Policies combining `Access-Control-Allow-Credentials: true` with a wildcard (`*`),
+    /// which [`CorsLayer`] rejects with a panic when applied, are denied as well. The
+    /// configuration of this layer applies to requests without an `Origin` header.
+    ///
+    /// The [`Vary`][mdn] header of this layer is sent in all responses, rather than the one
+    /// of the returned policy, such that caches see the same `Vary` header for every origin.
+    /// `origin` is added to it, as the response depends on the origin of the request.
+    ///
+    /// ```
+    /// use tower_async_http::cors::CorsLayer;
+    /// use http::{request::Parts as RequestParts, HeaderValue, Method};
+    ///
+    /// # async fn allowed_origins(tenant: &str) -> Vec<HeaderValue> { Vec::new() }
+    /// let layer = CorsLayer::new().policy_fn(|origin: &HeaderValue, parts: &RequestParts| {
+    ///     let origin = origin.clone();
+    ///     let tenant = parts.uri.host().unwrap_or_default().to_owned();
+    ///     async move {
+    ///         if !allowed_origins(&tenant).await.contains(&origin) {
+    ///             return None;
+    ///         }
+    ///         let policy = CorsLayer::new()
+    ///             .allow_origin(origin)
+    ///             .allow_methods([Method::GET, Method::POST])
+    ///             .allow_credentials(true);
+    ///         Some(policy)
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Vary
+    pub fn policy_fn<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(&HeaderValue, &RequestParts) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<CorsLayer>> + Send + 'static,
+    {
+        self.policy_fn = Some(PolicyFn::new(f));
+        self
+    }
 }
 
 /// Represents a wildcard value (`*`) used with some CORS headers such as
@@ -578,6 +642,17 @@ impl<S> Cors<S> {
         self.map_layer(|layer| layer.allow_private_network(allow_private_network))
     }
 
+    /// Look up the CORS policy of every request with an `Origin` header using an async callback.
+    ///
+    /// See [`CorsLayer::policy_fn`] for more details.
+    pub fn policy_fn<F, Fut>(self, f: F) -> Self
+    where
+        F: Fn(&HeaderValue, &RequestParts) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<CorsLayer>> + Send + 'static,
+    {
+        self.map_layer(|layer| layer.policy_fn(f))
+    }
+
     fn map_layer<F>(mut self, f: F) -> Self
     where
         F: FnOnce(CorsLayer) -> CorsLayer,
@@ -599,16 +674,31 @@ where
         let (parts, body) = req.into_parts();
         let origin = parts.headers.get(&header::ORIGIN);
 
+        let policy = match (&self.layer.policy_fn, origin) {
+            (Some(policy_fn), Some(origin)) => {
+                policy_fn.lookup(origin, &parts).await.map(Cow::Owned)
+            }
+            _ => Some(Cow::Borrowed(&self.layer)),
+        };
+
         let mut headers = HeaderMap::new();
 
         // These headers are applied to both preflight and subsequent regular CORS requests:
         // https://fetch.spec.whatwg.org/#http-responses
 
-        headers.extend(self.layer.allow_origin.to_header(origin, &parts));
-        headers.extend(self.layer.allow_credentials.to_header(origin, &parts));
-        headers.extend(self.layer.allow_private_network.to_header(origin, &parts));
+        if let Some(policy) = &policy {
+            headers.extend(policy.allow_origin.to_header(origin, &parts));
+            headers.extend(policy.allow_credentials.to_header(origin, &parts));
+            headers.extend(policy.allow_private_network.to_header(origin, &parts));
+        }
 
-        let mut vary_headers = self.layer.vary.values();
+        let mut vary_headers = self.layer.vary.values().chain(
+            self.layer
+                .policy_fn
+                .as_ref()
+                .filter(|_| !self.layer.vary.contains(&header::ORIGIN))
+                .map(|_| HeaderValue::from(header::ORIGIN)),
+        );
         if let Some(first) = vary_headers.next() {
             let mut header = match headers.entry(header::VARY) {
                 header::Entry::Occupied(_) => {
@@ -625,9 +715,11 @@ where
         // Return results immediately upon preflight request
         if parts.method == Method::OPTIONS {
             // These headers are applied only to preflight requests
-            headers.extend(self.layer.allow_methods.to_header(&parts));
-            headers.extend(self.layer.allow_headers.to_header(&parts));
-            headers.extend(self.layer.max_age.to_header(origin, &parts));
+            if let Some(policy) = &policy {
+                headers.extend(policy.allow_methods.to_header(&parts));
+                headers.extend(policy.allow_headers.to_header(&parts));
+                headers.extend(policy.max_age.to_header(origin, &parts));
+            }
 
             let mut response = Response::new(ResBody::default());
             mem::swap(response.headers_mut(), &mut headers);
@@ -635,7 +727,9 @@ where
             Ok(response)
         } else {
             // This header is applied only to non-preflight requests
-            headers.extend(self.layer.expose_headers.to_header(&parts));
+            if let Some(policy) = &policy {
+                headers.extend(policy.expose_headers.to_header(&parts));
+            }
 
             let req = Request::from_parts(parts, body);
 
@@ -648,30 +742,38 @@ where
 }
 
 fn ensure_usable_cors_rules(layer: &CorsLayer) {
-    if layer.allow_credentials.is_true() {
-        assert!(
-            !layer.allow_headers.is_wildcard(),
-            "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` \
-             with `Access-Control-Allow-Headers: *`"
-        );
+    if let Some(violation) = cors_rules_violation(layer) {
+        panic!("{}", violation);
+    }
+}
 
-        assert!(
-            !layer.allow_methods.is_wildcard(),
-            "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` \
-             with `Access-Control-Allow-Methods: *`"
-        );
+fn cors_rules_violation(layer: &CorsLayer) -> Option<&'static str> {
+    if !layer.allow_credentials.is_true() {
+        return None;
+    }
 
-        assert!(
-            !layer.allow_origin.is_wildcard(),
+    if layer.allow_headers.is_wildcard() {
+        Some(
             "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` \
-             with `Access-Control-Allow-Origin: *`"
-        );
-
-        assert!(
-            !layer.expose_headers.is_wildcard(),
+             with `Access-Control-Allow-Headers: *`",
+        )
+    } else if layer.allow_methods.is_wildcard() {
+        Some(
             "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` \
-             with `Access-Control-Expose-Headers: *`"
-        );
+             with `Access-Control-Allow-Methods: *`",
+        )
+    } else if layer.allow_origin.is_wildcard() {
+        Some(
+            "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` \
+             with `Access-Control-Allow-Origin: *`",
+        )
+    } else if layer.expose_headers.is_wildcard() {
+        Some(
+            "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` \
+             with `Access-Control-Expose-Headers: *`",
+        )
+    } else {
+        None
     }
 }
 
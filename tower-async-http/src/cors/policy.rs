@@ -0,0 +1,143 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use http::{header::HeaderValue, request::Parts as RequestParts};
+
+use super::CorsLayer;
+
+type PolicyFuture = Pin<Box<dyn Future<Output = Option<CorsLayer>> + Send>>;
+
+/// Holds the async callback looking up the CORS policy of a request.
+///
+/// See [`CorsLayer::policy_fn`] for more details.
+#[derive(Clone)]
+pub(super) struct PolicyFn(Arc<dyn Fn(&HeaderValue, &RequestParts) -> PolicyFuture + Send + Sync>);
+
+impl PolicyFn {
+    pub(super) fn new<F, Fut>(f: F) -> Self
+    where
+        F: Fn(&HeaderValue, &RequestParts) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<CorsLayer>> + Send + 'static,
+    {
+        Self(Arc::new(move |origin, parts| Box::pin(f(origin, parts))))
+    }
+
+    /// Looks up the policy for the origin of the request, denying policies
+    /// which can't be used, instead of panicking like [`CorsLayer::layer`].
+    ///
+    /// [`CorsLayer::layer`]: tower_async_layer::Layer::layer
+    pub(super) async fn lookup(
+        &self,
+        origin: &HeaderValue,
+        parts: &RequestParts,
+    ) -> Option<CorsLayer> {
+        let policy = (self.0)(origin, parts).await?;
+        if super::cors_rules_violation(&policy).is_some() {
+            return None;
+        }
+        Some(policy)
+    }
+}
+
+impl fmt::Debug for PolicyFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PolicyFn").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cors::{Any, CorsLayer};
+    use crate::test_helpers::Body;
+
+    use http::{header, request::Parts, HeaderValue, Method, Request, Response};
+    use tower_async::{BoxError, ServiceBuilder};
+    use tower_async_service::Service;
+
+    fn layer() -> CorsLayer {
+        CorsLayer::new().policy_fn(|origin: &HeaderValue, _: &Parts| {
+            let origin = origin.clone();
+            async move {
+                match origin.to_str().ok()? {
+                    "https://app.example.com" => Some(
+                        CorsLayer::new()
+                            .allow_origin(origin)
+                            .allow_methods([Method::GET, Method::PUT])
+                            .allow_credentials(true),
+                    ),
+                    // credentials can't be combined with a wildcard, so this policy is denied
+                    "https://evil.example.com" => {
+                        Some(CorsLayer::new().allow_origin(Any).allow_credentials(true))
+                    }
+                    _ => None,
+                }
+            }
+        })
+    }
+
+    fn request(method: Method, origin: &'static str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn applies_policy_of_origin() {
+        let service = ServiceBuilder::new().layer(layer()).service_fn(echo);
+
+        let res = service
+            .call(request(Method::OPTIONS, "https://app.example.com"))
+            .await
+            .unwrap();
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET,PUT");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert!(headers
+            .get_all(header::VARY)
+            .iter()
+            .any(|value| value == "origin"));
+
+        let res = service
+            .call(request(Method::GET, "https://app.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn denies_unknown_and_unusable_policies() {
+        let service = ServiceBuilder::new()
+            .layer(layer().vary([header::ACCESS_CONTROL_REQUEST_METHOD]))
+            .service_fn(echo);
+
+        for origin in ["https://other.example.com", "https://evil.example.com"] {
+            let res = service
+                .call(request(Method::OPTIONS, origin))
+                .await
+                .unwrap();
+            let headers = res.headers();
+            assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+            assert!(headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).is_none());
+            assert!(headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .is_none());
+
+            // the response depends on the origin, even if its policy was denied
+            let vary: Vec<_> = headers.get_all(header::VARY).iter().collect();
+            assert_eq!(vary, ["access-control-request-method", "origin"]);
+        }
+    }
+
+    async fn echo<Body>(req: Request<Body>) -> Result<Response<Body>, BoxError> {
+        Ok(Response::new(req.into_body()))
+    }
+}
@@ -29,6 +29,10 @@ impl Vary {
     pub(super) fn values(&self) -> impl Iterator<Item = HeaderValue> + '_ {
         self.0.iter().cloned()
     }
+
+    pub(super) fn contains(&self, name: &HeaderName) -> bool {
+        self.0.iter().any(|value| value == name.as_str())
+    }
 }
 
 impl Default for Vary {
@@ -0,0 +1,378 @@
+//! Middleware that propagates request deadlines between services.
+//!
+//! A client can tell a server how long it is willing to wait for a response by sending its
+//! remaining budget in a header, such as the `grpc-timeout` header of gRPC. The server then
+//! stops working on requests the client gave up on, and passes the remaining budget on to
+//! the services it calls in turn.
+//!
+//! - [`EnforceDeadline`] reads the header of incoming requests, stores the resulting
+//!   [`Deadline`] in the request extensions, makes it the deadline of the task handling the
+//!   request, and responds with `504 Gateway Timeout` once it expires. For gRPC, a
+//!   `DEADLINE_EXCEEDED` status is sent instead, which is classified as such by
+//!   [`GrpcErrorsAsFailures`].
+//! - [`PropagateDeadline`] sets the header of outgoing requests from the remaining budget of
+//!   the current [`Deadline`], such that the deadline carries over to the next service.
+//!
+//! The header and its format are configured using a [`DeadlineHeader`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{service_fn, Service, ServiceBuilder};
+//! use tower_async_http::deadline::{DeadlineHeader, EnforceDeadlineLayer, PropagateDeadlineLayer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let header = DeadlineHeader::millis("x-request-timeout".parse()?);
+//!
+//! // the client used by the handler to call a downstream service
+//! let client = ServiceBuilder::new()
+//!     .layer(PropagateDeadlineLayer::new(header.clone()))
+//!     .service_fn(|req: Request<Full<Bytes>>| async move {
+//!         // ...
+//!         # let _ = req;
+//!         Ok::<_, Infallible>(Response::new(Full::<Bytes>::default()))
+//!     });
+//!
+//! let svc = ServiceBuilder::new()
+//!     // never wait longer than 30 seconds, whatever the client asks for
+//!     .layer(EnforceDeadlineLayer::new(header).max_timeout(Duration::from_secs(30)))
+//!     .service_fn(move |_: Request<Full<Bytes>>| {
+//!         let client = client.clone();
+//!         async move {
+//!             // the downstream request is sent with the remaining budget
+//!             client.call(Request::new(Full::default())).await
+//!         }
+//!     });
+//!
+//! let request = Request::builder()
+//!     .header("x-request-timeout", "5000")
+//!     .body(Full::default())?;
+//! let response = svc.call(request).await?;
+//! # let _ = response;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`GrpcErrorsAsFailures`]: crate::classify::GrpcErrorsAsFailures
+
+use http::{header::HeaderName, HeaderMap, HeaderValue, Response, StatusCode};
+use std::time::Duration;
+
+mod service;
+
+pub use self::service::{
+    EnforceDeadline, EnforceDeadlineLayer, PropagateDeadline, PropagateDeadlineLayer,
+};
+pub use tower_async::timeout::Deadline;
+
+/// The header carrying the deadline of a request, and its format.
+#[derive(Debug, Clone)]
+pub struct DeadlineHeader {
+    name: HeaderName,
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    GrpcTimeout,
+    Millis,
+}
+
+/// The largest value allowed by the `grpc-timeout` header, which has at most 8 digits.
+const GRPC_TIMEOUT_MAX: u128 = 99_999_999;
+
+impl DeadlineHeader {
+    /// The `grpc-timeout` header used by gRPC, such as `100m` for 100 milliseconds.
+    ///
+    /// Expired deadlines are reported with a `DEADLINE_EXCEEDED` gRPC status.
+    pub fn grpc_timeout() -> Self {
+        DeadlineHeader {
+            name: HeaderName::from_static("grpc-timeout"),
+            format: Format::GrpcTimeout,
+        }
+    }
+
+    /// A header holding the remaining budget in milliseconds, such as `x-request-timeout: 100`.
+    ///
+    /// Expired deadlines are reported with a `504 Gateway Timeout` response.
+    pub fn millis(name: HeaderName) -> Self {
+        DeadlineHeader {
+            name,
+            format: Format::Millis,
+        }
+    }
+
+    /// Returns the name of the header.
+    pub fn name(&self) -> &HeaderName {
+        &self.name
+    }
+
+    /// Parse the timeout from the header, if present and valid.
+    pub(crate) fn decode(&self, headers: &HeaderMap) -> Option<Duration> {
+        let value = headers.get(&self.name)?.to_str().ok()?;
+        match self.format {
+            Format::GrpcTimeout => parse_grpc_timeout(value),
+            Format::Millis => value.parse().ok().map(Duration::from_millis),
+        }
+    }
+
+    pub(crate) fn encode(&self, timeout: Duration) -> HeaderValue {
+        match self.format {
+            Format::GrpcTimeout => encode_grpc_timeout(timeout),
+            Format::Millis => HeaderValue::from(timeout.as_millis().min(u64::MAX.into()) as u64),
+        }
+    }
+
+    /// The response sent once the deadline of a request expired.
+    pub(crate) fn deadline_exceeded<B>(&self) -> Response<B>
+    where
+        B: Default,
+    {
+        let mut res = Response::new(B::default());
+        match self.format {
+            Format::GrpcTimeout => {
+                // a trailers-only response with the `DEADLINE_EXCEEDED` status
+                res.headers_mut().insert(
+                    http::header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/grpc"),
+                );
+                res.headers_mut()
+                    .insert("grpc-status", HeaderValue::from_static("4"));
+            }
+            Format::Millis => *res.status_mut() = StatusCode::GATEWAY_TIMEOUT,
+        }
+        res.extensions_mut().insert(DeadlineExceeded { _priv: () });
+        res
+    }
+}
+
+/// Response extension marking responses sent by [`EnforceDeadline`]
+/// because the deadline of the request expired.
+#[derive(Debug, Clone, Copy)]
+pub struct DeadlineExceeded {
+    _priv: (),
+}
+
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if !(2..=9).contains(&value.len()) {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value: u64 = digits.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(value * 60 * 60),
+        "M" => Duration::from_secs(value * 60),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+fn encode_grpc_timeout(timeout: Duration) -> HeaderValue {
+    const UNITS: [(u128, char); 6] = [
+        (1, 'n'),
+        (1_000, 'u'),
+        (1_000_000, 'm'),
+        (1_000_000_000, 'S'),
+        (60 * 1_000_000_000, 'M'),
+        (60 * 60 * 1_000_000_000, 'H'),
+    ];
+
+    let nanos = timeout.as_nanos();
+    // use the most precise unit that fits, rounding down to never extend the deadline
+    let (value, unit) = UNITS
+        .iter()
+        .map(|&(nanos_per_unit, unit)| (nanos / nanos_per_unit, unit))
+        .find(|&(value, _)| value <= GRPC_TIMEOUT_MAX)
+        .unwrap_or((GRPC_TIMEOUT_MAX, 'H'));
+    HeaderValue::try_from(format!("{value}{unit}")).expect("valid header value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::classify::{
+        ClassifiedResponse, ClassifyResponse, GrpcErrorsAsFailures, GrpcFailureClass,
+    };
+    use crate::test_helpers::Body;
+    use http::Request;
+    use tokio::time::Instant;
+    use tower_async::{service_fn, BoxError, Layer, Service};
+
+    #[test]
+    fn grpc_timeout_format() {
+        for (value, timeout) in [
+            ("1H", Duration::from_secs(60 * 60)),
+            ("2M", Duration::from_secs(2 * 60)),
+            ("30S", Duration::from_secs(30)),
+            ("100m", Duration::from_millis(100)),
+            ("12u", Duration::from_micros(12)),
+            ("99999999n", Duration::from_nanos(99_999_999)),
+        ] {
+            assert_eq!(parse_grpc_timeout(value), Some(timeout), "{value}");
+        }
+        for value in ["", "1", "m", "100", "1x", "-1m", "123456789m"] {
+            assert_eq!(parse_grpc_timeout(value), None, "{value}");
+        }
+
+        assert_eq!(encode_grpc_timeout(Duration::from_nanos(50)), "50n");
+        assert_eq!(encode_grpc_timeout(Duration::from_millis(150)), "150000u");
+        assert_eq!(encode_grpc_timeout(Duration::from_secs(30)), "30000000u");
+        // rounded down to the precision that fits
+        assert_eq!(
+            encode_grpc_timeout(Duration::from_secs(100_000) + Duration::from_millis(1)),
+            "100000S"
+        );
+        assert_eq!(encode_grpc_timeout(Duration::MAX), "99999999H");
+    }
+
+    fn sleep_for(
+        delay: Duration,
+    ) -> impl Service<Request<Body>, Response = http::Response<Body>, Error = BoxError> + Clone
+    {
+        service_fn(move |req: Request<Body>| async move {
+            let deadline = *req.extensions().get::<Deadline>().unwrap();
+            assert_eq!(Deadline::current(), Some(deadline));
+            tokio::time::sleep(delay).await;
+            Ok(http::Response::new(Body::empty()))
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn enforces_deadline() {
+        let header = DeadlineHeader::millis(HeaderName::from_static("x-request-timeout"));
+        let svc = EnforceDeadlineLayer::new(header)
+            .max_timeout(Duration::from_secs(2))
+            .layer(sleep_for(Duration::from_secs(1)));
+
+        let req = Request::builder()
+            .header("x-request-timeout", "1500")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let start = Instant::now();
+        let req = Request::builder()
+            .header("x-request-timeout", "500")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(res.extensions().get::<DeadlineExceeded>().is_some());
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        // capped to the maximum timeout
+        let svc = EnforceDeadline::new(
+            sleep_for(Duration::from_secs(5)),
+            DeadlineHeader::millis(HeaderName::from_static("x-request-timeout")),
+        )
+        .max_timeout(Duration::from_secs(2));
+        let start = Instant::now();
+        let req = Request::builder()
+            .header("x-request-timeout", "60000")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_without_deadline_are_not_limited() {
+        let svc = EnforceDeadline::new(
+            service_fn(|req: Request<Body>| async move {
+                assert!(req.extensions().get::<Deadline>().is_none());
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok::<_, BoxError>(http::Response::new(Body::empty()))
+            }),
+            DeadlineHeader::grpc_timeout(),
+        );
+
+        // invalid headers are ignored
+        let req = Request::builder()
+            .header("grpc-timeout", "soon")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn grpc_deadline_exceeded() {
+        let svc = EnforceDeadline::new(
+            sleep_for(Duration::from_secs(1)),
+            DeadlineHeader::grpc_timeout(),
+        );
+
+        let req = Request::builder()
+            .header("grpc-timeout", "100m")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["grpc-status"], "4");
+
+        let classified = GrpcErrorsAsFailures::new().classify_response(&res);
+        let ClassifiedResponse::Ready(Err(GrpcFailureClass::Code(code))) = classified else {
+            panic!("expected a failure");
+        };
+        // DEADLINE_EXCEEDED
+        assert_eq!(code.get(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn propagates_remaining_budget() {
+        let client = PropagateDeadline::new(
+            service_fn(|req: Request<Body>| async move {
+                let timeout = req
+                    .headers()
+                    .get("grpc-timeout")
+                    .map(|value| value.to_str().unwrap().to_owned());
+                Ok::<_, BoxError>(timeout)
+            }),
+            DeadlineHeader::grpc_timeout(),
+        );
+
+        // no deadline, no header
+        assert_eq!(
+            client.call(Request::new(Body::empty())).await.unwrap(),
+            None
+        );
+
+        let deadline = Deadline::after(Duration::from_secs(10));
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        let timeout = deadline
+            .scope(client.call(Request::new(Body::empty())))
+            .await
+            .unwrap();
+        assert_eq!(timeout.as_deref(), Some("6000000u"));
+
+        // a shorter timeout set on the request is kept
+        let req = Request::builder()
+            .header("grpc-timeout", "1S")
+            .body(Body::empty())
+            .unwrap();
+        let timeout = deadline.scope(client.call(req)).await.unwrap();
+        assert_eq!(timeout.as_deref(), Some("1000000u"));
+
+        // the deadline of a request takes precedence over the one of the task
+        let mut req = Request::new(Body::empty());
+        req.extensions_mut()
+            .insert(Deadline::after(Duration::from_secs(2)));
+        let timeout = client.call(req).await.unwrap();
+        assert_eq!(timeout.as_deref(), Some("2000000u"));
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Deadline, DeadlineHeader};
+use http::{Request, Response};
+use std::time::Duration;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`EnforceDeadline`] middleware.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone)]
+pub struct EnforceDeadlineLayer {
+    header: DeadlineHeader,
+    max_timeout: Option<Duration>,
+}
+
+impl EnforceDeadlineLayer {
+    /// Creates a new [`EnforceDeadlineLayer`], reading the deadline from the given header.
+    pub fn new(header: DeadlineHeader) -> Self {
+        EnforceDeadlineLayer {
+            header,
+            max_timeout: None,
+        }
+    }
+
+    /// Cap the timeout requested by clients.
+    ///
+    /// See [`EnforceDeadline::max_timeout`] for more details.
+    pub fn max_timeout(mut self, max_timeout: Duration) -> Self {
+        self.max_timeout = Some(max_timeout);
+        self
+    }
+}
+
+impl<S> Layer<S> for EnforceDeadlineLayer {
+    type Service = EnforceDeadline<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EnforceDeadline {
+            inner,
+            header: self.header.clone(),
+            max_timeout: self.max_timeout,
+        }
+    }
+}
+
+/// Middleware enforcing the deadline sent by clients in a [`DeadlineHeader`].
+///
+/// The [`Deadline`] is inserted into the request extensions, and set as the deadline of the
+/// task handling the request, unless that task already has an earlier deadline. Once it
+/// expires, the inner service is dropped, and a `504 Gateway Timeout` response is sent, or
+/// a `DEADLINE_EXCEEDED` status for gRPC. Requests without a valid header are passed through
+/// without a deadline.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone)]
+pub struct EnforceDeadline<S> {
+    inner: S,
+    header: DeadlineHeader,
+    max_timeout: Option<Duration>,
+}
+
+impl<S> EnforceDeadline<S> {
+    /// Creates a new [`EnforceDeadline`], reading the deadline from the given header.
+    pub fn new(inner: S, header: DeadlineHeader) -> Self {
+        Self {
+            inner,
+            header,
+            max_timeout: None,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `EnforceDeadline` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(header: DeadlineHeader) -> EnforceDeadlineLayer {
+        EnforceDeadlineLayer::new(header)
+    }
+
+    /// Cap the timeout requested by clients, such that they can't hold on to
+    /// resources for longer than the server is willing to spend on a request.
+    ///
+    /// Not capped by default.
+    pub fn max_timeout(mut self, max_timeout: Duration) -> Self {
+        self.max_timeout = Some(max_timeout);
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for EnforceDeadline<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let Some(mut timeout) = self.header.decode(req.headers()) else {
+            return self.inner.call(req).await;
+        };
+        if let Some(max_timeout) = self.max_timeout {
+            timeout = timeout.min(max_timeout);
+        }
+
+        let mut deadline = Deadline::after(timeout);
+        if let Some(current) = Deadline::current() {
+            deadline = deadline.min(current);
+        }
+        req.extensions_mut().insert(deadline);
+
+        tokio::select! {
+            res = deadline.scope(self.inner.call(req)) => res,
+            _ = tokio::time::sleep_until(deadline.at()) => Ok(self.header.deadline_exceeded()),
+        }
+    }
+}
+
+/// Layer that applies the [`PropagateDeadline`] middleware.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone)]
+pub struct PropagateDeadlineLayer {
+    header: DeadlineHeader,
+}
+
+impl PropagateDeadlineLayer {
+    /// Creates a new [`PropagateDeadlineLayer`], sending the deadline in the given header.
+    pub fn new(header: DeadlineHeader) -> Self {
+        PropagateDeadlineLayer { header }
+    }
+}
+
+impl<S> Layer<S> for PropagateDeadlineLayer {
+    type Service = PropagateDeadline<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PropagateDeadline::new(inner, self.header.clone())
+    }
+}
+
+/// Middleware sending the remaining budget of a [`Deadline`] in a [`DeadlineHeader`].
+///
+/// The deadline is taken from the request extensions, or else from the current task, such as
+/// a deadline set by [`EnforceDeadline`] for the handler making the request. If the request
+/// already has a header with a shorter timeout, it is kept. Requests made without a deadline
+/// are passed through as they are.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone)]
+pub struct PropagateDeadline<S> {
+    inner: S,
+    header: DeadlineHeader,
+}
+
+impl<S> PropagateDeadline<S> {
+    /// Creates a new [`PropagateDeadline`], sending the deadline in the given header.
+    pub fn new(inner: S, header: DeadlineHeader) -> Self {
+        Self { inner, header }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `PropagateDeadline` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(header: DeadlineHeader) -> PropagateDeadlineLayer {
+        PropagateDeadlineLayer::new(header)
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for PropagateDeadline<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let deadline = req
+            .extensions()
+            .get::<Deadline>()
+            .copied()
+            .or_else(Deadline::current);
+
+        if let Some(deadline) = deadline {
+            let mut timeout = deadline.remaining();
+            if let Some(requested) = self.header.decode(req.headers()) {
+                timeout = timeout.min(requested);
+            }
+            req.headers_mut()
+                .insert(self.header.name().clone(), self.header.encode(timeout));
+        }
+
+        self.inner.call(req).await
+    }
+}
@@ -1,8 +1,7 @@
 #![allow(unused_imports)]
 
-use crate::compression_utils::CompressionLevel;
 use crate::{
-    compression_utils::{AsyncReadBody, BodyIntoStream, DecorateAsyncRead, WrapBody},
+    compression_utils::{AsyncReadBody, BodyIntoStream, CodecParams, DecorateAsyncRead, WrapBody},
     BoxError,
 };
 #[cfg(feature = "decompression-br")]
@@ -191,7 +190,7 @@ where
     type Input = AsyncReadBody<B>;
     type Output = GzipDecoder<Self::Input>;
 
-    fn apply(input: Self::Input, _quality: CompressionLevel) -> Self::Output {
+    fn apply(input: Self::Input, _params: CodecParams) -> Self::Output {
         let mut decoder = GzipDecoder::new(input);
         decoder.multiple_members(true);
         decoder
@@ -210,7 +209,7 @@ where
     type Input = AsyncReadBody<B>;
     type Output = ZlibDecoder<Self::Input>;
 
-    fn apply(input: Self::Input, _quality: CompressionLevel) -> Self::Output {
+    fn apply(input: Self::Input, _params: CodecParams) -> Self::Output {
         ZlibDecoder::new(input)
     }
 
@@ -227,7 +226,7 @@ where
     type Input = AsyncReadBody<B>;
     type Output = BrotliDecoder<Self::Input>;
 
-    fn apply(input: Self::Input, _quality: CompressionLevel) -> Self::Output {
+    fn apply(input: Self::Input, _params: CodecParams) -> Self::Output {
         BrotliDecoder::new(input)
     }
 
@@ -244,8 +243,8 @@ where
     type Input = AsyncReadBody<B>;
     type Output = ZstdDecoder<Self::Input>;
 
-    fn apply(input: Self::Input, _quality: CompressionLevel) -> Self::Output {
-        ZstdDecoder::new(input)
+    fn apply(input: Self::Input, params: CodecParams) -> Self::Output {
+        ZstdDecoder::with_params(input, &params.decompression.zstd_params())
     }
 
     fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
@@ -0,0 +1,34 @@
+use crate::content_encoding::Encoding;
+use http::Extensions;
+
+/// Response extension recording whether, and why, a response was decompressed.
+///
+/// Inserted by [`Decompression`] into every response. With the `tracing` feature enabled,
+/// the decision is also emitted as a `DEBUG` event.
+///
+/// [`Decompression`]: super::Decompression
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressionDecision {
+    /// The response body was decompressed from the given encoding.
+    Decompressed(Encoding),
+    /// The response didn't have a `Content-Encoding` header.
+    NotEncoded,
+    /// The `Content-Encoding` of the response is unknown, or not enabled,
+    /// and the body was passed through as is.
+    Unsupported,
+}
+
+impl DecompressionDecision {
+    /// Returns `true` if the response body was decompressed.
+    pub fn is_decompressed(&self) -> bool {
+        matches!(self, DecompressionDecision::Decompressed(_))
+    }
+
+    /// Inserts the decision into the response extensions, and traces it.
+    pub(crate) fn record(self, extensions: &mut Extensions) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(decision = ?self, "decompression decision");
+        extensions.insert(self);
+    }
+}
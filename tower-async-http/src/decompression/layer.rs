@@ -1,5 +1,5 @@
 use super::Decompression;
-use crate::compression_utils::AcceptEncoding;
+use crate::compression_utils::{AcceptEncoding, DecompressionOptions};
 use tower_async_layer::Layer;
 
 /// Decompresses response bodies of the underlying service.
@@ -11,6 +11,7 @@ use tower_async_layer::Layer;
 #[derive(Debug, Default, Clone)]
 pub struct DecompressionLayer {
     accept: AcceptEncoding,
+    options: DecompressionOptions,
 }
 
 impl<S> Layer<S> for DecompressionLayer {
@@ -20,6 +21,7 @@ impl<S> Layer<S> for DecompressionLayer {
         Decompression {
             inner: service,
             accept: self.accept,
+            options: self.options,
         }
     }
 }
@@ -89,4 +91,14 @@ impl DecompressionLayer {
         self.accept.set_zstd(false);
         self
     }
+
+    /// Sets the advanced options of the codecs.
+    ///
+    /// See [`Decompression::options`] for more details.
+    ///
+    /// [`Decompression::options`]: super::Decompression::options
+    pub fn options(mut self, options: DecompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
@@ -88,13 +88,18 @@
 mod request;
 
 mod body;
+mod decision;
 mod layer;
 mod service;
 
-pub use self::{body::DecompressionBody, layer::DecompressionLayer, service::Decompression};
+pub use self::{
+    body::DecompressionBody, decision::DecompressionDecision, layer::DecompressionLayer,
+    service::Decompression,
+};
 
 pub use self::request::layer::RequestDecompressionLayer;
 pub use self::request::service::RequestDecompression;
+pub use crate::compression_utils::DecompressionOptions;
 
 #[cfg(test)]
 mod tests {
@@ -104,7 +109,10 @@ mod tests {
     use std::io::Write;
 
     use crate::test_helpers::Body;
-    use crate::{compression::Compression, test_helpers::WithTrailers};
+    use crate::{
+        compression::{Compression, CompressionOptions},
+        test_helpers::WithTrailers,
+    };
 
     use flate2::write::GzEncoder;
     use http::{HeaderMap, HeaderName, Request, Response};
@@ -133,6 +141,42 @@ mod tests {
         assert_eq!(trailers["foo"], "bar");
     }
 
+    #[tokio::test]
+    async fn records_decision() {
+        let client = Decompression::new(Compression::new(service_fn(handle)));
+
+        let req = Request::builder()
+            .header("accept-encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = client.call(req).await.unwrap();
+        assert_eq!(
+            res.extensions().get::<DecompressionDecision>(),
+            Some(&DecompressionDecision::Decompressed(
+                crate::content_encoding::Encoding::Gzip
+            ))
+        );
+
+        let client = Decompression::new(service_fn(handle));
+        let res = client.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(
+            res.extensions().get::<DecompressionDecision>(),
+            Some(&DecompressionDecision::NotEncoded)
+        );
+
+        let client = Decompression::new(service_fn(|_: Request<Body>| async {
+            let mut res = Response::new(Body::from("compressed"));
+            res.headers_mut()
+                .insert("content-encoding", "compress".parse().unwrap());
+            Ok::<_, Infallible>(res)
+        }));
+        let res = client.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(
+            res.extensions().get::<DecompressionDecision>(),
+            Some(&DecompressionDecision::Unsupported)
+        );
+    }
+
     async fn handle(_req: Request<Body>) -> Result<Response<WithTrailers<Body>>, Infallible> {
         let mut trailers = HeaderMap::new();
         trailers.insert(HeaderName::from_static("foo"), "bar".parse().unwrap());
@@ -140,6 +184,40 @@ mod tests {
         Ok(Response::builder().body(body).unwrap())
     }
 
+    #[tokio::test]
+    async fn zstd_large_window() {
+        let server = || {
+            Compression::new(service_fn(handle))
+                .options(CompressionOptions::new().zstd_window_log(28))
+        };
+        let req = || {
+            Request::builder()
+                .header("accept-encoding", "zstd")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // larger than the window accepted by default
+        let client = Decompression::new(server());
+        let res = client.call(req()).await.unwrap();
+        assert!(res.into_body().collect().await.is_err());
+
+        let client = Decompression::new(server())
+            .options(DecompressionOptions::new().zstd_window_log_max(28));
+        let res = client.call(req()).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "Hello, World!");
+
+        // accepted for a single request
+        let client = Decompression::new(server());
+        let mut req = req();
+        req.extensions_mut()
+            .insert(DecompressionOptions::new().zstd_window_log_max(28));
+        let res = client.call(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "Hello, World!");
+    }
+
     #[tokio::test]
     async fn decompress_multi_gz() {
         let client = Decompression::new(service_fn(handle_multi_gz));
@@ -1,5 +1,5 @@
 use super::service::RequestDecompression;
-use crate::compression_utils::AcceptEncoding;
+use crate::compression_utils::{AcceptEncoding, DecompressionOptions};
 use tower_async_layer::Layer;
 
 /// Decompresses request bodies and calls its underlying service.
@@ -17,6 +17,7 @@ use tower_async_layer::Layer;
 pub struct RequestDecompressionLayer {
     accept: AcceptEncoding,
     pass_through_unaccepted: bool,
+    options: DecompressionOptions,
 }
 
 impl<S> Layer<S> for RequestDecompressionLayer {
@@ -27,6 +28,7 @@ impl<S> Layer<S> for RequestDecompressionLayer {
             inner: service,
             accept: self.accept,
             pass_through_unaccepted: self.pass_through_unaccepted,
+            options: self.options,
         }
     }
 }
@@ -102,4 +104,14 @@ impl RequestDecompressionLayer {
         self.pass_through_unaccepted = enable;
         self
     }
+
+    /// Sets the advanced options of the codecs.
+    ///
+    /// See [`RequestDecompression::options`] for more details.
+    ///
+    /// [`RequestDecompression::options`]: crate::decompression::RequestDecompression::options
+    pub fn options(mut self, options: DecompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
@@ -1,5 +1,5 @@
 use super::layer::RequestDecompressionLayer;
-use crate::compression_utils::CompressionLevel;
+use crate::compression_utils::DecompressionOptions;
 use crate::{
     compression_utils::AcceptEncoding, decompression::body::BodyInner,
     decompression::DecompressionBody, BoxError,
@@ -34,6 +34,7 @@ pub struct RequestDecompression<S> {
     pub(super) inner: S,
     pub(super) accept: AcceptEncoding,
     pub(super) pass_through_unaccepted: bool,
+    pub(super) options: DecompressionOptions,
 }
 
 impl<S, ReqBody, ResBody, D> Service<Request<ReqBody>> for RequestDecompression<S>
@@ -50,6 +51,11 @@ where
 
     async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
         let (mut parts, body) = req.into_parts();
+        let options = parts
+            .extensions
+            .get::<DecompressionOptions>()
+            .copied()
+            .unwrap_or(self.options);
 
         let body =
             if let header::Entry::Occupied(entry) = parts.headers.entry(header::CONTENT_ENCODING) {
@@ -58,36 +64,36 @@ where
                     b"gzip" if self.accept.gzip() => {
                         entry.remove();
                         parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::gzip(crate::compression_utils::WrapBody::new(
+                        BodyInner::gzip(crate::compression_utils::WrapBody::with_params(
                             body,
-                            CompressionLevel::default(),
+                            options.into(),
                         ))
                     }
                     #[cfg(feature = "decompression-deflate")]
                     b"deflate" if self.accept.deflate() => {
                         entry.remove();
                         parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::deflate(crate::compression_utils::WrapBody::new(
+                        BodyInner::deflate(crate::compression_utils::WrapBody::with_params(
                             body,
-                            CompressionLevel::default(),
+                            options.into(),
                         ))
                     }
                     #[cfg(feature = "decompression-br")]
                     b"br" if self.accept.br() => {
                         entry.remove();
                         parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::brotli(crate::compression_utils::WrapBody::new(
+                        BodyInner::brotli(crate::compression_utils::WrapBody::with_params(
                             body,
-                            CompressionLevel::default(),
+                            options.into(),
                         ))
                     }
                     #[cfg(feature = "decompression-zstd")]
                     b"zstd" if self.accept.zstd() => {
                         entry.remove();
                         parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::zstd(crate::compression_utils::WrapBody::new(
+                        BodyInner::zstd(crate::compression_utils::WrapBody::with_params(
                             body,
-                            CompressionLevel::default(),
+                            options.into(),
                         ))
                     }
                     b"identity" => BodyInner::identity(body),
@@ -133,6 +139,7 @@ impl<S> RequestDecompression<S> {
             inner: service,
             accept: AcceptEncoding::default(),
             pass_through_unaccepted: false,
+            options: DecompressionOptions::default(),
         }
     }
 
@@ -153,6 +160,15 @@ impl<S> RequestDecompression<S> {
         self
     }
 
+    /// Sets the advanced options of the codecs, such as the largest zstd window accepted.
+    ///
+    /// The options can be overridden for a single request by inserting
+    /// [`DecompressionOptions`] into the extensions of the request.
+    pub fn options(mut self, options: DecompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Sets whether to support gzip encoding.
     #[cfg(feature = "decompression-gzip")]
     pub fn gzip(mut self, enable: bool) -> Self {
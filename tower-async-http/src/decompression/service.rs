@@ -1,7 +1,7 @@
-use super::{body::BodyInner, DecompressionBody, DecompressionLayer};
+use super::{body::BodyInner, DecompressionBody, DecompressionDecision, DecompressionLayer};
 use crate::{
-    compression_utils::{AcceptEncoding, CompressionLevel, WrapBody},
-    content_encoding::SupportedEncodings,
+    compression_utils::{AcceptEncoding, DecompressionOptions, WrapBody},
+    content_encoding::{Encoding, SupportedEncodings},
 };
 use http::{
     header::{self, ACCEPT_ENCODING},
@@ -20,6 +20,7 @@ use tower_async_service::Service;
 pub struct Decompression<S> {
     pub(crate) inner: S,
     pub(crate) accept: AcceptEncoding,
+    pub(crate) options: DecompressionOptions,
 }
 
 impl<S> Decompression<S> {
@@ -28,6 +29,7 @@ impl<S> Decompression<S> {
         Self {
             inner: service,
             accept: AcceptEncoding::default(),
+            options: DecompressionOptions::default(),
         }
     }
 
@@ -99,6 +101,15 @@ impl<S> Decompression<S> {
         self.accept.set_zstd(false);
         self
     }
+
+    /// Sets the advanced options of the codecs, such as the largest zstd window accepted.
+    ///
+    /// The options can be overridden for a single request by inserting
+    /// [`DecompressionOptions`] into the extensions of the request.
+    pub fn options(mut self, options: DecompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
 
 impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Decompression<S>
@@ -116,46 +127,71 @@ where
             }
         }
 
+        let options = req
+            .extensions()
+            .get::<DecompressionOptions>()
+            .copied()
+            .unwrap_or(self.options);
+
         let res = self.inner.call(req).await?;
 
         let (mut parts, body) = res.into_parts();
 
         let res =
             if let header::Entry::Occupied(entry) = parts.headers.entry(header::CONTENT_ENCODING) {
-                let body = match entry.get().as_bytes() {
+                let (encoding, body) = match entry.get().as_bytes() {
                     #[cfg(feature = "decompression-gzip")]
-                    b"gzip" if self.accept.gzip() => DecompressionBody::new(BodyInner::gzip(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
+                    b"gzip" if self.accept.gzip() => (
+                        Encoding::Gzip,
+                        DecompressionBody::new(BodyInner::gzip(WrapBody::with_params(
+                            body,
+                            options.into(),
+                        ))),
+                    ),
 
                     #[cfg(feature = "decompression-deflate")]
-                    b"deflate" if self.accept.deflate() => DecompressionBody::new(
-                        BodyInner::deflate(WrapBody::new(body, CompressionLevel::default())),
+                    b"deflate" if self.accept.deflate() => (
+                        Encoding::Deflate,
+                        DecompressionBody::new(BodyInner::deflate(WrapBody::with_params(
+                            body,
+                            options.into(),
+                        ))),
                     ),
 
                     #[cfg(feature = "decompression-br")]
-                    b"br" if self.accept.br() => DecompressionBody::new(BodyInner::brotli(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
+                    b"br" if self.accept.br() => (
+                        Encoding::Brotli,
+                        DecompressionBody::new(BodyInner::brotli(WrapBody::with_params(
+                            body,
+                            options.into(),
+                        ))),
+                    ),
 
                     #[cfg(feature = "decompression-zstd")]
-                    b"zstd" if self.accept.zstd() => DecompressionBody::new(BodyInner::zstd(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
+                    b"zstd" if self.accept.zstd() => (
+                        Encoding::Zstd,
+                        DecompressionBody::new(BodyInner::zstd(WrapBody::with_params(
+                            body,
+                            options.into(),
+                        ))),
+                    ),
 
                     _ => {
+                        DecompressionDecision::Unsupported.record(&mut parts.extensions);
                         return Ok(Response::from_parts(
                             parts,
                             DecompressionBody::new(BodyInner::identity(body)),
-                        ))
+                        ));
                     }
                 };
 
                 entry.remove();
                 parts.headers.remove(header::CONTENT_LENGTH);
+                DecompressionDecision::Decompressed(encoding).record(&mut parts.extensions);
 
                 Response::from_parts(parts, body)
             } else {
+                DecompressionDecision::NotEncoded.record(&mut parts.extensions);
                 Response::from_parts(parts, DecompressionBody::new(BodyInner::identity(body)))
             };
 
@@ -0,0 +1,326 @@
+//! Middleware that exposes the current load to handlers and toggles degradation flags.
+//!
+//! The [`Degradation`] middleware reads the current [`Load`] from a [`LoadSource`],
+//! such as the [`ConcurrentPolicy`] of a [`Limit`] middleware, and inserts it into
+//! the [request extensions]. Flags can be registered for a utilization threshold, and
+//! all flags whose threshold is reached are inserted as [`DegradationFlags`],
+//! allowing handlers to adapt their behaviour to the load without bespoke wiring.
+//!
+//! The [`Degradation`] middleware should be placed in front of the [`Limit`] middleware
+//! it observes, such that the load does not yet include the request itself.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::convert::Infallible;
+//! use tower_async::{limit::policy::ConcurrentPolicy, Service, ServiceBuilder};
+//! use tower_async_http::degradation::{DegradationFlags, DegradationLayer, Load};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Degrade {
+//!     SkipPersonalization,
+//!     ServeStale,
+//! }
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let load = req.extensions().get::<Load>().unwrap();
+//!     tracing::debug!(utilization = load.utilization(), "handling request");
+//!     let flags = req.extensions().get::<DegradationFlags<Degrade>>().unwrap();
+//!     if flags.contains(&Degrade::ServeStale) {
+//!         // ...
+//!     }
+//!     # Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! let policy = ConcurrentPolicy::new(100);
+//!
+//! let svc = ServiceBuilder::new()
+//!     .layer(
+//!         DegradationLayer::new(policy.clone())
+//!             .flag_at(0.75, Degrade::SkipPersonalization)
+//!             .flag_at(0.9, Degrade::ServeStale),
+//!     )
+//!     .limit(policy)
+//!     .service_fn(handle);
+//!
+//! svc.call(Request::new(Full::default())).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ConcurrentPolicy`]: tower_async::limit::policy::ConcurrentPolicy
+//! [`Limit`]: tower_async::limit::Limit
+//! [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+
+use http::Request;
+use std::sync::Arc;
+use tower_async::limit::policy::ConcurrentPolicy;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The load of a service at the time a request was received.
+///
+/// Inserted into the request extensions by [`Degradation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Load {
+    /// Number of requests that are currently in flight.
+    pub in_flight: usize,
+    /// Maximum number of requests that can be in flight.
+    pub capacity: usize,
+}
+
+impl Load {
+    /// Returns the ratio of in-flight requests to the capacity.
+    ///
+    /// A service without capacity is considered fully utilized.
+    pub fn utilization(&self) -> f64 {
+        if self.capacity == 0 {
+            return 1.0;
+        }
+        self.in_flight as f64 / self.capacity as f64
+    }
+}
+
+/// Trait used by [`Degradation`] to get the current [`Load`].
+pub trait LoadSource {
+    /// Returns the current load.
+    fn load(&self) -> Load;
+}
+
+impl<B> LoadSource for ConcurrentPolicy<B> {
+    fn load(&self) -> Load {
+        Load {
+            in_flight: self.current(),
+            capacity: self.max(),
+        }
+    }
+}
+
+impl<F> LoadSource for F
+where
+    F: Fn() -> Load,
+{
+    fn load(&self) -> Load {
+        self()
+    }
+}
+
+/// The degradation flags active for a request.
+///
+/// Inserted into the request extensions by [`Degradation`],
+/// even if no flags are active.
+#[derive(Debug, Clone)]
+pub struct DegradationFlags<F> {
+    flags: Vec<F>,
+}
+
+impl<F> DegradationFlags<F> {
+    /// Returns `true` if the given flag is active.
+    pub fn contains(&self, flag: &F) -> bool
+    where
+        F: PartialEq,
+    {
+        self.flags.contains(flag)
+    }
+
+    /// Returns `true` if no flag is active.
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// Returns an iterator over the active flags.
+    pub fn iter(&self) -> impl Iterator<Item = &F> {
+        self.flags.iter()
+    }
+}
+
+/// Layer that applies the [`Degradation`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct DegradationLayer<L, F> {
+    source: L,
+    thresholds: Vec<(f64, F)>,
+}
+
+impl<L, F> DegradationLayer<L, F> {
+    /// Creates a new [`DegradationLayer`] observing the given [`LoadSource`].
+    pub fn new(source: L) -> Self {
+        Self {
+            source,
+            thresholds: Vec::new(),
+        }
+    }
+
+    /// Activate `flag` for all requests received
+    /// while the [utilization] is at least `threshold`.
+    ///
+    /// [utilization]: Load::utilization
+    pub fn flag_at(mut self, threshold: f64, flag: F) -> Self {
+        self.thresholds.push((threshold, flag));
+        self
+    }
+}
+
+impl<S, L, F> Layer<S> for DegradationLayer<L, F>
+where
+    L: Clone,
+    F: Clone,
+{
+    type Service = Degradation<S, L, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Degradation {
+            inner,
+            source: self.source.clone(),
+            thresholds: self.thresholds.clone().into(),
+        }
+    }
+}
+
+/// Middleware that exposes the current [`Load`] and active [`DegradationFlags`]
+/// to the inner service.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct Degradation<S, L, F> {
+    inner: S,
+    source: L,
+    thresholds: Arc<[(f64, F)]>,
+}
+
+impl<S, L, F> Degradation<S, L, F> {
+    /// Creates a new [`Degradation`] observing the given [`LoadSource`].
+    pub fn new(inner: S, source: L) -> Self {
+        Self {
+            inner,
+            source,
+            thresholds: Arc::new([]),
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Degradation` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(source: L) -> DegradationLayer<L, F> {
+        DegradationLayer::new(source)
+    }
+}
+
+impl<S, L, F, ReqBody> Service<Request<ReqBody>> for Degradation<S, L, F>
+where
+    S: Service<Request<ReqBody>>,
+    L: LoadSource,
+    F: Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let load = self.source.load();
+        let utilization = load.utilization();
+        let flags = DegradationFlags {
+            flags: self
+                .thresholds
+                .iter()
+                .filter(|(threshold, _)| utilization >= *threshold)
+                .map(|(_, flag)| flag.clone())
+                .collect(),
+        };
+
+        req.extensions_mut().insert(load);
+        req.extensions_mut().insert(flags);
+
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http::Response;
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Flag {
+        SkipPersonalization,
+        ServeStale,
+    }
+
+    async fn flags(req: Request<Body>) -> Result<Response<Vec<Flag>>, Infallible> {
+        let flags = req.extensions().get::<DegradationFlags<Flag>>().unwrap();
+        Ok(Response::new(flags.iter().cloned().collect()))
+    }
+
+    #[tokio::test]
+    async fn flags_toggle_at_thresholds() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+
+        let source = {
+            let in_flight = in_flight.clone();
+            move || Load {
+                in_flight: in_flight.load(Ordering::SeqCst),
+                capacity: 10,
+            }
+        };
+
+        let svc = ServiceBuilder::new()
+            .layer(
+                DegradationLayer::new(source)
+                    .flag_at(0.5, Flag::SkipPersonalization)
+                    .flag_at(0.9, Flag::ServeStale),
+            )
+            .service(service_fn(flags));
+
+        for (count, expected) in [
+            (0, vec![]),
+            (5, vec![Flag::SkipPersonalization]),
+            (9, vec![Flag::SkipPersonalization, Flag::ServeStale]),
+            (10, vec![Flag::SkipPersonalization, Flag::ServeStale]),
+        ] {
+            in_flight.store(count, Ordering::SeqCst);
+            let res = svc.call(Request::new(Body::empty())).await.unwrap();
+            assert_eq!(res.into_body(), expected, "in flight: {}", count);
+        }
+    }
+
+    #[tokio::test]
+    async fn load_from_concurrent_policy() {
+        let policy = ConcurrentPolicy::new(4);
+
+        let svc = ServiceBuilder::new()
+            .layer(DegradationLayer::<_, Flag>::new(policy.clone()))
+            .limit(policy)
+            .service(service_fn(|req: Request<Body>| async move {
+                let load = *req.extensions().get::<Load>().unwrap();
+                Ok::<_, Infallible>(Response::new(load))
+            }));
+
+        let load = svc
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap()
+            .into_body();
+        assert_eq!(
+            load,
+            Load {
+                in_flight: 0,
+                capacity: 4
+            }
+        );
+        assert_eq!(load.utilization(), 0.0);
+    }
+}
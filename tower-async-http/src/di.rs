@@ -0,0 +1,318 @@
+//! Middleware that lazily provides per-process singletons to each request's [extensions].
+//!
+//! Unlike [`AddExtension`], which requires the value to exist when the service is built,
+//! a [`ProvideLayer`] constructs its value asynchronously on first use, using a [`Provide`]
+//! implementation, and shares it with every following request. This is useful for expensive
+//! dependencies such as database pools and HTTP clients, which would otherwise need to be
+//! constructed and threaded through by hand when wiring an application.
+//!
+//! If the construction fails, the error is converted into the error of the inner service
+//! and returned for that request. The next request will try to construct the value again.
+//!
+//! All services produced by the same [`ProvideLayer`], and all clones of them, share
+//! the same value. Multiple dependencies are provided by stacking multiple layers.
+//!
+//! [extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+//! [`AddExtension`]: crate::add_extension::AddExtension
+//!
+//! # Example
+//!
+//! ```
+//! use tower_async_http::di::ProvideLayer;
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::sync::Arc;
+//!
+//! # struct DatabaseConnectionPool;
+//! # impl DatabaseConnectionPool {
+//! #     async fn connect(_url: &str) -> Result<DatabaseConnectionPool, BoxError> { Ok(DatabaseConnectionPool) }
+//! # }
+//! #
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     // Grab the pool from the request extensions.
+//!     let pool = req.extensions().get::<Arc<DatabaseConnectionPool>>().unwrap();
+//!
+//!     Ok(Response::new(Full::<Bytes>::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = ServiceBuilder::new()
+//!     // Connect to the database on the first request and share the pool with all requests.
+//!     .layer(ProvideLayer::new(|| async {
+//!         let pool = DatabaseConnectionPool::connect("postgres://localhost").await?;
+//!         Ok::<_, BoxError>(Arc::new(pool))
+//!     }))
+//!     .service_fn(handle);
+//!
+//! let response = service
+//!     .call(Request::new(Full::<Bytes>::default()))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use async_lock::OnceCell;
+use http::Request;
+use std::{fmt, future::Future, sync::Arc};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Constructs a value of type `T`, to be provided to requests by [`Inject`].
+///
+/// This trait is implemented for async closures returning a `Result<T, E>`.
+pub trait Provide<T> {
+    /// The error returned if the value could not be constructed.
+    type Error;
+
+    /// Construct the value.
+    fn provide(&self) -> impl Future<Output = Result<T, Self::Error>>;
+}
+
+impl<T, E, F, Fut> Provide<T> for F
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    type Error = E;
+
+    fn provide(&self) -> impl Future<Output = Result<T, Self::Error>> {
+        self()
+    }
+}
+
+struct Shared<T, P> {
+    value: OnceCell<T>,
+    provider: P,
+}
+
+/// [`Layer`] for lazily providing a value to [request extensions].
+///
+/// See the [module docs](crate::di) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+pub struct ProvideLayer<T, P> {
+    shared: Arc<Shared<T, P>>,
+}
+
+impl<T, P> ProvideLayer<T, P> {
+    /// Create a new [`ProvideLayer`].
+    ///
+    /// `provider` is used to construct the value on first use.
+    pub fn new(provider: P) -> Self
+    where
+        P: Provide<T>,
+    {
+        Self {
+            shared: Arc::new(Shared {
+                value: OnceCell::new(),
+                provider,
+            }),
+        }
+    }
+
+    /// Returns the provided value, if it has been constructed already.
+    pub fn get(&self) -> Option<&T> {
+        self.shared.value.get()
+    }
+}
+
+impl<T, P> Clone for ProvideLayer<T, P> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T, P> fmt::Debug for ProvideLayer<T, P>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProvideLayer")
+            .field("value", &self.get())
+            .field("provider", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+impl<S, T, P> Layer<S> for ProvideLayer<T, P> {
+    type Service = Inject<S, T, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Inject {
+            inner,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// Middleware for lazily providing a value to [request extensions].
+///
+/// See the [module docs](crate::di) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+pub struct Inject<S, T, P> {
+    inner: S,
+    shared: Arc<Shared<T, P>>,
+}
+
+impl<S, T, P> Inject<S, T, P> {
+    /// Create a new [`Inject`].
+    ///
+    /// `provider` is used to construct the value on first use.
+    pub fn new(inner: S, provider: P) -> Self
+    where
+        P: Provide<T>,
+    {
+        ProvideLayer::new(provider).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Inject` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(provider: P) -> ProvideLayer<T, P>
+    where
+        P: Provide<T>,
+    {
+        ProvideLayer::new(provider)
+    }
+
+    /// Returns the provided value, if it has been constructed already.
+    pub fn get(&self) -> Option<&T> {
+        self.shared.value.get()
+    }
+}
+
+impl<S, T, P> Clone for Inject<S, T, P>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<S, T, P> fmt::Debug for Inject<S, T, P>
+where
+    S: fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inject")
+            .field("inner", &self.inner)
+            .field("value", &self.get())
+            .field("provider", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+impl<ReqBody, S, T, P> Service<Request<ReqBody>> for Inject<S, T, P>
+where
+    S: Service<Request<ReqBody>>,
+    T: Clone + Send + Sync + 'static,
+    P: Provide<T>,
+    P::Error: Into<S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let value = self
+            .shared
+            .value
+            .get_or_try_init(|| self.shared.provider.provide())
+            .await
+            .map_err(Into::into)?;
+        req.extensions_mut().insert(value.clone());
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use crate::BoxError;
+
+    use http::Response;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[derive(Debug)]
+    struct Pool(usize);
+
+    #[tokio::test]
+    async fn value_is_constructed_once() {
+        let constructed = Arc::new(AtomicUsize::new(0));
+
+        let layer = ProvideLayer::new({
+            let constructed = constructed.clone();
+            move || {
+                let constructed = constructed.clone();
+                async move {
+                    let id = constructed.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, BoxError>(Arc::new(Pool(id)))
+                }
+            }
+        });
+        assert!(layer.get().is_none());
+
+        let handler = service_fn(|req: Request<Body>| async move {
+            let pool = req.extensions().get::<Arc<Pool>>().unwrap();
+            Ok::<_, BoxError>(Response::new(pool.0))
+        });
+        let a = ServiceBuilder::new().layer(layer.clone()).service(handler);
+        let b = ServiceBuilder::new().layer(layer.clone()).service(handler);
+
+        assert_eq!(
+            *a.call(Request::new(Body::empty())).await.unwrap().body(),
+            0
+        );
+        assert_eq!(
+            *b.call(Request::new(Body::empty())).await.unwrap().body(),
+            0
+        );
+        assert_eq!(constructed.load(Ordering::SeqCst), 1);
+        assert_eq!(layer.get().unwrap().0, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_construction_is_retried() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let svc = ServiceBuilder::new()
+            .layer(ProvideLayer::new({
+                let attempts = attempts.clone();
+                move || {
+                    let attempts = attempts.clone();
+                    async move {
+                        if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                            Err("connection refused")
+                        } else {
+                            Ok(Arc::new(Pool(1)))
+                        }
+                    }
+                }
+            }))
+            .service(service_fn(|req: Request<Body>| async move {
+                assert!(req.extensions().get::<Arc<Pool>>().is_some());
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }));
+
+        let err = svc.call(Request::new(Body::empty())).await.unwrap_err();
+        assert_eq!(err.to_string(), "connection refused");
+
+        svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
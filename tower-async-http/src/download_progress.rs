@@ -0,0 +1,517 @@
+//! Middleware reporting the progress of downloading response bodies.
+//!
+//! Command line tools and desktop applications downloading large files usually show a
+//! progress bar. The [`DownloadProgress`] client middleware wraps response bodies in a
+//! [`DownloadProgressBody`], which calls a callback with the [`Progress`] of the download
+//! while the body is read: the number of bytes received so far, and the total number of
+//! bytes, taken from the `Content-Length` header or the size hint of the body.
+//!
+//! The callback is called at most once per [interval](DownloadProgressLayer::interval),
+//! such that a fast download doesn't redraw the progress bar for every chunk, and always
+//! once the body was read to the end. If the callback returns an error, the download is
+//! cancelled: reading the body fails with that error.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::{BodyExt, Full};
+//! use std::sync::{Arc, Mutex};
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::download_progress::{DownloadProgressLayer, Progress};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! # let http_client = tower_async::service_fn(|_: Request<Full<Bytes>>| async {
+//! #     Ok::<_, BoxError>(Response::builder().header("content-length", "5").body(Full::<Bytes>::from("hello"))?)
+//! # });
+//! let reports = Arc::new(Mutex::new(Vec::new()));
+//! let client = ServiceBuilder::new()
+//!     .layer(DownloadProgressLayer::new({
+//!         let reports = reports.clone();
+//!         move |progress: &Progress| {
+//!             reports.lock().unwrap().push((progress.transferred(), progress.total()));
+//!             Ok::<_, BoxError>(())
+//!         }
+//!     }))
+//!     .service(http_client);
+//!
+//! let res = client.call(Request::new(Full::default())).await?;
+//! let body = res.into_body().collect().await?.to_bytes();
+//!
+//! assert_eq!(body, "hello");
+//! assert_eq!(*reports.lock().unwrap(), [(5, Some(5))]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::BoxError;
+use bytes::Buf;
+use futures_util::ready;
+use http::{header, Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The progress of transferring a body, reported by [`DownloadProgressBody`],
+/// and by the body of the `upload_progress` middleware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    transferred: u64,
+    total: Option<u64>,
+    complete: bool,
+}
+
+impl Progress {
+    /// Returns the number of bytes of the body transferred so far.
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    /// Returns the total number of bytes of the body, if known.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// Returns whether the body was transferred to the end.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// Reports the [`Progress`] of a body to a callback, at most once per interval,
+/// and once the body is complete.
+pub(crate) struct Reporter<F> {
+    callback: Option<Arc<F>>,
+    interval: Duration,
+    last_report: Option<Instant>,
+    progress: Progress,
+}
+
+impl<F> Reporter<F> {
+    pub(crate) fn new(callback: Arc<F>, interval: Duration, total: Option<u64>) -> Self {
+        Self {
+            callback: Some(callback),
+            interval,
+            last_report: None,
+            progress: Progress {
+                transferred: 0,
+                total,
+                complete: false,
+            },
+        }
+    }
+
+    pub(crate) fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Returns whether the callback cancelled the transfer by returning an error.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.callback.is_none()
+    }
+}
+
+impl<F, E> Reporter<F>
+where
+    F: Fn(&Progress) -> Result<(), E>,
+    E: Into<BoxError>,
+{
+    /// Records that `len` more bytes were transferred,
+    /// reporting the progress if the interval passed or the total was reached.
+    pub(crate) fn transferred(&mut self, len: u64) -> Result<(), BoxError> {
+        self.progress.transferred += len;
+        let now = Instant::now();
+        let due = match self.last_report {
+            Some(last) => now.duration_since(last) >= self.interval,
+            None => true,
+        };
+        let ended = self.progress.total == Some(self.progress.transferred);
+        if due || ended {
+            self.last_report = Some(now);
+            self.progress.complete = ended;
+            self.report()?;
+        }
+        Ok(())
+    }
+
+    /// Records the end of the body, reporting the progress unless its completion
+    /// was reported already.
+    pub(crate) fn end(&mut self) -> Result<(), BoxError> {
+        if self.progress.complete {
+            return Ok(());
+        }
+        self.progress.complete = true;
+        self.report()
+    }
+
+    fn report(&mut self) -> Result<(), BoxError> {
+        let Some(callback) = self.callback.as_ref() else {
+            return Ok(());
+        };
+        callback(&self.progress).map_err(|err| {
+            self.callback = None;
+            err.into()
+        })
+    }
+}
+
+pin_project! {
+    /// Response body for [`DownloadProgress`].
+    ///
+    /// Reports the [`Progress`] of reading the body to the callback of the middleware.
+    pub struct DownloadProgressBody<B, F> {
+        #[pin]
+        inner: B,
+        reporter: Reporter<F>,
+    }
+}
+
+impl<B, F> DownloadProgressBody<B, F>
+where
+    B: Body,
+{
+    fn new(inner: B, callback: Arc<F>, interval: Duration, total: Option<u64>) -> Self {
+        let total = total.or_else(|| inner.size_hint().exact());
+        Self {
+            inner,
+            reporter: Reporter::new(callback, interval, total),
+        }
+    }
+}
+
+impl<B, F> DownloadProgressBody<B, F> {
+    /// Returns the [`Progress`] of reading the body so far.
+    pub fn progress(&self) -> Progress {
+        self.reporter.progress()
+    }
+}
+
+impl<B, F> fmt::Debug for DownloadProgressBody<B, F>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadProgressBody")
+            .field("inner", &self.inner)
+            .field("progress", &self.progress())
+            .finish()
+    }
+}
+
+impl<B, F, E> Body for DownloadProgressBody<B, F>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+    F: Fn(&Progress) -> Result<(), E>,
+    E: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        // the download was cancelled by the callback
+        if this.reporter.is_cancelled() {
+            return Poll::Ready(None);
+        }
+
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => return Poll::Ready(this.reporter.end().err().map(Err)),
+        };
+        if let Some(data) = frame.data_ref() {
+            this.reporter.transferred(data.remaining() as u64)?;
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let progress = self.reporter.progress();
+        self.reporter.is_cancelled() || (progress.is_complete() && self.inner.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer that applies the [`DownloadProgress`] middleware.
+///
+/// See the [module docs](self) for an example.
+pub struct DownloadProgressLayer<F> {
+    callback: Arc<F>,
+    interval: Duration,
+}
+
+impl<F> DownloadProgressLayer<F> {
+    /// Create a new [`DownloadProgressLayer`], reporting the progress of response bodies
+    /// to `callback`, which is shared by all responses.
+    ///
+    /// The callback cancels the download by returning an error.
+    pub fn new(callback: F) -> Self {
+        Self {
+            callback: Arc::new(callback),
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the minimum interval between two reports of a response body, defaults to 100 milliseconds.
+    ///
+    /// Once the body was read to the end, the progress is reported regardless of the interval.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl<F> Clone for DownloadProgressLayer<F> {
+    fn clone(&self) -> Self {
+        Self {
+            callback: self.callback.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+impl<F> fmt::Debug for DownloadProgressLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadProgressLayer")
+            .field("callback", &format_args!("{}", std::any::type_name::<F>()))
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl<S, F> Layer<S> for DownloadProgressLayer<F> {
+    type Service = DownloadProgress<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DownloadProgress {
+            inner,
+            callback: self.callback.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+/// Client middleware reporting the [`Progress`] of downloading response bodies to a callback.
+///
+/// See the [module docs](self) for more details.
+pub struct DownloadProgress<S, F> {
+    inner: S,
+    callback: Arc<F>,
+    interval: Duration,
+}
+
+impl<S, F> DownloadProgress<S, F> {
+    /// Create a new [`DownloadProgress`], reporting the progress of response bodies to `callback`.
+    ///
+    /// The callback cancels the download by returning an error.
+    pub fn new(inner: S, callback: F) -> Self {
+        Self {
+            inner,
+            callback: Arc::new(callback),
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the minimum interval between two reports of a response body, defaults to 100 milliseconds.
+    ///
+    /// See [`DownloadProgressLayer::interval`] for more details.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `DownloadProgress` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(callback: F) -> DownloadProgressLayer<F> {
+        DownloadProgressLayer::new(callback)
+    }
+}
+
+impl<S, F> Clone for DownloadProgress<S, F>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            callback: self.callback.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+impl<S, F> fmt::Debug for DownloadProgress<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadProgress")
+            .field("inner", &self.inner)
+            .field("callback", &format_args!("{}", std::any::type_name::<F>()))
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl<S, F, ReqBody, ResBody> Service<Request<ReqBody>> for DownloadProgress<S, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<DownloadProgressBody<ResBody, F>>;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let res = self.inner.call(req).await?;
+        let total = res
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        Ok(res.map(|body| {
+            DownloadProgressBody::new(body, self.callback.clone(), self.interval, total)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use bytes::Bytes;
+    use http_body_util::BodyExt;
+    use std::sync::Mutex;
+    use tower_async::service_fn;
+
+    fn response(chunks: Vec<&'static str>, length: Option<u64>) -> Response<Body> {
+        let mut res = Response::new(Body::from_stream(futures_util::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, BoxError>(Bytes::from_static(chunk.as_bytes()))),
+        )));
+        if let Some(length) = length {
+            res.headers_mut()
+                .insert(header::CONTENT_LENGTH, length.to_string().parse().unwrap());
+        }
+        res
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_throttled_progress() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let svc = DownloadProgressLayer::new({
+            let reports = reports.clone();
+            move |progress: &Progress| {
+                reports.lock().unwrap().push(*progress);
+                Ok::<_, BoxError>(())
+            }
+        })
+        .interval(Duration::from_secs(1))
+        .layer(service_fn(|_: Request<()>| async {
+            Ok::<_, BoxError>(response(vec!["a", "bb", "ccc", "dddd"], Some(10)))
+        }));
+
+        let mut body = svc.call(Request::new(())).await.unwrap().into_body();
+        body.frame().await.unwrap().unwrap();
+        body.frame().await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        body.frame().await.unwrap().unwrap();
+        body.frame().await.unwrap().unwrap();
+        assert!(body.frame().await.is_none());
+
+        let reports: Vec<_> = reports
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|progress| {
+                (
+                    progress.transferred(),
+                    progress.total(),
+                    progress.is_complete(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            reports,
+            [
+                (1, Some(10), false),
+                (6, Some(10), false),
+                (10, Some(10), true)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_completion_without_length() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let svc = DownloadProgress::new(
+            service_fn(|_: Request<()>| async {
+                Ok::<_, BoxError>(response(vec!["hello", " world"], None))
+            }),
+            {
+                let reports = reports.clone();
+                move |progress: &Progress| {
+                    reports.lock().unwrap().push(*progress);
+                    Ok::<_, BoxError>(())
+                }
+            },
+        );
+
+        let res = svc.call(Request::new(())).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert_eq!((last.transferred(), last.total()), (11, None));
+        assert!(last.is_complete());
+    }
+
+    #[tokio::test]
+    async fn callback_error_cancels_download() {
+        let svc = DownloadProgress::new(
+            service_fn(|_: Request<()>| async {
+                Ok::<_, BoxError>(response(vec!["a", "b", "c"], Some(3)))
+            }),
+            |progress: &Progress| {
+                if progress.transferred() >= 2 {
+                    Err("cancelled by user")
+                } else {
+                    Ok(())
+                }
+            },
+        )
+        .interval(Duration::ZERO);
+
+        let err = svc
+            .call(Request::new(()))
+            .await
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "cancelled by user");
+    }
+}
@@ -0,0 +1,296 @@
+//! Middleware that sends `103 Early Hints` for critical assets.
+//!
+//! [RFC 8297] allows servers to send `Link` headers of critical assets, such as stylesheets
+//! and scripts, in an informational `103 Early Hints` response while the final response is
+//! still being produced. Clients can start preloading those assets right away.
+//!
+//! Informational responses are sent through the [`EarlyHints`] request extension. Servers
+//! that are able to emit them insert an [`EarlyHints`] handle, created with
+//! [`EarlyHints::new`], into every request. Handlers and middleware can then call
+//! [`EarlyHints::send`] to send hints.
+//!
+//! The [`SendEarlyHints`] middleware sends the hints configured for a route before the
+//! request is passed on to the inner service. If the server doesn't support informational
+//! responses, that is when the request has no [`EarlyHints`] extension, the middleware
+//! inserts one which collects the hints instead, and appends the hinted `Link` headers to the
+//! final response, such that clients can still preload the assets as soon as they receive
+//! the response headers. This is the case for services served by `hyper` (1.x), which offers
+//! no server API to send informational responses.
+//!
+//! [RFC 8297]: https://www.rfc-editor.org/rfc/rfc8297
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, HeaderMap, HeaderValue, Request, Response};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::early_hints::{EarlyHints, SendEarlyHintsLayer};
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // Handlers can send hints of their own as well.
+//!     let mut hints = HeaderMap::new();
+//!     hints.insert(header::LINK, HeaderValue::from_static("</app.js>; rel=preload; as=script"));
+//!     req.extensions().get::<EarlyHints>().unwrap().send(hints);
+//!
+//!     Ok(Response::new(Full::from("<!doctype html>")))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(SendEarlyHintsLayer::new().route("/", ["</style.css>; rel=preload; as=style"]))
+//!     .service_fn(handle);
+//!
+//! // Without a server that sends informational responses,
+//! // the hints are added to the final response.
+//! let res = svc.call(Request::get("/").body(Full::default())?).await?;
+//! let links: Vec<_> = res.headers().get_all(header::LINK).iter().collect();
+//! assert_eq!(
+//!     links,
+//!     ["</style.css>; rel=preload; as=style", "</app.js>; rel=preload; as=script"],
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Request extension to send `103 Early Hints` informational responses.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct EarlyHints {
+    sink: Arc<dyn Fn(HeaderMap) + Send + Sync>,
+}
+
+impl EarlyHints {
+    /// Create a new [`EarlyHints`] handle, which sends informational responses
+    /// with the given function.
+    ///
+    /// This is meant to be used by servers, which insert the handle in the
+    /// extensions of the request before calling the service.
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: Fn(HeaderMap) + Send + Sync + 'static,
+    {
+        Self {
+            sink: Arc::new(sink),
+        }
+    }
+
+    /// Send a `103 Early Hints` response with the given headers.
+    ///
+    /// Hints sent after the final response has been produced are ignored.
+    pub fn send(&self, headers: HeaderMap) {
+        (self.sink)(headers)
+    }
+}
+
+impl fmt::Debug for EarlyHints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EarlyHints").finish()
+    }
+}
+
+/// Layer that applies the [`SendEarlyHints`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct SendEarlyHintsLayer {
+    routes: Arc<Vec<(String, Vec<HeaderValue>)>>,
+}
+
+impl SendEarlyHintsLayer {
+    /// Create a new [`SendEarlyHintsLayer`], without any routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hint the given `Link` header values on requests for the given route.
+    ///
+    /// A route matches requests for exactly that path, or, if it ends with a `*`,
+    /// requests for any path starting with the part before the `*`, such as `/docs/*`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the links is not a valid header value.
+    pub fn route<I>(mut self, route: impl Into<String>, links: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: TryInto<HeaderValue>,
+        <I::Item as TryInto<HeaderValue>>::Error: fmt::Debug,
+    {
+        let links = links
+            .into_iter()
+            .map(|link| link.try_into().expect("invalid link header value"))
+            .collect();
+        Arc::make_mut(&mut self.routes).push((route.into(), links));
+        self
+    }
+}
+
+impl<S> Layer<S> for SendEarlyHintsLayer {
+    type Service = SendEarlyHints<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SendEarlyHints {
+            inner,
+            routes: self.routes.clone(),
+        }
+    }
+}
+
+/// Middleware that sends `103 Early Hints` for the critical assets of routes.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct SendEarlyHints<S> {
+    inner: S,
+    routes: Arc<Vec<(String, Vec<HeaderValue>)>>,
+}
+
+impl<S> SendEarlyHints<S> {
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SendEarlyHints` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> SendEarlyHintsLayer {
+        SendEarlyHintsLayer::new()
+    }
+
+    fn hints(&self, path: &str) -> HeaderMap {
+        let mut hints = HeaderMap::new();
+        for (route, links) in self.routes.iter() {
+            let matches = match route.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == route,
+            };
+            if matches {
+                for link in links {
+                    hints.append(header::LINK, link.clone());
+                }
+            }
+        }
+        hints
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SendEarlyHints<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        // Collect the hints if the server can't send informational responses.
+        let collected = if req.extensions().get::<EarlyHints>().is_none() {
+            let collected = Arc::new(Mutex::new(Some(HeaderMap::new())));
+            let sink = collected.clone();
+            req.extensions_mut().insert(EarlyHints::new(move |hints| {
+                if let Some(collected) = sink.lock().unwrap().as_mut() {
+                    for (name, value) in &hints {
+                        collected.append(name, value.clone());
+                    }
+                }
+            }));
+            Some(collected)
+        } else {
+            None
+        };
+
+        if req.method() == Method::GET {
+            let hints = self.hints(req.uri().path());
+            if !hints.is_empty() {
+                req.extensions().get::<EarlyHints>().unwrap().send(hints);
+            }
+        }
+
+        let mut res = self.inner.call(req).await?;
+
+        let hints = collected.and_then(|collected| collected.lock().unwrap().take());
+        if let Some(hints) = hints {
+            for link in hints.get_all(header::LINK) {
+                res.headers_mut().append(header::LINK, link.clone());
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    #[tokio::test]
+    async fn sends_hints_of_matching_routes() {
+        let svc = ServiceBuilder::new()
+            .layer(
+                SendEarlyHintsLayer::new()
+                    .route("/", ["</home.css>; rel=preload; as=style"])
+                    .route("/docs/*", ["</docs.css>; rel=preload; as=style"]),
+            )
+            .service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let send = |uri: &str| {
+            let sent = sent.clone();
+            let mut req = Request::get(uri).body(Body::empty()).unwrap();
+            req.extensions_mut().insert(EarlyHints::new(move |hints| {
+                sent.lock().unwrap().push(hints)
+            }));
+            svc.call(req)
+        };
+
+        let res = send("/docs/intro").await.unwrap();
+        // hints sent as informational responses are not added to the final response
+        assert!(res.headers().get(header::LINK).is_none());
+        send("/").await.unwrap();
+        send("/about").await.unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0][header::LINK], "</docs.css>; rel=preload; as=style");
+        assert_eq!(sent[1][header::LINK], "</home.css>; rel=preload; as=style");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_final_response() {
+        let svc = ServiceBuilder::new()
+            .layer(SendEarlyHintsLayer::new().route("/", ["</home.css>; rel=preload; as=style"]))
+            .service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc
+            .call(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            res.headers()[header::LINK],
+            "</home.css>; rel=preload; as=style"
+        );
+
+        let res = svc
+            .call(Request::post("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(res.headers().get(header::LINK).is_none());
+    }
+}
@@ -0,0 +1,370 @@
+//! Middleware that implements `Expect: 100-continue` semantics.
+//!
+//! Clients that send `Expect: 100-continue` wait for a `100 Continue` informational response
+//! before uploading the request body, such that the server can reject the request, for example
+//! because it isn't authorized or its body is too large, without the client wasting an upload.
+//!
+//! [hyper] sends the `100 Continue` response the first time the request body is polled. This
+//! makes it easy to signal continue too early: any middleware that reads the body before the
+//! request is validated, lets the client upload the body of a request that is rejected anyway.
+//!
+//! The [`ExpectContinue`] middleware makes the point at which a request may continue explicit:
+//!
+//! - the body of requests that expect `100-continue` can only be read once the request has
+//!   been approved, reading it before fails with a [`ContinueNotApproved`] error instead of
+//!   signalling continue;
+//! - requests are approved with the [`Continue`] request extension, or by the
+//!   [`ApproveContinue`] middleware, which is placed after the authorization and validation
+//!   middleware;
+//! - requests with any other expectation are rejected with `417 Expectation Failed`.
+//!
+//! [hyper]: https://docs.rs/hyper
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response, StatusCode};
+//! use http_body_util::{BodyExt, Full};
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::{
+//!     expect_continue::{ApproveContinueLayer, ExpectContinueBody, ExpectContinueLayer},
+//!     validate_request::ValidateRequestHeaderLayer,
+//! };
+//!
+//! async fn upload(req: Request<ExpectContinueBody<Full<Bytes>>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     let body = req.into_body().collect().await?.to_bytes();
+//!     Ok(Response::new(Full::from(format!("uploaded {} bytes", body.len()))))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(ExpectContinueLayer::new())
+//!     // Rejected requests never signal continue.
+//!     .layer(ValidateRequestHeaderLayer::bearer("secret"))
+//!     // Validated requests may continue.
+//!     .layer(ApproveContinueLayer::new())
+//!     .service_fn(upload);
+//!
+//! let req = Request::post("/upload")
+//!     .header(header::EXPECT, "100-continue")
+//!     .body(Full::from("large upload"))?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+//!
+//! let req = Request::post("/upload")
+//!     .header(header::EXPECT, "100-continue")
+//!     .header(header::AUTHORIZATION, "Bearer secret")
+//!     .body(Full::from("large upload"))?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::BoxError;
+use http::{header, Request, Response, StatusCode};
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Request extension to approve requests that expect `100-continue`.
+///
+/// Inserted by [`ExpectContinue`] into requests that expect `100-continue`.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct Continue {
+    approved: Arc<AtomicBool>,
+}
+
+impl Continue {
+    /// Approve the request, allowing its body to be read,
+    /// which signals the client to continue.
+    pub fn approve(&self) {
+        self.approved.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the request has been approved.
+    pub fn is_approved(&self) -> bool {
+        self.approved.load(Ordering::Acquire)
+    }
+}
+
+/// Error returned by [`ExpectContinueBody`] if the body is read
+/// before the request has been approved to continue.
+#[derive(Debug, Default)]
+pub struct ContinueNotApproved {
+    _priv: (),
+}
+
+impl fmt::Display for ContinueNotApproved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request body read before the request was approved to continue")
+    }
+}
+
+impl std::error::Error for ContinueNotApproved {}
+
+/// Layer that applies the [`ExpectContinue`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExpectContinueLayer {
+    _priv: (),
+}
+
+impl ExpectContinueLayer {
+    /// Create a new [`ExpectContinueLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for ExpectContinueLayer {
+    type Service = ExpectContinue<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExpectContinue::new(inner)
+    }
+}
+
+/// Middleware that holds back the body of requests that expect `100-continue`,
+/// until the request is approved.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectContinue<S> {
+    inner: S,
+}
+
+impl<S> ExpectContinue<S> {
+    /// Create a new [`ExpectContinue`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ExpectContinue` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> ExpectContinueLayer {
+        ExpectContinueLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ExpectContinue<S>
+where
+    S: Service<Request<ExpectContinueBody<ReqBody>>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+
+        let approval = match parts.headers.get(header::EXPECT) {
+            None => None,
+            Some(expect) if expect.as_bytes().eq_ignore_ascii_case(b"100-continue") => {
+                let approval = Continue::default();
+                parts.extensions.insert(approval.clone());
+                Some(approval)
+            }
+            Some(_) => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::EXPECTATION_FAILED;
+                return Ok(res);
+            }
+        };
+
+        let body = ExpectContinueBody {
+            inner: body,
+            approval,
+        };
+        self.inner.call(Request::from_parts(parts, body)).await
+    }
+}
+
+pin_project! {
+    /// Request body for [`ExpectContinue`].
+    ///
+    /// Fails with a [`ContinueNotApproved`] error if it is read before the request
+    /// has been approved to continue.
+    pub struct ExpectContinueBody<B> {
+        #[pin]
+        inner: B,
+        approval: Option<Continue>,
+    }
+}
+
+impl<B> fmt::Debug for ExpectContinueBody<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpectContinueBody")
+            .field("inner", &self.inner)
+            .field("approval", &self.approval)
+            .finish()
+    }
+}
+
+impl<B> Body for ExpectContinueBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if let Some(approval) = this.approval {
+            if !approval.is_approved() {
+                return Poll::Ready(Some(Err(ContinueNotApproved::default().into())));
+            }
+            *this.approval = None;
+        }
+        this.inner.poll_frame(cx).map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer that applies the [`ApproveContinue`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApproveContinueLayer {
+    _priv: (),
+}
+
+impl ApproveContinueLayer {
+    /// Create a new [`ApproveContinueLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for ApproveContinueLayer {
+    type Service = ApproveContinue<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApproveContinue::new(inner)
+    }
+}
+
+/// Middleware that approves all requests reaching it to continue.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct ApproveContinue<S> {
+    inner: S,
+}
+
+impl<S> ApproveContinue<S> {
+    /// Create a new [`ApproveContinue`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ApproveContinue` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> ApproveContinueLayer {
+        ApproveContinueLayer::new()
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ApproveContinue<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if let Some(approval) = req.extensions().get::<Continue>() {
+            approval.approve();
+        }
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http_body_util::BodyExt;
+    use tower_async::ServiceBuilder;
+
+    async fn read(req: Request<ExpectContinueBody<Body>>) -> Result<Response<Body>, BoxError> {
+        let body = req.into_body().collect().await?.to_bytes();
+        Ok(Response::new(Body::from(body)))
+    }
+
+    fn expect(value: &'static str) -> Request<Body> {
+        Request::post("/")
+            .header(header::EXPECT, value)
+            .body(Body::from("upload"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn body_is_held_back_until_approved() {
+        let svc = ServiceBuilder::new()
+            .layer(ExpectContinueLayer::new())
+            .service_fn(read);
+
+        let err = svc.call(expect("100-continue")).await.unwrap_err();
+        assert!(err.is::<ContinueNotApproved>());
+
+        // requests without expectation are not held back
+        let res = svc.call(Request::new(Body::from("upload"))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let svc = ServiceBuilder::new()
+            .layer(ExpectContinueLayer::new())
+            .layer(ApproveContinueLayer::new())
+            .service_fn(read);
+
+        let res = svc.call(expect("100-Continue")).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "upload");
+    }
+
+    #[tokio::test]
+    async fn unsupported_expectation() {
+        let svc = ServiceBuilder::new()
+            .layer(ExpectContinueLayer::new())
+            .service_fn(read);
+
+        let res = svc.call(expect("102-processing")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::EXPECTATION_FAILED);
+    }
+}
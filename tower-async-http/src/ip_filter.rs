@@ -0,0 +1,600 @@
+//! Middleware that allows or denies requests based on the IP address of the client.
+//!
+//! The [`IpFilter`] middleware matches the IP address of the client against sets of allowed
+//! and denied [`Cidr`] ranges, and rejects requests that aren't allowed with
+//! `403 Forbidden`, before they reach the inner service:
+//!
+//! - addresses within a denied range are rejected;
+//! - if any range is allowed, addresses outside of the allowed ranges are rejected as well.
+//!
+//! The address of the client is read from the [`ConnectInfo`] request extension, which the
+//! server has to insert into every request, for example with an [`AddExtensionLayer`] around
+//! the service created for every connection. Servers behind reverse proxies can read the
+//! address from the `Forwarded` or `X-Forwarded-For` header set by the proxies instead, see
+//! [`AddrSource`]. Requests whose address is unknown are rejected if any range is allowed or
+//! denied, such that requests can't bypass the filter by omitting it.
+//!
+//! The ranges can be changed while requests are served, through an [`IpFilterHandle`].
+//!
+//! [`AddExtensionLayer`]: crate::add_extension::AddExtensionLayer
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, net::SocketAddr};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::ip_filter::{ConnectInfo, IpFilterLayer};
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let layer = IpFilterLayer::new()
+//!     .allow("10.0.0.0/8".parse()?)
+//!     .deny("10.0.66.0/24".parse()?);
+//! let filter = layer.handle();
+//! let svc = ServiceBuilder::new().layer(layer).service_fn(handle);
+//!
+//! let peer: SocketAddr = "10.0.42.1:51234".parse()?;
+//! let req = Request::builder().extension(ConnectInfo(peer)).body(Full::default())?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//!
+//! // deny the address while serving requests
+//! filter.deny("10.0.42.1".parse()?);
+//! let req = Request::builder().extension(ConnectInfo(peer)).body(Full::default())?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::FORBIDDEN);
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderMap, Request, Response, StatusCode};
+use std::{
+    error, fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Request extension holding the address of the peer of the connection.
+///
+/// Servers have to insert it into every request, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectInfo(pub SocketAddr);
+
+/// Where the [`IpFilter`] reads the address of the client from.
+///
+/// Only the selected source is read. Proxies append the address of their peer to the
+/// forwarding header, so with the address read from a header, the filter uses the hop
+/// added by the first of the [trusted proxies] in front of the server, and ignores the
+/// hops before it, which the client can set to any address. Requests without the header,
+/// or with fewer hops than trusted proxies, have an unknown address.
+///
+/// Only read the address from a header that every trusted proxy sets, as clients can
+/// set any other header to any address.
+///
+/// [trusted proxies]: IpFilterLayer::trusted_proxies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AddrSource {
+    /// The address of the peer of the connection, from the [`ConnectInfo`] extension.
+    #[default]
+    Peer,
+    /// The `for` parameter of the `Forwarded` header.
+    Forwarded,
+    /// The `X-Forwarded-For` header.
+    XForwardedFor,
+}
+
+/// A range of IP addresses, in [CIDR notation], such as `192.168.0.0/16` or `2001:db8::/32`.
+///
+/// A single address, such as `192.168.1.1`, is a range of one address.
+///
+/// [CIDR notation]: https://en.wikipedia.org/wiki/Classless_Inter-Domain_Routing#CIDR_notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Create a new [`Cidr`] of the addresses sharing the first `prefix` bits with `addr`.
+    ///
+    /// Returns `None` if the prefix is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    /// Returns `true` if the range contains the given address.
+    ///
+    /// IPv4-mapped IPv6 addresses, such as `::ffff:10.0.0.1`, are matched as IPv4 addresses.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(addr)) => matches_prefix(
+                u32::from(range).into(),
+                u32::from(addr).into(),
+                32,
+                self.prefix,
+            ),
+            (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                matches_prefix(range.into(), addr.into(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn matches_prefix(range: u128, addr: u128, bits: u8, prefix: u8) -> bool {
+    let shift = bits - prefix;
+    shift == bits || (range >> shift) == (addr >> shift)
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(addr: IpAddr) -> Self {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((addr, prefix)) = s.split_once('/') else {
+            return s.parse::<IpAddr>().map(Self::from).map_err(|_| InvalidCidr);
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| InvalidCidr)?;
+        let prefix = prefix.parse::<u8>().map_err(|_| InvalidCidr)?;
+        Self::new(addr, prefix).ok_or(InvalidCidr)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Error returned when parsing an invalid [`Cidr`].
+#[derive(Debug)]
+pub struct InvalidCidr;
+
+impl fmt::Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid CIDR range")
+    }
+}
+
+impl error::Error for InvalidCidr {}
+
+#[derive(Debug, Clone, Default)]
+struct Rules {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl Rules {
+    fn is_allowed(&self, addr: Option<IpAddr>) -> bool {
+        let Some(addr) = addr else {
+            return self.allow.is_empty() && self.deny.is_empty();
+        };
+        !self.deny.iter().any(|cidr| cidr.contains(addr))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(addr)))
+    }
+}
+
+/// Handle to change the ranges of an [`IpFilter`] while requests are served.
+///
+/// Created with [`IpFilterLayer::handle`]. Changes apply to all services created by
+/// the layer.
+#[derive(Debug, Clone)]
+pub struct IpFilterHandle {
+    rules: Arc<RwLock<Rules>>,
+}
+
+impl IpFilterHandle {
+    /// Allow the given range.
+    pub fn allow(&self, cidr: Cidr) {
+        self.rules.write().unwrap().allow.push(cidr);
+    }
+
+    /// Deny the given range.
+    pub fn deny(&self, cidr: Cidr) {
+        self.rules.write().unwrap().deny.push(cidr);
+    }
+
+    /// Replace the allowed ranges.
+    pub fn set_allowed<I>(&self, cidrs: I)
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        self.rules.write().unwrap().allow = cidrs.into_iter().collect();
+    }
+
+    /// Replace the denied ranges.
+    pub fn set_denied<I>(&self, cidrs: I)
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        self.rules.write().unwrap().deny = cidrs.into_iter().collect();
+    }
+
+    /// Returns `true` if requests from the given address are allowed.
+    pub fn is_allowed(&self, addr: IpAddr) -> bool {
+        self.rules.read().unwrap().is_allowed(Some(addr))
+    }
+}
+
+/// Layer that applies the [`IpFilter`] middleware.
+///
+/// All services created by the layer, and all clones of the layer, share the same ranges,
+/// which are changed through its [`IpFilterHandle`]. Configuring the ranges of a clone with
+/// [`IpFilterLayer::allow`] or [`IpFilterLayer::deny`] doesn't change the other clones.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct IpFilterLayer {
+    rules: Arc<RwLock<Rules>>,
+    addr_source: AddrSource,
+    trusted_proxies: usize,
+}
+
+impl Default for IpFilterLayer {
+    fn default() -> Self {
+        Self {
+            rules: Default::default(),
+            addr_source: AddrSource::Peer,
+            trusted_proxies: 1,
+        }
+    }
+}
+
+impl IpFilterLayer {
+    /// Create a new [`IpFilterLayer`], allowing all requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the given range.
+    pub fn allow(mut self, cidr: Cidr) -> Self {
+        let mut rules = self.rules.read().unwrap().clone();
+        rules.allow.push(cidr);
+        self.rules = Arc::new(RwLock::new(rules));
+        self
+    }
+
+    /// Deny the given range.
+    pub fn deny(mut self, cidr: Cidr) -> Self {
+        let mut rules = self.rules.read().unwrap().clone();
+        rules.deny.push(cidr);
+        self.rules = Arc::new(RwLock::new(rules));
+        self
+    }
+
+    /// Set where the address of the client is read from.
+    ///
+    /// Defaults to [`AddrSource::Peer`].
+    pub fn addr_source(mut self, source: AddrSource) -> Self {
+        self.addr_source = source;
+        self
+    }
+
+    /// Set the number of reverse proxies in front of the server which append to the
+    /// forwarding header selected with [`IpFilterLayer::addr_source`].
+    ///
+    /// The address of the client is the `count`th hop from the end of the header.
+    /// Defaults to `1`, a single proxy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is zero.
+    pub fn trusted_proxies(mut self, count: usize) -> Self {
+        assert!(
+            count > 0,
+            "the number of trusted proxies must be at least 1"
+        );
+        self.trusted_proxies = count;
+        self
+    }
+
+    /// Returns an [`IpFilterHandle`] to change the ranges while requests are served.
+    ///
+    /// Ranges configured on the layer afterwards aren't shared with the handle.
+    pub fn handle(&self) -> IpFilterHandle {
+        IpFilterHandle {
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFilter<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFilter {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Middleware that allows or denies requests based on the IP address of the client.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct IpFilter<S> {
+    inner: S,
+    layer: IpFilterLayer,
+}
+
+impl<S> IpFilter<S> {
+    /// Create a new [`IpFilter`], allowing all requests.
+    pub fn new(inner: S) -> Self {
+        IpFilterLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with an `IpFilter` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> IpFilterLayer {
+        IpFilterLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for IpFilter<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let hop = self.layer.trusted_proxies;
+        let addr = match self.layer.addr_source {
+            AddrSource::Peer => req
+                .extensions()
+                .get::<ConnectInfo>()
+                .map(|ConnectInfo(addr)| addr.ip()),
+            AddrSource::Forwarded => forwarded_addr(req.headers(), hop),
+            AddrSource::XForwardedFor => hop_from_end(req.headers(), "x-forwarded-for", hop)
+                .and_then(|addr| addr.parse().ok()),
+        };
+
+        if !self.layer.rules.read().unwrap().is_allowed(addr) {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::FORBIDDEN;
+            return Ok(res);
+        }
+        self.inner.call(req).await
+    }
+}
+
+/// Returns the `for` address of the `hop`th element from the end of the `Forwarded` header.
+fn forwarded_addr(headers: &HeaderMap, hop: usize) -> Option<IpAddr> {
+    hop_from_end(headers, header::FORWARDED, hop)?
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+        .and_then(|(_, node)| parse_node(node.trim().trim_matches('"')))
+}
+
+/// Returns the `hop`th of the comma separated values of the header, counting from the end.
+fn hop_from_end(headers: &HeaderMap, name: impl header::AsHeaderName, hop: usize) -> Option<&str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .nth_back(hop - 1)
+}
+
+/// Parses a node of the `Forwarded` header, such as `192.0.2.43:47011` or `"[2001:db8::1]"`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Some(node) = node.strip_prefix('[') {
+        return node.split_once(']')?.0.parse().ok();
+    }
+    node.split(':').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http::HeaderName;
+    use std::convert::Infallible;
+    use tower_async::service_fn;
+
+    async fn ok(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_contains() {
+        assert!(cidr("10.0.0.0/8").contains(ip("10.255.0.1")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(cidr("10.0.0.0/8").contains(ip("::ffff:10.0.0.1")));
+        assert!(cidr("0.0.0.0/0").contains(ip("192.168.1.1")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("::1")));
+        assert!(cidr("2001:db8::/32").contains(ip("2001:db8:cafe::17")));
+        assert!(!cidr("2001:db8::/32").contains(ip("2001:db9::1")));
+        assert!(cidr("192.168.1.1").contains(ip("192.168.1.1")));
+        assert!(!cidr("192.168.1.1").contains(ip("192.168.1.2")));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert_eq!(cidr("::1").to_string(), "::1/128");
+    }
+
+    #[tokio::test]
+    async fn filters_by_connect_info() {
+        let layer = IpFilterLayer::new()
+            .allow(cidr("10.0.0.0/8"))
+            .deny(cidr("10.0.66.0/24"));
+        let handle = layer.handle();
+        let svc = layer.layer(service_fn(ok));
+
+        let status = |addr: Option<&str>| {
+            let mut req = Request::new(Body::empty());
+            if let Some(addr) = addr {
+                req.extensions_mut()
+                    .insert(ConnectInfo(SocketAddr::new(ip(addr), 1234)));
+            }
+            let svc = svc.clone();
+            async move { svc.call(req).await.unwrap().status() }
+        };
+
+        assert_eq!(status(Some("10.0.42.1")).await, StatusCode::OK);
+        assert_eq!(status(Some("10.0.66.1")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(Some("192.168.1.1")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(None).await, StatusCode::FORBIDDEN);
+
+        handle.set_allowed([]);
+        handle.set_denied([cidr("10.0.42.1")]);
+        assert_eq!(status(Some("10.0.42.1")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(Some("192.168.1.1")).await, StatusCode::OK);
+        assert_eq!(status(None).await, StatusCode::FORBIDDEN);
+
+        handle.set_denied([]);
+        assert_eq!(status(None).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn configuring_clones_doesnt_change_other_clones() {
+        let layer = IpFilterLayer::new().deny(cidr("10.0.0.0/8"));
+        let other = layer.clone().deny(cidr("192.168.0.0/16"));
+
+        assert!(!layer.handle().is_allowed(ip("10.0.0.1")));
+        assert!(layer.handle().is_allowed(ip("192.168.0.1")));
+        assert!(!other.handle().is_allowed(ip("192.168.0.1")));
+    }
+
+    #[tokio::test]
+    async fn filters_by_forwarded_headers() {
+        let layer = IpFilterLayer::new()
+            .allow(cidr("2001:db8::/32"))
+            .allow(cidr("192.0.2.0/24"));
+
+        let status = |source: AddrSource, name: &'static str, value: &'static str| {
+            let mut req = Request::builder()
+                .extension(ConnectInfo(SocketAddr::new(ip("192.0.2.1"), 1234)))
+                .body(Body::empty())
+                .unwrap();
+            if !name.is_empty() {
+                req.headers_mut()
+                    .insert(HeaderName::from_static(name), value.parse().unwrap());
+            }
+            let svc = layer.clone().addr_source(source).layer(service_fn(ok));
+            async move { svc.call(req).await.unwrap().status() }
+        };
+        let forwarded = |value| status(AddrSource::Forwarded, "forwarded", value);
+        let x_forwarded_for = |value| status(AddrSource::XForwardedFor, "x-forwarded-for", value);
+
+        assert_eq!(
+            forwarded("for=198.51.100.1, for=\"[2001:db8:cafe::17]:4711\"").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            forwarded("for=192.0.2.60;proto=http;by=203.0.113.43").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            forwarded("for=192.0.2.60, for=unknown").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            x_forwarded_for("198.51.100.1, 192.0.2.43").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            x_forwarded_for("192.0.2.43, 198.51.100.1").await,
+            StatusCode::FORBIDDEN
+        );
+
+        // the header isn't read unless selected
+        assert_eq!(
+            status(AddrSource::Peer, "x-forwarded-for", "198.51.100.1").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(AddrSource::XForwardedFor, "forwarded", "for=192.0.2.60").await,
+            StatusCode::FORBIDDEN
+        );
+        // doesn't fall back to the address of the peer
+        assert_eq!(
+            status(AddrSource::Forwarded, "", "").await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_spoofed_forwarded_header() {
+        let svc = IpFilterLayer::new()
+            .allow(cidr("192.0.2.0/24"))
+            .addr_source(AddrSource::XForwardedFor)
+            .layer(service_fn(ok));
+
+        // the proxy only appends to `X-Forwarded-For`, the client set `Forwarded` itself
+        let req = Request::builder()
+            .header(header::FORWARDED, "for=192.0.2.60")
+            .header("x-forwarded-for", "198.51.100.1")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn uses_hop_of_first_trusted_proxy() {
+        let layer = IpFilterLayer::new()
+            .allow(cidr("192.0.2.0/24"))
+            .addr_source(AddrSource::XForwardedFor)
+            .trusted_proxies(2);
+
+        let status = |value: &'static str| {
+            let req = Request::builder()
+                .header("x-forwarded-for", value)
+                .body(Body::empty())
+                .unwrap();
+            let svc = layer.clone().layer(service_fn(ok));
+            async move { svc.call(req).await.unwrap().status() }
+        };
+
+        // client, first proxy, second proxy
+        assert_eq!(
+            status("198.51.100.1, 192.0.2.43, 10.0.0.1").await,
+            StatusCode::OK
+        );
+        // spoofed hop prepended by the client
+        assert_eq!(
+            status("192.0.2.43, 198.51.100.1, 10.0.0.1").await,
+            StatusCode::FORBIDDEN
+        );
+        // fewer hops than trusted proxies
+        assert_eq!(status("192.0.2.43").await, StatusCode::FORBIDDEN);
+    }
+}
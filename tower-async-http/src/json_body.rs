@@ -0,0 +1,691 @@
+//! Middleware that parses JSON request bodies.
+//!
+//! The [`ParseJson`] middleware reads the body of each request, up to a [maximum size], and
+//! deserializes it into `T` with [`serde_json`] before the handler runs. The deserialized
+//! value is inserted into the request extensions as [`Json`], and the request is passed on
+//! with a [`Full`] body containing the raw payload.
+//!
+//! The syntax of the body is validated incrementally, as its frames arrive. Requests are
+//! therefore rejected as soon as the body is known to be invalid, or to exceed the maximum
+//! size, without reading the remainder of a large payload first.
+//!
+//! Requests are rejected with:
+//!
+//! - `415 Unsupported Media Type` if their content type isn't JSON, see
+//!   [`ParseJsonLayer::require_content_type`];
+//! - `413 Payload Too Large` if their body exceeds the maximum size;
+//! - `400 Bad Request` if their body isn't valid JSON, or can't be read;
+//! - `422 Unprocessable Entity` if their body is valid JSON, which can't be deserialized into `T`.
+//!
+//! [maximum size]: ParseJsonLayer::max_body_size
+//! [`Full`]: http_body_util::Full
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use std::collections::HashMap;
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::json_body::{Json, ParseJsonLayer};
+//!
+//! type Order = HashMap<String, u32>;
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     let Json(order) = req.extensions().get::<Json<Order>>().unwrap();
+//!     assert_eq!(order["apples"], 3);
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(ParseJsonLayer::<Order>::new().max_body_size(64 * 1024))
+//!     .service_fn(handle);
+//!
+//! let req = Request::post("/orders")
+//!     .header("content-type", "application/json")
+//!     .body(Full::<Bytes>::from(r#"{"apples": 3}"#))?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//!
+//! let req = Request::post("/orders")
+//!     .header("content-type", "application/json")
+//!     .body(Full::<Bytes>::from(r#"{"apples": "three"}"#))?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::{header, HeaderMap, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use serde::de::DeserializeOwned;
+use std::{fmt, marker::PhantomData};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The default maximum size of a body, 1 MiB.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The maximum nesting depth of arrays and objects, matching the recursion limit of
+/// [`serde_json`], such that deeply nested bodies are rejected early.
+const MAX_DEPTH: usize = 128;
+
+/// A JSON request body deserialized by [`ParseJson`].
+///
+/// Inserted into the request extensions by [`ParseJson`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+/// Layer that applies [`ParseJson`] which parses JSON request bodies into `T`.
+///
+/// See the [module docs](self) for more details.
+pub struct ParseJsonLayer<T> {
+    max_body_size: usize,
+    require_content_type: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ParseJsonLayer<T> {
+    /// Create a new [`ParseJsonLayer`].
+    pub fn new() -> Self {
+        Self {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            require_content_type: true,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set the maximum size of bodies that are parsed.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+
+    /// Set whether requests must have a JSON content type, that is `application/json`
+    /// or a type with the `+json` suffix.
+    ///
+    /// Defaults to `true`.
+    pub fn require_content_type(mut self, require: bool) -> Self {
+        self.require_content_type = require;
+        self
+    }
+}
+
+impl<T> Default for ParseJsonLayer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for ParseJsonLayer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ParseJsonLayer<T> {}
+
+impl<T> fmt::Debug for ParseJsonLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseJsonLayer")
+            .field("target", &format_args!("{}", std::any::type_name::<T>()))
+            .field("max_body_size", &self.max_body_size)
+            .field("require_content_type", &self.require_content_type)
+            .finish()
+    }
+}
+
+impl<S, T> Layer<S> for ParseJsonLayer<T> {
+    type Service = ParseJson<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ParseJson {
+            inner,
+            layer: *self,
+        }
+    }
+}
+
+/// Middleware that parses JSON request bodies into `T`.
+///
+/// See the [module docs](self) for more details.
+pub struct ParseJson<S, T> {
+    inner: S,
+    layer: ParseJsonLayer<T>,
+}
+
+impl<S, T> ParseJson<S, T> {
+    /// Create a new [`ParseJson`].
+    pub fn new(inner: S) -> Self {
+        ParseJsonLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ParseJson` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> ParseJsonLayer<T> {
+        ParseJsonLayer::new()
+    }
+}
+
+impl<S, T> Clone for ParseJson<S, T>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer,
+        }
+    }
+}
+
+impl<S, T> fmt::Debug for ParseJson<S, T>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseJson")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for ParseJson<S, T>
+where
+    S: Service<Request<Full<Bytes>>, Response = Response<ResBody>>,
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if self.layer.require_content_type && !is_json(req.headers()) {
+            return Ok(reject(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        }
+
+        let content_length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+        if content_length.is_some_and(|len| len > self.layer.max_body_size) {
+            return Ok(reject(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+
+        let (mut parts, body) = req.into_parts();
+        let mut body = std::pin::pin!(body);
+        let mut buf = BytesMut::with_capacity(content_length.unwrap_or_default());
+        let mut validator = Validator::default();
+        while let Some(frame) = body.frame().await {
+            let Ok(frame) = frame else {
+                return Ok(reject(StatusCode::BAD_REQUEST));
+            };
+            let Ok(mut data) = frame.into_data() else {
+                continue;
+            };
+            let chunk = data.copy_to_bytes(data.remaining());
+            if buf.len() + chunk.len() > self.layer.max_body_size {
+                return Ok(reject(StatusCode::PAYLOAD_TOO_LARGE));
+            }
+            if validator.feed(&chunk).is_err() {
+                return Ok(reject(StatusCode::BAD_REQUEST));
+            }
+            buf.extend_from_slice(&chunk);
+        }
+        if validator.finish().is_err() {
+            return Ok(reject(StatusCode::BAD_REQUEST));
+        }
+
+        let body = buf.freeze();
+        let value = match serde_json::from_slice::<T>(&body) {
+            Ok(value) => value,
+            Err(err) if err.is_data() => return Ok(reject(StatusCode::UNPROCESSABLE_ENTITY)),
+            Err(_) => return Ok(reject(StatusCode::BAD_REQUEST)),
+        };
+
+        parts.extensions.insert(Json(value));
+        self.inner
+            .call(Request::from_parts(parts, Full::new(body)))
+            .await
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+fn reject<B>(status: StatusCode) -> Response<B>
+where
+    B: Default,
+{
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    res
+}
+
+/// Error returned by [`Validator`] for invalid or incomplete JSON.
+#[derive(Debug)]
+struct InvalidJson;
+
+/// Validates the syntax of JSON incrementally, such that invalid documents are
+/// detected as soon as the first invalid byte arrives.
+///
+/// Only the syntax is validated, the UTF-8 encoding of strings is left to [`serde_json`].
+#[derive(Debug, Default)]
+struct Validator {
+    stack: Vec<Container>,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Container {
+    Object,
+    Array,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum State {
+    /// Expecting a value.
+    #[default]
+    Value,
+    /// After `[`, expecting a value or `]`.
+    ArrayStart,
+    /// After `{`, expecting a key or `}`.
+    ObjectStart,
+    /// After `,` in an object, expecting a key.
+    Key,
+    /// After a key, expecting `:`.
+    Colon,
+    /// After a value in a container, expecting `,` or the end of the container.
+    CommaOrEnd,
+    /// After the top-level value, expecting only whitespace.
+    Done,
+    String {
+        key: bool,
+        escape: Escape,
+    },
+    Number(Number),
+    Literal(&'static [u8]),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Escape {
+    None,
+    Start,
+    Unicode(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Minus,
+    Zero,
+    Integer,
+    Dot,
+    Fraction,
+    Exponent,
+    ExponentSign,
+    ExponentDigits,
+}
+
+impl Number {
+    fn next(self, byte: u8) -> Option<Self> {
+        use Number::{Dot, Exponent, ExponentDigits, ExponentSign, Fraction, Integer, Minus, Zero};
+
+        match (self, byte) {
+            (Minus, b'0') => Some(Zero),
+            (Minus, b'1'..=b'9') => Some(Integer),
+            (Integer, b'0'..=b'9') => Some(Integer),
+            (Zero | Integer, b'.') => Some(Dot),
+            (Dot | Fraction, b'0'..=b'9') => Some(Fraction),
+            (Zero | Integer | Fraction, b'e' | b'E') => Some(Exponent),
+            (Exponent, b'+' | b'-') => Some(ExponentSign),
+            (Exponent | ExponentSign | ExponentDigits, b'0'..=b'9') => Some(ExponentDigits),
+            _ => None,
+        }
+    }
+
+    fn is_complete(self) -> bool {
+        matches!(
+            self,
+            Number::Zero | Number::Integer | Number::Fraction | Number::ExponentDigits
+        )
+    }
+}
+
+impl Validator {
+    fn feed(&mut self, chunk: &[u8]) -> Result<(), InvalidJson> {
+        chunk.iter().try_for_each(|&byte| self.byte(byte))
+    }
+
+    fn finish(&self) -> Result<(), InvalidJson> {
+        match self.state {
+            State::Done => Ok(()),
+            State::Number(number) if number.is_complete() && self.stack.is_empty() => Ok(()),
+            _ => Err(InvalidJson),
+        }
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), InvalidJson> {
+        let is_whitespace = matches!(byte, b' ' | b'\t' | b'\n' | b'\r');
+        match self.state {
+            State::String { key, escape } => self.string(key, escape, byte),
+            State::Number(number) => match number.next(byte) {
+                Some(next) => {
+                    self.state = State::Number(next);
+                    Ok(())
+                }
+                None if number.is_complete() => {
+                    self.end_value();
+                    self.byte(byte)
+                }
+                None => Err(InvalidJson),
+            },
+            State::Literal(rest) => {
+                if rest.first() != Some(&byte) {
+                    return Err(InvalidJson);
+                }
+                if rest.len() == 1 {
+                    self.end_value();
+                } else {
+                    self.state = State::Literal(&rest[1..]);
+                }
+                Ok(())
+            }
+            _ if is_whitespace => Ok(()),
+            State::Value => self.start_value(byte),
+            State::ArrayStart if byte == b']' => self.end_container(Container::Array),
+            State::ArrayStart => self.start_value(byte),
+            State::ObjectStart if byte == b'}' => self.end_container(Container::Object),
+            State::ObjectStart | State::Key if byte == b'"' => {
+                self.state = State::String {
+                    key: true,
+                    escape: Escape::None,
+                };
+                Ok(())
+            }
+            State::Colon if byte == b':' => {
+                self.state = State::Value;
+                Ok(())
+            }
+            State::CommaOrEnd => match (self.stack.last(), byte) {
+                (Some(Container::Object), b',') => {
+                    self.state = State::Key;
+                    Ok(())
+                }
+                (Some(Container::Array), b',') => {
+                    self.state = State::Value;
+                    Ok(())
+                }
+                (_, b'}') => self.end_container(Container::Object),
+                (_, b']') => self.end_container(Container::Array),
+                _ => Err(InvalidJson),
+            },
+            _ => Err(InvalidJson),
+        }
+    }
+
+    fn start_value(&mut self, byte: u8) -> Result<(), InvalidJson> {
+        self.state = match byte {
+            b'{' | b'[' => {
+                if self.stack.len() == MAX_DEPTH {
+                    return Err(InvalidJson);
+                }
+                if byte == b'{' {
+                    self.stack.push(Container::Object);
+                    State::ObjectStart
+                } else {
+                    self.stack.push(Container::Array);
+                    State::ArrayStart
+                }
+            }
+            b'"' => State::String {
+                key: false,
+                escape: Escape::None,
+            },
+            b'-' => State::Number(Number::Minus),
+            b'0' => State::Number(Number::Zero),
+            b'1'..=b'9' => State::Number(Number::Integer),
+            b't' => State::Literal(b"rue"),
+            b'f' => State::Literal(b"alse"),
+            b'n' => State::Literal(b"ull"),
+            _ => return Err(InvalidJson),
+        };
+        Ok(())
+    }
+
+    fn string(&mut self, key: bool, escape: Escape, byte: u8) -> Result<(), InvalidJson> {
+        let escape = match (escape, byte) {
+            (Escape::None, b'"') => {
+                if key {
+                    self.state = State::Colon;
+                } else {
+                    self.end_value();
+                }
+                return Ok(());
+            }
+            (Escape::None, b'\\') => Escape::Start,
+            (_, 0x00..=0x1f) => return Err(InvalidJson),
+            (Escape::None, _) => Escape::None,
+            (Escape::Start, b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => Escape::None,
+            (Escape::Start, b'u') => Escape::Unicode(4),
+            (Escape::Unicode(remaining), byte) if byte.is_ascii_hexdigit() => {
+                if remaining == 1 {
+                    Escape::None
+                } else {
+                    Escape::Unicode(remaining - 1)
+                }
+            }
+            _ => return Err(InvalidJson),
+        };
+        self.state = State::String { key, escape };
+        Ok(())
+    }
+
+    fn end_container(&mut self, container: Container) -> Result<(), InvalidJson> {
+        match (self.stack.pop(), container) {
+            (Some(Container::Object), Container::Object)
+            | (Some(Container::Array), Container::Array) => {
+                self.end_value();
+                Ok(())
+            }
+            _ => Err(InvalidJson),
+        }
+    }
+
+    fn end_value(&mut self) {
+        self.state = if self.stack.is_empty() {
+            State::Done
+        } else {
+            State::CommaOrEnd
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use futures_util::StreamExt;
+    use serde_json::Value;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+    use tower_async::{service_fn, ServiceBuilder};
+
+    async fn echo(req: Request<Full<Bytes>>) -> Result<Response<Body>, Infallible> {
+        let Json(value) = req.extensions().get::<Json<Value>>().unwrap();
+        Ok(Response::new(Body::from(value.to_string())))
+    }
+
+    fn request(body: impl Into<Body>) -> Request<Body> {
+        Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .unwrap()
+    }
+
+    fn validate(json: &str) -> bool {
+        let mut validator = Validator::default();
+        validator.feed(json.as_bytes()).is_ok() && validator.finish().is_ok()
+    }
+
+    #[tokio::test]
+    async fn parses_body() {
+        let svc = ServiceBuilder::new()
+            .layer(ParseJsonLayer::<Value>::new())
+            .service_fn(echo);
+
+        let res = svc
+            .call(request(
+                r#" {"a": [1, -2.5e3, true, null], "b": "\u00e9"} "#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = crate::test_helpers::to_bytes(res.into_body())
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"a":[1,-2500.0,true,null],"b":"é"}"#);
+    }
+
+    #[tokio::test]
+    async fn rejects_requests() {
+        let svc = ParseJsonLayer::<Vec<u32>>::new()
+            .max_body_size(16)
+            .layer(service_fn(|_: Request<Full<Bytes>>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+
+        let req = Request::post("/").body(Body::from("[1]")).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut req = request("[1, 2, 3, 4, 5, 6, 7, 8]");
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, "24".parse().unwrap());
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let res = svc.call(request("[1, 2, 3, 4, 5, 6, 7, 8]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let res = svc.call(request("[1, 2,]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = svc.call(request("[1, -2]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let mut req = request("[1]");
+        req.headers_mut().insert(
+            header::CONTENT_TYPE,
+            "application/problem+json; charset=utf-8".parse().unwrap(),
+        );
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn aborts_early_on_invalid_prefix() {
+        let svc = ServiceBuilder::new()
+            .layer(ParseJsonLayer::<Value>::new().require_content_type(false))
+            .service_fn(echo);
+
+        // the rest of the body isn't read once its first frame is known to be invalid
+        let read = Arc::new(AtomicBool::new(false));
+        let rest = futures_util::stream::once({
+            let read = read.clone();
+            async move {
+                read.store(true, Ordering::SeqCst);
+                Ok(Bytes::from("}"))
+            }
+        });
+        let stream =
+            futures_util::stream::iter([Ok::<_, Infallible>(Bytes::from("{\"a\" 1"))]).chain(rest);
+        let req = Request::post("/").body(Body::from_stream(stream)).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(!read.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn validates_syntax() {
+        for valid in [
+            "0",
+            " -0.5E+10 ",
+            "\"\\\"\\u12aF\"",
+            "[]",
+            "{}",
+            "[[], {}, [{}]]",
+            r#"{"a": {"b": [1, "c", false]}, "d": null}"#,
+            "true",
+        ] {
+            assert!(validate(valid), "{valid}");
+        }
+
+        for invalid in [
+            "",
+            "01",
+            "-",
+            "1.",
+            "1e",
+            ".5",
+            "+1",
+            "[1,]",
+            "{\"a\"}",
+            "{\"a\":}",
+            "{1: 2}",
+            "[1 2]",
+            "[}",
+            "{]",
+            "\"\\x\"",
+            "\"\\u12g4\"",
+            "\"a\nb\"",
+            "tru",
+            "nul",
+            "[1] [2]",
+            "[[]",
+        ] {
+            assert!(!validate(invalid), "{invalid}");
+        }
+
+        assert!(validate(&format!(
+            "{}{}",
+            "[".repeat(MAX_DEPTH),
+            "]".repeat(MAX_DEPTH)
+        )));
+        assert!(!validate(&format!(
+            "{}{}",
+            "[".repeat(MAX_DEPTH + 1),
+            "]".repeat(MAX_DEPTH + 1)
+        )));
+    }
+}
@@ -0,0 +1,366 @@
+//! Middleware that keeps idle streaming responses alive.
+//!
+//! Proxies, load balancers and other intermediaries often close connections that have been
+//! idle for some time. Long-lived streaming responses, such as server-sent events or
+//! streamed JSON, can be idle for longer than that while they wait for the next event.
+//!
+//! The [`Keepalive`] middleware sends a small keepalive frame on such responses whenever
+//! they have been idle for a configured duration. The frame depends on the content type
+//! of the response:
+//!
+//! | Content type | Frame |
+//! |---|---|
+//! | `text/event-stream` | a `:` comment line, ignored by `EventSource` |
+//! | `application/x-ndjson`, `application/jsonl`, `application/json` | a `\n` |
+//!
+//! Keepalive frames are only sent in between messages, that is when the data sent so far
+//! ends with a blank line for server-sent events, or a newline for JSON, such that they never
+//! end up in the middle of a message. Other content types can be configured with
+//! [`KeepaliveLayer::content_type`]. Responses with a `Content-Length` header, and responses
+//! of any other content type, are passed through unchanged.
+//!
+//! Upgraded connections, such as WebSockets, are no longer served through the response body,
+//! so they have to be kept alive with ping frames by the WebSocket implementation instead.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::keepalive::KeepaliveLayer;
+//!
+//! async fn events(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // ...
+//!     # Ok(Response::builder()
+//!     #     .header(header::CONTENT_TYPE, "text/event-stream")
+//!     #     .body(Full::from("data: hello\n\n"))
+//!     #     .unwrap())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     // Send a keepalive frame after 15 seconds without data.
+//!     .layer(KeepaliveLayer::new(Duration::from_secs(15)))
+//!     .service_fn(events);
+//!
+//! let res = svc.call(Request::new(Full::default())).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::{Buf, Bytes};
+use http::{header, HeaderMap, Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep, Instant, Sleep};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Keepalive frame sent by [`Keepalive`] on idle responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepaliveFrame {
+    payload: Bytes,
+    boundary: Bytes,
+}
+
+impl KeepaliveFrame {
+    /// Create a new [`KeepaliveFrame`] with the given payload.
+    pub fn new(payload: impl Into<Bytes>) -> Self {
+        Self {
+            payload: payload.into(),
+            boundary: Bytes::new(),
+        }
+    }
+
+    /// Only send the frame if the data sent so far ends with the given boundary,
+    /// or no data was sent yet.
+    ///
+    /// By default the frame is sent regardless of the data sent so far.
+    pub fn after(mut self, boundary: impl Into<Bytes>) -> Self {
+        self.boundary = boundary.into();
+        self
+    }
+
+    fn server_sent_events() -> Self {
+        Self::new(Bytes::from_static(b":\n\n")).after(Bytes::from_static(b"\n\n"))
+    }
+
+    fn json() -> Self {
+        Self::new(Bytes::from_static(b"\n")).after(Bytes::from_static(b"\n"))
+    }
+}
+
+/// Layer that applies the [`Keepalive`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct KeepaliveLayer {
+    idle: Duration,
+    frames: Arc<Vec<(String, KeepaliveFrame)>>,
+}
+
+impl KeepaliveLayer {
+    /// Create a new [`KeepaliveLayer`], sending a keepalive frame
+    /// whenever a response has been idle for the given duration.
+    pub fn new(idle: Duration) -> Self {
+        Self {
+            idle,
+            frames: Arc::new(vec![
+                (
+                    "text/event-stream".to_owned(),
+                    KeepaliveFrame::server_sent_events(),
+                ),
+                ("application/x-ndjson".to_owned(), KeepaliveFrame::json()),
+                ("application/jsonl".to_owned(), KeepaliveFrame::json()),
+                ("application/json".to_owned(), KeepaliveFrame::json()),
+            ]),
+        }
+    }
+
+    /// Send the given frame on idle responses of the given media type,
+    /// replacing the default frame of that media type, if any.
+    pub fn content_type(mut self, media_type: impl Into<String>, frame: KeepaliveFrame) -> Self {
+        let media_type = media_type.into().to_ascii_lowercase();
+        let frames = Arc::make_mut(&mut self.frames);
+        frames.retain(|(existing, _)| *existing != media_type);
+        frames.push((media_type, frame));
+        self
+    }
+}
+
+impl<S> Layer<S> for KeepaliveLayer {
+    type Service = Keepalive<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Keepalive {
+            inner,
+            idle: self.idle,
+            frames: self.frames.clone(),
+        }
+    }
+}
+
+/// Middleware that sends keepalive frames on idle streaming responses.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Keepalive<S> {
+    inner: S,
+    idle: Duration,
+    frames: Arc<Vec<(String, KeepaliveFrame)>>,
+}
+
+impl<S> Keepalive<S> {
+    /// Create a new [`Keepalive`], sending a keepalive frame
+    /// whenever a response has been idle for the given duration.
+    pub fn new(inner: S, idle: Duration) -> Self {
+        KeepaliveLayer::new(idle).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Keepalive` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(idle: Duration) -> KeepaliveLayer {
+        KeepaliveLayer::new(idle)
+    }
+
+    fn frame(&self, headers: &HeaderMap) -> Option<KeepaliveFrame> {
+        if headers.contains_key(header::CONTENT_LENGTH) {
+            return None;
+        }
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        let media_type = content_type.split(';').next()?.trim();
+        self.frames
+            .iter()
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(media_type))
+            .map(|(_, frame)| frame.clone())
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Keepalive<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<KeepaliveBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let res = self.inner.call(req).await?;
+        let frame = self.frame(res.headers());
+        let idle = self.idle;
+        Ok(res.map(|body| KeepaliveBody::new(body, idle, frame)))
+    }
+}
+
+pin_project! {
+    /// Response body for [`Keepalive`].
+    pub struct KeepaliveBody<B> {
+        #[pin]
+        inner: B,
+        idle: Duration,
+        keepalive: Option<(KeepaliveFrame, Pin<Box<Sleep>>)>,
+        tail: Vec<u8>,
+    }
+}
+
+impl<B> KeepaliveBody<B> {
+    fn new(inner: B, idle: Duration, frame: Option<KeepaliveFrame>) -> Self {
+        Self {
+            inner,
+            idle,
+            keepalive: frame.map(|frame| (frame, Box::pin(sleep(idle)))),
+            tail: Vec::new(),
+        }
+    }
+}
+
+impl<B> fmt::Debug for KeepaliveBody<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeepaliveBody")
+            .field("inner", &self.inner)
+            .field("idle", &self.idle)
+            .field("frame", &self.keepalive.as_ref().map(|(frame, _)| frame))
+            .finish()
+    }
+}
+
+impl<B> Body for KeepaliveBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = match this.inner.poll_frame(cx) {
+            Poll::Ready(frame) => frame,
+            Poll::Pending => {
+                if let Some((frame, sleep)) = this.keepalive.as_mut() {
+                    if sleep.as_mut().poll(cx).is_ready() {
+                        sleep.as_mut().reset(Instant::now() + *this.idle);
+                        if this.tail.is_empty() || this.tail.ends_with(&frame.boundary) {
+                            return Poll::Ready(Some(Ok(Frame::data(frame.payload.clone()))));
+                        }
+                        // register the waker for the next keepalive
+                        let _ = sleep.as_mut().poll(cx);
+                    }
+                }
+                return Poll::Pending;
+            }
+        };
+
+        let frame = match frame {
+            Some(Ok(frame)) => frame.map_data(|mut data| data.copy_to_bytes(data.remaining())),
+            other => {
+                *this.keepalive = None;
+                return Poll::Ready(other.map(|res| {
+                    res.map(|frame| frame.map_data(|mut data| data.copy_to_bytes(data.remaining())))
+                }));
+            }
+        };
+
+        if let Some((keepalive, sleep)) = this.keepalive.as_mut() {
+            sleep.as_mut().reset(Instant::now() + *this.idle);
+            if let Some(data) = frame.data_ref().filter(|data| !data.is_empty()) {
+                // only the end of the data is needed to detect boundaries
+                this.tail.extend_from_slice(data);
+                let excess = this
+                    .tail
+                    .len()
+                    .saturating_sub(keepalive.boundary.len().max(1));
+                this.tail.drain(..excess);
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.keepalive.is_none() {
+            return self.inner.size_hint();
+        }
+        // keepalive frames come on top of the data of the inner body
+        let mut hint = SizeHint::new();
+        hint.set_lower(self.inner.size_hint().lower());
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    fn events(chunks: &'static [(u64, &'static str)]) -> Body {
+        Body::from_stream(futures_util::stream::unfold(
+            chunks.iter(),
+            |mut chunks| async move {
+                let (delay, chunk) = chunks.next()?;
+                tokio::time::sleep(Duration::from_secs(*delay)).await;
+                Some((
+                    Ok::<_, Infallible>(Bytes::from_static(chunk.as_bytes())),
+                    chunks,
+                ))
+            },
+        ))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_keepalive_frames_in_between_events() {
+        let svc = ServiceBuilder::new()
+            .layer(KeepaliveLayer::new(Duration::from_secs(10)))
+            .service_fn(|_: Request<Body>| async {
+                let body = events(&[(25, "data: 1\n\n"), (25, "data: "), (25, "2\n\n")]);
+                let res = Response::builder()
+                    .header(header::CONTENT_TYPE, "text/event-stream; charset=utf-8")
+                    .body(body)
+                    .unwrap();
+                Ok::<_, Infallible>(res)
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, ":\n\n:\n\ndata: 1\n\n:\n\n:\n\ndata: 2\n\n");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_other_responses() {
+        let svc = ServiceBuilder::new()
+            .layer(KeepaliveLayer::new(Duration::from_secs(10)))
+            .service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(events(&[(25, "hello")])))
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+    }
+}
@@ -169,6 +169,9 @@
 //! tower-async-http = { version = "0.2", features = ["full"] }
 //! ```
 //!
+//! Middleware relying on unstable Rust features, such as the [`RangeFetch`] middleware, also
+//! enables the `nightly` feature and thus requires a nightly toolchain. It isn't part of `"full"`.
+//!
 //! [tower-async]: https://crates.io/crates/tower-async
 //! [http]: https://crates.io/crates/http
 //! [http-body]: https://crates.io/crates/http-body
@@ -179,6 +182,7 @@
 //! [`AddExtension`]: crate::add_extension::AddExtension
 //! [`Service`]: https://docs.rs/tower-async/latest/tower-async/trait.Service.html
 //! [`Timeout`]: crate::timeout::Timeout
+//! [`RangeFetch`]: crate::range_fetch::RangeFetch
 
 #![warn(
     clippy::all,
@@ -217,6 +221,8 @@
 #![allow(elided_lifetimes_in_paths, clippy::type_complexity)]
 #![forbid(unsafe_code)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![cfg_attr(feature = "nightly", feature(return_type_notation))]
+#![cfg_attr(feature = "nightly", allow(incomplete_features))]
 #![cfg_attr(test, allow(clippy::float_cmp))]
 
 #[macro_use]
@@ -245,6 +251,9 @@ pub mod compression;
 #[cfg(feature = "add-extension")]
 pub mod add_extension;
 
+#[cfg(feature = "di")]
+pub mod di;
+
 #[cfg(feature = "sensitive-headers")]
 pub mod sensitive_headers;
 
@@ -267,7 +276,7 @@ pub mod decompression;
     feature = "decompression-zstd",
     feature = "fs" // Used for serving precompressed static files as well
 ))]
-mod content_encoding;
+pub mod content_encoding;
 
 #[cfg(any(
     feature = "compression-br",
@@ -291,7 +300,17 @@ mod compression_utils;
     feature = "decompression-gzip",
     feature = "decompression-zstd",
 ))]
-pub use compression_utils::CompressionLevel;
+pub use compression_utils::{CompressionLevel, CompressionOptions, DecompressionOptions};
+
+#[cfg(any(
+    feature = "compression-br",
+    feature = "compression-deflate",
+    feature = "compression-gzip",
+    feature = "compression-zstd",
+    feature = "metrics",
+    feature = "trace",
+))]
+pub mod body_size;
 
 #[cfg(feature = "map-response-body")]
 pub mod map_response_body;
@@ -323,6 +342,30 @@ pub mod set_status;
 #[cfg(feature = "timeout")]
 pub mod timeout;
 
+#[cfg(feature = "deadline")]
+pub mod deadline;
+
+#[cfg(feature = "slow-request")]
+pub mod slow_request;
+
+#[cfg(feature = "degradation")]
+pub mod degradation;
+
+#[cfg(feature = "backpressure")]
+pub mod backpressure;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "accounting")]
+pub mod accounting;
+
+#[cfg(feature = "ndjson")]
+pub mod ndjson;
+
+#[cfg(feature = "sign-request")]
+pub mod sign_request;
+
 #[cfg(feature = "normalize-path")]
 pub mod normalize_path;
 
@@ -339,6 +382,72 @@ pub use self::builder::ServiceBuilderExt;
 #[cfg(feature = "validate-request")]
 pub mod validate_request;
 
+#[cfg(feature = "typed-header")]
+pub mod typed_header;
+
+#[cfg(feature = "verify-signature")]
+pub mod verify_signature;
+
+#[cfg(feature = "content-digest")]
+pub mod content_digest;
+
+#[cfg(feature = "conditional-get")]
+pub mod conditional_get;
+
+#[cfg(feature = "early-hints")]
+pub mod early_hints;
+
+#[cfg(feature = "expect-continue")]
+pub mod expect_continue;
+
+#[cfg(feature = "keepalive")]
+pub mod keepalive;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "cookie-store")]
+pub mod cookie_store;
+
+#[cfg(feature = "server-timing")]
+pub mod server_timing;
+
+#[cfg(feature = "range-fetch")]
+pub mod range_fetch;
+
+#[cfg(feature = "redact")]
+pub mod redact;
+
+#[cfg(feature = "tenant-config")]
+pub mod tenant_config;
+
+#[cfg(feature = "canonical-headers")]
+pub mod canonical_headers;
+
+#[cfg(feature = "tee-body")]
+pub mod tee_body;
+
+#[cfg(feature = "download-progress")]
+pub mod download_progress;
+
+#[cfg(feature = "upload-progress")]
+pub mod upload_progress;
+
+#[cfg(feature = "auto-head")]
+pub mod auto_head;
+
+#[cfg(feature = "method-not-allowed")]
+pub mod method_not_allowed;
+
+#[cfg(feature = "method-policy")]
+pub mod method_policy;
+
+#[cfg(feature = "tls-policy")]
+pub mod tls_policy;
+
+#[cfg(feature = "cookies")]
+pub mod cookies;
+
 /// The latency unit used to report latencies by middleware.
 #[non_exhaustive]
 #[derive(Copy, Clone, Debug)]
@@ -360,3 +469,21 @@ mod sealed {
     #[allow(unreachable_pub)]
     pub trait Sealed<T> {}
 }
+
+#[cfg(feature = "normalize-query")]
+pub mod normalize_query;
+
+#[cfg(feature = "route-metadata")]
+pub mod route_metadata;
+
+#[cfg(feature = "json-body")]
+pub mod json_body;
+
+#[cfg(feature = "ws")]
+pub mod ws;
+
+#[cfg(feature = "alt-svc")]
+pub mod alt_svc;
+
+#[cfg(feature = "ip-filter")]
+pub mod ip_filter;
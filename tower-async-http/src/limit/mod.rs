@@ -1,4 +1,6 @@
-//! Middleware for limiting request bodies.
+//! Middleware for limiting request and response bodies.
+//!
+//! See the [`rate`] module for limiting the rate of requests instead.
 //!
 //! This layer will also intercept requests with a `Content-Length` header
 //! larger than the allowable limit and return an immediate error response
@@ -126,13 +128,55 @@
 //! [`http_body_util::Limited`] and checking for [`http_body_util::LengthLimitError`]
 //! like in the previous example.
 //!
+//! ## Limiting response bodies
+//!
+//! [`ResponseBodyLimitLayer`] limits the length of the response bodies of the inner service,
+//! such as responses proxied from untrusted upstreams. Bodies exceeding the limit fail with a
+//! [`http_body_util::LengthLimitError`], or are cut off at the limit when configured with
+//! [`ResponseBodyLimitLayer::truncate`], in which case a `Content-Length` header exceeding
+//! the limit is removed. Apply it outside of [`Decompression`] to limit the length of the
+//! decompressed bodies.
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use std::convert::Infallible;
+//! use http::{Request, Response};
+//! use http_body_util::{BodyExt, Full};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::limit::ResponseBodyLimitLayer;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! async fn upstream(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::from(vec![0u8; 8192])))
+//! }
+//!
+//! let svc = ServiceBuilder::new()
+//!     // Truncate response bodies to 4096 bytes.
+//!     .layer(ResponseBodyLimitLayer::new(4096).truncate())
+//!     .service_fn(upstream);
+//!
+//! let response = svc.call(Request::new(Full::default())).await?;
+//! let body = response.into_body().collect().await?.to_bytes();
+//!
+//! assert_eq!(body.len(), 4096);
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! [`MapRequestBody`]: crate::map_request_body
+//! [`Decompression`]: crate::decompression
 //! [hyper]: https://crates.io/crates/hyper
 
+pub mod rate;
+
 mod body;
 mod layer;
+mod response;
 mod service;
 
 pub use body::ResponseBody;
 pub use layer::RequestBodyLimitLayer;
+pub use response::{LimitedResponseBody, ResponseBodyLimit, ResponseBodyLimitLayer};
 pub use service::RequestBodyLimit;
@@ -0,0 +1,543 @@
+//! Middleware limiting the rate of requests, responding with `429 Too Many Requests`.
+//!
+//! Unlike the [`RateLimit`](tower_async::limit::RateLimit) middleware of `tower-async`, which
+//! makes requests exceeding the rate wait, the [`RateLimit`] middleware answers them right
+//! away with a `429 Too Many Requests` response, carrying a `Retry-After` header with the
+//! number of seconds after which the client is allowed to send a request again.
+//!
+//! Every request is assigned a key by a [`KeyExtractor`], such as the IP address of the
+//! client ([`PeerIp`]), the value of a header holding an API key ([`HeaderKey`]) or a
+//! closure, and every key is allowed `num` requests per period. Requests without a key are
+//! not limited. By default all requests share the same key, limiting the overall rate.
+//!
+//! The requests of a key are counted using one of two [`Algorithm`]s:
+//!
+//! - a [fixed window](Algorithm::FixedWindow), which starts with the first request of a key and
+//!   allows `num` requests until it ends, after which the next request starts a new window.
+//!   This only keeps a counter per key, but allows bursts of up to `2 * num` requests around
+//!   the end of a window.
+//! - a [sliding window](Algorithm::SlidingWindow), which allows a request if fewer than `num`
+//!   requests were allowed during the period before it. This enforces the rate exactly,
+//!   but keeps the time of up to `num` requests per key.
+//!
+//! Keys which didn't send requests for a period are forgotten.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, HeaderName, Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::limit::rate::{Algorithm, HeaderKey, RateLimitLayer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! let svc = ServiceBuilder::new()
+//!     // Allow every API key two requests per minute.
+//!     .layer(
+//!         RateLimitLayer::new(2, Duration::from_secs(60))
+//!             .algorithm(Algorithm::SlidingWindow)
+//!             .key(HeaderKey::new(HeaderName::from_static("x-api-key"))),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let request = || Request::builder().header("x-api-key", "secret").body(Full::default());
+//!
+//! assert_eq!(svc.call(request()?).await?.status(), StatusCode::OK);
+//! assert_eq!(svc.call(request()?).await?.status(), StatusCode::OK);
+//!
+//! let res = svc.call(request()?).await?;
+//! assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+//! assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderName, HeaderValue, Request, Response, StatusCode};
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hash},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Assigns requests the key whose rate is limited by [`RateLimit`].
+///
+/// Implemented for `()`, which assigns all requests the same key, and for closures of the
+/// form `Fn(&Request<B>) -> Option<K>`. Requests for which no key is returned are not limited.
+pub trait KeyExtractor<B> {
+    /// The key of a request.
+    type Key: Hash;
+
+    /// Returns the key of the request, or `None` if its rate should not be limited.
+    fn extract(&self, req: &Request<B>) -> Option<Self::Key>;
+}
+
+impl<B> KeyExtractor<B> for () {
+    type Key = ();
+
+    fn extract(&self, _: &Request<B>) -> Option<Self::Key> {
+        Some(())
+    }
+}
+
+impl<F, K, B> KeyExtractor<B> for F
+where
+    F: Fn(&Request<B>) -> Option<K>,
+    K: Hash,
+{
+    type Key = K;
+
+    fn extract(&self, req: &Request<B>) -> Option<Self::Key> {
+        self(req)
+    }
+}
+
+/// A [`KeyExtractor`] using the value of a header as the key, such as an API key.
+///
+/// Requests without the header are not limited, so they should be rejected by
+/// another middleware, such as the authorization of the service.
+#[derive(Debug, Clone)]
+pub struct HeaderKey {
+    name: HeaderName,
+}
+
+impl HeaderKey {
+    /// Create a new [`HeaderKey`], using the value of the header with the given name.
+    pub fn new(name: HeaderName) -> Self {
+        Self { name }
+    }
+}
+
+impl<B> KeyExtractor<B> for HeaderKey {
+    type Key = HeaderValue;
+
+    fn extract(&self, req: &Request<B>) -> Option<Self::Key> {
+        req.headers().get(&self.name).cloned()
+    }
+}
+
+/// A [`KeyExtractor`] using the IP address of the client as the key, read from the
+/// [`ConnectInfo`] request extension.
+///
+/// Requests without the extension are not limited.
+///
+/// [`ConnectInfo`]: crate::ip_filter::ConnectInfo
+#[cfg(feature = "ip-filter")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeerIp;
+
+#[cfg(feature = "ip-filter")]
+impl<B> KeyExtractor<B> for PeerIp {
+    type Key = std::net::IpAddr;
+
+    fn extract(&self, req: &Request<B>) -> Option<Self::Key> {
+        req.extensions()
+            .get::<crate::ip_filter::ConnectInfo>()
+            .map(|info| info.0.ip())
+    }
+}
+
+/// The algorithm used by [`RateLimit`] to count the requests of a key.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Count the requests of fixed windows, starting with the first request of a key.
+    #[default]
+    FixedWindow,
+    /// Count the requests of the period before every request.
+    SlidingWindow,
+}
+
+/// The requests counted for a key.
+enum Window {
+    Fixed { start: Instant, count: u64 },
+    Sliding { allowed: VecDeque<Instant> },
+}
+
+impl Window {
+    /// Allows a request, or returns the time after which a request would be allowed.
+    fn acquire(&mut self, num: u64, per: Duration, now: Instant) -> Result<(), Duration> {
+        match self {
+            Window::Fixed { start, count } => {
+                if now.duration_since(*start) >= per {
+                    *start = now;
+                    *count = 0;
+                }
+                if *count < num {
+                    *count += 1;
+                    Ok(())
+                } else {
+                    Err(*start + per - now)
+                }
+            }
+            Window::Sliding { allowed } => {
+                while allowed
+                    .front()
+                    .is_some_and(|allowed| now.duration_since(*allowed) >= per)
+                {
+                    allowed.pop_front();
+                }
+                if (allowed.len() as u64) < num {
+                    allowed.push_back(now);
+                    Ok(())
+                } else {
+                    Err(allowed[0] + per - now)
+                }
+            }
+        }
+    }
+
+    /// Returns whether the window still counts requests which limit the next request.
+    fn is_active(&self, per: Duration, now: Instant) -> bool {
+        match self {
+            Window::Fixed { start, .. } => now.duration_since(*start) < per,
+            Window::Sliding { allowed } => allowed
+                .back()
+                .is_some_and(|allowed| now.duration_since(*allowed) < per),
+        }
+    }
+}
+
+struct State {
+    hasher: RandomState,
+    windows: HashMap<u64, Window>,
+    last_sweep: Instant,
+}
+
+/// Layer that applies the [`RateLimit`] middleware.
+///
+/// All services produced by the layer share the same rate.
+///
+/// See the [module docs](self) for an example.
+pub struct RateLimitLayer<K = ()> {
+    num: u64,
+    per: Duration,
+    algorithm: Algorithm,
+    key: K,
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimitLayer {
+    /// Create a new [`RateLimitLayer`], allowing `num` requests per `per` period,
+    /// shared by all requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(num: u64, per: Duration) -> Self {
+        assert!(num > 0, "rate must allow at least one request");
+        assert!(
+            per > Duration::ZERO,
+            "rate period must be greater than zero"
+        );
+        Self {
+            num,
+            per,
+            algorithm: Algorithm::default(),
+            key: (),
+            state: Arc::new(Mutex::new(State {
+                hasher: RandomState::new(),
+                windows: HashMap::new(),
+                last_sweep: Instant::now(),
+            })),
+        }
+    }
+}
+
+impl<K> RateLimitLayer<K> {
+    /// Set the [`Algorithm`] used to count requests, defaults to [`Algorithm::FixedWindow`].
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Limit the rate of every key returned by the given [`KeyExtractor`] separately.
+    pub fn key<K2>(self, key: K2) -> RateLimitLayer<K2> {
+        RateLimitLayer {
+            num: self.num,
+            per: self.per,
+            algorithm: self.algorithm,
+            key,
+            state: self.state,
+        }
+    }
+}
+
+impl<K> Clone for RateLimitLayer<K>
+where
+    K: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            num: self.num,
+            per: self.per,
+            algorithm: self.algorithm,
+            key: self.key.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for RateLimitLayer<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitLayer")
+            .field("num", &self.num)
+            .field("per", &self.per)
+            .field("algorithm", &self.algorithm)
+            .field("key", &format_args!("{}", std::any::type_name::<K>()))
+            .finish()
+    }
+}
+
+impl<S, K> Layer<S> for RateLimitLayer<K>
+where
+    K: Clone,
+{
+    type Service = RateLimit<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Middleware that responds with `429 Too Many Requests` to the requests
+/// exceeding the rate of their key.
+///
+/// See the [module docs](self) for more details.
+pub struct RateLimit<S, K = ()> {
+    inner: S,
+    layer: RateLimitLayer<K>,
+}
+
+impl<S> RateLimit<S> {
+    /// Create a new [`RateLimit`], allowing `num` requests per `per` period,
+    /// shared by all requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(inner: S, num: u64, per: Duration) -> Self {
+        RateLimitLayer::new(num, per).layer(inner)
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `RateLimit` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(num: u64, per: Duration) -> RateLimitLayer {
+        RateLimitLayer::new(num, per)
+    }
+}
+
+impl<S, K> RateLimit<S, K> {
+    define_inner_service_accessors!();
+}
+
+impl<S, K> Clone for RateLimit<S, K>
+where
+    S: Clone,
+    K: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, K> fmt::Debug for RateLimit<S, K>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, K> RateLimit<S, K> {
+    /// Allows a request of the key, or returns the time after which a request would be allowed.
+    fn acquire(&self, key: impl Hash) -> Result<(), Duration> {
+        let RateLimitLayer {
+            num,
+            per,
+            algorithm,
+            ..
+        } = self.layer;
+        let now = Instant::now();
+        let mut state = self.layer.state.lock().unwrap();
+        if now.duration_since(state.last_sweep) >= per {
+            state.last_sweep = now;
+            state.windows.retain(|_, window| window.is_active(per, now));
+        }
+
+        let hash = state.hasher.hash_one(key);
+        state
+            .windows
+            .entry(hash)
+            .or_insert_with(|| match algorithm {
+                Algorithm::FixedWindow => Window::Fixed {
+                    start: now,
+                    count: 0,
+                },
+                Algorithm::SlidingWindow => Window::Sliding {
+                    allowed: VecDeque::new(),
+                },
+            })
+            .acquire(num, per, now)
+    }
+}
+
+impl<S, K, ReqBody, ResBody> Service<Request<ReqBody>> for RateLimit<S, K>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    K: KeyExtractor<ReqBody>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if let Some(key) = self.layer.key.extract(&req) {
+            if let Err(retry_after) = self.acquire(key) {
+                // round up, such that a request sent after it is allowed
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                res.headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+                return Ok(res);
+            }
+        }
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::service_fn;
+
+    async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    async fn status<S>(svc: &S, user: &str) -> (StatusCode, Option<HeaderValue>)
+    where
+        S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+    {
+        let req = Request::builder()
+            .header("x-user", user)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        (
+            res.status(),
+            res.headers().get(header::RETRY_AFTER).cloned(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fixed_window() {
+        let svc = RateLimitLayer::new(2, Duration::from_secs(10)).layer(service_fn(handle));
+
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        tokio::time::sleep(Duration::from_millis(5500)).await;
+        assert_eq!(status(&svc, "b").await.0, StatusCode::OK);
+        assert_eq!(
+            status(&svc, "a").await,
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                Some(HeaderValue::from_static("5"))
+            )
+        );
+
+        // a new window starts once the first one ended
+        tokio::time::sleep(Duration::from_millis(4500)).await;
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        assert_eq!(status(&svc, "a").await.0, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sliding_window() {
+        let svc = RateLimitLayer::new(2, Duration::from_secs(10))
+            .algorithm(Algorithm::SlidingWindow)
+            .layer(service_fn(handle));
+
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+
+        // the first request leaves the window after 10 seconds, the second after 16 seconds
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        assert_eq!(
+            status(&svc, "a").await,
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                Some(HeaderValue::from_static("6"))
+            )
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keys_are_limited_separately() {
+        let svc = RateLimitLayer::new(1, Duration::from_secs(10))
+            .key(HeaderKey::new(HeaderName::from_static("x-user")))
+            .layer(service_fn(handle));
+
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        assert_eq!(status(&svc, "b").await.0, StatusCode::OK);
+        assert_eq!(status(&svc, "a").await.0, StatusCode::TOO_MANY_REQUESTS);
+
+        // requests without a key are not limited
+        let svc = RateLimitLayer::new(1, Duration::from_secs(10))
+            .key(|_: &Request<Body>| None::<()>)
+            .layer(service_fn(handle));
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+        assert_eq!(status(&svc, "a").await.0, StatusCode::OK);
+    }
+
+    #[cfg(feature = "ip-filter")]
+    #[tokio::test(start_paused = true)]
+    async fn peer_ip_key() {
+        use crate::ip_filter::ConnectInfo;
+
+        let svc = RateLimitLayer::new(1, Duration::from_secs(10))
+            .key(PeerIp)
+            .layer(service_fn(handle));
+        let request = |port| {
+            Request::builder()
+                .extension(ConnectInfo(([203, 0, 113, 1], port).into()))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(svc.call(request(1)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(
+            svc.call(request(2)).await.unwrap().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
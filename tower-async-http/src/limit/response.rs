@@ -0,0 +1,251 @@
+use bytes::Bytes;
+use http::{header, Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::Limited;
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+use crate::BoxError;
+
+/// Layer that applies the [`ResponseBodyLimit`] middleware that limits the length
+/// of the response bodies of the inner service.
+///
+/// See the [module docs](crate::limit) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseBodyLimitLayer {
+    limit: usize,
+    truncate: bool,
+}
+
+impl ResponseBodyLimitLayer {
+    /// Create a new `ResponseBodyLimitLayer` with the given body length limit.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            truncate: false,
+        }
+    }
+
+    /// Truncate response bodies at the limit, instead of failing them
+    /// with a [`LengthLimitError`].
+    ///
+    /// [`LengthLimitError`]: http_body_util::LengthLimitError
+    pub fn truncate(mut self) -> Self {
+        self.truncate = true;
+        self
+    }
+}
+
+impl<S> Layer<S> for ResponseBodyLimitLayer {
+    type Service = ResponseBodyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseBodyLimit {
+            inner,
+            limit: self.limit,
+            truncate: self.truncate,
+        }
+    }
+}
+
+/// Middleware that limits the length of the response bodies of the inner service,
+/// failing them with a [`LengthLimitError`] or truncating them once the limit is exceeded.
+///
+/// See the [module docs](crate::limit) for an example.
+///
+/// [`LengthLimitError`]: http_body_util::LengthLimitError
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseBodyLimit<S> {
+    inner: S,
+    limit: usize,
+    truncate: bool,
+}
+
+impl<S> ResponseBodyLimit<S> {
+    /// Create a new `ResponseBodyLimit` with the given body length limit.
+    pub fn new(inner: S, limit: usize) -> Self {
+        ResponseBodyLimitLayer::new(limit).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ResponseBodyLimit` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(limit: usize) -> ResponseBodyLimitLayer {
+        ResponseBodyLimitLayer::new(limit)
+    }
+}
+
+impl<ReqBody, ResBody, S> Service<Request<ReqBody>> for ResponseBodyLimit<S>
+where
+    ResBody: Body<Data = Bytes>,
+    ResBody::Error: Into<BoxError>,
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<LimitedResponseBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let mut res = self.inner.call(req).await?;
+        if !self.truncate {
+            return Ok(res.map(|body| LimitedResponseBody {
+                inner: LimitedInner::Limited {
+                    body: Limited::new(body, self.limit),
+                },
+            }));
+        }
+
+        let content_length = res
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+        if matches!(content_length, Some(len) if len > self.limit) {
+            res.headers_mut().remove(header::CONTENT_LENGTH);
+        }
+        Ok(res.map(|body| LimitedResponseBody {
+            inner: LimitedInner::Truncated {
+                body,
+                remaining: self.limit,
+            },
+        }))
+    }
+}
+
+pin_project! {
+    /// Response body for [`ResponseBodyLimit`].
+    pub struct LimitedResponseBody<B> {
+        #[pin]
+        inner: LimitedInner<B>,
+    }
+}
+
+pin_project! {
+    #[project = LimitedProj]
+    enum LimitedInner<B> {
+        Limited {
+            #[pin]
+            body: Limited<B>,
+        },
+        Truncated {
+            #[pin]
+            body: B,
+            remaining: usize,
+        },
+    }
+}
+
+impl<B> Body for LimitedResponseBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.project().inner.project() {
+            LimitedProj::Limited { body } => body.poll_frame(cx),
+            LimitedProj::Truncated { body, remaining } => {
+                if *remaining == 0 {
+                    return Poll::Ready(None);
+                }
+                let frame = match ready!(body.poll_frame(cx)) {
+                    Some(Ok(frame)) => frame,
+                    Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                    None => return Poll::Ready(None),
+                };
+                match frame.into_data() {
+                    Ok(mut data) => {
+                        if data.len() > *remaining {
+                            data.truncate(*remaining);
+                            // trailers of truncated bodies are dropped
+                            *remaining = 0;
+                        } else {
+                            *remaining -= data.len();
+                        }
+                        Poll::Ready(Some(Ok(Frame::data(data))))
+                    }
+                    Err(frame) => Poll::Ready(Some(Ok(frame))),
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            LimitedInner::Limited { body } => body.is_end_stream(),
+            LimitedInner::Truncated { body, remaining } => *remaining == 0 || body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            LimitedInner::Limited { body } => body.size_hint(),
+            LimitedInner::Truncated { body, remaining } => {
+                let hint = body.size_hint();
+                let remaining = *remaining as u64;
+                let mut truncated = SizeHint::new();
+                truncated.set_lower(hint.lower().min(remaining));
+                truncated.set_upper(hint.upper().unwrap_or(remaining).min(remaining));
+                truncated
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http_body_util::{BodyExt, LengthLimitError};
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let stream = futures_util::stream::iter(
+            ["hello ", "world"].map(|chunk| Ok::<_, Infallible>(Bytes::from(chunk))),
+        );
+        let res = Response::builder()
+            .header(header::CONTENT_LENGTH, "11")
+            .body(Body::from_stream(stream))
+            .unwrap();
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn fails_bodies_exceeding_the_limit() {
+        let svc = ResponseBodyLimit::new(service_fn(handle), 8);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "11");
+        let err = res.into_body().collect().await.unwrap_err();
+        assert!(err.is::<LengthLimitError>());
+
+        let svc = ResponseBodyLimit::new(service_fn(handle), 11);
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn truncates_bodies_exceeding_the_limit() {
+        let svc = ServiceBuilder::new()
+            .layer(ResponseBodyLimitLayer::new(8).truncate())
+            .service_fn(handle);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello wo");
+    }
+}
@@ -0,0 +1,294 @@
+//! Middleware that answers `OPTIONS` requests and requests with an unsupported method,
+//! using the methods allowed for the path, as reported by the router.
+//!
+//! A server must answer a request whose method isn't supported by the resource with
+//! `405 Method Not Allowed` and an `Allow` header listing the methods which are, and should
+//! answer `OPTIONS` requests with the same `Allow` header. Hand-rolled routers often answer both
+//! with `404 Not Found`, as they only match the method and the path at once.
+//!
+//! The [`MethodNotAllowed`] middleware wraps a router, which only has to insert the
+//! [`AllowedMethods`] of the path it matched into the extensions of its response, including
+//! the fallback response it returns when none of the routes of the path handles the method.
+//! The middleware then:
+//!
+//! - answers `OPTIONS` requests which the router didn't handle, with a `404 Not Found` or
+//!   `405 Method Not Allowed` response, with `204 No Content`;
+//! - replaces `404 Not Found` responses to requests with a method which isn't allowed with
+//!   `405 Method Not Allowed`;
+//! - adds the `Allow` header to those responses, as well as to other `OPTIONS` and
+//!   `405 Method Not Allowed` responses which lack it.
+//!
+//! `OPTIONS` is always listed in the `Allow` header, as the middleware answers it.
+//! Responses without [`AllowedMethods`], because the router didn't match the path, are
+//! passed through unchanged.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Method, Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::method_not_allowed::{AllowedMethods, MethodNotAllowedLayer};
+//!
+//! async fn router(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let mut res = Response::new(Full::default());
+//!     match (req.uri().path(), req.method()) {
+//!         ("/users", &Method::GET | &Method::POST) => {}
+//!         ("/users", _) => *res.status_mut() = StatusCode::NOT_FOUND,
+//!         _ => {
+//!             *res.status_mut() = StatusCode::NOT_FOUND;
+//!             return Ok(res);
+//!         }
+//!     }
+//!     res.extensions_mut()
+//!         .insert(AllowedMethods::new([Method::GET, Method::POST]));
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(MethodNotAllowedLayer::new())
+//!     .service_fn(router);
+//!
+//! let res = svc.call(Request::delete("/users").body(Full::default())?).await?;
+//! assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+//! assert_eq!(res.headers()[header::ALLOW], "GET, POST, OPTIONS");
+//!
+//! let req = Request::builder()
+//!     .method(Method::OPTIONS)
+//!     .uri("/users")
+//!     .body(Full::default())?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::NO_CONTENT);
+//! assert_eq!(res.headers()[header::ALLOW], "GET, POST, OPTIONS");
+//!
+//! let res = svc.call(Request::get("/posts").body(Full::default())?).await?;
+//! assert_eq!(res.status(), StatusCode::NOT_FOUND);
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The methods allowed for the path matched by a router.
+///
+/// Inserted into the extensions of the response by the router, see the
+/// [module docs](self) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedMethods {
+    methods: Arc<[Method]>,
+}
+
+impl AllowedMethods {
+    /// Create a new [`AllowedMethods`] from the methods handled by the routes of a path.
+    ///
+    /// Duplicate methods are ignored.
+    pub fn new(methods: impl IntoIterator<Item = Method>) -> Self {
+        let mut unique = Vec::new();
+        for method in methods {
+            if !unique.contains(&method) {
+                unique.push(method);
+            }
+        }
+        Self {
+            methods: unique.into(),
+        }
+    }
+
+    /// Returns whether the method is allowed.
+    ///
+    /// `OPTIONS` is always allowed.
+    pub fn contains(&self, method: &Method) -> bool {
+        method == Method::OPTIONS || self.methods.contains(method)
+    }
+
+    /// Returns the methods handled by the routes, in the order they were given.
+    pub fn methods(&self) -> &[Method] {
+        &self.methods
+    }
+
+    /// Returns the value of the `Allow` header, listing the methods and `OPTIONS`.
+    fn header_value(&self) -> HeaderValue {
+        let mut value = String::new();
+        for method in self.methods.iter().filter(|m| **m != Method::OPTIONS) {
+            value.push_str(method.as_str());
+            value.push_str(", ");
+        }
+        value.push_str(Method::OPTIONS.as_str());
+        // methods only contain token characters
+        HeaderValue::from_str(&value).unwrap()
+    }
+}
+
+impl FromIterator<Method> for AllowedMethods {
+    fn from_iter<I: IntoIterator<Item = Method>>(iter: I) -> Self {
+        Self::new(iter)
+    }
+}
+
+/// Layer that applies the [`MethodNotAllowed`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MethodNotAllowedLayer {
+    _priv: (),
+}
+
+impl MethodNotAllowedLayer {
+    /// Create a new [`MethodNotAllowedLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for MethodNotAllowedLayer {
+    type Service = MethodNotAllowed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MethodNotAllowed::new(inner)
+    }
+}
+
+/// Middleware that answers `OPTIONS` requests and requests with an unsupported method,
+/// using the [`AllowedMethods`] reported by the router.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct MethodNotAllowed<S> {
+    inner: S,
+}
+
+impl<S> MethodNotAllowed<S> {
+    /// Create a new [`MethodNotAllowed`].
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `MethodNotAllowed` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> MethodNotAllowedLayer {
+        MethodNotAllowedLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MethodNotAllowed<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let method = req.method().clone();
+        let res = self.inner.call(req).await?;
+
+        let allow = match res.extensions().get::<AllowedMethods>() {
+            Some(allowed) => allowed.clone(),
+            None => return Ok(res),
+        };
+
+        let unhandled = matches!(
+            res.status(),
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+        );
+        let status = if method == Method::OPTIONS && unhandled {
+            StatusCode::NO_CONTENT
+        } else if !allow.contains(&method) && unhandled {
+            StatusCode::METHOD_NOT_ALLOWED
+        } else {
+            let mut res = res;
+            if method == Method::OPTIONS || res.status() == StatusCode::METHOD_NOT_ALLOWED {
+                res.headers_mut()
+                    .entry(header::ALLOW)
+                    .or_insert_with(|| allow.header_value());
+            }
+            return Ok(res);
+        };
+
+        let mut synthesized = Response::new(ResBody::default());
+        *synthesized.status_mut() = status;
+        *synthesized.version_mut() = res.version();
+        synthesized
+            .headers_mut()
+            .insert(header::ALLOW, allow.header_value());
+        synthesized.extensions_mut().insert(allow);
+        Ok(synthesized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    async fn router(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let mut res = Response::new(Body::empty());
+        match (req.uri().path(), req.method()) {
+            ("/items", &Method::GET) | ("/custom", &Method::OPTIONS) => {}
+            ("/items" | "/custom", _) => *res.status_mut() = StatusCode::NOT_FOUND,
+            _ => {
+                *res.status_mut() = StatusCode::NOT_FOUND;
+                return Ok(res);
+            }
+        }
+        res.extensions_mut().insert(AllowedMethods::new([
+            Method::GET,
+            Method::HEAD,
+            Method::GET,
+        ]));
+        Ok(res)
+    }
+
+    async fn call(method: Method, path: &str) -> Response<Body> {
+        let svc = ServiceBuilder::new()
+            .layer(MethodNotAllowedLayer::new())
+            .service_fn(router);
+        let req = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        svc.call(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn synthesizes_options_and_method_not_allowed() {
+        let res = call(Method::OPTIONS, "/items").await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+
+        let res = call(Method::PUT, "/items").await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+    }
+
+    #[tokio::test]
+    async fn passes_through_handled_requests() {
+        let res = call(Method::GET, "/items").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::ALLOW));
+
+        // a route handling OPTIONS itself gets the allow header added
+        let res = call(Method::OPTIONS, "/custom").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+
+        // paths not matched by the router are left alone
+        let res = call(Method::PUT, "/unknown").await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(!res.headers().contains_key(header::ALLOW));
+    }
+}
@@ -0,0 +1,242 @@
+//! Middleware that rejects requests based on their method.
+//!
+//! The [`MethodPolicy`] middleware rejects:
+//!
+//! - requests with a blocked method with `501 Not Implemented`. `TRACE` and `TRACK`, which
+//!   echo the request back and can leak credentials through cross-site tracing, are blocked by
+//!   default, and [more methods](MethodPolicyLayer::block), including custom ones, can be
+//!   blocked as well. Blocked methods aren't supported for any resource, rather than
+//!   `405 Method Not Allowed` which would require an `Allow` header listing the methods
+//!   supported by the requested resource;
+//! - requests with a method which isn't safe, such as `POST` or `DELETE`, with
+//!   `503 Service Unavailable` while the service is in [read-only mode](MethodPolicyLayer::read_only).
+//!   Read-only mode is switched at runtime through a [`Dynamic`], for example while the
+//!   service is under maintenance, such that clients can still read but no longer write.
+//!
+//! The inner service isn't called for rejected requests.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Method, Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{dynamic::Dynamic, Service, ServiceBuilder};
+//! use tower_async_http::method_policy::MethodPolicyLayer;
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let read_only = Dynamic::new(false);
+//!
+//! let svc = ServiceBuilder::new()
+//!     .layer(MethodPolicyLayer::new().read_only(read_only.clone()))
+//!     .service_fn(handle);
+//!
+//! let req = Request::builder().method(Method::TRACE).body(Full::default())?;
+//! assert_eq!(svc.call(req).await?.status(), StatusCode::NOT_IMPLEMENTED);
+//!
+//! // enter maintenance
+//! read_only.set(true);
+//!
+//! let req = Request::post("/").body(Full::default())?;
+//! assert_eq!(svc.call(req).await?.status(), StatusCode::SERVICE_UNAVAILABLE);
+//! let req = Request::get("/").body(Full::default())?;
+//! assert_eq!(svc.call(req).await?.status(), StatusCode::OK);
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_async::dynamic::Dynamic;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`MethodPolicy`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct MethodPolicyLayer {
+    blocked: Arc<[Method]>,
+    read_only: Dynamic<bool>,
+}
+
+impl MethodPolicyLayer {
+    /// Create a new [`MethodPolicyLayer`], blocking `TRACE` and `TRACK`.
+    pub fn new() -> Self {
+        Self {
+            blocked: Arc::new([Method::TRACE, Method::from_bytes(b"TRACK").unwrap()]),
+            read_only: Dynamic::new(false),
+        }
+    }
+
+    /// Block requests with the given method as well.
+    pub fn block(mut self, method: Method) -> Self {
+        if !self.blocked.contains(&method) {
+            let mut blocked = self.blocked.to_vec();
+            blocked.push(method);
+            self.blocked = blocked.into();
+        }
+        self
+    }
+
+    /// Allow requests with the given method, such as `TRACE` which is blocked by default.
+    pub fn allow(mut self, method: &Method) -> Self {
+        self.blocked = self
+            .blocked
+            .iter()
+            .filter(|m| *m != method)
+            .cloned()
+            .collect();
+        self
+    }
+
+    /// Reject requests with a method which isn't safe while the given switch is on,
+    /// defaults to off.
+    ///
+    /// Only `GET`, `HEAD`, `OPTIONS` and `TRACE` are safe.
+    pub fn read_only(mut self, read_only: impl Into<Dynamic<bool>>) -> Self {
+        self.read_only = read_only.into();
+        self
+    }
+}
+
+impl Default for MethodPolicyLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for MethodPolicyLayer {
+    type Service = MethodPolicy<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MethodPolicy {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Middleware that rejects requests with a blocked method, or a method
+/// which isn't safe while in read-only mode.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct MethodPolicy<S> {
+    inner: S,
+    layer: MethodPolicyLayer,
+}
+
+impl<S> MethodPolicy<S> {
+    /// Create a new [`MethodPolicy`], blocking `TRACE` and `TRACK`.
+    pub fn new(inner: S) -> Self {
+        MethodPolicyLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `MethodPolicy` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> MethodPolicyLayer {
+        MethodPolicyLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MethodPolicy<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let method = req.method();
+        let status = if self.layer.blocked.contains(method) {
+            StatusCode::NOT_IMPLEMENTED
+        } else if !is_safe(method) && *self.layer.read_only.load() {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            return self.inner.call(req).await;
+        };
+
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = status;
+        Ok(res)
+    }
+}
+
+/// Returns whether the method is safe, see RFC 9110 section 9.2.1.
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::ServiceBuilder;
+
+    async fn status<S>(svc: &S, method: &[u8]) -> StatusCode
+    where
+        S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+    {
+        let req = Request::builder()
+            .method(Method::from_bytes(method).unwrap())
+            .body(Body::empty())
+            .unwrap();
+        svc.call(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn blocks_methods() {
+        let svc = ServiceBuilder::new()
+            .layer(
+                MethodPolicyLayer::new()
+                    .block(Method::from_bytes(b"DEBUG").unwrap())
+                    .allow(&Method::TRACE),
+            )
+            .service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        assert_eq!(status(&svc, b"TRACK").await, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(status(&svc, b"DEBUG").await, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(status(&svc, b"TRACE").await, StatusCode::OK);
+        assert_eq!(status(&svc, b"POST").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsafe_methods_while_read_only() {
+        let read_only = Dynamic::new(true);
+        let svc = ServiceBuilder::new()
+            .layer(MethodPolicyLayer::new().read_only(read_only.clone()))
+            .service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        assert_eq!(status(&svc, b"GET").await, StatusCode::OK);
+        assert_eq!(status(&svc, b"HEAD").await, StatusCode::OK);
+        assert_eq!(status(&svc, b"PUT").await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            status(&svc, b"PURGE").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(status(&svc, b"TRACE").await, StatusCode::NOT_IMPLEMENTED);
+
+        read_only.set(false);
+        assert_eq!(status(&svc, b"PUT").await, StatusCode::OK);
+    }
+}
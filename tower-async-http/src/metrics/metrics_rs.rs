@@ -0,0 +1,63 @@
+use super::{status_label, MetricsRecorder};
+use crate::body_size::BodySize;
+use http::{Method, StatusCode};
+use std::time::Duration;
+
+/// [`MetricsRecorder`] that records through the [`metrics`] crate.
+///
+/// Metrics are recorded to the globally installed [`metrics::Recorder`],
+/// so any exporter of the [`metrics`] ecosystem can be used to export them.
+///
+/// See the [module docs](super) for the metrics that are recorded.
+///
+/// [`metrics`]: https://docs.rs/metrics
+/// [`metrics::Recorder`]: https://docs.rs/metrics/latest/metrics/trait.Recorder.html
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRsRecorder {
+    _priv: (),
+}
+
+impl MetricsRsRecorder {
+    /// Create a new [`MetricsRsRecorder`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MetricsRecorder for MetricsRsRecorder {
+    fn on_request(&self, _: &Method) {
+        ::metrics::gauge!("http_server_requests_in_flight").increment(1.0);
+    }
+
+    fn on_response(&self, method: &Method, status: Option<StatusCode>, latency: Duration) {
+        ::metrics::gauge!("http_server_requests_in_flight").decrement(1.0);
+
+        let labels = [
+            ("method", method.to_string()),
+            ("status", status_label(status)),
+        ];
+        ::metrics::counter!("http_server_requests_total", &labels).increment(1);
+        ::metrics::histogram!("http_server_request_duration_seconds", &labels)
+            .record(latency.as_secs_f64());
+    }
+
+    fn on_response_body(&self, method: &Method, status: StatusCode, size: BodySize) {
+        let labels = [
+            ("method", method.to_string()),
+            ("status", status_label(Some(status))),
+        ];
+        ::metrics::counter!("http_server_response_body_bytes_total", &labels)
+            .increment(size.bytes());
+        if let Some(ratio) = size.compression_ratio() {
+            ::metrics::histogram!("http_server_response_compression_ratio", &labels).record(ratio);
+        }
+    }
+
+    fn on_abort(&self, method: &Method, status: Option<StatusCode>) {
+        let labels = [
+            ("method", method.to_string()),
+            ("status", status_label(status)),
+        ];
+        ::metrics::counter!("http_server_requests_aborted_total", &labels).increment(1);
+    }
+}
@@ -0,0 +1,573 @@
+//! Middleware that records metrics about requests.
+//!
+//! The [`Metrics`] middleware reports every request to a [`MetricsRecorder`]: once when
+//! the request is received and once when the response head is produced, the inner service
+//! failed or the request was aborted. Recorders for common metrics ecosystems are available
+//! behind feature flags:
+//!
+//! - `metrics-rs`: [`MetricsRsRecorder`] records through the globally installed
+//!   recorder of the [`metrics`] crate;
+//! - `metrics-prometheus`: [`PrometheusRecorder`] registers its metrics in a
+//!   [`prometheus_client`] registry, which can be served in the OpenMetrics text format
+//!   by [`MetricsEndpoint`].
+//!
+//! The following metrics are recorded by these recorders:
+//!
+//! - `http_server_requests_total`: counter of handled requests,
+//!   labeled by `method` and `status`;
+//! - `http_server_request_duration_seconds`: histogram of the time it took to produce
+//!   the response head, labeled by `method` and `status`;
+//! - `http_server_requests_in_flight`: gauge of requests currently in flight;
+//! - `http_server_requests_aborted_total`: counter of requests aborted before their response
+//!   was sent, as the response future or body was dropped, labeled by `method` and `status`;
+//! - `http_server_response_body_bytes_total`: counter of the bytes of response bodies
+//!   sent, labeled by `method` and `status`;
+//! - `http_server_response_compression_ratio`: histogram of the ratio of the size before
+//!   compression to the size sent of compressed response bodies, labeled by `method` and
+//!   `status`.
+//!
+//! `status` is the status code of the response or `error` if no response was produced.
+//!
+//! The size of response bodies is counted as they are sent, such that streaming responses
+//! without a `Content-Length` header are accounted for as well. It is reported once the body
+//! has ended, see the [`body_size`](crate::body_size) module for more details. Compressed
+//! responses are only recognized if the [`Compression`] middleware is placed inside of
+//! [`Metrics`].
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(feature = "metrics-prometheus")]
+//! # {
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use prometheus_client::registry::Registry;
+//! use std::{convert::Infallible, sync::Arc};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::{
+//!     metrics::{MetricsLayer, PrometheusRecorder},
+//!     services::MetricsEndpoint,
+//! };
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut registry = Registry::default();
+//! let recorder = PrometheusRecorder::new(&mut registry);
+//!
+//! let svc = ServiceBuilder::new()
+//!     .layer(MetricsLayer::new(recorder))
+//!     .service_fn(handle);
+//!
+//! // Serve this on a path of your choosing, such as `/metrics`.
+//! let endpoint: MetricsEndpoint<Full<Bytes>> = MetricsEndpoint::new(Arc::new(registry));
+//!
+//! svc.call(Request::new(Full::default())).await?;
+//! let res = endpoint.call(Request::new(Full::<Bytes>::default())).await?;
+//! # Ok(())
+//! # }
+//! # }
+//! ```
+//!
+//! [`metrics`]: https://docs.rs/metrics
+//! [`prometheus_client`]: https://docs.rs/prometheus-client
+//! [`MetricsEndpoint`]: crate::services::MetricsEndpoint
+//! [`Compression`]: crate::compression::Compression
+
+use crate::body_size::{is_bodiless, BodySize, BodySizeCounter};
+use bytes::Buf;
+use futures_core::ready;
+use http::{Method, Request, Response, StatusCode};
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+    time::Instant,
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+#[cfg(feature = "metrics-rs")]
+mod metrics_rs;
+
+#[cfg(feature = "metrics-rs")]
+#[doc(inline)]
+pub use self::metrics_rs::MetricsRsRecorder;
+
+#[cfg(feature = "metrics-prometheus")]
+mod prometheus;
+
+#[cfg(feature = "metrics-prometheus")]
+#[doc(inline)]
+pub use self::prometheus::PrometheusRecorder;
+
+/// Trait used by [`Metrics`] to record metrics about requests.
+pub trait MetricsRecorder {
+    /// Called when a request is received.
+    fn on_request(&self, method: &Method);
+
+    /// Called when the request has been handled.
+    ///
+    /// `status` is `None` if the inner service failed or the request was aborted.
+    /// `latency` is the duration since the request was received.
+    fn on_response(&self, method: &Method, status: Option<StatusCode>, latency: Duration);
+
+    /// Called when the body of a response has ended.
+    ///
+    /// `size` is the [`BodySize`] of the response body that was sent.
+    ///
+    /// Defaults to doing nothing.
+    fn on_response_body(&self, method: &Method, status: StatusCode, size: BodySize) {
+        let _ = (method, status, size);
+    }
+
+    /// Called when the request was aborted before its response was sent, that is when
+    /// the response future or the response body was dropped before it ended, such as when
+    /// the client went away.
+    ///
+    /// `status` is `None` if the request was aborted before the response head was produced.
+    /// Bodies which are never sent, those of responses to `HEAD` requests and of `1xx`,
+    /// `204 No Content` and `304 Not Modified` responses, aren't reported.
+    ///
+    /// Defaults to doing nothing.
+    fn on_abort(&self, method: &Method, status: Option<StatusCode>) {
+        let _ = (method, status);
+    }
+}
+
+impl MetricsRecorder for () {
+    #[inline]
+    fn on_request(&self, _: &Method) {}
+
+    #[inline]
+    fn on_response(&self, _: &Method, _: Option<StatusCode>, _: Duration) {}
+}
+
+impl<T> MetricsRecorder for Arc<T>
+where
+    T: MetricsRecorder + ?Sized,
+{
+    fn on_request(&self, method: &Method) {
+        (**self).on_request(method)
+    }
+
+    fn on_response(&self, method: &Method, status: Option<StatusCode>, latency: Duration) {
+        (**self).on_response(method, status, latency)
+    }
+
+    fn on_response_body(&self, method: &Method, status: StatusCode, size: BodySize) {
+        (**self).on_response_body(method, status, size)
+    }
+
+    fn on_abort(&self, method: &Method, status: Option<StatusCode>) {
+        (**self).on_abort(method, status)
+    }
+}
+
+/// Layer that applies the [`Metrics`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct MetricsLayer<R> {
+    recorder: R,
+}
+
+impl<R> MetricsLayer<R> {
+    /// Creates a new [`MetricsLayer`] reporting to the given [`MetricsRecorder`].
+    pub fn new(recorder: R) -> Self {
+        Self { recorder }
+    }
+}
+
+impl<S, R> Layer<S> for MetricsLayer<R>
+where
+    R: Clone,
+{
+    type Service = Metrics<S, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Metrics {
+            inner,
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+/// Middleware that records metrics about requests.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Metrics<S, R> {
+    inner: S,
+    recorder: R,
+}
+
+impl<S, R> Metrics<S, R> {
+    /// Creates a new [`Metrics`] reporting to the given [`MetricsRecorder`].
+    pub fn new(inner: S, recorder: R) -> Self {
+        Self { inner, recorder }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Metrics` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(recorder: R) -> MetricsLayer<R> {
+        MetricsLayer::new(recorder)
+    }
+}
+
+impl<S, R, ReqBody, ResBody> Service<Request<ReqBody>> for Metrics<S, R>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    R: MetricsRecorder + Clone,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody, R>>;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        self.recorder.on_request(req.method());
+        let method = req.method().clone();
+        let mut guard = ResponseGuard {
+            recorder: &self.recorder,
+            method: method.clone(),
+            status: None,
+            start: Instant::now(),
+            aborted: true,
+        };
+
+        let result = self.inner.call(req).await;
+        guard.aborted = false;
+        let res = result?;
+        guard.status = Some(res.status());
+
+        let (parts, body) = res.into_parts();
+        let bodiless = is_bodiless(&method, parts.status);
+        let mut body = ResponseBody {
+            inner: body,
+            size: BodySizeCounter::new(&parts.extensions),
+            report: BodyReport {
+                report: Some((self.recorder.clone(), method, parts.status)),
+                on_abort: R::on_abort,
+            },
+        };
+        // bodies that are empty from the start, or never sent, are never polled
+        if body.inner.is_end_stream() || bodiless {
+            body.report();
+        }
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+pin_project! {
+    /// Response body for [`Metrics`].
+    ///
+    /// Reports its size to the [`MetricsRecorder`] once it has ended.
+    pub struct ResponseBody<B, R> {
+        #[pin]
+        inner: B,
+        size: BodySizeCounter,
+        report: BodyReport<R>,
+    }
+}
+
+impl<B, R> ResponseBody<B, R>
+where
+    R: MetricsRecorder,
+{
+    fn report(&mut self) {
+        if let Some((recorder, method, status)) = self.report.report.take() {
+            recorder.on_response_body(&method, status, self.size.size());
+        }
+    }
+}
+
+/// Calls [`MetricsRecorder::on_abort`] when dropped before the body was reported.
+struct BodyReport<R> {
+    report: Option<(R, Method, StatusCode)>,
+    // captured at construction so that `ResponseBody` doesn't have to carry
+    // a `MetricsRecorder` bound
+    on_abort: fn(&R, &Method, Option<StatusCode>),
+}
+
+impl<R> Drop for BodyReport<R> {
+    fn drop(&mut self) {
+        if let Some((recorder, method, status)) = self.report.take() {
+            (self.on_abort)(&recorder, &method, Some(status));
+        }
+    }
+}
+
+impl<B, R> std::fmt::Debug for ResponseBody<B, R>
+where
+    B: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseBody")
+            .field("inner", &self.inner)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl<B, R> Body for ResponseBody<B, R>
+where
+    B: Body,
+    R: MetricsRecorder,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let result = ready!(this.inner.as_mut().poll_frame(cx));
+
+        if let Some(data) = result
+            .as_ref()
+            .and_then(|frame| frame.as_ref().ok())
+            .and_then(Frame::data_ref)
+        {
+            this.size.add(data.remaining());
+        }
+
+        // a failed body has ended as well, it isn't reported as aborted
+        if !matches!(result, Some(Ok(_))) || this.inner.is_end_stream() {
+            if let Some((recorder, method, status)) = this.report.report.take() {
+                recorder.on_response_body(&method, status, this.size.size());
+            }
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Calls [`MetricsRecorder::on_response`] when dropped,
+/// such that aborted requests are recorded as well.
+struct ResponseGuard<'a, R: MetricsRecorder> {
+    recorder: &'a R,
+    method: Method,
+    status: Option<StatusCode>,
+    start: Instant,
+    aborted: bool,
+}
+
+impl<R: MetricsRecorder> Drop for ResponseGuard<'_, R> {
+    fn drop(&mut self) {
+        self.recorder
+            .on_response(&self.method, self.status, self.start.elapsed());
+        if self.aborted {
+            self.recorder.on_abort(&self.method, None);
+        }
+    }
+}
+
+/// Label value used for the `status` label when no response was produced.
+#[cfg(any(feature = "metrics-rs", feature = "metrics-prometheus"))]
+const STATUS_ERROR: &str = "error";
+
+#[cfg(any(feature = "metrics-rs", feature = "metrics-prometheus"))]
+fn status_label(status: Option<StatusCode>) -> String {
+    match status {
+        Some(status) => status.as_u16().to_string(),
+        None => STATUS_ERROR.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use futures_util::FutureExt;
+    use std::sync::Mutex;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[derive(Debug, Default)]
+    struct TestRecorder {
+        in_flight: Mutex<usize>,
+        responses: Mutex<Vec<(Method, Option<StatusCode>)>>,
+        bodies: Mutex<Vec<BodySize>>,
+        aborts: Mutex<Vec<(Method, Option<StatusCode>)>>,
+    }
+
+    impl MetricsRecorder for TestRecorder {
+        fn on_request(&self, _: &Method) {
+            *self.in_flight.lock().unwrap() += 1;
+        }
+
+        fn on_response(&self, method: &Method, status: Option<StatusCode>, _: Duration) {
+            *self.in_flight.lock().unwrap() -= 1;
+            self.responses
+                .lock()
+                .unwrap()
+                .push((method.clone(), status));
+        }
+
+        fn on_response_body(&self, _: &Method, _: StatusCode, size: BodySize) {
+            self.bodies.lock().unwrap().push(size);
+        }
+
+        fn on_abort(&self, method: &Method, status: Option<StatusCode>) {
+            self.aborts.lock().unwrap().push((method.clone(), status));
+        }
+    }
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, &'static str> {
+        match req.uri().path() {
+            "/error" => Err("error"),
+            "/pending" => futures_util::future::pending().await,
+            _ => Ok(Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()),
+        }
+    }
+
+    fn request(method: Method, path: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_responses_errors_and_aborts() {
+        let recorder = Arc::new(TestRecorder::default());
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder.clone()))
+            .service(service_fn(handle));
+
+        svc.call(request(Method::POST, "/")).await.unwrap();
+        svc.call(request(Method::GET, "/error")).await.unwrap_err();
+        assert!(svc
+            .call(request(Method::PUT, "/pending"))
+            .now_or_never()
+            .is_none());
+
+        assert_eq!(*recorder.in_flight.lock().unwrap(), 0);
+        assert_eq!(
+            *recorder.responses.lock().unwrap(),
+            vec![
+                (Method::POST, Some(StatusCode::CREATED)),
+                (Method::GET, None),
+                (Method::PUT, None),
+            ]
+        );
+        assert_eq!(*recorder.aborts.lock().unwrap(), vec![(Method::PUT, None)]);
+    }
+
+    #[tokio::test]
+    async fn records_dropped_bodies_as_aborts() {
+        let recorder = Arc::new(TestRecorder::default());
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder.clone()))
+            .service_fn(|_: Request<Body>| async {
+                let chunks = ["a", "b"].map(Ok::<_, std::io::Error>);
+                Ok::<_, std::convert::Infallible>(Response::new(Body::from_stream(
+                    futures_util::stream::iter(chunks),
+                )))
+            });
+
+        drop(svc.call(request(Method::GET, "/")).await.unwrap());
+        // the body of a response to a `HEAD` request is never sent
+        drop(svc.call(request(Method::HEAD, "/")).await.unwrap());
+
+        assert_eq!(
+            *recorder.aborts.lock().unwrap(),
+            vec![(Method::GET, Some(StatusCode::OK))]
+        );
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn records_size_of_streaming_bodies() {
+        use crate::compression::CompressionLayer;
+        use http::header;
+
+        let recorder = Arc::new(TestRecorder::default());
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder.clone()))
+            .layer(CompressionLayer::new())
+            .service_fn(|_: Request<Body>| async {
+                let chunks = ["a".repeat(1024), "b".repeat(1024)]
+                    .map(|chunk| Ok::<_, std::io::Error>(bytes::Bytes::from(chunk)));
+                Ok::<_, std::convert::Infallible>(Response::new(Body::from_stream(
+                    futures_util::stream::iter(chunks),
+                )))
+            });
+
+        let res = svc.call(request(Method::GET, "/")).await.unwrap();
+        crate::test_helpers::to_bytes(res.into_body())
+            .await
+            .unwrap();
+
+        let mut req = request(Method::GET, "/");
+        req.headers_mut()
+            .insert(header::ACCEPT_ENCODING, "gzip".parse().unwrap());
+        let res = svc.call(req).await.unwrap();
+        let compressed = crate::test_helpers::to_bytes(res.into_body())
+            .await
+            .unwrap();
+
+        let bodies = recorder.bodies.lock().unwrap();
+        assert_eq!(bodies[0].bytes(), 2048);
+        assert_eq!(bodies[0].compression_ratio(), None);
+        assert_eq!(bodies[1].bytes(), compressed.len() as u64);
+        assert_eq!(bodies[1].uncompressed_bytes(), Some(2048));
+        assert!(bodies[1].compression_ratio().unwrap() > 1.0);
+    }
+
+    #[cfg(feature = "metrics-prometheus")]
+    #[tokio::test]
+    async fn prometheus_recorder_is_served_by_endpoint() {
+        use crate::services::MetricsEndpoint;
+        use prometheus_client::registry::Registry;
+
+        let mut registry = Registry::default();
+        let recorder = PrometheusRecorder::new(&mut registry);
+        let svc = ServiceBuilder::new()
+            .layer(MetricsLayer::new(recorder))
+            .service(service_fn(handle));
+
+        svc.call(request(Method::POST, "/")).await.unwrap();
+        svc.call(request(Method::POST, "/")).await.unwrap();
+        svc.call(request(Method::GET, "/error")).await.unwrap_err();
+
+        let endpoint = MetricsEndpoint::<Body>::new(Arc::new(registry));
+        let res = endpoint
+            .call(request(Method::GET, "/metrics"))
+            .await
+            .unwrap();
+        let body = crate::test_helpers::to_bytes(res.into_body())
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains(r#"http_server_requests_total{method="POST",status="201"} 2"#));
+        assert!(body.contains(r#"http_server_requests_total{method="GET",status="error"} 1"#));
+        assert!(body.contains("http_server_requests_in_flight 0"));
+        assert!(body.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,123 @@
+use super::{status_label, MetricsRecorder};
+use crate::body_size::BodySize;
+use http::{Method, StatusCode};
+use prometheus_client::{
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use std::time::Duration;
+
+type Labels = [(&'static str, String); 2];
+
+/// [`MetricsRecorder`] that records to metrics registered in a [`prometheus_client`] registry.
+///
+/// The registry can be served using [`MetricsEndpoint`].
+/// See the [module docs](super) for the metrics that are recorded.
+///
+/// [`prometheus_client`]: https://docs.rs/prometheus-client
+/// [`MetricsEndpoint`]: crate::services::MetricsEndpoint
+#[derive(Debug, Clone)]
+pub struct PrometheusRecorder {
+    requests: Family<Labels, Counter>,
+    duration: Family<Labels, Histogram, fn() -> Histogram>,
+    in_flight: Gauge,
+    aborted: Family<Labels, Counter>,
+    body_bytes: Family<Labels, Counter>,
+    compression_ratio: Family<Labels, Histogram, fn() -> Histogram>,
+}
+
+impl PrometheusRecorder {
+    /// Create a new [`PrometheusRecorder`], registering its metrics in `registry`.
+    pub fn new(registry: &mut Registry) -> Self {
+        let recorder = Self {
+            requests: Family::default(),
+            duration: Family::new_with_constructor(|| {
+                // 5ms up to ~10s
+                Histogram::new(exponential_buckets(0.005, 2.0, 12))
+            }),
+            in_flight: Gauge::default(),
+            aborted: Family::default(),
+            body_bytes: Family::default(),
+            compression_ratio: Family::new_with_constructor(|| {
+                // 1 up to 64
+                Histogram::new(exponential_buckets(1.0, 2.0, 7))
+            }),
+        };
+
+        registry.register(
+            "http_server_requests",
+            "Number of handled HTTP requests",
+            recorder.requests.clone(),
+        );
+        registry.register(
+            "http_server_request_duration_seconds",
+            "Duration until the HTTP response head was produced",
+            recorder.duration.clone(),
+        );
+        registry.register(
+            "http_server_requests_in_flight",
+            "Number of HTTP requests currently in flight",
+            recorder.in_flight.clone(),
+        );
+        registry.register(
+            "http_server_requests_aborted",
+            "Number of HTTP requests aborted before their response was sent",
+            recorder.aborted.clone(),
+        );
+        registry.register(
+            "http_server_response_body_bytes",
+            "Number of bytes of HTTP response bodies sent",
+            recorder.body_bytes.clone(),
+        );
+        registry.register(
+            "http_server_response_compression_ratio",
+            "Ratio of the size before compression to the size sent of compressed HTTP response bodies",
+            recorder.compression_ratio.clone(),
+        );
+
+        recorder
+    }
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn on_request(&self, _: &Method) {
+        self.in_flight.inc();
+    }
+
+    fn on_response(&self, method: &Method, status: Option<StatusCode>, latency: Duration) {
+        self.in_flight.dec();
+
+        let labels = [
+            ("method", method.to_string()),
+            ("status", status_label(status)),
+        ];
+        self.requests.get_or_create(&labels).inc();
+        self.duration
+            .get_or_create(&labels)
+            .observe(latency.as_secs_f64());
+    }
+
+    fn on_response_body(&self, method: &Method, status: StatusCode, size: BodySize) {
+        let labels = [
+            ("method", method.to_string()),
+            ("status", status_label(Some(status))),
+        ];
+        self.body_bytes.get_or_create(&labels).inc_by(size.bytes());
+        if let Some(ratio) = size.compression_ratio() {
+            self.compression_ratio.get_or_create(&labels).observe(ratio);
+        }
+    }
+
+    fn on_abort(&self, method: &Method, status: Option<StatusCode>) {
+        let labels = [
+            ("method", method.to_string()),
+            ("status", status_label(status)),
+        ];
+        self.aborted.get_or_create(&labels).inc();
+    }
+}
@@ -0,0 +1,283 @@
+//! Newline delimited JSON ([NDJSON]) response bodies.
+//!
+//! [`StreamBody`] converts a [`Stream`] of items into a response body that contains one
+//! serialized item per line, as commonly used by long polling and export endpoints.
+//!
+//! Items are serialized as JSON using [`Json`] by default, but any [`Serializer`] can be used,
+//! including closures that write an item to a buffer.
+//!
+//! # Backpressure
+//!
+//! The stream is only polled when the body is polled, so items are produced
+//! no faster than the client consumes them.
+//!
+//! # Flushing
+//!
+//! By default every item is sent as its own data frame, such that clients receive it as soon
+//! as possible. With [`StreamBody::flush_threshold`] consecutive items that are ready are
+//! buffered into a single frame until the threshold is reached, trading some latency for
+//! fewer and larger frames. Buffered items are always flushed as soon as the stream has to
+//! wait for the next item.
+//!
+//! # Compression
+//!
+//! Compression encoders buffer their output, which delays lines until enough data has been
+//! produced. This is fine for exports, but defeats the purpose of long polling endpoints.
+//! Such responses can be excluded from compression with [`NotForContentType::NDJSON`].
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::BodyExt;
+//! use std::convert::Infallible;
+//! use tower_async_http::ndjson::StreamBody;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! async fn export(_req: Request<()>) -> Result<Response<StreamBody<impl futures_core::Stream<Item = Result<u32, Infallible>>>>, Infallible> {
+//!     let rows = futures_util::stream::iter([1, 2, 3].map(Ok));
+//!     Ok(StreamBody::new(rows).into_response())
+//! }
+//!
+//! let res = export(Request::new(())).await.unwrap();
+//! assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+//!
+//! let body = res.into_body().collect().await.unwrap().to_bytes();
+//! assert_eq!(body, "1\n2\n3\n");
+//! # }
+//! ```
+//!
+//! [NDJSON]: https://github.com/ndjson/ndjson-spec
+//! [`Stream`]: futures_core::Stream
+//! [`NotForContentType::NDJSON`]: crate::compression::predicate::NotForContentType::NDJSON
+
+use crate::BoxError;
+use bytes::{Bytes, BytesMut};
+use futures_core::Stream;
+use http::{header, HeaderValue, Response};
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The content type of NDJSON bodies.
+pub const CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Serializes a single item of a [`StreamBody`].
+///
+/// The serialized item must not contain a newline,
+/// the [`StreamBody`] terminates every item with one.
+pub trait Serializer<T> {
+    /// Write the serialized `item` to `buf`.
+    fn serialize(&mut self, item: &T, buf: &mut BytesMut) -> Result<(), BoxError>;
+}
+
+impl<T, F> Serializer<T> for F
+where
+    F: FnMut(&T, &mut BytesMut) -> Result<(), BoxError>,
+{
+    fn serialize(&mut self, item: &T, buf: &mut BytesMut) -> Result<(), BoxError> {
+        self(item, buf)
+    }
+}
+
+/// [`Serializer`] that serializes items as compact JSON using [`serde_json`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl<T> Serializer<T> for Json
+where
+    T: serde::Serialize,
+{
+    fn serialize(&mut self, item: &T, buf: &mut BytesMut) -> Result<(), BoxError> {
+        use bytes::BufMut;
+        serde_json::to_writer(buf.writer(), item)?;
+        Ok(())
+    }
+}
+
+pin_project! {
+    /// Response body that serializes the items of a [`Stream`] as newline delimited JSON.
+    ///
+    /// See the [module docs](self) for more details.
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub struct StreamBody<S, F = Json> {
+        #[pin]
+        stream: S,
+        serializer: F,
+        buf: BytesMut,
+        flush_threshold: usize,
+        error: Option<BoxError>,
+        done: bool,
+    }
+}
+
+impl<S> StreamBody<S> {
+    /// Create a new [`StreamBody`] serializing items as JSON.
+    pub fn new(stream: S) -> Self {
+        Self::with_serializer(stream, Json)
+    }
+}
+
+impl<S, F> StreamBody<S, F> {
+    /// Create a new [`StreamBody`] serializing items with the given [`Serializer`].
+    pub fn with_serializer(stream: S, serializer: F) -> Self {
+        Self {
+            stream,
+            serializer,
+            buf: BytesMut::new(),
+            flush_threshold: 0,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// Buffer items that are ready into a single frame of up to `bytes`.
+    ///
+    /// Defaults to `0`, sending every item as its own frame.
+    pub fn flush_threshold(mut self, bytes: usize) -> Self {
+        self.flush_threshold = bytes;
+        self
+    }
+
+    /// Wrap the body in a response with the `application/x-ndjson` content type.
+    pub fn into_response(self) -> Response<Self> {
+        let mut res = Response::new(self);
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+        res
+    }
+}
+
+impl<S, F> fmt::Debug for StreamBody<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamBody")
+            .field("stream", &format_args!("{}", std::any::type_name::<S>()))
+            .field(
+                "serializer",
+                &format_args!("{}", std::any::type_name::<F>()),
+            )
+            .field("flush_threshold", &self.flush_threshold)
+            .finish()
+    }
+}
+
+impl<S, F, T, E> Body for StreamBody<S, F>
+where
+    S: Stream<Item = Result<T, E>>,
+    E: Into<BoxError>,
+    F: Serializer<T>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        while !*this.done && this.error.is_none() {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(item))) => {
+                    if let Err(err) = this.serializer.serialize(&item, this.buf) {
+                        *this.error = Some(err);
+                        break;
+                    }
+                    this.buf.extend_from_slice(b"\n");
+                    if this.buf.len() >= *this.flush_threshold {
+                        break;
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => *this.error = Some(err.into()),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending if this.buf.is_empty() => return Poll::Pending,
+                Poll::Pending => break,
+            }
+        }
+
+        // lines serialized before an error are still sent
+        if !this.buf.is_empty() {
+            let data = this.buf.split().freeze();
+            return Poll::Ready(Some(Ok(Frame::data(data))));
+        }
+
+        match this.error.take() {
+            Some(err) => {
+                *this.done = true;
+                Poll::Ready(Some(Err(err)))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done && self.buf.is_empty() && self.error.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures_util::stream;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+
+    async fn frames<B>(body: B) -> Vec<Result<Bytes, BoxError>>
+    where
+        B: Body<Data = Bytes, Error = BoxError>,
+    {
+        let mut body = std::pin::pin!(body);
+        let mut frames = Vec::new();
+        while let Some(frame) = body.frame().await {
+            frames.push(frame.map(|frame| frame.into_data().unwrap()));
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn every_item_is_a_frame_by_default() {
+        let rows = stream::iter([
+            Ok::<_, Infallible>(serde_json::json!({ "id": 1, "name": "a" })),
+            Ok(serde_json::json!({ "id": 2, "name": "b" })),
+        ]);
+
+        let frames = frames(StreamBody::new(rows)).await;
+        let frames: Vec<_> = frames.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            frames,
+            ["{\"id\":1,\"name\":\"a\"}\n", "{\"id\":2,\"name\":\"b\"}\n"]
+        );
+    }
+
+    #[tokio::test]
+    async fn ready_items_are_buffered_up_to_flush_threshold() {
+        let items = stream::iter((0..5).map(Ok::<_, Infallible>));
+        let serializer = |item: &u32, buf: &mut BytesMut| -> Result<(), BoxError> {
+            buf.extend_from_slice(item.to_string().as_bytes());
+            Ok(())
+        };
+
+        let body = StreamBody::with_serializer(items, serializer).flush_threshold(4);
+        let frames: Vec<_> = frames(body).await.into_iter().map(Result::unwrap).collect();
+        assert_eq!(frames, ["0\n1\n", "2\n3\n", "4\n"]);
+    }
+
+    #[tokio::test]
+    async fn buffered_items_are_flushed_before_error() {
+        let items = stream::iter([Ok(1), Ok(2), Err("boom")]);
+
+        let body = StreamBody::new(items).flush_threshold(1024);
+        let mut frames = frames(body).await.into_iter();
+        assert_eq!(frames.next().unwrap().unwrap(), "1\n2\n");
+        assert_eq!(frames.next().unwrap().unwrap_err().to_string(), "boom");
+        assert!(frames.next().is_none());
+    }
+}
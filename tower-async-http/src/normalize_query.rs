@@ -0,0 +1,398 @@
+//! Middleware that normalizes query strings.
+//!
+//! Requests for the same resource often differ only in their query string: the parameters
+//! are in a different order, repeated, percent-encoded differently, or carry tracking
+//! parameters such as `utm_source` which don't affect the response. Normalizing the query
+//! improves the hit rate of caches keyed by the URI, and makes logs consistent.
+//!
+//! [`NormalizeQuery`] rewrites the query of each request by:
+//!
+//! - removing the configured parameters, such as [tracking parameters];
+//! - sorting the parameters by name, keeping the order of parameters with the same name;
+//! - removing parameters which are exact duplicates of a previous one, as well as empty ones;
+//! - normalizing percent-encoding: escaped unreserved characters are decoded, and the other
+//!   escapes use uppercase hexadecimal digits, such that `%7euser%2f` becomes `~user%2F`.
+//!
+//! The query is removed altogether if no parameters are left. The original query is
+//! available to the inner service as an [`OriginalQuery`] request extension.
+//!
+//! # Example
+//!
+//! ```
+//! use tower_async_http::normalize_query::{NormalizeQueryLayer, OriginalQuery};
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::convert::Infallible;
+//! use tower_async::{ServiceBuilder, Service};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     assert_eq!(req.uri(), "/search?page=2&q=rust");
+//!
+//!     let original = req.extensions().get::<OriginalQuery>().unwrap();
+//!     assert_eq!(original.as_str(), "q=rust&utm_source=newsletter&page=2");
+//!     # Ok(Response::new(Full::default()))
+//! }
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(NormalizeQueryLayer::new().remove_tracking_params())
+//!     .service_fn(handle);
+//!
+//! let request = Request::builder()
+//!     .uri("/search?q=rust&utm_source=newsletter&page=2")
+//!     .body(Full::<Bytes>::default())?;
+//!
+//! service.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [tracking parameters]: NormalizeQueryLayer::remove_tracking_params
+
+use http::{uri::PathAndQuery, Request, Uri};
+use std::{fmt::Write, sync::Arc};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The original query of a request, before it was normalized by [`NormalizeQuery`].
+///
+/// Inserted as a request extension if the request had a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalQuery(String);
+
+impl OriginalQuery {
+    /// Returns the original query, without the leading `?`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    remove: Vec<String>,
+    remove_prefixes: Vec<String>,
+    sort: bool,
+    dedup: bool,
+}
+
+/// Layer that applies [`NormalizeQuery`] which normalizes query strings.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct NormalizeQueryLayer {
+    config: Arc<Config>,
+}
+
+impl Default for NormalizeQueryLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NormalizeQueryLayer {
+    /// Create a new [`NormalizeQueryLayer`].
+    ///
+    /// Sorts and deduplicates parameters and normalizes their percent-encoding,
+    /// without removing any parameters.
+    pub fn new() -> Self {
+        NormalizeQueryLayer {
+            config: Arc::new(Config {
+                remove: Vec::new(),
+                remove_prefixes: Vec::new(),
+                sort: true,
+                dedup: true,
+            }),
+        }
+    }
+
+    /// Remove parameters with the given name.
+    pub fn remove_param(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).remove.push(name.into());
+        self
+    }
+
+    /// Remove parameters whose name starts with the given prefix.
+    pub fn remove_params_with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config)
+            .remove_prefixes
+            .push(prefix.into());
+        self
+    }
+
+    /// Remove common tracking parameters: `utm_*` parameters, `gclid`, `fbclid` and `msclkid`.
+    pub fn remove_tracking_params(self) -> Self {
+        self.remove_params_with_prefix("utm_")
+            .remove_param("gclid")
+            .remove_param("fbclid")
+            .remove_param("msclkid")
+    }
+
+    /// Set whether parameters are sorted by name, defaults to `true`.
+    pub fn sort(mut self, sort: bool) -> Self {
+        Arc::make_mut(&mut self.config).sort = sort;
+        self
+    }
+
+    /// Set whether parameters which are exact duplicates of a previous one are removed,
+    /// defaults to `true`.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        Arc::make_mut(&mut self.config).dedup = dedup;
+        self
+    }
+}
+
+impl<S> Layer<S> for NormalizeQueryLayer {
+    type Service = NormalizeQuery<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NormalizeQuery {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that normalizes query strings.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct NormalizeQuery<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> NormalizeQuery<S> {
+    /// Create a new [`NormalizeQuery`], see [`NormalizeQueryLayer::new`].
+    pub fn new(inner: S) -> Self {
+        NormalizeQueryLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `NormalizeQuery` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> NormalizeQueryLayer {
+        NormalizeQueryLayer::new()
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for NormalizeQuery<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if let Some(query) = req.uri().query() {
+            let original = OriginalQuery(query.to_owned());
+            normalize_query(req.uri_mut(), &self.config);
+            req.extensions_mut().insert(original);
+        }
+        self.inner.call(req).await
+    }
+}
+
+fn normalize_query(uri: &mut Uri, config: &Config) {
+    let Some(query) = uri.query() else {
+        return;
+    };
+
+    let mut params: Vec<(String, Option<String>)> = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => (normalize_encoding(name), Some(normalize_encoding(value))),
+            None => (normalize_encoding(param), None),
+        })
+        .filter(|(name, _)| {
+            !config.remove.iter().any(|removed| removed == name)
+                && !config
+                    .remove_prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix.as_str()))
+        })
+        .collect();
+
+    if config.sort {
+        params.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+    if config.dedup {
+        let mut seen = Vec::with_capacity(params.len());
+        params.retain(|param| {
+            if seen.contains(param) {
+                false
+            } else {
+                seen.push(param.clone());
+                true
+            }
+        });
+    }
+
+    let mut path_and_query = uri.path().to_owned();
+    for (i, (name, value)) in params.iter().enumerate() {
+        path_and_query.push(if i == 0 { '?' } else { '&' });
+        path_and_query.push_str(name);
+        if let Some(value) = value {
+            path_and_query.push('=');
+            path_and_query.push_str(value);
+        }
+    }
+
+    let mut parts = uri.clone().into_parts();
+    let Ok(path_and_query) = PathAndQuery::try_from(path_and_query) else {
+        return;
+    };
+    parts.path_and_query = Some(path_and_query);
+    if let Ok(new_uri) = Uri::from_parts(parts) {
+        *uri = new_uri;
+    }
+}
+
+/// Decodes escaped unreserved characters and uppercases the hexadecimal digits of other escapes.
+fn normalize_encoding(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                let byte = hi << 4 | lo;
+                if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+                    out.push(byte as char);
+                } else {
+                    let _ = write!(out, "%{byte:02X}");
+                }
+                i += 3;
+                continue;
+            }
+        }
+        // The query of a `Uri` only contains ASCII characters.
+        out.push(bytes[i] as char);
+        i += 1;
+    }
+    out
+}
+
+fn hex(byte: u8) -> Option<u8> {
+    match byte {
+        byte @ b'0'..=b'9' => Some(byte - b'0'),
+        byte @ b'a'..=b'f' => Some(byte - b'a' + 10),
+        byte @ b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http::Response;
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    fn normalize(layer: &NormalizeQueryLayer, uri: &str) -> String {
+        let mut uri = uri.parse::<Uri>().unwrap();
+        normalize_query(&mut uri, &layer.config);
+        uri.to_string()
+    }
+
+    #[tokio::test]
+    async fn works() {
+        async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+            assert_eq!(request.uri(), "/foo?a=1&b=2");
+            assert_eq!(
+                request
+                    .extensions()
+                    .get::<OriginalQuery>()
+                    .unwrap()
+                    .as_str(),
+                "b=2&a=1"
+            );
+            Ok(Response::new(Body::empty()))
+        }
+
+        let svc = ServiceBuilder::new()
+            .layer(NormalizeQueryLayer::new())
+            .service_fn(handle);
+
+        svc.call(
+            Request::builder()
+                .uri("/foo?b=2&a=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn no_query() {
+        let svc = NormalizeQuery::new(service_fn(|request: Request<Body>| async move {
+            assert_eq!(request.uri(), "/foo");
+            assert!(request.extensions().get::<OriginalQuery>().is_none());
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+
+        svc.call(Request::builder().uri("/foo").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn sorts_params() {
+        let layer = NormalizeQueryLayer::new();
+        assert_eq!(normalize(&layer, "/?c=3&a=1&b=2"), "/?a=1&b=2&c=3");
+        assert_eq!(normalize(&layer, "/?b=2&a=y&a=x"), "/?a=y&a=x&b=2");
+
+        let layer = NormalizeQueryLayer::new().sort(false);
+        assert_eq!(normalize(&layer, "/?c=3&a=1&b=2"), "/?c=3&a=1&b=2");
+    }
+
+    #[test]
+    fn removes_duplicate_and_empty_params() {
+        let layer = NormalizeQueryLayer::new();
+        assert_eq!(normalize(&layer, "/?a=1&&a=1&a&a&a=2&"), "/?a=1&a&a=2");
+        assert_eq!(normalize(&layer, "/?a=%7e&a=~"), "/?a=~");
+
+        let layer = NormalizeQueryLayer::new().dedup(false);
+        assert_eq!(normalize(&layer, "/?a=1&a=1"), "/?a=1&a=1");
+    }
+
+    #[test]
+    fn removes_configured_params() {
+        let layer = NormalizeQueryLayer::new()
+            .remove_param("session")
+            .remove_tracking_params();
+        assert_eq!(
+            normalize(
+                &layer,
+                "/?q=x&utm_source=a&utm_medium=b&gclid=c&fbclid=d&session=e&sessions=f"
+            ),
+            "/?q=x&sessions=f"
+        );
+        assert_eq!(normalize(&layer, "/path?utm_source=a&gclid=b"), "/path");
+        assert_eq!(
+            normalize(&layer, "https://example.com/?utm_source=a"),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn normalizes_percent_encoding() {
+        let layer = NormalizeQueryLayer::new();
+        assert_eq!(
+            normalize(&layer, "/?q=%7euser%2f%41%2a&%61=b"),
+            "/?a=b&q=~user%2FA%2A"
+        );
+        assert_eq!(normalize(&layer, "/?q=a+b%20c"), "/?q=a+b%20c");
+        assert_eq!(
+            normalize(&layer, "/?q=100%&r=%zz&s=%4"),
+            "/?q=100%&r=%zz&s=%4"
+        );
+    }
+}
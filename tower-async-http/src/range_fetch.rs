@@ -0,0 +1,686 @@
+//! Middleware that fetches ranged requests from upstream in parallel chunks.
+//!
+//! [`RangeFetch`] wraps an HTTP client, such as a proxy's upstream client. A `GET` request
+//! for a single byte range, e.g. `Range: bytes=0-1048575`, is split into multiple upstream
+//! requests for consecutive chunks of that range, which are fetched in parallel. The chunks
+//! are reassembled in order into the body of a single `206 Partial Content` response, as
+//! accelerating CDNs and download managers do for large files.
+//!
+//! The first chunk is requested on its own, as its `Content-Range` header tells the total size
+//! of the resource, which is required for open ended ranges such as `bytes=1024-`. Its body is
+//! streamed as-is, after which the remaining chunks are fetched, with at most
+//! [`RangeFetchLayer::concurrency`] upstream requests in flight. Each chunk is buffered until
+//! the chunks before it have been sent, so no more than that many chunks are buffered at once.
+//!
+//! The upstream response is returned unchanged if:
+//!
+//! - the request isn't a `GET` request, or doesn't have a single `bytes` range,
+//!   or has a suffix range such as `bytes=-500`;
+//! - upstream doesn't respond to the first chunk with `206 Partial Content`
+//!   and a valid `Content-Range` header;
+//! - the first chunk already covers the whole range.
+//!
+//! If upstream fails to return one of the other chunks, the response body fails with an error.
+//!
+//! The other chunks are requested with an `If-Range` header holding the strong `ETag`, or else
+//! the `Last-Modified` date, of the first chunk, such that they are taken from the same version
+//! of the resource. If the resource changes while it is fetched, upstream responds to them with
+//! `200 OK`, or with another validator, and the response body fails with an error rather than
+//! mixing the versions. Without either validator, such changes can't be detected.
+//!
+//! Chunk requests copy the method, URI, version, headers and extensions of the original
+//! request, and have an empty body. As the futures of the upstream client are driven by the
+//! response body, they must be [`Send`].
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response, StatusCode};
+//! use http_body_util::{BodyExt, Full};
+//! use std::convert::Infallible;
+//! use tower_async::{service_fn, Service, ServiceBuilder};
+//! use tower_async_http::range_fetch::RangeFetchLayer;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! # let upstream = service_fn(|req: Request<Full<Bytes>>| async move {
+//! #     let range = req.headers()[header::RANGE].to_str().unwrap();
+//! #     let (start, end) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+//! #     let (start, end) = (start.parse::<usize>().unwrap(), end.parse::<usize>().unwrap().min(99));
+//! #     Ok::<_, Infallible>(
+//! #         Response::builder()
+//! #             .status(StatusCode::PARTIAL_CONTENT)
+//! #             .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/100"))
+//! #             .body(Full::from(vec![b'a'; end - start + 1]))
+//! #             .unwrap(),
+//! #     )
+//! # });
+//! let client = ServiceBuilder::new()
+//!     // Fetch ranges in chunks of 16 KiB, with up to 4 chunks in flight.
+//!     .layer(RangeFetchLayer::new(16 * 1024).concurrency(4))
+//!     .service(upstream);
+//!
+//! let req = Request::get("http://example.com/large-file")
+//!     .header(header::RANGE, "bytes=0-")
+//!     .body(Full::<Bytes>::default())?;
+//! let res = client.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+//! assert_eq!(res.into_body().collect().await?.to_bytes().len(), 100);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::BoxError;
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::StreamExt;
+use http::{header, request::Parts, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::BodyExt;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Layer that applies the [`RangeFetch`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Copy)]
+pub struct RangeFetchLayer {
+    chunk_size: u64,
+    concurrency: usize,
+}
+
+impl RangeFetchLayer {
+    /// Create a new [`RangeFetchLayer`], fetching ranges in chunks of `chunk_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn new(chunk_size: u64) -> Self {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        Self {
+            chunk_size,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Set the maximum number of chunks that are fetched, or buffered, at once.
+    ///
+    /// Defaults to 4.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is zero.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be greater than zero");
+        self.concurrency = concurrency;
+        self
+    }
+}
+
+impl<S> Layer<S> for RangeFetchLayer {
+    type Service = RangeFetch<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RangeFetch {
+            inner,
+            chunk_size: self.chunk_size,
+            concurrency: self.concurrency,
+        }
+    }
+}
+
+/// Middleware that fetches ranged requests from upstream in parallel chunks.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct RangeFetch<S> {
+    inner: S,
+    chunk_size: u64,
+    concurrency: usize,
+}
+
+impl<S> RangeFetch<S> {
+    /// Create a new [`RangeFetch`], fetching ranges in chunks of `chunk_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn new(inner: S, chunk_size: u64) -> Self {
+        RangeFetchLayer::new(chunk_size).layer(inner)
+    }
+
+    /// Set the maximum number of chunks that are fetched, or buffered, at once.
+    ///
+    /// Defaults to 4.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is zero.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be greater than zero");
+        self.concurrency = concurrency;
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a [`RangeFetch`] middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(chunk_size: u64) -> RangeFetchLayer {
+        RangeFetchLayer::new(chunk_size)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RangeFetch<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>, call(): Send>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    S::Error: Into<BoxError>,
+    ReqBody: Default + Send + 'static,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<RangeFetchBody<ResBody>>;
+    type Error = BoxError;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let range = if req.method() == Method::GET {
+            parse_range(req.headers())
+        } else {
+            None
+        };
+        let Some((start, end)) = range else {
+            let res = self.inner.call(req).await.map_err(Into::into)?;
+            return Ok(res.map(RangeFetchBody::passthrough));
+        };
+
+        let (parts, body) = req.into_parts();
+        let first_end = chunk_end(start, self.chunk_size, end);
+        let first = Request::from_parts(chunk_parts(&parts, start, first_end), body);
+        let res = self.inner.call(first).await.map_err(Into::into)?;
+
+        let content_range = (res.status() == StatusCode::PARTIAL_CONTENT)
+            .then(|| parse_content_range(res.headers()))
+            .flatten();
+        let Some((first_start, first_end, total)) = content_range else {
+            return Ok(res.map(RangeFetchBody::passthrough));
+        };
+        let end = end.unwrap_or(u64::MAX).min(total - 1);
+        if first_start != start || first_end >= end {
+            return Ok(res.map(RangeFetchBody::passthrough));
+        }
+
+        let validator = Validator::from_headers(res.headers());
+        let chunk_size = self.chunk_size;
+        let chunks = std::iter::successors(Some(first_end + 1), move |start| {
+            start.checked_add(chunk_size).filter(|start| *start <= end)
+        })
+        .map(move |start| (start, chunk_end(start, chunk_size, Some(end))));
+
+        let inner = self.inner.clone();
+        let rest = futures_util::stream::iter(chunks)
+            .map(move |(start, end)| {
+                let inner = inner.clone();
+                let validator = validator.clone();
+                let mut parts = chunk_parts(&parts, start, end);
+                if let Some(validator) = &validator {
+                    parts
+                        .headers
+                        .insert(header::IF_RANGE, validator.value().clone());
+                }
+                let req = Request::from_parts(parts, ReqBody::default());
+                async move { fetch_chunk(inner, req, start, end, validator).await }
+            })
+            .buffered(self.concurrency);
+
+        let (mut parts, body) = res.into_parts();
+        parts.headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&format!("bytes {start}-{end}/{total}")).unwrap(),
+        );
+        parts
+            .headers
+            .insert(header::CONTENT_LENGTH, (end - start + 1).into());
+
+        Ok(Response::from_parts(
+            parts,
+            RangeFetchBody {
+                first: body,
+                rest: Some(Box::pin(rest)),
+                first_done: false,
+            },
+        ))
+    }
+}
+
+async fn fetch_chunk<S, ReqBody, ResBody>(
+    inner: S,
+    req: Request<ReqBody>,
+    start: u64,
+    end: u64,
+    validator: Option<Validator>,
+) -> Result<Bytes, BoxError>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    ResBody: Body<Data = Bytes>,
+    ResBody::Error: Into<BoxError>,
+{
+    let res = inner.call(req).await.map_err(Into::into)?;
+    let content_range = (res.status() == StatusCode::PARTIAL_CONTENT)
+        .then(|| parse_content_range(res.headers()))
+        .flatten();
+    if !matches!(content_range, Some((s, e, _)) if s == start && e == end) {
+        return Err(InvalidChunk { start, end }.into());
+    }
+    if validator.is_some_and(|validator| !validator.matches(res.headers())) {
+        return Err(ResourceChanged { start, end }.into());
+    }
+
+    let chunk = res
+        .into_body()
+        .collect()
+        .await
+        .map_err(Into::into)?
+        .to_bytes();
+    if chunk.len() as u64 != end - start + 1 {
+        return Err(InvalidChunk { start, end }.into());
+    }
+    Ok(chunk)
+}
+
+/// The validator of the first chunk, which the other chunks must match.
+#[derive(Debug, Clone)]
+enum Validator {
+    ETag(HeaderValue),
+    LastModified(HeaderValue),
+}
+
+impl Validator {
+    /// Returns the strong `ETag`, or else the `Last-Modified` date of a response,
+    /// as weak entity tags can't be used with `If-Range`.
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let etag = headers
+            .get(header::ETAG)
+            .filter(|etag| !etag.as_bytes().starts_with(b"W/"));
+        match etag {
+            Some(etag) => Some(Validator::ETag(etag.clone())),
+            None => headers
+                .get(header::LAST_MODIFIED)
+                .cloned()
+                .map(Validator::LastModified),
+        }
+    }
+
+    fn value(&self) -> &HeaderValue {
+        match self {
+            Validator::ETag(value) | Validator::LastModified(value) => value,
+        }
+    }
+
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        let name = match self {
+            Validator::ETag(_) => header::ETAG,
+            Validator::LastModified(_) => header::LAST_MODIFIED,
+        };
+        headers.get(name) == Some(self.value())
+    }
+}
+
+/// Returns the last byte of the chunk starting at `start`, capped to `end`.
+fn chunk_end(start: u64, chunk_size: u64, end: Option<u64>) -> u64 {
+    let chunk_end = start.saturating_add(chunk_size - 1);
+    end.map_or(chunk_end, |end| chunk_end.min(end))
+}
+
+fn chunk_parts(parts: &Parts, start: u64, end: u64) -> Parts {
+    let (mut chunk, ()) = Request::new(()).into_parts();
+    chunk.method = parts.method.clone();
+    chunk.uri = parts.uri.clone();
+    chunk.version = parts.version;
+    chunk.headers = parts.headers.clone();
+    chunk.extensions = parts.extensions.clone();
+    chunk.headers.insert(
+        header::RANGE,
+        HeaderValue::from_str(&format!("bytes={start}-{end}")).unwrap(),
+    );
+    chunk.headers.remove(header::CONTENT_LENGTH);
+    chunk
+}
+
+/// Parses a single `bytes=start-end` or `bytes=start-` range.
+fn parse_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let mut ranges = headers.get_all(header::RANGE).iter();
+    let range = ranges.next()?.to_str().ok()?;
+    if ranges.next().is_some() {
+        return None;
+    }
+
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok().filter(|end| *end >= start)?),
+    };
+    Some((start, end))
+}
+
+/// Parses a `bytes start-end/total` content range.
+fn parse_content_range(headers: &HeaderMap) -> Option<(u64, u64, u64)> {
+    let content_range = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = content_range
+        .trim()
+        .strip_prefix("bytes ")?
+        .split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end, total): (u64, u64, u64) =
+        (start.parse().ok()?, end.parse().ok()?, total.parse().ok()?);
+    (start <= end && end < total).then_some((start, end, total))
+}
+
+pin_project! {
+    /// Response body of [`RangeFetch`].
+    ///
+    /// Streams the body of the first chunk, followed by the other chunks in order.
+    pub struct RangeFetchBody<B> {
+        #[pin]
+        first: B,
+        rest: Option<Pin<Box<dyn Stream<Item = Result<Bytes, BoxError>> + Send>>>,
+        first_done: bool,
+    }
+}
+
+impl<B> RangeFetchBody<B> {
+    fn passthrough(body: B) -> Self {
+        Self {
+            first: body,
+            rest: None,
+            first_done: false,
+        }
+    }
+}
+
+impl<B> Body for RangeFetchBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        if !*this.first_done {
+            loop {
+                match ready!(this.first.as_mut().poll_frame(cx)) {
+                    // the trailers of the first chunk don't apply to the whole range
+                    Some(Ok(frame)) if frame.is_trailers() && this.rest.is_some() => {}
+                    Some(frame) => return Poll::Ready(Some(frame.map_err(Into::into))),
+                    None => {
+                        *this.first_done = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let Some(rest) = this.rest else {
+            return Poll::Ready(None);
+        };
+        match ready!(rest.as_mut().poll_next(cx)) {
+            Some(chunk) => Poll::Ready(Some(chunk.map(Frame::data))),
+            None => {
+                *this.rest = None;
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.rest.is_none() && (self.first_done || self.first.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.rest {
+            None if !self.first_done => self.first.size_hint(),
+            None => SizeHint::with_exact(0),
+            Some(_) => SizeHint::default(),
+        }
+    }
+}
+
+impl<B> fmt::Debug for RangeFetchBody<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RangeFetchBody")
+            .field("first", &self.first)
+            .field("first_done", &self.first_done)
+            .finish()
+    }
+}
+
+/// Error returned by the body of [`RangeFetch`] if upstream didn't return a requested chunk.
+#[derive(Debug)]
+pub struct InvalidChunk {
+    start: u64,
+    end: u64,
+}
+
+impl fmt::Display for InvalidChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "upstream did not return the requested range bytes={}-{}",
+            self.start, self.end
+        )
+    }
+}
+
+impl std::error::Error for InvalidChunk {}
+
+/// Error returned by the body of [`RangeFetch`] if the resource changed while its chunks
+/// were fetched, such that upstream returned a chunk of another version of the resource.
+#[derive(Debug)]
+pub struct ResourceChanged {
+    start: u64,
+    end: u64,
+}
+
+impl fmt::Display for ResourceChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "upstream returned the range bytes={}-{} of another version of the resource",
+            self.start, self.end
+        )
+    }
+}
+
+impl std::error::Error for ResourceChanged {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+    use tower_async::ServiceBuilder;
+
+    const DATA: &[u8; 100] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ\
+        0123456789abcdefghijklmnopqrstuvwxyzAB";
+
+    #[derive(Clone)]
+    struct Upstream {
+        calls: Arc<AtomicUsize>,
+        etag: Arc<Mutex<Option<&'static str>>>,
+    }
+
+    impl Service<Request<Body>> for Upstream {
+        type Response = Response<Body>;
+        type Error = Infallible;
+
+        async fn call(&self, req: Request<Body>) -> Result<Self::Response, Self::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let etag = *self.etag.lock().unwrap();
+            let mut res = Response::builder();
+            if let Some(etag) = etag {
+                res = res.header(header::ETAG, etag);
+            }
+            // the whole resource is returned if it changed since `If-Range`
+            let unchanged = req
+                .headers()
+                .get(header::IF_RANGE)
+                .is_none_or(|if_range| Some(if_range.as_bytes()) == etag.map(str::as_bytes));
+            let res = match parse_range(req.headers()).filter(|_| unchanged) {
+                Some((start, end)) => {
+                    let end = end.unwrap_or(99).min(99);
+                    let chunk = &DATA[start as usize..=end as usize];
+                    res.status(StatusCode::PARTIAL_CONTENT)
+                        .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/100"))
+                        .header(header::CONTENT_TYPE, "text/plain")
+                        .body(Body::from(Bytes::from_static(chunk)))
+                }
+                None => res.body(Body::from(Bytes::from_static(DATA))),
+            };
+            Ok(res.unwrap())
+        }
+    }
+
+    fn upstream(calls: Arc<AtomicUsize>) -> Upstream {
+        Upstream {
+            calls,
+            etag: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_range_in_chunks() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = ServiceBuilder::new()
+            .layer(RangeFetchLayer::new(16).concurrency(2))
+            .service(upstream(calls.clone()));
+
+        let req = Request::builder()
+            .header(header::RANGE, "bytes=10-69")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 10-69/100");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "60");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &DATA[10..70]);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn fetches_open_ended_range() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = RangeFetch::new(upstream(calls.clone()), 32);
+
+        let req = Request::builder()
+            .header(header::RANGE, "bytes=20-")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 20-99/100");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &DATA[20..]);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn fails_if_resource_changes() {
+        let upstream = upstream(Arc::new(AtomicUsize::new(0)));
+        *upstream.etag.lock().unwrap() = Some("\"v1\"");
+        let svc = RangeFetch::new(upstream.clone(), 16);
+
+        let range = || {
+            Request::builder()
+                .header(header::RANGE, "bytes=0-63")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // unchanged, the chunks are requested with the entity tag of the first chunk
+        let res = svc.call(range()).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &DATA[..64]);
+
+        // changed after the first chunk, upstream returns the whole new version
+        let res = svc.call(range()).await.unwrap();
+        *upstream.etag.lock().unwrap() = Some("\"v2\"");
+        let err = res.into_body().collect().await.unwrap_err();
+        assert!(err.is::<InvalidChunk>());
+
+        // upstream ignoring `If-Range` returns a chunk of another version
+        let svc = RangeFetch::new(
+            tower_async::service_fn(move |mut req: Request<Body>| {
+                let upstream = upstream.clone();
+                async move {
+                    let etag = if req.headers_mut().remove(header::IF_RANGE).is_some() {
+                        "\"v3\""
+                    } else {
+                        "\"v2\""
+                    };
+                    *upstream.etag.lock().unwrap() = Some(etag);
+                    upstream.call(req).await
+                }
+            }),
+            16,
+        );
+        let res = svc.call(range()).await.unwrap();
+        let err = res.into_body().collect().await.unwrap_err();
+        assert!(err.is::<ResourceChanged>());
+    }
+
+    #[tokio::test]
+    async fn passes_through_other_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = RangeFetch::new(upstream(calls.clone()), 16);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &DATA[..]);
+
+        let req = Request::builder()
+            .header(header::RANGE, "bytes=0-9")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 0-9/100");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &DATA[..10]);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
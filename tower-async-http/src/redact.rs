@@ -0,0 +1,402 @@
+//! Redaction of sensitive data in headers and bodies.
+//!
+//! A [`Redactor`] defines what data is sensitive once. The [`Trace`] middleware redacts the
+//! headers it records on spans with [`DefaultMakeSpan::redactor`], which is the only middleware
+//! of this crate consuming a [`Redactor`] so far. Code logging or archiving requests and
+//! responses on its own, such as bodies copied by the [`TeeBody`] middleware, can hide the same
+//! data by calling [`Redactor::headers`] and [`Redactor::body`] itself. Note that bodies are
+//! redacted as a whole, they must be buffered first. It composes three kinds of rules:
+//!
+//! - **Header rules**: a deny list of headers whose values are redacted, which by default
+//!   contains the headers carrying credentials (`Authorization`, `Proxy-Authorization`,
+//!   `Cookie` and `Set-Cookie`), or an allow list of headers whose values are kept, redacting
+//!   all others. Header values marked as [sensitive] are always redacted.
+//! - **JSON path rules**: values of JSON bodies at a given path, such as `user.password`,
+//!   are replaced. A `*` segment matches any key of an object or any element of an array,
+//!   such as `tokens.*.secret`, and a leading `$.` is optional.
+//! - **Patterns**: matches of a regular expression, such as card numbers or e-mail addresses,
+//!   are masked in header values that are kept, in JSON strings and in text bodies.
+//!
+//! Redacted values are replaced by `[REDACTED]`, which can be changed with [`Redactor::mask`].
+//!
+//! # Example
+//!
+//! ```
+//! use http::{header, HeaderMap, HeaderValue};
+//! use regex::Regex;
+//! use tower_async_http::redact::Redactor;
+//!
+//! let redactor = Redactor::new()
+//!     .deny_header(header::HeaderName::from_static("x-api-key"))
+//!     .json_path("user.password")
+//!     .pattern(Regex::new(r"\b\d{4}-\d{4}-\d{4}-\d{4}\b").unwrap());
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("x-api-key", HeaderValue::from_static("secret"));
+//! headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+//! assert_eq!(
+//!     format!("{:?}", redactor.headers(&headers)),
+//!     r#"{"x-api-key": "[REDACTED]", "accept": "application/json"}"#,
+//! );
+//!
+//! let body = redactor.body(br#"{"user":{"name":"ferris","password":"hunter2"},"card":"1234-5678-9012-3456"}"#);
+//! assert_eq!(
+//!     body,
+//!     r#"{"card":"[REDACTED]","user":{"name":"ferris","password":"[REDACTED]"}}"#,
+//! );
+//! ```
+//!
+//! [`Trace`]: crate::trace::Trace
+//! [`DefaultMakeSpan::redactor`]: crate::trace::DefaultMakeSpan::redactor
+//! [`TeeBody`]: crate::tee_body::TeeBodyService
+//! [sensitive]: http::HeaderValue::set_sensitive
+
+use bytes::Bytes;
+use http::{header, header::HeaderName, HeaderMap, HeaderValue};
+use regex::{NoExpand, Regex};
+use serde_json::Value;
+use std::{borrow::Cow, fmt, sync::Arc};
+
+const DEFAULT_MASK: &str = "[REDACTED]";
+
+/// Composable redaction rules for headers and bodies.
+///
+/// Cloning a [`Redactor`] is cheap, such that it can be shared by multiple middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Clone)]
+struct Inner {
+    headers: HeaderRule,
+    json_paths: Vec<Vec<String>>,
+    patterns: Vec<Regex>,
+    mask: Cow<'static, str>,
+}
+
+#[derive(Debug, Clone)]
+enum HeaderRule {
+    Deny(Vec<HeaderName>),
+    Allow(Vec<HeaderName>),
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// Create a new [`Redactor`], redacting the values of the headers carrying credentials.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                headers: HeaderRule::Deny(vec![
+                    header::AUTHORIZATION,
+                    header::PROXY_AUTHORIZATION,
+                    header::COOKIE,
+                    header::SET_COOKIE,
+                ]),
+                json_paths: Vec::new(),
+                patterns: Vec::new(),
+                mask: Cow::Borrowed(DEFAULT_MASK),
+            }),
+        }
+    }
+
+    /// Redact the values of the given header.
+    ///
+    /// Has no effect if an allow list is used, as all headers that aren't allowed
+    /// are redacted already.
+    pub fn deny_header(mut self, name: HeaderName) -> Self {
+        if let HeaderRule::Deny(names) = &mut Arc::make_mut(&mut self.inner).headers {
+            names.push(name);
+        }
+        self
+    }
+
+    /// Only keep the values of the given headers, redacting the values of all other headers.
+    ///
+    /// Replaces the deny list, including the default one.
+    pub fn allow_headers<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        let inner = Arc::make_mut(&mut self.inner);
+        match &mut inner.headers {
+            HeaderRule::Allow(allowed) => allowed.extend(names),
+            headers @ HeaderRule::Deny(_) => {
+                *headers = HeaderRule::Allow(names.into_iter().collect())
+            }
+        }
+        self
+    }
+
+    /// Redact the values of JSON bodies at the given path.
+    ///
+    /// The path consists of object keys and array indices separated by dots, where `*`
+    /// matches any key or index, e.g. `user.password` or `$.tokens.*.secret`.
+    pub fn json_path(mut self, path: &str) -> Self {
+        let path = path.strip_prefix("$.").unwrap_or(path);
+        let segments = path
+            .split('.')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Arc::make_mut(&mut self.inner).json_paths.push(segments);
+        self
+    }
+
+    /// Mask all matches of the given pattern in header values, JSON strings and text bodies.
+    pub fn pattern(mut self, pattern: Regex) -> Self {
+        Arc::make_mut(&mut self.inner).patterns.push(pattern);
+        self
+    }
+
+    /// Set the text that replaces redacted values.
+    ///
+    /// Defaults to `[REDACTED]`.
+    pub fn mask(mut self, mask: impl Into<Cow<'static, str>>) -> Self {
+        Arc::make_mut(&mut self.inner).mask = mask.into();
+        self
+    }
+
+    /// Returns whether the values of the given header are redacted.
+    pub fn is_header_redacted(&self, name: &HeaderName) -> bool {
+        match &self.inner.headers {
+            HeaderRule::Deny(names) => names.contains(name),
+            HeaderRule::Allow(names) => !names.contains(name),
+        }
+    }
+
+    /// Redact a header value.
+    pub fn header_value<'a>(&'a self, name: &HeaderName, value: &'a HeaderValue) -> Cow<'a, str> {
+        if value.is_sensitive() || self.is_header_redacted(name) {
+            return Cow::Borrowed(&self.inner.mask);
+        }
+        match value.to_str() {
+            Ok(value) => self.text(value),
+            Err(_) => Cow::Owned(format!("{value:?}")),
+        }
+    }
+
+    /// Returns a value that formats the given headers with their values redacted,
+    /// with its [`Debug`](fmt::Debug) implementation.
+    pub fn headers<'a>(&'a self, headers: &'a HeaderMap) -> RedactedHeaders<'a> {
+        RedactedHeaders {
+            redactor: self,
+            headers,
+            names: None,
+        }
+    }
+
+    /// Mask the matches of the patterns in the given text.
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.inner.patterns {
+            if let Cow::Owned(masked) = pattern.replace_all(&text, NoExpand(&self.inner.mask)) {
+                text = Cow::Owned(masked);
+            }
+        }
+        text
+    }
+
+    /// Redact a JSON value in place, applying the JSON path rules and patterns.
+    pub fn json(&self, value: &mut Value) {
+        for path in &self.inner.json_paths {
+            redact_path(value, path, &self.inner.mask);
+        }
+        if !self.inner.patterns.is_empty() {
+            self.mask_strings(value);
+        }
+    }
+
+    /// Redact a body.
+    ///
+    /// Bodies that are valid JSON are redacted with [`Redactor::json`], other UTF-8 bodies
+    /// with [`Redactor::text`]. Binary bodies are returned unchanged.
+    pub fn body(&self, body: &[u8]) -> Bytes {
+        if let Ok(mut value) = serde_json::from_slice::<Value>(body) {
+            self.json(&mut value);
+            return Bytes::from(value.to_string());
+        }
+        match std::str::from_utf8(body) {
+            Ok(text) => match self.text(text) {
+                Cow::Borrowed(_) => Bytes::copy_from_slice(body),
+                Cow::Owned(text) => Bytes::from(text),
+            },
+            Err(_) => Bytes::copy_from_slice(body),
+        }
+    }
+
+    fn mask_strings(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => {
+                if let Cow::Owned(masked) = self.text(text) {
+                    *text = masked;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.mask_strings(value)),
+            Value::Object(map) => map.values_mut().for_each(|value| self.mask_strings(value)),
+            _ => {}
+        }
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String], mask: &str) {
+    let Some((segment, rest)) = path.split_first() else {
+        *value = Value::String(mask.to_owned());
+        return;
+    };
+    match value {
+        Value::Object(map) if segment == "*" => {
+            map.values_mut()
+                .for_each(|value| redact_path(value, rest, mask));
+        }
+        Value::Object(map) => {
+            if let Some(value) = map.get_mut(segment) {
+                redact_path(value, rest, mask);
+            }
+        }
+        Value::Array(values) if segment == "*" => {
+            values
+                .iter_mut()
+                .for_each(|value| redact_path(value, rest, mask));
+        }
+        Value::Array(values) => {
+            if let Some(value) = segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| values.get_mut(index))
+            {
+                redact_path(value, rest, mask);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Headers formatted with their values redacted by a [`Redactor`].
+///
+/// Created by [`Redactor::headers`].
+pub struct RedactedHeaders<'a> {
+    redactor: &'a Redactor,
+    headers: &'a HeaderMap,
+    names: Option<&'a [HeaderName]>,
+}
+
+impl<'a> RedactedHeaders<'a> {
+    /// Only include the given headers.
+    pub fn only(mut self, names: &'a [HeaderName]) -> Self {
+        self.names = Some(names);
+        self
+    }
+}
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        match self.names {
+            Some(names) => {
+                for name in names {
+                    for value in self.headers.get_all(name) {
+                        map.entry(name, &self.redactor.header_value(name, value));
+                    }
+                }
+            }
+            None => {
+                for (name, value) in self.headers {
+                    map.entry(name, &self.redactor.header_value(name, value));
+                }
+            }
+        }
+        map.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn redacts_denied_and_sensitive_headers() {
+        let redactor = Redactor::new().pattern(Regex::new(r"\d{3}-\d{4}").unwrap());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("session=abc"));
+        let mut sensitive = HeaderValue::from_static("secret");
+        sensitive.set_sensitive(true);
+        headers.insert("x-token", sensitive);
+        headers.insert("x-phone", HeaderValue::from_static("call 555-1234"));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+
+        assert_eq!(
+            format!("{:?}", redactor.headers(&headers)),
+            r#"{"cookie": "[REDACTED]", "x-token": "[REDACTED]", "x-phone": "call [REDACTED]", "accept": "*/*"}"#,
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                redactor
+                    .headers(&headers)
+                    .only(&[header::ACCEPT, header::USER_AGENT])
+            ),
+            r#"{"accept": "*/*"}"#,
+        );
+    }
+
+    #[test]
+    fn allow_list_redacts_other_headers() {
+        let redactor = Redactor::new().allow_headers([header::ACCEPT]).mask("***");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl"));
+
+        assert_eq!(
+            format!("{:?}", redactor.headers(&headers)),
+            r#"{"accept": "*/*", "user-agent": "***"}"#,
+        );
+    }
+
+    #[test]
+    fn redacts_json_paths() {
+        let redactor = Redactor::new()
+            .json_path("$.user.password")
+            .json_path("tokens.*.secret")
+            .json_path("cards.0");
+
+        let mut value = json!({
+            "user": { "name": "ferris", "password": "hunter2" },
+            "tokens": [{ "id": 1, "secret": "a" }, { "id": 2, "secret": "b" }],
+            "cards": ["1234", "5678"],
+        });
+        redactor.json(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "user": { "name": "ferris", "password": "[REDACTED]" },
+                "tokens": [{ "id": 1, "secret": "[REDACTED]" }, { "id": 2, "secret": "[REDACTED]" }],
+                "cards": ["[REDACTED]", "5678"],
+            })
+        );
+    }
+
+    #[test]
+    fn masks_patterns_in_text_bodies() {
+        let redactor = Redactor::new().pattern(Regex::new(r"[\w.]+@[\w.]+").unwrap());
+
+        assert_eq!(
+            redactor.body(b"contact ferris@example.com"),
+            "contact [REDACTED]"
+        );
+        assert_eq!(redactor.body(b"nothing to hide"), "nothing to hide");
+        assert_eq!(redactor.body(&[0xff, 0xfe]), &[0xff, 0xfe][..]);
+    }
+}
@@ -0,0 +1,436 @@
+//! Attach documentation metadata to routes, and generate an OpenAPI skeleton from it.
+//!
+//! Routers know which route template matched a request, such as `/users/{id}`, but the
+//! middleware wrapping the router only sees the URI. The [`SetRouteMetadata`] middleware wraps
+//! the service of a single route, and inserts its [`RouteMetadata`], the method, the path
+//! template and the operation id of the route, into the extensions of both the request and the
+//! response. Observability layers can then label requests by route template instead of by URI,
+//! whose number of values is unbounded. With the `trace` feature enabled, a [`MatchedRoute`] is
+//! inserted into the request as well, which [`Trace`] captures in its `RequestMetadata`.
+//!
+//! The layers are created using a [`RouteCatalog`], which collects the metadata of all the
+//! routes as the router is configured. Once it is, [`RouteCatalog::openapi`] emits a minimal
+//! [OpenAPI] document describing the operations of the routes, which can be served or
+//! completed by other tools, without depending on a full web framework.
+//!
+//! [`MatchedRoute`]: crate::trace::MatchedRoute
+//! [`Trace`]: crate::trace::Trace
+//! [OpenAPI]: https://spec.openapis.org/oas/v3.0.3
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Method, Request, Response};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{Layer, Service, service_fn};
+//! use tower_async_http::route_metadata::{RouteCatalog, RouteMetadata};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let catalog = RouteCatalog::new("users", "1.0.0");
+//!
+//! // Wrap the service of each route while configuring the router.
+//! let get_user = catalog
+//!     .layer(RouteMetadata::new(Method::GET, "/users/{id}").operation_id("getUser"))
+//!     .layer(service_fn(|_: Request<Full<Bytes>>| async {
+//!         Ok::<_, Infallible>(Response::new(Full::<Bytes>::default()))
+//!     }));
+//!
+//! let res = get_user.call(Request::get("/users/42").body(Full::default())?).await?;
+//! let route = res.extensions().get::<RouteMetadata>().unwrap();
+//! assert_eq!(route.path(), "/users/{id}");
+//!
+//! let openapi = catalog.openapi();
+//! assert_eq!(openapi["paths"]["/users/{id}"]["get"]["operationId"], "getUser");
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{Method, Request, Response};
+use serde_json::{json, Map, Value};
+use std::sync::{Arc, Mutex};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Documentation metadata of a route.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteMetadata {
+    method: Method,
+    path: Arc<str>,
+    operation_id: Option<Arc<str>>,
+    summary: Option<Arc<str>>,
+}
+
+impl RouteMetadata {
+    /// Create a new [`RouteMetadata`] for the route with the given method and path template.
+    ///
+    /// Path parameters are written as `{name}`, like in OpenAPI, or as `:name`
+    /// and `*name`, like in many routers.
+    pub fn new(method: Method, path: impl Into<Arc<str>>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            operation_id: None,
+            summary: None,
+        }
+    }
+
+    /// Set the operation id of the route, which uniquely identifies it.
+    pub fn operation_id(mut self, operation_id: impl Into<Arc<str>>) -> Self {
+        self.operation_id = Some(operation_id.into());
+        self
+    }
+
+    /// Set a short summary of what the route does.
+    pub fn summary(mut self, summary: impl Into<Arc<str>>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    /// Returns the method of the route.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the path template of the route, as it was given.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the operation id of the route, if any.
+    pub fn get_operation_id(&self) -> Option<&str> {
+        self.operation_id.as_deref()
+    }
+
+    /// Returns the summary of the route, if any.
+    pub fn get_summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+}
+
+/// Collects the [`RouteMetadata`] of routes, to generate an OpenAPI document.
+///
+/// Clones of a catalog share the same routes.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct RouteCatalog {
+    title: Arc<str>,
+    version: Arc<str>,
+    routes: Arc<Mutex<Vec<RouteMetadata>>>,
+}
+
+impl RouteCatalog {
+    /// Create a new, empty [`RouteCatalog`] for the API with the given title and version.
+    pub fn new(title: impl Into<Arc<str>>, version: impl Into<Arc<str>>) -> Self {
+        Self {
+            title: title.into(),
+            version: version.into(),
+            routes: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Add a route to the catalog, and return a [`SetRouteMetadataLayer`]
+    /// to wrap the service of the route with.
+    ///
+    /// If the catalog already has a route with the same method and path,
+    /// it is replaced.
+    pub fn layer(&self, metadata: RouteMetadata) -> SetRouteMetadataLayer {
+        self.insert(metadata.clone());
+        SetRouteMetadataLayer::new(metadata)
+    }
+
+    /// Add a route to the catalog, without wrapping a service.
+    ///
+    /// If the catalog already has a route with the same method and path,
+    /// it is replaced.
+    pub fn insert(&self, metadata: RouteMetadata) {
+        let mut routes = self.routes.lock().unwrap();
+        match routes
+            .iter_mut()
+            .find(|route| route.method == metadata.method && route.path == metadata.path)
+        {
+            Some(route) => *route = metadata,
+            None => routes.push(metadata),
+        }
+    }
+
+    /// Returns the routes of the catalog, in the order they were added.
+    pub fn routes(&self) -> Vec<RouteMetadata> {
+        self.routes.lock().unwrap().clone()
+    }
+
+    /// Generate a minimal OpenAPI 3.0 document describing the routes of the catalog.
+    ///
+    /// Every route becomes an operation with its operation id, summary and path
+    /// parameters, and a `default` response.
+    pub fn openapi(&self) -> Value {
+        let mut paths = Map::new();
+        for route in self.routes.lock().unwrap().iter() {
+            let (path, parameters) = openapi_path(&route.path);
+
+            let mut operation = Map::new();
+            if let Some(operation_id) = &route.operation_id {
+                operation.insert("operationId".to_owned(), json!(operation_id.as_ref()));
+            }
+            if let Some(summary) = &route.summary {
+                operation.insert("summary".to_owned(), json!(summary.as_ref()));
+            }
+            if !parameters.is_empty() {
+                let parameters = parameters
+                    .into_iter()
+                    .map(|name| {
+                        json!({
+                            "name": name,
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "string" },
+                        })
+                    })
+                    .collect();
+                operation.insert("parameters".to_owned(), Value::Array(parameters));
+            }
+            operation.insert(
+                "responses".to_owned(),
+                json!({ "default": { "description": "Default response" } }),
+            );
+
+            paths
+                .entry(path)
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .unwrap()
+                .insert(
+                    route.method.as_str().to_ascii_lowercase(),
+                    Value::Object(operation),
+                );
+        }
+
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": self.title.as_ref(),
+                "version": self.version.as_ref(),
+            },
+            "paths": paths,
+        })
+    }
+}
+
+/// Converts a path template to the OpenAPI syntax, returning the names of its parameters.
+fn openapi_path(template: &str) -> (String, Vec<String>) {
+    let mut parameters = Vec::new();
+    let segments: Vec<_> = template
+        .split('/')
+        .map(|segment| {
+            let name = segment
+                .strip_prefix(':')
+                .or_else(|| segment.strip_prefix('*'))
+                .or_else(|| {
+                    segment
+                        .strip_prefix('{')
+                        .and_then(|segment| segment.strip_suffix('}'))
+                        .map(|name| name.trim_start_matches('*'))
+                });
+            match name {
+                Some(name) if !name.is_empty() => {
+                    parameters.push(name.to_owned());
+                    format!("{{{name}}}")
+                }
+                _ => segment.to_owned(),
+            }
+        })
+        .collect();
+    (segments.join("/"), parameters)
+}
+
+/// Layer that applies [`SetRouteMetadata`] which inserts the [`RouteMetadata`] of a route
+/// into the extensions of requests and responses.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct SetRouteMetadataLayer {
+    metadata: RouteMetadata,
+}
+
+impl SetRouteMetadataLayer {
+    /// Create a new [`SetRouteMetadataLayer`].
+    ///
+    /// Use [`RouteCatalog::layer`] to have the route included in the generated OpenAPI document.
+    pub fn new(metadata: RouteMetadata) -> Self {
+        Self { metadata }
+    }
+}
+
+impl<S> Layer<S> for SetRouteMetadataLayer {
+    type Service = SetRouteMetadata<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetRouteMetadata {
+            inner,
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+/// Middleware that inserts the [`RouteMetadata`] of a route into the extensions of
+/// requests and responses.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct SetRouteMetadata<S> {
+    inner: S,
+    metadata: RouteMetadata,
+}
+
+impl<S> SetRouteMetadata<S> {
+    /// Create a new [`SetRouteMetadata`].
+    pub fn new(inner: S, metadata: RouteMetadata) -> Self {
+        SetRouteMetadataLayer::new(metadata).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SetRouteMetadata` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(metadata: RouteMetadata) -> SetRouteMetadataLayer {
+        SetRouteMetadataLayer::new(metadata)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetRouteMetadata<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        #[cfg(feature = "trace")]
+        req.extensions_mut()
+            .insert(crate::trace::MatchedRoute::new(self.metadata.path.clone()));
+        req.extensions_mut().insert(self.metadata.clone());
+
+        let mut res = self.inner.call(req).await?;
+        res.extensions_mut().insert(self.metadata.clone());
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn inserts_metadata_into_request_and_response() {
+        let metadata = RouteMetadata::new(Method::POST, "/users").operation_id("createUser");
+
+        let svc = ServiceBuilder::new()
+            .layer(SetRouteMetadataLayer::new(metadata.clone()))
+            .service_fn(|req: Request<Body>| async move {
+                let route = req.extensions().get::<RouteMetadata>().unwrap();
+                assert_eq!(route.get_operation_id(), Some("createUser"));
+                #[cfg(feature = "trace")]
+                assert_eq!(
+                    req.extensions()
+                        .get::<crate::trace::MatchedRoute>()
+                        .unwrap()
+                        .as_str(),
+                    "/users"
+                );
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc
+            .call(Request::post("/users").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.extensions().get::<RouteMetadata>(), Some(&metadata));
+    }
+
+    #[test]
+    fn converts_path_templates() {
+        assert_eq!(openapi_path("/"), ("/".to_owned(), vec![]));
+        assert_eq!(
+            openapi_path("/users/:id/posts/{post_id}"),
+            (
+                "/users/{id}/posts/{post_id}".to_owned(),
+                vec!["id".to_owned(), "post_id".to_owned()]
+            )
+        );
+        assert_eq!(
+            openapi_path("/files/*path"),
+            ("/files/{path}".to_owned(), vec!["path".to_owned()])
+        );
+        assert_eq!(
+            openapi_path("/files/{*path}"),
+            ("/files/{path}".to_owned(), vec!["path".to_owned()])
+        );
+    }
+
+    #[test]
+    fn generates_openapi_document() {
+        let catalog = RouteCatalog::new("users", "1.0.0");
+        let svc = service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        });
+        let _list = catalog
+            .layer(RouteMetadata::new(Method::GET, "/users").summary("List users"))
+            .layer(svc);
+        let _get = catalog
+            .layer(RouteMetadata::new(Method::GET, "/users/:id").operation_id("getUser"))
+            .layer(svc);
+        catalog
+            .clone()
+            .insert(RouteMetadata::new(Method::DELETE, "/users/{id}").operation_id("deleteUser"));
+        catalog.insert(RouteMetadata::new(Method::GET, "/users").operation_id("listUsers"));
+
+        assert_eq!(catalog.routes().len(), 3);
+        assert_eq!(
+            catalog.openapi(),
+            json!({
+                "openapi": "3.0.3",
+                "info": { "title": "users", "version": "1.0.0" },
+                "paths": {
+                    "/users": {
+                        "get": {
+                            "operationId": "listUsers",
+                            "responses": { "default": { "description": "Default response" } },
+                        },
+                    },
+                    "/users/{id}": {
+                        "get": {
+                            "operationId": "getUser",
+                            "parameters": [{
+                                "name": "id",
+                                "in": "path",
+                                "required": true,
+                                "schema": { "type": "string" },
+                            }],
+                            "responses": { "default": { "description": "Default response" } },
+                        },
+                        "delete": {
+                            "operationId": "deleteUser",
+                            "parameters": [{
+                                "name": "id",
+                                "in": "path",
+                                "required": true,
+                                "schema": { "type": "string" },
+                            }],
+                            "responses": { "default": { "description": "Default response" } },
+                        },
+                    },
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! Middleware that adds a `Server-Timing` header to responses.
+//!
+//! The [`Server-Timing`] header communicates how the server spent its time on a request to the
+//! client, where browsers show it in their developer tools next to the timings of the request,
+//! making it possible to correlate frontend and backend performance without a separate tracing
+//! backend.
+//!
+//! The header is composed from:
+//!
+//! - the [own] time of every layer added after [`ServiceBuilder::timed`], if the
+//!   [`ServerTimingLayer`] is the first layer added to the timed builder, such that the layers
+//!   below it completed before the header is written;
+//! - the marks recorded by handlers through the [`ServerTimingMarks`] request extension;
+//! - the budget left of the [`Deadline`] of the request, if enabled using
+//!   [`ServerTimingLayer::remaining_deadline`];
+//! - the total time spent below the [`ServerTiming`] middleware.
+//!
+//! Metric names are restricted to the characters allowed by the header, other characters are
+//! replaced by an underscore. Layers without an explicit name are named after their type,
+//! without its path and generic parameters.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{Service, ServiceBuilder};
+//! use tower_async_http::server_timing::{ServerTimingLayer, ServerTimingMarks};
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let marks = req.extensions().get::<ServerTimingMarks>().unwrap();
+//!
+//!     let timer = marks.start("db");
+//!     // query the database...
+//!     drop(timer);
+//!
+//!     marks.record_with_description("cache", Duration::from_millis(2), "miss");
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     .timed()
+//!     .layer(ServerTimingLayer::new())
+//!     .named_layer("timeout", tower_async::timeout::TimeoutLayer::new(Duration::from_secs(10)))
+//!     .service_fn(handle);
+//!
+//! let response = svc.call(Request::new(Full::default())).await?;
+//!
+//! // e.g. `timeout;dur=0.012, db;dur=0.001, cache;dur=2;desc="miss", total;dur=2.153`
+//! let header = response.headers()["server-timing"].to_str()?;
+//! assert!(header.starts_with("timeout;dur="));
+//! assert!(header.contains("cache;dur=2;desc=\"miss\""));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Server-Timing`]: https://www.w3.org/TR/server-timing/
+//! [own]: tower_async::timing::LayerTiming::own
+//! [`ServiceBuilder::timed`]: tower_async::ServiceBuilder::timed
+//! [`Deadline`]: tower_async::timeout::Deadline
+
+use http::{header::HeaderName, HeaderValue, Request, Response};
+use std::{
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tower_async::{timeout::Deadline, timing::TimingReport};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Layer that applies the [`ServerTiming`] middleware.
+///
+/// See the [module docs](crate::server_timing) for more details.
+#[derive(Clone, Copy, Debug)]
+pub struct ServerTimingLayer {
+    config: Config,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Config {
+    layer_timings: bool,
+    remaining_deadline: bool,
+    total: bool,
+}
+
+impl Default for ServerTimingLayer {
+    fn default() -> Self {
+        Self {
+            config: Config {
+                layer_timings: true,
+                remaining_deadline: false,
+                total: true,
+            },
+        }
+    }
+}
+
+impl ServerTimingLayer {
+    /// Create a new [`ServerTimingLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include the [own] time of the timed layers below the middleware.
+    ///
+    /// Enabled by default.
+    ///
+    /// [own]: tower_async::timing::LayerTiming::own
+    pub fn layer_timings(mut self, enabled: bool) -> Self {
+        self.config.layer_timings = enabled;
+        self
+    }
+
+    /// Include the budget left of the [`Deadline`] of the request when responding,
+    /// as the `deadline` metric.
+    ///
+    /// Disabled by default.
+    ///
+    /// [`Deadline`]: tower_async::timeout::Deadline
+    pub fn remaining_deadline(mut self, enabled: bool) -> Self {
+        self.config.remaining_deadline = enabled;
+        self
+    }
+
+    /// Include the total time spent below the middleware, as the `total` metric.
+    ///
+    /// Enabled by default.
+    pub fn total(mut self, enabled: bool) -> Self {
+        self.config.total = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for ServerTimingLayer {
+    type Service = ServerTiming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ServerTiming {
+            inner,
+            config: self.config,
+        }
+    }
+}
+
+/// Middleware that adds a `Server-Timing` header to responses.
+///
+/// See the [module docs](crate::server_timing) for more details.
+#[derive(Clone, Copy, Debug)]
+pub struct ServerTiming<S> {
+    inner: S,
+    config: Config,
+}
+
+impl<S> ServerTiming<S> {
+    /// Create a new [`ServerTiming`], with the defaults of [`ServerTimingLayer::new`].
+    pub fn new(inner: S) -> Self {
+        ServerTimingLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `ServerTiming` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> ServerTimingLayer {
+        ServerTimingLayer::new()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ServerTiming<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let marks = ServerTimingMarks::default();
+        req.extensions_mut().insert(marks.clone());
+
+        let start = Instant::now();
+        let mut res = self.inner.call(req).await?;
+        let total = start.elapsed();
+
+        let mut header = String::new();
+        if self.config.layer_timings {
+            if let Some(report) = TimingReport::current() {
+                for layer in report.layers() {
+                    write_metric(&mut header, layer_name(layer.name()), layer.own(), None);
+                }
+            }
+        }
+        for mark in marks.marks.lock().unwrap().iter() {
+            write_metric(
+                &mut header,
+                mark.name,
+                mark.duration,
+                mark.description.as_deref(),
+            );
+        }
+        if self.config.remaining_deadline {
+            if let Some(deadline) = Deadline::current() {
+                write_metric(&mut header, "deadline", deadline.remaining(), None);
+            }
+        }
+        if self.config.total {
+            write_metric(&mut header, "total", total, None);
+        }
+
+        if !header.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&header) {
+                res.headers_mut().append(SERVER_TIMING, value);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Marks recorded by handlers, added to the `Server-Timing` header of the response.
+///
+/// The [`ServerTiming`] middleware inserts the marks into the extensions of every request.
+///
+/// See the [module docs](crate::server_timing) for an example.
+#[derive(Clone, Debug, Default)]
+pub struct ServerTimingMarks {
+    marks: Arc<Mutex<Vec<Mark>>>,
+}
+
+#[derive(Debug)]
+struct Mark {
+    name: &'static str,
+    duration: Duration,
+    description: Option<String>,
+}
+
+impl ServerTimingMarks {
+    /// Record that `name` took `duration`.
+    pub fn record(&self, name: &'static str, duration: Duration) {
+        self.push(name, duration, None);
+    }
+
+    /// Record that `name` took `duration`, with a human readable description.
+    pub fn record_with_description(
+        &self,
+        name: &'static str,
+        duration: Duration,
+        description: impl Into<String>,
+    ) {
+        self.push(name, duration, Some(description.into()));
+    }
+
+    /// Start timing `name`, which is recorded once the returned [`MarkTimer`] is dropped.
+    pub fn start(&self, name: &'static str) -> MarkTimer {
+        MarkTimer {
+            marks: self.clone(),
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    fn push(&self, name: &'static str, duration: Duration, description: Option<String>) {
+        self.marks.lock().unwrap().push(Mark {
+            name,
+            duration,
+            description,
+        });
+    }
+}
+
+/// Records the time elapsed since it was created by [`ServerTimingMarks::start`],
+/// once dropped.
+#[must_use = "the mark is recorded once the timer is dropped"]
+#[derive(Debug)]
+pub struct MarkTimer {
+    marks: ServerTimingMarks,
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for MarkTimer {
+    fn drop(&mut self) {
+        self.marks.record(self.name, self.start.elapsed());
+    }
+}
+
+/// Returns the name of a layer without the path and generic parameters of its type name.
+fn layer_name(name: &str) -> &str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+fn write_metric(header: &mut String, name: &str, duration: Duration, description: Option<&str>) {
+    if !header.is_empty() {
+        header.push_str(", ");
+    }
+
+    if name.is_empty() {
+        header.push('_');
+    }
+    header.extend(name.chars().map(|c| if is_tchar(c) { c } else { '_' }));
+
+    // milliseconds, with up to microsecond precision
+    let micros = duration.as_micros();
+    let _ = write!(header, ";dur={}", micros / 1000);
+    let fraction = micros % 1000;
+    if fraction > 0 {
+        let fraction = format!("{fraction:03}");
+        let _ = write!(header, ".{}", fraction.trim_end_matches('0'));
+    }
+
+    if let Some(description) = description {
+        header.push_str(";desc=\"");
+        for c in description
+            .chars()
+            .filter(|c| *c == ' ' || c.is_ascii_graphic())
+        {
+            if c == '"' || c == '\\' {
+                header.push('\\');
+            }
+            header.push(c);
+        }
+        header.push('"');
+    }
+}
+
+/// Returns `true` for the characters allowed in a token, such as a metric name.
+fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let marks = req.extensions().get::<ServerTimingMarks>().unwrap();
+        marks.record("db", Duration::from_micros(12_500));
+        marks.record_with_description("cache:hit", Duration::from_millis(3), "say \"hi\"\n");
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn metrics(res: &Response<Body>) -> Vec<String> {
+        res.headers()[SERVER_TIMING]
+            .to_str()
+            .unwrap()
+            .split(", ")
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn records_marks() {
+        let svc = ServerTiming::new(service_fn(handle));
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+
+        let metrics = metrics(&res);
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(metrics[0], "db;dur=12.5");
+        assert_eq!(metrics[1], r#"cache_hit;dur=3;desc="say \"hi\"""#);
+        assert!(metrics[2].starts_with("total;dur="), "{}", metrics[2]);
+    }
+
+    #[tokio::test]
+    async fn includes_layer_timings_and_deadline() {
+        let svc = ServiceBuilder::new()
+            .timed()
+            .layer(
+                ServerTimingLayer::new()
+                    .remaining_deadline(true)
+                    .total(false),
+            )
+            .named_layer("auth", tower_async::layer::util::Identity::new())
+            .layer(tower_async::timeout::TimeoutLayer::new(
+                Duration::from_secs(10),
+            ))
+            .service_fn(handle);
+
+        let res = Deadline::after(Duration::from_secs(60))
+            .scope(svc.call(Request::new(Body::empty())))
+            .await
+            .unwrap();
+
+        let names: Vec<_> = metrics(&res)
+            .iter()
+            .map(|metric| metric.split(';').next().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            names,
+            ["auth", "TimeoutLayer", "db", "cache_hit", "deadline"]
+        );
+    }
+
+    #[test]
+    fn formats_durations() {
+        let mut header = String::new();
+        write_metric(&mut header, "a", Duration::from_millis(1), None);
+        write_metric(&mut header, "", Duration::from_nanos(1_230_999), None);
+        write_metric(&mut header, "c", Duration::ZERO, Some(""));
+        assert_eq!(header, r#"a;dur=1, _;dur=1.23, c;dur=0;desc="""#);
+    }
+}
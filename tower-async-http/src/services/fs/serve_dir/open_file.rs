@@ -2,7 +2,7 @@ use super::{
     headers::{IfModifiedSince, IfUnmodifiedSince, LastModified},
     ServeVariant,
 };
-use crate::content_encoding::{Encoding, QValue};
+use crate::content_encoding::{AcceptedEncodings, Encoding};
 use bytes::Bytes;
 use http::{header, HeaderValue, Method, Request, Uri};
 use http_body_util::Empty;
@@ -42,7 +42,7 @@ pub(super) async fn open_file(
     variant: ServeVariant,
     mut path_to_file: PathBuf,
     req: Request<Empty<Bytes>>,
-    negotiated_encodings: Vec<(Encoding, QValue)>,
+    negotiated_encodings: AcceptedEncodings,
     range_header: Option<String>,
     buf_chunk_size: usize,
 ) -> io::Result<OpenFileOutput> {
@@ -174,9 +174,12 @@ fn check_modified_headers(
 // to the corresponding file extension for the encoding.
 fn preferred_encoding(
     path: &mut PathBuf,
-    negotiated_encoding: &[(Encoding, QValue)],
+    negotiated_encoding: &AcceptedEncodings,
 ) -> Option<Encoding> {
-    let preferred_encoding = Encoding::preferred_encoding(negotiated_encoding);
+    // the uncompressed file is used when identity is preferred
+    let preferred_encoding = negotiated_encoding
+        .preferred()
+        .filter(|encoding| *encoding != Encoding::Identity);
 
     if let Some(file_extension) =
         preferred_encoding.and_then(|encoding| encoding.to_file_extension())
@@ -201,7 +204,7 @@ fn preferred_encoding(
 // file the uncompressed file is used as a fallback.
 async fn open_file_with_fallback(
     mut path: PathBuf,
-    mut negotiated_encoding: Vec<(Encoding, QValue)>,
+    mut negotiated_encoding: AcceptedEncodings,
 ) -> io::Result<(File, Option<Encoding>)> {
     let (file, encoding) = loop {
         // Get the preferred encoding among the negotiated ones.
@@ -213,8 +216,7 @@ async fn open_file_with_fallback(
                 // to reset the path before the next iteration.
                 path.set_extension(OsStr::new(""));
                 // Remove the encoding from the negotiated_encodings since the file doesn't exist
-                negotiated_encoding
-                    .retain(|(negotiated_encoding, _)| *negotiated_encoding != encoding);
+                negotiated_encoding.remove(encoding);
                 continue;
             }
             (Err(err), _) => return Err(err),
@@ -228,7 +230,7 @@ async fn open_file_with_fallback(
 // file the uncompressed file is used as a fallback.
 async fn file_metadata_with_fallback(
     mut path: PathBuf,
-    mut negotiated_encoding: Vec<(Encoding, QValue)>,
+    mut negotiated_encoding: AcceptedEncodings,
 ) -> io::Result<(Metadata, Option<Encoding>)> {
     let (file, encoding) = loop {
         // Get the preferred encoding among the negotiated ones.
@@ -240,8 +242,7 @@ async fn file_metadata_with_fallback(
                 // to reset the path before the next iteration.
                 path.set_extension(OsStr::new(""));
                 // Remove the encoding from the negotiated_encodings since the file doesn't exist
-                negotiated_encoding
-                    .retain(|(negotiated_encoding, _)| *negotiated_encoding != encoding);
+                negotiated_encoding.remove(encoding);
                 continue;
             }
             (Err(err), _) => return Err(err),
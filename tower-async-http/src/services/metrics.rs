@@ -0,0 +1,106 @@
+//! Service that serves the metrics of a [`prometheus_client`] registry.
+//!
+//! # Example
+//!
+//! ```rust
+//! use http::{Request, StatusCode};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use prometheus_client::{metrics::counter::Counter, registry::Registry};
+//! use std::sync::Arc;
+//! use tower_async::{Service, ServiceExt};
+//! use tower_async_http::services::MetricsEndpoint;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let counter: Counter = Counter::default();
+//! let mut registry = Registry::default();
+//! registry.register("jobs", "Number of jobs", counter.clone());
+//! counter.inc();
+//!
+//! let service: MetricsEndpoint<Full<Bytes>> = MetricsEndpoint::new(Arc::new(registry));
+//!
+//! let response = service.oneshot(Request::new(Full::<Bytes>::default())).await?;
+//!
+//! assert_eq!(response.status(), StatusCode::OK);
+//! assert_eq!(
+//!     response.headers()["content-type"],
+//!     "application/openmetrics-text; version=1.0.0; charset=utf-8",
+//! );
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`prometheus_client`]: https://docs.rs/prometheus-client
+
+use http::{header, HeaderValue, Response, StatusCode};
+use prometheus_client::{encoding::text::encode, registry::Registry};
+use std::{convert::Infallible, fmt, marker::PhantomData, sync::Arc};
+use tower_async_service::Service;
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Service that serves the metrics of a [`prometheus_client`] registry
+/// in the [OpenMetrics text format], for all requests.
+///
+/// See the [module docs](crate::services::metrics) for more details.
+///
+/// [`prometheus_client`]: https://docs.rs/prometheus-client
+/// [OpenMetrics text format]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+pub struct MetricsEndpoint<ResBody> {
+    registry: Arc<Registry>,
+    // Covariant over ResBody, no dropping of ResBody
+    _marker: PhantomData<fn() -> ResBody>,
+}
+
+impl<ResBody> MetricsEndpoint<ResBody> {
+    /// Create a new [`MetricsEndpoint`] serving the metrics of `registry`.
+    pub fn new(registry: Arc<Registry>) -> Self {
+        Self {
+            registry,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<R, ResBody> Service<R> for MetricsEndpoint<ResBody>
+where
+    ResBody: From<String> + Default,
+{
+    type Response = Response<ResBody>;
+    type Error = Infallible;
+
+    async fn call(&self, _req: R) -> Result<Self::Response, Self::Error> {
+        let mut buffer = String::new();
+        if encode(&mut buffer, &self.registry).is_err() {
+            let mut res = Response::default();
+            *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(res);
+        }
+
+        let mut res = Response::new(ResBody::from(buffer));
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(OPENMETRICS_CONTENT_TYPE),
+        );
+        Ok(res)
+    }
+}
+
+impl<ResBody> fmt::Debug for MetricsEndpoint<ResBody> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsEndpoint")
+            .field("registry", &self.registry)
+            .finish()
+    }
+}
+
+impl<ResBody> Clone for MetricsEndpoint<ResBody> {
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
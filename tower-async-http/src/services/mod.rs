@@ -19,3 +19,17 @@ pub mod fs;
 #[cfg(feature = "fs")]
 #[doc(inline)]
 pub use self::fs::{ServeDir, ServeFile};
+
+#[cfg(feature = "metrics-prometheus")]
+pub mod metrics;
+
+#[cfg(feature = "metrics-prometheus")]
+#[doc(inline)]
+pub use self::metrics::MetricsEndpoint;
+
+#[cfg(feature = "resumable-upload")]
+pub mod resumable_upload;
+
+#[cfg(feature = "resumable-upload")]
+#[doc(inline)]
+pub use self::resumable_upload::ResumableUpload;
@@ -0,0 +1,120 @@
+use base64::Engine as _;
+use std::collections::BTreeMap;
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// The metadata of an upload, sent by the client in the `Upload-Metadata` header
+/// when creating the upload, such as its file name or type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadMetadata {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl UploadMetadata {
+    /// Create new empty [`UploadMetadata`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the value of an `Upload-Metadata` header, a comma separated list of keys, each
+    /// followed by a space and its base64 encoded value, if it has a value.
+    ///
+    /// Returns `None` if the header is malformed, or contains a key more than once.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut metadata = Self::new();
+        for pair in header.split(',') {
+            let pair = pair.trim();
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = match pair.split_once(' ') {
+                Some((key, value)) => (key, BASE64.decode(value.trim()).ok()?),
+                None => (pair, Vec::new()),
+            };
+            if metadata.entries.insert(key.to_owned(), value).is_some() {
+                return None;
+            }
+        }
+        Some(metadata)
+    }
+
+    /// Insert an entry, replacing the value of the key if present.
+    ///
+    /// Keys must not be empty, and must not contain spaces or commas.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is invalid.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) {
+        let key = key.into();
+        assert!(
+            !key.is_empty() && key.bytes().all(|b| b.is_ascii_graphic() && b != b','),
+            "invalid upload metadata key {key:?}"
+        );
+        self.entries.insert(key, value.into());
+    }
+
+    /// Returns the value of `key`, if present.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Returns the value of `key`, if present and valid UTF-8.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Returns an iterator over the entries, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    /// Returns `true` if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encode the metadata as the value of an `Upload-Metadata` header.
+    pub fn encode(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| {
+                if value.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{key} {}", BASE64.encode(value))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_encode() {
+        let metadata =
+            UploadMetadata::parse("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential")
+                .unwrap();
+        assert_eq!(
+            metadata.get_str("filename"),
+            Some("world_domination_plan.pdf")
+        );
+        assert_eq!(metadata.get("is_confidential"), Some(&[][..]));
+        assert_eq!(metadata.get("missing"), None);
+        assert_eq!(
+            metadata.encode(),
+            "filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential"
+        );
+
+        assert_eq!(UploadMetadata::parse(""), Some(UploadMetadata::new()));
+        assert_eq!(UploadMetadata::parse("a not-base64"), None);
+        assert_eq!(UploadMetadata::parse("a,a"), None);
+    }
+}
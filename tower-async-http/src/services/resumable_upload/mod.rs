@@ -0,0 +1,679 @@
+//! Service that receives resumable uploads, following the [tus] protocol.
+//!
+//! Large uploads which are interrupted, such as by a flaky mobile connection, do not have to
+//! start over: the client creates an upload, sends its data in one or more `PATCH` requests,
+//! and asks the server how much data it received after an interruption to resume from there.
+//!
+//! [`ResumableUpload`] implements the core protocol of [tus] version `1.0.0`, with the
+//! `creation` and `termination` extensions, for the URLs below a base path such as `/files`:
+//!
+//! - `POST /files` creates an upload, whose size is given by the `Upload-Length` header, and
+//!   responds with `201 Created` and the URL of the upload in the `Location` header;
+//! - `HEAD /files/{id}` responds with the number of bytes received in the `Upload-Offset` header;
+//! - `PATCH /files/{id}` appends the request body at the offset given by the `Upload-Offset`
+//!   header, which has to match the number of bytes received so far;
+//! - `DELETE /files/{id}` deletes the upload;
+//! - `OPTIONS` requests respond with the protocol versions and extensions supported.
+//!
+//! Uploads are stored by an [`UploadStorage`], such as the [`FileStorage`] which stores uploads
+//! as files in a directory. The data of a `PATCH` request is stored as it is received, such that
+//! the data received before an interruption is kept.
+//!
+//! Uploads can be rejected on creation using [`ResumableUpload::validate`], based on their size
+//! and [`UploadMetadata`], and completed uploads are handed over using
+//! [`ResumableUpload::on_complete`]. Authorization is left to middleware wrapping the service.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, StatusCode};
+//! use http_body_util::Full;
+//! use tower_async::Service;
+//! use tower_async_http::services::resumable_upload::{MemoryStorage, ResumableUpload};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let storage = MemoryStorage::new();
+//! let svc: ResumableUpload<_, Full<Bytes>> = ResumableUpload::new(storage.clone(), "/files")
+//!     .max_size(1024 * 1024 * 1024)
+//!     .validate(|length, metadata| {
+//!         // only accept uploads with a file name
+//!         match metadata.get_str("filename") {
+//!             Some(_) => Ok(()),
+//!             None => Err(StatusCode::BAD_REQUEST),
+//!         }
+//!     })
+//!     .on_complete(|upload| println!("received {}", upload.id()));
+//!
+//! // create an upload of 11 bytes, named `hello.txt`
+//! let request = Request::post("/files")
+//!     .header("tus-resumable", "1.0.0")
+//!     .header("upload-length", "11")
+//!     .header("upload-metadata", "filename aGVsbG8udHh0")
+//!     .body(Full::<Bytes>::default())?;
+//! let response = svc.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::CREATED);
+//! let location = response.headers()["location"].to_str()?.to_owned();
+//!
+//! // send the data, which could be split over multiple requests
+//! let request = Request::patch(&location)
+//!     .header("tus-resumable", "1.0.0")
+//!     .header("upload-offset", "0")
+//!     .header("content-type", "application/offset+octet-stream")
+//!     .body(Full::<Bytes>::from("hello world"))?;
+//! let response = svc.call(request).await?;
+//! assert_eq!(response.status(), StatusCode::NO_CONTENT);
+//! assert_eq!(response.headers()["upload-offset"], "11");
+//!
+//! let id = location.trim_start_matches("/files/");
+//! assert_eq!(storage.data(id).unwrap(), "hello world");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [tus]: https://tus.io/protocols/resumable-upload
+
+use bytes::Buf;
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode,
+};
+use http_body::Body;
+use http_body_util::BodyExt;
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fmt,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+use tower_async_service::Service;
+
+mod metadata;
+mod storage;
+
+pub use self::{
+    metadata::UploadMetadata,
+    storage::{FileStorage, MemoryStorage, Upload, UploadStorage},
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination";
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+const TUS_RESUMABLE_HEADER: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION_HEADER: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE_HEADER: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+
+type Validate = Arc<dyn Fn(u64, &UploadMetadata) -> Result<(), StatusCode> + Send + Sync>;
+type OnComplete = Arc<dyn Fn(&Upload) + Send + Sync>;
+
+/// Service that receives resumable uploads, following the [tus] protocol.
+///
+/// See the [module docs](crate::services::resumable_upload) for more details.
+///
+/// [tus]: https://tus.io/protocols/resumable-upload
+pub struct ResumableUpload<S, ResBody> {
+    storage: S,
+    base_path: String,
+    max_size: Option<u64>,
+    validate: Option<Validate>,
+    on_complete: Option<OnComplete>,
+    // the uploads receiving data right now
+    locked: Arc<Mutex<HashSet<String>>>,
+    // Covariant over ResBody, no dropping of ResBody
+    _marker: PhantomData<fn() -> ResBody>,
+}
+
+impl<S, ResBody> ResumableUpload<S, ResBody> {
+    /// Create a new [`ResumableUpload`] service, storing uploads in `storage`,
+    /// for the URLs below `base_path`.
+    pub fn new(storage: S, base_path: impl Into<String>) -> Self {
+        let mut base_path = base_path.into();
+        while base_path.ends_with('/') {
+            base_path.pop();
+        }
+        Self {
+            storage,
+            base_path,
+            max_size: None,
+            validate: None,
+            on_complete: None,
+            locked: Default::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reject uploads larger than `max_size` bytes with `413 Payload Too Large`.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Validate uploads before they are created, given their size and metadata.
+    ///
+    /// Uploads for which `f` returns an error are rejected with the returned status code.
+    pub fn validate<F>(mut self, f: F) -> Self
+    where
+        F: Fn(u64, &UploadMetadata) -> Result<(), StatusCode> + Send + Sync + 'static,
+    {
+        self.validate = Some(Arc::new(f));
+        self
+    }
+
+    /// Call `f` once all the data of an upload was received.
+    ///
+    /// The upload is kept in the storage, `f` is responsible for deleting it once processed.
+    pub fn on_complete<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Upload) + Send + Sync + 'static,
+    {
+        self.on_complete = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns the [`UploadStorage`] uploads are stored in.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ResumableUpload<S, ResBody>
+where
+    S: UploadStorage,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = Infallible;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if req.method() == Method::OPTIONS {
+            let mut res = respond(StatusCode::NO_CONTENT);
+            let headers = res.headers_mut();
+            headers.insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+            headers.insert(
+                TUS_EXTENSION_HEADER,
+                HeaderValue::from_static(TUS_EXTENSIONS),
+            );
+            if let Some(max_size) = self.max_size {
+                headers.insert(TUS_MAX_SIZE_HEADER, max_size.into());
+            }
+            return Ok(res);
+        }
+
+        if req.headers().get(TUS_RESUMABLE_HEADER) != Some(&HeaderValue::from_static(TUS_VERSION)) {
+            let mut res = respond(StatusCode::PRECONDITION_FAILED);
+            res.headers_mut()
+                .insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+            return Ok(res);
+        }
+
+        let path = req.uri().path();
+        if path.trim_end_matches('/') == self.base_path {
+            return Ok(match *req.method() {
+                Method::POST => self.create(req.headers()).await,
+                _ => respond(StatusCode::METHOD_NOT_ALLOWED),
+            });
+        }
+
+        let id = match path
+            .strip_prefix(self.base_path.as_str())
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(id) if is_valid_id(id) => id.to_owned(),
+            _ => return Ok(respond(StatusCode::NOT_FOUND)),
+        };
+        Ok(match *req.method() {
+            Method::HEAD => self.head(&id).await,
+            Method::PATCH => self.patch(&id, req).await,
+            Method::DELETE => match self.storage.delete(&id).await {
+                Ok(true) => respond(StatusCode::NO_CONTENT),
+                Ok(false) => respond(StatusCode::NOT_FOUND),
+                Err(_) => respond(StatusCode::INTERNAL_SERVER_ERROR),
+            },
+            _ => respond(StatusCode::METHOD_NOT_ALLOWED),
+        })
+    }
+}
+
+impl<S, ResBody> ResumableUpload<S, ResBody>
+where
+    S: UploadStorage,
+    ResBody: Default,
+{
+    async fn create(&self, headers: &HeaderMap) -> Response<ResBody> {
+        let Some(length) = parse_header::<u64>(headers, &UPLOAD_LENGTH) else {
+            return respond(StatusCode::BAD_REQUEST);
+        };
+        if self.max_size.is_some_and(|max_size| length > max_size) {
+            return respond(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        let metadata = match headers.get(UPLOAD_METADATA) {
+            Some(value) => match value.to_str().ok().and_then(UploadMetadata::parse) {
+                Some(metadata) => metadata,
+                None => return respond(StatusCode::BAD_REQUEST),
+            },
+            None => UploadMetadata::new(),
+        };
+        if let Some(validate) = &self.validate {
+            if let Err(status) = validate(length, &metadata) {
+                return respond(status);
+            }
+        }
+
+        let upload = match self.storage.create(length, metadata).await {
+            Ok(upload) => upload,
+            Err(_) => return respond(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        if upload.is_complete() {
+            self.complete(&upload);
+        }
+
+        let mut res = respond(StatusCode::CREATED);
+        let location = format!("{}/{}", self.base_path, upload.id());
+        match HeaderValue::try_from(location) {
+            Ok(location) => {
+                res.headers_mut().insert(header::LOCATION, location);
+                res
+            }
+            Err(_) => respond(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    async fn head(&self, id: &str) -> Response<ResBody> {
+        let upload = match self.storage.get(id).await {
+            Ok(Some(upload)) => upload,
+            Ok(None) => return respond(StatusCode::NOT_FOUND),
+            Err(_) => return respond(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+
+        let mut res = respond(StatusCode::OK);
+        let headers = res.headers_mut();
+        headers.insert(UPLOAD_OFFSET, upload.offset().into());
+        headers.insert(UPLOAD_LENGTH, upload.length().into());
+        if !upload.metadata().is_empty() {
+            if let Ok(metadata) = HeaderValue::try_from(upload.metadata().encode()) {
+                headers.insert(UPLOAD_METADATA, metadata);
+            }
+        }
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        res
+    }
+
+    async fn patch<ReqBody>(&self, id: &str, req: Request<ReqBody>) -> Response<ResBody>
+    where
+        ReqBody: Body,
+    {
+        if req.headers().get(header::CONTENT_TYPE)
+            != Some(&HeaderValue::from_static(OFFSET_OCTET_STREAM))
+        {
+            return respond(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let Some(offset) = parse_header::<u64>(req.headers(), &UPLOAD_OFFSET) else {
+            return respond(StatusCode::BAD_REQUEST);
+        };
+
+        // concurrent requests for the same upload can't agree on the offset
+        let Some(_lock) = UploadLock::acquire(&self.locked, id) else {
+            return respond(StatusCode::LOCKED);
+        };
+
+        let mut upload = match self.storage.get(id).await {
+            Ok(Some(upload)) => upload,
+            Ok(None) => return respond(StatusCode::NOT_FOUND),
+            Err(_) => return respond(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        if offset != upload.offset() {
+            return respond(StatusCode::CONFLICT);
+        }
+
+        let mut status = StatusCode::NO_CONTENT;
+        let mut body = std::pin::pin!(req.into_body());
+        while let Some(frame) = body.frame().await {
+            let Ok(frame) = frame else {
+                // the data received before is kept, to be resumed from
+                status = StatusCode::BAD_REQUEST;
+                break;
+            };
+            let Ok(mut data) = frame.into_data() else {
+                continue;
+            };
+            let data = data.copy_to_bytes(data.remaining());
+            if data.len() as u64 > upload.length() - upload.offset() {
+                status = StatusCode::PAYLOAD_TOO_LARGE;
+                break;
+            }
+            if data.is_empty() {
+                continue;
+            }
+            match self.storage.append(id, upload.offset(), data).await {
+                Ok(offset) => upload.set_offset(offset),
+                Err(_) => {
+                    status = StatusCode::INTERNAL_SERVER_ERROR;
+                    break;
+                }
+            }
+        }
+
+        if upload.is_complete() {
+            self.complete(&upload);
+        }
+
+        let mut res = respond(status);
+        res.headers_mut()
+            .insert(UPLOAD_OFFSET, upload.offset().into());
+        res
+    }
+
+    fn complete(&self, upload: &Upload) {
+        if let Some(on_complete) = &self.on_complete {
+            on_complete(upload);
+        }
+    }
+}
+
+impl<S, ResBody> Clone for ResumableUpload<S, ResBody>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            base_path: self.base_path.clone(),
+            max_size: self.max_size,
+            validate: self.validate.clone(),
+            on_complete: self.on_complete.clone(),
+            locked: self.locked.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, ResBody> fmt::Debug for ResumableUpload<S, ResBody>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResumableUpload")
+            .field("storage", &self.storage)
+            .field("base_path", &self.base_path)
+            .field("max_size", &self.max_size)
+            .field("validate", &self.validate.is_some())
+            .field("on_complete", &self.on_complete.is_some())
+            .finish()
+    }
+}
+
+/// Marks an upload as receiving data, until dropped.
+struct UploadLock<'a> {
+    locked: &'a Mutex<HashSet<String>>,
+    id: String,
+}
+
+impl<'a> UploadLock<'a> {
+    fn acquire(locked: &'a Mutex<HashSet<String>>, id: &str) -> Option<Self> {
+        locked.lock().unwrap().insert(id.to_owned()).then(|| Self {
+            locked,
+            id: id.to_owned(),
+        })
+    }
+}
+
+impl Drop for UploadLock<'_> {
+    fn drop(&mut self) {
+        self.locked.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn respond<B>(status: StatusCode) -> Response<B>
+where
+    B: Default,
+{
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    res.headers_mut()
+        .insert(TUS_RESUMABLE_HEADER, HeaderValue::from_static(TUS_VERSION));
+    res
+}
+
+fn parse_header<T>(headers: &HeaderMap, name: &HeaderName) -> Option<T>
+where
+    T: std::str::FromStr,
+{
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+
+    fn request(method: Method, uri: &str) -> http::request::Builder {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(TUS_RESUMABLE_HEADER, TUS_VERSION)
+    }
+
+    fn patch(uri: &str, offset: u64, body: Body) -> Request<Body> {
+        request(Method::PATCH, uri)
+            .header(UPLOAD_OFFSET, offset)
+            .header(header::CONTENT_TYPE, OFFSET_OCTET_STREAM)
+            .body(body)
+            .unwrap()
+    }
+
+    async fn create<S>(svc: &ResumableUpload<S, Body>, length: u64) -> String
+    where
+        S: UploadStorage,
+    {
+        let req = request(Method::POST, "/files/")
+            .header(UPLOAD_LENGTH, length)
+            .header(UPLOAD_METADATA, "filename aGVsbG8udHh0,private")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        res.headers()[header::LOCATION].to_str().unwrap().to_owned()
+    }
+
+    async fn offset<S>(svc: &ResumableUpload<S, Body>, location: &str) -> Option<u64>
+    where
+        S: UploadStorage,
+    {
+        let req = request(Method::HEAD, location).body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap();
+        res.headers()
+            .get(UPLOAD_OFFSET)
+            .map(|offset| offset.to_str().unwrap().parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn resumes_uploads() {
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        let storage = MemoryStorage::new();
+        let svc = ResumableUpload::new(storage.clone(), "/files").on_complete({
+            let completed = completed.clone();
+            move |upload| completed.lock().unwrap().push(upload.clone())
+        });
+
+        let location = create(&svc, 11).await;
+        assert!(location.starts_with("/files/"));
+        let id = location.trim_start_matches("/files/");
+
+        let req = request(Method::HEAD, &location)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[UPLOAD_OFFSET], "0");
+        assert_eq!(res.headers()[UPLOAD_LENGTH], "11");
+        assert_eq!(
+            res.headers()[UPLOAD_METADATA],
+            "filename aGVsbG8udHh0,private"
+        );
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-store");
+
+        // interrupted after the first chunk
+        let body = Body::from_stream(futures_util::stream::iter([
+            Ok(bytes::Bytes::from("hello")),
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)),
+        ]));
+        let res = svc.call(patch(&location, 0, body)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(offset(&svc, &location).await, Some(5));
+
+        // resumed at the wrong offset
+        let res = svc
+            .call(patch(&location, 0, Body::from("hello")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let res = svc
+            .call(patch(&location, 5, Body::from(" world")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[UPLOAD_OFFSET], "11");
+        assert_eq!(res.headers()[TUS_RESUMABLE_HEADER], TUS_VERSION);
+        assert_eq!(storage.data(id).unwrap(), "hello world");
+
+        let completed = completed.lock().unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].id(), id);
+        assert_eq!(
+            completed[0].metadata().get_str("filename"),
+            Some("hello.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_requests() {
+        let svc = ResumableUpload::new(MemoryStorage::new(), "/files")
+            .max_size(10)
+            .validate(|_, metadata| match metadata.get("filename") {
+                Some(_) => Ok(()),
+                None => Err(StatusCode::UNPROCESSABLE_ENTITY),
+            });
+
+        let res = svc
+            .call(Request::options("/files").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[TUS_EXTENSION_HEADER], TUS_EXTENSIONS);
+        assert_eq!(res.headers()[TUS_MAX_SIZE_HEADER], "10");
+
+        // not speaking tus
+        let res = svc
+            .call(Request::post("/files").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(res.headers()[TUS_VERSION_HEADER], TUS_VERSION);
+
+        for (length, metadata, status) in [
+            ("11", "filename YQ==", StatusCode::PAYLOAD_TOO_LARGE),
+            ("10", "", StatusCode::UNPROCESSABLE_ENTITY),
+            ("10", "filename !", StatusCode::BAD_REQUEST),
+            ("ten", "filename YQ==", StatusCode::BAD_REQUEST),
+        ] {
+            let req = request(Method::POST, "/files")
+                .header(UPLOAD_LENGTH, length)
+                .header(UPLOAD_METADATA, metadata)
+                .body(Body::empty())
+                .unwrap();
+            assert_eq!(svc.call(req).await.unwrap().status(), status);
+        }
+
+        let req = request(Method::POST, "/files")
+            .header(UPLOAD_LENGTH, "4")
+            .header(UPLOAD_METADATA, "filename YQ==")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        let location = res.headers()[header::LOCATION].to_str().unwrap();
+
+        let res = svc
+            .call(patch(location, 0, Body::from("too long")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(res.headers()[UPLOAD_OFFSET], "0");
+
+        let mut req = patch(location, 0, Body::from("data"));
+        req.headers_mut().remove(header::CONTENT_TYPE);
+        assert_eq!(
+            svc.call(req).await.unwrap().status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        for uri in ["/files/../secret", "/files/unknown", "/other"] {
+            let req = request(Method::HEAD, uri).body(Body::empty()).unwrap();
+            assert_eq!(svc.call(req).await.unwrap().status(), StatusCode::NOT_FOUND);
+        }
+        let req = request(Method::GET, location).body(Body::empty()).unwrap();
+        assert_eq!(
+            svc.call(req).await.unwrap().status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        let req = request(Method::DELETE, location)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            svc.call(req).await.unwrap().status(),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(offset(&svc, location).await, None);
+    }
+
+    #[tokio::test]
+    async fn file_storage() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&dir).unwrap();
+        let storage = FileStorage::new(&dir);
+        let svc = ResumableUpload::new(storage.clone(), "/files");
+
+        let location = create(&svc, 11).await;
+        let id = location.trim_start_matches("/files/");
+        let res = svc
+            .call(patch(&location, 0, Body::from("hello")))
+            .await
+            .unwrap();
+        assert_eq!(res.headers()[UPLOAD_OFFSET], "5");
+
+        // uploads survive restarts
+        let svc = ResumableUpload::<_, Body>::new(FileStorage::new(&dir), "/files");
+        assert_eq!(offset(&svc, &location).await, Some(5));
+        svc.call(patch(&location, 5, Body::from(" world")))
+            .await
+            .unwrap();
+        let data = std::fs::read(storage.data_path(id).unwrap()).unwrap();
+        assert_eq!(data, b"hello world");
+
+        let upload = storage.get(id).await.unwrap().unwrap();
+        assert!(upload.is_complete());
+        assert_eq!(upload.metadata().get("private"), Some(&[][..]));
+
+        assert!(storage.delete(id).await.unwrap());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        assert_eq!(storage.get(id).await.unwrap(), None);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+use super::UploadMetadata;
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// An upload, as stored by an [`UploadStorage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upload {
+    id: String,
+    length: u64,
+    offset: u64,
+    metadata: UploadMetadata,
+}
+
+impl Upload {
+    /// Create a new [`Upload`] of `length` bytes, of which `offset` bytes were received.
+    pub fn new(id: impl Into<String>, length: u64, offset: u64, metadata: UploadMetadata) -> Self {
+        Self {
+            id: id.into(),
+            length,
+            offset,
+            metadata,
+        }
+    }
+
+    /// Returns the id of the upload, which is the last segment of its URL.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the size of the upload in bytes.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the number of bytes received so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the metadata sent by the client when creating the upload.
+    pub fn metadata(&self) -> &UploadMetadata {
+        &self.metadata
+    }
+
+    pub(super) fn set_offset(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    /// Returns `true` if all bytes of the upload were received.
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.length
+    }
+}
+
+/// Stores the uploads received by a [`ResumableUpload`] service.
+///
+/// Ids of uploads consist of ASCII alphanumeric characters, dashes and underscores only,
+/// requests for other ids are rejected before reaching the storage.
+///
+/// [`ResumableUpload`]: super::ResumableUpload
+pub trait UploadStorage {
+    /// The error returned if the storage failed.
+    type Error;
+
+    /// Create a new empty upload of `length` bytes.
+    fn create(
+        &self,
+        length: u64,
+        metadata: UploadMetadata,
+    ) -> impl Future<Output = Result<Upload, Self::Error>>;
+
+    /// Returns the upload with the given id, or `None` if there is no such upload.
+    fn get(&self, id: &str) -> impl Future<Output = Result<Option<Upload>, Self::Error>>;
+
+    /// Append `data` to the upload with the given id, whose current offset is `offset`,
+    /// returning the new offset.
+    ///
+    /// The data never extends the upload beyond its length.
+    fn append(
+        &self,
+        id: &str,
+        offset: u64,
+        data: Bytes,
+    ) -> impl Future<Output = Result<u64, Self::Error>>;
+
+    /// Delete the upload with the given id, returning `false` if there is no such upload.
+    fn delete(&self, id: &str) -> impl Future<Output = Result<bool, Self::Error>>;
+}
+
+impl<T> UploadStorage for Arc<T>
+where
+    T: UploadStorage,
+{
+    type Error = T::Error;
+
+    fn create(
+        &self,
+        length: u64,
+        metadata: UploadMetadata,
+    ) -> impl Future<Output = Result<Upload, Self::Error>> {
+        (**self).create(length, metadata)
+    }
+
+    fn get(&self, id: &str) -> impl Future<Output = Result<Option<Upload>, Self::Error>> {
+        (**self).get(id)
+    }
+
+    fn append(
+        &self,
+        id: &str,
+        offset: u64,
+        data: Bytes,
+    ) -> impl Future<Output = Result<u64, Self::Error>> {
+        (**self).append(id, offset, data)
+    }
+
+    fn delete(&self, id: &str) -> impl Future<Output = Result<bool, Self::Error>> {
+        (**self).delete(id)
+    }
+}
+
+/// [`UploadStorage`] keeping uploads in memory, for tests and small uploads.
+///
+/// All clones share the same uploads.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    uploads: Arc<Mutex<HashMap<String, (Upload, Vec<u8>)>>>,
+}
+
+impl MemoryStorage {
+    /// Create a new empty [`MemoryStorage`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the bytes received so far for the upload with the given id.
+    pub fn data(&self, id: &str) -> Option<Bytes> {
+        let uploads = self.uploads.lock().unwrap();
+        uploads
+            .get(id)
+            .map(|(_, data)| Bytes::copy_from_slice(data))
+    }
+}
+
+impl UploadStorage for MemoryStorage {
+    type Error = io::Error;
+
+    async fn create(&self, length: u64, metadata: UploadMetadata) -> io::Result<Upload> {
+        let upload = Upload::new(new_id(), length, 0, metadata);
+        self.uploads
+            .lock()
+            .unwrap()
+            .insert(upload.id.clone(), (upload.clone(), Vec::new()));
+        Ok(upload)
+    }
+
+    async fn get(&self, id: &str) -> io::Result<Option<Upload>> {
+        let uploads = self.uploads.lock().unwrap();
+        Ok(uploads.get(id).map(|(upload, _)| upload.clone()))
+    }
+
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> io::Result<u64> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let (upload, stored) = uploads.get_mut(id).ok_or(io::ErrorKind::NotFound)?;
+        if upload.offset != offset {
+            return Err(offset_mismatch());
+        }
+        stored.extend_from_slice(&data);
+        upload.offset += data.len() as u64;
+        Ok(upload.offset)
+    }
+
+    async fn delete(&self, id: &str) -> io::Result<bool> {
+        Ok(self.uploads.lock().unwrap().remove(id).is_some())
+    }
+}
+
+/// [`UploadStorage`] keeping uploads as files in a directory.
+///
+/// The data of an upload is stored in a file named after its id, its length and metadata
+/// in a file with the same name and the `.info` extension. The offset of an upload is the
+/// size of its data file, such that all data written before a crash is kept.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    /// Create a new [`FileStorage`] storing uploads in `dir`, which must exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the path of the file holding the data of the upload with the given id.
+    ///
+    /// Returns `None` for invalid ids.
+    pub fn data_path(&self, id: &str) -> Option<PathBuf> {
+        super::is_valid_id(id).then(|| self.dir.join(id))
+    }
+
+    fn info_path(&self, id: &str) -> Option<PathBuf> {
+        super::is_valid_id(id).then(|| self.dir.join(format!("{id}.info")))
+    }
+}
+
+impl UploadStorage for FileStorage {
+    type Error = io::Error;
+
+    async fn create(&self, length: u64, metadata: UploadMetadata) -> io::Result<Upload> {
+        let id = new_id();
+        let info = format!("{length}\n{}\n", metadata.encode());
+        fs::write(self.info_path(&id).unwrap(), info).await?;
+        fs::File::create(self.data_path(&id).unwrap()).await?;
+        Ok(Upload::new(id, length, 0, metadata))
+    }
+
+    async fn get(&self, id: &str) -> io::Result<Option<Upload>> {
+        let (Some(info_path), Some(data_path)) = (self.info_path(id), self.data_path(id)) else {
+            return Ok(None);
+        };
+        let info = match fs::read_to_string(info_path).await {
+            Ok(info) => info,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut lines = info.lines();
+        let length = lines.next().and_then(|length| length.parse().ok());
+        let metadata = UploadMetadata::parse(lines.next().unwrap_or_default());
+        let (Some(length), Some(metadata)) = (length, metadata) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupt upload info file",
+            ));
+        };
+        let offset = fs::metadata(data_path).await?.len();
+        Ok(Some(Upload::new(id, length, offset, metadata)))
+    }
+
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> io::Result<u64> {
+        let path = self.data_path(id).ok_or(io::ErrorKind::NotFound)?;
+        let mut file = fs::OpenOptions::new().append(true).open(path).await?;
+        if file.metadata().await?.len() != offset {
+            return Err(offset_mismatch());
+        }
+        file.write_all(&data).await?;
+        file.flush().await?;
+        Ok(offset + data.len() as u64)
+    }
+
+    async fn delete(&self, id: &str) -> io::Result<bool> {
+        let (Some(info_path), Some(data_path)) = (self.info_path(id), self.data_path(id)) else {
+            return Ok(false);
+        };
+        match fs::remove_file(info_path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        match fs::remove_file(data_path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(true),
+        }
+    }
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn offset_mismatch() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "offset does not match the offset of the upload",
+    )
+}
@@ -0,0 +1,362 @@
+//! Middleware that signs outgoing requests.
+//!
+//! Signatures, such as [AWS Signature Version 4] or an HMAC over the request, cover the
+//! headers and the body of a request. They are only valid if nothing modifies the request
+//! after it was signed, and can only be computed once the whole body is known. The
+//! [`SignRequest`] middleware therefore:
+//!
+//! 1. buffers the request body;
+//! 2. finalizes the headers which are typically covered by signatures, by setting the `Host`
+//!    header from the request URI and the `Content-Length` header from the buffered body,
+//!    unless they are already present;
+//! 3. signs the request with a [`Signer`], passing the finalized head and buffered body;
+//! 4. passes the request on to the inner service with a [`Full`] body.
+//!
+//! The middleware should be the last layer before the client that sends the request,
+//! such that no other middleware can modify the request after it was signed.
+//!
+//! [`HmacSha256`] is a ready-made [`Signer`] for services that share a secret key.
+//! Other schemes, such as [AWS Signature Version 4], can be implemented with the
+//! [`Signer`] trait, which is async to allow loading credentials on demand.
+//!
+//! [AWS Signature Version 4]: https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_aws-signing.html
+//! [`Full`]: http_body_util::Full
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::sign_request::{HmacSha256, SignRequestLayer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! # let http_client = tower_async::service_fn(|req: Request<Full<Bytes>>| async move {
+//! #     assert!(req.headers().contains_key("x-signature"));
+//! #     Ok::<_, BoxError>(Response::new(Full::<Bytes>::default()))
+//! # });
+//! let client = ServiceBuilder::new()
+//!     // Sign requests as the last step before sending them.
+//!     .layer(SignRequestLayer::new(HmacSha256::new(b"secret")))
+//!     .service(http_client);
+//!
+//! let req = Request::post("https://example.com/orders").body(Full::<Bytes>::from("{}"))?;
+//! let res = client.call(req).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{canonical_headers::CanonicalHeaders, BoxError};
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use http::{header, request::Parts, HeaderName, HeaderValue, Request};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, future::Future};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Signs requests for [`SignRequest`].
+///
+/// The signer is given the finalized head of the request, which it can modify
+/// to add the signature, and the buffered body.
+pub trait Signer {
+    /// The error returned if the request could not be signed.
+    type Error;
+
+    /// Sign the request.
+    fn sign(
+        &self,
+        parts: &mut Parts,
+        body: &Bytes,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// [`Signer`] that signs requests with an HMAC-SHA256 using a shared secret key.
+///
+/// The signature covers the method, the path and query, the `Host` header and
+/// any additional [signed headers], and a SHA-256 digest of the body, in the form:
+///
+/// ```text
+/// METHOD\n
+/// /path?query\n
+/// host:example.com\n
+/// signed-header:value\n
+/// \n
+/// hex(sha256(body))
+/// ```
+///
+/// The signed headers are in their [canonical form], sorted by name. Missing headers
+/// are signed as empty values. The signature is inserted as a lowercase hex string in
+/// the `x-signature` header, or the configured [header].
+///
+/// [signed headers]: HmacSha256::signed_header
+/// [canonical form]: crate::canonical_headers
+/// [header]: HmacSha256::header
+#[derive(Clone)]
+pub struct HmacSha256 {
+    mac: Hmac<Sha256>,
+    header: HeaderName,
+    signed_headers: Vec<HeaderName>,
+    canonical_headers: CanonicalHeaders,
+}
+
+impl HmacSha256 {
+    /// Create a new [`HmacSha256`] signer using the given secret key.
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(key.as_ref()).expect("HMAC accepts keys of any size"),
+            header: HeaderName::from_static("x-signature"),
+            signed_headers: vec![header::HOST],
+            canonical_headers: CanonicalHeaders::new().only([header::HOST]),
+        }
+    }
+
+    /// Set the header the signature is inserted in.
+    ///
+    /// Defaults to `x-signature`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Include the given header in the signature, in addition to the `Host` header.
+    pub fn signed_header(mut self, header: HeaderName) -> Self {
+        self.signed_headers.push(header);
+        self.canonical_headers = CanonicalHeaders::new().only(self.signed_headers.iter().cloned());
+        self
+    }
+
+    /// Compute the signature of a request, as a lowercase hex string.
+    pub fn signature(&self, parts: &Parts, body: &[u8]) -> String {
+        let mut mac = self.mac.clone();
+        mac.update(parts.method.as_str().as_bytes());
+        mac.update(b"\n");
+        mac.update(
+            parts
+                .uri
+                .path_and_query()
+                .map_or("/", |path| path.as_str())
+                .as_bytes(),
+        );
+        mac.update(b"\n");
+        mac.update(&self.canonical_headers.canonicalize(&parts.headers));
+        mac.update(b"\n");
+        mac.update(hex(&Sha256::digest(body)).as_bytes());
+        hex(&mac.finalize().into_bytes())
+    }
+}
+
+impl std::fmt::Debug for HmacSha256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the key is deliberately not printed
+        f.debug_struct("HmacSha256")
+            .field("header", &self.header)
+            .field("signed_headers", &self.signed_headers)
+            .finish()
+    }
+}
+
+impl Signer for HmacSha256 {
+    type Error = std::convert::Infallible;
+
+    async fn sign(&self, parts: &mut Parts, body: &Bytes) -> Result<(), Self::Error> {
+        let signature = self.signature(parts, body);
+        parts.headers.insert(
+            self.header.clone(),
+            HeaderValue::try_from(signature).expect("hex is a valid header value"),
+        );
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+/// Layer that applies the [`SignRequest`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct SignRequestLayer<G> {
+    signer: G,
+}
+
+impl<G> SignRequestLayer<G> {
+    /// Create a new [`SignRequestLayer`] signing requests with the given [`Signer`].
+    pub fn new(signer: G) -> Self {
+        Self { signer }
+    }
+}
+
+impl<S, G> Layer<S> for SignRequestLayer<G>
+where
+    G: Clone,
+{
+    type Service = SignRequest<S, G>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SignRequest {
+            inner,
+            signer: self.signer.clone(),
+        }
+    }
+}
+
+/// Middleware that buffers, finalizes and signs outgoing requests.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct SignRequest<S, G> {
+    inner: S,
+    signer: G,
+}
+
+impl<S, G> SignRequest<S, G> {
+    /// Create a new [`SignRequest`] signing requests with the given [`Signer`].
+    pub fn new(inner: S, signer: G) -> Self {
+        Self { inner, signer }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SignRequest` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(signer: G) -> SignRequestLayer<G> {
+        SignRequestLayer::new(signer)
+    }
+}
+
+impl<S, G, ReqBody> Service<Request<ReqBody>> for SignRequest<S, G>
+where
+    S: Service<Request<Full<Bytes>>>,
+    S::Error: Into<BoxError>,
+    G: Signer,
+    G::Error: Into<BoxError>,
+    ReqBody: Body,
+    ReqBody::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+        let body = body.collect().await.map_err(Into::into)?.to_bytes();
+
+        if !parts.headers.contains_key(header::HOST) {
+            if let Some(authority) = parts.uri.authority() {
+                parts
+                    .headers
+                    .insert(header::HOST, HeaderValue::from_str(authority.as_str())?);
+            }
+        }
+        if !body.is_empty() && !parts.headers.contains_key(header::CONTENT_LENGTH) {
+            parts
+                .headers
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        }
+
+        self.signer
+            .sign(&mut parts, &body)
+            .await
+            .map_err(Into::into)?;
+
+        self.inner
+            .call(Request::from_parts(parts, Full::new(body)))
+            .await
+            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http::Response;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn signs_finalized_request() {
+        let signer = HmacSha256::new("secret").signed_header(header::CONTENT_TYPE);
+
+        let svc = ServiceBuilder::new()
+            .layer(SignRequestLayer::new(signer.clone()))
+            .service(service_fn(|req: Request<Full<Bytes>>| async move {
+                Ok::<_, BoxError>(Response::new(req))
+            }));
+
+        let req = Request::post("https://example.com/orders?id=1")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let req = svc.call(req).await.unwrap().into_body();
+        assert_eq!(req.headers()[header::HOST], "example.com");
+        assert_eq!(req.headers()[header::CONTENT_LENGTH], "2");
+
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{}");
+        assert_eq!(
+            parts.headers["x-signature"],
+            signer.signature(&parts, &body)
+        );
+    }
+
+    #[test]
+    fn signature_is_independent_of_header_order() {
+        let signer = HmacSha256::new("secret").signed_header(header::CONTENT_TYPE);
+        let parts = |headers: &[(HeaderName, &'static str)]| {
+            let mut req = Request::post("/orders");
+            for (name, value) in headers {
+                req = req.header(name, *value);
+            }
+            req.body(()).unwrap().into_parts().0
+        };
+
+        let signature = signer.signature(
+            &parts(&[
+                (header::HOST, "example.com"),
+                (header::CONTENT_TYPE, "application/json"),
+            ]),
+            b"{}",
+        );
+        assert_eq!(
+            signature,
+            signer.signature(
+                &parts(&[
+                    (header::CONTENT_TYPE, "application/json "),
+                    (header::HOST, "example.com"),
+                ]),
+                b"{}",
+            )
+        );
+        assert_ne!(
+            signature,
+            signer.signature(&parts(&[(header::HOST, "example.com")]), b"{}")
+        );
+    }
+
+    #[test]
+    fn signature_covers_request() {
+        let signer = HmacSha256::new("secret");
+        let parts = |uri: &str| Request::post(uri).body(()).unwrap().into_parts().0;
+
+        let signature = signer.signature(&parts("/orders"), b"{}");
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, signer.signature(&parts("/orders"), b"{}"));
+        assert_ne!(signature, signer.signature(&parts("/orders"), b"[]"));
+        assert_ne!(signature, signer.signature(&parts("/orders?id=1"), b"{}"));
+        assert_ne!(
+            signature,
+            HmacSha256::new("other").signature(&parts("/orders"), b"{}")
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Middleware that reports requests which are taking longer than expected.
+//!
+//! Unlike [`Timeout`], requests that exceed the threshold are *not* aborted. Instead
+//! an [`OnSlowRequest`] callback is invoked while the request is still in flight,
+//! optionally re-firing periodically for as long as the request keeps running.
+//! This makes it easy to locate stuck handlers that never hit a hard timeout.
+//!
+//! The callback is called from within the task polling the request, so when used in
+//! combination with [`Trace`] the events are recorded within the span of the request.
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::ServiceBuilder;
+//! use tower_async_http::slow_request::{DefaultOnSlowRequest, SlowRequestLayer};
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = ServiceBuilder::new()
+//!     // Warn about requests still running after 5 seconds,
+//!     // and keep doing so every 10 seconds after that.
+//!     .layer(
+//!         SlowRequestLayer::new(Duration::from_secs(5), DefaultOnSlowRequest::new())
+//!             .repeat_every(Duration::from_secs(10)),
+//!     )
+//!     .service_fn(handle);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Timeout`]: crate::timeout::Timeout
+//! [`Trace`]: crate::trace::Trace
+
+use http::{Method, Request, Uri};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+use tracing::Level;
+
+/// Trait used to tell [`SlowRequest`] what to do when a request exceeds its threshold.
+pub trait OnSlowRequest {
+    /// Do the thing.
+    ///
+    /// `elapsed` is the duration since the request was received.
+    fn on_slow_request(&self, method: &Method, uri: &Uri, elapsed: Duration);
+}
+
+impl OnSlowRequest for () {
+    #[inline]
+    fn on_slow_request(&self, _: &Method, _: &Uri, _: Duration) {}
+}
+
+impl<F> OnSlowRequest for F
+where
+    F: Fn(&Method, &Uri, Duration),
+{
+    fn on_slow_request(&self, method: &Method, uri: &Uri, elapsed: Duration) {
+        self(method, uri, elapsed)
+    }
+}
+
+/// The default [`OnSlowRequest`] implementation, which emits a [tracing event].
+///
+/// [tracing event]: https://docs.rs/tracing/latest/tracing/#events
+#[derive(Clone, Debug)]
+pub struct DefaultOnSlowRequest {
+    level: Level,
+}
+
+impl Default for DefaultOnSlowRequest {
+    fn default() -> Self {
+        Self { level: Level::WARN }
+    }
+}
+
+impl DefaultOnSlowRequest {
+    /// Create a new [`DefaultOnSlowRequest`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the [`Level`] used for [tracing events].
+    ///
+    /// Defaults to [`Level::WARN`].
+    ///
+    /// [tracing events]: https://docs.rs/tracing/latest/tracing/#events
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl OnSlowRequest for DefaultOnSlowRequest {
+    fn on_slow_request(&self, method: &Method, uri: &Uri, elapsed: Duration) {
+        event_dynamic_lvl!(
+            self.level,
+            %method,
+            %uri,
+            ?elapsed,
+            "request is taking longer than expected"
+        );
+    }
+}
+
+/// Layer that applies the [`SlowRequest`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct SlowRequestLayer<F> {
+    threshold: Duration,
+    interval: Option<Duration>,
+    on_slow_request: F,
+}
+
+impl<F> SlowRequestLayer<F> {
+    /// Creates a new [`SlowRequestLayer`].
+    ///
+    /// `on_slow_request` is called once the request has been in flight for `threshold`.
+    pub fn new(threshold: Duration, on_slow_request: F) -> Self {
+        Self {
+            threshold,
+            interval: None,
+            on_slow_request,
+        }
+    }
+
+    /// Keep calling the [`OnSlowRequest`] callback every `interval`
+    /// for as long as the request is still in flight after the first time.
+    ///
+    /// By default the callback is only called once per request.
+    pub fn repeat_every(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+}
+
+impl<S, F> Layer<S> for SlowRequestLayer<F>
+where
+    F: Clone,
+{
+    type Service = SlowRequest<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SlowRequest {
+            inner,
+            threshold: self.threshold,
+            interval: self.interval,
+            on_slow_request: self.on_slow_request.clone(),
+        }
+    }
+}
+
+/// Middleware that reports requests that are still in flight after a threshold.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct SlowRequest<S, F> {
+    inner: S,
+    threshold: Duration,
+    interval: Option<Duration>,
+    on_slow_request: F,
+}
+
+impl<S, F> SlowRequest<S, F> {
+    /// Creates a new [`SlowRequest`].
+    pub fn new(inner: S, threshold: Duration, on_slow_request: F) -> Self {
+        Self {
+            inner,
+            threshold,
+            interval: None,
+            on_slow_request,
+        }
+    }
+
+    /// Keep calling the [`OnSlowRequest`] callback every `interval`
+    /// for as long as the request is still in flight after the first time.
+    ///
+    /// By default the callback is only called once per request.
+    pub fn repeat_every(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `SlowRequest` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(threshold: Duration, on_slow_request: F) -> SlowRequestLayer<F> {
+        SlowRequestLayer::new(threshold, on_slow_request)
+    }
+}
+
+impl<S, F, ReqBody> Service<Request<ReqBody>> for SlowRequest<S, F>
+where
+    S: Service<Request<ReqBody>>,
+    F: OnSlowRequest,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+
+        let future = self.inner.call(req);
+        futures_util::pin_mut!(future);
+
+        let mut deadline = start + self.threshold;
+        loop {
+            tokio::select! {
+                res = &mut future => return res,
+                _ = tokio::time::sleep_until(deadline) => {
+                    self.on_slow_request.on_slow_request(&method, &uri, start.elapsed());
+                    match self.interval {
+                        Some(interval) => deadline += interval,
+                        None => return future.await,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use http::Response;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tower_async::{service_fn, ServiceBuilder};
+
+    async fn sleep_for_request_ms(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let ms: u64 = req.uri().path().trim_start_matches('/').parse().unwrap();
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn counting_layer(counter: Arc<AtomicUsize>) -> SlowRequestLayer<impl OnSlowRequest + Clone> {
+        SlowRequestLayer::new(
+            Duration::from_millis(100),
+            move |_: &Method, _: &Uri, _: Duration| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            },
+        )
+    }
+
+    fn request(path: &str) -> Request<Body> {
+        Request::builder().uri(path).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fast_request_is_not_reported() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = ServiceBuilder::new()
+            .layer(counting_layer(counter.clone()))
+            .service(service_fn(sleep_for_request_ms));
+
+        svc.call(request("/50")).await.unwrap();
+        assert_eq!(0, counter.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_request_is_reported_once() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = ServiceBuilder::new()
+            .layer(counting_layer(counter.clone()))
+            .service(service_fn(sleep_for_request_ms));
+
+        svc.call(request("/1000")).await.unwrap();
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_request_is_reported_periodically() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = ServiceBuilder::new()
+            .layer(counting_layer(counter.clone()).repeat_every(Duration::from_millis(200)))
+            .service(service_fn(sleep_for_request_ms));
+
+        // fires at 100ms, 300ms, 500ms, 700ms and 900ms
+        svc.call(request("/1000")).await.unwrap();
+        assert_eq!(5, counter.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,480 @@
+//! Middleware that copies request bodies to a secondary sink while they are streamed.
+//!
+//! Archiving request bodies for audits, or scanning uploads for viruses, requires a copy of
+//! the body, while the handler should still receive the body as a stream, without it being
+//! buffered first. The [`TeeBodyService`] middleware decides for every request whether its body
+//! should be copied, by calling a closure which returns a [`TeeSink`]. Every data frame read
+//! by the handler is then sent to that sink as well:
+//!
+//! - a [channel](TeeSink::channel), whose receiver can be consumed by another task;
+//! - an [`AsyncWrite`](TeeSink::writer), such as a file, written to by a spawned task.
+//!
+//! Sinks buffer a bounded number of chunks. Once that buffer is full, reading the body
+//! waits until the sink has caught up, such that a slow sink slows down the handler,
+//! instead of buffering the whole body in memory.
+//!
+//! The handler is not affected if the sink goes away, e.g. because the receiver of a channel
+//! was dropped or a writer failed: the body is still passed to the handler as a whole, it is
+//! just no longer copied. If the body fails or is dropped before it was read to the end, the
+//! sink receives a [`Truncated`] error, such that incomplete copies can be discarded.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Method, Request, Response};
+//! use http_body_util::{BodyExt, Full};
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::tee_body::{TeeBody, TeeBodyLayer, TeeSink};
+//!
+//! async fn handle(req: Request<TeeBody<Full<Bytes>>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     let body = req.into_body().collect().await?.to_bytes();
+//!     Ok(Response::new(Full::new(body)))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let (sink, mut archive) = TeeSink::channel(16);
+//!
+//! let svc = ServiceBuilder::new()
+//!     // Only archive uploads.
+//!     .layer(TeeBodyLayer::new(move |req: &Request<Full<Bytes>>| {
+//!         (req.method() == Method::PUT).then(|| sink.clone())
+//!     }))
+//!     .service_fn(handle);
+//!
+//! let req = Request::put("/files/report.pdf").body(Full::from("%PDF-1.7"))?;
+//! svc.call(req).await?;
+//!
+//! // Usually done by another task.
+//! assert_eq!(archive.recv().await.unwrap()?, "%PDF-1.7");
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use futures_util::ready;
+use http::Request;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+use tokio_util::sync::PollSender;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Error received by a [`TeeSink`] if the request body failed,
+/// or was dropped before it was read to the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncated;
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request body was not read to the end")
+    }
+}
+
+impl std::error::Error for Truncated {}
+
+/// The destination request bodies are copied to by [`TeeBody`].
+///
+/// Sinks are cheap to clone. A sink used for multiple requests receives the chunks of
+/// all of them, so a sink is usually created per request, e.g. using [`TeeSink::writer`].
+#[derive(Debug, Clone)]
+pub struct TeeSink {
+    tx: mpsc::Sender<Result<Bytes, Truncated>>,
+}
+
+impl TeeSink {
+    /// Create a sink backed by a channel buffering up to `capacity` chunks.
+    ///
+    /// The receiver receives the chunks of the body, followed by a [`Truncated`] error if
+    /// the body was not read to the end. The channel is closed once all senders are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn channel(capacity: usize) -> (Self, mpsc::Receiver<Result<Bytes, Truncated>>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (Self { tx }, rx)
+    }
+
+    /// Create a sink writing the body to `writer`, buffering up to `capacity` chunks.
+    ///
+    /// The writer is written to by a task spawned on the current Tokio runtime. It is
+    /// shut down once the body was read to the end, and dropped without being shut down
+    /// if the body was truncated or writing to it failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero, or if called outside of a Tokio runtime.
+    pub fn writer<W>(mut writer: W, capacity: usize) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sink, mut rx) = Self::channel(capacity);
+        tokio::spawn(async move {
+            while let Some(chunk) = rx.recv().await {
+                match chunk {
+                    Ok(chunk) => {
+                        if writer.write_all(&chunk).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(Truncated) => return,
+                }
+            }
+            let _ = writer.shutdown().await;
+        });
+        sink
+    }
+}
+
+pin_project! {
+    /// Request body for [`TeeBodyService`].
+    ///
+    /// Data frames read from the body are copied to a [`TeeSink`], if one was selected
+    /// for the request.
+    pub struct TeeBody<B> {
+        #[pin]
+        inner: B,
+        sink: Option<PollSender<Result<Bytes, Truncated>>>,
+    }
+
+    impl<B> PinnedDrop for TeeBody<B> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if let Some(sink) = this.sink.as_mut() {
+                // release the slot reserved for the next chunk, if any
+                sink.abort_send();
+                if let Some(tx) = sink.get_ref() {
+                    // best effort, the sink may be full
+                    let _ = tx.try_send(Err(Truncated));
+                }
+            }
+        }
+    }
+}
+
+impl<B> TeeBody<B> {
+    fn new(inner: B, sink: Option<TeeSink>) -> Self {
+        Self {
+            inner,
+            sink: sink.map(|sink| PollSender::new(sink.tx)),
+        }
+    }
+
+    /// Returns whether the body is copied to a [`TeeSink`].
+    ///
+    /// This is `false` if no sink was selected for the request, or the sink went away.
+    pub fn is_teed(&self) -> bool {
+        self.sink.as_ref().is_some_and(|sink| !sink.is_closed())
+    }
+}
+
+impl<B> fmt::Debug for TeeBody<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TeeBody")
+            .field("inner", &self.inner)
+            .field("is_teed", &self.is_teed())
+            .finish()
+    }
+}
+
+impl<B> Body for TeeBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        // Reserve room for the next chunk first, such that a slow sink applies backpressure.
+        if let Some(sink) = this.sink.as_mut() {
+            if ready!(sink.poll_reserve(cx)).is_err() {
+                *this.sink = None;
+            }
+        }
+
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let (Some(data), Some(sink)) = (frame.data_ref(), this.sink.as_mut()) {
+                    if sink.send_item(Ok(data.clone())).is_err() {
+                        *this.sink = None;
+                    }
+                }
+            }
+            Some(Err(_)) => {
+                if let Some(mut sink) = this.sink.take() {
+                    let _ = sink.send_item(Err(Truncated));
+                }
+            }
+            None => {}
+        }
+        if this.inner.is_end_stream() || frame.is_none() {
+            // closes the sink, unless cloned, without reporting a truncated body on drop
+            *this.sink = None;
+        }
+
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer that applies the [`TeeBodyService`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Clone)]
+pub struct TeeBodyLayer<F> {
+    sink: F,
+}
+
+impl<F> TeeBodyLayer<F> {
+    /// Create a new [`TeeBodyLayer`].
+    ///
+    /// `sink` is called for every request, and returns the [`TeeSink`] its body is
+    /// copied to, or `None` if it should not be copied.
+    pub fn new(sink: F) -> Self {
+        Self { sink }
+    }
+}
+
+impl<F> fmt::Debug for TeeBodyLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TeeBodyLayer")
+            .field("sink", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F> Layer<S> for TeeBodyLayer<F>
+where
+    F: Clone,
+{
+    type Service = TeeBodyService<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TeeBodyService {
+            inner,
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+/// Middleware that copies request bodies to a [`TeeSink`] while they are read.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct TeeBodyService<S, F> {
+    inner: S,
+    sink: F,
+}
+
+impl<S, F> TeeBodyService<S, F> {
+    /// Create a new [`TeeBodyService`].
+    ///
+    /// `sink` is called for every request, and returns the [`TeeSink`] its body is
+    /// copied to, or `None` if it should not be copied.
+    pub fn new(inner: S, sink: F) -> Self {
+        Self { inner, sink }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `TeeBodyService` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(sink: F) -> TeeBodyLayer<F> {
+        TeeBodyLayer::new(sink)
+    }
+}
+
+impl<S, F> fmt::Debug for TeeBodyService<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TeeBodyService")
+            .field("inner", &self.inner)
+            .field("sink", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F, ReqBody> Service<Request<ReqBody>> for TeeBodyService<S, F>
+where
+    S: Service<Request<TeeBody<ReqBody>>>,
+    F: Fn(&Request<ReqBody>) -> Option<TeeSink>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let sink = (self.sink)(&req);
+        self.inner
+            .call(req.map(|body| TeeBody::new(body, sink)))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use crate::BoxError;
+    use http::Response;
+    use http_body_util::BodyExt;
+    use std::sync::{Arc, Mutex};
+    use tower_async::{service_fn, ServiceBuilder};
+
+    fn request(chunks: &[&'static str]) -> Request<Body> {
+        Request::new(Body::from_stream(futures_util::stream::iter(
+            chunks
+                .iter()
+                .map(|chunk| Ok::<_, BoxError>(Bytes::from_static(chunk.as_bytes())))
+                .collect::<Vec<_>>(),
+        )))
+    }
+
+    async fn collect(req: Request<TeeBody<Body>>) -> Result<Response<Bytes>, BoxError> {
+        let body = req.into_body().collect().await?.to_bytes();
+        Ok(Response::new(body))
+    }
+
+    #[tokio::test]
+    async fn body_is_copied_to_channel() {
+        let (sink, mut rx) = TeeSink::channel(1);
+        let svc = ServiceBuilder::new()
+            .layer(TeeBodyLayer::new(move |_: &Request<Body>| {
+                Some(sink.clone())
+            }))
+            .service_fn(collect);
+
+        let (res, copied) = tokio::join!(svc.call(request(&["hello ", "world"])), async {
+            let mut copied = Vec::new();
+            while let Some(chunk) = rx.recv().await {
+                copied.extend_from_slice(&chunk.unwrap());
+                if copied.len() == 11 {
+                    break;
+                }
+            }
+            copied
+        });
+
+        assert_eq!(res.unwrap().into_body(), "hello world");
+        assert_eq!(copied, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn full_sink_applies_backpressure() {
+        let (sink, mut rx) = TeeSink::channel(1);
+        let mut body = TeeBody::new(request(&["a", "b", "c"]).into_body(), Some(sink));
+
+        assert_eq!(
+            body.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "a"
+        );
+        // the sink is full, so reading the next chunk waits for it to catch up
+        let next = tokio::time::timeout(std::time::Duration::from_millis(20), body.frame()).await;
+        assert!(next.is_err());
+
+        assert_eq!(rx.recv().await.unwrap().unwrap(), "a");
+        assert_eq!(
+            body.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "b"
+        );
+        assert_eq!(rx.recv().await.unwrap().unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn closed_sink_does_not_affect_handler() {
+        let (sink, rx) = TeeSink::channel(1);
+        drop(rx);
+        let svc = TeeBodyService::new(service_fn(collect), move |_: &Request<Body>| {
+            Some(sink.clone())
+        });
+
+        let res = svc.call(request(&["hello ", "world"])).await.unwrap();
+        assert_eq!(res.into_body(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn dropped_body_is_reported_as_truncated() {
+        let (sink, mut rx) = TeeSink::channel(4);
+        let mut body = TeeBody::new(request(&["a", "b"]).into_body(), Some(sink));
+
+        body.frame().await.unwrap().unwrap();
+        drop(body);
+
+        assert_eq!(rx.recv().await.unwrap().unwrap(), "a");
+        assert_eq!(rx.recv().await.unwrap(), Err(Truncated));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[derive(Clone, Default)]
+    struct Writer(Arc<Mutex<(Vec<u8>, bool)>>);
+
+    impl AsyncWrite for Writer {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.0.lock().unwrap().0.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.0.lock().unwrap().1 = true;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn body_is_written_to_writer() {
+        let writer = Writer::default();
+        let svc = TeeBodyService::new(service_fn(collect), {
+            let writer = writer.clone();
+            move |_: &Request<Body>| Some(TeeSink::writer(writer.clone(), 1))
+        });
+
+        let res = svc.call(request(&["hello ", "world"])).await.unwrap();
+        assert_eq!(res.into_body(), "hello world");
+
+        for _ in 0..10 {
+            if writer.0.lock().unwrap().1 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(*writer.0.lock().unwrap(), (b"hello world".to_vec(), true));
+    }
+}
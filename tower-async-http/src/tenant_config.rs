@@ -0,0 +1,443 @@
+//! Middleware that resolves the configuration of the tenant of each request.
+//!
+//! Multi-tenant services often need per-tenant settings, such as rate limits, feature flags
+//! or the upstream to route to. Instead of having every layer look these up on its own,
+//! [`TenantConfig`] reads the tenant of a request from its [extensions], resolves the
+//! configuration of that tenant using a [`ConfigResolver`] and inserts it into the request
+//! extensions, where downstream layers and handlers can find it.
+//!
+//! The tenant is expected to be inserted as an extension of type `K` by an earlier layer,
+//! e.g. by the authentication middleware. Requests without such an extension are passed to
+//! the inner service as is.
+//!
+//! Resolved configurations are cached per tenant for a configurable time to live, which
+//! defaults to 60 seconds. The cache is shared by all services produced by the same
+//! [`TenantConfigLayer`] and all clones of them. Concurrent requests for a tenant that is
+//! not cached yet may each resolve its configuration.
+//!
+//! If the resolution fails, the error is converted into the error of the inner service
+//! and returned for that request. Failures are not cached.
+//!
+//! [extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use std::time::Duration;
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::tenant_config::TenantConfigLayer;
+//!
+//! #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+//! struct TenantId(String);
+//!
+//! #[derive(Debug, Clone)]
+//! struct Plan {
+//!     requests_per_second: u32,
+//! }
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     // Grab the configuration of the tenant from the request extensions.
+//!     let plan = req.extensions().get::<Plan>().unwrap();
+//!     assert_eq!(plan.requests_per_second, 100);
+//!
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = ServiceBuilder::new()
+//!     .layer(
+//!         TenantConfigLayer::new(|tenant: TenantId| async move {
+//!             // Look up the plan of the tenant, e.g. in a database.
+//!             let requests_per_second = if tenant.0 == "acme" { 100 } else { 10 };
+//!             Ok::<_, BoxError>(Plan { requests_per_second })
+//!         })
+//!         .ttl(Duration::from_secs(300)),
+//!     )
+//!     .service_fn(handle);
+//!
+//! let mut request = Request::new(Full::default());
+//! // Usually inserted by the authentication middleware.
+//! request.extensions_mut().insert(TenantId("acme".to_owned()));
+//!
+//! let response = service.call(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use http::Request;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// Resolves the configuration of a tenant, to be provided to requests by [`TenantConfig`].
+///
+/// This trait is implemented for async closures taking the tenant and returning a
+/// `Result<C, E>`.
+pub trait ConfigResolver<K> {
+    /// The configuration of a tenant.
+    type Config;
+
+    /// The error returned if the configuration could not be resolved.
+    type Error;
+
+    /// Resolve the configuration of `tenant`.
+    fn resolve(&self, tenant: K) -> impl Future<Output = Result<Self::Config, Self::Error>>;
+}
+
+impl<K, C, E, F, Fut> ConfigResolver<K> for F
+where
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<C, E>>,
+{
+    type Config = C;
+    type Error = E;
+
+    fn resolve(&self, tenant: K) -> impl Future<Output = Result<C, E>> {
+        self(tenant)
+    }
+}
+
+struct Shared<K, C, R> {
+    resolver: R,
+    cache: Mutex<HashMap<K, (Instant, C)>>,
+}
+
+impl<K, C, R> Shared<K, C, R>
+where
+    K: Eq + Hash,
+    C: Clone,
+{
+    fn get(&self, tenant: &K) -> Option<C> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(tenant) {
+            Some((expires_at, config)) if *expires_at > Instant::now() => Some(config.clone()),
+            Some(_) => {
+                cache.remove(tenant);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, tenant: K, config: C, ttl: Duration) {
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (expires_at, _)| *expires_at > now);
+        cache.insert(tenant, (now + ttl, config));
+    }
+
+    fn invalidate(&self, tenant: &K) {
+        self.cache.lock().unwrap().remove(tenant);
+    }
+}
+
+/// [`Layer`] for resolving the configuration of the tenant of each request
+/// and inserting it into the [request extensions].
+///
+/// See the [module docs](crate::tenant_config) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+pub struct TenantConfigLayer<K, R>
+where
+    R: ConfigResolver<K>,
+{
+    shared: Arc<Shared<K, R::Config, R>>,
+    ttl: Duration,
+}
+
+impl<K, R> TenantConfigLayer<K, R>
+where
+    R: ConfigResolver<K>,
+{
+    /// Create a new [`TenantConfigLayer`].
+    ///
+    /// `resolver` is used to resolve the configuration of tenants that are not cached.
+    pub fn new(resolver: R) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                resolver,
+                cache: Mutex::new(HashMap::new()),
+            }),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Set how long a resolved configuration is cached.
+    ///
+    /// Defaults to 60 seconds.
+    ///
+    /// Clones of the layer, and the services it produced before, share their cache with
+    /// this layer, but keep caching configurations for their own TTL.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Remove the cached configuration of `tenant`,
+    /// such that it is resolved again for the next request.
+    pub fn invalidate(&self, tenant: &K)
+    where
+        K: Eq + Hash,
+        R::Config: Clone,
+    {
+        self.shared.invalidate(tenant);
+    }
+}
+
+impl<K, R> Clone for TenantConfigLayer<K, R>
+where
+    R: ConfigResolver<K>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<K, R> fmt::Debug for TenantConfigLayer<K, R>
+where
+    R: ConfigResolver<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantConfigLayer")
+            .field("ttl", &self.ttl)
+            .field("resolver", &format_args!("{}", std::any::type_name::<R>()))
+            .finish()
+    }
+}
+
+impl<S, K, R> Layer<S> for TenantConfigLayer<K, R>
+where
+    R: ConfigResolver<K>,
+{
+    type Service = TenantConfig<S, K, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantConfig {
+            inner,
+            shared: self.shared.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+/// Middleware for resolving the configuration of the tenant of each request
+/// and inserting it into the [request extensions].
+///
+/// See the [module docs](crate::tenant_config) for more details.
+///
+/// [request extensions]: https://docs.rs/http/latest/http/struct.Extensions.html
+pub struct TenantConfig<S, K, R>
+where
+    R: ConfigResolver<K>,
+{
+    inner: S,
+    shared: Arc<Shared<K, R::Config, R>>,
+    ttl: Duration,
+}
+
+impl<S, K, R> TenantConfig<S, K, R>
+where
+    R: ConfigResolver<K>,
+{
+    /// Create a new [`TenantConfig`].
+    ///
+    /// `resolver` is used to resolve the configuration of tenants that are not cached.
+    pub fn new(inner: S, resolver: R) -> Self {
+        TenantConfigLayer::new(resolver).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `TenantConfig` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(resolver: R) -> TenantConfigLayer<K, R> {
+        TenantConfigLayer::new(resolver)
+    }
+
+    /// Remove the cached configuration of `tenant`,
+    /// such that it is resolved again for the next request.
+    pub fn invalidate(&self, tenant: &K)
+    where
+        K: Eq + Hash,
+        R::Config: Clone,
+    {
+        self.shared.invalidate(tenant);
+    }
+}
+
+impl<S, K, R> Clone for TenantConfig<S, K, R>
+where
+    S: Clone,
+    R: ConfigResolver<K>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<S, K, R> fmt::Debug for TenantConfig<S, K, R>
+where
+    S: fmt::Debug,
+    R: ConfigResolver<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantConfig")
+            .field("inner", &self.inner)
+            .field("ttl", &self.ttl)
+            .field("resolver", &format_args!("{}", std::any::type_name::<R>()))
+            .finish()
+    }
+}
+
+impl<ReqBody, S, K, R> Service<Request<ReqBody>> for TenantConfig<S, K, R>
+where
+    S: Service<Request<ReqBody>>,
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    R: ConfigResolver<K>,
+    R::Config: Clone + Send + Sync + 'static,
+    R::Error: Into<S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if let Some(tenant) = req.extensions().get::<K>().cloned() {
+            let config = match self.shared.get(&tenant) {
+                Some(config) => config,
+                None => {
+                    let config = self
+                        .shared
+                        .resolver
+                        .resolve(tenant.clone())
+                        .await
+                        .map_err(Into::into)?;
+                    self.shared.insert(tenant, config.clone(), self.ttl);
+                    config
+                }
+            };
+            req.extensions_mut().insert(config);
+        }
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use crate::BoxError;
+
+    use http::Response;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Tenant(&'static str);
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Limits(u32);
+
+    fn request(tenant: Option<&'static str>) -> Request<Body> {
+        let mut req = Request::new(Body::empty());
+        if let Some(tenant) = tenant {
+            req.extensions_mut().insert(Tenant(tenant));
+        }
+        req
+    }
+
+    async fn echo_limits(req: Request<Body>) -> Result<Response<Option<Limits>>, BoxError> {
+        Ok(Response::new(req.extensions().get::<Limits>().cloned()))
+    }
+
+    fn counting_resolver(
+        resolved: Arc<AtomicUsize>,
+    ) -> impl Fn(Tenant) -> std::future::Ready<Result<Limits, BoxError>> + Clone {
+        move |tenant: Tenant| {
+            resolved.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(match tenant.0 {
+                "acme" => Ok(Limits(100)),
+                "globex" => Ok(Limits(10)),
+                _ => Err("unknown tenant".into()),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn config_is_resolved_per_tenant_and_cached() {
+        let resolved = Arc::new(AtomicUsize::new(0));
+        let svc = ServiceBuilder::new()
+            .layer(TenantConfigLayer::new(counting_resolver(resolved.clone())))
+            .service_fn(echo_limits);
+
+        for _ in 0..3 {
+            let res = svc.call(request(Some("acme"))).await.unwrap();
+            assert_eq!(res.into_body(), Some(Limits(100)));
+        }
+        let res = svc.call(request(Some("globex"))).await.unwrap();
+        assert_eq!(res.into_body(), Some(Limits(10)));
+        assert_eq!(resolved.load(Ordering::SeqCst), 2);
+
+        svc.invalidate(&Tenant("acme"));
+        svc.call(request(Some("acme"))).await.unwrap();
+        assert_eq!(resolved.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn config_expires_after_ttl() {
+        let resolved = Arc::new(AtomicUsize::new(0));
+        let layer = TenantConfigLayer::new(counting_resolver(resolved.clone()));
+        // the TTL can be set on a layer which was cloned already
+        let svc = layer
+            .clone()
+            .ttl(Duration::ZERO)
+            .layer(service_fn(echo_limits));
+
+        svc.call(request(Some("acme"))).await.unwrap();
+        svc.call(request(Some("acme"))).await.unwrap();
+        assert_eq!(resolved.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn requests_without_tenant_are_passed_through() {
+        let resolved = Arc::new(AtomicUsize::new(0));
+        let svc = TenantConfig::new(service_fn(echo_limits), counting_resolver(resolved.clone()));
+
+        let res = svc.call(request(None)).await.unwrap();
+        assert_eq!(res.into_body(), None);
+        assert_eq!(resolved.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn resolution_errors_are_returned_and_not_cached() {
+        let resolved = Arc::new(AtomicUsize::new(0));
+        let svc = TenantConfig::new(service_fn(echo_limits), counting_resolver(resolved.clone()));
+
+        for _ in 0..2 {
+            let err = svc.call(request(Some("initech"))).await.unwrap_err();
+            assert_eq!(err.to_string(), "unknown tenant");
+        }
+        assert_eq!(resolved.load(Ordering::SeqCst), 2);
+    }
+}
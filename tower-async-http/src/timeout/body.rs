@@ -0,0 +1,91 @@
+use crate::BoxError;
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep, Sleep};
+
+pin_project! {
+    /// Request body for [`RequestBodyTimeout`].
+    ///
+    /// Fails with a [`TimeoutError`] if it is read after the timeout has elapsed.
+    ///
+    /// [`RequestBodyTimeout`]: super::RequestBodyTimeout
+    pub struct TimeoutBody<B> {
+        #[pin]
+        inner: B,
+        #[pin]
+        sleep: Sleep,
+        timeout: Duration,
+    }
+}
+
+impl<B> TimeoutBody<B> {
+    /// Creates a new [`TimeoutBody`], timing out after the given duration.
+    pub fn new(inner: B, timeout: Duration) -> Self {
+        Self {
+            inner,
+            sleep: sleep(timeout),
+            timeout,
+        }
+    }
+}
+
+impl<B> fmt::Debug for TimeoutBody<B>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutBody")
+            .field("inner", &self.inner)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<B> Body for TimeoutBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if this.sleep.poll(cx).is_ready() {
+            return Poll::Ready(Some(Err(TimeoutError { _priv: () }.into())));
+        }
+        this.inner.poll_frame(cx).map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Error returned by [`TimeoutBody`] if the timeout elapsed before the body was read.
+#[derive(Debug)]
+pub struct TimeoutError {
+    _priv: (),
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request body timed out")
+    }
+}
+
+impl std::error::Error for TimeoutError {}
@@ -38,8 +38,120 @@
 //! # }
 //! ```
 //!
+//! # Aborting request bodies
+//!
+//! When the timeout fires, the handler is dropped, but it might have read only part of the
+//! request body, or passed the body on to a task that keeps reading it. Combine the
+//! [`Timeout`] with a [`RequestBodyTimeout`] of the same duration to abort reading the body
+//! when the timeout fires, and enable [`TimeoutLayer::close_connection`] such that the client
+//! doesn't send another request over a connection with a half-consumed body:
+//!
+//! ```
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::ServiceBuilder;
+//! use tower_async_http::timeout::{RequestBodyTimeoutLayer, TimeoutBody, TimeoutLayer};
+//!
+//! async fn handle(_: Request<TimeoutBody<Full<Bytes>>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let timeout = Duration::from_secs(30);
+//! let svc = ServiceBuilder::new()
+//!     .layer(TimeoutLayer::new(timeout).close_connection(true))
+//!     .layer(RequestBodyTimeoutLayer::new(timeout))
+//!     .service_fn(handle);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Timeout tiers
+//!
+//! [`TieredTimeout`] applies a different timeout to requests depending on their priority, read
+//! from the request extensions, such as a short timeout for interactive requests and a long one
+//! for batch requests. The tier that applied is inserted into the response extensions as a
+//! [`TimeoutTier`].
+//!
 //! [`Infallible`]: std::convert::Infallible
 
+mod body;
 mod service;
+mod tiered;
+
+pub use body::{TimeoutBody, TimeoutError};
+pub use service::{RequestBodyTimeout, RequestBodyTimeoutLayer, Timeout, TimeoutLayer};
+pub use tiered::{TieredTimeout, TieredTimeoutLayer, TimeoutTier};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use crate::BoxError;
+    use http::{header, Request, Response, StatusCode, Version};
+    use http_body_util::BodyExt;
+    use std::{convert::Infallible, time::Duration};
+    use tower_async::{dynamic::Dynamic, Service, ServiceBuilder};
+
+    #[tokio::test(start_paused = true)]
+    async fn closes_http1_connections() {
+        let svc = ServiceBuilder::new()
+            .layer(TimeoutLayer::new(Duration::from_secs(1)).close_connection(true))
+            .service_fn(|_: Request<Body>| async {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(res.headers()[header::CONNECTION], "close");
+
+        let req = Request::builder()
+            .version(Version::HTTP_2)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+        assert!(res.headers().get(header::CONNECTION).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dynamic_timeout() {
+        let timeout = Dynamic::new(Duration::from_secs(3));
+        let svc = ServiceBuilder::new()
+            .layer(TimeoutLayer::new(timeout.clone()))
+            .service_fn(|_: Request<Body>| async {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        timeout.set(Duration::from_secs(1));
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn aborts_request_body() {
+        let svc = ServiceBuilder::new()
+            .layer(RequestBodyTimeoutLayer::new(Duration::from_secs(1)))
+            .service_fn(|req: Request<TimeoutBody<Body>>| async move {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                let body = req.into_body().collect().await?.to_bytes();
+                Ok::<_, BoxError>(Response::new(Body::from(body)))
+            });
 
-pub use service::{Timeout, TimeoutLayer};
+        let err = svc
+            .call(Request::new(Body::from("late")))
+            .await
+            .unwrap_err();
+        assert!(err.is::<TimeoutError>());
+    }
+}
@@ -1,20 +1,37 @@
-use http::{Request, Response, StatusCode};
+use super::body::TimeoutBody;
+use http::{header, HeaderValue, Request, Response, StatusCode, Version};
 use std::time::Duration;
+use tower_async::dynamic::Dynamic;
 use tower_async_layer::Layer;
 use tower_async_service::Service;
 
 /// Layer that applies the [`Timeout`] middleware which apply a timeout to requests.
 ///
 /// See the [module docs](super) for an example.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct TimeoutLayer {
-    timeout: Duration,
+    timeout: Dynamic<Duration>,
+    close_connection: bool,
 }
 
 impl TimeoutLayer {
     /// Creates a new [`TimeoutLayer`].
-    pub fn new(timeout: Duration) -> Self {
-        TimeoutLayer { timeout }
+    ///
+    /// The timeout can be a [`Dynamic`] duration, in which case updates apply to subsequent
+    /// requests of all services produced by the layer.
+    pub fn new(timeout: impl Into<Dynamic<Duration>>) -> Self {
+        TimeoutLayer {
+            timeout: timeout.into(),
+            close_connection: false,
+        }
+    }
+
+    /// Mark `408 Request Timeout` responses to HTTP/1 requests with `Connection: close`.
+    ///
+    /// See [`Timeout::close_connection`] for more details.
+    pub fn close_connection(mut self, close_connection: bool) -> Self {
+        self.close_connection = close_connection;
+        self
     }
 }
 
@@ -22,7 +39,7 @@ impl<S> Layer<S> for TimeoutLayer {
     type Service = Timeout<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        Timeout::new(inner, self.timeout)
+        Timeout::new(inner, self.timeout.clone()).close_connection(self.close_connection)
     }
 }
 
@@ -32,16 +49,24 @@ impl<S> Layer<S> for TimeoutLayer {
 /// Request Timeout` response will be sent.
 ///
 /// See the [module docs](super) for an example.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Timeout<S> {
     inner: S,
-    timeout: Duration,
+    timeout: Dynamic<Duration>,
+    close_connection: bool,
 }
 
 impl<S> Timeout<S> {
     /// Creates a new [`Timeout`].
-    pub fn new(inner: S, timeout: Duration) -> Self {
-        Self { inner, timeout }
+    ///
+    /// The timeout can be a [`Dynamic`] duration, in which case updates apply to subsequent
+    /// requests.
+    pub fn new(inner: S, timeout: impl Into<Dynamic<Duration>>) -> Self {
+        Self {
+            inner,
+            timeout: timeout.into(),
+            close_connection: false,
+        }
     }
 
     define_inner_service_accessors!();
@@ -49,9 +74,22 @@ impl<S> Timeout<S> {
     /// Returns a new [`Layer`] that wraps services with a `Timeout` middleware.
     ///
     /// [`Layer`]: tower_async_layer::Layer
-    pub fn layer(timeout: Duration) -> TimeoutLayer {
+    pub fn layer(timeout: impl Into<Dynamic<Duration>>) -> TimeoutLayer {
         TimeoutLayer::new(timeout)
     }
+
+    /// Mark `408 Request Timeout` responses to HTTP/1 requests with `Connection: close`.
+    ///
+    /// A handler that times out might have read only part of the request body, leaving
+    /// the connection in a state where the next request can't be read reliably. Closing
+    /// the connection avoids that. HTTP/2 and later don't have this problem, and don't
+    /// allow the `Connection` header, so their responses are left as they are.
+    ///
+    /// Defaults to `false`.
+    pub fn close_connection(mut self, close_connection: bool) -> Self {
+        self.close_connection = close_connection;
+        self
+    }
 }
 
 impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Timeout<S>
@@ -63,13 +101,89 @@ where
     type Error = S::Error;
 
     async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let version = req.version();
         tokio::select! {
             res = self.inner.call(req) => res,
-            _ = tokio::time::sleep(self.timeout) => {
+            _ = tokio::time::sleep(self.timeout.get()) => {
                 let mut res = Response::new(ResBody::default());
                 *res.status_mut() = StatusCode::REQUEST_TIMEOUT;
+                if self.close_connection && version <= Version::HTTP_11 {
+                    res.headers_mut()
+                        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+                }
                 Ok(res)
             }
         }
     }
 }
+
+/// Layer that applies the [`RequestBodyTimeout`] middleware which aborts reading
+/// request bodies once a timeout has elapsed.
+///
+/// See the [module docs](super) for an example.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestBodyTimeoutLayer {
+    timeout: Duration,
+}
+
+impl RequestBodyTimeoutLayer {
+    /// Creates a new [`RequestBodyTimeoutLayer`].
+    pub fn new(timeout: Duration) -> Self {
+        RequestBodyTimeoutLayer { timeout }
+    }
+}
+
+impl<S> Layer<S> for RequestBodyTimeoutLayer {
+    type Service = RequestBodyTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestBodyTimeout::new(inner, self.timeout)
+    }
+}
+
+/// Middleware which aborts reading request bodies once a timeout has elapsed.
+///
+/// The timeout starts when the request is received, such that the body is aborted
+/// at the same time a [`Timeout`] with the same duration fires. Reading the body after
+/// that fails with a [`TimeoutError`], including reads by tasks the handler spawned,
+/// which would otherwise keep reading from the connection.
+///
+/// See the [module docs](super) for an example.
+///
+/// [`TimeoutError`]: super::TimeoutError
+#[derive(Debug, Clone, Copy)]
+pub struct RequestBodyTimeout<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S> RequestBodyTimeout<S> {
+    /// Creates a new [`RequestBodyTimeout`].
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `RequestBodyTimeout` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(timeout: Duration) -> RequestBodyTimeoutLayer {
+        RequestBodyTimeoutLayer::new(timeout)
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RequestBodyTimeout<S>
+where
+    S: Service<Request<TimeoutBody<ReqBody>>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let timeout = self.timeout;
+        self.inner
+            .call(req.map(|body| TimeoutBody::new(body, timeout)))
+            .await
+    }
+}
@@ -0,0 +1,308 @@
+use http::{header, HeaderValue, Request, Response, StatusCode, Version};
+use std::{sync::Arc, time::Duration};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`TieredTimeout`] middleware, which applies a timeout to requests
+/// based on their priority.
+///
+/// See [`TieredTimeout`] for more details.
+#[derive(Debug, Clone)]
+pub struct TieredTimeoutLayer<P> {
+    tiers: Arc<Vec<(P, Duration)>>,
+    default: Duration,
+    close_connection: bool,
+}
+
+impl<P> TieredTimeoutLayer<P> {
+    /// Creates a new [`TieredTimeoutLayer`], applying the `default` timeout to requests
+    /// without a priority, or with a priority without tier.
+    pub fn new(default: Duration) -> Self {
+        TieredTimeoutLayer {
+            tiers: Arc::new(Vec::new()),
+            default,
+            close_connection: false,
+        }
+    }
+
+    /// Applies the given timeout to requests with the given priority.
+    ///
+    /// If a tier for the priority already exists, its timeout is replaced.
+    pub fn tier(mut self, priority: P, timeout: Duration) -> Self
+    where
+        P: PartialEq + Clone,
+    {
+        let tiers = Arc::make_mut(&mut self.tiers);
+        match tiers.iter_mut().find(|(other, _)| *other == priority) {
+            Some((_, existing)) => *existing = timeout,
+            None => tiers.push((priority, timeout)),
+        }
+        self
+    }
+
+    /// Mark `408 Request Timeout` responses to HTTP/1 requests with `Connection: close`.
+    ///
+    /// See [`Timeout::close_connection`] for more details.
+    ///
+    /// [`Timeout::close_connection`]: super::Timeout::close_connection
+    pub fn close_connection(mut self, close_connection: bool) -> Self {
+        self.close_connection = close_connection;
+        self
+    }
+}
+
+impl<S, P> Layer<S> for TieredTimeoutLayer<P> {
+    type Service = TieredTimeout<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TieredTimeout {
+            inner,
+            tiers: self.tiers.clone(),
+            default: self.default,
+            close_connection: self.close_connection,
+        }
+    }
+}
+
+/// Middleware which applies a timeout to requests, based on their priority.
+///
+/// The priority of a request is read from its extensions, as a value of the priority type `P`,
+/// which is usually an enum defined by the application. Each priority can have its own timeout
+/// tier, such as a short timeout for interactive requests and a longer one for batch requests,
+/// while other requests get the default timeout.
+///
+/// If the request does not complete within its timeout it will be aborted and a `408 Request
+/// Timeout` response will be sent, like the [`Timeout`] middleware. The tier that applied is
+/// inserted into the extensions of the response, timed out or not, as a [`TimeoutTier`].
+///
+/// # Example
+///
+/// ```
+/// use http::{Request, Response, StatusCode};
+/// use http_body_util::Full;
+/// use bytes::Bytes;
+/// use std::{convert::Infallible, time::Duration};
+/// use tower_async::{service_fn, Service, ServiceBuilder};
+/// use tower_async_http::timeout::{TieredTimeoutLayer, TimeoutTier};
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// enum Priority {
+///     Interactive,
+///     Batch,
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// let svc = ServiceBuilder::new()
+///     .layer(
+///         TieredTimeoutLayer::new(Duration::from_secs(10))
+///             .tier(Priority::Interactive, Duration::from_secs(2))
+///             .tier(Priority::Batch, Duration::from_secs(30)),
+///     )
+///     .service(service_fn(|_: Request<Full<Bytes>>| async {
+///         tokio::time::sleep(Duration::from_secs(5)).await;
+///         Ok::<_, Infallible>(Response::new(Full::<Bytes>::default()))
+///     }));
+///
+/// let mut req = Request::new(Full::default());
+/// req.extensions_mut().insert(Priority::Interactive);
+/// let res = svc.call(req).await.unwrap();
+/// assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+///
+/// let mut req = Request::new(Full::default());
+/// req.extensions_mut().insert(Priority::Batch);
+/// let res = svc.call(req).await.unwrap();
+/// assert_eq!(res.status(), StatusCode::OK);
+///
+/// let tier = res.extensions().get::<TimeoutTier<Priority>>().unwrap();
+/// assert_eq!(tier.priority(), Some(&Priority::Batch));
+/// assert_eq!(tier.timeout(), Duration::from_secs(30));
+/// # }
+/// ```
+///
+/// [`Timeout`]: super::Timeout
+#[derive(Debug, Clone)]
+pub struct TieredTimeout<S, P> {
+    inner: S,
+    tiers: Arc<Vec<(P, Duration)>>,
+    default: Duration,
+    close_connection: bool,
+}
+
+impl<S, P> TieredTimeout<S, P> {
+    /// Creates a new [`TieredTimeout`], applying the `default` timeout to requests
+    /// without a priority, or with a priority without tier.
+    ///
+    /// Use [`TieredTimeoutLayer`] to configure the tiers.
+    pub fn new(inner: S, default: Duration) -> Self {
+        TieredTimeoutLayer::new(default).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `TieredTimeout` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(default: Duration) -> TieredTimeoutLayer<P> {
+        TieredTimeoutLayer::new(default)
+    }
+}
+
+impl<S, P, ReqBody, ResBody> Service<Request<ReqBody>> for TieredTimeout<S, P>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    P: PartialEq + Clone + Send + Sync + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let version = req.version();
+        let tier = req
+            .extensions()
+            .get::<P>()
+            .and_then(|priority| self.tiers.iter().find(|(other, _)| other == priority))
+            .map(|(priority, timeout)| TimeoutTier {
+                priority: Some(priority.clone()),
+                timeout: *timeout,
+            })
+            .unwrap_or(TimeoutTier {
+                priority: None,
+                timeout: self.default,
+            });
+
+        let mut res = tokio::select! {
+            res = self.inner.call(req) => res?,
+            _ = tokio::time::sleep(tier.timeout) => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::REQUEST_TIMEOUT;
+                if self.close_connection && version <= Version::HTTP_11 {
+                    res.headers_mut()
+                        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+                }
+                res
+            }
+        };
+        res.extensions_mut().insert(tier);
+        Ok(res)
+    }
+}
+
+/// The timeout tier applied by [`TieredTimeout`], inserted into the extensions of responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeoutTier<P> {
+    priority: Option<P>,
+    timeout: Duration,
+}
+
+impl<P> TimeoutTier<P> {
+    /// The priority of the tier, or `None` if the default timeout applied.
+    pub fn priority(&self) -> Option<&P> {
+        self.priority.as_ref()
+    }
+
+    /// The timeout of the tier.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Priority {
+        Interactive,
+        Batch,
+        Background,
+    }
+
+    async fn sleep(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let millis = req.uri().path()[1..].parse().unwrap();
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn request(millis: u64, priority: Option<Priority>) -> Request<Body> {
+        let mut req = Request::get(format!("/{millis}"))
+            .body(Body::empty())
+            .unwrap();
+        if let Some(priority) = priority {
+            req.extensions_mut().insert(priority);
+        }
+        req
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn applies_tier_of_priority() {
+        let svc = ServiceBuilder::new()
+            .layer(
+                TieredTimeoutLayer::new(Duration::from_millis(100))
+                    .tier(Priority::Interactive, Duration::from_millis(20))
+                    .tier(Priority::Batch, Duration::from_millis(300)),
+            )
+            .service_fn(sleep);
+
+        let cases = [
+            (
+                50,
+                Some(Priority::Interactive),
+                StatusCode::REQUEST_TIMEOUT,
+                Some(Priority::Interactive),
+                20,
+            ),
+            (
+                10,
+                Some(Priority::Interactive),
+                StatusCode::OK,
+                Some(Priority::Interactive),
+                20,
+            ),
+            (
+                200,
+                Some(Priority::Batch),
+                StatusCode::OK,
+                Some(Priority::Batch),
+                300,
+            ),
+            (
+                200,
+                Some(Priority::Background),
+                StatusCode::REQUEST_TIMEOUT,
+                None,
+                100,
+            ),
+            (200, None, StatusCode::REQUEST_TIMEOUT, None, 100),
+            (50, None, StatusCode::OK, None, 100),
+        ];
+        for (millis, priority, status, tier_priority, tier_timeout) in cases {
+            let res = svc.call(request(millis, priority)).await.unwrap();
+            assert_eq!(res.status(), status, "{millis}ms {priority:?}");
+            let tier = res.extensions().get::<TimeoutTier<Priority>>().unwrap();
+            assert_eq!(tier.priority(), tier_priority.as_ref());
+            assert_eq!(tier.timeout(), Duration::from_millis(tier_timeout));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tier_can_be_replaced() {
+        let svc = TieredTimeoutLayer::new(Duration::from_millis(100))
+            .tier(Priority::Batch, Duration::from_millis(10))
+            .tier(Priority::Batch, Duration::from_millis(300))
+            .close_connection(true)
+            .layer(service_fn(sleep));
+
+        let res = svc.call(request(200, Some(Priority::Batch))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = svc.call(request(200, None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(res.headers()[header::CONNECTION], "close");
+    }
+}
@@ -0,0 +1,620 @@
+//! Client middleware that enforces a TLS policy for the hosts requests are sent to.
+//!
+//! Clients often talk to hosts which must never be reached without TLS, or only with a recent
+//! version of the protocol, or only when they present a known public key. This module enforces
+//! such [`TlsRequirements`] per host, in two places:
+//!
+//! - the [`TlsPolicy`] middleware fails requests to a host requiring TLS with a URI which isn't
+//!   `https` with a [`TlsPolicyError`], before they are sent;
+//! - the [`TlsPolicyConnector`], wrapping the connector of the client, fails connections to such
+//!   a host which aren't encrypted, don't meet the minimum protocol version, or whose public key
+//!   doesn't match one of the pinned SPKI hashes, using the [`TlsInfo`] reported by the
+//!   [`TlsConnection`]. As this happens once the handshake completed, no request is ever
+//!   written to a connection violating the policy.
+//!
+//! Both are created from the same [`TlsPolicyLayer`], such that they share the policy. Every
+//! violation is reported to an [`OnTlsViolation`] callback before failing, such that it can be
+//! audited.
+//!
+//! Hosts are compared case-insensitively, without a trailing dot and without the brackets of
+//! IPv6 addresses, such that `https://API.example.com./` uses the policy of `api.example.com`.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, Uri};
+//! use http_body_util::Full;
+//! use std::convert::Infallible;
+//! use tower_async::{service_fn, Layer, Service, ServiceBuilder};
+//! use tower_async_http::tls_policy::{
+//!     TlsConnection, TlsInfo, TlsPolicyError, TlsPolicyLayer, TlsRequirements, TlsVersion,
+//! };
+//!
+//! // the connections of the client, reporting their negotiated TLS parameters
+//! struct Connection {
+//!     tls: Option<TlsInfo>,
+//! }
+//!
+//! impl TlsConnection for Connection {
+//!     fn tls_info(&self) -> Option<TlsInfo> {
+//!         self.tls.clone()
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! # let connector = service_fn(|_: Uri| async move {
+//! #     Ok::<_, Infallible>(Connection { tls: Some(TlsInfo::new(TlsVersion::Tls12)) })
+//! # });
+//! # let http_client = service_fn(|_: Request<Full<Bytes>>| async move {
+//! #     Ok::<_, Infallible>(Response::new(Full::<Bytes>::default()))
+//! # });
+//! let policy = TlsPolicyLayer::new()
+//!     .host("api.example.com", TlsRequirements::new())
+//!     .host(
+//!         "*.payments.example.com",
+//!         TlsRequirements::new().min_version(TlsVersion::Tls13),
+//!     )
+//!     .on_violation(|violation: &TlsPolicyError| {
+//!         eprintln!("TLS policy violated: {}", violation);
+//!     });
+//!
+//! // the connector negotiated TLS 1.2, so the connection is closed before sending anything
+//! let connector = policy.connector(connector);
+//! let uri = Uri::from_static("https://eu.payments.example.com");
+//! let err = connector.call(uri).await.err().unwrap();
+//! assert!(err.is::<TlsPolicyError>());
+//!
+//! // the request would be sent in plain text
+//! let client = ServiceBuilder::new().layer(policy).service(http_client);
+//! let req = Request::get("http://api.example.com").body(Full::default())?;
+//! let err = client.call(req).await.unwrap_err();
+//! assert!(err.is::<TlsPolicyError>());
+//! # Ok(())
+//! # }
+//! ```
+
+use http::{uri::Scheme, Request, Response, Uri};
+use std::{fmt, sync::Arc};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+use crate::BoxError;
+
+/// A version of the TLS protocol, ordered from the oldest to the most recent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.0.
+    Tls10,
+    /// TLS 1.1.
+    Tls11,
+    /// TLS 1.2.
+    Tls12,
+    /// TLS 1.3.
+    Tls13,
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsVersion::Tls10 => f.write_str("TLS 1.0"),
+            TlsVersion::Tls11 => f.write_str("TLS 1.1"),
+            TlsVersion::Tls12 => f.write_str("TLS 1.2"),
+            TlsVersion::Tls13 => f.write_str("TLS 1.3"),
+        }
+    }
+}
+
+/// The negotiated parameters of a TLS connection, reported by its [`TlsConnection`].
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    version: TlsVersion,
+    peer_spki_sha256: Option<[u8; 32]>,
+}
+
+impl TlsInfo {
+    /// Create a new [`TlsInfo`] of a connection which negotiated the given protocol version.
+    pub fn new(version: TlsVersion) -> Self {
+        Self {
+            version,
+            peer_spki_sha256: None,
+        }
+    }
+
+    /// Set the SHA-256 hash of the DER-encoded SubjectPublicKeyInfo of the certificate
+    /// presented by the peer.
+    pub fn peer_spki_sha256(mut self, hash: [u8; 32]) -> Self {
+        self.peer_spki_sha256 = Some(hash);
+        self
+    }
+
+    /// Returns the negotiated protocol version.
+    pub fn version(&self) -> TlsVersion {
+        self.version
+    }
+
+    /// Returns the SHA-256 hash of the SubjectPublicKeyInfo of the peer, if reported.
+    pub fn get_peer_spki_sha256(&self) -> Option<&[u8; 32]> {
+        self.peer_spki_sha256.as_ref()
+    }
+}
+
+/// The TLS requirements of a host.
+///
+/// Every host with requirements requires TLS, see the [module docs](self) for more details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsRequirements {
+    min_version: Option<TlsVersion>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl TlsRequirements {
+    /// Create new [`TlsRequirements`], only requiring TLS.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require at least the given protocol version.
+    pub fn min_version(mut self, version: TlsVersion) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    /// Pin the SHA-256 hash of the SubjectPublicKeyInfo of a certificate of the host.
+    ///
+    /// Pinning multiple hashes, such as the one of a backup key, allows any of them.
+    pub fn pin_spki_sha256(mut self, hash: [u8; 32]) -> Self {
+        self.pins.push(hash);
+        self
+    }
+
+    fn check(&self, info: Option<&TlsInfo>) -> Result<(), TlsViolation> {
+        let info = info.ok_or(TlsViolation::NotEncrypted)?;
+        if let Some(minimum) = self.min_version {
+            if info.version < minimum {
+                return Err(TlsViolation::ProtocolVersion {
+                    negotiated: info.version,
+                    minimum,
+                });
+            }
+        }
+        if !self.pins.is_empty()
+            && !matches!(&info.peer_spki_sha256, Some(hash) if self.pins.contains(hash))
+        {
+            return Err(TlsViolation::PinMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The way a request violated the TLS policy of its host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TlsViolation {
+    /// The request would have been sent without TLS.
+    NotEncrypted,
+    /// The connection negotiated a protocol version older than the minimum.
+    ProtocolVersion {
+        /// The version negotiated by the connection.
+        negotiated: TlsVersion,
+        /// The minimum version required for the host.
+        minimum: TlsVersion,
+    },
+    /// The public key of the peer didn't match any of the pinned hashes.
+    PinMismatch,
+}
+
+/// Error returned by [`TlsPolicy`] and [`TlsPolicyConnector`] for requests and connections
+/// violating the TLS policy of their host.
+#[derive(Debug, Clone)]
+pub struct TlsPolicyError {
+    host: String,
+    violation: TlsViolation,
+}
+
+impl TlsPolicyError {
+    /// Returns the host the request was sent to.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the way the request violated the policy.
+    pub fn violation(&self) -> TlsViolation {
+        self.violation
+    }
+}
+
+impl fmt::Display for TlsPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TLS policy of {} violated: ", self.host)?;
+        match self.violation {
+            TlsViolation::NotEncrypted => f.write_str("request would be sent without TLS"),
+            TlsViolation::ProtocolVersion {
+                negotiated,
+                minimum,
+            } => write!(
+                f,
+                "negotiated {}, but at least {} is required",
+                negotiated, minimum
+            ),
+            TlsViolation::PinMismatch => f.write_str("public key doesn't match any pin"),
+        }
+    }
+}
+
+impl std::error::Error for TlsPolicyError {}
+
+/// Trait used to tell [`TlsPolicy`] and [`TlsPolicyConnector`] what to do when a request or
+/// connection violates the policy,
+/// such as recording an audit event.
+pub trait OnTlsViolation {
+    /// Do the thing.
+    fn on_tls_violation(&self, error: &TlsPolicyError);
+}
+
+impl OnTlsViolation for () {
+    #[inline]
+    fn on_tls_violation(&self, _: &TlsPolicyError) {}
+}
+
+impl<F> OnTlsViolation for F
+where
+    F: Fn(&TlsPolicyError),
+{
+    fn on_tls_violation(&self, error: &TlsPolicyError) {
+        self(error)
+    }
+}
+
+/// Layer that applies the [`TlsPolicy`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Clone)]
+pub struct TlsPolicyLayer<F = ()> {
+    hosts: Arc<Vec<(String, TlsRequirements)>>,
+    on_violation: F,
+}
+
+impl TlsPolicyLayer {
+    /// Create a new [`TlsPolicyLayer`], without requirements for any host.
+    pub fn new() -> Self {
+        Self {
+            hosts: Arc::new(Vec::new()),
+            on_violation: (),
+        }
+    }
+}
+
+impl Default for TlsPolicyLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> TlsPolicyLayer<F> {
+    /// Set the requirements of the given host.
+    ///
+    /// A host starting with `*.` applies to all its subdomains, such as `*.example.com` to
+    /// `api.example.com`, but not to `example.com` itself. Requirements of an exact host take
+    /// precedence over the ones of its subdomains, and later calls over earlier ones.
+    pub fn host(mut self, host: impl Into<String>, requirements: TlsRequirements) -> Self {
+        let host = normalize_host(&host.into());
+        let hosts = Arc::make_mut(&mut self.hosts);
+        hosts.retain(|(h, _)| *h != host);
+        hosts.push((host, requirements));
+        self
+    }
+
+    /// Call the given [`OnTlsViolation`] for every request or connection violating the policy.
+    pub fn on_violation<F2>(self, on_violation: F2) -> TlsPolicyLayer<F2> {
+        TlsPolicyLayer {
+            hosts: self.hosts,
+            on_violation,
+        }
+    }
+
+    /// Wrap the given connector with a [`TlsPolicyConnector`] enforcing this policy.
+    pub fn connector<C>(&self, connector: C) -> TlsPolicyConnector<C, F>
+    where
+        F: Clone,
+    {
+        TlsPolicyConnector {
+            inner: connector,
+            layer: self.clone(),
+        }
+    }
+
+    fn requirements(&self, host: &str) -> Option<&TlsRequirements> {
+        let exact = self.hosts.iter().find(|(h, _)| *h == host);
+        exact
+            .or_else(|| {
+                self.hosts.iter().find(|(h, _)| {
+                    h.strip_prefix('*')
+                        .is_some_and(|suffix| suffix.starts_with('.') && host.ends_with(suffix))
+                })
+            })
+            .map(|(_, requirements)| requirements)
+    }
+}
+
+impl<F> fmt::Debug for TlsPolicyLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsPolicyLayer")
+            .field("hosts", &self.hosts)
+            .field(
+                "on_violation",
+                &format_args!("{}", std::any::type_name::<F>()),
+            )
+            .finish()
+    }
+}
+
+impl<S, F> Layer<S> for TlsPolicyLayer<F>
+where
+    F: Clone,
+{
+    type Service = TlsPolicy<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TlsPolicy {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Middleware that enforces the TLS policy of the hosts requests are sent to.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct TlsPolicy<S, F = ()> {
+    inner: S,
+    layer: TlsPolicyLayer<F>,
+}
+
+impl<S> TlsPolicy<S> {
+    /// Create a new [`TlsPolicy`], without requirements for any host.
+    pub fn new(inner: S) -> Self {
+        TlsPolicyLayer::new().layer(inner)
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `TlsPolicy` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> TlsPolicyLayer {
+        TlsPolicyLayer::new()
+    }
+}
+
+impl<S, F> TlsPolicy<S, F> {
+    define_inner_service_accessors!();
+}
+
+impl<S, F> fmt::Debug for TlsPolicy<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsPolicy")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, F, ReqBody, ResBody> Service<Request<ReqBody>> for TlsPolicy<S, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    F: OnTlsViolation,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let host = normalize_host(req.uri().host().unwrap_or_default());
+        if self.layer.requirements(&host).is_some() && req.uri().scheme() != Some(&Scheme::HTTPS) {
+            return Err(self.layer.violation(host, TlsViolation::NotEncrypted));
+        }
+        self.inner.call(req).await.map_err(Into::into)
+    }
+}
+
+/// A connection established by the connector wrapped by a [`TlsPolicyConnector`].
+pub trait TlsConnection {
+    /// Returns the negotiated parameters of the connection,
+    /// or `None` if it isn't encrypted.
+    fn tls_info(&self) -> Option<TlsInfo>;
+}
+
+/// Connector that enforces the TLS policy of the hosts it connects to,
+/// created by [`TlsPolicyLayer::connector`].
+///
+/// Connections violating the policy are dropped before they are returned to the client,
+/// such that no request is written to them. See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct TlsPolicyConnector<S, F = ()> {
+    inner: S,
+    layer: TlsPolicyLayer<F>,
+}
+
+impl<S, F> TlsPolicyConnector<S, F> {
+    define_inner_service_accessors!();
+}
+
+impl<C, F> fmt::Debug for TlsPolicyConnector<C, F>
+where
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsPolicyConnector")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<C, F> Service<Uri> for TlsPolicyConnector<C, F>
+where
+    C: Service<Uri>,
+    C::Response: TlsConnection,
+    C::Error: Into<BoxError>,
+    F: OnTlsViolation,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+
+    async fn call(&self, uri: Uri) -> Result<Self::Response, Self::Error> {
+        let host = normalize_host(uri.host().unwrap_or_default());
+        let conn = self.inner.call(uri).await.map_err(Into::into)?;
+        if let Some(requirements) = self.layer.requirements(&host) {
+            if let Err(violation) = requirements.check(conn.tls_info().as_ref()) {
+                return Err(self.layer.violation(host, violation));
+            }
+        }
+        Ok(conn)
+    }
+}
+
+impl<F: OnTlsViolation> TlsPolicyLayer<F> {
+    fn violation(&self, host: String, violation: TlsViolation) -> BoxError {
+        let error = TlsPolicyError { host, violation };
+        self.on_violation.on_tls_violation(&error);
+        error.into()
+    }
+}
+
+/// Lowercases the host, and strips its trailing dot and the brackets of IPv6 addresses.
+fn normalize_host(host: &str) -> String {
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::sync::Mutex;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    const PIN: [u8; 32] = [7; 32];
+
+    struct Connection(Option<TlsInfo>);
+
+    impl TlsConnection for Connection {
+        fn tls_info(&self) -> Option<TlsInfo> {
+            self.0.clone()
+        }
+    }
+
+    fn policy(violations: Arc<Mutex<Vec<String>>>) -> TlsPolicyLayer<impl OnTlsViolation + Clone> {
+        TlsPolicyLayer::new()
+            .host("plain.example.com", TlsRequirements::new())
+            .host(
+                "*.example.com",
+                TlsRequirements::new()
+                    .min_version(TlsVersion::Tls13)
+                    .pin_spki_sha256(PIN),
+            )
+            .host("[::1]", TlsRequirements::new())
+            .on_violation(move |err: &TlsPolicyError| {
+                violations.lock().unwrap().push(err.host().to_owned())
+            })
+    }
+
+    #[tokio::test]
+    async fn enforces_requirements_of_connections() {
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let connector = policy(violations.clone()).connector(service_fn(|uri: Uri| async move {
+            let info = match uri.path() {
+                "/tls12" => Some(TlsInfo::new(TlsVersion::Tls12).peer_spki_sha256(PIN)),
+                "/tls13" => Some(TlsInfo::new(TlsVersion::Tls13).peer_spki_sha256(PIN)),
+                "/other-key" => Some(TlsInfo::new(TlsVersion::Tls13).peer_spki_sha256([0; 32])),
+                _ => None,
+            };
+            Ok::<_, BoxError>(Connection(info))
+        }));
+        let connect = |uri: &'static str| {
+            let connector = &connector;
+            async move {
+                connector
+                    .call(Uri::from_static(uri))
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.downcast_ref::<TlsPolicyError>().unwrap().violation())
+            }
+        };
+
+        assert!(connect("https://api.example.com/tls13").await.is_ok());
+        assert_eq!(
+            connect("https://api.example.com/tls12").await.unwrap_err(),
+            TlsViolation::ProtocolVersion {
+                negotiated: TlsVersion::Tls12,
+                minimum: TlsVersion::Tls13
+            }
+        );
+        assert_eq!(
+            connect("https://API.example.com./other-key")
+                .await
+                .unwrap_err(),
+            TlsViolation::PinMismatch
+        );
+        assert_eq!(
+            connect("https://api.example.com/").await.unwrap_err(),
+            TlsViolation::NotEncrypted
+        );
+
+        // the exact host only requires TLS, and other hosts have no requirements
+        assert!(connect("https://plain.example.com/tls12").await.is_ok());
+        assert!(connect("http://example.com/").await.is_ok());
+
+        assert_eq!(
+            *violations.lock().unwrap(),
+            ["api.example.com", "api.example.com", "api.example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_without_tls() {
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let client = ServiceBuilder::new()
+            .layer(policy(violations.clone()))
+            .service(service_fn(|_: Request<Body>| async move {
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            }));
+        let call = |uri: &'static str| {
+            let req = Request::get(uri).body(Body::empty()).unwrap();
+            client.call(req)
+        };
+
+        assert!(call("https://plain.example.com/").await.is_ok());
+        assert!(call("http://example.com/").await.is_ok());
+        for uri in [
+            "http://plain.example.com/",
+            "http://plain.example.com./",
+            "http://api.example.com/",
+            "http://[::1]/",
+        ] {
+            let err = call(uri).await.unwrap_err();
+            let err = err.downcast_ref::<TlsPolicyError>().unwrap();
+            assert_eq!(err.violation(), TlsViolation::NotEncrypted);
+        }
+        assert_eq!(
+            *violations.lock().unwrap(),
+            [
+                "plain.example.com",
+                "plain.example.com",
+                "api.example.com",
+                "::1"
+            ]
+        );
+    }
+}
@@ -1,6 +1,14 @@
-use super::{OnBodyChunk, OnEos, OnFailure};
-use crate::classify::ClassifyEos;
+use super::{
+    on_abort::AbortGuard, AbortReason, DefaultOnAbort, OnBodyChunk, OnEos, OnFailure,
+    RequestMetadata,
+};
+use crate::{
+    body_size::{is_bodiless, BodySizeCounter},
+    classify::ClassifyEos,
+};
+use bytes::Buf;
 use futures_core::ready;
+use http::StatusCode;
 use http_body::{Body, Frame};
 use pin_project_lite::pin_project;
 use std::{
@@ -15,20 +23,39 @@ pin_project! {
     /// Response body for [`Trace`].
     ///
     /// [`Trace`]: super::Trace
-    pub struct ResponseBody<B, C, OnBodyChunk, OnEos, OnFailure> {
+    pub struct ResponseBody<B, C, OnBodyChunk, OnEos, OnFailure, OnAbort = DefaultOnAbort> {
         #[pin]
         pub(crate) inner: B,
         pub(crate) classify_eos: Option<C>,
         pub(crate) on_eos: Option<(OnEos, Instant)>,
+        pub(crate) size: BodySizeCounter,
         pub(crate) on_body_chunk: OnBodyChunk,
         pub(crate) on_failure: Option<OnFailure>,
+        pub(crate) on_abort: AbortGuard<OnAbort>,
+        pub(crate) sampled: bool,
+        pub(crate) request: RequestMetadata,
         pub(crate) start: Instant,
         pub(crate) span: Span,
     }
 }
 
-impl<B, C, OnBodyChunkT, OnEosT, OnFailureT> Body
-    for ResponseBody<B, C, OnBodyChunkT, OnEosT, OnFailureT>
+impl<B, C, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    ResponseBody<B, C, OnBodyChunk, OnEos, OnFailure, OnAbort>
+where
+    B: Body,
+{
+    pub(crate) fn disarm_abort_if_unneeded(mut self, status: StatusCode) -> Self {
+        // aborts are not traced for unsampled requests, nor for bodies which are never sent
+        if !self.sampled || self.inner.is_end_stream() || is_bodiless(self.request.method(), status)
+        {
+            self.on_abort.disarm();
+        }
+        self
+    }
+}
+
+impl<B, C, OnBodyChunkT, OnEosT, OnFailureT, OnAbortT> Body
+    for ResponseBody<B, C, OnBodyChunkT, OnEosT, OnFailureT, OnAbortT>
 where
     B: Body,
     B::Error: fmt::Display,
@@ -44,18 +71,26 @@ where
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        let this = self.project();
+        let mut this = self.project();
         let _guard = this.span.enter();
-        let result = ready!(this.inner.poll_frame(cx));
+        let result = ready!(this.inner.as_mut().poll_frame(cx));
 
         let latency = this.start.elapsed();
         *this.start = Instant::now();
 
+        // the body was fully consumed, so dropping it from here on is no longer an abort
+        if result.is_none() || this.inner.is_end_stream() {
+            this.on_abort.disarm();
+        }
+
         match result {
             Some(Ok(frame)) => {
                 let frame = match frame.into_data() {
                     Ok(chunk) => {
-                        this.on_body_chunk.on_body_chunk(&chunk, latency, this.span);
+                        this.size.add(chunk.remaining());
+                        if *this.sampled {
+                            this.on_body_chunk.on_body_chunk(&chunk, latency, this.span);
+                        }
                         Frame::data(chunk)
                     }
                     Err(frame) => frame,
@@ -64,7 +99,14 @@ where
                 let frame = match frame.into_trailers() {
                     Ok(trailers) => {
                         if let Some((on_eos, stream_start)) = this.on_eos.take() {
-                            on_eos.on_eos(Some(&trailers), stream_start.elapsed(), this.span);
+                            if *this.sampled {
+                                on_eos.on_eos_with_size(
+                                    Some(&trailers),
+                                    stream_start.elapsed(),
+                                    this.size.size(),
+                                    this.span,
+                                );
+                            }
                         }
                         Frame::trailers(trailers)
                     }
@@ -78,14 +120,24 @@ where
                     this.classify_eos.take().zip(this.on_failure.take())
                 {
                     let failure_class = classify_eos.classify_error(&err);
-                    on_failure.on_failure(failure_class, latency, this.span);
+                    on_failure.on_failure(failure_class, latency, this.request, this.span);
+                    this.on_abort.disarm();
+                } else {
+                    this.on_abort.abort(AbortReason::ResponseBodyFailed);
                 }
 
                 Poll::Ready(Some(Err(err)))
             }
             None => {
                 if let Some((on_eos, stream_start)) = this.on_eos.take() {
-                    on_eos.on_eos(None, stream_start.elapsed(), this.span);
+                    if *this.sampled {
+                        on_eos.on_eos_with_size(
+                            None,
+                            stream_start.elapsed(),
+                            this.size.size(),
+                            this.span,
+                        );
+                    }
                 }
 
                 Poll::Ready(None)
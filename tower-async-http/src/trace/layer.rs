@@ -1,6 +1,6 @@
 use super::{
-    DefaultMakeSpan, DefaultOnBodyChunk, DefaultOnEos, DefaultOnFailure, DefaultOnRequest,
-    DefaultOnResponse, Trace,
+    DefaultMakeSpan, DefaultOnAbort, DefaultOnBodyChunk, DefaultOnEos, DefaultOnFailure,
+    DefaultOnRequest, DefaultOnResponse, Sampling, Trace,
 };
 use crate::classify::{
     GrpcErrorsAsFailures, MakeClassifier, ServerErrorsAsFailures, SharedClassifier,
@@ -23,6 +23,7 @@ pub struct TraceLayer<
     OnBodyChunk = DefaultOnBodyChunk,
     OnEos = DefaultOnEos,
     OnFailure = DefaultOnFailure,
+    OnAbort = DefaultOnAbort,
 > {
     pub(crate) make_classifier: M,
     pub(crate) make_span: MakeSpan,
@@ -31,6 +32,9 @@ pub struct TraceLayer<
     pub(crate) on_body_chunk: OnBodyChunk,
     pub(crate) on_eos: OnEos,
     pub(crate) on_failure: OnFailure,
+    pub(crate) on_abort: OnAbort,
+    pub(crate) sampling: Sampling,
+    pub(crate) always_on_eos: bool,
 }
 
 impl<M> TraceLayer<M> {
@@ -43,6 +47,9 @@ impl<M> TraceLayer<M> {
             make_classifier,
             make_span: DefaultMakeSpan::new(),
             on_failure: DefaultOnFailure::default(),
+            on_abort: DefaultOnAbort::default(),
+            sampling: Sampling::default(),
+            always_on_eos: false,
             on_request: DefaultOnRequest::default(),
             on_eos: DefaultOnEos::default(),
             on_body_chunk: DefaultOnBodyChunk::default(),
@@ -51,8 +58,8 @@ impl<M> TraceLayer<M> {
     }
 }
 
-impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
-    TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
+impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
 {
     /// Customize what to do when a request is received.
     ///
@@ -62,7 +69,8 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_request<NewOnRequest>(
         self,
         new_on_request: NewOnRequest,
-    ) -> TraceLayer<M, MakeSpan, NewOnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure> {
+    ) -> TraceLayer<M, MakeSpan, NewOnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         TraceLayer {
             on_request: new_on_request,
             on_failure: self.on_failure,
@@ -71,6 +79,9 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             make_span: self.make_span,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -82,7 +93,8 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_response<NewOnResponse>(
         self,
         new_on_response: NewOnResponse,
-    ) -> TraceLayer<M, MakeSpan, OnRequest, NewOnResponse, OnBodyChunk, OnEos, OnFailure> {
+    ) -> TraceLayer<M, MakeSpan, OnRequest, NewOnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         TraceLayer {
             on_response: new_on_response,
             on_request: self.on_request,
@@ -91,6 +103,9 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_failure: self.on_failure,
             make_span: self.make_span,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -102,7 +117,8 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_body_chunk<NewOnBodyChunk>(
         self,
         new_on_body_chunk: NewOnBodyChunk,
-    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, NewOnBodyChunk, OnEos, OnFailure> {
+    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, NewOnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         TraceLayer {
             on_body_chunk: new_on_body_chunk,
             on_eos: self.on_eos,
@@ -111,6 +127,9 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             make_span: self.make_span,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -122,7 +141,8 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_eos<NewOnEos>(
         self,
         new_on_eos: NewOnEos,
-    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, NewOnEos, OnFailure> {
+    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, NewOnEos, OnFailure, OnAbort>
+    {
         TraceLayer {
             on_eos: new_on_eos,
             on_body_chunk: self.on_body_chunk,
@@ -131,6 +151,9 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             make_span: self.make_span,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -142,7 +165,8 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_failure<NewOnFailure>(
         self,
         new_on_failure: NewOnFailure,
-    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, NewOnFailure> {
+    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, NewOnFailure, OnAbort>
+    {
         TraceLayer {
             on_failure: new_on_failure,
             on_request: self.on_request,
@@ -151,6 +175,33 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             make_span: self.make_span,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
+        }
+    }
+
+    /// Customize what to do when a request has been aborted by the client.
+    ///
+    /// `NewOnAbort` is expected to implement [`OnAbort`].
+    ///
+    /// [`OnAbort`]: super::OnAbort
+    pub fn on_abort<NewOnAbort>(
+        self,
+        new_on_abort: NewOnAbort,
+    ) -> TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, NewOnAbort>
+    {
+        TraceLayer {
+            on_abort: new_on_abort,
+            make_span: self.make_span,
+            on_request: self.on_request,
+            on_response: self.on_response,
+            on_body_chunk: self.on_body_chunk,
+            on_eos: self.on_eos,
+            on_failure: self.on_failure,
+            make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
         }
     }
 
@@ -163,7 +214,8 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn make_span_with<NewMakeSpan>(
         self,
         new_make_span: NewMakeSpan,
-    ) -> TraceLayer<M, NewMakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure> {
+    ) -> TraceLayer<M, NewMakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         TraceLayer {
             make_span: new_make_span,
             on_request: self.on_request,
@@ -172,8 +224,30 @@ impl<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_eos: self.on_eos,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
+
+    /// Customize which requests are traced.
+    ///
+    /// See the [module docs](super#sampling) for more details.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Call `on_eos` at the end of every response body, not only of those whose
+    /// end-of-stream is classified.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// See the [module docs](super#body-size) for more details.
+    pub fn always_on_eos(mut self, always_on_eos: bool) -> Self {
+        self.always_on_eos = always_on_eos;
+        self
+    }
 }
 
 impl TraceLayer<SharedClassifier<ServerErrorsAsFailures>> {
@@ -188,6 +262,9 @@ impl TraceLayer<SharedClassifier<ServerErrorsAsFailures>> {
             on_body_chunk: DefaultOnBodyChunk::default(),
             on_eos: DefaultOnEos::default(),
             on_failure: DefaultOnFailure::default(),
+            on_abort: DefaultOnAbort::default(),
+            sampling: Sampling::default(),
+            always_on_eos: false,
         }
     }
 }
@@ -204,12 +281,15 @@ impl TraceLayer<SharedClassifier<GrpcErrorsAsFailures>> {
             on_body_chunk: DefaultOnBodyChunk::default(),
             on_eos: DefaultOnEos::default(),
             on_failure: DefaultOnFailure::default(),
+            on_abort: DefaultOnAbort::default(),
+            sampling: Sampling::default(),
+            always_on_eos: false,
         }
     }
 }
 
-impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure> Layer<S>
-    for TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
+impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort> Layer<S>
+    for TraceLayer<M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
 where
     M: Clone,
     MakeSpan: Clone,
@@ -218,8 +298,10 @@ where
     OnEos: Clone,
     OnBodyChunk: Clone,
     OnFailure: Clone,
+    OnAbort: Clone,
 {
-    type Service = Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>;
+    type Service =
+        Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>;
 
     fn layer(&self, inner: S) -> Self::Service {
         Trace {
@@ -231,6 +313,9 @@ where
             on_body_chunk: self.on_body_chunk.clone(),
             on_response: self.on_response.clone(),
             on_failure: self.on_failure.clone(),
+            on_abort: self.on_abort.clone(),
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
         }
     }
 }
@@ -1,4 +1,5 @@
-use http::Request;
+use http::{header::HeaderName, Extensions, HeaderMap, HeaderValue, Request};
+use std::fmt;
 use tracing::{Level, Span};
 
 use super::DEFAULT_MESSAGE_LEVEL;
@@ -11,12 +12,30 @@ use super::DEFAULT_MESSAGE_LEVEL;
 pub trait MakeSpan<B> {
     /// Make a span from a request.
     fn make_span(&self, request: &Request<B>) -> Span;
+
+    /// Make a span retroactively for a request which wasn't [sampled], from its head as
+    /// buffered before the request was passed on.
+    ///
+    /// Only called with [`Sampling::sample_failures`] enabled, for requests which failed, or
+    /// whose failure depends on the end of the response stream. Defaults to [`Span::none`],
+    /// as the head doesn't have a body of type `B`.
+    ///
+    /// [sampled]: super#sampling
+    /// [`Sampling::sample_failures`]: super::Sampling::sample_failures
+    fn make_span_from_head(&self, head: &Request<()>) -> Span {
+        let _ = head;
+        Span::none()
+    }
 }
 
 impl<B> MakeSpan<B> for Span {
     fn make_span(&self, _request: &Request<B>) -> Span {
         self.clone()
     }
+
+    fn make_span_from_head(&self, _head: &Request<()>) -> Span {
+        self.clone()
+    }
 }
 
 impl<F, B> MakeSpan<B> for F
@@ -36,14 +55,26 @@ where
 pub struct DefaultMakeSpan {
     level: Level,
     include_headers: bool,
+    headers: Vec<HeaderName>,
+    redacted_headers: Vec<HeaderName>,
+    #[cfg(feature = "redact")]
+    redactor: Option<crate::redact::Redactor>,
+    extensions: Vec<(&'static str, GetExtension)>,
 }
 
+type GetExtension = fn(&Extensions) -> Option<&dyn fmt::Debug>;
+
 impl DefaultMakeSpan {
     /// Create a new `DefaultMakeSpan`.
     pub fn new() -> Self {
         Self {
             level: DEFAULT_MESSAGE_LEVEL,
             include_headers: false,
+            headers: Vec::new(),
+            redacted_headers: Vec::new(),
+            #[cfg(feature = "redact")]
+            redactor: None,
+            extensions: Vec::new(),
         }
     }
 
@@ -59,13 +90,127 @@ impl DefaultMakeSpan {
 
     /// Include request headers on the [`Span`].
     ///
-    /// By default headers are not included.
+    /// By default headers are not included. The values of sensitive headers and headers given
+    /// to [`DefaultMakeSpan::redact_header`] are redacted.
     ///
     /// [`Span`]: tracing::Span
     pub fn include_headers(mut self, include_headers: bool) -> Self {
         self.include_headers = include_headers;
         self
     }
+
+    /// Record the values of the given request header on the [`Span`].
+    ///
+    /// The recorded headers are included in the `headers` field of the span, which only
+    /// contains the headers recorded this way, unless [`DefaultMakeSpan::include_headers`]
+    /// is enabled. Headers missing from a request are omitted.
+    ///
+    /// [`Span`]: tracing::Span
+    pub fn record_header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    /// Redact the values of the given request header on the [`Span`].
+    ///
+    /// Header values marked as [sensitive], for example by the [`SetSensitiveRequestHeaders`]
+    /// middleware, are always redacted.
+    ///
+    /// [`Span`]: tracing::Span
+    /// [sensitive]: http::HeaderValue::set_sensitive
+    /// [`SetSensitiveRequestHeaders`]: crate::sensitive_headers::SetSensitiveRequestHeaders
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        self.redacted_headers.push(name);
+        self
+    }
+
+    /// Redact the headers included on the [`Span`] with the given [`Redactor`].
+    ///
+    /// Applies to both the headers included with [`DefaultMakeSpan::include_headers`] and
+    /// those recorded with [`DefaultMakeSpan::record_header`]. The headers given to
+    /// [`DefaultMakeSpan::redact_header`] are ignored, as the redactor defines which
+    /// headers are redacted instead.
+    ///
+    /// [`Span`]: tracing::Span
+    /// [`Redactor`]: crate::redact::Redactor
+    #[cfg(feature = "redact")]
+    pub fn redactor(mut self, redactor: crate::redact::Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Record the request extension of type `T` on the [`Span`], under the given name.
+    ///
+    /// The recorded extensions are included in the `extensions` field of the span.
+    /// Extensions missing from a request are omitted.
+    ///
+    /// [`Span`]: tracing::Span
+    pub fn record_extension<T>(mut self, name: &'static str) -> Self
+    where
+        T: fmt::Debug + Send + Sync + 'static,
+    {
+        self.extensions.push((name, |extensions| {
+            extensions.get::<T>().map(|value| value as &dyn fmt::Debug)
+        }));
+        self
+    }
+}
+
+/// Formats the recorded headers of a request, redacting sensitive values.
+struct RecordedHeaders<'a> {
+    headers: &'a HeaderMap,
+    /// The names of the recorded headers, or `None` to record all headers.
+    names: Option<&'a [HeaderName]>,
+    redacted: &'a [HeaderName],
+}
+
+impl fmt::Debug for RecordedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        let mut entry = |name: &HeaderName, value: &HeaderValue| {
+            if value.is_sensitive() || self.redacted.contains(name) {
+                // the `Debug` impl of `HeaderValue` hides sensitive values
+                let mut value = value.clone();
+                value.set_sensitive(true);
+                map.entry(name, &value);
+            } else {
+                map.entry(name, value);
+            }
+        };
+        match self.names {
+            Some(names) => {
+                for name in names {
+                    for value in self.headers.get_all(name) {
+                        entry(name, value);
+                    }
+                }
+            }
+            None => {
+                for (name, value) in self.headers {
+                    entry(name, value);
+                }
+            }
+        }
+        map.finish()
+    }
+}
+
+/// Formats the recorded extensions of a request.
+struct RecordedExtensions<'a> {
+    extensions: &'a Extensions,
+    getters: &'a [(&'static str, GetExtension)],
+}
+
+impl fmt::Debug for RecordedExtensions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, get) in self.getters {
+            if let Some(value) = get(self.extensions) {
+                map.entry(name, value);
+            }
+        }
+        map.finish()
+    }
 }
 
 impl Default for DefaultMakeSpan {
@@ -76,38 +221,176 @@ impl Default for DefaultMakeSpan {
 
 impl<B> MakeSpan<B> for DefaultMakeSpan {
     fn make_span(&self, request: &Request<B>) -> Span {
+        self.make(request)
+    }
+
+    fn make_span_from_head(&self, head: &Request<()>) -> Span {
+        self.make(head)
+    }
+}
+
+impl DefaultMakeSpan {
+    fn make<B>(&self, request: &Request<B>) -> Span {
         // This ugly macro is needed, unfortunately, because `tracing::span!`
         // required the level argument to be static. Meaning we can't just pass
         // `self.level`.
         macro_rules! make_span {
             ($level:expr) => {
-                if self.include_headers {
-                    tracing::span!(
-                        $level,
-                        "request",
-                        method = %request.method(),
-                        uri = %request.uri(),
-                        version = ?request.version(),
-                        headers = ?request.headers(),
-                    )
-                } else {
-                    tracing::span!(
-                        $level,
-                        "request",
-                        method = %request.method(),
-                        uri = %request.uri(),
-                        version = ?request.version(),
-                    )
-                }
+                tracing::span!(
+                    $level,
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    version = ?request.version(),
+                    headers = tracing::field::Empty,
+                    extensions = tracing::field::Empty,
+                )
             }
         }
 
-        match self.level {
+        let span = match self.level {
             Level::ERROR => make_span!(Level::ERROR),
             Level::WARN => make_span!(Level::WARN),
             Level::INFO => make_span!(Level::INFO),
             Level::DEBUG => make_span!(Level::DEBUG),
             Level::TRACE => make_span!(Level::TRACE),
+        };
+
+        #[cfg(feature = "redact")]
+        if let Some(redactor) = &self.redactor {
+            if self.include_headers {
+                span.record(
+                    "headers",
+                    tracing::field::debug(redactor.headers(request.headers())),
+                );
+            } else if !self.headers.is_empty() {
+                span.record(
+                    "headers",
+                    tracing::field::debug(redactor.headers(request.headers()).only(&self.headers)),
+                );
+            }
+            return self.record_extensions(span, request);
+        }
+
+        if self.include_headers || !self.headers.is_empty() {
+            span.record(
+                "headers",
+                tracing::field::debug(RecordedHeaders {
+                    headers: request.headers(),
+                    names: (!self.include_headers).then_some(&self.headers[..]),
+                    redacted: &self.redacted_headers,
+                }),
+            );
+        }
+
+        self.record_extensions(span, request)
+    }
+}
+
+impl DefaultMakeSpan {
+    fn record_extensions<B>(&self, span: Span, request: &Request<B>) -> Span {
+        if !self.extensions.is_empty() {
+            span.record(
+                "extensions",
+                tracing::field::debug(RecordedExtensions {
+                    extensions: request.extensions(),
+                    getters: &self.extensions,
+                }),
+            );
+        }
+
+        span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header;
+    use std::sync::{Arc, Mutex};
+    use tracing::{field::Visit, span, Subscriber};
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    #[test]
+    fn recorded_headers_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl"));
+        headers.insert(header::COOKIE, HeaderValue::from_static("session=secret"));
+        let mut authorization = HeaderValue::from_static("Bearer secret");
+        authorization.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, authorization);
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+
+        let recorded = RecordedHeaders {
+            headers: &headers,
+            names: Some(&[
+                header::USER_AGENT,
+                header::COOKIE,
+                header::AUTHORIZATION,
+                header::HOST,
+            ]),
+            redacted: &[header::COOKIE],
+        };
+        assert_eq!(
+            format!("{:?}", recorded),
+            r#"{"user-agent": "curl", "cookie": Sensitive, "authorization": Sensitive}"#
+        );
+    }
+
+    #[test]
+    fn included_headers_are_redacted() {
+        // records the `headers` field of spans
+        struct Recorder(Arc<Mutex<String>>);
+
+        struct HeadersField<'a>(&'a mut String);
+
+        impl<S: Subscriber> Layer<S> for Recorder {
+            fn on_record(&self, _: &span::Id, values: &span::Record<'_>, _: Context<'_, S>) {
+                values.record(&mut HeadersField(&mut self.0.lock().unwrap()));
+            }
+        }
+
+        impl Visit for HeadersField<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                if field.name() == "headers" {
+                    *self.0 = format!("{:?}", value);
+                }
+            }
         }
+
+        let recorded = Arc::new(Mutex::new(String::new()));
+        let subscriber = tracing_subscriber::registry().with(Recorder(recorded.clone()));
+        let request = Request::get("/")
+            .header(header::USER_AGENT, "curl")
+            .header(header::COOKIE, "session=secret")
+            .body(())
+            .unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            DefaultMakeSpan::new()
+                .level(Level::ERROR)
+                .include_headers(true)
+                .redact_header(header::COOKIE)
+                .make_span(&request)
+        });
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            r#"{"user-agent": "curl", "cookie": Sensitive}"#
+        );
+    }
+
+    #[test]
+    fn recorded_extensions() {
+        let make_span = DefaultMakeSpan::new()
+            .record_extension::<u64>("user_id")
+            .record_extension::<String>("missing");
+
+        let mut extensions = Extensions::new();
+        extensions.insert(42u64);
+        let recorded = RecordedExtensions {
+            extensions: &extensions,
+            getters: &make_span.extensions,
+        };
+        assert_eq!(format!("{:?}", recorded), r#"{"user_id": 42}"#);
     }
 }
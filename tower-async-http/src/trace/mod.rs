@@ -82,7 +82,7 @@
 //!                     .level(Level::INFO)
 //!                     .latency_unit(LatencyUnit::Micros)
 //!             )
-//!             // on so on for `on_eos`, `on_body_chunk`, and `on_failure`
+//!             // on so on for `on_eos`, `on_body_chunk`, `on_failure` and `on_abort`
 //!     )
 //!     .service_fn(handle);
 //! # let mut service = service;
@@ -100,7 +100,10 @@
 //! use http_body_util::Full;
 //! use bytes::Bytes;
 //! use tower_async::ServiceBuilder;
-//! use tower_async_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
+//! use tower_async_http::{
+//!     classify::ServerErrorsFailureClass,
+//!     trace::{AbortReason, RequestMetadata, TraceLayer},
+//! };
 //! use std::time::Duration;
 //! use tracing::Span;
 //! # use tower_async::Service;
@@ -122,8 +125,8 @@
 //!             .on_request(|request: &Request<Full<Bytes>>, _span: &Span| {
 //!                 tracing::debug!("started {} {}", request.method(), request.uri().path())
 //!             })
-//!             .on_response(|response: &Response<Full<Bytes>>, latency: Duration, _span: &Span| {
-//!                 tracing::debug!("response generated in {:?}", latency)
+//!             .on_response(|response: &Response<Full<Bytes>>, latency: Duration, request: &RequestMetadata, _span: &Span| {
+//!                 tracing::debug!("response to {} generated in {:?}", request.uri().path(), latency)
 //!             })
 //!             .on_body_chunk(|chunk: &Bytes, latency: Duration, _span: &Span| {
 //!                 tracing::debug!("sending {} bytes", chunk.len())
@@ -131,9 +134,12 @@
 //!             .on_eos(|trailers: Option<&HeaderMap>, stream_duration: Duration, _span: &Span| {
 //!                 tracing::debug!("stream closed after {:?}", stream_duration)
 //!             })
-//!             .on_failure(|error: ServerErrorsFailureClass, latency: Duration, _span: &Span| {
+//!             .on_failure(|error: ServerErrorsFailureClass, latency: Duration, _request: &RequestMetadata, _span: &Span| {
 //!                 tracing::debug!("something went wrong")
 //!             })
+//!             .on_abort(|reason: AbortReason, latency: Duration, _span: &Span| {
+//!                 tracing::debug!("client went away: {}", reason)
+//!             })
 //!     )
 //!     .service_fn(handle);
 //! # let mut service = service;
@@ -151,7 +157,10 @@
 //! ```rust
 //! use http::StatusCode;
 //! use tower_async::ServiceBuilder;
-//! use tower_async_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
+//! use tower_async_http::{
+//!     classify::ServerErrorsFailureClass,
+//!     trace::{RequestMetadata, TraceLayer},
+//! };
 //! use std::time::Duration;
 //! use tracing::Span;
 //! # use tower_async::Service;
@@ -177,7 +186,8 @@
 //!             .on_response(())
 //!             .on_body_chunk(())
 //!             .on_eos(())
-//!             .on_failure(|error: ServerErrorsFailureClass, latency: Duration, _span: &Span| {
+//!             .on_abort(())
+//!             .on_failure(|error: ServerErrorsFailureClass, latency: Duration, _request: &RequestMetadata, _span: &Span| {
 //!                 tracing::debug!("something went wrong")
 //!             })
 //!     )
@@ -209,6 +219,10 @@
 //! `on_response` callback is still called. `on_failure` would _also_ be called
 //! in this case since the response was classified as a failure.
 //!
+//! Both `on_response` and `on_failure` receive the [`RequestMetadata`] of the request,
+//! captured before it was passed to the inner service, such as its method, URI and
+//! [`MatchedRoute`].
+//!
 //! ### `on_body_chunk`
 //!
 //! The `on_body_chunk` callback is called when the response body produces a new
@@ -232,6 +246,102 @@
 //! - [`http_body::Body::poll_frame`] returns an error.
 //! - An end-of-stream is classified as a failure.
 //!
+//! ### `on_abort`
+//!
+//! The `on_abort` callback is called when the request was aborted before it was fully
+//! handled, that is when:
+//!
+//! - The response future is dropped before the inner [`Service`] produced a response.
+//! - The response body is dropped before it reached its end of stream, unless the body
+//!   is never sent, such as for responses to `HEAD` requests or `304 Not Modified` responses.
+//! - The response body returned an error, which wasn't passed to `on_failure`.
+//!
+//! HTTP servers such as `hyper` drop the response future or body when a client disconnects.
+//! Such requests are not passed to `on_failure`, so that clients hanging up don't count as
+//! server failures. The [`AbortReason`] tells whether the client went away or the body failed.
+//!
+//! # Sampling
+//!
+//! By default all requests are traced. A [`Sampling`] can be given to
+//! [`TraceLayer::sampling`] to honor the sampling decision of the caller, as propagated by the
+//! `sampled` flag of the [W3C trace context] `traceparent` header. None of the callbacks are
+//! called for requests the caller did not sample. The parsed [`TraceParent`] is inserted into
+//! the request extensions, such that it can be propagated to upstream services.
+//!
+//! With [`Sampling::sample_failures`] enabled, unsampled requests are still sampled once they
+//! are classified as a failure: `on_response` is called retroactively, followed by `on_failure`.
+//! `on_request` cannot be replayed, as the request has been consumed by then.
+//!
+//! Spans are only made for sampled requests. The head of unsampled requests is buffered when
+//! [`Sampling::sample_failures`] is enabled, and their span is made from it with
+//! [`MakeSpan::make_span_from_head`] once they failed, or when their failure depends on the
+//! end of the response stream, as for gRPC.
+//!
+//! ```rust
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use tower_async::ServiceBuilder;
+//! use tower_async_http::trace::{Sampling, TraceLayer};
+//! use std::convert::Infallible;
+//!
+//! # async fn handle(request: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//! #     Ok(Response::new(Full::from("foo")))
+//! # }
+//! let service = ServiceBuilder::new()
+//!     .layer(
+//!         TraceLayer::new_for_http().sampling(
+//!             Sampling::new()
+//!                 .honor_traceparent(true)
+//!                 .sample_failures(true),
+//!         ),
+//!     )
+//!     .service_fn(handle);
+//! ```
+//!
+//! # Body size
+//!
+//! `on_eos` is only called for responses whose end-of-stream is classified, such as gRPC
+//! responses. With [`TraceLayer::always_on_eos`] enabled it is called at the end of every
+//! response body instead, which allows reporting the size of streaming responses without a
+//! `Content-Length` header.
+//!
+//! [`OnEos::on_eos_with_size`] receives the [`BodySize`] of the response body that was sent.
+//! If the [`Compression`] middleware is placed inside of [`Trace`], the size before compression
+//! and the compression ratio are reported as well. [`DefaultOnEos`] records them as the `bytes`
+//! and `compression_ratio` fields of its event.
+//!
+//! ```rust
+//! use http::{Request, Response, HeaderMap};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use tower_async::ServiceBuilder;
+//! use tower_async_http::{body_size::BodySize, trace::{OnEos, TraceLayer}};
+//! use tracing::Span;
+//! use std::{convert::Infallible, time::Duration};
+//!
+//! # async fn handle(request: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//! #     Ok(Response::new(Full::from("foo")))
+//! # }
+//! #[derive(Clone)]
+//! struct RecordSize;
+//!
+//! impl OnEos for RecordSize {
+//!     fn on_eos(self, _: Option<&HeaderMap>, _: Duration, _: &Span) {}
+//!
+//!     fn on_eos_with_size(self, _: Option<&HeaderMap>, _: Duration, size: BodySize, span: &Span) {
+//!         span.record("bytes", size.bytes());
+//!     }
+//! }
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(TraceLayer::new_for_http().always_on_eos(true).on_eos(RecordSize))
+//!     .service_fn(handle);
+//! ```
+//!
+//! [`BodySize`]: crate::body_size::BodySize
+//! [`Compression`]: crate::compression::Compression
+//!
 //! # Recording fields on the span
 //!
 //! All callbacks receive a reference to the [tracing] [`Span`], corresponding to this request,
@@ -243,7 +353,7 @@
 //! use http_body_util::Full;
 //! use bytes::Bytes;
 //! use tower_async::ServiceBuilder;
-//! use tower_async_http::trace::TraceLayer;
+//! use tower_async_http::trace::{RequestMetadata, TraceLayer};
 //! use tracing::Span;
 //! use std::time::Duration;
 //! use std::convert::Infallible;
@@ -264,7 +374,7 @@
 //!                     status_code = tracing::field::Empty,
 //!                 )
 //!             })
-//!             .on_response(|response: &Response<Full<Bytes>>, _latency: Duration, span: &Span| {
+//!             .on_response(|response: &Response<Full<Bytes>>, _latency: Duration, _request: &RequestMetadata, span: &Span| {
 //!                 span.record("status_code", &tracing::field::display(response.status()));
 //!
 //!                 tracing::debug!("response generated")
@@ -375,6 +485,7 @@
 //! [record]: https://docs.rs/tracing/latest/tracing/span/struct.Span.html#method.record
 //! [`TraceLayer::make_span_with`]: crate::trace::TraceLayer::make_span_with
 //! [`Span`]: tracing::Span
+//! [W3C trace context]: https://www.w3.org/TR/trace-context/
 //! [`ServerErrorsAsFailures`]: crate::classify::ServerErrorsAsFailures
 
 use std::{fmt, time::Duration};
@@ -385,71 +496,30 @@ pub use self::{
     body::ResponseBody,
     layer::TraceLayer,
     make_span::{DefaultMakeSpan, MakeSpan},
+    on_abort::{AbortReason, DefaultOnAbort, OnAbort},
     on_body_chunk::{DefaultOnBodyChunk, OnBodyChunk},
     on_eos::{DefaultOnEos, OnEos},
     on_failure::{DefaultOnFailure, OnFailure},
     on_request::{DefaultOnRequest, OnRequest},
     on_response::{DefaultOnResponse, OnResponse},
+    request_metadata::{MatchedRoute, RequestMetadata},
+    sampling::{Sampling, TraceParent},
     service::Trace,
 };
 
 use crate::LatencyUnit;
 
-macro_rules! event_dynamic_lvl {
-    ( $(target: $target:expr,)? $(parent: $parent:expr,)? $lvl:expr, $($tt:tt)* ) => {
-        match $lvl {
-            tracing::Level::ERROR => {
-                tracing::event!(
-                    $(target: $target,)?
-                    $(parent: $parent,)?
-                    tracing::Level::ERROR,
-                    $($tt)*
-                );
-            }
-            tracing::Level::WARN => {
-                tracing::event!(
-                    $(target: $target,)?
-                    $(parent: $parent,)?
-                    tracing::Level::WARN,
-                    $($tt)*
-                );
-            }
-            tracing::Level::INFO => {
-                tracing::event!(
-                    $(target: $target,)?
-                    $(parent: $parent,)?
-                    tracing::Level::INFO,
-                    $($tt)*
-                );
-            }
-            tracing::Level::DEBUG => {
-                tracing::event!(
-                    $(target: $target,)?
-                    $(parent: $parent,)?
-                    tracing::Level::DEBUG,
-                    $($tt)*
-                );
-            }
-            tracing::Level::TRACE => {
-                tracing::event!(
-                    $(target: $target,)?
-                    $(parent: $parent,)?
-                    tracing::Level::TRACE,
-                    $($tt)*
-                );
-            }
-        }
-    };
-}
-
 mod body;
 mod layer;
 mod make_span;
+mod on_abort;
 mod on_body_chunk;
 mod on_eos;
 mod on_failure;
 mod on_request;
 mod on_response;
+mod request_metadata;
+mod sampling;
 mod service;
 
 const DEFAULT_MESSAGE_LEVEL: Level = Level::DEBUG;
@@ -475,14 +545,20 @@ impl fmt::Display for Latency {
 mod tests {
     use super::*;
 
-    use crate::classify::ServerErrorsFailureClass;
+    use crate::body_size::BodySize;
+    use crate::classify::{GrpcFailureClass, ServerErrorsFailureClass};
     use crate::test_helpers::{self, Body};
 
     use bytes::Bytes;
+    use futures::FutureExt;
     use http::{HeaderMap, Request, Response};
+    use http_body_util::BodyExt;
     use once_cell::sync::Lazy;
     use std::{
-        sync::atomic::{AtomicU32, Ordering},
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc, Mutex,
+        },
         time::Duration,
     };
     use tower_async::{BoxError, Service, ServiceBuilder};
@@ -504,9 +580,14 @@ mod tests {
                 span.record("foo", 42);
                 ON_REQUEST_COUNT.fetch_add(1, Ordering::SeqCst);
             })
-            .on_response(|_res: &Response<Body>, _latency: Duration, _span: &Span| {
-                ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
-            })
+            .on_response(
+                |_res: &Response<Body>,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
+                },
+            )
             .on_body_chunk(|_chunk: &Bytes, _latency: Duration, _span: &Span| {
                 ON_BODY_CHUNK_COUNT.fetch_add(1, Ordering::SeqCst);
             })
@@ -516,7 +597,10 @@ mod tests {
                 },
             )
             .on_failure(
-                |_class: ServerErrorsFailureClass, _latency: Duration, _span: &Span| {
+                |_class: ServerErrorsFailureClass,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
                     ON_FAILURE.fetch_add(1, Ordering::SeqCst);
                 },
             );
@@ -549,9 +633,14 @@ mod tests {
             .on_request(|_req: &Request<Body>, _span: &Span| {
                 ON_REQUEST_COUNT.fetch_add(1, Ordering::SeqCst);
             })
-            .on_response(|_res: &Response<Body>, _latency: Duration, _span: &Span| {
-                ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
-            })
+            .on_response(
+                |_res: &Response<Body>,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
+                },
+            )
             .on_body_chunk(|_chunk: &Bytes, _latency: Duration, _span: &Span| {
                 ON_BODY_CHUNK_COUNT.fetch_add(1, Ordering::SeqCst);
             })
@@ -561,7 +650,10 @@ mod tests {
                 },
             )
             .on_failure(
-                |_class: ServerErrorsFailureClass, _latency: Duration, _span: &Span| {
+                |_class: ServerErrorsFailureClass,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
                     ON_FAILURE.fetch_add(1, Ordering::SeqCst);
                 },
             );
@@ -584,6 +676,418 @@ mod tests {
         assert_eq!(0, ON_FAILURE.load(Ordering::SeqCst), "failure");
     }
 
+    #[tokio::test]
+    async fn always_on_eos_reports_body_size() {
+        #[derive(Clone)]
+        struct RecordSize(Arc<Mutex<Vec<BodySize>>>);
+
+        impl OnEos for RecordSize {
+            fn on_eos(self, _: Option<&HeaderMap>, _: Duration, _: &Span) {
+                unreachable!("on_eos_with_size is called instead")
+            }
+
+            fn on_eos_with_size(
+                self,
+                _: Option<&HeaderMap>,
+                _: Duration,
+                size: BodySize,
+                _: &Span,
+            ) {
+                self.0.lock().unwrap().push(size);
+            }
+        }
+
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let svc = ServiceBuilder::new()
+            .layer(
+                TraceLayer::new_for_http()
+                    .always_on_eos(true)
+                    .on_eos(RecordSize(sizes.clone())),
+            )
+            .service_fn(streaming_body);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        test_helpers::to_bytes(res.into_body()).await.unwrap();
+
+        let sizes = sizes.lock().unwrap();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes[0].bytes(), "onetwothree".len() as u64);
+        assert_eq!(sizes[0].uncompressed_bytes(), None);
+    }
+
+    #[tokio::test]
+    async fn aborted_response_future() {
+        static ON_RESPONSE_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_FAILURE: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_ABORT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_http()
+            .on_response(
+                |_res: &Response<Body>,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_failure(
+                |_class: ServerErrorsFailureClass,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_FAILURE.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_abort(|reason: AbortReason, _latency: Duration, _span: &Span| {
+                assert_eq!(reason, AbortReason::ResponseFutureDropped);
+                ON_ABORT.fetch_add(1, Ordering::SeqCst);
+            });
+
+        let svc =
+            ServiceBuilder::new()
+                .layer(trace_layer)
+                .service_fn(|_req: Request<Body>| async {
+                    std::future::pending::<Result<Response<Body>, BoxError>>().await
+                });
+
+        // poll the response future once and then drop it, as a server would do
+        // when the client disconnects
+        assert!(svc
+            .call(Request::new(Body::empty()))
+            .now_or_never()
+            .is_none());
+
+        assert_eq!(0, ON_RESPONSE_COUNT.load(Ordering::SeqCst), "response");
+        assert_eq!(0, ON_FAILURE.load(Ordering::SeqCst), "failure");
+        assert_eq!(1, ON_ABORT.load(Ordering::SeqCst), "abort");
+    }
+
+    #[tokio::test]
+    async fn aborted_response_body() {
+        static ON_EOS: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_FAILURE: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_ABORT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_grpc()
+            .on_eos(
+                |_trailers: Option<&HeaderMap>, _latency: Duration, _span: &Span| {
+                    ON_EOS.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_failure(
+                |_class: GrpcFailureClass,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_FAILURE.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_abort(|reason: AbortReason, _latency: Duration, _span: &Span| {
+                assert_eq!(reason, AbortReason::ResponseBodyDropped);
+                ON_ABORT.fetch_add(1, Ordering::SeqCst);
+            });
+
+        let svc = ServiceBuilder::new()
+            .layer(trace_layer)
+            .service_fn(streaming_body);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(0, ON_ABORT.load(Ordering::SeqCst), "abort");
+
+        // consume only part of the body
+        let mut body = res.into_body();
+        body.frame().await.unwrap().unwrap();
+        drop(body);
+
+        assert_eq!(0, ON_EOS.load(Ordering::SeqCst), "eos");
+        assert_eq!(0, ON_FAILURE.load(Ordering::SeqCst), "failure");
+        assert_eq!(1, ON_ABORT.load(Ordering::SeqCst), "abort");
+    }
+
+    #[tokio::test]
+    async fn consumed_response_body_is_not_aborted() {
+        static ON_ABORT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_http().on_abort(
+            |_reason: AbortReason, _latency: Duration, _span: &Span| {
+                ON_ABORT.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        let svc = ServiceBuilder::new()
+            .layer(trace_layer.clone())
+            .service_fn(streaming_body);
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        test_helpers::to_bytes(res.into_body()).await.unwrap();
+
+        // an empty body has nothing left to send, so dropping it is fine
+        let svc = ServiceBuilder::new().layer(trace_layer).service_fn(echo);
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        drop(res);
+
+        assert_eq!(0, ON_ABORT.load(Ordering::SeqCst), "abort");
+    }
+
+    #[tokio::test]
+    async fn bodiless_response_body_is_not_aborted() {
+        static ON_ABORT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_http().on_abort(
+            |_reason: AbortReason, _latency: Duration, _span: &Span| {
+                ON_ABORT.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        let svc =
+            ServiceBuilder::new()
+                .layer(trace_layer)
+                .service_fn(|req: Request<Body>| async move {
+                    let mut res = streaming_body(req).await?;
+                    *res.status_mut() = http::StatusCode::NOT_MODIFIED;
+                    Ok::<_, BoxError>(res)
+                });
+
+        // servers drop the body of bodiless responses without polling it
+        drop(svc.call(Request::new(Body::empty())).await.unwrap());
+
+        assert_eq!(0, ON_ABORT.load(Ordering::SeqCst), "abort");
+    }
+
+    #[tokio::test]
+    async fn failed_response_body_is_aborted() {
+        static ON_ABORT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_http().on_abort(
+            |reason: AbortReason, _latency: Duration, _span: &Span| {
+                assert_eq!(reason, AbortReason::ResponseBodyFailed);
+                assert!(!reason.is_client());
+                ON_ABORT.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        let svc =
+            ServiceBuilder::new()
+                .layer(trace_layer)
+                .service_fn(|_req: Request<Body>| async {
+                    let stream = futures::stream::iter([
+                        Ok::<_, BoxError>(Bytes::from("one")),
+                        Err("read error".into()),
+                    ]);
+                    Ok::<_, BoxError>(Response::new(Body::from_stream(stream)))
+                });
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        test_helpers::to_bytes(res.into_body()).await.unwrap_err();
+
+        assert_eq!(1, ON_ABORT.load(Ordering::SeqCst), "abort");
+    }
+
+    #[tokio::test]
+    async fn unsampled_traceparent_is_not_traced() {
+        static ON_REQUEST_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_RESPONSE_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_BODY_CHUNK_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_ABORT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_http()
+            .on_request(|_req: &Request<Body>, _span: &Span| {
+                ON_REQUEST_COUNT.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_response(
+                |_res: &Response<Body>,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_body_chunk(|_chunk: &Bytes, _latency: Duration, _span: &Span| {
+                ON_BODY_CHUNK_COUNT.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_abort(|_reason: AbortReason, _latency: Duration, _span: &Span| {
+                ON_ABORT.fetch_add(1, Ordering::SeqCst);
+            })
+            .sampling(Sampling::new().honor_traceparent(true));
+
+        let svc =
+            ServiceBuilder::new()
+                .layer(trace_layer)
+                .service_fn(|req: Request<Body>| async move {
+                    assert!(req.extensions().get::<TraceParent>().is_some());
+                    streaming_body(req).await
+                });
+
+        let req = Request::builder()
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        let mut body = res.into_body();
+        body.frame().await.unwrap().unwrap();
+        drop(body);
+
+        assert_eq!(0, ON_REQUEST_COUNT.load(Ordering::SeqCst), "request");
+        assert_eq!(0, ON_RESPONSE_COUNT.load(Ordering::SeqCst), "response");
+        assert_eq!(0, ON_BODY_CHUNK_COUNT.load(Ordering::SeqCst), "body chunk");
+        assert_eq!(0, ON_ABORT.load(Ordering::SeqCst), "abort");
+
+        // requests sampled by the caller are traced
+        let req = Request::builder()
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        svc.call(req).await.unwrap();
+
+        assert_eq!(1, ON_REQUEST_COUNT.load(Ordering::SeqCst), "request");
+        assert_eq!(1, ON_RESPONSE_COUNT.load(Ordering::SeqCst), "response");
+    }
+
+    #[tokio::test]
+    async fn unsampled_failures_are_sampled_retroactively() {
+        static ON_REQUEST_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_RESPONSE_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_FAILURE: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        static MAKE_SPAN: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static MAKE_SPAN_FROM_HEAD: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        #[derive(Clone)]
+        struct CountingMakeSpan;
+
+        impl MakeSpan<Body> for CountingMakeSpan {
+            fn make_span(&self, _request: &Request<Body>) -> Span {
+                MAKE_SPAN.fetch_add(1, Ordering::SeqCst);
+                Span::none()
+            }
+
+            fn make_span_from_head(&self, head: &Request<()>) -> Span {
+                assert_eq!(head.uri().path(), "/fail");
+                MAKE_SPAN_FROM_HEAD.fetch_add(1, Ordering::SeqCst);
+                Span::none()
+            }
+        }
+
+        let trace_layer = TraceLayer::new_for_http()
+            .make_span_with(CountingMakeSpan)
+            .on_request(|_req: &Request<Body>, _span: &Span| {
+                ON_REQUEST_COUNT.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_response(
+                |_res: &Response<Body>,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_failure(
+                |_class: ServerErrorsFailureClass,
+                 _latency: Duration,
+                 _request: &RequestMetadata,
+                 _span: &Span| {
+                    ON_FAILURE.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .sampling(
+                Sampling::new()
+                    .honor_traceparent(true)
+                    .sample_failures(true),
+            );
+
+        let svc =
+            ServiceBuilder::new()
+                .layer(trace_layer)
+                .service_fn(|req: Request<Body>| async move {
+                    let status = if req.uri().path() == "/fail" {
+                        http::StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        http::StatusCode::OK
+                    };
+                    let mut res = Response::new(Body::empty());
+                    *res.status_mut() = status;
+                    Ok::<_, BoxError>(res)
+                });
+
+        for path in ["/ok", "/fail"] {
+            let req = Request::builder()
+                .uri(path)
+                .header(
+                    "traceparent",
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+                )
+                .body(Body::empty())
+                .unwrap();
+            svc.call(req).await.unwrap();
+        }
+
+        assert_eq!(0, ON_REQUEST_COUNT.load(Ordering::SeqCst), "request");
+        assert_eq!(1, ON_RESPONSE_COUNT.load(Ordering::SeqCst), "response");
+        assert_eq!(1, ON_FAILURE.load(Ordering::SeqCst), "failure");
+        // only the failed request got a span, made from its buffered head
+        assert_eq!(0, MAKE_SPAN.load(Ordering::SeqCst), "make span");
+        assert_eq!(
+            1,
+            MAKE_SPAN_FROM_HEAD.load(Ordering::SeqCst),
+            "span from head"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_metadata_is_passed_to_callbacks() {
+        static ON_RESPONSE_COUNT: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+        static ON_FAILURE: Lazy<AtomicU32> = Lazy::new(|| AtomicU32::new(0));
+
+        let trace_layer = TraceLayer::new_for_http()
+            .on_response(
+                |_res: &Response<Body>,
+                 _latency: Duration,
+                 request: &RequestMetadata,
+                 _span: &Span| {
+                    assert_eq!(request.method(), http::Method::POST);
+                    assert_eq!(request.uri().path(), "/users/42");
+                    assert_eq!(request.matched_route().unwrap().as_str(), "/users/:id");
+                    ON_RESPONSE_COUNT.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+            .on_failure(
+                |_class: ServerErrorsFailureClass,
+                 _latency: Duration,
+                 request: &RequestMetadata,
+                 _span: &Span| {
+                    assert_eq!(request.uri().path(), "/users/42");
+                    ON_FAILURE.fetch_add(1, Ordering::SeqCst);
+                },
+            );
+
+        let svc =
+            ServiceBuilder::new()
+                .layer(trace_layer)
+                .service_fn(|req: Request<Body>| async move {
+                    // the request metadata is captured before calling the inner service
+                    assert!(req.extensions().get::<MatchedRoute>().is_some());
+                    let mut res = Response::new(Body::empty());
+                    *res.status_mut() = http::StatusCode::INTERNAL_SERVER_ERROR;
+                    Ok::<_, BoxError>(res)
+                });
+
+        let mut req = Request::builder()
+            .method(http::Method::POST)
+            .uri("/users/42")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(MatchedRoute::new("/users/:id"));
+        svc.call(req).await.unwrap();
+
+        assert_eq!(1, ON_RESPONSE_COUNT.load(Ordering::SeqCst), "response");
+        assert_eq!(1, ON_FAILURE.load(Ordering::SeqCst), "failure");
+    }
+
     async fn echo(req: Request<Body>) -> Result<Response<Body>, BoxError> {
         Ok(Response::new(req.into_body()))
     }
@@ -0,0 +1,196 @@
+use super::{Latency, DEFAULT_MESSAGE_LEVEL};
+use crate::LatencyUnit;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use tracing::{Level, Span};
+
+/// Trait used to tell [`Trace`] what to do when a request is aborted before it was fully handled.
+///
+/// See the [module docs](../trace/index.html#on_abort) for details on exactly when the
+/// `on_abort` callback is called.
+///
+/// [`Trace`]: super::Trace
+pub trait OnAbort {
+    /// Do the thing.
+    ///
+    /// `latency` is the duration since the request was received.
+    ///
+    /// `span` is the `tracing` [`Span`], corresponding to this request, produced by the closure
+    /// passed to [`TraceLayer::make_span_with`]. It can be used to [record field values][record]
+    /// that weren't known when the span was created.
+    ///
+    /// [`Span`]: https://docs.rs/tracing/latest/tracing/span/index.html
+    /// [record]: https://docs.rs/tracing/latest/tracing/span/struct.Span.html#method.record
+    /// [`TraceLayer::make_span_with`]: crate::trace::TraceLayer::make_span_with
+    fn on_abort(&self, reason: AbortReason, latency: Duration, span: &Span);
+}
+
+impl OnAbort for () {
+    #[inline]
+    fn on_abort(&self, _: AbortReason, _: Duration, _: &Span) {}
+}
+
+impl<F> OnAbort for F
+where
+    F: Fn(AbortReason, Duration, &Span),
+{
+    fn on_abort(&self, reason: AbortReason, latency: Duration, span: &Span) {
+        self(reason, latency, span)
+    }
+}
+
+/// The reason why a request was considered aborted.
+///
+/// HTTP servers such as `hyper` drop the response future or the response body
+/// as soon as they notice that the client hung up. [`Trace`] detects this and reports
+/// it through [`OnAbort`] rather than [`OnFailure`], so that clients going away
+/// do not get counted as server failures.
+///
+/// Response bodies which fail while they are streamed, such as a file failing to be read,
+/// are reported as aborted as well, unless the failure was already reported to [`OnFailure`]
+/// by classifying the end of the stream. [`AbortReason::is_client`] tells both causes apart.
+///
+/// [`Trace`]: super::Trace
+/// [`OnFailure`]: super::OnFailure
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortReason {
+    /// The response future was dropped before the inner service produced a response.
+    ResponseFutureDropped,
+    /// The response body was dropped before it reached its end of stream.
+    ///
+    /// Bodies which are never sent, those of responses to `HEAD` requests and of
+    /// `1xx`, `204 No Content` and `304 Not Modified` responses, aren't reported.
+    ResponseBodyDropped,
+    /// The response body returned an error before it reached its end of stream.
+    ResponseBodyFailed,
+}
+
+impl AbortReason {
+    /// Returns `true` if the request was aborted because the client went away, rather than
+    /// because the response body failed.
+    pub fn is_client(&self) -> bool {
+        matches!(
+            self,
+            Self::ResponseFutureDropped | Self::ResponseBodyDropped
+        )
+    }
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ResponseFutureDropped => f.write_str("response future dropped"),
+            Self::ResponseBodyDropped => f.write_str("response body dropped"),
+            Self::ResponseBodyFailed => f.write_str("response body failed"),
+        }
+    }
+}
+
+/// The default [`OnAbort`] implementation used by [`Trace`].
+///
+/// [`Trace`]: super::Trace
+#[derive(Clone, Debug)]
+pub struct DefaultOnAbort {
+    level: Level,
+    latency_unit: LatencyUnit,
+}
+
+impl Default for DefaultOnAbort {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_MESSAGE_LEVEL,
+            latency_unit: LatencyUnit::Millis,
+        }
+    }
+}
+
+impl DefaultOnAbort {
+    /// Create a new `DefaultOnAbort`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the [`Level`] used for [tracing events].
+    ///
+    /// Defaults to [`Level::DEBUG`].
+    ///
+    /// [tracing events]: https://docs.rs/tracing/latest/tracing/#events
+    /// [`Level::DEBUG`]: https://docs.rs/tracing/latest/tracing/struct.Level.html#associatedconstant.DEBUG
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set the [`LatencyUnit`] latencies will be reported in.
+    ///
+    /// Defaults to [`LatencyUnit::Millis`].
+    pub fn latency_unit(mut self, latency_unit: LatencyUnit) -> Self {
+        self.latency_unit = latency_unit;
+        self
+    }
+}
+
+impl OnAbort for DefaultOnAbort {
+    fn on_abort(&self, reason: AbortReason, latency: Duration, _: &Span) {
+        let latency = Latency {
+            unit: self.latency_unit,
+            duration: latency,
+        };
+        if reason.is_client() {
+            event_dynamic_lvl!(
+                self.level,
+                %reason,
+                %latency,
+                "request aborted by client"
+            );
+        } else {
+            event_dynamic_lvl!(self.level, %reason, %latency, "request aborted");
+        }
+    }
+}
+
+/// Calls [`OnAbort::on_abort`] when dropped while still armed.
+pub(crate) struct AbortGuard<T> {
+    armed: Option<(T, Span)>,
+    reason: AbortReason,
+    start: Instant,
+    // captured at construction so that neither the guard nor the
+    // types embedding it have to carry an `OnAbort` bound
+    fire: fn(&T, AbortReason, Duration, &Span),
+}
+
+impl<T> AbortGuard<T> {
+    pub(crate) fn new(on_abort: T, reason: AbortReason, start: Instant, span: Span) -> Self
+    where
+        T: OnAbort,
+    {
+        Self {
+            armed: Some((on_abort, span)),
+            reason,
+            start,
+            fire: T::on_abort,
+        }
+    }
+
+    pub(crate) fn disarm(&mut self) {
+        self.armed = None;
+    }
+
+    /// Calls [`OnAbort::on_abort`] right away with the given reason, if still armed.
+    pub(crate) fn abort(&mut self, reason: AbortReason) {
+        self.reason = reason;
+        if let Some((on_abort, span)) = self.armed.take() {
+            let _guard = span.enter();
+            (self.fire)(&on_abort, self.reason, self.start.elapsed(), &span);
+        }
+    }
+}
+
+impl<T> Drop for AbortGuard<T> {
+    fn drop(&mut self) {
+        self.abort(self.reason);
+    }
+}
@@ -1,5 +1,7 @@
 use super::{Latency, DEFAULT_MESSAGE_LEVEL};
-use crate::{classify::grpc_errors_as_failures::ParsedGrpcStatus, LatencyUnit};
+use crate::{
+    body_size::BodySize, classify::grpc_errors_as_failures::ParsedGrpcStatus, LatencyUnit,
+};
 use http::header::HeaderMap;
 use std::time::Duration;
 use tracing::{Level, Span};
@@ -23,6 +25,25 @@ pub trait OnEos {
     /// [record]: https://docs.rs/tracing/latest/tracing/span/struct.Span.html#method.record
     /// [`TraceLayer::make_span_with`]: crate::trace::TraceLayer::make_span_with
     fn on_eos(self, trailers: Option<&HeaderMap>, stream_duration: Duration, span: &Span);
+
+    /// Do the thing, given the size of the response body.
+    ///
+    /// `size` is the [`BodySize`] of the response body that was sent, see the
+    /// [`body_size`](crate::body_size) module for more details.
+    ///
+    /// Defaults to calling [`OnEos::on_eos`].
+    fn on_eos_with_size(
+        self,
+        trailers: Option<&HeaderMap>,
+        stream_duration: Duration,
+        size: BodySize,
+        span: &Span,
+    ) where
+        Self: Sized,
+    {
+        let _ = size;
+        self.on_eos(trailers, stream_duration, span)
+    }
 }
 
 impl OnEos for () {
@@ -83,13 +104,9 @@ impl DefaultOnEos {
     }
 }
 
-impl OnEos for DefaultOnEos {
-    fn on_eos(self, trailers: Option<&HeaderMap>, stream_duration: Duration, _span: &Span) {
-        let stream_duration = Latency {
-            unit: self.latency_unit,
-            duration: stream_duration,
-        };
-        let status = trailers.and_then(|trailers| {
+impl DefaultOnEos {
+    fn grpc_status(trailers: Option<&HeaderMap>) -> Option<i32> {
+        trailers.and_then(|trailers| {
             match crate::classify::grpc_errors_as_failures::classify_grpc_metadata(
                 trailers,
                 crate::classify::GrpcCode::Ok.into_bitmask(),
@@ -100,8 +117,43 @@ impl OnEos for DefaultOnEos {
                 ParsedGrpcStatus::NonSuccess(status) => Some(status.get()),
                 ParsedGrpcStatus::GrpcStatusHeaderMissing => None,
             }
-        });
+        })
+    }
+}
+
+impl OnEos for DefaultOnEos {
+    fn on_eos(self, trailers: Option<&HeaderMap>, stream_duration: Duration, _span: &Span) {
+        let stream_duration = Latency {
+            unit: self.latency_unit,
+            duration: stream_duration,
+        };
+        let status = Self::grpc_status(trailers);
 
         event_dynamic_lvl!(self.level, %stream_duration, status, "end of stream");
     }
+
+    fn on_eos_with_size(
+        self,
+        trailers: Option<&HeaderMap>,
+        stream_duration: Duration,
+        size: BodySize,
+        _span: &Span,
+    ) {
+        let stream_duration = Latency {
+            unit: self.latency_unit,
+            duration: stream_duration,
+        };
+        let status = Self::grpc_status(trailers);
+        let bytes = size.bytes();
+        let compression_ratio = size.compression_ratio();
+
+        event_dynamic_lvl!(
+            self.level,
+            %stream_duration,
+            status,
+            bytes,
+            compression_ratio,
+            "end of stream"
+        );
+    }
 }
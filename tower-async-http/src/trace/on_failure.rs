@@ -1,4 +1,4 @@
-use super::{Latency, DEFAULT_ERROR_LEVEL};
+use super::{Latency, RequestMetadata, DEFAULT_ERROR_LEVEL};
 use crate::LatencyUnit;
 use std::{fmt, time::Duration};
 use tracing::{Level, Span};
@@ -14,6 +14,8 @@ pub trait OnFailure<FailureClass> {
     ///
     /// `latency` is the duration since the request was received.
     ///
+    /// `request` is the [`RequestMetadata`] of the request that failed.
+    ///
     /// `span` is the `tracing` [`Span`], corresponding to this request, produced by the closure
     /// passed to [`TraceLayer::make_span_with`]. It can be used to [record field values][record]
     /// that weren't known when the span was created.
@@ -21,20 +23,32 @@ pub trait OnFailure<FailureClass> {
     /// [`Span`]: https://docs.rs/tracing/latest/tracing/span/index.html
     /// [record]: https://docs.rs/tracing/latest/tracing/span/struct.Span.html#method.record
     /// [`TraceLayer::make_span_with`]: crate::trace::TraceLayer::make_span_with
-    fn on_failure(&self, failure_classification: FailureClass, latency: Duration, span: &Span);
+    fn on_failure(
+        &self,
+        failure_classification: FailureClass,
+        latency: Duration,
+        request: &RequestMetadata,
+        span: &Span,
+    );
 }
 
 impl<FailureClass> OnFailure<FailureClass> for () {
     #[inline]
-    fn on_failure(&self, _: FailureClass, _: Duration, _: &Span) {}
+    fn on_failure(&self, _: FailureClass, _: Duration, _: &RequestMetadata, _: &Span) {}
 }
 
 impl<F, FailureClass> OnFailure<FailureClass> for F
 where
-    F: Fn(FailureClass, Duration, &Span),
+    F: Fn(FailureClass, Duration, &RequestMetadata, &Span),
 {
-    fn on_failure(&self, failure_classification: FailureClass, latency: Duration, span: &Span) {
-        self(failure_classification, latency, span)
+    fn on_failure(
+        &self,
+        failure_classification: FailureClass,
+        latency: Duration,
+        request: &RequestMetadata,
+        span: &Span,
+    ) {
+        self(failure_classification, latency, request, span)
     }
 }
 
@@ -85,7 +99,13 @@ impl<FailureClass> OnFailure<FailureClass> for DefaultOnFailure
 where
     FailureClass: fmt::Display,
 {
-    fn on_failure(&self, failure_classification: FailureClass, latency: Duration, _: &Span) {
+    fn on_failure(
+        &self,
+        failure_classification: FailureClass,
+        latency: Duration,
+        _: &RequestMetadata,
+        _: &Span,
+    ) {
         let latency = Latency {
             unit: self.latency_unit,
             duration: latency,
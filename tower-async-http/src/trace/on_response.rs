@@ -1,4 +1,4 @@
-use super::{Latency, DEFAULT_MESSAGE_LEVEL};
+use super::{Latency, RequestMetadata, DEFAULT_MESSAGE_LEVEL};
 use crate::LatencyUnit;
 use http::Response;
 use std::time::Duration;
@@ -16,6 +16,8 @@ pub trait OnResponse<B> {
     ///
     /// `latency` is the duration since the request was received.
     ///
+    /// `request` is the [`RequestMetadata`] of the request this is the response to.
+    ///
     /// `span` is the `tracing` [`Span`], corresponding to this request, produced by the closure
     /// passed to [`TraceLayer::make_span_with`]. It can be used to [record field values][record]
     /// that weren't known when the span was created.
@@ -23,20 +25,32 @@ pub trait OnResponse<B> {
     /// [`Span`]: https://docs.rs/tracing/latest/tracing/span/index.html
     /// [record]: https://docs.rs/tracing/latest/tracing/span/struct.Span.html#method.record
     /// [`TraceLayer::make_span_with`]: crate::trace::TraceLayer::make_span_with
-    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span);
+    fn on_response(
+        self,
+        response: &Response<B>,
+        latency: Duration,
+        request: &RequestMetadata,
+        span: &Span,
+    );
 }
 
 impl<B> OnResponse<B> for () {
     #[inline]
-    fn on_response(self, _: &Response<B>, _: Duration, _: &Span) {}
+    fn on_response(self, _: &Response<B>, _: Duration, _: &RequestMetadata, _: &Span) {}
 }
 
 impl<B, F> OnResponse<B> for F
 where
-    F: Fn(&Response<B>, Duration, &Span),
+    F: Fn(&Response<B>, Duration, &RequestMetadata, &Span),
 {
-    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
-        self(response, latency, span)
+    fn on_response(
+        self,
+        response: &Response<B>,
+        latency: Duration,
+        request: &RequestMetadata,
+        span: &Span,
+    ) {
+        self(response, latency, request, span)
     }
 }
 
@@ -102,7 +116,7 @@ impl DefaultOnResponse {
 }
 
 impl<B> OnResponse<B> for DefaultOnResponse {
-    fn on_response(self, response: &Response<B>, latency: Duration, _: &Span) {
+    fn on_response(self, response: &Response<B>, latency: Duration, _: &RequestMetadata, _: &Span) {
         let latency = Latency {
             unit: self.latency_unit,
             duration: latency,
@@ -0,0 +1,79 @@
+use http::{Method, Request, Uri, Version};
+use std::{fmt, sync::Arc};
+
+/// Metadata of a request, captured by [`Trace`] before the request is passed to the inner service.
+///
+/// It is passed to [`OnResponse`] and [`OnFailure`], such that responses and failures can be
+/// correlated with the request they belong to, without having to rely on span fields.
+///
+/// [`Trace`]: super::Trace
+/// [`OnResponse`]: super::OnResponse
+/// [`OnFailure`]: super::OnFailure
+#[derive(Debug, Clone)]
+pub struct RequestMetadata {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    matched_route: Option<MatchedRoute>,
+}
+
+impl RequestMetadata {
+    /// Capture the metadata of a request.
+    pub fn from_request<B>(request: &Request<B>) -> Self {
+        Self {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            version: request.version(),
+            matched_route: request.extensions().get::<MatchedRoute>().cloned(),
+        }
+    }
+
+    /// Returns the method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the URI of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Returns the HTTP version of the request.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns the [`MatchedRoute`] of the request, if any.
+    pub fn matched_route(&self) -> Option<&MatchedRoute> {
+        self.matched_route.as_ref()
+    }
+}
+
+/// The route template that matched a request, such as `/users/:id`.
+///
+/// Routers and middleware that run before [`Trace`] can insert it into the
+/// request extensions, to have it captured in the [`RequestMetadata`].
+/// Unlike the URI, route templates have a bounded number of values,
+/// which makes them suitable for aggregating responses per route.
+///
+/// [`Trace`]: super::Trace
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MatchedRoute(Arc<str>);
+
+impl MatchedRoute {
+    /// Create a new [`MatchedRoute`].
+    pub fn new(route: impl Into<Arc<str>>) -> Self {
+        Self(route.into())
+    }
+
+    /// Returns the route template as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MatchedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
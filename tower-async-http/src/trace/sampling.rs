@@ -0,0 +1,182 @@
+use http::{HeaderMap, HeaderValue};
+
+const TRACEPARENT: &str = "traceparent";
+const FLAG_SAMPLED: u8 = 0x01;
+
+/// The [W3C trace context] of a request, as propagated by the `traceparent` header.
+///
+/// When [`Sampling::honor_traceparent`] is enabled, [`Trace`] inserts the trace context
+/// of a request into its extensions, such that it can be propagated to upstream services.
+///
+/// [W3C trace context]: https://www.w3.org/TR/trace-context/
+/// [`Trace`]: super::Trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    trace_id: u128,
+    parent_id: u64,
+    flags: u8,
+}
+
+impl TraceParent {
+    /// Parse the trace context from the `traceparent` header.
+    ///
+    /// Returns `None` if the header is missing or invalid.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::parse(headers.get(TRACEPARENT)?.to_str().ok()?)
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+
+        // unknown future versions may append more fields, version 00 may not
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id.len() != 32 || parent_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+        // `from_str_radix` accepts a leading `+`, which is not valid here
+        if ![version, trace_id, parent_id, flags]
+            .iter()
+            .all(|part| part.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        if trace_id == 0 || parent_id == 0 {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            parent_id,
+            flags,
+        })
+    }
+
+    /// Returns the id of the trace.
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// Returns the id of the span of the caller.
+    pub fn parent_id(&self) -> u64 {
+        self.parent_id
+    }
+
+    /// Returns the trace flags.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns `true` if the caller sampled the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & FLAG_SAMPLED != 0
+    }
+
+    /// Encode the trace context as a `traceparent` header value,
+    /// with the given id of the span of the current service as parent.
+    pub fn to_header_value(&self, parent_id: u64) -> HeaderValue {
+        let value = format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, parent_id, self.flags
+        );
+        HeaderValue::try_from(value).expect("valid header value")
+    }
+}
+
+/// Decides which requests are traced by [`Trace`].
+///
+/// By default all requests are sampled.
+///
+/// See the [module docs](super#sampling) for more details.
+///
+/// [`Trace`]: super::Trace
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sampling {
+    honor_traceparent: bool,
+    sample_failures: bool,
+}
+
+impl Sampling {
+    /// Create a new [`Sampling`] that samples all requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Honor the sampling decision of the caller, as propagated by the `sampled`
+    /// flag of the `traceparent` header.
+    ///
+    /// Requests without a valid `traceparent` header are sampled.
+    pub fn honor_traceparent(mut self, honor: bool) -> Self {
+        self.honor_traceparent = honor;
+        self
+    }
+
+    /// Retroactively sample requests that were not sampled,
+    /// once they are classified as a failure.
+    pub fn sample_failures(mut self, sample: bool) -> Self {
+        self.sample_failures = sample;
+        self
+    }
+
+    pub(crate) fn is_sampled(&self, trace_parent: Option<&TraceParent>) -> bool {
+        match trace_parent {
+            Some(trace_parent) if self.honor_traceparent => trace_parent.is_sampled(),
+            _ => true,
+        }
+    }
+
+    pub(crate) fn honors_traceparent(&self) -> bool {
+        self.honor_traceparent
+    }
+
+    pub(crate) fn samples_failures(&self) -> bool {
+        self.sample_failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_traceparent() {
+        let tp =
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(tp.trace_id(), 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(tp.parent_id(), 0x00f067aa0ba902b7);
+        assert!(tp.is_sampled());
+        assert_eq!(
+            tp.to_header_value(0x1),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000001-01"
+        );
+
+        let tp =
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap();
+        assert!(!tp.is_sampled());
+    }
+
+    #[test]
+    fn parse_invalid_traceparent() {
+        for s in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-+bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(TraceParent::parse(s), None, "{:?}", s);
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use super::{
-    DefaultMakeSpan, DefaultOnBodyChunk, DefaultOnEos, DefaultOnFailure, DefaultOnRequest,
-    DefaultOnResponse, MakeSpan, OnBodyChunk, OnEos, OnFailure, OnRequest, OnResponse,
-    ResponseBody, TraceLayer,
+    on_abort::AbortGuard, AbortReason, DefaultMakeSpan, DefaultOnAbort, DefaultOnBodyChunk,
+    DefaultOnEos, DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, MakeSpan, OnAbort,
+    OnBodyChunk, OnEos, OnFailure, OnRequest, OnResponse, RequestMetadata, ResponseBody, Sampling,
+    TraceLayer, TraceParent,
 };
+use crate::body_size::BodySizeCounter;
 use crate::classify::{
     ClassifiedResponse, ClassifyResponse, GrpcErrorsAsFailures, MakeClassifier,
     ServerErrorsAsFailures, SharedClassifier,
@@ -11,6 +13,7 @@ use http::{Request, Response};
 use http_body::Body;
 use std::{fmt, time::Instant};
 use tower_async_service::Service;
+use tracing::Span;
 
 /// Middleware that adds high level [tracing] to a [`Service`].
 ///
@@ -28,6 +31,7 @@ pub struct Trace<
     OnBodyChunk = DefaultOnBodyChunk,
     OnEos = DefaultOnEos,
     OnFailure = DefaultOnFailure,
+    OnAbort = DefaultOnAbort,
 > {
     pub(crate) inner: S,
     pub(crate) make_classifier: M,
@@ -37,6 +41,9 @@ pub struct Trace<
     pub(crate) on_body_chunk: OnBodyChunk,
     pub(crate) on_eos: OnEos,
     pub(crate) on_failure: OnFailure,
+    pub(crate) on_abort: OnAbort,
+    pub(crate) sampling: Sampling,
+    pub(crate) always_on_eos: bool,
 }
 
 impl<S, M> Trace<S, M> {
@@ -54,6 +61,9 @@ impl<S, M> Trace<S, M> {
             on_body_chunk: DefaultOnBodyChunk::default(),
             on_eos: DefaultOnEos::default(),
             on_failure: DefaultOnFailure::default(),
+            on_abort: DefaultOnAbort::default(),
+            sampling: Sampling::default(),
+            always_on_eos: false,
         }
     }
 
@@ -68,8 +78,8 @@ impl<S, M> Trace<S, M> {
     }
 }
 
-impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
-    Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
+impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
 {
     define_inner_service_accessors!();
 
@@ -81,7 +91,8 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_request<NewOnRequest>(
         self,
         new_on_request: NewOnRequest,
-    ) -> Trace<S, M, MakeSpan, NewOnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure> {
+    ) -> Trace<S, M, MakeSpan, NewOnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         Trace {
             on_request: new_on_request,
             inner: self.inner,
@@ -91,6 +102,9 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             make_span: self.make_span,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -102,7 +116,8 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_response<NewOnResponse>(
         self,
         new_on_response: NewOnResponse,
-    ) -> Trace<S, M, MakeSpan, OnRequest, NewOnResponse, OnBodyChunk, OnEos, OnFailure> {
+    ) -> Trace<S, M, MakeSpan, OnRequest, NewOnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         Trace {
             on_response: new_on_response,
             inner: self.inner,
@@ -112,6 +127,9 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_eos: self.on_eos,
             make_span: self.make_span,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -123,7 +141,8 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_body_chunk<NewOnBodyChunk>(
         self,
         new_on_body_chunk: NewOnBodyChunk,
-    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, NewOnBodyChunk, OnEos, OnFailure> {
+    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, NewOnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         Trace {
             on_body_chunk: new_on_body_chunk,
             on_eos: self.on_eos,
@@ -133,6 +152,9 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_request: self.on_request,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -144,7 +166,8 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_eos<NewOnEos>(
         self,
         new_on_eos: NewOnEos,
-    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, NewOnEos, OnFailure> {
+    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, NewOnEos, OnFailure, OnAbort>
+    {
         Trace {
             on_eos: new_on_eos,
             make_span: self.make_span,
@@ -154,6 +177,9 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_body_chunk: self.on_body_chunk,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
 
@@ -165,7 +191,8 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn on_failure<NewOnFailure>(
         self,
         new_on_failure: NewOnFailure,
-    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, NewOnFailure> {
+    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, NewOnFailure, OnAbort>
+    {
         Trace {
             on_failure: new_on_failure,
             inner: self.inner,
@@ -175,6 +202,34 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_eos: self.on_eos,
             on_response: self.on_response,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
+        }
+    }
+
+    /// Customize what to do when a request has been aborted by the client.
+    ///
+    /// `NewOnAbort` is expected to implement [`OnAbort`].
+    ///
+    /// [`OnAbort`]: super::OnAbort
+    pub fn on_abort<NewOnAbort>(
+        self,
+        new_on_abort: NewOnAbort,
+    ) -> Trace<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, NewOnAbort>
+    {
+        Trace {
+            on_abort: new_on_abort,
+            inner: self.inner,
+            make_span: self.make_span,
+            on_request: self.on_request,
+            on_response: self.on_response,
+            on_body_chunk: self.on_body_chunk,
+            on_eos: self.on_eos,
+            on_failure: self.on_failure,
+            make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
         }
     }
 
@@ -187,7 +242,8 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
     pub fn make_span_with<NewMakeSpan>(
         self,
         new_make_span: NewMakeSpan,
-    ) -> Trace<S, M, NewMakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure> {
+    ) -> Trace<S, M, NewMakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure, OnAbort>
+    {
         Trace {
             make_span: new_make_span,
             inner: self.inner,
@@ -197,8 +253,30 @@ impl<S, M, MakeSpan, OnRequest, OnResponse, OnBodyChunk, OnEos, OnFailure>
             on_response: self.on_response,
             on_eos: self.on_eos,
             make_classifier: self.make_classifier,
+            sampling: self.sampling,
+            always_on_eos: self.always_on_eos,
+            on_abort: self.on_abort,
         }
     }
+
+    /// Customize which requests are traced.
+    ///
+    /// See the [module docs](super#sampling) for more details.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Call `on_eos` at the end of every response body, not only of those whose
+    /// end-of-stream is classified.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// See the [module docs](super#body-size) for more details.
+    pub fn always_on_eos(mut self, always_on_eos: bool) -> Self {
+        self.always_on_eos = always_on_eos;
+        self
+    }
 }
 
 impl<S>
@@ -225,6 +303,9 @@ impl<S>
             on_body_chunk: DefaultOnBodyChunk::default(),
             on_eos: DefaultOnEos::default(),
             on_failure: DefaultOnFailure::default(),
+            on_abort: DefaultOnAbort::default(),
+            sampling: Sampling::default(),
+            always_on_eos: false,
         }
     }
 }
@@ -253,6 +334,9 @@ impl<S>
             on_body_chunk: DefaultOnBodyChunk::default(),
             on_eos: DefaultOnEos::default(),
             on_failure: DefaultOnFailure::default(),
+            on_abort: DefaultOnAbort::default(),
+            sampling: Sampling::default(),
+            always_on_eos: false,
         }
     }
 }
@@ -267,9 +351,10 @@ impl<
         OnFailureT,
         OnBodyChunkT,
         OnEosT,
+        OnAbortT,
         MakeSpanT,
     > Service<Request<ReqBody>>
-    for Trace<S, M, MakeSpanT, OnRequestT, OnResponseT, OnBodyChunkT, OnEosT, OnFailureT>
+    for Trace<S, M, MakeSpanT, OnRequestT, OnResponseT, OnBodyChunkT, OnEosT, OnFailureT, OnAbortT>
 where
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
     ReqBody: Body,
@@ -284,74 +369,165 @@ where
     OnBodyChunkT: OnBodyChunk<ResBody::Data> + Clone,
     OnEosT: OnEos + Clone,
     OnFailureT: OnFailure<M::FailureClass> + Clone,
+    OnAbortT: OnAbort + Clone,
 {
     type Response =
-        Response<ResponseBody<ResBody, M::ClassifyEos, OnBodyChunkT, OnEosT, OnFailureT>>;
+        Response<ResponseBody<ResBody, M::ClassifyEos, OnBodyChunkT, OnEosT, OnFailureT, OnAbortT>>;
     type Error = S::Error;
 
-    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+    async fn call(&self, mut req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
         let start = Instant::now();
 
-        let span = self.make_span.make_span(&req);
+        let trace_parent = TraceParent::from_headers(req.headers());
+        let sampled = self.sampling.is_sampled(trace_parent.as_ref());
+        let samples_failures = self.sampling.samples_failures();
+        if let Some(trace_parent) = trace_parent.filter(|_| self.sampling.honors_traceparent()) {
+            req.extensions_mut().insert(trace_parent);
+        }
+
+        // spans are only made for sampled requests, unsampled ones which may still fail
+        // buffer their head instead, to make their span retroactively
+        let span = if sampled {
+            self.make_span.make_span(&req)
+        } else {
+            Span::none()
+        };
+        let head = (!sampled && samples_failures).then(|| request_head(&req));
 
         let classifier = self.make_classifier.make_classifier(&req);
 
+        let request = RequestMetadata::from_request(&req);
+
+        // the response future is dropped when the client goes away before we got a response
+        let mut abort_guard = AbortGuard::new(
+            self.on_abort.clone(),
+            AbortReason::ResponseFutureDropped,
+            start,
+            span.clone(),
+        );
+        if !sampled {
+            abort_guard.disarm();
+        }
+
         let result = {
             let _guard = span.enter();
-            self.on_request.on_request(&req, &span);
+            if sampled {
+                self.on_request.on_request(&req, &span);
+            }
             self.inner.call(req)
         }
         .await;
         let latency = start.elapsed();
+        abort_guard.disarm();
 
         match result {
             Ok(res) => {
                 let classification = classifier.classify_response(&res);
 
-                self.on_response.clone().on_response(&res, latency, &span);
+                // unsampled responses are only traced once they are known to be a failure
+                let failed = matches!(classification, ClassifiedResponse::Ready(Err(_)));
+                let span = match &head {
+                    Some(head) if !matches!(classification, ClassifiedResponse::Ready(Ok(_))) => {
+                        self.make_span.make_span_from_head(head)
+                    }
+                    _ => span,
+                };
+                if sampled || (failed && samples_failures) {
+                    self.on_response
+                        .clone()
+                        .on_response(&res, latency, &request, &span);
+                }
+
+                let on_failure = (sampled || samples_failures).then(|| self.on_failure.clone());
 
                 match classification {
                     ClassifiedResponse::Ready(classification) => {
                         if let Err(failure_class) = classification {
-                            self.on_failure.on_failure(failure_class, latency, &span);
+                            if let Some(on_failure) = &on_failure {
+                                on_failure.on_failure(failure_class, latency, &request, &span);
+                            }
                         }
 
                         let span = span.clone();
-                        let res = res.map(|body| ResponseBody {
+                        let (parts, body) = res.into_parts();
+                        let status = parts.status;
+                        let body = ResponseBody {
                             inner: body,
                             classify_eos: None,
-                            on_eos: None,
+                            on_eos: self
+                                .always_on_eos
+                                .then(|| (self.on_eos.clone(), Instant::now())),
+                            size: BodySizeCounter::new(&parts.extensions),
                             on_body_chunk: self.on_body_chunk.clone(),
-                            on_failure: Some(self.on_failure.clone()),
+                            on_failure,
+                            on_abort: AbortGuard::new(
+                                self.on_abort.clone(),
+                                AbortReason::ResponseBodyDropped,
+                                start,
+                                span.clone(),
+                            ),
+                            sampled,
+                            request,
                             start,
                             span,
-                        });
+                        }
+                        .disarm_abort_if_unneeded(status);
 
-                        Ok(res)
+                        Ok(Response::from_parts(parts, body))
                     }
                     ClassifiedResponse::RequiresEos(classify_eos) => {
                         let span = span.clone();
-                        let res = res.map(|body| ResponseBody {
+                        let (parts, body) = res.into_parts();
+                        let status = parts.status;
+                        let body = ResponseBody {
                             inner: body,
                             classify_eos: Some(classify_eos),
                             on_eos: Some((self.on_eos.clone(), Instant::now())),
+                            size: BodySizeCounter::new(&parts.extensions),
                             on_body_chunk: self.on_body_chunk.clone(),
-                            on_failure: Some(self.on_failure.clone()),
+                            on_failure,
+                            on_abort: AbortGuard::new(
+                                self.on_abort.clone(),
+                                AbortReason::ResponseBodyDropped,
+                                start,
+                                span.clone(),
+                            ),
+                            sampled,
+                            request,
                             start,
                             span,
-                        });
+                        }
+                        .disarm_abort_if_unneeded(status);
 
-                        Ok(res)
+                        Ok(Response::from_parts(parts, body))
                     }
                 }
             }
             Err(err) => {
                 let failure_class: <M as MakeClassifier>::FailureClass =
                     classifier.classify_error(&err);
-                self.on_failure.on_failure(failure_class, latency, &span);
+                let span = match &head {
+                    Some(head) => self.make_span.make_span_from_head(head),
+                    None => span,
+                };
+                if sampled || samples_failures {
+                    self.on_failure
+                        .on_failure(failure_class, latency, &request, &span);
+                }
 
                 Err(err)
             }
         }
     }
 }
+
+/// Buffers the head of a request, to make a span for it once the request was passed on.
+fn request_head<B>(req: &Request<B>) -> Request<()> {
+    let mut head = Request::new(());
+    *head.method_mut() = req.method().clone();
+    *head.uri_mut() = req.uri().clone();
+    *head.version_mut() = req.version();
+    *head.headers_mut() = req.headers().clone();
+    *head.extensions_mut() = req.extensions().clone();
+    head
+}
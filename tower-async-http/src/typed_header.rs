@@ -0,0 +1,368 @@
+//! Typed header support, using the [`headers`] crate.
+//!
+//! Instead of reading and writing header values as strings, the types of the [`headers`]
+//! crate, or any custom type implementing [`Header`], can be used to:
+//!
+//! - read and write headers of requests and responses with [`TypedHeadersExt`];
+//! - insert headers into responses with [`InsertTypedHeaderLayer`];
+//! - validate request headers with [`RequireTypedHeader`],
+//!   also available as [`ValidateRequestHeaderLayer::typed_header`].
+//!
+//! # Example
+//!
+//! ```
+//! use headers::{authorization::Bearer, Authorization, CacheControl};
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use bytes::Bytes;
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::{
+//!     typed_header::{InsertTypedHeaderLayer, RequireTypedHeader, TypedHeadersExt},
+//!     validate_request::ValidateRequestHeaderLayer,
+//! };
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     // The header was validated already, so it's known to be present.
+//!     let auth = req.typed_header::<Authorization<Bearer>>().unwrap();
+//!     # assert_eq!(auth.token(), "secret");
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     // Don't allow responses to be cached.
+//!     .layer(InsertTypedHeaderLayer::overriding(CacheControl::new().with_no_store()))
+//!     // Require a bearer token.
+//!     .layer(ValidateRequestHeaderLayer::custom(
+//!         RequireTypedHeader::new(|auth: &Authorization<Bearer>| auth.token() == "secret")
+//!             .rejection_status(StatusCode::UNAUTHORIZED),
+//!     ))
+//!     .service_fn(handle);
+//!
+//! let mut req = Request::new(Full::default());
+//! req.insert_typed_header(Authorization::bearer("secret").unwrap());
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::OK);
+//! assert_eq!(res.typed_header::<CacheControl>(), Some(CacheControl::new().with_no_store()));
+//!
+//! let res = svc.call(Request::new(Full::default())).await?;
+//! assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`headers`]: https://docs.rs/headers
+//! [`ValidateRequestHeaderLayer::typed_header`]: crate::validate_request::ValidateRequestHeaderLayer::typed_header
+
+use crate::validate_request::ValidateRequest;
+use headers::{Header, HeaderMapExt};
+use http::{Request, Response, StatusCode};
+use std::{fmt, marker::PhantomData};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Extension trait to read and write typed headers of requests and responses.
+pub trait TypedHeadersExt: sealed::Sealed {
+    /// Returns the header of type `H`, if present and valid.
+    fn typed_header<H>(&self) -> Option<H>
+    where
+        H: Header;
+
+    /// Returns the header of type `H`, if present,
+    /// or an error if the header is present but invalid.
+    fn try_typed_header<H>(&self) -> Result<Option<H>, headers::Error>
+    where
+        H: Header;
+
+    /// Insert the header of type `H`, replacing any previous values.
+    fn insert_typed_header<H>(&mut self, header: H)
+    where
+        H: Header;
+}
+
+impl<B> TypedHeadersExt for Request<B> {
+    fn typed_header<H>(&self) -> Option<H>
+    where
+        H: Header,
+    {
+        self.headers().typed_get()
+    }
+
+    fn try_typed_header<H>(&self) -> Result<Option<H>, headers::Error>
+    where
+        H: Header,
+    {
+        self.headers().typed_try_get()
+    }
+
+    fn insert_typed_header<H>(&mut self, header: H)
+    where
+        H: Header,
+    {
+        self.headers_mut().typed_insert(header)
+    }
+}
+
+impl<B> TypedHeadersExt for Response<B> {
+    fn typed_header<H>(&self) -> Option<H>
+    where
+        H: Header,
+    {
+        self.headers().typed_get()
+    }
+
+    fn try_typed_header<H>(&self) -> Result<Option<H>, headers::Error>
+    where
+        H: Header,
+    {
+        self.headers().typed_try_get()
+    }
+
+    fn insert_typed_header<H>(&mut self, header: H)
+    where
+        H: Header,
+    {
+        self.headers_mut().typed_insert(header)
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl<B> Sealed for http::Request<B> {}
+    impl<B> Sealed for http::Response<B> {}
+}
+
+/// Layer that applies [`InsertTypedHeader`] which inserts a typed header into responses.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct InsertTypedHeaderLayer<H> {
+    header: H,
+    overriding: bool,
+}
+
+impl<H> InsertTypedHeaderLayer<H> {
+    /// Create a new [`InsertTypedHeaderLayer`].
+    ///
+    /// If a previous value exists for the same header, it is removed and replaced with the new
+    /// header value.
+    pub fn overriding(header: H) -> Self {
+        Self {
+            header,
+            overriding: true,
+        }
+    }
+
+    /// Create a new [`InsertTypedHeaderLayer`].
+    ///
+    /// If a previous value exists for the header, the new value is not inserted.
+    pub fn if_not_present(header: H) -> Self {
+        Self {
+            header,
+            overriding: false,
+        }
+    }
+}
+
+impl<S, H> Layer<S> for InsertTypedHeaderLayer<H>
+where
+    H: Clone,
+{
+    type Service = InsertTypedHeader<S, H>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InsertTypedHeader {
+            inner,
+            header: self.header.clone(),
+            overriding: self.overriding,
+        }
+    }
+}
+
+/// Middleware that inserts a typed header into responses.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct InsertTypedHeader<S, H> {
+    inner: S,
+    header: H,
+    overriding: bool,
+}
+
+impl<S, H> InsertTypedHeader<S, H> {
+    /// Create a new [`InsertTypedHeader`].
+    ///
+    /// If a previous value exists for the same header, it is removed and replaced with the new
+    /// header value.
+    pub fn overriding(inner: S, header: H) -> Self {
+        Self {
+            inner,
+            header,
+            overriding: true,
+        }
+    }
+
+    /// Create a new [`InsertTypedHeader`].
+    ///
+    /// If a previous value exists for the header, the new value is not inserted.
+    pub fn if_not_present(inner: S, header: H) -> Self {
+        Self {
+            inner,
+            header,
+            overriding: false,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<ReqBody, ResBody, S, H> Service<Request<ReqBody>> for InsertTypedHeader<S, H>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    H: Header + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let mut res = self.inner.call(req).await?;
+        if self.overriding || !res.headers().contains_key(H::name()) {
+            res.insert_typed_header(self.header.clone());
+        }
+        Ok(res)
+    }
+}
+
+/// Type that validates requests have a valid header of type `H`, matching a predicate.
+///
+/// Requests are rejected if the header is missing, can't be decoded, or doesn't
+/// match the predicate. Used with [`ValidateRequestHeader`].
+///
+/// See the [module docs](self) for an example.
+///
+/// [`ValidateRequestHeader`]: crate::validate_request::ValidateRequestHeader
+pub struct RequireTypedHeader<H, F, ResBody> {
+    predicate: F,
+    rejection_status: StatusCode,
+    _ty: PhantomData<fn(&H) -> ResBody>,
+}
+
+impl<H, F, ResBody> RequireTypedHeader<H, F, ResBody> {
+    /// Create a new [`RequireTypedHeader`] accepting headers that match `predicate`.
+    pub fn new(predicate: F) -> Self
+    where
+        F: Fn(&H) -> bool,
+    {
+        Self {
+            predicate,
+            rejection_status: StatusCode::BAD_REQUEST,
+            _ty: PhantomData,
+        }
+    }
+
+    /// Set the status code of responses for rejected requests.
+    ///
+    /// Defaults to `400 Bad Request`.
+    pub fn rejection_status(mut self, status: StatusCode) -> Self {
+        self.rejection_status = status;
+        self
+    }
+}
+
+impl<H, F, ResBody> Clone for RequireTypedHeader<H, F, ResBody>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            predicate: self.predicate.clone(),
+            rejection_status: self.rejection_status,
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<H, F, ResBody> fmt::Debug for RequireTypedHeader<H, F, ResBody> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequireTypedHeader")
+            .field("header", &format_args!("{}", std::any::type_name::<H>()))
+            .field("predicate", &format_args!("{}", std::any::type_name::<F>()))
+            .field("rejection_status", &self.rejection_status)
+            .finish()
+    }
+}
+
+impl<B, H, F, ResBody> ValidateRequest<B> for RequireTypedHeader<H, F, ResBody>
+where
+    H: Header,
+    F: Fn(&H) -> bool,
+    ResBody: Default,
+{
+    type ResponseBody = ResBody;
+
+    fn validate(&self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        match request.try_typed_header::<H>() {
+            Ok(Some(header)) if (self.predicate)(&header) => Ok(()),
+            _ => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = self.rejection_status;
+                Err(res)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{test_helpers::Body, validate_request::ValidateRequestHeaderLayer};
+    use headers::{ContentType, UserAgent};
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn insert_typed_header() {
+        let svc = ServiceBuilder::new()
+            .layer(InsertTypedHeaderLayer::if_not_present(ContentType::text()))
+            .service(service_fn(|req: Request<Body>| async move {
+                let mut res = Response::new(Body::empty());
+                if req.uri().path() == "/json" {
+                    res.insert_typed_header(ContentType::json());
+                }
+                Ok::<_, Infallible>(res)
+            }));
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.typed_header::<ContentType>(), Some(ContentType::text()));
+
+        let req = Request::get("/json").body(Body::empty()).unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.typed_header::<ContentType>(), Some(ContentType::json()));
+    }
+
+    #[tokio::test]
+    async fn require_typed_header() {
+        let svc = ServiceBuilder::new()
+            .layer(ValidateRequestHeaderLayer::typed_header(
+                |agent: &UserAgent| !agent.as_str().contains("bot"),
+            ))
+            .service(service_fn(|_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+
+        let mut req = Request::new(Body::empty());
+        req.insert_typed_header(UserAgent::from_static("curl/8.0"));
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut req = Request::new(Body::empty());
+        req.insert_typed_header(UserAgent::from_static("crawlbot/1.0"));
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
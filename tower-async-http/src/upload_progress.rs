@@ -0,0 +1,466 @@
+//! Middleware reporting the progress of uploading request bodies, and enforcing their length.
+//!
+//! The [`UploadProgress`] client middleware is the counterpart of the
+//! [`DownloadProgress`](crate::download_progress::DownloadProgress) middleware for request
+//! bodies: it wraps them in an [`UploadProgressBody`], which calls a callback with the
+//! [`Progress`] of the upload while the body is sent, at most once per
+//! [interval](UploadProgressLayer::interval) and once the body was sent to the end. If the
+//! callback returns an error, the upload is cancelled: reading the body fails with that error.
+//!
+//! Requests declaring a `Content-Length` must send exactly that many bytes. A body sending
+//! more bytes is cut off, as the server would read them as the start of the next request,
+//! while a body sending fewer bytes leaves the server waiting for the rest, until the
+//! connection times out. Reading the body fails with a [`LengthMismatch`] error as soon as
+//! the body exceeds the declared length, or ends before reaching it, such that the client
+//! fails fast instead.
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::{BodyExt, Full};
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::upload_progress::{Progress, UploadProgressLayer};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! # let http_client = tower_async::service_fn(|req: Request<_>| async move {
+//! #     let body = BodyExt::collect(req.into_body()).await?.to_bytes();
+//! #     Ok::<_, BoxError>(Response::new(Full::new(body)))
+//! # });
+//! let client = ServiceBuilder::new()
+//!     .layer(UploadProgressLayer::new(|progress: &Progress| {
+//!         println!("uploaded {} of {:?} bytes", progress.transferred(), progress.total());
+//!         Ok::<_, BoxError>(())
+//!     }))
+//!     .service(http_client);
+//!
+//! let req = Request::put("https://example.com/upload")
+//!     .header("content-length", "5")
+//!     .body(Full::<Bytes>::from("hello"))?;
+//! client.call(req).await?;
+//!
+//! // the body is shorter than its declared length
+//! let req = Request::put("https://example.com/upload")
+//!     .header("content-length", "10")
+//!     .body(Full::<Bytes>::from("hello"))?;
+//! let err = client.call(req).await.unwrap_err();
+//! assert_eq!(err.to_string(), "request body of 5 bytes doesn't match its content-length of 10 bytes");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{download_progress::Reporter, BoxError};
+use bytes::Buf;
+use futures_util::ready;
+use http::{header, Request};
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+pub use crate::download_progress::Progress;
+
+/// Error returned by [`UploadProgressBody`] when the request body didn't send
+/// as many bytes as declared by its `Content-Length` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthMismatch {
+    declared: u64,
+    actual: u64,
+}
+
+impl LengthMismatch {
+    /// Returns the length declared by the `Content-Length` header.
+    pub fn declared(&self) -> u64 {
+        self.declared
+    }
+
+    /// Returns the number of bytes of the body read until the mismatch was detected,
+    /// which is a lower bound of the length of the body if it exceeded the declared length.
+    pub fn actual(&self) -> u64 {
+        self.actual
+    }
+}
+
+impl fmt::Display for LengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "request body of {} bytes doesn't match its content-length of {} bytes",
+            self.actual, self.declared
+        )
+    }
+}
+
+impl std::error::Error for LengthMismatch {}
+
+pin_project! {
+    /// Request body for [`UploadProgress`].
+    ///
+    /// Reports the [`Progress`] of sending the body to the callback of the middleware,
+    /// and fails with a [`LengthMismatch`] error if the body doesn't match its declared length.
+    pub struct UploadProgressBody<B, F> {
+        #[pin]
+        inner: B,
+        reporter: Reporter<F>,
+        declared: Option<u64>,
+        failed: bool,
+    }
+}
+
+impl<B, F> UploadProgressBody<B, F>
+where
+    B: Body,
+{
+    fn new(inner: B, callback: Arc<F>, interval: Duration, declared: Option<u64>) -> Self {
+        let total = declared.or_else(|| inner.size_hint().exact());
+        Self {
+            inner,
+            reporter: Reporter::new(callback, interval, total),
+            declared,
+            failed: false,
+        }
+    }
+}
+
+impl<B, F> UploadProgressBody<B, F> {
+    /// Returns the [`Progress`] of sending the body so far.
+    pub fn progress(&self) -> Progress {
+        self.reporter.progress()
+    }
+}
+
+impl<B, F> fmt::Debug for UploadProgressBody<B, F>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadProgressBody")
+            .field("inner", &self.inner)
+            .field("progress", &self.progress())
+            .field("declared", &self.declared)
+            .finish()
+    }
+}
+
+impl<B, F, E> Body for UploadProgressBody<B, F>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+    F: Fn(&Progress) -> Result<(), E>,
+    E: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        // the upload was cancelled by the callback, or didn't match its length
+        if *this.failed || this.reporter.is_cancelled() {
+            return Poll::Ready(None);
+        }
+
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => {
+                let actual = this.reporter.progress().transferred();
+                if let Some(declared) = *this.declared {
+                    if actual != declared {
+                        *this.failed = true;
+                        return Poll::Ready(Some(Err(LengthMismatch { declared, actual }.into())));
+                    }
+                }
+                return Poll::Ready(this.reporter.end().err().map(Err));
+            }
+        };
+        if let Some(data) = frame.data_ref() {
+            let len = data.remaining() as u64;
+            let actual = this.reporter.progress().transferred();
+            if let Some(declared) = *this.declared {
+                if actual + len > declared {
+                    // don't send any of the excess bytes
+                    *this.failed = true;
+                    let actual = actual + len;
+                    return Poll::Ready(Some(Err(LengthMismatch { declared, actual }.into())));
+                }
+            }
+            this.reporter.transferred(len)?;
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let progress = self.reporter.progress();
+        self.failed
+            || self.reporter.is_cancelled()
+            || (progress.is_complete() && self.inner.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.declared {
+            Some(declared) => SizeHint::with_exact(declared),
+            None => self.inner.size_hint(),
+        }
+    }
+}
+
+/// Layer that applies the [`UploadProgress`] middleware.
+///
+/// See the [module docs](self) for an example.
+pub struct UploadProgressLayer<F> {
+    callback: Arc<F>,
+    interval: Duration,
+}
+
+impl<F> UploadProgressLayer<F> {
+    /// Create a new [`UploadProgressLayer`], reporting the progress of request bodies
+    /// to `callback`, which is shared by all requests.
+    ///
+    /// The callback cancels the upload by returning an error.
+    pub fn new(callback: F) -> Self {
+        Self {
+            callback: Arc::new(callback),
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the minimum interval between two reports of a request body, defaults to 100 milliseconds.
+    ///
+    /// Once the body was sent to the end, the progress is reported regardless of the interval.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl<F> Clone for UploadProgressLayer<F> {
+    fn clone(&self) -> Self {
+        Self {
+            callback: self.callback.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+impl<F> fmt::Debug for UploadProgressLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadProgressLayer")
+            .field("callback", &format_args!("{}", std::any::type_name::<F>()))
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl<S, F> Layer<S> for UploadProgressLayer<F> {
+    type Service = UploadProgress<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UploadProgress {
+            inner,
+            callback: self.callback.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+/// Client middleware reporting the [`Progress`] of uploading request bodies to a callback,
+/// and failing bodies which don't match their declared `Content-Length`.
+///
+/// See the [module docs](self) for more details.
+pub struct UploadProgress<S, F> {
+    inner: S,
+    callback: Arc<F>,
+    interval: Duration,
+}
+
+impl<S, F> UploadProgress<S, F> {
+    /// Create a new [`UploadProgress`], reporting the progress of request bodies to `callback`.
+    ///
+    /// The callback cancels the upload by returning an error.
+    pub fn new(inner: S, callback: F) -> Self {
+        Self {
+            inner,
+            callback: Arc::new(callback),
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the minimum interval between two reports of a request body, defaults to 100 milliseconds.
+    ///
+    /// See [`UploadProgressLayer::interval`] for more details.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `UploadProgress` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(callback: F) -> UploadProgressLayer<F> {
+        UploadProgressLayer::new(callback)
+    }
+}
+
+impl<S, F> Clone for UploadProgress<S, F>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            callback: self.callback.clone(),
+            interval: self.interval,
+        }
+    }
+}
+
+impl<S, F> fmt::Debug for UploadProgress<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadProgress")
+            .field("inner", &self.inner)
+            .field("callback", &format_args!("{}", std::any::type_name::<F>()))
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl<S, F, ReqBody> Service<Request<ReqBody>> for UploadProgress<S, F>
+where
+    S: Service<Request<UploadProgressBody<ReqBody, F>>>,
+    ReqBody: Body,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let declared = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        let req = req.map(|body| {
+            UploadProgressBody::new(body, self.callback.clone(), self.interval, declared)
+        });
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use bytes::Bytes;
+    use http_body_util::BodyExt;
+    use std::sync::Mutex;
+
+    fn request(chunks: Vec<&'static str>, length: Option<u64>) -> Request<Body> {
+        let mut req = Request::new(Body::from_stream(futures_util::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, BoxError>(Bytes::from_static(chunk.as_bytes()))),
+        )));
+        if let Some(length) = length {
+            req.headers_mut()
+                .insert(header::CONTENT_LENGTH, length.to_string().parse().unwrap());
+        }
+        req
+    }
+
+    fn body(
+        req: Request<Body>,
+        callback: impl Fn(&Progress) -> Result<(), BoxError>,
+    ) -> UploadProgressBody<Body, impl Fn(&Progress) -> Result<(), BoxError>> {
+        let declared = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .map(|value| value.to_str().unwrap().parse().unwrap());
+        UploadProgressBody::new(
+            req.into_body(),
+            Arc::new(callback),
+            Duration::ZERO,
+            declared,
+        )
+    }
+
+    #[tokio::test]
+    async fn reports_progress() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let body = body(request(vec!["hello", " world"], Some(11)), {
+            let reports = reports.clone();
+            move |progress| {
+                reports.lock().unwrap().push(*progress);
+                Ok(())
+            }
+        });
+
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "hello world");
+        let reports: Vec<_> = reports
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|progress| {
+                (
+                    progress.transferred(),
+                    progress.total(),
+                    progress.is_complete(),
+                )
+            })
+            .collect();
+        assert_eq!(reports, [(5, Some(11), false), (11, Some(11), true)]);
+    }
+
+    #[tokio::test]
+    async fn longer_body_fails_before_sending_excess() {
+        let mut body = body(request(vec!["hello", " world"], Some(8)), |_| Ok(()));
+
+        assert_eq!(
+            body.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "hello"
+        );
+        let err = body.frame().await.unwrap().unwrap_err();
+        let err = err.downcast_ref::<LengthMismatch>().unwrap();
+        assert_eq!((err.declared(), err.actual()), (8, 11));
+        assert!(http_body::Body::is_end_stream(&body));
+    }
+
+    #[tokio::test]
+    async fn shorter_body_fails_at_end() {
+        let body = body(request(vec!["hello"], Some(8)), |_| Ok(()));
+
+        let err = body.collect().await.unwrap_err();
+        let err = err.downcast_ref::<LengthMismatch>().unwrap();
+        assert_eq!((err.declared(), err.actual()), (8, 5));
+    }
+
+    #[tokio::test]
+    async fn callback_error_cancels_upload() {
+        let body = body(request(vec!["a", "b", "c"], None), |progress| {
+            if progress.transferred() >= 2 {
+                Err("cancelled by user".into())
+            } else {
+                Ok(())
+            }
+        });
+
+        let err = body.collect().await.unwrap_err();
+        assert_eq!(err.to_string(), "cancelled by user");
+    }
+}
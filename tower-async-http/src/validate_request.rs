@@ -157,6 +157,24 @@ impl<ResBody> ValidateRequestHeaderLayer<AcceptHeader<ResBody>> {
     }
 }
 
+#[cfg(feature = "typed-header")]
+impl<H, F, ResBody>
+    ValidateRequestHeaderLayer<crate::typed_header::RequireTypedHeader<H, F, ResBody>>
+{
+    /// Validate requests have a valid header of type `H` matching `predicate`.
+    ///
+    /// Requests are rejected with a `400 Bad Request` response otherwise.
+    /// See [`RequireTypedHeader`] for more details.
+    ///
+    /// [`RequireTypedHeader`]: crate::typed_header::RequireTypedHeader
+    pub fn typed_header(predicate: F) -> Self
+    where
+        F: Fn(&H) -> bool,
+    {
+        Self::custom(crate::typed_header::RequireTypedHeader::new(predicate))
+    }
+}
+
 impl<T> ValidateRequestHeaderLayer<T> {
     /// Validate requests using a custom method.
     pub fn custom(validate: T) -> ValidateRequestHeaderLayer<T> {
@@ -209,6 +227,27 @@ impl<S, ResBody> ValidateRequestHeader<S, AcceptHeader<ResBody>> {
     }
 }
 
+#[cfg(feature = "typed-header")]
+impl<S, H, F, ResBody>
+    ValidateRequestHeader<S, crate::typed_header::RequireTypedHeader<H, F, ResBody>>
+{
+    /// Validate requests have a valid header of type `H` matching `predicate`.
+    ///
+    /// Requests are rejected with a `400 Bad Request` response otherwise.
+    /// See [`RequireTypedHeader`] for more details.
+    ///
+    /// [`RequireTypedHeader`]: crate::typed_header::RequireTypedHeader
+    pub fn typed_header(inner: S, predicate: F) -> Self
+    where
+        F: Fn(&H) -> bool,
+    {
+        Self::custom(
+            inner,
+            crate::typed_header::RequireTypedHeader::new(predicate),
+        )
+    }
+}
+
 impl<S, T> ValidateRequestHeader<S, T> {
     /// Validate requests using a custom method.
     pub fn custom(inner: S, validate: T) -> ValidateRequestHeader<S, T> {
@@ -0,0 +1,632 @@
+//! Middleware that verifies the signatures of inbound webhooks.
+//!
+//! Webhook providers sign the payload of each request they send, such that the receiver can
+//! verify that the request originates from the provider and has not been tampered with. As the
+//! signature covers the raw body, the [`VerifySignature`] middleware buffers the body, up to a
+//! [maximum size], and verifies the signature with a [`Verifier`] before the handler runs.
+//!
+//! Requests with a missing or invalid signature are rejected with `401 Unauthorized`,
+//! requests with a body exceeding the maximum size with `413 Payload Too Large`.
+//! Verified requests are passed on with a [`Full`] body, and the payload is inserted
+//! into the request extensions as [`VerifiedPayload`].
+//!
+//! Ready-made verifiers are provided for:
+//!
+//! - HMAC-SHA256 signatures of the body in a header, such as used by [GitHub]: [`HmacSha256`];
+//! - [Stripe] signatures: [`Stripe`];
+//! - [Slack] signatures: [`Slack`];
+//! - Ed25519 signatures, such as used by [Discord]: `Ed25519`, which requires the
+//!   `verify-signature-ed25519` feature.
+//!
+//! Signatures are compared in constant time. Verifiers of schemes that sign a timestamp
+//! reject requests with a timestamp outside of a [tolerance], to prevent replay attacks.
+//!
+//! [maximum size]: VerifySignatureLayer::max_body_size
+//! [`Full`]: http_body_util::Full
+//! [GitHub]: https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries
+//! [Stripe]: https://docs.stripe.com/webhooks#verify-manually
+//! [Slack]: https://api.slack.com/authentication/verifying-requests-from-slack
+//! [Discord]: https://discord.com/developers/docs/interactions/overview#setting-up-an-endpoint-validating-security-request-headers
+//! [tolerance]: Stripe::tolerance
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response, StatusCode};
+//! use http_body_util::Full;
+//! use tower_async::{BoxError, Service, ServiceBuilder};
+//! use tower_async_http::verify_signature::{HmacSha256, VerifiedPayload, VerifySignatureLayer};
+//!
+//! async fn handle(req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, BoxError> {
+//!     let payload = req.extensions().get::<VerifiedPayload>().unwrap();
+//!     // parse the payload ...
+//!     # let _ = payload;
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = ServiceBuilder::new()
+//!     .layer(VerifySignatureLayer::new(HmacSha256::github("webhook secret")))
+//!     .service_fn(handle);
+//!
+//! let req = Request::post("/webhooks/github")
+//!     .header("x-hub-signature-256", "sha256=0000")
+//!     .body(Full::<Bytes>::from("{}"))?;
+//! let res = svc.call(req).await?;
+//! assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use http::{request::Parts, HeaderMap, HeaderName, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use sha2::Sha256;
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// The default maximum size of a payload, 1 MiB.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The default tolerance of signed timestamps, 5 minutes.
+const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// Verifies the signature of a request for [`VerifySignature`].
+pub trait Verifier {
+    /// Returns `true` if the request carries a valid signature of `body`.
+    fn verify(&self, parts: &Parts, body: &Bytes) -> bool;
+}
+
+/// The raw payload of a request whose signature was verified.
+///
+/// Inserted into the request extensions by [`VerifySignature`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedPayload(pub Bytes);
+
+/// [`Verifier`] for HMAC-SHA256 signatures of the body,
+/// hex encoded in a header with an optional prefix.
+#[derive(Clone)]
+pub struct HmacSha256 {
+    mac: Hmac<Sha256>,
+    header: HeaderName,
+    prefix: &'static str,
+}
+
+impl HmacSha256 {
+    /// Create a new [`HmacSha256`] verifying signatures in the given header.
+    pub fn new(secret: impl AsRef<[u8]>, header: HeaderName) -> Self {
+        Self {
+            mac: new_mac(secret.as_ref()),
+            header,
+            prefix: "",
+        }
+    }
+
+    /// Create a new [`HmacSha256`] verifying the signatures of [GitHub webhooks],
+    /// found in the `X-Hub-Signature-256` header.
+    ///
+    /// [GitHub webhooks]: https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries
+    pub fn github(secret: impl AsRef<[u8]>) -> Self {
+        Self::new(secret, HeaderName::from_static("x-hub-signature-256")).prefix("sha256=")
+    }
+
+    /// Set the prefix of the signature in the header, such as `sha256=`.
+    pub fn prefix(mut self, prefix: &'static str) -> Self {
+        self.prefix = prefix;
+        self
+    }
+}
+
+impl fmt::Debug for HmacSha256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the secret is deliberately not printed
+        f.debug_struct("HmacSha256")
+            .field("header", &self.header)
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+impl Verifier for HmacSha256 {
+    fn verify(&self, parts: &Parts, body: &Bytes) -> bool {
+        let signature = match header_str(&parts.headers, &self.header)
+            .and_then(|value| value.strip_prefix(self.prefix))
+            .and_then(decode_hex)
+        {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let mut mac = self.mac.clone();
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+/// [`Verifier`] for the signatures of [Stripe webhooks].
+///
+/// The `Stripe-Signature` header contains a timestamp and one or more signatures of
+/// `{timestamp}.{body}`, any of which has to be valid.
+///
+/// [Stripe webhooks]: https://docs.stripe.com/webhooks#verify-manually
+#[derive(Clone)]
+pub struct Stripe {
+    mac: Hmac<Sha256>,
+    tolerance: Duration,
+}
+
+impl Stripe {
+    /// Create a new [`Stripe`] verifier using the endpoint's signing secret.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            mac: new_mac(secret.as_ref()),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Set the maximum difference between the signed timestamp and the current time.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl fmt::Debug for Stripe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stripe")
+            .field("tolerance", &self.tolerance)
+            .finish()
+    }
+}
+
+impl Verifier for Stripe {
+    fn verify(&self, parts: &Parts, body: &Bytes) -> bool {
+        let header = match header_str(&parts.headers, "stripe-signature") {
+            Some(header) => header,
+            None => return false,
+        };
+        let fields = || header.split(',').filter_map(|field| field.split_once('='));
+        let timestamp = match fields().find(|(key, _)| *key == "t") {
+            Some((_, timestamp)) if is_recent(timestamp, self.tolerance) => timestamp,
+            _ => return false,
+        };
+
+        let mut mac = self.mac.clone();
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        fields()
+            .filter(|(key, _)| *key == "v1")
+            .filter_map(|(_, signature)| decode_hex(signature))
+            .any(|signature| mac.clone().verify_slice(&signature).is_ok())
+    }
+}
+
+/// [`Verifier`] for the signatures of [Slack requests].
+///
+/// The `X-Slack-Signature` header contains a signature of `v0:{timestamp}:{body}`,
+/// where the timestamp is taken from the `X-Slack-Request-Timestamp` header.
+///
+/// [Slack requests]: https://api.slack.com/authentication/verifying-requests-from-slack
+#[derive(Clone)]
+pub struct Slack {
+    mac: Hmac<Sha256>,
+    tolerance: Duration,
+}
+
+impl Slack {
+    /// Create a new [`Slack`] verifier using the app's signing secret.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            mac: new_mac(secret.as_ref()),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Set the maximum difference between the signed timestamp and the current time.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl fmt::Debug for Slack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Slack")
+            .field("tolerance", &self.tolerance)
+            .finish()
+    }
+}
+
+impl Verifier for Slack {
+    fn verify(&self, parts: &Parts, body: &Bytes) -> bool {
+        let timestamp = match header_str(&parts.headers, "x-slack-request-timestamp") {
+            Some(timestamp) if is_recent(timestamp, self.tolerance) => timestamp,
+            _ => return false,
+        };
+        let signature = match header_str(&parts.headers, "x-slack-signature")
+            .and_then(|value| value.strip_prefix("v0="))
+            .and_then(decode_hex)
+        {
+            Some(signature) => signature,
+            None => return false,
+        };
+
+        let mut mac = self.mac.clone();
+        mac.update(b"v0:");
+        mac.update(timestamp.as_bytes());
+        mac.update(b":");
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+#[cfg(feature = "verify-signature-ed25519")]
+pub use self::ed25519::Ed25519;
+
+#[cfg(feature = "verify-signature-ed25519")]
+mod ed25519 {
+    use super::{decode_hex, header_str, Verifier};
+    use bytes::Bytes;
+    use ed25519_dalek::{Signature, VerifyingKey};
+    use http::{request::Parts, HeaderName};
+
+    /// [`Verifier`] for hex encoded Ed25519 signatures of the body,
+    /// optionally prefixed with a timestamp taken from another header.
+    #[derive(Debug, Clone)]
+    pub struct Ed25519 {
+        key: VerifyingKey,
+        header: HeaderName,
+        timestamp_header: Option<HeaderName>,
+    }
+
+    impl Ed25519 {
+        /// Create a new [`Ed25519`] verifier using the given public key,
+        /// verifying signatures in the given header.
+        pub fn new(public_key: VerifyingKey, header: HeaderName) -> Self {
+            Self {
+                key: public_key,
+                header,
+                timestamp_header: None,
+            }
+        }
+
+        /// Create a new [`Ed25519`] verifier for [Discord interactions],
+        /// verifying the `X-Signature-Ed25519` header over the `X-Signature-Timestamp`
+        /// header followed by the body.
+        ///
+        /// [Discord interactions]: https://discord.com/developers/docs/interactions/overview#setting-up-an-endpoint-validating-security-request-headers
+        pub fn discord(public_key: VerifyingKey) -> Self {
+            Self::new(public_key, HeaderName::from_static("x-signature-ed25519"))
+                .timestamp_header(HeaderName::from_static("x-signature-timestamp"))
+        }
+
+        /// Prefix the signed message with the value of the given header.
+        pub fn timestamp_header(mut self, header: HeaderName) -> Self {
+            self.timestamp_header = Some(header);
+            self
+        }
+    }
+
+    impl Verifier for Ed25519 {
+        fn verify(&self, parts: &Parts, body: &Bytes) -> bool {
+            let signature = match header_str(&parts.headers, &self.header)
+                .and_then(decode_hex)
+                .and_then(|signature| <[u8; 64]>::try_from(signature).ok())
+            {
+                Some(signature) => Signature::from_bytes(&signature),
+                None => return false,
+            };
+
+            let mut message = Vec::new();
+            if let Some(header) = &self.timestamp_header {
+                match header_str(&parts.headers, header) {
+                    Some(timestamp) => message.extend_from_slice(timestamp.as_bytes()),
+                    None => return false,
+                }
+            }
+            message.extend_from_slice(body);
+            self.key.verify_strict(&message, &signature).is_ok()
+        }
+    }
+}
+
+fn new_mac(secret: &[u8]) -> Hmac<Sha256> {
+    Hmac::new_from_slice(secret).expect("HMAC accepts keys of any size")
+}
+
+fn header_str<K>(headers: &HeaderMap, name: K) -> Option<&str>
+where
+    K: http::header::AsHeaderName,
+{
+    headers.get(name)?.to_str().ok()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    // odd lengths are rejected, as the last pair is out of bounds
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Returns `true` if the unix `timestamp` is within `tolerance` of the current time.
+fn is_recent(timestamp: &str, tolerance: Duration) -> bool {
+    let timestamp = match timestamp.parse::<u64>() {
+        Ok(timestamp) => timestamp,
+        Err(_) => return false,
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    now.abs_diff(timestamp) <= tolerance.as_secs()
+}
+
+/// Layer that applies the [`VerifySignature`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct VerifySignatureLayer<V> {
+    verifier: V,
+    max_body_size: usize,
+}
+
+impl<V> VerifySignatureLayer<V> {
+    /// Create a new [`VerifySignatureLayer`] verifying signatures with the given [`Verifier`].
+    pub fn new(verifier: V) -> Self {
+        Self {
+            verifier,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the maximum size of payloads that are buffered to verify their signature.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+}
+
+impl<S, V> Layer<S> for VerifySignatureLayer<V>
+where
+    V: Clone,
+{
+    type Service = VerifySignature<S, V>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifySignature {
+            inner,
+            verifier: self.verifier.clone(),
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+/// Middleware that verifies the signatures of inbound webhooks.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct VerifySignature<S, V> {
+    inner: S,
+    verifier: V,
+    max_body_size: usize,
+}
+
+impl<S, V> VerifySignature<S, V> {
+    /// Create a new [`VerifySignature`] verifying signatures with the given [`Verifier`].
+    pub fn new(inner: S, verifier: V) -> Self {
+        Self {
+            inner,
+            verifier,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `VerifySignature` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(verifier: V) -> VerifySignatureLayer<V> {
+        VerifySignatureLayer::new(verifier)
+    }
+
+    /// Set the maximum size of payloads that are buffered to verify their signature.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+}
+
+impl<S, V, ReqBody, ResBody> Service<Request<ReqBody>> for VerifySignature<S, V>
+where
+    S: Service<Request<Full<Bytes>>, Response = Response<ResBody>>,
+    V: Verifier,
+    ReqBody: Body,
+    ReqBody::Error: Into<crate::BoxError>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+        let body = match Limited::new(body, self.max_body_size).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(err) if err.is::<LengthLimitError>() => {
+                return Ok(reject(StatusCode::PAYLOAD_TOO_LARGE))
+            }
+            Err(_) => return Ok(reject(StatusCode::BAD_REQUEST)),
+        };
+
+        if !self.verifier.verify(&parts, &body) {
+            return Ok(reject(StatusCode::UNAUTHORIZED));
+        }
+
+        parts.extensions.insert(VerifiedPayload(body.clone()));
+        self.inner
+            .call(Request::from_parts(parts, Full::new(body)))
+            .await
+    }
+}
+
+fn reject<B>(status: StatusCode) -> Response<B>
+where
+    B: Default,
+{
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tower_async::{service_fn, ServiceBuilder};
+
+    fn sign(secret: &str, message: &[u8]) -> String {
+        let mut mac = new_mac(secret.as_bytes());
+        mac.update(message);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn parts(headers: &[(&str, String)]) -> Parts {
+        let mut req = Request::post("/webhook");
+        for (name, value) in headers {
+            req = req.header(*name, value);
+        }
+        req.body(()).unwrap().into_parts().0
+    }
+
+    #[tokio::test]
+    async fn verified_payload_is_passed_to_handler() {
+        let svc = ServiceBuilder::new()
+            .layer(VerifySignatureLayer::new(HmacSha256::github("secret")).max_body_size(16))
+            .service(service_fn(|req: Request<Full<Bytes>>| async move {
+                let payload = req.extensions().get::<VerifiedPayload>().unwrap().clone();
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                assert_eq!(payload.0, body);
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+
+        let signature = format!("sha256={}", sign("secret", b"{\"ok\":true}"));
+        let req = Request::post("/")
+            .header("x-hub-signature-256", &signature)
+            .body(Body::from("{\"ok\":true}"))
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::post("/")
+            .header("x-hub-signature-256", &signature)
+            .body(Body::from("{\"ok\":false}"))
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = Request::post("/")
+            .header("x-hub-signature-256", &signature)
+            .body(Body::from("{\"ok\":true,\"padding\":true}"))
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn stripe() {
+        let verifier = Stripe::new("whsec");
+        let body = Bytes::from_static(b"{}");
+        let t = now();
+        let valid = sign("whsec", format!("{}.{{}}", t).as_bytes());
+
+        let header = format!("t={},v1={},v1={}", t, "00".repeat(32), valid);
+        assert!(verifier.verify(&parts(&[("stripe-signature", header)]), &body));
+
+        let header = format!("t={},v1={}", t - 600, valid);
+        assert!(!verifier.verify(&parts(&[("stripe-signature", header)]), &body));
+
+        let header = format!("v1={}", valid);
+        assert!(!verifier.verify(&parts(&[("stripe-signature", header)]), &body));
+    }
+
+    #[test]
+    fn slack() {
+        let verifier = Slack::new("signing");
+        let body = Bytes::from_static(b"token=x");
+        let t = now();
+        let signature = format!(
+            "v0={}",
+            sign("signing", format!("v0:{}:token=x", t).as_bytes())
+        );
+
+        let valid = parts(&[
+            ("x-slack-request-timestamp", t.to_string()),
+            ("x-slack-signature", signature.clone()),
+        ]);
+        assert!(verifier.verify(&valid, &body));
+
+        let replayed = parts(&[
+            ("x-slack-request-timestamp", (t + 1).to_string()),
+            ("x-slack-signature", signature),
+        ]);
+        assert!(!verifier.verify(&replayed, &body));
+    }
+
+    #[cfg(feature = "verify-signature-ed25519")]
+    #[test]
+    fn ed25519_discord() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let verifier = Ed25519::discord(key.verifying_key());
+        let body = Bytes::from_static(b"{\"type\":1}");
+        let signature: String = key
+            .sign(b"1700000000{\"type\":1}")
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let valid = parts(&[
+            ("x-signature-timestamp", "1700000000".to_owned()),
+            ("x-signature-ed25519", signature.clone()),
+        ]);
+        assert!(verifier.verify(&valid, &body));
+
+        let tampered = parts(&[
+            ("x-signature-timestamp", "1700000001".to_owned()),
+            ("x-signature-ed25519", signature),
+        ]);
+        assert!(!verifier.verify(&tampered, &body));
+    }
+}
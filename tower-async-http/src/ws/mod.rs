@@ -0,0 +1,651 @@
+//! Middleware that accepts WebSocket connections.
+//!
+//! The [`WebSocketUpgrade`] middleware performs the [WebSocket handshake] for requests that
+//! ask to upgrade the connection to a WebSocket, and passes all other requests on to the
+//! inner service. Once the connection has been upgraded, it is handed to a handler as a
+//! [`WebSocket`], on a task spawned on the current Tokio runtime.
+//!
+//! Handlers can receive and send [`WsMessage`]s themselves, if need be concurrently after
+//! [splitting](WebSocket::split) the connection, or hand the connection to a
+//! `Service<WsMessage>` with [`WebSocket::serve`], such that middleware like timeouts and
+//! concurrency limits can be applied to the handling of messages as well.
+//!
+//! Upgrades are performed through the [`OnUpgrade`] request extension. Servers that are
+//! able to upgrade connections insert an [`OnUpgrade`], created with [`OnUpgrade::new`], into
+//! every request, such as the `HyperUpgrade` middleware of `tower-async-hyper` does for
+//! connections served by `hyper`. Handshakes are rejected with:
+//!
+//! - `400 Bad Request` if the request isn't a valid handshake;
+//! - `426 Upgrade Required` if the client uses a version of the protocol other than 13;
+//! - `500 Internal Server Error` if the request has no [`OnUpgrade`] extension.
+//!
+//! [WebSocket handshake]: https://www.rfc-editor.org/rfc/rfc6455#section-4
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use http_body_util::Full;
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{service_fn, BoxError, ServiceBuilder};
+//! use tower_async_http::ws::{WebSocket, WebSocketUpgradeLayer, WsMessage};
+//!
+//! async fn echo(message: WsMessage) -> Result<Option<WsMessage>, BoxError> {
+//!     Ok(Some(message))
+//! }
+//!
+//! async fn handle(_: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::default()))
+//! }
+//!
+//! let ws = WebSocketUpgradeLayer::new(|ws: WebSocket| {
+//!     // Every message has to be handled within 5 seconds.
+//!     let service = ServiceBuilder::new()
+//!         .timeout(Duration::from_secs(5))
+//!         .service_fn(echo);
+//!     ws.serve(service)
+//! })
+//! .protocols(["chat"])
+//! .max_message_size(64 * 1024)
+//! .idle_timeout(Duration::from_secs(60));
+//!
+//! let svc = ServiceBuilder::new().layer(ws).service_fn(handle);
+//! ```
+
+use base64::Engine as _;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use sha1::{Digest, Sha1};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+mod socket;
+
+pub use self::socket::{CloseFrame, WebSocket, WsError, WsMessage, WsReceiver, WsSender};
+
+use self::socket::Io;
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// The GUID appended to the key of the client to compute the accept key.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The default maximum size of a message, 1 MiB.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+type Upgrade = Pin<Box<dyn Future<Output = io::Result<Box<dyn Io>>> + Send>>;
+
+/// Request extension to upgrade the connection of a request.
+///
+/// See the [module docs](self) for more details.
+///
+/// # Example
+///
+/// Inserting an [`OnUpgrade`] into requests served by [`hyper`], which the `HyperUpgrade`
+/// middleware of `tower-async-hyper` does as well:
+///
+/// ```
+/// use http::Request;
+/// use hyper_util::rt::TokioIo;
+/// use std::io;
+/// use tower_async_http::ws::OnUpgrade;
+///
+/// fn insert_on_upgrade<B>(req: &mut Request<B>) {
+///     let on_upgrade = hyper::upgrade::on(&mut *req);
+///     req.extensions_mut().insert(OnUpgrade::new(async move {
+///         on_upgrade.await.map(TokioIo::new).map_err(io::Error::other)
+///     }));
+/// }
+/// ```
+///
+/// [`hyper`]: https://docs.rs/hyper
+#[derive(Clone)]
+pub struct OnUpgrade {
+    upgrade: Arc<Mutex<Option<Upgrade>>>,
+}
+
+impl OnUpgrade {
+    /// Create a new [`OnUpgrade`] from a future resolving to the upgraded connection,
+    /// once the `101 Switching Protocols` response has been sent.
+    ///
+    /// This is meant to be used by servers, which insert it in the extensions of the
+    /// request before calling the service.
+    pub fn new<F, I>(upgrade: F) -> Self
+    where
+        F: Future<Output = io::Result<I>> + Send + 'static,
+        I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let upgrade: Upgrade = Box::pin(async move {
+            let io = upgrade.await?;
+            Ok(Box::new(io) as Box<dyn Io>)
+        });
+        Self {
+            upgrade: Arc::new(Mutex::new(Some(upgrade))),
+        }
+    }
+
+    fn take(&self) -> Option<Upgrade> {
+        self.upgrade.lock().unwrap().take()
+    }
+}
+
+impl fmt::Debug for OnUpgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnUpgrade").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    protocols: Vec<String>,
+    max_message_size: usize,
+    idle_timeout: Option<Duration>,
+}
+
+/// Layer that applies the [`WebSocketUpgrade`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct WebSocketUpgradeLayer<H> {
+    handler: H,
+    config: Arc<Config>,
+}
+
+impl<H> WebSocketUpgradeLayer<H> {
+    /// Create a new [`WebSocketUpgradeLayer`], handing upgraded connections to `handler`.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            config: Arc::new(Config {
+                protocols: Vec::new(),
+                max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+                idle_timeout: None,
+            }),
+        }
+    }
+
+    /// Set the subprotocols supported by the handler.
+    ///
+    /// The first subprotocol requested by the client which is supported is selected,
+    /// and available through [`WebSocket::protocol`].
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the maximum size of a message, including all of its fragments.
+    ///
+    /// Connections receiving larger messages are closed with status code `1009`.
+    /// Defaults to 1 MiB.
+    pub fn max_message_size(mut self, limit: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_message_size = limit;
+        self
+    }
+
+    /// Close connections with status code `1001` when no message was received within
+    /// the given duration.
+    ///
+    /// Pings and pongs count as messages, such that clients can keep connections alive.
+    /// By default connections can be idle indefinitely.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.config).idle_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<H> fmt::Debug for WebSocketUpgradeLayer<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketUpgradeLayer")
+            .field("handler", &format_args!("{}", std::any::type_name::<H>()))
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S, H> Layer<S> for WebSocketUpgradeLayer<H>
+where
+    H: Clone,
+{
+    type Service = WebSocketUpgrade<S, H>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WebSocketUpgrade {
+            inner,
+            handler: self.handler.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that accepts WebSocket connections.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct WebSocketUpgrade<S, H> {
+    inner: S,
+    handler: H,
+    config: Arc<Config>,
+}
+
+impl<S, H> WebSocketUpgrade<S, H> {
+    /// Create a new [`WebSocketUpgrade`], handing upgraded connections to `handler`.
+    pub fn new(inner: S, handler: H) -> Self {
+        let WebSocketUpgradeLayer { handler, config } = WebSocketUpgradeLayer::new(handler);
+        Self {
+            inner,
+            handler,
+            config,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `WebSocketUpgrade` middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(handler: H) -> WebSocketUpgradeLayer<H> {
+        WebSocketUpgradeLayer::new(handler)
+    }
+}
+
+impl<S, H> fmt::Debug for WebSocketUpgrade<S, H>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketUpgrade")
+            .field("inner", &self.inner)
+            .field("handler", &format_args!("{}", std::any::type_name::<H>()))
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S, H, Fut, ReqBody, ResBody> Service<Request<ReqBody>> for WebSocketUpgrade<S, H>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    H: Fn(WebSocket) -> Fut + Clone + Send + 'static,
+    Fut: Future + Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, req: Request<ReqBody>) -> Result<Self::Response, Self::Error> {
+        if !has_token(req.headers(), header::UPGRADE, "websocket") {
+            return self.inner.call(req).await;
+        }
+
+        let headers = req.headers();
+        if req.method() != Method::GET || !has_token(headers, header::CONNECTION, "upgrade") {
+            return Ok(reject(StatusCode::BAD_REQUEST));
+        }
+        if headers.get(header::SEC_WEBSOCKET_VERSION) != Some(&HeaderValue::from_static("13")) {
+            let mut res = reject(StatusCode::UPGRADE_REQUIRED);
+            res.headers_mut().insert(
+                header::SEC_WEBSOCKET_VERSION,
+                HeaderValue::from_static("13"),
+            );
+            return Ok(res);
+        }
+        let Some(key) = headers.get(header::SEC_WEBSOCKET_KEY).filter(|key| {
+            BASE64
+                .decode(key.as_bytes())
+                .is_ok_and(|key| key.len() == 16)
+        }) else {
+            return Ok(reject(StatusCode::BAD_REQUEST));
+        };
+        let accept = accept_key(key.as_bytes());
+        let protocol = select_protocol(headers, &self.config.protocols);
+
+        let Some(upgrade) = req
+            .extensions()
+            .get::<OnUpgrade>()
+            .and_then(OnUpgrade::take)
+        else {
+            return Ok(reject(StatusCode::INTERNAL_SERVER_ERROR));
+        };
+
+        let (parts, _) = req.into_parts();
+        let handler = self.handler.clone();
+        let config = self.config.clone();
+        let selected = protocol.clone();
+        tokio::spawn(async move {
+            if let Ok(io) = upgrade.await {
+                let ws = WebSocket::new(io, parts.uri, parts.headers, selected, &config);
+                handler(ws).await;
+            }
+        });
+
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        let headers = res.headers_mut();
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(header::SEC_WEBSOCKET_ACCEPT, accept);
+        if let Some(protocol) = protocol.and_then(|p| HeaderValue::try_from(p).ok()) {
+            headers.insert(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        Ok(res)
+    }
+}
+
+fn reject<B>(status: StatusCode) -> Response<B>
+where
+    B: Default,
+{
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    res
+}
+
+/// Returns `true` if the comma separated values of the header contain `token`.
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+fn select_protocol(headers: &HeaderMap, supported: &[String]) -> Option<String> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .find(|protocol| supported.iter().any(|supported| supported == protocol))
+        .map(ToOwned::to_owned)
+}
+
+fn accept_key(key: &[u8]) -> HeaderValue {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(GUID.as_bytes());
+    HeaderValue::try_from(BASE64.encode(sha1.finalize())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_helpers::Body;
+    use std::convert::Infallible;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+        sync::oneshot,
+    };
+    use tower_async::service_fn;
+
+    fn handshake(server: DuplexStream) -> http::request::Builder {
+        Request::get("/chat")
+            .header(header::UPGRADE, "websocket")
+            .header(header::CONNECTION, "keep-alive, Upgrade")
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .extension(OnUpgrade::new(async move { Ok(server) }))
+    }
+
+    async fn ok(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    async fn write_frame(io: &mut DuplexStream, fin: bool, opcode: u8, payload: &[u8]) {
+        io.write_all(&frame(fin, opcode, payload)).await.unwrap();
+    }
+
+    fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![if fin { 0x80 | opcode } else { opcode }, 0x80];
+        match payload.len() {
+            len if len < 126 => frame[1] |= len as u8,
+            len => {
+                frame[1] |= 126;
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    async fn read_frame(io: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let mut head = [0; 2];
+        io.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0] & 0x80, 0x80);
+        assert_eq!(head[1] & 0x80, 0);
+        let len = match head[1] {
+            126 => io.read_u16().await.unwrap() as usize,
+            len => len as usize,
+        };
+        let mut payload = vec![0; len];
+        io.read_exact(&mut payload).await.unwrap();
+        (head[0] & 0x0f, payload)
+    }
+
+    #[tokio::test]
+    async fn serves_upgraded_connections() {
+        let svc = WebSocketUpgradeLayer::new(|ws: WebSocket| {
+            assert_eq!(ws.uri().path(), "/chat");
+            assert_eq!(ws.protocol(), Some("chat"));
+            ws.serve(service_fn(|message: WsMessage| async move {
+                Ok::<_, Infallible>(match message {
+                    WsMessage::Text(text) => Some(WsMessage::Text(text.to_uppercase())),
+                    _ => None,
+                })
+            }))
+        })
+        .protocols(["v2.chat", "chat"])
+        .layer(service_fn(ok));
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        let req = handshake(server)
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "superchat, chat")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            res.headers()[header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_PROTOCOL], "chat");
+
+        // a fragmented message, interleaved with a ping
+        write_frame(&mut client, false, 0x1, b"hel").await;
+        write_frame(&mut client, true, 0x9, b"ping").await;
+        write_frame(&mut client, true, 0x0, b"lo").await;
+        assert_eq!(read_frame(&mut client).await, (0xa, b"ping".to_vec()));
+        assert_eq!(read_frame(&mut client).await, (0x1, b"HELLO".to_vec()));
+
+        let long = "a".repeat(300);
+        write_frame(&mut client, true, 0x1, long.as_bytes()).await;
+        assert_eq!(
+            read_frame(&mut client).await,
+            (0x1, long.to_uppercase().into_bytes())
+        );
+
+        write_frame(&mut client, true, 0x8, &[0x03, 0xe8, b'b', b'y', b'e']).await;
+        assert_eq!(
+            read_frame(&mut client).await,
+            (0x8, b"\x03\xe8bye".to_vec())
+        );
+        assert_eq!(
+            client.read_u8().await.unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_handshakes() {
+        let svc = WebSocketUpgradeLayer::new(|_: WebSocket| async {}).layer(service_fn(ok));
+
+        let res = svc.call(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let (_client, server) = tokio::io::duplex(64);
+        let mut req = handshake(server).body(Body::empty()).unwrap();
+        req.headers_mut()
+            .insert(header::SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_VERSION], "13");
+
+        let (_client, server) = tokio::io::duplex(64);
+        let mut req = handshake(server).body(Body::empty()).unwrap();
+        req.headers_mut().insert(
+            header::SEC_WEBSOCKET_KEY,
+            HeaderValue::from_static("not a key"),
+        );
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let (_client, server) = tokio::io::duplex(64);
+        let mut req = handshake(server).body(Body::empty()).unwrap();
+        req.extensions_mut().clear();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn closes_connections_exceeding_limits() {
+        let (tx, rx) = oneshot::channel();
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        let svc = WebSocketUpgradeLayer::new(move |mut ws: WebSocket| {
+            let tx = tx.clone();
+            async move {
+                let err = ws.recv().await.unwrap().unwrap_err();
+                tx.lock().unwrap().take().unwrap().send(err).unwrap();
+            }
+        })
+        .max_message_size(8)
+        .layer(service_fn(ok));
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        let res = svc
+            .call(handshake(server).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        write_frame(&mut client, false, 0x2, b"12345").await;
+        write_frame(&mut client, true, 0x0, b"6789").await;
+        assert_eq!(read_frame(&mut client).await, (0x8, b"\x03\xf1".to_vec()));
+        assert!(rx.await.unwrap().is_message_too_large());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn closes_idle_connections() {
+        let svc = WebSocketUpgradeLayer::new(|ws: WebSocket| async move {
+            assert!(ws
+                .serve(service_fn(|_: WsMessage| async {
+                    Ok::<_, Infallible>(None)
+                }))
+                .await
+                .unwrap_err()
+                .is_idle_timeout());
+        })
+        .idle_timeout(Duration::from_secs(30))
+        .layer(service_fn(ok));
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        svc.call(handshake(server).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        write_frame(&mut client, true, 0x2, b"data").await;
+        assert_eq!(read_frame(&mut client).await, (0x8, b"\x03\xe9".to_vec()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_partial_frames_when_receiving_is_cancelled() {
+        let (tx, rx) = oneshot::channel();
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        let svc = WebSocketUpgradeLayer::new(move |mut ws: WebSocket| {
+            let tx = tx.clone();
+            async move {
+                let mut cancelled = 0;
+                let message = loop {
+                    tokio::select! {
+                        message = ws.recv() => break message.unwrap().unwrap(),
+                        _ = tokio::time::sleep(Duration::from_millis(10)) => cancelled += 1,
+                    }
+                };
+                tx.lock()
+                    .unwrap()
+                    .take()
+                    .unwrap()
+                    .send((message, cancelled))
+                    .unwrap();
+            }
+        })
+        .layer(service_fn(ok));
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        svc.call(handshake(server).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let frame = frame(true, 0x1, b"hello world");
+        for chunk in frame.chunks(3) {
+            client.write_all(chunk).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        let (message, cancelled) = rx.await.unwrap();
+        assert_eq!(message, WsMessage::from("hello world"));
+        assert!(cancelled > 0);
+    }
+
+    #[tokio::test]
+    async fn splits_into_receiver_and_sender() {
+        let (tx, rx) = oneshot::channel();
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        let svc = WebSocketUpgradeLayer::new(move |ws: WebSocket| {
+            let tx = tx.clone();
+            async move {
+                let (mut receiver, mut sender) = ws.split();
+                let sending = tokio::spawn(async move {
+                    sender.send(WsMessage::from("hello")).await.unwrap();
+                    sender
+                });
+                while let Some(message) = receiver.recv().await {
+                    message.unwrap();
+                }
+                let mut sender = sending.await.unwrap();
+                let result = sender.send(WsMessage::from("bye")).await;
+                tx.lock().unwrap().take().unwrap().send(result).unwrap();
+            }
+        })
+        .layer(service_fn(ok));
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        svc.call(handshake(server).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(read_frame(&mut client).await, (0x1, b"hello".to_vec()));
+        // pings are answered by the receiver while the sender is held elsewhere
+        write_frame(&mut client, true, 0x9, b"ping").await;
+        assert_eq!(read_frame(&mut client).await, (0xa, b"ping".to_vec()));
+        write_frame(&mut client, true, 0x8, &[0x03, 0xe8]).await;
+        assert_eq!(read_frame(&mut client).await, (0x8, b"\x03\xe8".to_vec()));
+
+        // no more messages are sent once the closing handshake completed
+        assert!(rx.await.unwrap().is_err());
+    }
+}
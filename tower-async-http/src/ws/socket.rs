@@ -0,0 +1,689 @@
+use super::Config;
+use crate::BoxError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use http::{HeaderMap, Uri};
+use std::{
+    error, fmt, io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tower_async_service::Service;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+/// The maximum payload size of control frames.
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// The minimum number of bytes read from the connection at once.
+const READ_BUF_SIZE: usize = 8 * 1024;
+
+/// An upgraded connection, as produced by [`OnUpgrade`](super::OnUpgrade).
+pub(super) trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T> Io for T where T: AsyncRead + AsyncWrite + Send + Unpin {}
+
+/// A message sent or received over a [`WebSocket`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsMessage {
+    /// A text message.
+    Text(String),
+    /// A binary message.
+    Binary(Bytes),
+    /// A ping, answered with a pong by the receiving end.
+    Ping(Bytes),
+    /// A pong, sent in reply to a ping, or as a unidirectional heartbeat.
+    Pong(Bytes),
+    /// A close message, which starts or completes the closing handshake.
+    Close(Option<CloseFrame>),
+}
+
+impl From<String> for WsMessage {
+    fn from(text: String) -> Self {
+        WsMessage::Text(text)
+    }
+}
+
+impl From<&str> for WsMessage {
+    fn from(text: &str) -> Self {
+        WsMessage::Text(text.to_owned())
+    }
+}
+
+impl From<Bytes> for WsMessage {
+    fn from(data: Bytes) -> Self {
+        WsMessage::Binary(data)
+    }
+}
+
+impl From<Vec<u8>> for WsMessage {
+    fn from(data: Vec<u8>) -> Self {
+        WsMessage::Binary(data.into())
+    }
+}
+
+/// The status code and reason of a [`WsMessage::Close`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    code: u16,
+    reason: String,
+}
+
+impl CloseFrame {
+    /// Create a new [`CloseFrame`] with the given [status code] and reason.
+    ///
+    /// [status code]: https://www.rfc-editor.org/rfc/rfc6455#section-7.4
+    pub fn new(code: u16, reason: impl Into<String>) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+        }
+    }
+
+    /// The status code of the close frame.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The reason of the close frame, which may be empty.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// An accepted WebSocket connection.
+///
+/// Handed to the handler of a [`WebSocketUpgrade`](super::WebSocketUpgrade) middleware once
+/// the connection has been upgraded. Messages are received with [`WebSocket::recv`] and sent
+/// with [`WebSocket::send`], or handled by a [`Service`] with [`WebSocket::serve`]. To receive
+/// and send messages concurrently, for example from different tasks, [split] the connection
+/// into a [`WsReceiver`] and a [`WsSender`].
+///
+/// Pings are answered, and close messages are echoed, automatically while receiving.
+/// Protocol violations, messages exceeding the [maximum size] and [idle connections] close
+/// the connection with the corresponding status code.
+///
+/// [split]: WebSocket::split
+/// [maximum size]: super::WebSocketUpgradeLayer::max_message_size
+/// [idle connections]: super::WebSocketUpgradeLayer::idle_timeout
+pub struct WebSocket {
+    receiver: WsReceiver,
+    sender: WsSender,
+    uri: Uri,
+    headers: HeaderMap,
+    protocol: Option<String>,
+}
+
+impl WebSocket {
+    pub(super) fn new(
+        io: Box<dyn Io>,
+        uri: Uri,
+        headers: HeaderMap,
+        protocol: Option<String>,
+        config: &Config,
+    ) -> Self {
+        let (read, write) = tokio::io::split(io);
+        let writer = Arc::new(Writer {
+            queue: Mutex::new(Queue {
+                buf: BytesMut::new(),
+                close_sent: false,
+            }),
+            io: tokio::sync::Mutex::new(WriteIo {
+                io: write,
+                buf: BytesMut::new(),
+            }),
+        });
+        Self {
+            receiver: WsReceiver {
+                io: read,
+                buf: BytesMut::new(),
+                writer: writer.clone(),
+                max_message_size: config.max_message_size,
+                idle_timeout: config.idle_timeout,
+                partial: None,
+                ready: None,
+                done: false,
+            },
+            sender: WsSender { writer },
+            uri,
+            headers,
+            protocol,
+        }
+    }
+
+    /// The URI of the handshake request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The headers of the handshake request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The subprotocol agreed upon during the handshake, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Receive the next message.
+    ///
+    /// See [`WsReceiver::recv`] for more details.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe. If it is used as the event in a `tokio::select!` statement
+    /// and some other branch completes first, no message is lost.
+    pub async fn recv(&mut self) -> Option<Result<WsMessage, WsError>> {
+        self.receiver.recv().await
+    }
+
+    /// Send a message.
+    ///
+    /// See [`WsSender::send`] for more details.
+    ///
+    /// # Cancel safety
+    ///
+    /// Cancelling this method never leaves a partially written frame on the connection,
+    /// see [`WsSender::send`].
+    pub async fn send(&mut self, message: WsMessage) -> Result<(), WsError> {
+        self.sender.send(message).await
+    }
+
+    /// Split the connection into a [`WsReceiver`] and a [`WsSender`], such that messages can
+    /// be received and sent concurrently.
+    ///
+    /// The receiver keeps answering pings and echoing close messages through the write half
+    /// shared with the sender.
+    pub fn split(self) -> (WsReceiver, WsSender) {
+        (self.receiver, self.sender)
+    }
+
+    /// Serve the connection with the given service.
+    ///
+    /// Every text and binary message received is passed to the service, and the message it
+    /// returns, if any, is sent back. Middleware such as timeouts and concurrency limits can
+    /// be applied to the service like to any other.
+    ///
+    /// Returns once the connection is closed. If the service fails, the connection is closed
+    /// with status code `1011` and the error is returned.
+    pub async fn serve<S>(mut self, service: S) -> Result<(), WsError>
+    where
+        S: Service<WsMessage, Response = Option<WsMessage>>,
+        S::Error: Into<BoxError>,
+    {
+        while let Some(message) = self.receiver.recv().await {
+            let message = message?;
+            if !matches!(message, WsMessage::Text(_) | WsMessage::Binary(_)) {
+                continue;
+            }
+            match service.call(message).await {
+                Ok(Some(reply)) => self.sender.send(reply).await?,
+                Ok(None) => {}
+                Err(err) => {
+                    let err = WsError::new(Kind::Service(err.into()));
+                    return Err(self.receiver.fail(err).await);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("uri", &self.uri)
+            .field("protocol", &self.protocol)
+            .field("receiver", &self.receiver)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The receiving half of a [`WebSocket`], created with [`WebSocket::split`].
+pub struct WsReceiver {
+    io: ReadHalf<Box<dyn Io>>,
+    /// The bytes read from the connection which don't form a whole frame yet.
+    buf: BytesMut,
+    writer: Arc<Writer>,
+    max_message_size: usize,
+    idle_timeout: Option<Duration>,
+    /// The opcode and payload of a fragmented message received so far.
+    partial: Option<(u8, BytesMut)>,
+    /// The outcome of a receive which is returned once the replies it queued are written.
+    ready: Option<Result<WsMessage, WsError>>,
+    /// Whether no more messages are received.
+    done: bool,
+}
+
+impl WsReceiver {
+    /// Receive the next message.
+    ///
+    /// Returns `None` once the connection is closed, that is after a [`WsMessage::Close`] or
+    /// an error has been returned, or when the peer closed the connection.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancel safe. If it is used as the event in a `tokio::select!` statement
+    /// and some other branch completes first, no message is lost: partially received frames
+    /// are buffered, and a message which was received, but not yet returned, is returned by
+    /// the next call.
+    pub async fn recv(&mut self) -> Option<Result<WsMessage, WsError>> {
+        if self.ready.is_none() {
+            if self.done {
+                return None;
+            }
+            let result = match self.idle_timeout {
+                Some(idle_timeout) => tokio::time::timeout(idle_timeout, self.read_message())
+                    .await
+                    .unwrap_or_else(|_| Err(WsError::new(Kind::IdleTimeout))),
+                None => self.read_message().await,
+            };
+            self.ready = self.reply(result);
+        }
+
+        // write the pong or close frame replying to the message before returning it
+        let flushed = self.writer.flush(self.done).await;
+        let outcome = self.ready.take();
+        match flushed {
+            Err(err) if !self.done => Some(Err(self.fail(err.into()).await)),
+            _ => outcome,
+        }
+    }
+
+    /// Queue the reply to the outcome of a receive, without awaiting: a pong to a ping,
+    /// and a close frame to a close message or an error.
+    fn reply(
+        &mut self,
+        result: Result<Option<WsMessage>, WsError>,
+    ) -> Option<Result<WsMessage, WsError>> {
+        match result {
+            Ok(Some(WsMessage::Ping(data))) => {
+                // no pong is sent once the closing handshake started
+                let _ = self.writer.queue(PONG, &data);
+                Some(Ok(WsMessage::Ping(data)))
+            }
+            Ok(Some(WsMessage::Close(frame))) => {
+                self.done = true;
+                let _ = self.writer.queue(CLOSE, &close_payload(frame.as_ref()));
+                Some(Ok(WsMessage::Close(frame)))
+            }
+            Ok(Some(message)) => Some(Ok(message)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => Some(Err(self.close_with(err))),
+        }
+    }
+
+    /// Queue a close frame with the status code of the error, if any.
+    fn close_with(&mut self, err: WsError) -> WsError {
+        self.done = true;
+        if let Some(code) = err.close_code() {
+            let payload = close_payload(Some(&CloseFrame::new(code, "")));
+            let _ = self.writer.queue(CLOSE, &payload);
+        }
+        err
+    }
+
+    /// Close the connection after an error, with the status code of the error.
+    async fn fail(&mut self, err: WsError) -> WsError {
+        let err = self.close_with(err);
+        let _ = self.writer.flush(true).await;
+        err
+    }
+
+    /// Read the next message, or `None` if the peer closed the connection.
+    ///
+    /// All state is kept in the receiver in between reads, such that this is cancel safe.
+    async fn read_message(&mut self) -> Result<Option<WsMessage>, WsError> {
+        loop {
+            let buffered = self.partial.as_ref().map_or(0, |(_, buf)| buf.len());
+            let Some((fin, opcode, payload)) = self.read_frame(buffered).await? else {
+                if self.partial.is_some() {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                return Ok(None);
+            };
+
+            match opcode {
+                CONTINUATION => {
+                    let Some((_, buf)) = &mut self.partial else {
+                        return Err(WsError::protocol("unexpected continuation frame"));
+                    };
+                    buf.extend_from_slice(&payload);
+                    if fin {
+                        let (opcode, buf) = self.partial.take().unwrap();
+                        return data_message(opcode, buf.freeze()).map(Some);
+                    }
+                }
+                TEXT | BINARY => {
+                    if self.partial.is_some() {
+                        return Err(WsError::protocol("expected continuation frame"));
+                    }
+                    if fin {
+                        return data_message(opcode, payload).map(Some);
+                    }
+                    self.partial = Some((opcode, BytesMut::from(&payload[..])));
+                }
+                CLOSE => return close_message(payload).map(Some),
+                PING => return Ok(Some(WsMessage::Ping(payload))),
+                _ => return Ok(Some(WsMessage::Pong(payload))),
+            }
+        }
+    }
+
+    /// Read a frame, or `None` if the peer closed the connection in between frames.
+    ///
+    /// `buffered` is the size of the fragmented message received so far.
+    async fn read_frame(&mut self, buffered: usize) -> Result<Option<(bool, u8, Bytes)>, WsError> {
+        let limit = self.max_message_size.saturating_sub(buffered);
+        loop {
+            if let Some(frame) = parse_frame(&mut self.buf, limit)? {
+                return Ok(Some(frame));
+            }
+            self.buf.reserve(READ_BUF_SIZE);
+            if self.io.read_buf(&mut self.buf).await? == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+}
+
+impl fmt::Debug for WsReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsReceiver")
+            .field("max_message_size", &self.max_message_size)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The sending half of a [`WebSocket`], created with [`WebSocket::split`].
+pub struct WsSender {
+    writer: Arc<Writer>,
+}
+
+impl WsSender {
+    /// Send a message.
+    ///
+    /// Sending a [`WsMessage::Close`] starts the closing handshake, after which no more
+    /// messages can be sent. Keep receiving messages until `None` is returned to complete it.
+    ///
+    /// # Cancel safety
+    ///
+    /// The message is queued before anything is written, such that cancelling this method
+    /// never leaves a partially written frame on the connection. A message whose sending
+    /// was cancelled is written along with the next frame sent.
+    pub async fn send(&mut self, message: WsMessage) -> Result<(), WsError> {
+        match message {
+            WsMessage::Text(text) => self.writer.queue(TEXT, text.as_bytes())?,
+            WsMessage::Binary(data) => self.writer.queue(BINARY, &data)?,
+            WsMessage::Ping(data) => self.queue_control(PING, &data)?,
+            WsMessage::Pong(data) => self.queue_control(PONG, &data)?,
+            WsMessage::Close(frame) => self.queue_control(CLOSE, &close_payload(frame.as_ref()))?,
+        }
+        self.writer.flush(false).await?;
+        Ok(())
+    }
+
+    fn queue_control(&self, opcode: u8, payload: &[u8]) -> Result<(), WsError> {
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(WsError::protocol("control frame payload too large"));
+        }
+        self.writer.queue(opcode, payload)
+    }
+}
+
+impl fmt::Debug for WsSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsSender").finish_non_exhaustive()
+    }
+}
+
+/// The write half of a connection, shared by its [`WsReceiver`] and [`WsSender`].
+///
+/// Frames are queued without awaiting, and written from the queue afterwards, such that
+/// cancelling a receive or a send never loses a reply nor writes part of a frame.
+struct Writer {
+    queue: Mutex<Queue>,
+    io: tokio::sync::Mutex<WriteIo>,
+}
+
+struct Queue {
+    /// The frames queued to be written.
+    buf: BytesMut,
+    /// Whether a close frame was queued.
+    close_sent: bool,
+}
+
+struct WriteIo {
+    io: WriteHalf<Box<dyn Io>>,
+    /// The frames taken from the queue which aren't fully written yet.
+    buf: BytesMut,
+}
+
+impl Writer {
+    /// Queue a frame, failing once a close frame was queued.
+    fn queue(&self, opcode: u8, payload: &[u8]) -> Result<(), WsError> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.close_sent {
+            return Err(WsError::new(Kind::Closed));
+        }
+        queue.close_sent = opcode == CLOSE;
+
+        let buf = &mut queue.buf;
+        buf.put_u8(0x80 | opcode);
+        match payload.len() {
+            len if len < 126 => buf.put_u8(len as u8),
+            len if len <= usize::from(u16::MAX) => {
+                buf.put_u8(126);
+                buf.put_u16(len as u16);
+            }
+            len => {
+                buf.put_u8(127);
+                buf.put_u64(len as u64);
+            }
+        }
+        buf.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Write the queued frames, and shut the connection down afterwards if `shutdown` is set.
+    async fn flush(&self, shutdown: bool) -> io::Result<()> {
+        if !shutdown && self.queue.lock().unwrap().buf.is_empty() {
+            return Ok(());
+        }
+        let mut io = self.io.lock().await;
+        let WriteIo { io, buf } = &mut *io;
+        let queued = self.queue.lock().unwrap().buf.split();
+        buf.unsplit(queued);
+
+        while !buf.is_empty() {
+            if io.write_buf(buf).await? == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+        }
+        io.flush().await?;
+        if shutdown {
+            io.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the frame at the start of `buf`, or return `None` if `buf` doesn't hold the whole
+/// frame yet, in which case `buf` is left as is.
+///
+/// `limit` is the maximum size of the payload of a data frame.
+fn parse_frame(buf: &mut BytesMut, limit: usize) -> Result<Option<(bool, u8, Bytes)>, WsError> {
+    let [first, second, ..] = buf[..] else {
+        return Ok(None);
+    };
+
+    let fin = first & 0x80 != 0;
+    let opcode = first & 0x0f;
+    if first & 0x70 != 0 {
+        return Err(WsError::protocol("reserved bits are set"));
+    }
+    if !matches!(opcode, CONTINUATION | TEXT | BINARY | CLOSE | PING | PONG) {
+        return Err(WsError::protocol("unknown opcode"));
+    }
+    if second & 0x80 == 0 {
+        return Err(WsError::protocol("client frames must be masked"));
+    }
+
+    let (offset, len) = match second & 0x7f {
+        126 => match buf.get(2..4) {
+            Some(len) => (4, u64::from(u16::from_be_bytes([len[0], len[1]]))),
+            None => return Ok(None),
+        },
+        127 => match buf.get(2..10) {
+            Some(len) => (10, u64::from_be_bytes(len.try_into().unwrap())),
+            None => return Ok(None),
+        },
+        len => (2, u64::from(len)),
+    };
+    if opcode >= CLOSE {
+        if !fin || len > MAX_CONTROL_PAYLOAD as u64 {
+            return Err(WsError::protocol("invalid control frame"));
+        }
+    } else if len > limit as u64 {
+        return Err(WsError::new(Kind::MessageTooLarge));
+    }
+
+    let len = len as usize;
+    if buf.len() < offset + 4 + len {
+        return Ok(None);
+    }
+    buf.advance(offset);
+    let mask = buf.split_to(4);
+    let mut payload = buf.split_to(len);
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Some((fin, opcode, payload.freeze())))
+}
+
+fn data_message(opcode: u8, payload: Bytes) -> Result<WsMessage, WsError> {
+    if opcode == BINARY {
+        return Ok(WsMessage::Binary(payload));
+    }
+    String::from_utf8(payload.into())
+        .map(WsMessage::Text)
+        .map_err(|_| WsError::new(Kind::InvalidUtf8))
+}
+
+fn close_message(payload: Bytes) -> Result<WsMessage, WsError> {
+    match payload.len() {
+        0 => return Ok(WsMessage::Close(None)),
+        1 => return Err(WsError::protocol("invalid close frame")),
+        _ => {}
+    }
+    let code = u16::from_be_bytes([payload[0], payload[1]]);
+    if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+        return Err(WsError::protocol("invalid close code"));
+    }
+    let reason =
+        String::from_utf8(payload[2..].to_vec()).map_err(|_| WsError::new(Kind::InvalidUtf8))?;
+    Ok(WsMessage::Close(Some(CloseFrame { code, reason })))
+}
+
+fn close_payload(frame: Option<&CloseFrame>) -> Vec<u8> {
+    let Some(frame) = frame else {
+        return Vec::new();
+    };
+    let mut payload = frame.code.to_be_bytes().to_vec();
+    payload.extend_from_slice(frame.reason.as_bytes());
+    payload
+}
+
+/// Error returned by a [`WebSocket`].
+#[derive(Debug)]
+pub struct WsError {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Io(io::Error),
+    Protocol(&'static str),
+    InvalidUtf8,
+    MessageTooLarge,
+    IdleTimeout,
+    Closed,
+    Service(BoxError),
+}
+
+impl WsError {
+    fn new(kind: Kind) -> Self {
+        Self { kind }
+    }
+
+    fn protocol(reason: &'static str) -> Self {
+        Self::new(Kind::Protocol(reason))
+    }
+
+    /// Returns `true` if the connection was closed for being idle.
+    pub fn is_idle_timeout(&self) -> bool {
+        matches!(self.kind, Kind::IdleTimeout)
+    }
+
+    /// Returns `true` if a message exceeded the maximum size.
+    pub fn is_message_too_large(&self) -> bool {
+        matches!(self.kind, Kind::MessageTooLarge)
+    }
+
+    /// The status code the connection is closed with, if any.
+    fn close_code(&self) -> Option<u16> {
+        match self.kind {
+            Kind::Protocol(_) => Some(1002),
+            Kind::InvalidUtf8 => Some(1007),
+            Kind::MessageTooLarge => Some(1009),
+            Kind::IdleTimeout => Some(1001),
+            Kind::Service(_) => Some(1011),
+            Kind::Io(_) | Kind::Closed => None,
+        }
+    }
+}
+
+impl From<io::Error> for WsError {
+    fn from(err: io::Error) -> Self {
+        Self::new(Kind::Io(err))
+    }
+}
+
+impl fmt::Display for WsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Io(err) => write!(f, "websocket io error: {err}"),
+            Kind::Protocol(reason) => write!(f, "websocket protocol error: {reason}"),
+            Kind::InvalidUtf8 => f.write_str("websocket text is not valid UTF-8"),
+            Kind::MessageTooLarge => f.write_str("websocket message exceeds the maximum size"),
+            Kind::IdleTimeout => f.write_str("websocket connection was idle for too long"),
+            Kind::Closed => f.write_str("websocket connection is closed"),
+            Kind::Service(err) => write!(f, "websocket service error: {err}"),
+        }
+    }
+}
+
+impl error::Error for WsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.kind {
+            Kind::Io(err) => Some(err),
+            Kind::Service(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
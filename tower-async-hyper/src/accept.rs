@@ -0,0 +1,414 @@
+//! Middleware for accept loops, throttling new connections.
+//!
+//! Servers accept connections in a loop, from a listener implementing [`Accept`]. The
+//! [`AcceptLimit`] middleware wraps such a listener, and limits the rate at which new
+//! connections are accepted and the number of connections open at the same time. This
+//! protects servers from accept storms before any HTTP parsing occurs.
+//!
+//! Connections exceeding a limit are handled according to the [`Overflow`] strategy:
+//! [delayed](Overflow::Delay), leaving them in the backlog of the listener until they fit
+//! within the limits, or [dropped](Overflow::Drop), closing them right after they were
+//! accepted.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use tokio::net::TcpListener;
+//! use tower_async_hyper::accept::{Accept, AcceptLimit, Overflow};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> std::io::Result<()> {
+//! let listener = AcceptLimit::new(TcpListener::bind("127.0.0.1:8080").await?)
+//!     .rate(100, Duration::from_secs(1))
+//!     .max_connections(10_000)
+//!     .overflow(Overflow::Drop);
+//!
+//! loop {
+//!     // The open connection is counted until `stream` is dropped.
+//!     let (stream, _) = listener.accept().await?;
+//!     tokio::spawn(async move {
+//!         // serve the connection ...
+//!         # drop(stream);
+//!     });
+//! }
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+/// A listener accepting connections, such as a [`TcpListener`].
+pub trait Accept {
+    /// The connections accepted by the listener.
+    type Io;
+    /// The addresses of the peers of accepted connections.
+    type Addr;
+
+    /// Accept a new connection.
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Io, Self::Addr)>> + Send;
+}
+
+impl Accept for TcpListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Io, Self::Addr)>> + Send {
+        TcpListener::accept(self)
+    }
+}
+
+/// How [`AcceptLimit`] handles connections exceeding its limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Stop accepting connections until they fit within the limits again,
+    /// leaving new connections in the backlog of the listener.
+    #[default]
+    Delay,
+    /// Keep accepting connections, but close the ones exceeding the limits right away.
+    Drop,
+}
+
+/// Middleware for an [`Accept`] listener, limiting the rate of new connections and
+/// the number of open connections.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug)]
+pub struct AcceptLimit<L> {
+    inner: L,
+    rate: Option<Rate>,
+    connections: Option<Arc<Semaphore>>,
+    overflow: Overflow,
+}
+
+#[derive(Debug)]
+struct Rate {
+    num: u64,
+    per: Duration,
+    state: Mutex<(Instant, u64)>,
+}
+
+impl Rate {
+    /// Take a token, returning when the current period ends if none is left.
+    fn try_take(&self) -> Result<(), Instant> {
+        let mut state = self.state.lock().unwrap();
+        let (until, remaining) = &mut *state;
+        let now = Instant::now();
+        if now >= *until {
+            *until = now + self.per;
+            *remaining = self.num;
+        }
+        if *remaining == 0 {
+            return Err(*until);
+        }
+        *remaining -= 1;
+        Ok(())
+    }
+}
+
+impl<L> AcceptLimit<L> {
+    /// Create a new [`AcceptLimit`], without any limits.
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            rate: None,
+            connections: None,
+            overflow: Overflow::default(),
+        }
+    }
+
+    /// Accept at most `num` new connections per `per` period.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn rate(mut self, num: u64, per: Duration) -> Self {
+        assert!(num > 0, "rate must allow at least one connection");
+        assert!(
+            per > Duration::ZERO,
+            "rate period must be greater than zero"
+        );
+        self.rate = Some(Rate {
+            num,
+            per,
+            state: Mutex::new((Instant::now(), num)),
+        });
+        self
+    }
+
+    /// Keep at most `max` accepted connections open at the same time.
+    ///
+    /// Connections are counted until the [`Limited`] connection is dropped.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.connections = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Set how connections exceeding the limits are handled.
+    ///
+    /// Defaults to [`Overflow::Delay`].
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Returns the number of connections that can still be opened before reaching
+    /// the [maximum](AcceptLimit::max_connections), if any.
+    pub fn available_connections(&self) -> Option<usize> {
+        self.connections
+            .as_ref()
+            .map(|connections| connections.available_permits())
+    }
+
+    /// Returns a reference to the inner listener.
+    pub fn get_ref(&self) -> &L {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the inner listener.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L> AcceptLimit<L>
+where
+    L: Accept + Sync,
+    L::Io: Send,
+    L::Addr: Send,
+{
+    async fn accept_delayed(&self) -> io::Result<(Limited<L::Io>, L::Addr)> {
+        let permit = match &self.connections {
+            Some(connections) => Some(connections.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        if let Some(rate) = &self.rate {
+            while let Err(until) = rate.try_take() {
+                tokio::time::sleep_until(until).await;
+            }
+        }
+        let (io, addr) = self.inner.accept().await?;
+        Ok((
+            Limited {
+                io,
+                _permit: permit,
+            },
+            addr,
+        ))
+    }
+
+    async fn accept_or_drop(&self) -> io::Result<(Limited<L::Io>, L::Addr)> {
+        loop {
+            let (io, addr) = self.inner.accept().await?;
+            let permit = match &self.connections {
+                Some(connections) => match connections.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            if self
+                .rate
+                .as_ref()
+                .is_some_and(|rate| rate.try_take().is_err())
+            {
+                continue;
+            }
+            return Ok((
+                Limited {
+                    io,
+                    _permit: permit,
+                },
+                addr,
+            ));
+        }
+    }
+}
+
+impl<L> Accept for AcceptLimit<L>
+where
+    L: Accept + Sync,
+    L::Io: Send,
+    L::Addr: Send,
+{
+    type Io = Limited<L::Io>;
+    type Addr = L::Addr;
+
+    async fn accept(&self) -> io::Result<(Self::Io, Self::Addr)> {
+        match self.overflow {
+            Overflow::Delay => self.accept_delayed().await,
+            Overflow::Drop => self.accept_or_drop().await,
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A connection accepted by [`AcceptLimit`], counted as open until it is dropped.
+    #[derive(Debug)]
+    pub struct Limited<T> {
+        #[pin]
+        io: T,
+        _permit: Option<OwnedSemaphorePermit>,
+    }
+}
+
+impl<T> Limited<T> {
+    /// Returns a reference to the inner connection.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Returns a mutable reference to the inner connection.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+}
+
+impl<T> AsyncRead for Limited<T>
+where
+    T: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().io.poll_read(cx, buf)
+    }
+}
+
+impl<T> AsyncWrite for Limited<T>
+where
+    T: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc;
+
+    /// A listener accepting the connections sent through a channel.
+    struct Channel(tokio::sync::Mutex<mpsc::UnboundedReceiver<u32>>);
+
+    impl Accept for Channel {
+        type Io = u32;
+        type Addr = ();
+
+        async fn accept(&self) -> io::Result<(u32, ())> {
+            let io = self.0.lock().await.recv().await;
+            io.map(|io| (io, ()))
+                .ok_or_else(|| io::ErrorKind::NotConnected.into())
+        }
+    }
+
+    fn channel() -> (mpsc::UnboundedSender<u32>, Channel) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Channel(tokio::sync::Mutex::new(rx)))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_connections() {
+        let (tx, listener) = channel();
+        let listener = AcceptLimit::new(listener)
+            .rate(2, Duration::from_secs(1))
+            .max_connections(3);
+        for io in 0..4 {
+            tx.send(io).unwrap();
+        }
+
+        let start = Instant::now();
+        let (first, _) = listener.accept().await.unwrap();
+        let (second, _) = listener.accept().await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        let (third, _) = listener.accept().await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(listener.available_connections(), Some(0));
+        assert_eq!(
+            [*first.get_ref(), *second.get_ref(), *third.get_ref()],
+            [0, 1, 2]
+        );
+
+        // the fourth connection waits until another one is closed
+        let fourth = tokio::spawn(async move { listener.accept().await.unwrap().0.io });
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!fourth.is_finished());
+        drop(first);
+        assert_eq!(fourth.await.unwrap(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_connections() {
+        let (tx, listener) = channel();
+        let listener = AcceptLimit::new(listener)
+            .rate(2, Duration::from_secs(1))
+            .overflow(Overflow::Drop);
+        for io in 0..3 {
+            tx.send(io).unwrap();
+        }
+        drop(tx);
+
+        assert_eq!(listener.accept().await.unwrap().0.io, 0);
+        assert_eq!(listener.accept().await.unwrap().0.io, 1);
+        // the third connection exceeds the rate, and is dropped
+        listener.accept().await.unwrap_err();
+
+        let (tx, listener) = channel();
+        let listener = AcceptLimit::new(listener)
+            .max_connections(1)
+            .overflow(Overflow::Drop);
+        for io in 0..2 {
+            tx.send(io).unwrap();
+        }
+        drop(tx);
+
+        let (first, _) = listener.accept().await.unwrap();
+        assert_eq!(first.io, 0);
+        // the second connection exceeds the maximum number of connections, and is dropped
+        listener.accept().await.unwrap_err();
+        drop(first);
+        assert_eq!(listener.available_connections(), Some(1));
+    }
+}
@@ -0,0 +1,245 @@
+//! Server backends, serving a `tower-async` service stack.
+//!
+//! A [`Backend`] accepts connections and serves their requests with a service. The
+//! [`HyperBackend`] serves a service stack over HTTP/1.1 and HTTP/2 with `hyper`.
+//!
+//! This crate doesn't provide an HTTP/3 (QUIC) backend. Other backends, such as one built on
+//! top of an h3 server, can implement [`Backend`] for the services they support, converting
+//! each request into an [`http::Request`] with a body of their own. Stacks that should be
+//! served by several backends then have to be generic over their request body, rather than
+//! requiring the [`Incoming`] body of `hyper`, which the [`HyperBackend`] serves.
+//!
+//! Clients discover HTTP/3 endpoints through the `Alt-Svc` header, which can be added to
+//! responses served over TCP with the [`tower_async_http::alt_svc`] middleware.
+//!
+//! [`http::Request`]: https://docs.rs/http/latest/http/request/struct.Request.html
+//! [`tower_async_http::alt_svc`]: https://docs.rs/tower-async-http/latest/tower_async_http/alt_svc/index.html
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use http::{Request, Response};
+//! use std::{convert::Infallible, time::Duration};
+//! use tokio::net::TcpListener;
+//! use tower_async::ServiceBuilder;
+//! use tower_async_http::alt_svc::AltSvcLayer;
+//! use tower_async_hyper::backend::{Backend, HyperBackend};
+//!
+//! async fn handle<B>(_: Request<B>) -> Result<Response<String>, Infallible> {
+//!     Ok(Response::new(String::from("hello")))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//!     let service = ServiceBuilder::new()
+//!         .layer(AltSvcLayer::new().h3(443))
+//!         .timeout(Duration::from_secs(5))
+//!         .service_fn(handle);
+//!
+//!     let listener = TcpListener::bind("127.0.0.1:8080").await?;
+//!     HyperBackend::new(listener).serve(service).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::{convert::Infallible, future::Future, io, time::Duration};
+
+use http_body::Body;
+use hyper::{body::Incoming, Request, Response};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tower_async_service::Service;
+
+use crate::{accept::Accept, TowerHyperServiceExt};
+
+/// A server backend, serving the requests of the connections it accepts with a service.
+///
+/// See the [module docs](self) for more details.
+pub trait Backend<S> {
+    /// Errors produced by the backend.
+    type Error;
+
+    /// Serve connections with the given service, until the backend shuts down.
+    fn serve(self, service: S) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// A [`Backend`] serving HTTP/1.1 and HTTP/2 connections accepted from a listener,
+/// such as a [`TcpListener`], with `hyper`.
+///
+/// Each connection is served on a task spawned on the current Tokio runtime,
+/// with support for upgrades. New connections can be throttled by wrapping the listener
+/// in an [`AcceptLimit`](crate::accept::AcceptLimit).
+///
+/// Errors accepting a connection don't stop the backend. Errors of the connection itself,
+/// such as a reset before it was accepted, are ignored. Other errors, such as running out of
+/// file descriptors, are logged, after which the backend waits a second before accepting
+/// connections again.
+#[derive(Debug)]
+pub struct HyperBackend<L = TcpListener> {
+    listener: L,
+    builder: Builder<TokioExecutor>,
+}
+
+impl<L> HyperBackend<L> {
+    /// Create a new [`HyperBackend`], serving the connections accepted from `listener`.
+    pub fn new(listener: L) -> Self {
+        Self {
+            listener,
+            builder: Builder::new(TokioExecutor::new()),
+        }
+    }
+
+    /// Returns a mutable reference to the connection builder, to configure
+    /// the HTTP/1.1 and HTTP/2 protocols.
+    pub fn builder_mut(&mut self) -> &mut Builder<TokioExecutor> {
+        &mut self.builder
+    }
+}
+
+impl<L, S, ResBody> Backend<S> for HyperBackend<L>
+where
+    L: Accept + Send + Sync + 'static,
+    L::Io: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S: Service<Request<Incoming>, Response = Response<ResBody>, call(): Send>
+        + Send
+        + Sync
+        + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    ResBody: Body + Send + 'static,
+    ResBody::Data: Send,
+    ResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Error = Infallible;
+
+    fn serve(self, service: S) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        let service = service.into_hyper_service();
+        async move {
+            loop {
+                let accepted = self.listener.accept().await.map(|(stream, _)| stream);
+                let stream = match accepted {
+                    Ok(stream) => stream,
+                    Err(err) if is_connection_error(&err) => continue,
+                    Err(err) => {
+                        tracing::error!(
+                            error = %err,
+                            "failed to accept connection, retrying in {:?}",
+                            ACCEPT_ERROR_BACKOFF
+                        );
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+                let service = service.clone();
+                let builder = self.builder.clone();
+                tokio::spawn(async move {
+                    let _ = builder
+                        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        }
+    }
+}
+
+/// How long to wait before accepting connections again after failing to accept one.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Returns whether the error concerns the accepted connection only, rather than the listener.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).await.unwrap();
+        res
+    }
+
+    #[tokio::test]
+    async fn hyper_backend_serves_connections() {
+        let service = tower_async::ServiceBuilder::new()
+            .timeout(std::time::Duration::from_secs(5))
+            .service_fn(|req: Request<Incoming>| async move {
+                Ok::<_, Infallible>(Response::new(format!("hello {}", req.uri().path())))
+            });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = crate::accept::AcceptLimit::new(listener).max_connections(1);
+        tokio::spawn(HyperBackend::new(listener).serve(service));
+
+        let res = get(addr, "/world").await;
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with("\r\n\r\nhello /world"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hyper_backend_keeps_accepting_after_errors() {
+        // fails to accept the first connection, like when running out of file descriptors
+        struct Flaky {
+            listener: TcpListener,
+            failed: AtomicBool,
+        }
+
+        impl Accept for Flaky {
+            type Io = TcpStream;
+            type Addr = SocketAddr;
+
+            async fn accept(&self) -> io::Result<(Self::Io, Self::Addr)> {
+                if !self.failed.swap(true, Ordering::SeqCst) {
+                    return Err(io::Error::other("too many open files"));
+                }
+                self.listener.accept().await
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = Flaky {
+            listener,
+            failed: AtomicBool::new(false),
+        };
+        let service = tower_async::ServiceBuilder::new()
+            .timeout(std::time::Duration::from_secs(5))
+            .service_fn(|req: Request<Incoming>| async move {
+                Ok::<_, Infallible>(Response::new(format!("hello {}", req.uri().path())))
+            });
+        tokio::spawn(HyperBackend::new(listener).serve(service));
+
+        let res = get(addr, "/again").await;
+        assert!(res.ends_with("\r\n\r\nhello /again"));
+    }
+}
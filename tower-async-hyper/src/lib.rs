@@ -7,6 +7,25 @@
 //!
 //! [`tower_async_http::map_request_body::MapRequestBodyLayer`]: https://docs.rs/tower-async-http/latest/tower_async_http/map_request_body/struct.MapRequestBodyLayer.html
 //!
+//! # Informational responses
+//!
+//! `hyper` (1.x) offers no server API to send informational (`1xx`) responses, such as
+//! `103 Early Hints`, ahead of the final response. The bridge therefore doesn't insert an
+//! `EarlyHints` extension into requests, and the [`tower_async_http::early_hints`] middleware
+//! falls back to adding the hinted `Link` headers to the final response instead.
+//!
+//! [`tower_async_http::early_hints`]: https://docs.rs/tower-async-http/latest/tower_async_http/early_hints/index.html
+//!
+//! # Server backends
+//!
+//! With the `backend` feature enabled, the [`backend`] module abstracts serving a service stack
+//! over the server backend, and serves it with `hyper` over TCP. No HTTP/3 backend is provided.
+//!
+//! # Accept loops
+//!
+//! With the `accept` feature enabled, the [`accept`] module offers middleware for accept loops,
+//! limiting the rate of new connections and the number of open connections.
+//!
 //! # Example
 //!
 //! ```rust,no_run
@@ -77,3 +96,12 @@ pub use service::{BoxFuture, HyperServiceWrapper, TowerHyperServiceExt};
 
 mod body;
 pub use body::Body as HyperBody;
+
+#[cfg(feature = "accept")]
+pub mod accept;
+
+#[cfg(feature = "backend")]
+pub mod backend;
+
+#[cfg(feature = "upgrade")]
+pub mod upgrade;
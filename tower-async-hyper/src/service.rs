@@ -28,7 +28,7 @@ where
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 /// A wrapper around a [`tower_async::Service`] that implements [`hyper::service::Service`].
 ///
 /// [`tower_async::Service`]: https://docs.rs/tower-async/latest/tower_async/trait.Service.html
@@ -37,6 +37,14 @@ pub struct HyperServiceWrapper<S> {
     service: Arc<S>,
 }
 
+impl<S> Clone for HyperServiceWrapper<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+        }
+    }
+}
+
 impl<S, Request> HyperService<Request> for HyperServiceWrapper<S>
 where
     S: Service<Request, call(): Send> + Send + Sync + 'static,
@@ -88,6 +96,17 @@ mod test {
         inner_test_hyper_service(hyper_service).await;
     }
 
+    #[tokio::test]
+    async fn test_boxed_hyper_service() {
+        let service = tower_async::util::BoxSendService::new(
+            tower_async::ServiceBuilder::new()
+                .timeout(std::time::Duration::from_secs(5))
+                .service_fn(|req: &'static str| async move { Ok::<_, Infallible>(req) }),
+        );
+        let hyper_service = service.into_hyper_service();
+        inner_test_hyper_service(hyper_service).await;
+    }
+
     async fn inner_test_hyper_service<H>(hyper_service: H)
     where
         H: HyperService<&'static str, Response = &'static str>,
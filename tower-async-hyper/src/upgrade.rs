@@ -0,0 +1,180 @@
+//! Middleware bridging `hyper` connection upgrades to `tower-async-http`.
+//!
+//! The [`HyperUpgrade`] middleware inserts an [`OnUpgrade`] extension into every request,
+//! resolving to the connection once `hyper` upgraded it, such that middleware like
+//! [`WebSocketUpgrade`] can take over connections served by `hyper`.
+//!
+//! Connections have to be served with upgrades enabled, as [`HyperBackend`] does, or with
+//! `serve_connection_with_upgrades` of the `hyper-util` server builder.
+//!
+//! [`OnUpgrade`]: tower_async_http::ws::OnUpgrade
+//! [`WebSocketUpgrade`]: tower_async_http::ws::WebSocketUpgrade
+//! [`HyperBackend`]: crate::backend::HyperBackend
+//!
+//! # Example
+//!
+//! ```
+//! use http::{Request, Response};
+//! use hyper::body::Incoming;
+//! use std::convert::Infallible;
+//! use tower_async::{BoxError, ServiceBuilder};
+//! use tower_async_http::ws::{WebSocket, WebSocketUpgradeLayer, WsMessage};
+//! use tower_async_hyper::upgrade::HyperUpgradeLayer;
+//!
+//! async fn echo(message: WsMessage) -> Result<Option<WsMessage>, BoxError> {
+//!     Ok(Some(message))
+//! }
+//!
+//! async fn handle(_: Request<Incoming>) -> Result<Response<String>, Infallible> {
+//!     Ok(Response::new(String::from("hello")))
+//! }
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(HyperUpgradeLayer::new())
+//!     .layer(WebSocketUpgradeLayer::new(|ws: WebSocket| {
+//!         ws.serve(tower_async::service_fn(echo))
+//!     }))
+//!     .service_fn(handle);
+//! ```
+
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use std::io;
+use tower_async_http::ws::OnUpgrade;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Layer that applies the [`HyperUpgrade`] middleware.
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct HyperUpgradeLayer;
+
+impl HyperUpgradeLayer {
+    /// Create a new [`HyperUpgradeLayer`].
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for HyperUpgradeLayer {
+    type Service = HyperUpgrade<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HyperUpgrade::new(inner)
+    }
+}
+
+/// Middleware inserting an [`OnUpgrade`] extension, resolving to the connection upgraded
+/// by `hyper`, into every request.
+///
+/// See the [module docs](self) for more details.
+///
+/// [`OnUpgrade`]: tower_async_http::ws::OnUpgrade
+#[derive(Debug, Clone)]
+pub struct HyperUpgrade<S> {
+    inner: S,
+}
+
+impl<S> HyperUpgrade<S> {
+    /// Create a new [`HyperUpgrade`] middleware.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a [`HyperUpgrade`] middleware.
+    pub fn layer() -> HyperUpgradeLayer {
+        HyperUpgradeLayer::new()
+    }
+
+    /// Gets a reference to the underlying service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the underlying service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for HyperUpgrade<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, mut req: Request<B>) -> Result<Self::Response, Self::Error> {
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        req.extensions_mut().insert(OnUpgrade::new(async move {
+            on_upgrade.await.map(TokioIo::new).map_err(io::Error::other)
+        }));
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::TowerHyperServiceExt;
+    use hyper::body::Incoming;
+    use hyper_util::{rt::TokioExecutor, server::conn::auto::Builder};
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tower_async_http::ws::{WebSocket, WebSocketUpgradeLayer, WsMessage};
+
+    #[tokio::test]
+    async fn upgrades_connections_served_by_hyper() {
+        let service = tower_async::ServiceBuilder::new()
+            .layer(HyperUpgradeLayer::new())
+            .layer(WebSocketUpgradeLayer::new(|ws: WebSocket| {
+                ws.serve(tower_async::service_fn(|message: WsMessage| async move {
+                    Ok::<_, Infallible>(Some(message))
+                }))
+            }))
+            .service_fn(|_: Request<Incoming>| async {
+                Ok::<_, Infallible>(hyper::Response::new(String::new()))
+            });
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(server), service.into_hyper_service())
+                .await
+                .unwrap();
+        });
+
+        client
+            .write_all(
+                b"GET /chat HTTP/1.1\r\n\
+                  host: localhost\r\n\
+                  upgrade: websocket\r\n\
+                  connection: upgrade\r\n\
+                  sec-websocket-version: 13\r\n\
+                  sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(client.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(head.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        // a masked text frame, echoed back unmasked
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | 5];
+        frame.extend_from_slice(&mask);
+        frame.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        client.write_all(&frame).await.unwrap();
+
+        let mut echo = [0; 7];
+        client.read_exact(&mut echo).await.unwrap();
+        assert_eq!(&echo, b"\x81\x05hello");
+    }
+}
@@ -242,3 +242,33 @@ where
         (**self).call(request)
     }
 }
+
+impl<S, Request> Service<Request> for std::sync::Arc<S>
+where
+    S: Service<Request> + ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call(
+        &self,
+        request: Request,
+    ) -> impl std::future::Future<Output = Result<Self::Response, Self::Error>> {
+        (**self).call(request)
+    }
+}
+
+impl<S, Request> Service<Request> for std::rc::Rc<S>
+where
+    S: Service<Request> + ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call(
+        &self,
+        request: Request,
+    ) -> impl std::future::Future<Output = Result<Self::Response, Self::Error>> {
+        (**self).call(request)
+    }
+}
@@ -520,3 +520,24 @@ where
         }
     }
 }
+
+#[cfg(feature = "snapshot")]
+impl<B, Error> ResponseTester<http::Response<B>, Error>
+where
+    B: http_body::Body,
+    B::Error: std::fmt::Debug,
+    Error: std::fmt::Debug,
+{
+    /// Asserts that the response matches the golden file with the given name,
+    /// see [`crate::snapshot::Snapshot::assert_response`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the response is an error or if it does not match the golden file.
+    pub async fn expect_snapshot(self, snapshot: &crate::snapshot::Snapshot, name: &str) {
+        match self.result {
+            Ok(response) => snapshot.assert_response(name, response).await,
+            Err(err) => panic!("expected response, got error: {:?}", err),
+        }
+    }
+}
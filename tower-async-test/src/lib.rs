@@ -42,10 +42,17 @@
 //!         .expect_response("pong");
 //! }
 //! ```
+//!
+//! With the `snapshot` feature enabled, the [`snapshot`] module provides golden-file
+//! snapshot assertions for HTTP responses, which can also be used to check the response
+//! of a test using [`crate::builder::ResponseTester::expect_snapshot`].
 
 pub mod builder;
 pub mod mock;
 
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+
 pub use builder::Builder;
 
 #[cfg(test)]
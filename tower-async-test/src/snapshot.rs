@@ -0,0 +1,356 @@
+//! Golden-file snapshot assertions for HTTP responses.
+//!
+//! A [`Snapshot`] renders an [`http::Response`] into a canonical text format and compares it
+//! against a golden file committed next to the tests. Changes in the behavior of a middleware
+//! then show up as a diff of the golden files, which makes them easy to review.
+//!
+//! The rendered format contains the status line, the headers sorted by name, the body and,
+//! if present, the trailers:
+//!
+//! ```text
+//! HTTP/1.1 200 OK
+//! content-type: text/plain
+//! x-request-id: [redacted]
+//!
+//! hello world
+//! ```
+//!
+//! Bodies which are not valid UTF-8 are rendered as a hex dump. Headers whose values differ
+//! from run to run, such as request ids or dates, can be [redacted](Snapshot::redact_header).
+//!
+//! # Updating golden files
+//!
+//! If the rendered response does not match the golden file, or the golden file does not
+//! exist yet, the assertion panics and shows the difference. Running the tests with the
+//! `TOWER_ASYNC_UPDATE_SNAPSHOTS` environment variable set to `1` instead (re)writes the
+//! golden files, which can then be reviewed and committed:
+//!
+//! ```text
+//! TOWER_ASYNC_UPDATE_SNAPSHOTS=1 cargo test
+//! ```
+//!
+//! # Example
+//!
+//! ```no_run
+//! use http::{header, Response};
+//! use tower_async_test::snapshot::Snapshot;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let snapshot = Snapshot::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots"))
+//!     .redact_header(header::DATE);
+//!
+//! let response = Response::builder()
+//!     .header(header::CONTENT_TYPE, "text/plain")
+//!     .header(header::DATE, "Tue, 15 Nov 1994 08:12:31 GMT")
+//!     .body("hello world".to_owned())
+//!     .unwrap();
+//!
+//! // compares against `tests/snapshots/hello_world.snap`
+//! snapshot.assert_response("hello_world", response).await;
+//! # }
+//! ```
+
+use std::{
+    fmt::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use http::{HeaderMap, HeaderName, Response};
+use http_body::Body;
+use http_body_util::BodyExt;
+
+/// The environment variable which, if set to `1`, makes [`Snapshot`] assertions
+/// (re)write the golden files instead of comparing against them.
+pub const UPDATE_ENV_VAR: &str = "TOWER_ASYNC_UPDATE_SNAPSHOTS";
+
+const REDACTED: &str = "[redacted]";
+
+/// Renders HTTP responses and compares them against golden files.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    dir: PathBuf,
+    redacted_headers: Vec<HeaderName>,
+    update: bool,
+}
+
+impl Snapshot {
+    /// Creates a new [`Snapshot`] storing its golden files in `dir`.
+    ///
+    /// Golden files are updated if the `TOWER_ASYNC_UPDATE_SNAPSHOTS`
+    /// environment variable is set to `1`, see [`Snapshot::update`].
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            redacted_headers: Vec::new(),
+            update: std::env::var_os(UPDATE_ENV_VAR).is_some_and(|value| value == "1"),
+        }
+    }
+
+    /// Replaces the values of the given header with `[redacted]`.
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        self.redacted_headers.push(name);
+        self
+    }
+
+    /// Sets whether golden files are (re)written instead of compared against.
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Returns the path of the golden file of the snapshot with the given name.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.snap"))
+    }
+
+    /// Renders the response into the canonical text format of the golden files.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body of the response fails.
+    pub async fn render<B>(&self, response: Response<B>) -> String
+    where
+        B: Body,
+        B::Error: fmt::Debug,
+    {
+        let (parts, body) = response.into_parts();
+        let collected = body.collect().await.expect("response body failed");
+        let trailers = collected.trailers().cloned();
+        let body = collected.to_bytes();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{:?} {}", parts.version, parts.status);
+        self.render_headers(&mut out, &parts.headers);
+        out.push('\n');
+        render_body(&mut out, &body);
+        if let Some(trailers) = trailers {
+            out.push_str("\n-- trailers --\n");
+            self.render_headers(&mut out, &trailers);
+        }
+        out
+    }
+
+    /// Renders the response and compares it against the golden file with the given name,
+    /// or (re)writes the golden file if [updating](Snapshot::update) is enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rendered response does not match the golden file,
+    /// or if the golden file could not be read or written.
+    pub async fn assert_response<B>(&self, name: &str, response: Response<B>)
+    where
+        B: Body,
+        B::Error: fmt::Debug,
+    {
+        let actual = self.render(response).await;
+        self.assert_rendered(name, &actual);
+    }
+
+    fn assert_rendered(&self, name: &str, actual: &str) {
+        let path = self.path(name);
+        if self.update {
+            write_golden_file(&path, actual);
+            return;
+        }
+
+        let expected = match std::fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => panic!(
+                "snapshot `{name}` does not exist at {}, run with {UPDATE_ENV_VAR}=1 to create it:\n\n{actual}",
+                path.display(),
+            ),
+            Err(err) => panic!("failed to read snapshot {}: {err}", path.display()),
+        };
+
+        if expected != actual {
+            panic!(
+                "snapshot `{name}` at {} does not match, run with {UPDATE_ENV_VAR}=1 to update it:\n\n{}",
+                path.display(),
+                diff(&expected, actual),
+            );
+        }
+    }
+
+    fn render_headers(&self, out: &mut String, headers: &HeaderMap) {
+        let mut names: Vec<_> = headers.keys().collect();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in names {
+            for value in headers.get_all(name) {
+                if self.redacted_headers.contains(name) {
+                    let _ = writeln!(out, "{name}: {REDACTED}");
+                } else {
+                    match value.to_str() {
+                        Ok(value) => {
+                            let _ = writeln!(out, "{name}: {value}");
+                        }
+                        Err(_) => {
+                            let _ = writeln!(out, "{name}: {:?}", value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn render_body(out: &mut String, body: &[u8]) {
+    match std::str::from_utf8(body) {
+        Ok(text) => {
+            out.push_str(text);
+            if !text.is_empty() && !text.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        Err(_) => {
+            let _ = writeln!(out, "[binary, {} bytes]", body.len());
+            for (i, line) in body.chunks(16).enumerate() {
+                let _ = write!(out, "{:08x}:", i * 16);
+                for byte in line {
+                    let _ = write!(out, " {byte:02x}");
+                }
+                out.push('\n');
+            }
+        }
+    }
+}
+
+fn write_golden_file(path: &Path, contents: &str) {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .unwrap_or_else(|err| panic!("failed to create {}: {err}", dir.display()));
+    }
+    std::fs::write(path, contents)
+        .unwrap_or_else(|err| panic!("failed to write snapshot {}: {err}", path.display()));
+}
+
+/// Line by line diff, good enough to spot what changed in a small response.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+
+    let mut out = String::from("--- expected\n+++ actual\n");
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {
+                let _ = writeln!(out, " {e}");
+            }
+            (e, a) => {
+                if let Some(e) = e {
+                    let _ = writeln!(out, "-{e}");
+                }
+                if let Some(a) = a {
+                    let _ = writeln!(out, "+{a}");
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+    use http::{header, HeaderValue, StatusCode};
+    use http_body_util::Full;
+
+    fn snapshot_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "tower-async-test-snapshot-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn response(body: &'static [u8]) -> Response<Full<Bytes>> {
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("x-request-id", "9f1c")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::VARY, "accept")
+            .header(header::VARY, "accept-encoding")
+            .body(Full::new(Bytes::from_static(body)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn render_is_canonical() {
+        let snapshot = Snapshot::new(snapshot_dir("render"))
+            .redact_header(HeaderName::from_static("x-request-id"));
+
+        assert_eq!(
+            snapshot.render(response(b"not found")).await,
+            "HTTP/1.1 404 Not Found\n\
+             content-type: text/plain\n\
+             vary: accept\n\
+             vary: accept-encoding\n\
+             x-request-id: [redacted]\n\
+             \n\
+             not found\n"
+        );
+
+        let mut binary = response(b"\x1f\x8b\x08\x00");
+        binary
+            .headers_mut()
+            .insert("x-request-id", HeaderValue::from_static("other"));
+        assert!(snapshot
+            .render(binary)
+            .await
+            .ends_with("\n[binary, 4 bytes]\n00000000: 1f 8b 08 00\n"));
+    }
+
+    #[tokio::test]
+    async fn update_then_compare() {
+        let dir = snapshot_dir("update");
+        let snapshot = Snapshot::new(&dir).update(true);
+        snapshot.assert_response("not_found", response(b"v1")).await;
+        assert!(snapshot.path("not_found").exists());
+
+        let snapshot = snapshot.update(false);
+        snapshot.assert_response("not_found", response(b"v1")).await;
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn expect_snapshot_of_layer_response() {
+        let dir = snapshot_dir("builder");
+        let snapshot = Snapshot::new(&dir).update(true);
+
+        crate::Builder::new("ping")
+            .send_response(response(b"pong"))
+            .expect_request("ping")
+            .test(tower_async_layer::Identity::new())
+            .await
+            .expect_snapshot(&snapshot, "pong")
+            .await;
+
+        let expected = std::fs::read_to_string(snapshot.path("pong")).unwrap();
+        assert!(expected.starts_with("HTTP/1.1 404 Not Found\n"));
+        assert!(expected.ends_with("\n\npong\n"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "-v1\n+v2")]
+    async fn mismatch_panics_with_diff() {
+        let snapshot = Snapshot::new(snapshot_dir("mismatch")).update(true);
+        snapshot.assert_response("not_found", response(b"v1")).await;
+
+        let snapshot = snapshot.update(false);
+        snapshot.assert_response("not_found", response(b"v2")).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "does not exist")]
+    async fn missing_golden_file_panics() {
+        let snapshot = Snapshot::new(snapshot_dir("missing")).update(false);
+        snapshot.assert_response("not_found", response(b"v1")).await;
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::net::TcpListener;
+use tokio_util::codec::LinesCodec;
+use tower_async::{codec::Server, BoxError, Service, ServiceBuilder, ServiceExt};
+
+/// A key / value store speaking a line based protocol:
+///
+/// - `GET <key>` answers with `VALUE <value>` or `NOT_FOUND`;
+/// - `SET <key> <value>` answers with `OK`;
+/// - anything else answers with `ERROR <reason>`.
+#[derive(Debug, Clone, Default)]
+struct KeyValueStore {
+    db: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Service<String> for KeyValueStore {
+    type Response = String;
+    type Error = Infallible;
+
+    async fn call(&self, line: String) -> Result<Self::Response, Self::Error> {
+        let mut parts = line.splitn(3, ' ');
+        let response = match (parts.next(), parts.next(), parts.next()) {
+            (Some("GET"), Some(key), None) => match self.db.lock().unwrap().get(key) {
+                Some(value) => format!("VALUE {value}"),
+                None => "NOT_FOUND".to_owned(),
+            },
+            (Some("SET"), Some(key), Some(value)) => {
+                self.db
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_owned(), value.to_owned());
+                "OK".to_owned()
+            }
+            _ => "ERROR unknown command".to_owned(),
+        };
+        Ok(response)
+    }
+}
+
+/// Answers errors, such as timeouts, with an `ERROR <reason>` line
+/// instead of closing the connection.
+async fn error_to_response(result: Result<String, BoxError>) -> Result<String, BoxError> {
+    Ok(result.unwrap_or_else(|err| format!("ERROR {err}")))
+}
+
+#[tokio::main]
+async fn main() {
+    let store = KeyValueStore::default();
+
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 6380));
+    let listener = TcpListener::bind(addr).await.unwrap();
+    println!("listening on {addr}, try `nc localhost 6380`");
+
+    loop {
+        let (stream, peer) = listener.accept().await.unwrap();
+
+        // The same middleware as for HTTP services can be used,
+        // here a timeout, whose error is turned into a response.
+        let service = ServiceBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .service(store.clone())
+            .then(error_to_response);
+
+        tokio::spawn(async move {
+            if let Err(err) = Server::new(service).serve(stream, LinesCodec::new()).await {
+                eprintln!("connection with {peer} failed: {err}");
+            }
+        });
+    }
+}
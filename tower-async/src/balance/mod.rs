@@ -0,0 +1,495 @@
+//! Middleware that balances requests over a set of discovered endpoints.
+//!
+//! [`Balance`] sends every request to one of the services found by a [`Discover`] source,
+//! using the "power of two choices" algorithm: two endpoints are picked at random, and the
+//! request is sent to the endpoint with the fewest requests in flight. This spreads the load
+//! about as well as comparing all endpoints, at a fraction of the cost.
+//!
+//! Changes in the set of endpoints are applied before each request is balanced. Requests
+//! received while no endpoints are known wait until an endpoint is discovered. In large
+//! deployments, the endpoints of each client can be limited to a stable subset of all
+//! endpoints by discovering them through a [`Subset`].
+//!
+//! # Outlier detection
+//!
+//! Endpoints which fail or slow down can be ejected from the balancer for a while, using an
+//! [`OutlierPolicy`], such as the [`FailureRate`] policy. Once the ejection time is over,
+//! an endpoint is reintroduced gradually: during its [ramp up](Balance::ramp_up) period it
+//! looks more loaded than it is, such that it receives a growing share of the requests.
+//! To keep serving requests when many endpoints fail at once, no more than
+//! [a share](Balance::max_ejected_percent) of the endpoints is ejected at the same time.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{
+//!     balance::{Balance, FailureRate},
+//!     discover::ServiceList,
+//!     service_fn, Service,
+//! };
+//!
+//! fn backend(name: &'static str) -> impl Service<(), Response = &'static str, Error = Infallible> {
+//!     service_fn(move |()| async move { Ok(name) })
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! let balance = Balance::new(ServiceList::new([backend("a"), backend("b")]))
+//!     .outlier_detection(FailureRate::new(0.5).base_ejection(Duration::from_secs(10)));
+//!
+//! let response = balance.call(()).await?;
+//! assert!(response == "a" || response == "b");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Discover`]: crate::discover::Discover
+//! [`Subset`]: crate::discover::Subset
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use futures_util::FutureExt;
+use tokio::time::Instant;
+use tower_async_service::Service;
+
+use crate::{
+    discover::{Change, Discover},
+    util::rng::{HasherRng, Rng},
+    BoxError,
+};
+
+mod outlier;
+
+pub use self::outlier::{FailureRate, FailureRateStats, NeverEject, OutlierPolicy};
+
+/// Balances requests over the endpoints found by a [`Discover`] source.
+///
+/// See the [module docs](self) for more details.
+///
+/// [`Discover`]: crate::discover::Discover
+pub struct Balance<D, P = NeverEject>
+where
+    D: Discover,
+    P: OutlierPolicy,
+{
+    discover: D,
+    policy: P,
+    state: Mutex<State<D::Key, D::Service, P::Stats>>,
+    max_ejected_percent: usize,
+    ramp_up: Duration,
+}
+
+struct State<K, S, T> {
+    slots: Vec<Slot<K, S, T>>,
+    rng: HasherRng,
+}
+
+struct Slot<K, S, T> {
+    key: K,
+    endpoint: Arc<Endpoint<S>>,
+    stats: T,
+    ejected_until: Option<Instant>,
+    reintroduced_at: Option<Instant>,
+}
+
+struct Endpoint<S> {
+    service: S,
+    in_flight: AtomicUsize,
+}
+
+impl<D> Balance<D>
+where
+    D: Discover,
+{
+    /// Creates a new [`Balance`], balancing requests over the endpoints found by `discover`.
+    pub fn new(discover: D) -> Self {
+        Balance {
+            discover,
+            policy: NeverEject,
+            state: Mutex::new(State {
+                slots: Vec::new(),
+                rng: HasherRng::new(),
+            }),
+            max_ejected_percent: 50,
+            ramp_up: Duration::from_secs(10),
+        }
+    }
+}
+
+impl<D, P> Balance<D, P>
+where
+    D: Discover,
+    P: OutlierPolicy,
+{
+    /// Set the [`OutlierPolicy`] which decides when an endpoint is ejected.
+    ///
+    /// By default endpoints are never ejected.
+    pub fn outlier_detection<NewP>(self, policy: NewP) -> Balance<D, NewP>
+    where
+        NewP: OutlierPolicy,
+    {
+        let state = self.state.into_inner().unwrap();
+        let slots = state
+            .slots
+            .into_iter()
+            .map(|slot| Slot {
+                key: slot.key,
+                endpoint: slot.endpoint,
+                stats: NewP::Stats::default(),
+                ejected_until: None,
+                reintroduced_at: None,
+            })
+            .collect();
+        Balance {
+            discover: self.discover,
+            policy,
+            state: Mutex::new(State {
+                slots,
+                rng: state.rng,
+            }),
+            max_ejected_percent: self.max_ejected_percent,
+            ramp_up: self.ramp_up,
+        }
+    }
+
+    /// Sets the maximum share of the endpoints which can be ejected at the same time,
+    /// in percent, defaults to 50.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percent` is larger than 100.
+    pub fn max_ejected_percent(mut self, percent: usize) -> Self {
+        assert!(percent <= 100, "max_ejected_percent must be at most 100");
+        self.max_ejected_percent = percent;
+        self
+    }
+
+    /// Sets the duration over which a reintroduced endpoint gradually receives its full share
+    /// of requests, defaults to 10 seconds.
+    ///
+    /// A duration of zero reintroduces endpoints at once.
+    pub fn ramp_up(mut self, duration: Duration) -> Self {
+        self.ramp_up = duration;
+        self
+    }
+
+    /// Returns the number of known endpoints, including the ejected ones.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().slots.len()
+    }
+
+    /// Returns `true` if no endpoints are known.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of endpoints which are ejected right now.
+    pub fn ejected(&self) -> usize {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap();
+        state
+            .slots
+            .iter()
+            .filter(|slot| slot.is_ejected(now))
+            .count()
+    }
+
+    /// Get a reference to the inner [`Discover`] source.
+    ///
+    /// [`Discover`]: crate::discover::Discover
+    pub fn get_ref(&self) -> &D {
+        &self.discover
+    }
+
+    /// Applies a discovered change to the set of endpoints.
+    fn apply(&self, change: Change<D::Key, D::Service>) {
+        let mut state = self.state.lock().unwrap();
+        let index = state
+            .slots
+            .iter()
+            .position(|slot| &slot.key == change.key());
+        match (change, index) {
+            (Change::Insert(key, service), index) => {
+                let slot = Slot {
+                    key,
+                    endpoint: Arc::new(Endpoint {
+                        service,
+                        in_flight: AtomicUsize::new(0),
+                    }),
+                    stats: P::Stats::default(),
+                    ejected_until: None,
+                    reintroduced_at: None,
+                };
+                match index {
+                    Some(index) => state.slots[index] = slot,
+                    None => state.slots.push(slot),
+                }
+            }
+            (Change::Remove(_), Some(index)) => {
+                state.slots.swap_remove(index);
+            }
+            (Change::Remove(_), None) => {}
+        }
+    }
+
+    /// Applies all changes which were discovered already.
+    fn update(&self) -> Result<(), BoxError>
+    where
+        D::Error: Into<BoxError>,
+    {
+        while let Some(change) = self.discover.discover().now_or_never() {
+            self.apply(change.map_err(Into::into)?);
+        }
+        Ok(())
+    }
+
+    /// Picks the less loaded of two random endpoints which aren't ejected,
+    /// or of all endpoints if all of them are ejected.
+    fn pick(&self) -> Option<Arc<Endpoint<D::Service>>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let State { slots, rng } = &mut *state;
+
+        for slot in slots.iter_mut() {
+            if slot.ejected_until.is_some_and(|until| until <= now) {
+                slot.ejected_until = None;
+                slot.reintroduced_at = Some(now);
+            }
+            if slot
+                .reintroduced_at
+                .is_some_and(|at| now.duration_since(at) >= self.ramp_up)
+            {
+                slot.reintroduced_at = None;
+            }
+        }
+
+        let mut candidates: Vec<_> = slots.iter().filter(|slot| !slot.is_ejected(now)).collect();
+        if candidates.is_empty() {
+            candidates = slots.iter().collect();
+        }
+        let picked = match candidates.len() {
+            0 => return None,
+            1 => candidates[0],
+            len => {
+                let a = rng.next_range(0..len as u64) as usize;
+                let b = (a + 1 + rng.next_range(0..len as u64 - 1) as usize) % len;
+                let (a, b) = (candidates[a], candidates[b]);
+                if self.load(a, now) <= self.load(b, now) {
+                    a
+                } else {
+                    b
+                }
+            }
+        };
+        Some(picked.endpoint.clone())
+    }
+
+    /// The load of an endpoint, inflated while it is being reintroduced.
+    fn load(&self, slot: &Slot<D::Key, D::Service, P::Stats>, now: Instant) -> f64 {
+        let load = (slot.endpoint.in_flight.load(Ordering::SeqCst) + 1) as f64;
+        match slot.reintroduced_at {
+            Some(at) if !self.ramp_up.is_zero() => {
+                let weight = now.duration_since(at).as_secs_f64() / self.ramp_up.as_secs_f64();
+                load / weight.clamp(0.1, 1.0)
+            }
+            _ => load,
+        }
+    }
+
+    /// Records the outcome of a call, ejecting the endpoint if the policy says so.
+    fn record(&self, endpoint: &Arc<Endpoint<D::Service>>, success: bool, latency: Duration) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let ejected = state
+            .slots
+            .iter()
+            .filter(|slot| slot.is_ejected(now))
+            .count();
+        let len = state.slots.len();
+        let Some(slot) = state
+            .slots
+            .iter_mut()
+            .find(|slot| Arc::ptr_eq(&slot.endpoint, endpoint))
+        else {
+            // the endpoint was removed or replaced in the meantime
+            return;
+        };
+
+        let Some(duration) = self.policy.record(&mut slot.stats, success, latency) else {
+            return;
+        };
+        if !slot.is_ejected(now) && (ejected + 1) * 100 <= len * self.max_ejected_percent {
+            slot.ejected_until = Some(now + duration);
+            slot.reintroduced_at = None;
+        }
+    }
+}
+
+impl<K, S, T> Slot<K, S, T> {
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until.is_some_and(|until| until > now)
+    }
+}
+
+impl<D, P, Request> Service<Request> for Balance<D, P>
+where
+    D: Discover,
+    D::Error: Into<BoxError>,
+    D::Service: Service<Request>,
+    <D::Service as Service<Request>>::Error: Into<BoxError>,
+    P: OutlierPolicy,
+{
+    type Response = <D::Service as Service<Request>>::Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        self.update()?;
+        let endpoint = loop {
+            if let Some(endpoint) = self.pick() {
+                break endpoint;
+            }
+            let change = self.discover.discover().await.map_err(Into::into)?;
+            self.apply(change);
+            self.update()?;
+        };
+
+        endpoint.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight(&endpoint.in_flight);
+        let start = Instant::now();
+        let result = endpoint.service.call(request).await;
+        drop(guard);
+
+        self.record(&endpoint, result.is_ok(), start.elapsed());
+        result.map_err(Into::into)
+    }
+}
+
+/// Decrements the number of requests in flight of an endpoint when dropped.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<D, P> fmt::Debug for Balance<D, P>
+where
+    D: Discover + fmt::Debug,
+    P: OutlierPolicy + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("discover", &self.discover)
+            .field("policy", &self.policy)
+            .field("endpoints", &self.len())
+            .field("max_ejected_percent", &self.max_ejected_percent)
+            .field("ramp_up", &self.ramp_up)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{collections::HashMap, convert::Infallible};
+
+    use tokio::sync::watch;
+
+    use crate::{
+        discover::{ServiceList, WatchDiscover},
+        service_fn,
+    };
+
+    fn backend(
+        name: &'static str,
+        healthy: bool,
+    ) -> impl Service<(), Response = &'static str, Error = &'static str> {
+        service_fn(move |()| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if healthy {
+                Ok(name)
+            } else {
+                Err(name)
+            }
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn prefers_least_loaded() {
+        let balance = Balance::new(ServiceList::new([backend("a", true), backend("b", true)]));
+        for _ in 0..10 {
+            let (first, second) = tokio::join!(balance.call(()), balance.call(()));
+            assert_ne!(first.unwrap(), second.unwrap());
+        }
+        assert_eq!(balance.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ejects_outliers() {
+        let balance = Balance::new(ServiceList::new([backend("a", true), backend("b", false)]))
+            .outlier_detection(
+                FailureRate::new(0.5)
+                    .window(2)
+                    .base_ejection(Duration::from_secs(30)),
+            )
+            .ramp_up(Duration::ZERO);
+
+        let mut failures = 0;
+        while balance.ejected() == 0 {
+            failures += balance.call(()).await.is_err() as usize;
+            assert!(failures <= 2);
+        }
+        for _ in 0..10 {
+            assert_eq!(balance.call(()).await.unwrap(), "a");
+        }
+
+        // the outlier is reintroduced once its ejection time is over
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(balance.ejected(), 0);
+        let mut calls = 0;
+        while balance.call(()).await.is_ok() {
+            calls += 1;
+            assert!(calls < 100);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_ejected_endpoints() {
+        let balance = Balance::new(ServiceList::new([backend("a", false)]))
+            .outlier_detection(FailureRate::new(0.5).window(1));
+        for _ in 0..5 {
+            assert!(balance.call(()).await.is_err());
+        }
+        assert_eq!(balance.ejected(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_endpoints() {
+        let (tx, rx) = watch::channel(HashMap::new());
+        let balance = Balance::new(WatchDiscover::by_key(rx));
+
+        let pending = tokio::time::timeout(Duration::from_secs(1), balance.call(())).await;
+        assert!(pending.is_err());
+
+        tx.send_replace(HashMap::from([(
+            1,
+            service_fn(|()| async { Ok::<_, Infallible>(1) }),
+        )]));
+        assert_eq!(balance.call(()).await.unwrap(), 1);
+
+        tx.send_replace(HashMap::new());
+        let pending = tokio::time::timeout(Duration::from_secs(1), balance.call(())).await;
+        assert!(pending.is_err());
+        assert!(balance.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// Decides when an endpoint of a [`Balance`] is an outlier, such that it is ejected from the
+/// balancer for a while.
+///
+/// The balancer keeps [`Stats`] for every endpoint, and records the outcome of every call to
+/// the endpoint in them. The stats of an endpoint are reset when it is discovered again.
+///
+/// [`Balance`]: super::Balance
+/// [`Stats`]: OutlierPolicy::Stats
+pub trait OutlierPolicy {
+    /// The statistics kept for every endpoint.
+    type Stats: Default;
+
+    /// Record the outcome of a call to an endpoint, which succeeded if `success` is `true`
+    /// and took `latency`.
+    ///
+    /// Returns the duration for which the endpoint is to be ejected,
+    /// or `None` to keep it in the balancer.
+    fn record(&self, stats: &mut Self::Stats, success: bool, latency: Duration)
+        -> Option<Duration>;
+}
+
+/// An [`OutlierPolicy`] which never ejects an endpoint.
+///
+/// This is the policy used by a [`Balance`] unless configured otherwise.
+///
+/// [`Balance`]: super::Balance
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeverEject;
+
+impl OutlierPolicy for NeverEject {
+    type Stats = ();
+
+    fn record(&self, _stats: &mut (), _success: bool, _latency: Duration) -> Option<Duration> {
+        None
+    }
+}
+
+/// An [`OutlierPolicy`] ejecting endpoints whose share of failed calls
+/// exceeds a threshold.
+///
+/// A call fails if it returned an error, or if it took longer than the
+/// [slow call](FailureRate::slow_call) threshold, when configured. The failure rate is
+/// computed over the last [`window`](FailureRate::window) calls, once that many calls were
+/// recorded.
+///
+/// An ejected endpoint is ejected for the [base ejection time](FailureRate::base_ejection)
+/// multiplied by the number of consecutive times it was ejected, up to the
+/// [maximum ejection time](FailureRate::max_ejection). Once an endpoint completes a window
+/// of calls below the threshold, its next ejection starts from the base ejection time again.
+#[derive(Debug, Clone)]
+pub struct FailureRate {
+    threshold: f64,
+    window: usize,
+    slow_call: Option<Duration>,
+    base_ejection: Duration,
+    max_ejection: Duration,
+}
+
+impl FailureRate {
+    /// Create a new [`FailureRate`] policy, ejecting endpoints of which at least `threshold`
+    /// (between 0 and 1) of the calls failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is not larger than 0 and at most 1.
+    pub fn new(threshold: f64) -> Self {
+        assert!(
+            threshold > 0.0 && threshold <= 1.0,
+            "threshold must be larger than 0 and at most 1"
+        );
+        Self {
+            threshold,
+            window: 20,
+            slow_call: None,
+            base_ejection: Duration::from_secs(30),
+            max_ejection: Duration::from_secs(300),
+        }
+    }
+
+    /// Sets the number of calls over which the failure rate is computed, defaults to 20.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    pub fn window(mut self, window: usize) -> Self {
+        assert!(window > 0, "window must be larger than 0");
+        self.window = window;
+        self
+    }
+
+    /// Counts calls taking longer than `latency` as failed, even if they succeeded.
+    ///
+    /// By default only calls which returned an error fail.
+    pub fn slow_call(mut self, latency: Duration) -> Self {
+        self.slow_call = Some(latency);
+        self
+    }
+
+    /// Sets the duration for which an endpoint is ejected the first time, defaults to 30 seconds.
+    pub fn base_ejection(mut self, duration: Duration) -> Self {
+        self.base_ejection = duration;
+        self
+    }
+
+    /// Sets the maximum duration for which an endpoint is ejected, defaults to 300 seconds.
+    pub fn max_ejection(mut self, duration: Duration) -> Self {
+        self.max_ejection = duration;
+        self
+    }
+}
+
+/// The [`Stats`](OutlierPolicy::Stats) of the [`FailureRate`] policy.
+#[derive(Debug, Clone, Default)]
+pub struct FailureRateStats {
+    outcomes: VecDeque<bool>,
+    failures: usize,
+    ejections: u32,
+}
+
+impl OutlierPolicy for FailureRate {
+    type Stats = FailureRateStats;
+
+    fn record(
+        &self,
+        stats: &mut FailureRateStats,
+        success: bool,
+        latency: Duration,
+    ) -> Option<Duration> {
+        let failed = !success || self.slow_call.is_some_and(|slow| latency > slow);
+        stats.outcomes.push_back(failed);
+        stats.failures += failed as usize;
+        if stats.outcomes.len() > self.window {
+            stats.failures -= stats.outcomes.pop_front().unwrap() as usize;
+        }
+        if stats.outcomes.len() < self.window {
+            return None;
+        }
+
+        if stats.failures as f64 / self.window as f64 >= self.threshold {
+            stats.ejections = stats.ejections.saturating_add(1);
+            stats.outcomes.clear();
+            stats.failures = 0;
+            Some(
+                self.base_ejection
+                    .saturating_mul(stats.ejections)
+                    .min(self.max_ejection),
+            )
+        } else {
+            stats.ejections = 0;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_rate() {
+        let policy = FailureRate::new(0.5)
+            .window(4)
+            .slow_call(Duration::from_millis(100))
+            .base_ejection(Duration::from_secs(10))
+            .max_ejection(Duration::from_secs(15));
+        let mut stats = FailureRateStats::default();
+        let fast = Duration::from_millis(10);
+        let slow = Duration::from_millis(200);
+
+        // the rate is only computed once the window is full
+        assert_eq!(policy.record(&mut stats, false, fast), None);
+        assert_eq!(policy.record(&mut stats, true, fast), None);
+        assert_eq!(policy.record(&mut stats, true, fast), None);
+        assert_eq!(policy.record(&mut stats, true, fast), None);
+        assert_eq!(policy.record(&mut stats, true, slow), None);
+        assert_eq!(
+            policy.record(&mut stats, false, fast),
+            Some(Duration::from_secs(10))
+        );
+
+        // consecutive ejections last longer, up to the maximum
+        for _ in 0..3 {
+            assert_eq!(policy.record(&mut stats, false, fast), None);
+        }
+        assert_eq!(
+            policy.record(&mut stats, false, fast),
+            Some(Duration::from_secs(15))
+        );
+
+        // a healthy window resets the ejection time
+        for _ in 0..4 {
+            assert_eq!(policy.record(&mut stats, true, fast), None);
+        }
+        assert_eq!(policy.record(&mut stats, false, fast), None);
+        assert_eq!(
+            policy.record(&mut stats, false, fast),
+            Some(Duration::from_secs(10))
+        );
+    }
+}
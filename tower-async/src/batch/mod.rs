@@ -0,0 +1,448 @@
+//! Middleware that coalesces requests into batches, calling the inner service once per batch.
+//!
+//! Many backends handle a batch of requests much more efficiently than the same requests one
+//! by one, such as a database inserting many rows in one statement, or a model computing the
+//! results of many inputs at once. [`Batch`] collects the requests of its callers into a
+//! `Vec<Request>`, and calls a service handling such a batch, which returns a `Vec<Response>`
+//! with one response for each request, in the same order. Each response is then sent back to
+//! the caller of its request.
+//!
+//! The batches are collected by a [`Worker`], which owns the service. A batch is sent to the
+//! service once it holds `max_size` requests, or once `max_latency` passed since its first
+//! request was received, whichever comes first. At most [`max_in_flight`] batches, one by
+//! default, are processed at the same time. Once that many are in flight, the worker stops
+//! receiving requests, such that callers wait for room in the channel to the worker.
+//!
+//! With the `nightly` feature enabled, `Batch::new` spawns the worker on the Tokio runtime
+//! by itself, and `BatchLayer` wraps services in a [`Batch`]. Otherwise [`Batch::pair`]
+//! returns the worker, which has to be driven by the caller.
+//!
+//! If the service fails, every request of the batch fails with a [`BatchError`], sharing the
+//! error of the service. If the worker is dropped, requests fail with a [`Closed`] error.
+//!
+//! [`max_in_flight`]: Worker::max_in_flight
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{batch::Batch, service_fn, Service};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! // squares a batch of numbers at once
+//! let square = service_fn(|batch: Vec<u64>| async move {
+//!     Ok::<_, Infallible>(batch.into_iter().map(|n| n * n).collect::<Vec<_>>())
+//! });
+//!
+//! let (batch, worker) = Batch::pair(square, 16, Duration::from_millis(5));
+//! tokio::spawn(worker.run());
+//!
+//! let (a, b) = tokio::join!(batch.call(3), batch.call(4));
+//! assert_eq!((a?, b?), (9, 16));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{error, fmt, sync::Arc, time::Duration};
+
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+use tower_async_service::Service;
+
+use crate::BoxError;
+
+#[cfg(feature = "nightly")]
+mod spawn;
+
+#[cfg(feature = "nightly")]
+pub use self::spawn::BatchLayer;
+
+/// A request sent to the [`Worker`], with the channel to send its response back on.
+struct Message<Request, Response> {
+    request: Request,
+    tx: oneshot::Sender<Result<Response, BoxError>>,
+}
+
+/// A cheap [`Clone`] handle which sends requests to the [`Worker`] collecting them in batches.
+///
+/// See the [module docs](self) for more details.
+pub struct Batch<Request, Response> {
+    tx: mpsc::Sender<Message<Request, Response>>,
+}
+
+impl<Request, Response> Batch<Request, Response> {
+    /// Creates a new [`Batch`] for the given service, and the [`Worker`] calling it.
+    ///
+    /// Batches hold at most `max_size` requests, and are sent to the service at most
+    /// `max_latency` after their first request was received. At most `max_size` requests
+    /// wait for the worker to receive them, after which callers wait for room in the channel.
+    /// The worker must be driven, for example by spawning [`Worker::run`], for requests
+    /// to be processed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_size` is 0.
+    pub fn pair<S>(
+        service: S,
+        max_size: usize,
+        max_latency: Duration,
+    ) -> (Self, Worker<S, Request, Response>)
+    where
+        S: Service<Vec<Request>, Response = Vec<Response>>,
+        S::Error: Into<BoxError>,
+    {
+        assert!(max_size > 0, "max_size must be larger than 0");
+        let (tx, rx) = mpsc::channel(max_size);
+        let worker = Worker {
+            service,
+            rx,
+            max_size,
+            max_latency,
+            max_in_flight: 1,
+        };
+        (Batch { tx }, worker)
+    }
+}
+
+impl<Request, Response> Service<Request> for Batch<Request, Response> {
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(Message { request, tx })
+            .await
+            .map_err(|_| Closed::new())?;
+        rx.await.map_err(|_| Closed::new())?
+    }
+}
+
+impl<Request, Response> Clone for Batch<Request, Response> {
+    fn clone(&self) -> Self {
+        Batch {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<Request, Response> fmt::Debug for Batch<Request, Response> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batch")
+            .field("capacity", &self.tx.max_capacity())
+            .finish()
+    }
+}
+
+/// The worker owning the service of a [`Batch`], collecting the requests it receives in
+/// batches and calling the service for each batch.
+///
+/// See the [module docs](self) for more details.
+pub struct Worker<S, Request, Response> {
+    service: S,
+    rx: mpsc::Receiver<Message<Request, Response>>,
+    max_size: usize,
+    max_latency: Duration,
+    max_in_flight: usize,
+}
+
+impl<S, Request, Response> Worker<S, Request, Response>
+where
+    S: Service<Vec<Request>, Response = Vec<Response>>,
+    S::Error: Into<BoxError>,
+{
+    /// Sets the maximum number of batches sent to the service at the same time.
+    ///
+    /// Defaults to 1, a batch is only sent once the previous one completed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be larger than 0");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Processes the requests sent by the [`Batch`] handles, until all of them are dropped
+    /// and the batches in flight are completed.
+    ///
+    /// The requests received once all handles are dropped are sent as a last batch right away.
+    pub async fn run(self) {
+        let Worker {
+            service,
+            mut rx,
+            max_size,
+            max_latency,
+            max_in_flight,
+        } = self;
+        let service = &service;
+        let mut in_flight = FuturesUnordered::new();
+        let mut batch = Vec::new();
+        let deadline = tokio::time::sleep(max_latency);
+        tokio::pin!(deadline);
+        let mut receiving = true;
+
+        loop {
+            tokio::select! {
+                // a batch is only collected once it can be sent to the service,
+                // until then the requests wait in the channel
+                message = rx.recv(), if receiving && in_flight.len() < max_in_flight => {
+                    match message {
+                        Some(message) => {
+                            if batch.is_empty() {
+                                deadline.as_mut().reset(Instant::now() + max_latency);
+                            }
+                            batch.push(message);
+                        }
+                        None => receiving = false,
+                    }
+                    if batch.len() >= max_size || (!receiving && !batch.is_empty()) {
+                        in_flight.push(call_batch(service, std::mem::take(&mut batch)));
+                    }
+                }
+                () = &mut deadline, if !batch.is_empty() => {
+                    in_flight.push(call_batch(service, std::mem::take(&mut batch)));
+                }
+                Some(()) = in_flight.next(), if !in_flight.is_empty() => {}
+                else => break,
+            }
+        }
+    }
+}
+
+/// Calls the service with a batch, and sends each response to the caller of its request.
+async fn call_batch<S, Request, Response>(service: &S, batch: Vec<Message<Request, Response>>)
+where
+    S: Service<Vec<Request>, Response = Vec<Response>>,
+    S::Error: Into<BoxError>,
+{
+    // the callers which are gone are no longer interested in the response
+    let (requests, txs): (Vec<_>, Vec<_>) = batch
+        .into_iter()
+        .filter(|message| !message.tx.is_closed())
+        .map(|Message { request, tx }| (request, tx))
+        .unzip();
+    if requests.is_empty() {
+        return;
+    }
+
+    let error = match service.call(requests).await {
+        Ok(responses) if responses.len() == txs.len() => {
+            for (tx, response) in txs.into_iter().zip(responses) {
+                let _ = tx.send(Ok(response));
+            }
+            return;
+        }
+        Ok(responses) => BatchError::new(
+            format!(
+                "batch of {} requests returned {} responses",
+                txs.len(),
+                responses.len()
+            )
+            .into(),
+        ),
+        Err(err) => BatchError::new(err.into()),
+    };
+    for tx in txs {
+        let _ = tx.send(Err(error.clone().into()));
+    }
+}
+
+impl<S, Request, Response> fmt::Debug for Worker<S, Request, Response>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("service", &self.service)
+            .field("max_size", &self.max_size)
+            .field("max_latency", &self.max_latency)
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+/// Error returned by [`Batch`] when the service failed for the batch containing the request,
+/// or didn't return one response for each request of the batch.
+///
+/// The error of the service is shared by all requests of the batch.
+#[derive(Debug, Clone)]
+pub struct BatchError {
+    inner: Arc<dyn error::Error + Send + Sync>,
+}
+
+impl BatchError {
+    fn new(inner: BoxError) -> Self {
+        BatchError {
+            inner: Arc::from(inner),
+        }
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "batch failed: {}", self.inner)
+    }
+}
+
+impl error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.inner)
+    }
+}
+
+/// Error returned by [`Batch`] when its [`Worker`] is gone,
+/// because it was dropped or panicked while processing the request.
+#[derive(Debug, Default)]
+pub struct Closed(pub(super) ());
+
+impl Closed {
+    /// Construct a new closed error.
+    pub fn new() -> Self {
+        Closed(())
+    }
+}
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("batch's worker closed unexpectedly")
+    }
+}
+
+impl error::Error for Closed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use tokio::sync::Semaphore;
+
+    use crate::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_by_size_and_latency() {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let (batch, worker) = Batch::pair(
+            service_fn({
+                let sizes = sizes.clone();
+                move |batch: Vec<u64>| {
+                    sizes.lock().unwrap().push(batch.len());
+                    async move { Ok::<_, Infallible>(batch.into_iter().map(|n| n * 2).collect()) }
+                }
+            }),
+            2,
+            Duration::from_secs(1),
+        );
+        tokio::spawn(worker.run());
+
+        let start = Instant::now();
+        let responses = futures_util::future::join_all((1..=5).map(|n| batch.call(n))).await;
+        assert_eq!(
+            responses
+                .into_iter()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+            [2, 4, 6, 8, 10]
+        );
+        assert_eq!(*sizes.lock().unwrap(), [2, 2, 1]);
+        // the last request waited for more requests to join its batch
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shares_errors() {
+        let (batch, worker) = Batch::pair(
+            service_fn(|batch: Vec<u64>| async move {
+                match batch.len() {
+                    2 => Err("overloaded"),
+                    _ => Ok(Vec::<u64>::new()),
+                }
+            }),
+            2,
+            Duration::from_secs(1),
+        );
+        tokio::spawn(worker.run());
+
+        let (a, b) = tokio::join!(batch.call(1), batch.call(2));
+        for err in [a.unwrap_err(), b.unwrap_err()] {
+            let err = err.downcast::<BatchError>().unwrap();
+            assert_eq!(err.to_string(), "batch failed: overloaded");
+        }
+
+        // the service must return a response for each request
+        let err = batch.call(3).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "batch failed: batch of 1 requests returned 0 responses"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_batches_in_flight() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(Semaphore::new(0));
+        let (batch, worker) = Batch::pair(
+            service_fn({
+                let calls = calls.clone();
+                let done = done.clone();
+                move |batch: Vec<u64>| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let done = done.clone();
+                    async move {
+                        done.acquire().await.unwrap().forget();
+                        Ok::<_, Infallible>(batch)
+                    }
+                }
+            }),
+            1,
+            Duration::from_secs(1),
+        );
+        tokio::spawn(worker.run());
+
+        let callers: Vec<_> = (0..4)
+            .map(|n| {
+                let batch = batch.clone();
+                tokio::spawn(async move { batch.call(n).await.unwrap() })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        // one batch is in flight, one request waits in the channel,
+        // and the other callers wait for room in the channel
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(batch.tx.capacity(), 0);
+        assert!(callers.iter().all(|caller| !caller.is_finished()));
+
+        done.add_permits(4);
+        for (n, caller) in callers.into_iter().enumerate() {
+            assert_eq!(caller.await.unwrap(), n as u64);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn worker_dropped() {
+        let (batch, worker) = Batch::pair(
+            service_fn(|batch: Vec<()>| async move { Ok::<_, Infallible>(batch) }),
+            8,
+            Duration::from_secs(1),
+        );
+        drop(worker);
+
+        let err = batch.call(()).await.unwrap_err();
+        assert!(err.is::<Closed>());
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fmt, marker::PhantomData, time::Duration};
+
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+use super::Batch;
+use crate::BoxError;
+
+impl<Request, Response> Batch<Request, Response>
+where
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    /// Creates a new [`Batch`] for the given service, spawning its [`Worker`] on the
+    /// Tokio runtime.
+    ///
+    /// Batches hold at most `max_size` requests, and are sent to the service at most
+    /// `max_latency` after their first request was received.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_size` is 0, or if called outside of a Tokio runtime.
+    ///
+    /// [`Worker`]: super::Worker
+    pub fn new<S>(service: S, max_size: usize, max_latency: Duration) -> Self
+    where
+        S: Service<Vec<Request>, Response = Vec<Response>, call(): Send> + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+    {
+        let (batch, worker) = Batch::pair(service, max_size, max_latency);
+        tokio::spawn(worker.run());
+        batch
+    }
+}
+
+/// Wraps services in a [`Batch`], spawning a worker for each of them.
+///
+/// See the [module docs](super) for more details.
+pub struct BatchLayer<Request> {
+    max_size: usize,
+    max_latency: Duration,
+    max_in_flight: usize,
+    _marker: PhantomData<fn(Request)>,
+}
+
+impl<Request> BatchLayer<Request> {
+    /// Creates a new [`BatchLayer`], with the given maximum size and latency of a batch.
+    ///
+    /// See [`Batch::new`] for more details.
+    pub fn new(max_size: usize, max_latency: Duration) -> Self {
+        BatchLayer {
+            max_size,
+            max_latency,
+            max_in_flight: 1,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of batches sent to a service at the same time.
+    ///
+    /// See [`Worker::max_in_flight`] for more details.
+    ///
+    /// [`Worker::max_in_flight`]: super::Worker::max_in_flight
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+}
+
+impl<S, Request, Response> Layer<S> for BatchLayer<Request>
+where
+    S: Service<Vec<Request>, Response = Vec<Response>, call(): Send> + Send + Sync + 'static,
+    S::Error: Into<BoxError>,
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    type Service = Batch<Request, Response>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let (batch, worker) = Batch::pair(service, self.max_size, self.max_latency);
+        tokio::spawn(worker.max_in_flight(self.max_in_flight).run());
+        batch
+    }
+}
+
+impl<Request> Clone for BatchLayer<Request> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Request> Copy for BatchLayer<Request> {}
+
+impl<Request> fmt::Debug for BatchLayer<Request> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchLayer")
+            .field("max_size", &self.max_size)
+            .field("max_latency", &self.max_latency)
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn spawns_worker() {
+        let batch = ServiceBuilder::new()
+            .layer(BatchLayer::new(4, Duration::from_millis(1)))
+            .service(service_fn(|batch: Vec<u64>| async move {
+                Ok::<_, Infallible>(batch.into_iter().map(|n| n * 2).collect::<Vec<_>>())
+            }));
+
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let batch = batch.clone();
+                tokio::spawn(async move { batch.call(n).await.unwrap() })
+            })
+            .collect();
+        for (n, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.await.unwrap(), n as u64 * 2);
+        }
+    }
+}
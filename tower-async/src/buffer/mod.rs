@@ -0,0 +1,317 @@
+//! Middleware that shares a service between many callers through a channel.
+//!
+//! A service which isn't [`Clone`], such as one keeping its state behind interior mutability,
+//! is awkward to share between the tasks of a server. [`Buffer`] solves this by moving the
+//! service into a [`Worker`], and handing out a cheap [`Clone`] handle which sends requests
+//! to the worker over a bounded channel. The worker calls the service for every request, and
+//! sends the response back to the caller.
+//!
+//! Up to `capacity` requests are processed concurrently by the worker. Once that many are in
+//! flight, the worker stops receiving requests, and once `capacity` more requests are waiting
+//! for the worker to receive them, callers wait for room in the channel, which applies
+//! backpressure to them.
+//!
+//! With the `nightly` feature enabled, `Buffer::new` spawns the worker on the Tokio runtime
+//! by itself, and `BufferLayer` wraps services in a [`Buffer`]. Otherwise [`Buffer::pair`]
+//! returns the worker, which has to be driven by the caller.
+//!
+//! If the worker is dropped, requests fail with a [`Closed`] error.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, sync::atomic::{AtomicU64, Ordering}};
+//! use tower_async::{buffer::Buffer, Service};
+//!
+//! /// A service which isn't `Clone`.
+//! struct Counter(AtomicU64);
+//!
+//! impl Service<()> for Counter {
+//!     type Response = u64;
+//!     type Error = Infallible;
+//!
+//!     async fn call(&self, _: ()) -> Result<u64, Infallible> {
+//!         Ok(self.0.fetch_add(1, Ordering::SeqCst))
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! let (buffer, worker) = Buffer::pair(Counter(AtomicU64::new(0)), 32);
+//! tokio::spawn(worker.run());
+//!
+//! let other = buffer.clone();
+//! tokio::spawn(async move { other.call(()).await }).await.unwrap()?;
+//! assert_eq!(buffer.call(()).await?, 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{error, fmt};
+
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tower_async_service::Service;
+
+use crate::BoxError;
+
+#[cfg(feature = "nightly")]
+mod spawn;
+
+#[cfg(feature = "nightly")]
+pub use self::spawn::BufferLayer;
+
+/// A request sent to the [`Worker`], with the channel to send its response back on.
+struct Message<Request, Response> {
+    request: Request,
+    tx: oneshot::Sender<Result<Response, BoxError>>,
+}
+
+/// A cheap [`Clone`] handle which sends requests to the service owned by a [`Worker`].
+///
+/// See the [module docs](self) for more details.
+pub struct Buffer<Request, Response> {
+    tx: mpsc::Sender<Message<Request, Response>>,
+}
+
+impl<Request, Response> Buffer<Request, Response> {
+    /// Creates a new [`Buffer`] for the given service, and the [`Worker`] calling it.
+    ///
+    /// The worker processes at most `capacity` requests concurrently, and at most `capacity`
+    /// more requests wait for the worker to receive them, after which callers wait for room
+    /// in the channel. The worker must be driven, for example by spawning
+    /// [`Worker::run`], for requests to be processed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn pair<S>(service: S, capacity: usize) -> (Self, Worker<S, Request, Response>)
+    where
+        S: Service<Request, Response = Response>,
+        S::Error: Into<BoxError>,
+    {
+        let (tx, rx) = mpsc::channel(capacity);
+        (
+            Buffer { tx },
+            Worker {
+                service,
+                rx,
+                capacity,
+            },
+        )
+    }
+}
+
+impl<Request, Response> Service<Request> for Buffer<Request, Response> {
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(Message { request, tx })
+            .await
+            .map_err(|_| Closed::new())?;
+        rx.await.map_err(|_| Closed::new())?
+    }
+}
+
+impl<Request, Response> Clone for Buffer<Request, Response> {
+    fn clone(&self) -> Self {
+        Buffer {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<Request, Response> fmt::Debug for Buffer<Request, Response> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("capacity", &self.tx.max_capacity())
+            .finish()
+    }
+}
+
+/// The worker owning the service of a [`Buffer`], calling it for the requests it receives.
+///
+/// See the [module docs](self) for more details.
+pub struct Worker<S, Request, Response> {
+    service: S,
+    rx: mpsc::Receiver<Message<Request, Response>>,
+    capacity: usize,
+}
+
+impl<S, Request, Response> Worker<S, Request, Response>
+where
+    S: Service<Request, Response = Response>,
+    S::Error: Into<BoxError>,
+{
+    /// Processes the requests sent by the [`Buffer`] handles, until all of them are dropped
+    /// and the requests in flight are completed.
+    ///
+    /// At most `capacity` requests are processed concurrently.
+    pub async fn run(self) {
+        let Worker {
+            service,
+            mut rx,
+            capacity,
+        } = self;
+        let service = &service;
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                message = rx.recv(), if in_flight.len() < capacity => match message {
+                    Some(Message { request, tx }) => in_flight.push(async move {
+                        // The caller is no longer interested in the response.
+                        if tx.is_closed() {
+                            return;
+                        }
+                        let result = service.call(request).await.map_err(Into::into);
+                        let _ = tx.send(result);
+                    }),
+                    None => break,
+                },
+                Some(()) = in_flight.next(), if !in_flight.is_empty() => {}
+            }
+        }
+        while in_flight.next().await.is_some() {}
+    }
+}
+
+impl<S, Request, Response> fmt::Debug for Worker<S, Request, Response>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("service", &self.service)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+/// Error returned by [`Buffer`] when its [`Worker`] is gone,
+/// because it was dropped or panicked while processing the request.
+#[derive(Debug, Default)]
+pub struct Closed(pub(super) ());
+
+impl Closed {
+    /// Construct a new closed error.
+    pub fn new() -> Self {
+        Closed(())
+    }
+}
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("buffer's worker closed unexpectedly")
+    }
+}
+
+impl error::Error for Closed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use crate::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn processes_requests_concurrently() {
+        let (buffer, worker) = Buffer::pair(
+            service_fn(|n: u64| async move {
+                tokio::time::sleep(Duration::from_secs(n)).await;
+                Ok::<_, Infallible>(n)
+            }),
+            3,
+        );
+        tokio::spawn(worker.run());
+
+        let start = tokio::time::Instant::now();
+        let responses = futures_util::future::join_all((1..=3).map(|n| buffer.call(n))).await;
+        assert_eq!(
+            responses
+                .into_iter()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_concurrency_to_capacity() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let (buffer, worker) = Buffer::pair(
+            service_fn({
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                move |()| {
+                    let in_flight = in_flight.clone();
+                    let max_in_flight = max_in_flight.clone();
+                    async move {
+                        let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_in_flight.fetch_max(n, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        Ok::<_, Infallible>(())
+                    }
+                }
+            }),
+            2,
+        );
+        tokio::spawn(worker.run());
+
+        let start = tokio::time::Instant::now();
+        let responses = futures_util::future::join_all((0..6).map(|_| buffer.call(()))).await;
+        assert!(responses.into_iter().all(|res| res.is_ok()));
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn shares_service() {
+        struct Log(Mutex<Vec<&'static str>>);
+
+        impl Service<&'static str> for Log {
+            type Response = usize;
+            type Error = Infallible;
+
+            async fn call(&self, entry: &'static str) -> Result<usize, Infallible> {
+                let mut log = self.0.lock().unwrap();
+                log.push(entry);
+                Ok(log.len())
+            }
+        }
+
+        let (buffer, worker) = Buffer::pair(Log(Mutex::new(Vec::new())), 8);
+        let worker = tokio::spawn(worker.run());
+
+        let other = buffer.clone();
+        assert_eq!(other.call("a").await.unwrap(), 1);
+        assert_eq!(buffer.call("b").await.unwrap(), 2);
+
+        // the worker stops once all handles are dropped
+        drop((buffer, other));
+        worker.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn worker_dropped() {
+        let (buffer, worker) = Buffer::pair(service_fn(|()| async { Ok::<_, Infallible>(()) }), 8);
+        drop(worker);
+
+        let err = buffer.call(()).await.unwrap_err();
+        assert!(err.is::<Closed>());
+    }
+}
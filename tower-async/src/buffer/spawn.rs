@@ -0,0 +1,113 @@
+use std::{fmt, marker::PhantomData};
+
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+use super::Buffer;
+use crate::BoxError;
+
+impl<Request, Response> Buffer<Request, Response>
+where
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    /// Creates a new [`Buffer`] for the given service, spawning its [`Worker`] on the
+    /// Tokio runtime.
+    ///
+    /// The worker processes at most `capacity` requests concurrently, and at most `capacity`
+    /// more requests wait for the worker to receive them, after which callers wait for room
+    /// in the channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0, or if called outside of a Tokio runtime.
+    ///
+    /// [`Worker`]: super::Worker
+    pub fn new<S>(service: S, capacity: usize) -> Self
+    where
+        S: Service<Request, Response = Response, call(): Send> + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+    {
+        let (buffer, worker) = Buffer::pair(service, capacity);
+        tokio::spawn(worker.run());
+        buffer
+    }
+}
+
+/// Wraps services in a [`Buffer`], spawning a worker for each of them.
+///
+/// See the [module docs](super) for more details.
+pub struct BufferLayer<Request> {
+    capacity: usize,
+    _marker: PhantomData<fn(Request)>,
+}
+
+impl<Request> BufferLayer<Request> {
+    /// Creates a new [`BufferLayer`], with the given capacity.
+    ///
+    /// See [`Buffer::new`] for more details.
+    pub fn new(capacity: usize) -> Self {
+        BufferLayer {
+            capacity,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, Request> Layer<S> for BufferLayer<Request>
+where
+    S: Service<Request, call(): Send> + Send + Sync + 'static,
+    S::Response: Send + 'static,
+    S::Error: Into<BoxError>,
+    Request: Send + 'static,
+{
+    type Service = Buffer<Request, S::Response>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        Buffer::new(service, self.capacity)
+    }
+}
+
+impl<Request> Clone for BufferLayer<Request> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Request> Copy for BufferLayer<Request> {}
+
+impl<Request> fmt::Debug for BufferLayer<Request> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferLayer")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn spawns_worker() {
+        let buffer = ServiceBuilder::new()
+            .layer(BufferLayer::new(8))
+            .service(service_fn(
+                |n: u64| async move { Ok::<_, Infallible>(n * 2) },
+            ));
+
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let buffer = buffer.clone();
+                tokio::spawn(async move { buffer.call(n).await.unwrap() })
+            })
+            .collect();
+        for (n, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.await.unwrap(), n as u64 * 2);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Error types
+
+use std::{error::Error, fmt, sync::Arc};
+
+/// The errors of the layers which failed to build,
+/// returned by [`ServiceBuilder::try_build`].
+///
+/// [`ServiceBuilder::try_build`]: super::ServiceBuilder::try_build
+#[derive(Debug, Clone)]
+pub struct BuildError {
+    errors: Vec<Arc<dyn Error + Send + Sync>>,
+}
+
+impl BuildError {
+    pub(super) fn new(errors: Vec<Arc<dyn Error + Send + Sync>>) -> Self {
+        BuildError { errors }
+    }
+
+    /// Returns the errors of the layers which failed to build,
+    /// in the order in which the layers were added.
+    pub fn errors(&self) -> impl Iterator<Item = &(dyn Error + Send + Sync + 'static)> {
+        self.errors.iter().map(|error| &**error)
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errors.len() {
+            1 => f.write_str("failed to build 1 layer")?,
+            n => write!(f, "failed to build {n} layers")?,
+        }
+        for (i, error) in self.errors.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{separator}{error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BuildError {}
@@ -3,7 +3,23 @@
 use tower_async_layer::{Identity, Layer, Stack};
 use tower_async_service::Service;
 
-use std::fmt;
+use std::{fmt, sync::Arc};
+
+mod error;
+#[cfg(all(feature = "util", feature = "nightly"))]
+mod send;
+mod stack;
+#[cfg(feature = "timing")]
+mod timed;
+
+pub use self::error::BuildError;
+#[cfg(feature = "timing")]
+pub use self::timed::TimedServiceBuilder;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use super::stack::{layer_at, StackLayer};
+}
 
 /// Declaratively construct [`Service`] values.
 ///
@@ -31,6 +47,7 @@ use std::fmt;
 #[derive(Clone)]
 pub struct ServiceBuilder<L> {
     layer: L,
+    errors: Vec<Arc<dyn std::error::Error + Send + Sync>>,
 }
 
 impl Default for ServiceBuilder<Identity> {
@@ -44,6 +61,7 @@ impl ServiceBuilder<Identity> {
     pub fn new() -> Self {
         ServiceBuilder {
             layer: Identity::new(),
+            errors: Vec::new(),
         }
     }
 }
@@ -58,6 +76,7 @@ impl<L> ServiceBuilder<L> {
     pub fn layer<T>(self, layer: T) -> ServiceBuilder<Stack<T, L>> {
         ServiceBuilder {
             layer: Stack::new(layer, self.layer),
+            errors: self.errors,
         }
     }
 
@@ -85,6 +104,75 @@ impl<L> ServiceBuilder<L> {
         self.layer(crate::util::option_layer(layer))
     }
 
+    /// Add a new layer `T` into the [`ServiceBuilder`], if it was built successfully.
+    ///
+    /// If the layer failed to build, no layer is added, and the error is recorded instead,
+    /// such that configuration driven stacks report all their errors at once. Use
+    /// [`ServiceBuilder::try_build`] to get them. Wrapping a service while errors are
+    /// recorded panics.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use tower_async::{timeout::TimeoutLayer, ServiceBuilder};
+    ///
+    /// fn timeout(config: &str) -> Result<TimeoutLayer, std::num::ParseIntError> {
+    ///     let secs = config.parse()?;
+    ///     Ok(TimeoutLayer::new(Duration::from_secs(secs)))
+    /// }
+    ///
+    /// let result = ServiceBuilder::new()
+    ///     .maybe_layer(timeout("10"))
+    ///     .maybe_layer(timeout("ten"))
+    ///     .maybe_layer(timeout(""))
+    ///     .try_build();
+    ///
+    /// let error = result.unwrap_err();
+    /// assert_eq!(error.errors().count(), 2);
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "failed to build 2 layers: invalid digit found in string; \
+    ///      cannot parse integer from empty string",
+    /// );
+    /// ```
+    #[cfg(feature = "util")]
+    pub fn maybe_layer<T, E>(
+        mut self,
+        layer: Result<T, E>,
+    ) -> ServiceBuilder<Stack<crate::util::Either<T, Identity>, L>>
+    where
+        E: Into<crate::BoxError>,
+    {
+        let layer = match layer {
+            Ok(layer) => Some(layer),
+            Err(error) => {
+                self.errors.push(Arc::from(error.into()));
+                None
+            }
+        };
+        self.option_layer(layer)
+    }
+
+    /// Returns the [`ServiceBuilder`] if all its layers were built successfully,
+    /// or the errors of all the layers added using [`ServiceBuilder::maybe_layer`]
+    /// which failed to build.
+    pub fn try_build(self) -> Result<Self, BuildError> {
+        if self.errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(BuildError::new(self.errors))
+        }
+    }
+
+    /// Returns the layer, panicking if any of the layers failed to build.
+    fn built(&self) -> &L {
+        if !self.errors.is_empty() {
+            panic!("{}", BuildError::new(self.errors.clone()));
+        }
+        &self.layer
+    }
+
     /// Add a [`Layer`] built from a function that accepts a service and returns another service.
     ///
     /// See the documentation for [`layer_fn`] for more details.
@@ -94,6 +182,25 @@ impl<L> ServiceBuilder<L> {
         self.layer(crate::layer::layer_fn(f))
     }
 
+    /// Record the time spent in each layer added after this call.
+    ///
+    /// Returns a [`TimedServiceBuilder`], which wraps every layer added to it in a
+    /// [`TimedLayer`], and reports the time each layer spent on a request to an
+    /// [`OnReport`] callback. See the [`timing`] module docs for more details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the layers added using [`ServiceBuilder::maybe_layer`] failed to build.
+    ///
+    /// [`TimedLayer`]: crate::timing::TimedLayer
+    /// [`OnReport`]: crate::timing::OnReport
+    /// [`timing`]: crate::timing
+    #[cfg(feature = "timing")]
+    pub fn timed(self) -> TimedServiceBuilder<L, Identity, crate::timing::DefaultOnReport> {
+        self.built();
+        TimedServiceBuilder::new(self.layer)
+    }
+
     /// Retry failed requests according to the given [retry policy][policy].
     ///
     /// `policy` determines which failed requests will be retried. It must
@@ -114,18 +221,53 @@ impl<L> ServiceBuilder<L> {
     /// If the next layer takes more than `timeout` to respond to a request,
     /// processing is terminated and an error is returned.
     ///
+    /// The timeout can be a [`Dynamic`] duration, which can be updated while
+    /// the service is running.
+    ///
     /// This wraps the inner service with an instance of the [`timeout`]
     /// middleware.
     ///
     /// [`timeout`]: crate::timeout
+    /// [`Dynamic`]: crate::dynamic::Dynamic
     #[cfg(feature = "timeout")]
     pub fn timeout(
         self,
-        timeout: std::time::Duration,
+        timeout: impl Into<crate::dynamic::Dynamic<std::time::Duration>>,
     ) -> ServiceBuilder<Stack<crate::timeout::TimeoutLayer, L>> {
         self.layer(crate::timeout::TimeoutLayer::new(timeout))
     }
 
+    /// Fail requests fast while too many requests to the inner service fail,
+    /// as configured by `config`.
+    ///
+    /// This wraps the inner service with an instance of the [`CircuitBreaker`]
+    /// middleware.
+    ///
+    /// [`CircuitBreaker`]: crate::circuit_breaker::CircuitBreaker
+    #[cfg(feature = "circuit-breaker")]
+    pub fn circuit_breaker(
+        self,
+        config: crate::circuit_breaker::CircuitBreakerConfig,
+    ) -> ServiceBuilder<Stack<crate::circuit_breaker::CircuitBreakerLayer, L>> {
+        self.layer(crate::circuit_breaker::CircuitBreakerLayer::new(config))
+    }
+
+    /// Set a task-local value, derived from the request by `f`,
+    /// for the duration of the inner service's `call`.
+    ///
+    /// This wraps the inner service with an instance of the [`TaskLocal`]
+    /// middleware.
+    ///
+    /// [`TaskLocal`]: crate::task_local::TaskLocal
+    #[cfg(feature = "task-local")]
+    pub fn task_local<T, F>(
+        self,
+        key: &'static tokio::task::LocalKey<T>,
+        f: F,
+    ) -> ServiceBuilder<Stack<crate::task_local::TaskLocalLayer<T, F>, L>> {
+        self.layer(crate::task_local::TaskLocalLayer::new(key, f))
+    }
+
     /// Conditionally reject requests based on `predicate`.
     ///
     /// `predicate` must implement the [`Predicate`] trait.
@@ -172,6 +314,40 @@ impl<L> ServiceBuilder<L> {
         self.layer(crate::limit::LimitLayer::new(policy))
     }
 
+    /// Reject requests with an [`Overloaded`] error when the limit `policy` is at capacity,
+    /// instead of having them wait.
+    ///
+    /// This wraps the inner service with an instance of the [`LoadShed`]
+    /// middleware.
+    ///
+    /// [`LoadShed`]: crate::load_shed::LoadShed
+    /// [`Overloaded`]: crate::load_shed::Overloaded
+    #[cfg(feature = "load-shed")]
+    pub fn load_shed<P>(
+        self,
+        policy: P,
+    ) -> ServiceBuilder<Stack<crate::load_shed::LoadShedLayer<P>, L>> {
+        self.layer(crate::load_shed::LoadShedLayer::new(policy))
+    }
+
+    /// Limit requests to at most `num` per the given duration.
+    ///
+    /// Both can be [`Dynamic`] values, which can be updated while the service is running.
+    ///
+    /// This wraps the inner service with an instance of the [`RateLimit`]
+    /// middleware.
+    ///
+    /// [`RateLimit`]: crate::limit::RateLimit
+    /// [`Dynamic`]: crate::dynamic::Dynamic
+    #[cfg(feature = "limit")]
+    pub fn rate_limit(
+        self,
+        num: impl Into<crate::dynamic::Dynamic<u64>>,
+        per: impl Into<crate::dynamic::Dynamic<std::time::Duration>>,
+    ) -> ServiceBuilder<Stack<crate::limit::RateLimitLayer, L>> {
+        self.layer(crate::limit::RateLimitLayer::new(num, per))
+    }
+
     /// Map one request type to another.
     ///
     /// This wraps the inner service with an instance of the [`MapRequest`]
@@ -333,20 +509,90 @@ impl<L> ServiceBuilder<L> {
     }
 
     /// Returns the underlying `Layer` implementation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the layers added using [`ServiceBuilder::maybe_layer`] failed to build.
     pub fn into_inner(self) -> L {
+        self.built();
         self.layer
     }
 
+    /// Erase the type of the layers added to this [`ServiceBuilder`], returning a [`BoxLayer`]
+    /// that wraps services of type `S` into a [`BoxService`].
+    ///
+    /// The nested [`Stack`] types of a builder are impractical to name, such as in struct fields.
+    /// Erased stacks of different layers can be named, stored together and selected at runtime.
+    ///
+    /// The futures of a [`BoxService`] aren't `Send`, such that the erased stacks can't be
+    /// served where their calls are spawned, such as with `tower-async-hyper`. With the
+    /// `nightly` feature enabled, `ServiceBuilder::into_box_send_layer` erases the stack
+    /// into a `BoxSendLayer` instead, keeping the futures `Send`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use tower_async::{
+    ///     util::{BoxLayer, BoxService},
+    ///     BoxError, Layer, Service, ServiceBuilder, ServiceExt,
+    /// };
+    ///
+    /// type Handler = BoxService<String, usize, BoxError>;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let stacks: Vec<BoxLayer<Handler, String, usize, BoxError>> = vec![
+    ///     ServiceBuilder::new()
+    ///         .timeout(Duration::from_secs(10))
+    ///         .into_box_layer(),
+    ///     ServiceBuilder::new()
+    ///         .map_request(|request: String| request.trim().to_owned())
+    ///         .map_response(|len: usize| len * 2)
+    ///         .into_box_layer(),
+    /// ];
+    ///
+    /// let handler = tower_async::service_fn(|request: String| async move {
+    ///     Ok::<_, BoxError>(request.len())
+    /// });
+    /// let service = stacks[1].layer(handler.into_dyn());
+    /// assert_eq!(service.call(" foo ".to_owned()).await.unwrap(), 6);
+    /// # }
+    /// ```
+    ///
+    /// [`BoxLayer`]: crate::util::BoxLayer
+    /// [`BoxService`]: crate::util::BoxService
+    #[cfg(feature = "util")]
+    pub fn into_box_layer<S, Request>(
+        self,
+    ) -> crate::util::BoxLayer<
+        S,
+        Request,
+        <L::Service as Service<Request>>::Response,
+        <L::Service as Service<Request>>::Error,
+    >
+    where
+        L: Layer<S> + Send + Sync + 'static,
+        L::Service: Service<Request> + Send + Sync + 'static,
+    {
+        self.built();
+        crate::util::BoxLayer::new(self.layer)
+    }
+
     /// Wrap the service `S` with the middleware provided by this
     /// [`ServiceBuilder`]'s [`Layer`]'s, returning a new [`Service`].
     ///
+    /// # Panics
+    ///
+    /// Panics if any of the layers added using [`ServiceBuilder::maybe_layer`] failed to build.
+    ///
     /// [`Layer`]: crate::Layer
     /// [`Service`]: crate::Service
     pub fn service<S>(&self, service: S) -> L::Service
     where
         L: Layer<S>,
     {
-        self.layer.layer(service)
+        self.built().layer(service)
     }
 
     /// Wrap the async function `F` with the middleware provided by this [`ServiceBuilder`]'s
@@ -399,6 +645,49 @@ impl<L> ServiceBuilder<L> {
         self.service(crate::util::service_fn(f))
     }
 
+    /// Wrap the async function `F`, which is called with a clone of `state` for every request,
+    /// with the middleware provided by this [`ServiceBuilder`]'s [`Layer`]s,
+    /// returning a new [`Service`].
+    ///
+    /// This is a convenience method which is equivalent to calling
+    /// [`ServiceBuilder::service`] with a [`service_fn_with_state`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::{sync::Arc, time::Duration};
+    /// use tower_async::{BoxError, Service, ServiceBuilder};
+    ///
+    /// struct Config {
+    ///     greeting: &'static str,
+    /// }
+    ///
+    /// async fn handle(config: Arc<Config>, name: &'static str) -> Result<String, BoxError> {
+    ///     Ok(format!("{}, {name}!", config.greeting))
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), BoxError> {
+    /// let svc = ServiceBuilder::new()
+    ///     .timeout(Duration::from_secs(10))
+    ///     .service_fn_with_state(Arc::new(Config { greeting: "Hello" }), handle);
+    ///
+    /// assert_eq!(svc.call("World").await?, "Hello, World!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Layer`]: crate::Layer
+    /// [`Service`]: crate::Service
+    /// [`service_fn_with_state`]: crate::service_fn_with_state
+    #[cfg(feature = "util")]
+    pub fn service_fn_with_state<S, F>(self, state: S, f: F) -> L::Service
+    where
+        L: Layer<crate::util::ServiceFnWithState<S, F>>,
+    {
+        self.service(crate::util::service_fn_with_state(state, f))
+    }
+
     /// Check that the builder implements `Clone`.
     ///
     /// This can be useful when debugging type errors in `ServiceBuilder`s with lots of layers.
@@ -535,6 +824,6 @@ where
     type Service = L::Service;
 
     fn layer(&self, inner: S) -> Self::Service {
-        self.layer.layer(inner)
+        self.built().layer(inner)
     }
 }
@@ -0,0 +1,61 @@
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+use super::ServiceBuilder;
+use crate::util::BoxSendLayer;
+
+impl<L> ServiceBuilder<L> {
+    /// Erase the type of the layers added to this [`ServiceBuilder`], returning a
+    /// [`BoxSendLayer`] that wraps services of type `S` into a [`BoxSendService`].
+    ///
+    /// Like [`ServiceBuilder::into_box_layer`], but keeping the futures of the services `Send`,
+    /// such that the erased stacks can be served where their calls are spawned,
+    /// such as with `tower-async-hyper`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use tower_async::{
+    ///     util::{BoxSendLayer, BoxSendService},
+    ///     BoxError, Layer, Service, ServiceBuilder,
+    /// };
+    ///
+    /// type Handler = BoxSendService<String, usize, BoxError>;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let stack: BoxSendLayer<Handler, String, usize, BoxError> = ServiceBuilder::new()
+    ///     .timeout(Duration::from_secs(10))
+    ///     .into_box_send_layer();
+    ///
+    /// let handler = tower_async::service_fn(|request: String| async move {
+    ///     Ok::<_, BoxError>(request.len())
+    /// });
+    /// let service = stack.layer(BoxSendService::new(handler));
+    /// let response = tokio::spawn(async move { service.call("foo".to_owned()).await });
+    /// assert_eq!(response.await.unwrap().unwrap(), 3);
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the layers added using [`ServiceBuilder::maybe_layer`] failed to build.
+    ///
+    /// [`BoxSendService`]: crate::util::BoxSendService
+    pub fn into_box_send_layer<S, Request>(
+        self,
+    ) -> BoxSendLayer<
+        S,
+        Request,
+        <L::Service as Service<Request>>::Response,
+        <L::Service as Service<Request>>::Error,
+    >
+    where
+        L: Layer<S> + Send + Sync + 'static,
+        L::Service: Service<Request, call(): Send> + Send + Sync + 'static,
+    {
+        self.built();
+        BoxSendLayer::new(self.layer)
+    }
+}
@@ -0,0 +1,107 @@
+use tower_async_layer::Layer;
+
+/// Compose layers and a service into a single service, without nesting [`Stack`]s.
+///
+/// `stack![a, b, c => service]` is equivalent to
+/// `ServiceBuilder::new().layer(a).layer(b).layer(c).service(service)`: layers are
+/// listed in the order in which they see the request, the service is the last to see it.
+///
+/// Each layer is applied to the service it wraps on its own, instead of being combined
+/// into a `Stack<Stack<...>>` first. Type errors therefore point at the offending layer
+/// expression and mention its index in the list, starting at `0` for the outermost layer,
+/// rather than at the type of the whole stack.
+///
+/// # Example
+///
+/// ```
+/// use std::{convert::Infallible, time::Duration};
+/// use tower_async::{
+///     limit::{policy::ConcurrentPolicy, LimitLayer},
+///     service_fn,
+///     stack,
+///     timeout::TimeoutLayer,
+///     Service,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), tower_async::BoxError> {
+/// let svc = stack![
+///     TimeoutLayer::new(Duration::from_secs(30)),
+///     LimitLayer::new(ConcurrentPolicy::new(64))
+///     => service_fn(|request: &'static str| async move {
+///         Ok::<_, Infallible>(request.len())
+///     })
+/// ];
+///
+/// assert_eq!(svc.call("hello").await?, 5);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Using something that isn't a layer names the offending entry:
+///
+/// ```compile_fail
+/// use std::convert::Infallible;
+/// use tower_async::{service_fn, stack};
+///
+/// let svc = stack![
+///     tower_async::layer::util::Identity::new(),
+///     // error: layer #1 of the stack (`&str`) cannot wrap the service below it
+///     "not a layer"
+///     => service_fn(|request: ()| async move { Ok::<_, Infallible>(request) })
+/// ];
+/// ```
+///
+/// [`Stack`]: crate::layer::util::Stack
+#[macro_export]
+macro_rules! stack {
+    ($($layer:expr),+ => $service:expr $(,)?) => {
+        $crate::stack!(@layer [0] $($layer),+ => $service)
+    };
+    ($service:expr $(,)?) => {
+        $service
+    };
+    (@layer [$($index:tt)*] $layer:expr => $service:expr) => {
+        $crate::builder::__private::layer_at::<{ $($index)* }, _, _>($layer, $service)
+    };
+    (@layer [$($index:tt)*] $layer:expr, $($rest:expr),+ => $service:expr) => {
+        $crate::builder::__private::layer_at::<{ $($index)* }, _, _>(
+            $layer,
+            $crate::stack!(@layer [$($index)* + 1] $($rest),+ => $service),
+        )
+    };
+}
+
+/// [`Layer`] used at a given index of a [`stack!`](crate::stack).
+///
+/// Only exists to give errors about layers in a stack a readable message.
+#[diagnostic::on_unimplemented(
+    message = "layer #{INDEX} of the stack (`{Self}`) cannot wrap the service below it",
+    label = "layer #{INDEX} does not implement `Layer`"
+)]
+pub trait StackLayer<S, const INDEX: usize> {
+    /// The wrapped service.
+    type Service;
+
+    /// Wrap the given service.
+    fn stack_layer(&self, service: S) -> Self::Service;
+}
+
+impl<L, S, const INDEX: usize> StackLayer<S, INDEX> for L
+where
+    L: Layer<S>,
+{
+    type Service = L::Service;
+
+    fn stack_layer(&self, service: S) -> Self::Service {
+        self.layer(service)
+    }
+}
+
+/// Wrap `service` with the layer at the given index of a [`stack!`](crate::stack).
+pub fn layer_at<const INDEX: usize, L, S>(layer: L, service: S) -> L::Service
+where
+    L: StackLayer<S, INDEX>,
+{
+    layer.stack_layer(service)
+}
@@ -0,0 +1,126 @@
+use crate::timing::{DefaultOnReport, TimedLayer, TimingReportLayer, WithTimingReport};
+use tower_async_layer::{Identity, Layer, Stack};
+
+use std::fmt;
+
+/// A [`ServiceBuilder`] that records the time spent in each layer added to it.
+///
+/// Created by [`ServiceBuilder::timed`].
+/// See the [`timing`] module docs for more details.
+///
+/// [`ServiceBuilder`]: super::ServiceBuilder
+/// [`ServiceBuilder::timed`]: super::ServiceBuilder::timed
+/// [`timing`]: crate::timing
+#[derive(Clone)]
+pub struct TimedServiceBuilder<L, T, F> {
+    outer: L,
+    timed: T,
+    on_report: F,
+    next_index: usize,
+}
+
+impl<L> TimedServiceBuilder<L, Identity, DefaultOnReport> {
+    pub(super) fn new(outer: L) -> Self {
+        TimedServiceBuilder {
+            outer,
+            timed: Identity::new(),
+            on_report: DefaultOnReport::new(),
+            next_index: 0,
+        }
+    }
+}
+
+impl<L, T, F> TimedServiceBuilder<L, T, F> {
+    /// Add a new layer `U` into the [`TimedServiceBuilder`],
+    /// recording the time spent in it under the type name of the layer.
+    pub fn layer<U>(self, layer: U) -> TimedServiceBuilder<L, Stack<TimedLayer<U>, T>, F> {
+        self.named_layer(std::any::type_name::<U>(), layer)
+    }
+
+    /// Add a new layer `U` into the [`TimedServiceBuilder`],
+    /// recording the time spent in it under the given name.
+    pub fn named_layer<U>(
+        self,
+        name: &'static str,
+        layer: U,
+    ) -> TimedServiceBuilder<L, Stack<TimedLayer<U>, T>, F> {
+        TimedServiceBuilder {
+            outer: self.outer,
+            timed: Stack::new(TimedLayer::new(layer, name, self.next_index), self.timed),
+            on_report: self.on_report,
+            next_index: self.next_index + 1,
+        }
+    }
+
+    /// Set the [`OnReport`] callback the [`TimingReport`] of each request is passed to.
+    ///
+    /// Defaults to [`DefaultOnReport`].
+    ///
+    /// [`OnReport`]: crate::timing::OnReport
+    /// [`TimingReport`]: crate::timing::TimingReport
+    pub fn on_report<G>(self, on_report: G) -> TimedServiceBuilder<L, T, G> {
+        TimedServiceBuilder {
+            outer: self.outer,
+            timed: self.timed,
+            on_report,
+            next_index: self.next_index,
+        }
+    }
+
+    /// Wrap the service `S` with the middleware provided by this
+    /// [`TimedServiceBuilder`]'s [`Layer`]'s, returning a new [`Service`].
+    ///
+    /// [`Layer`]: crate::Layer
+    /// [`Service`]: crate::Service
+    pub fn service<S>(&self, service: S) -> L::Service
+    where
+        T: Layer<S>,
+        L: Layer<WithTimingReport<T::Service, F>>,
+        F: Clone,
+    {
+        Layer::layer(self, service)
+    }
+
+    /// Wrap the async function `G` with the middleware provided by this
+    /// [`TimedServiceBuilder`]'s [`Layer`]s, returning a new [`Service`].
+    ///
+    /// [`Layer`]: crate::Layer
+    /// [`Service`]: crate::Service
+    #[cfg(feature = "util")]
+    pub fn service_fn<G>(self, f: G) -> L::Service
+    where
+        T: Layer<crate::util::ServiceFn<G>>,
+        L: Layer<WithTimingReport<T::Service, F>>,
+        F: Clone,
+    {
+        self.service(crate::util::service_fn(f))
+    }
+}
+
+impl<L, T, F> fmt::Debug for TimedServiceBuilder<L, T, F>
+where
+    L: fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedServiceBuilder")
+            .field("outer", &self.outer)
+            .field("timed", &self.timed)
+            .field("on_report", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, L, T, F> Layer<S> for TimedServiceBuilder<L, T, F>
+where
+    T: Layer<S>,
+    L: Layer<WithTimingReport<T::Service, F>>,
+    F: Clone,
+{
+    type Service = L::Service;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let inner = TimingReportLayer::new(self.on_report.clone()).layer(self.timed.layer(inner));
+        self.outer.layer(inner)
+    }
+}
@@ -0,0 +1,98 @@
+use super::{Cache, Config};
+use std::{fmt, marker::PhantomData, time::Duration};
+use tower_async_layer::Layer;
+
+/// Caches the responses of the wrapped services by a key of the request.
+///
+/// Each wrapped service gets its own cache. See the [module docs](super) for more details.
+pub struct CacheLayer<F, K, Response> {
+    key_fn: F,
+    config: Config,
+    _marker: PhantomData<fn() -> (K, Response)>,
+}
+
+impl<F, K, Response> CacheLayer<F, K, Response> {
+    /// Creates a new [`CacheLayer`], caching responses by the key returned by `key_fn`.
+    ///
+    /// Requests for which `key_fn` returns `None` bypass the cache.
+    pub fn new(key_fn: F) -> Self {
+        CacheLayer {
+            key_fn,
+            config: Config::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets how long responses are fresh, and returned without calling the inner service,
+    /// defaults to 60 seconds.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.ttl = ttl;
+        self
+    }
+
+    /// Sets how long after having become stale a response is still returned, while it is
+    /// refreshed in the background, defaults to zero.
+    pub fn stale_while_revalidate(mut self, duration: Duration) -> Self {
+        self.config.stale_while_revalidate = duration;
+        self
+    }
+
+    /// Sets how long after having become stale a response is still returned if the inner
+    /// service fails, defaults to zero.
+    pub fn stale_if_error(mut self, duration: Duration) -> Self {
+        self.config.stale_if_error = duration;
+        self
+    }
+
+    /// Sets the maximum number of refreshes running in the background, defaults to 8.
+    pub fn max_refreshes(mut self, max_refreshes: usize) -> Self {
+        self.config.max_refreshes = max_refreshes;
+        self
+    }
+
+    /// Sets the maximum number of cached responses, defaults to 1024.
+    ///
+    /// Once full, expired responses are removed, or otherwise the oldest response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be larger than 0");
+        self.config.capacity = capacity;
+        self
+    }
+}
+
+impl<S, F, K, Response> Layer<S> for CacheLayer<F, K, Response>
+where
+    F: Clone,
+{
+    type Service = Cache<S, F, K, Response>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Cache::with_config(inner, self.key_fn.clone(), self.config)
+    }
+}
+
+impl<F, K, Response> Clone for CacheLayer<F, K, Response>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        CacheLayer {
+            key_fn: self.key_fn.clone(),
+            config: self.config,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<F, K, Response> fmt::Debug for CacheLayer<F, K, Response> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheLayer")
+            .field("key_fn", &format_args!("{}", std::any::type_name::<F>()))
+            .field("config", &self.config)
+            .finish()
+    }
+}
@@ -0,0 +1,558 @@
+//! Middleware that caches responses by a key of the request.
+//!
+//! [`Cache`] is a read-through cache: the response of the inner service is stored under the
+//! key extracted from the request, and returned for subsequent requests with the same key
+//! while it is fresh, that is for the configured [time to live](CacheLayer::ttl). Requests
+//! without a key bypass the cache.
+//!
+//! Once a response is no longer fresh, it is stale, and the cache can be configured to keep
+//! using it for a bounded amount of time:
+//!
+//! - [`stale_while_revalidate`]: a stale response is returned immediately, while the inner
+//!   service is called in the background to refresh it. At most one refresh per key is in
+//!   flight, and at most [`max_refreshes`] refreshes overall. Requests for which no refresh
+//!   can be started are still answered with the stale response;
+//! - [`stale_if_error`]: if the inner service fails, a stale response is returned instead
+//!   of the error.
+//!
+//! Responses older than that are never returned. As background refreshes are spawned on the
+//! Tokio runtime, the inner service and its futures must be [`Send`].
+//!
+//! # Example
+//!
+//! ```
+//! use std::{
+//!     convert::Infallible,
+//!     sync::atomic::{AtomicUsize, Ordering},
+//!     time::Duration,
+//! };
+//! use tower_async::{cache::CacheLayer, service_fn, Service, ServiceBuilder};
+//!
+//! static CALLS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! let service = ServiceBuilder::new()
+//!     .layer(
+//!         CacheLayer::new(|user_id: &u64| Some(*user_id))
+//!             .ttl(Duration::from_secs(10))
+//!             .stale_while_revalidate(Duration::from_secs(60))
+//!             .stale_if_error(Duration::from_secs(300)),
+//!     )
+//!     .service(service_fn(|user_id: u64| async move {
+//!         CALLS.fetch_add(1, Ordering::SeqCst);
+//!         Ok::<_, Infallible>(format!("user {user_id}"))
+//!     }));
+//!
+//! assert_eq!(service.call(42).await?, "user 42");
+//! assert_eq!(service.call(42).await?, "user 42");
+//! assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`stale_while_revalidate`]: CacheLayer::stale_while_revalidate
+//! [`stale_if_error`]: CacheLayer::stale_if_error
+//! [`max_refreshes`]: CacheLayer::max_refreshes
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+use tower_async_service::Service;
+
+mod layer;
+
+pub use self::layer::CacheLayer;
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    ttl: Duration,
+    stale_while_revalidate: Duration,
+    stale_if_error: Duration,
+    max_refreshes: usize,
+    capacity: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ttl: Duration::from_secs(60),
+            stale_while_revalidate: Duration::ZERO,
+            stale_if_error: Duration::ZERO,
+            max_refreshes: 8,
+            capacity: 1024,
+        }
+    }
+}
+
+impl Config {
+    fn max_age(&self) -> Duration {
+        self.ttl + self.stale_while_revalidate.max(self.stale_if_error)
+    }
+}
+
+/// Caches the responses of the inner service by a key of the request.
+///
+/// See the [module docs](self) for more details.
+pub struct Cache<S, F, K, Response> {
+    inner: S,
+    key_fn: F,
+    config: Config,
+    shared: Arc<Shared<K, Response>>,
+}
+
+struct Shared<K, Response> {
+    state: Mutex<State<K, Response>>,
+    refreshes: Arc<Semaphore>,
+}
+
+struct State<K, Response> {
+    entries: HashMap<K, (Instant, Response)>,
+    refreshing: HashSet<K>,
+}
+
+enum Lookup<K, Response>
+where
+    K: Hash + Eq,
+{
+    Miss,
+    Fresh(Response),
+    /// Stale, to be returned while it is refreshed in the background.
+    Revalidate(Response, Refresh<K, Response>),
+    /// Stale, while a refresh is already in flight, or no refresh can be started.
+    Refreshing(Response),
+    /// Stale, only to be returned if the inner service fails.
+    StaleIfError(Response),
+}
+
+/// Marks a key as being refreshed until dropped,
+/// including when the refresh panics.
+struct Refresh<K, Response>
+where
+    K: Hash + Eq,
+{
+    shared: Arc<Shared<K, Response>>,
+    key: K,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<K, Response> Drop for Refresh<K, Response>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.refreshing.remove(&self.key);
+        }
+    }
+}
+
+impl<S, F, K, Response> Cache<S, F, K, Response> {
+    /// Creates a new [`Cache`] with the default configuration,
+    /// see [`CacheLayer`] to configure it.
+    pub fn new(inner: S, key_fn: F) -> Self {
+        Self::with_config(inner, key_fn, Config::default())
+    }
+
+    fn with_config(inner: S, key_fn: F, config: Config) -> Self {
+        Cache {
+            inner,
+            key_fn,
+            config,
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    entries: HashMap::new(),
+                    refreshing: HashSet::new(),
+                }),
+                refreshes: Arc::new(Semaphore::new(config.max_refreshes)),
+            }),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a [`Cache`] middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(key_fn: F) -> CacheLayer<F, K, Response> {
+        CacheLayer::new(key_fn)
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, K, Response> Cache<S, F, K, Response>
+where
+    K: Hash + Eq,
+{
+    /// Removes the cached response of the given key, if any.
+    pub fn invalidate(&self, key: &K) {
+        self.shared.state.lock().unwrap().entries.remove(key);
+    }
+
+    /// Returns the number of cached responses, including stale ones.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if no responses are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, Response> Shared<K, Response>
+where
+    K: Hash + Eq + Clone,
+    Response: Clone,
+{
+    fn lookup(self: &Arc<Self>, key: &K, config: &Config) -> Lookup<K, Response> {
+        let mut state = self.state.lock().unwrap();
+        let Some((stored, response)) = state.entries.get(key) else {
+            return Lookup::Miss;
+        };
+        let age = stored.elapsed();
+        if age < config.ttl {
+            return Lookup::Fresh(response.clone());
+        }
+        if age >= config.max_age() {
+            state.entries.remove(key);
+            return Lookup::Miss;
+        }
+
+        let response = response.clone();
+        if age >= config.ttl + config.stale_while_revalidate {
+            Lookup::StaleIfError(response)
+        } else if state.refreshing.contains(key) {
+            Lookup::Refreshing(response)
+        } else if let Ok(permit) = self.refreshes.clone().try_acquire_owned() {
+            // marked while still locked, such that only one caller starts the refresh
+            state.refreshing.insert(key.clone());
+            let refresh = Refresh {
+                shared: self.clone(),
+                key: key.clone(),
+                _permit: permit,
+            };
+            Lookup::Revalidate(response, refresh)
+        } else {
+            // if no refresh can be started, the stale response is still fine to serve
+            Lookup::Refreshing(response)
+        }
+    }
+
+    fn insert(&self, key: K, response: Response, config: &Config) {
+        let mut state = self.state.lock().unwrap();
+        if state.entries.len() >= config.capacity && !state.entries.contains_key(&key) {
+            let max_age = config.max_age();
+            state
+                .entries
+                .retain(|_, (stored, _)| stored.elapsed() < max_age);
+            if state.entries.len() >= config.capacity {
+                let oldest = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (stored, _))| *stored)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    state.entries.remove(&oldest);
+                }
+            }
+        }
+        state.entries.insert(key, (Instant::now(), response));
+    }
+}
+
+impl<S, F, K, Request> Service<Request> for Cache<S, F, K, S::Response>
+where
+    S: Service<Request, call(): Send> + Clone + Send + Sync + 'static,
+    S::Response: Clone + Send + Sync + 'static,
+    F: Fn(&Request) -> Option<K>,
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let Some(key) = (self.key_fn)(&request) else {
+            return self.inner.call(request).await;
+        };
+
+        let stale = match self.shared.lookup(&key, &self.config) {
+            Lookup::Fresh(response) | Lookup::Refreshing(response) => return Ok(response),
+            Lookup::Revalidate(response, refresh) => {
+                let inner = self.inner.clone();
+                let config = self.config;
+                tokio::spawn(async move {
+                    if let Ok(response) = inner.call(request).await {
+                        refresh
+                            .shared
+                            .insert(refresh.key.clone(), response, &config);
+                    }
+                });
+                return Ok(response);
+            }
+            Lookup::StaleIfError(response) => Some(response),
+            Lookup::Miss => None,
+        };
+
+        match self.inner.call(request).await {
+            Ok(response) => {
+                self.shared.insert(key, response.clone(), &self.config);
+                Ok(response)
+            }
+            Err(err) => stale.ok_or(err),
+        }
+    }
+}
+
+impl<S, F, K, Response> Clone for Cache<S, F, K, Response>
+where
+    S: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        Cache {
+            inner: self.inner.clone(),
+            key_fn: self.key_fn.clone(),
+            config: self.config,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<S, F, K, Response> fmt::Debug for Cache<S, F, K, Response>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("inner", &self.inner)
+            .field("key_fn", &format_args!("{}", std::any::type_name::<F>()))
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use crate::service_fn;
+
+    #[derive(Clone)]
+    struct Counter {
+        calls: Arc<AtomicUsize>,
+        fail: Arc<AtomicBool>,
+        panic: Arc<AtomicBool>,
+    }
+
+    impl Counter {
+        fn new() -> Self {
+            Counter {
+                calls: Arc::new(AtomicUsize::new(0)),
+                fail: Arc::new(Default::default()),
+                panic: Arc::new(Default::default()),
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+
+        fn fail(&self, fail: bool) {
+            self.fail.store(fail, Ordering::SeqCst);
+        }
+
+        fn panic(&self, panic: bool) {
+            self.panic.store(panic, Ordering::SeqCst);
+        }
+    }
+
+    impl Service<&'static str> for Counter {
+        type Response = String;
+        type Error = &'static str;
+
+        async fn call(&self, key: &'static str) -> Result<Self::Response, Self::Error> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if self.fail.load(Ordering::SeqCst) {
+                return Err("failed");
+            }
+            if self.panic.load(Ordering::SeqCst) {
+                panic!("refresh panicked");
+            }
+            Ok(format!("{key} #{call}"))
+        }
+    }
+
+    fn cache<F>(
+        layer: CacheLayer<F, &'static str, String>,
+        counter: &Counter,
+    ) -> Cache<Counter, F, &'static str, String>
+    where
+        F: Clone,
+    {
+        tower_async_layer::Layer::layer(&layer, counter.clone())
+    }
+
+    fn key(request: &&'static str) -> Option<&'static str> {
+        (*request != "uncached").then_some(*request)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fresh_responses_are_cached() {
+        let counter = Counter::new();
+        let service = cache(CacheLayer::new(key).ttl(Duration::from_secs(10)), &counter);
+
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        assert_eq!(service.call("b").await.unwrap(), "b #2");
+        assert_eq!(service.call("uncached").await.unwrap(), "uncached #3");
+        assert_eq!(service.call("uncached").await.unwrap(), "uncached #4");
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(service.call("a").await.unwrap(), "a #5");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_while_revalidate() {
+        let counter = Counter::new();
+        let service = cache(
+            CacheLayer::new(key)
+                .ttl(Duration::from_secs(10))
+                .stale_while_revalidate(Duration::from_secs(10)),
+            &counter,
+        );
+
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        tokio::time::advance(Duration::from_secs(15)).await;
+
+        // served stale, while refreshing in the background
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        tokio::task::yield_now().await;
+        assert_eq!(counter.calls(), 2);
+        assert_eq!(service.call("a").await.unwrap(), "a #2");
+
+        // too old to be served stale
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert_eq!(service.call("a").await.unwrap(), "a #3");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panicked_refresh_can_be_retried() {
+        let counter = Counter::new();
+        let service = cache(
+            CacheLayer::new(key)
+                .ttl(Duration::from_secs(10))
+                .stale_while_revalidate(Duration::from_secs(60))
+                .max_refreshes(1),
+            &counter,
+        );
+
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        tokio::time::advance(Duration::from_secs(15)).await;
+
+        counter.panic(true);
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        tokio::task::yield_now().await;
+        assert_eq!(counter.calls(), 2);
+
+        // the key and the permit of the refresh were released
+        counter.panic(false);
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        tokio::task::yield_now().await;
+        assert_eq!(counter.calls(), 3);
+        assert_eq!(service.call("a").await.unwrap(), "a #3");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_are_bounded() {
+        let counter = Counter::new();
+        let service = cache(
+            CacheLayer::new(key)
+                .stale_while_revalidate(Duration::from_secs(60))
+                .max_refreshes(1),
+            &counter,
+        );
+
+        service.call("a").await.unwrap();
+        service.call("b").await.unwrap();
+        tokio::time::advance(Duration::from_secs(61)).await;
+
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        assert_eq!(service.call("b").await.unwrap(), "b #2");
+        tokio::task::yield_now().await;
+        assert_eq!(counter.calls(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_if_error() {
+        let counter = Counter::new();
+        let service = cache(
+            CacheLayer::new(key)
+                .ttl(Duration::from_secs(10))
+                .stale_if_error(Duration::from_secs(10)),
+            &counter,
+        );
+
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        counter.fail(true);
+
+        tokio::time::advance(Duration::from_secs(15)).await;
+        assert_eq!(service.call("a").await.unwrap(), "a #1");
+        assert_eq!(counter.calls(), 2);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(service.call("a").await.unwrap_err(), "failed");
+
+        counter.fail(false);
+        assert_eq!(service.call("a").await.unwrap(), "a #4");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn capacity_evicts_oldest() {
+        let counter = Counter::new();
+        let service = cache(CacheLayer::new(key).capacity(2), &counter);
+
+        service.call("a").await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        service.call("b").await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        service.call("c").await.unwrap();
+
+        assert_eq!(service.len(), 2);
+        assert_eq!(service.call("b").await.unwrap(), "b #2");
+        assert_eq!(service.call("a").await.unwrap(), "a #4");
+    }
+
+    #[tokio::test]
+    async fn service_fn_with_closure_key() {
+        let service = CacheLayer::new(|n: &u32| Some(*n % 2));
+        let service = tower_async_layer::Layer::layer(
+            &service,
+            service_fn(|n: u32| async move { Ok::<_, std::convert::Infallible>(n) }),
+        );
+        assert_eq!(service.call(1).await.unwrap(), 1);
+        assert_eq!(service.call(3).await.unwrap(), 1);
+        service.invalidate(&1);
+        assert_eq!(service.call(3).await.unwrap(), 3);
+    }
+}
@@ -0,0 +1,23 @@
+use super::{CircuitBreaker, CircuitBreakerConfig};
+use tower_async_layer::Layer;
+
+/// Wraps services with a [`CircuitBreaker`], each with a circuit of its own.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerLayer {
+    config: CircuitBreakerConfig,
+}
+
+impl CircuitBreakerLayer {
+    /// Creates a new [`CircuitBreakerLayer`] from the given configuration.
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreakerLayer { config }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        CircuitBreaker::new(service, self.config.clone())
+    }
+}
@@ -0,0 +1,547 @@
+//! Middleware that stops calling a failing service for a while, failing requests fast instead.
+//!
+//! A [`CircuitBreaker`] tracks the share of requests which failed over a sliding window of
+//! time. While this share is below the threshold, the circuit is closed and requests are
+//! passed to the inner service. Once enough requests failed, the circuit opens: requests fail
+//! right away with a [`CircuitOpen`] error, without calling the inner service, giving it time
+//! to recover.
+//!
+//! After the open duration the circuit is half-open: a limited number of probe requests is
+//! passed to the inner service, while other requests still fail fast. Once all probes
+//! succeeded the circuit closes again, while a single failed probe opens it again.
+//!
+//! Requests fail if the inner service returns an error. Failed responses, such as an HTTP
+//! response with a server error status, can be turned into errors in front of the circuit
+//! breaker, using [`ServiceExt::map_result`].
+//!
+//! All clones of a [`CircuitBreaker`] share the same circuit.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tower_async::{
+//!     circuit_breaker::{CircuitBreakerConfig, CircuitOpen},
+//!     service_fn, Service, ServiceBuilder,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = ServiceBuilder::new()
+//!     .circuit_breaker(
+//!         CircuitBreakerConfig::new()
+//!             .failure_threshold(0.5)
+//!             .min_requests(2)
+//!             .open_duration(Duration::from_secs(30)),
+//!     )
+//!     .service(service_fn(|_: ()| async { Err::<(), _>("unavailable") }));
+//!
+//! for _ in 0..2 {
+//!     let err = service.call(()).await.unwrap_err();
+//!     assert_eq!(err.to_string(), "unavailable");
+//! }
+//!
+//! // the circuit is open, requests fail without calling the service
+//! let err = service.call(()).await.unwrap_err();
+//! assert!(err.is::<CircuitOpen>());
+//! # }
+//! ```
+//!
+//! [`ServiceExt::map_result`]: crate::ServiceExt::map_result
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tower_async_service::Service;
+
+use crate::BoxError;
+
+mod layer;
+
+pub use self::layer::CircuitBreakerLayer;
+
+const BUCKETS: u32 = 10;
+
+/// The configuration of a [`CircuitBreaker`].
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    failure_threshold: f64,
+    min_requests: u64,
+    window: Duration,
+    open_duration: Duration,
+    probes: usize,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: 0.5,
+            min_requests: 10,
+            window: Duration::from_secs(10),
+            open_duration: Duration::from_secs(30),
+            probes: 3,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Create a new [`CircuitBreakerConfig`] with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the share of failed requests (between 0 and 1) at which the circuit opens,
+    /// defaults to 0.5.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is not larger than 0 and at most 1.
+    pub fn failure_threshold(mut self, threshold: f64) -> Self {
+        assert!(
+            threshold > 0.0 && threshold <= 1.0,
+            "failure_threshold must be larger than 0 and at most 1"
+        );
+        self.failure_threshold = threshold;
+        self
+    }
+
+    /// Sets the minimum number of requests within the window before the circuit can open,
+    /// defaults to 10.
+    pub fn min_requests(mut self, min_requests: u64) -> Self {
+        self.min_requests = min_requests;
+        self
+    }
+
+    /// Sets the duration of the sliding window over which failures are counted,
+    /// defaults to 10 seconds.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the duration for which the circuit stays open before probe requests are let
+    /// through, defaults to 30 seconds.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+
+    /// Sets the number of probe requests let through while the circuit is half-open,
+    /// all of which must succeed for the circuit to close, defaults to 3.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `probes` is 0.
+    pub fn probes(mut self, probes: usize) -> Self {
+        assert!(probes > 0, "probes must be larger than 0");
+        self.probes = probes;
+        self
+    }
+}
+
+/// The state of the circuit of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are passed to the inner service.
+    Closed,
+    /// Requests fail with a [`CircuitOpen`] error.
+    Open,
+    /// A limited number of probe requests is passed to the inner service.
+    HalfOpen,
+}
+
+/// Stops calling the inner service while too many of its requests fail.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct CircuitBreaker<S> {
+    inner: S,
+    config: CircuitBreakerConfig,
+    circuit: Arc<Mutex<Circuit>>,
+}
+
+struct Circuit {
+    state: State,
+    window: Window,
+    /// Incremented on every transition, such that outcomes of requests
+    /// started in a previous state are ignored.
+    generation: u64,
+}
+
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { in_flight: usize, successes: usize },
+}
+
+impl<S> CircuitBreaker<S> {
+    /// Creates a new [`CircuitBreaker`], with a closed circuit.
+    pub fn new(inner: S, config: CircuitBreakerConfig) -> Self {
+        let window = Window::new(config.window);
+        CircuitBreaker {
+            inner,
+            config,
+            circuit: Arc::new(Mutex::new(Circuit {
+                state: State::Closed,
+                window,
+                generation: 0,
+            })),
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a [`CircuitBreaker`] middleware.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(config: CircuitBreakerConfig) -> CircuitBreakerLayer {
+        CircuitBreakerLayer::new(config)
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let circuit = self.circuit.lock().unwrap();
+        match circuit.state {
+            State::Closed => CircuitState::Closed,
+            State::Open { until } if until > Instant::now() => CircuitState::Open,
+            State::Open { .. } | State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Lets a request through, or returns `None` if it must fail fast.
+    fn acquire(&self) -> Option<Permit<'_, S>> {
+        let now = Instant::now();
+        let mut circuit = self.circuit.lock().unwrap();
+        if let State::Open { until } = circuit.state {
+            if until > now {
+                return None;
+            }
+            circuit.transition(State::HalfOpen {
+                in_flight: 0,
+                successes: 0,
+            });
+        }
+        let probe = match &mut circuit.state {
+            State::Closed => false,
+            State::HalfOpen { in_flight, .. } if *in_flight < self.config.probes => {
+                *in_flight += 1;
+                true
+            }
+            State::HalfOpen { .. } | State::Open { .. } => return None,
+        };
+        Some(Permit {
+            breaker: self,
+            generation: circuit.generation,
+            probe,
+            done: false,
+        })
+    }
+}
+
+impl Circuit {
+    fn transition(&mut self, state: State) {
+        self.state = state;
+        self.generation += 1;
+        self.window.clear();
+    }
+}
+
+/// A request let through by the circuit breaker, recording its outcome.
+struct Permit<'a, S> {
+    breaker: &'a CircuitBreaker<S>,
+    generation: u64,
+    probe: bool,
+    done: bool,
+}
+
+impl<S> Permit<'_, S> {
+    fn record(mut self, success: bool) {
+        self.done = true;
+        let config = &self.breaker.config;
+        let now = Instant::now();
+        let mut circuit = self.breaker.circuit.lock().unwrap();
+        if circuit.generation != self.generation {
+            return;
+        }
+        match &mut circuit.state {
+            State::Closed => {
+                circuit.window.record(now, success);
+                let (total, failures) = circuit.window.totals(now);
+                if total >= config.min_requests.max(1)
+                    && failures as f64 >= total as f64 * config.failure_threshold
+                {
+                    circuit.transition(State::Open {
+                        until: now + config.open_duration,
+                    });
+                }
+            }
+            State::HalfOpen {
+                in_flight,
+                successes,
+            } => {
+                *in_flight -= 1;
+                if !success {
+                    circuit.transition(State::Open {
+                        until: now + config.open_duration,
+                    });
+                } else {
+                    *successes += 1;
+                    if *successes >= config.probes {
+                        circuit.transition(State::Closed);
+                    }
+                }
+            }
+            State::Open { .. } => {}
+        }
+    }
+}
+
+impl<S> Drop for Permit<'_, S> {
+    fn drop(&mut self) {
+        // a cancelled probe makes room for another one
+        if self.done || !self.probe {
+            return;
+        }
+        let mut circuit = self.breaker.circuit.lock().unwrap();
+        if circuit.generation != self.generation {
+            return;
+        }
+        if let State::HalfOpen { in_flight, .. } = &mut circuit.state {
+            *in_flight -= 1;
+        }
+    }
+}
+
+impl<S, Request> Service<Request> for CircuitBreaker<S>
+where
+    S: Service<Request>,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let permit = self.acquire().ok_or(CircuitOpen)?;
+        let result = self.inner.call(request).await;
+        permit.record(result.is_ok());
+        result.map_err(Into::into)
+    }
+}
+
+impl<S> fmt::Debug for CircuitBreaker<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// Counts the requests and failures over a sliding window, in buckets of a tenth
+/// of the window.
+struct Window {
+    buckets: VecDeque<Bucket>,
+    width: Duration,
+}
+
+struct Bucket {
+    start: Instant,
+    total: u64,
+    failures: u64,
+}
+
+impl Window {
+    fn new(window: Duration) -> Self {
+        Window {
+            buckets: VecDeque::new(),
+            width: window / BUCKETS,
+        }
+    }
+
+    fn record(&mut self, now: Instant, success: bool) {
+        match self.buckets.back_mut() {
+            Some(bucket) if now < bucket.start + self.width => {
+                bucket.total += 1;
+                bucket.failures += !success as u64;
+            }
+            _ => self.buckets.push_back(Bucket {
+                start: now,
+                total: 1,
+                failures: !success as u64,
+            }),
+        }
+    }
+
+    /// Returns the number of requests and failures within the window.
+    fn totals(&mut self, now: Instant) -> (u64, u64) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| bucket.start + self.width * BUCKETS <= now)
+        {
+            self.buckets.pop_front();
+        }
+        self.buckets
+            .iter()
+            .fold((0, 0), |(total, failures), bucket| {
+                (total + bucket.total, failures + bucket.failures)
+            })
+    }
+
+    fn clear(&mut self) {
+        self.buckets.clear();
+    }
+}
+
+/// The error that indicates the request failed fast,
+/// because the circuit of the [`CircuitBreaker`] is open.
+#[derive(Debug)]
+pub struct CircuitOpen;
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("circuit breaker is open")
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use crate::service_fn;
+
+    struct Backend {
+        healthy: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    fn breaker(
+        config: CircuitBreakerConfig,
+    ) -> (
+        Arc<Backend>,
+        impl Service<(), Response = (), Error = BoxError>,
+    ) {
+        let backend = Arc::new(Backend {
+            healthy: AtomicBool::new(false),
+            calls: AtomicUsize::new(0),
+        });
+        let service = CircuitBreaker::new(
+            service_fn({
+                let backend = backend.clone();
+                move |()| {
+                    let backend = backend.clone();
+                    async move {
+                        backend.calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        if backend.healthy.load(Ordering::SeqCst) {
+                            Ok(())
+                        } else {
+                            Err("unavailable")
+                        }
+                    }
+                }
+            }),
+            config,
+        );
+        (backend, service)
+    }
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig::new()
+            .failure_threshold(0.5)
+            .min_requests(4)
+            .window(Duration::from_secs(10))
+            .open_duration(Duration::from_secs(30))
+            .probes(2)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn opens_and_closes() {
+        let (backend, service) = breaker(config());
+
+        for _ in 0..4 {
+            let err = service.call(()).await.unwrap_err();
+            assert!(!err.is::<CircuitOpen>());
+        }
+        let err = service.call(()).await.unwrap_err();
+        assert!(err.is::<CircuitOpen>());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 4);
+
+        // after the open duration, only the probes are let through
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        backend.healthy.store(true, Ordering::SeqCst);
+        let (a, b, c) = tokio::join!(service.call(()), service.call(()), service.call(()));
+        assert!(a.is_ok() && b.is_ok());
+        assert!(c.unwrap_err().is::<CircuitOpen>());
+
+        // all probes succeeded, the circuit is closed
+        for _ in 0..10 {
+            service.call(()).await.unwrap();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_probe_opens_again() {
+        let (backend, service) = breaker(config());
+        for _ in 0..4 {
+            let _ = service.call(()).await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        let err = service.call(()).await.unwrap_err();
+        assert!(!err.is::<CircuitOpen>());
+        let err = service.call(()).await.unwrap_err();
+        assert!(err.is::<CircuitOpen>());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failures_expire() {
+        let (backend, service) = breaker(config());
+        for _ in 0..3 {
+            let _ = service.call(()).await;
+        }
+
+        // the failures leave the window before the circuit opens
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        for _ in 0..3 {
+            let err = service.call(()).await.unwrap_err();
+            assert!(!err.is::<CircuitOpen>());
+        }
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn state() {
+        let service = CircuitBreaker::new(
+            service_fn(|()| async { Err::<(), _>("unavailable") }),
+            config().min_requests(1),
+        );
+        assert_eq!(service.state(), CircuitState::Closed);
+        let _ = service.call(()).await;
+        assert_eq!(service.state(), CircuitState::Open);
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(service.state(), CircuitState::HalfOpen);
+    }
+}
@@ -0,0 +1,330 @@
+//! Serve non-HTTP protocols with a [`Service`], framed by a codec.
+//!
+//! A [`Server`] reads requests from an [`AsyncRead`] + [`AsyncWrite`] connection using a
+//! [`Decoder`], calls the service for each of them and writes the responses back using an
+//! [`Encoder`]. Any codec of [`tokio_util::codec`] can be used, such as the
+//! [`LengthDelimitedCodec`] or the [`LinesCodec`], so a TCP protocol can be served by the same
+//! middleware stacks as HTTP services.
+//!
+//! Two modes are supported:
+//!
+//! - [`Server::serve`] handles one request at a time and writes the responses in the order of
+//!   the requests, which suits simple request / response protocols. Clients may still pipeline
+//!   requests, they are buffered until the previous response has been written;
+//! - [`Server::serve_multiplexed`] handles up to [`max_in_flight`] requests concurrently and
+//!   writes each response as soon as it is ready. Requests and responses therefore carry an id,
+//!   such that clients can match them. The [`Tagged`] codec adds such an id to the frames of a
+//!   byte oriented codec.
+//!
+//! Both are built on the drivers of the [`transport`] module, which also provides the clients
+//! for these protocols.
+//!
+//! The connection is served until the peer closes it, or until the codec or the service
+//! returns an error. Services which can fail for a single request without breaking the
+//! connection should turn their errors into responses instead.
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+//! use tokio_util::codec::LinesCodec;
+//! use tower_async::{codec::Server, service_fn};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let (client, server) = tokio::io::duplex(64);
+//!
+//! tokio::spawn(async move {
+//!     let service = service_fn(|line: String| async move {
+//!         Ok::<_, Infallible>(line.to_uppercase())
+//!     });
+//!     Server::new(service)
+//!         .serve(server, LinesCodec::new())
+//!         .await
+//!         .unwrap();
+//! });
+//!
+//! let (read, mut write) = tokio::io::split(client);
+//! write.write_all(b"hello\nworld\n").await.unwrap();
+//!
+//! let mut lines = BufReader::new(read).lines();
+//! assert_eq!(lines.next_line().await.unwrap().unwrap(), "HELLO");
+//! assert_eq!(lines.next_line().await.unwrap().unwrap(), "WORLD");
+//! # }
+//! ```
+//!
+//! [`AsyncRead`]: tokio::io::AsyncRead
+//! [`AsyncWrite`]: tokio::io::AsyncWrite
+//! [`LengthDelimitedCodec`]: tokio_util::codec::LengthDelimitedCodec
+//! [`LinesCodec`]: tokio_util::codec::LinesCodec
+//! [`max_in_flight`]: Server::max_in_flight
+//! [`transport`]: crate::transport
+
+use std::{error, fmt};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use tower_async_service::Service;
+
+use crate::transport;
+
+mod tagged;
+
+pub use self::tagged::Tagged;
+
+/// Serves connections framed by a codec using a [`Service`].
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Server<S> {
+    service: S,
+    max_in_flight: usize,
+}
+
+impl<S> Server<S> {
+    /// Creates a new [`Server`] calling the given service for each request.
+    pub fn new(service: S) -> Self {
+        Server {
+            service,
+            max_in_flight: 32,
+        }
+    }
+
+    /// Sets the maximum number of requests handled concurrently by
+    /// [`Server::serve_multiplexed`], defaults to 32.
+    ///
+    /// No more requests are read from a connection while this many requests are in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be larger than 0");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Consume `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+
+    /// Serves the connection, handling one request at a time.
+    ///
+    /// Responses are written in the order of the requests. Returns once the peer closed
+    /// the connection, after having written the responses and shut down the connection.
+    pub async fn serve<IO, C, Request>(
+        &self,
+        io: IO,
+        codec: C,
+    ) -> Result<(), ServeError<<C as Decoder>::Error, S::Error>>
+    where
+        IO: AsyncRead + AsyncWrite,
+        C: Decoder<Item = Request> + Encoder<S::Response, Error = <C as Decoder>::Error>,
+        S: Service<Request>,
+    {
+        transport::pipeline::serve(&self.service, 1, Framed::new(io, codec))
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Serves the connection, handling up to [`max_in_flight`] requests concurrently.
+    ///
+    /// The codec decodes requests together with their id, which is used to encode the
+    /// matching response. Responses are written as soon as they are ready, regardless of the
+    /// order of the requests. Returns once the peer closed the connection, after having
+    /// written the responses of all requests in flight and shut down the connection.
+    ///
+    /// The requests are handled on the task calling this method, such that the service
+    /// does not need to be `Send` nor `'static`.
+    ///
+    /// [`max_in_flight`]: Server::max_in_flight
+    pub async fn serve_multiplexed<IO, C, Id, Request>(
+        &self,
+        io: IO,
+        codec: C,
+    ) -> Result<(), ServeError<<C as Decoder>::Error, S::Error>>
+    where
+        IO: AsyncRead + AsyncWrite,
+        C: Decoder<Item = (Id, Request)>
+            + Encoder<(Id, S::Response), Error = <C as Decoder>::Error>,
+        S: Service<Request>,
+    {
+        transport::multiplex::serve(&self.service, self.max_in_flight, Framed::new(io, codec))
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// Error returned by [`Server::serve`] and [`Server::serve_multiplexed`].
+#[derive(Debug)]
+pub enum ServeError<C, S> {
+    /// The codec failed to decode a request or to encode a response,
+    /// which includes I/O errors of the connection.
+    Codec(C),
+    /// The service failed to handle a request.
+    Service(S),
+}
+
+impl<C, S> From<transport::ServeError<C, S>> for ServeError<C, S> {
+    fn from(err: transport::ServeError<C, S>) -> Self {
+        match err {
+            transport::ServeError::Transport(err) => ServeError::Codec(err),
+            transport::ServeError::Service(err) => ServeError::Service(err),
+        }
+    }
+}
+
+impl<C, S> fmt::Display for ServeError<C, S>
+where
+    C: fmt::Display,
+    S: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServeError::Codec(err) => write!(f, "codec error: {err}"),
+            ServeError::Service(err) => write!(f, "service error: {err}"),
+        }
+    }
+}
+
+impl<C, S> error::Error for ServeError<C, S>
+where
+    C: error::Error + 'static,
+    S: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ServeError::Codec(err) => Some(err),
+            ServeError::Service(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{convert::Infallible, io, time::Duration};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::{
+        bytes::{Bytes, BytesMut},
+        codec::{LengthDelimitedCodec, LinesCodec},
+    };
+
+    use crate::service_fn;
+
+    #[tokio::test]
+    async fn serve_answers_in_order() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let service =
+            service_fn(|line: String| async move { Ok::<_, Infallible>(line.len().to_string()) });
+
+        client.write_all(b"a\nabc\nab\n").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        Server::new(service)
+            .serve(server, LinesCodec::new())
+            .await
+            .unwrap();
+
+        let mut output = String::new();
+        client.read_to_string(&mut output).await.unwrap();
+        assert_eq!(output, "1\n3\n2\n");
+    }
+
+    #[tokio::test]
+    async fn serve_stops_on_service_error() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let service = service_fn(|line: String| async move {
+            if line == "fail" {
+                Err("failed")
+            } else {
+                Ok(line)
+            }
+        });
+
+        client.write_all(b"ok\nfail\nnever\n").await.unwrap();
+
+        let err = Server::new(service)
+            .serve(server, LinesCodec::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServeError::Service("failed")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serve_multiplexed_answers_when_ready() {
+        let (mut client, server) = tokio::io::duplex(256);
+        let service = service_fn(|payload: BytesMut| async move {
+            let delay = payload[0] as u64;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Ok::<_, io::Error>(payload.freeze())
+        });
+
+        let mut codec = Tagged::new(LengthDelimitedCodec::new());
+        let mut buf = BytesMut::new();
+        for (id, delay) in [(1, 30), (2, 10), (3, 20)] {
+            codec
+                .encode((id, Bytes::from(vec![delay])), &mut buf)
+                .unwrap();
+        }
+        client.write_all(&buf).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        Server::new(service)
+            .serve_multiplexed(server, Tagged::new(LengthDelimitedCodec::new()))
+            .await
+            .unwrap();
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        let mut output = BytesMut::from(&output[..]);
+        let mut ids = Vec::new();
+        while let Some((id, _)) = codec.decode(&mut output).unwrap() {
+            ids.push(id);
+        }
+        assert_eq!(ids, [2, 3, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serve_multiplexed_limits_in_flight() {
+        let (mut client, server) = tokio::io::duplex(256);
+        let service = service_fn(|payload: BytesMut| async move {
+            let delay = payload[0] as u64;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Ok::<_, io::Error>(payload.freeze())
+        });
+
+        let mut codec = Tagged::new(LengthDelimitedCodec::new());
+        let mut buf = BytesMut::new();
+        for (id, delay) in [(1, 30), (2, 10), (3, 5)] {
+            codec
+                .encode((id, Bytes::from(vec![delay])), &mut buf)
+                .unwrap();
+        }
+        client.write_all(&buf).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        Server::new(service)
+            .max_in_flight(1)
+            .serve_multiplexed(server, Tagged::new(LengthDelimitedCodec::new()))
+            .await
+            .unwrap();
+
+        let mut output = Vec::new();
+        client.read_to_end(&mut output).await.unwrap();
+        let mut output = BytesMut::from(&output[..]);
+        let mut ids = Vec::new();
+        while let Some((id, _)) = codec.decode(&mut output).unwrap() {
+            ids.push(id);
+        }
+        assert_eq!(ids, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::io;
+
+use tokio_util::{
+    bytes::{BufMut, Bytes, BytesMut},
+    codec::{Decoder, Encoder},
+};
+
+/// A codec prefixing the frames of an inner, byte oriented, codec with a request id.
+///
+/// Each frame starts with the id, encoded as a big-endian `u64`, followed by the payload.
+/// Decoded requests are `(u64, BytesMut)` pairs and responses are encoded from `(u64, Bytes)`
+/// pairs, such that it can be used by [`Server::serve_multiplexed`]. Frames shorter than the
+/// id result in an [`io::ErrorKind::InvalidData`] error.
+///
+/// # Example
+///
+/// ```
+/// use tokio_util::codec::LengthDelimitedCodec;
+/// use tower_async::codec::Tagged;
+///
+/// // | length: u32 | id: u64 | payload |
+/// let codec = Tagged::new(LengthDelimitedCodec::new());
+/// ```
+///
+/// [`Server::serve_multiplexed`]: crate::codec::Server::serve_multiplexed
+#[derive(Debug, Clone, Default)]
+pub struct Tagged<C> {
+    inner: C,
+}
+
+impl<C> Tagged<C> {
+    /// Creates a new [`Tagged`] codec, framing the tagged payloads using `inner`.
+    pub fn new(inner: C) -> Self {
+        Tagged { inner }
+    }
+
+    /// Get a reference to the inner codec.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner codec.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consume `self`, returning the inner codec.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+const ID_LEN: usize = std::mem::size_of::<u64>();
+
+impl<C> Decoder for Tagged<C>
+where
+    C: Decoder<Item = BytesMut>,
+    C::Error: From<io::Error>,
+{
+    type Item = (u64, BytesMut);
+    type Error = C::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.inner.decode(src)?.map(untag).transpose()
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.inner.decode_eof(src)?.map(untag).transpose()
+    }
+}
+
+fn untag<E: From<io::Error>>(mut frame: BytesMut) -> Result<(u64, BytesMut), E> {
+    if frame.len() < ID_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame is missing its id").into());
+    }
+    let payload = frame.split_off(ID_LEN);
+    let id = u64::from_be_bytes(frame[..].try_into().expect("id is 8 bytes long"));
+    Ok((id, payload))
+}
+
+impl<C> Encoder<(u64, Bytes)> for Tagged<C>
+where
+    C: Encoder<Bytes>,
+{
+    type Error = C::Error;
+
+    fn encode(&mut self, (id, payload): (u64, Bytes), dst: &mut BytesMut) -> Result<(), C::Error> {
+        let mut frame = BytesMut::with_capacity(ID_LEN + payload.len());
+        frame.put_u64(id);
+        frame.put(payload);
+        self.inner.encode(frame.freeze(), dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_util::codec::LengthDelimitedCodec;
+
+    #[test]
+    fn round_trip() {
+        let mut codec = Tagged::new(LengthDelimitedCodec::new());
+        let mut buf = BytesMut::new();
+        codec
+            .encode((42, Bytes::from_static(b"ping")), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], b"\0\0\0\x0c\0\0\0\0\0\0\0\x2aping");
+
+        let (id, payload) = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(id, 42);
+        assert_eq!(&payload[..], b"ping");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn frame_without_id() {
+        let mut codec = Tagged::new(LengthDelimitedCodec::new());
+        let mut buf = BytesMut::from(&b"\0\0\0\x02hi"[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
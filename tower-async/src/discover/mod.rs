@@ -0,0 +1,328 @@
+//! Service discovery, finding the endpoints of a set of services as they change over time.
+//!
+//! A [`Discover`] source reports endpoints joining and leaving a set as [`Change`]s, each
+//! identified by a key. Balancers and pools, such as a [`Shard`], wait for these changes
+//! while serving requests, adding and removing services as they are discovered.
+//!
+//! This module provides two sources:
+//!
+//! - [`ServiceList`] discovers a fixed list of services, inserting each of them once;
+//! - [`WatchDiscover`] discovers a set of services published through a
+//!   [`tokio::sync::watch`] channel, diffing each new snapshot against the previous one.
+//!   Services which changed under an existing key are inserted again, replacing the
+//!   previous service.
+//!
+//! In large deployments, [`Subset`] limits the services discovered by another source
+//! to a stable subset per client, bounding the number of connections of each client.
+//!
+//! [`Shard`]: crate::shard::Shard
+//!
+//! # Example
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use tokio::sync::watch;
+//! use tower_async::discover::{Change, Discover, WatchDiscover};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let (tx, rx) = watch::channel(HashMap::from([("10.0.0.1:80", "a")]));
+//! let discover = WatchDiscover::new(rx);
+//!
+//! let change = discover.discover().await.unwrap();
+//! assert!(matches!(change, Change::Insert("10.0.0.1:80", "a")));
+//!
+//! // the endpoints are replaced by the publisher, such as a DNS resolver
+//! tx.send_replace(HashMap::from([("10.0.0.2:80", "b")]));
+//!
+//! let mut changes = vec![
+//!     discover.discover().await.unwrap(),
+//!     discover.discover().await.unwrap(),
+//! ];
+//! changes.sort_by_key(|change| matches!(change, Change::Insert(..)));
+//! assert!(matches!(changes[0], Change::Remove("10.0.0.1:80")));
+//! assert!(matches!(changes[1], Change::Insert("10.0.0.2:80", "b")));
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    fmt,
+    future::Future,
+    hash::Hash,
+    sync::Mutex,
+};
+
+use tokio::sync::watch;
+
+mod subset;
+
+pub use self::subset::Subset;
+
+/// A change in the set of discovered services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change<K, S> {
+    /// A new service, identified by the key, was discovered.
+    ///
+    /// If a service with the same key was discovered before, it is replaced.
+    Insert(K, S),
+    /// The service identified by the key has left the set.
+    Remove(K),
+}
+
+impl<K, S> Change<K, S> {
+    /// Returns the key of the service this change applies to.
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Insert(key, _) | Change::Remove(key) => key,
+        }
+    }
+}
+
+/// A source of [`Change`]s in a set of services.
+///
+/// See the [module docs](self) for more details.
+pub trait Discover {
+    /// The key identifying a discovered service.
+    type Key: Eq;
+    /// The discovered services.
+    type Service;
+    /// The error returned when the set of services could not be discovered.
+    type Error;
+
+    /// Wait for the next change in the set of services.
+    ///
+    /// Sources which will not report any further changes, such as a [`ServiceList`]
+    /// which inserted all of its services, never resolve.
+    fn discover(
+        &self,
+    ) -> impl Future<Output = Result<Change<Self::Key, Self::Service>, Self::Error>>;
+}
+
+/// Discovers a fixed list of services, keyed by their index in the list.
+///
+/// Each service is inserted once, after which no further changes are discovered.
+#[derive(Debug)]
+pub struct ServiceList<S> {
+    services: Mutex<std::iter::Enumerate<std::vec::IntoIter<S>>>,
+}
+
+impl<S> ServiceList<S> {
+    /// Create a new [`ServiceList`], discovering the given services.
+    pub fn new(services: impl IntoIterator<Item = S>) -> Self {
+        let services: Vec<_> = services.into_iter().collect();
+        Self {
+            services: Mutex::new(services.into_iter().enumerate()),
+        }
+    }
+}
+
+impl<S> FromIterator<S> for ServiceList<S> {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self::new(iter)
+    }
+}
+
+impl<S> Discover for ServiceList<S> {
+    type Key = usize;
+    type Service = S;
+    type Error = Infallible;
+
+    async fn discover(&self) -> Result<Change<Self::Key, Self::Service>, Self::Error> {
+        let next = self.services.lock().unwrap().next();
+        match next {
+            Some((index, service)) => Ok(Change::Insert(index, service)),
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Discovers a set of services published through a [`watch`] channel.
+///
+/// The services currently in the channel are inserted first. Every time a new set is
+/// published, it is compared to the previous one: services with a new key are inserted,
+/// services whose key is gone are removed and services which aren't equal to the previous
+/// service with their key are inserted again, replacing it.
+///
+/// Services which can't be compared, such as closures, can be discovered with
+/// [`WatchDiscover::by_key`] instead.
+///
+/// Once all senders are dropped, no further changes are discovered.
+pub struct WatchDiscover<K, S> {
+    state: tokio::sync::Mutex<WatchState<K, S>>,
+}
+
+struct WatchState<K, S> {
+    receiver: watch::Receiver<HashMap<K, S>>,
+    known: HashMap<K, S>,
+    pending: VecDeque<Change<K, S>>,
+    initialized: bool,
+    /// Returns whether the service published under a known key changed.
+    changed: fn(&S, &S) -> bool,
+}
+
+impl<K, S> WatchDiscover<K, S> {
+    /// Create a new [`WatchDiscover`], discovering the services published through
+    /// the given receiver.
+    pub fn new(receiver: watch::Receiver<HashMap<K, S>>) -> Self
+    where
+        S: PartialEq,
+    {
+        Self::with_changed(receiver, S::ne)
+    }
+
+    /// Create a new [`WatchDiscover`], discovering the services published through
+    /// the given receiver by key only.
+    ///
+    /// Services whose key remains in the set are not inserted again, even if the service
+    /// itself changed, so a publisher replacing a service should give it a new key.
+    pub fn by_key(receiver: watch::Receiver<HashMap<K, S>>) -> Self {
+        Self::with_changed(receiver, |_, _| false)
+    }
+
+    fn with_changed(receiver: watch::Receiver<HashMap<K, S>>, changed: fn(&S, &S) -> bool) -> Self {
+        Self {
+            state: tokio::sync::Mutex::new(WatchState {
+                receiver,
+                known: HashMap::new(),
+                pending: VecDeque::new(),
+                initialized: false,
+                changed,
+            }),
+        }
+    }
+}
+
+impl<K, S> WatchState<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: Clone,
+{
+    /// Queue the changes between the known services and the current set of services.
+    fn diff(&mut self) {
+        let services = self.receiver.borrow_and_update();
+        self.known.retain(|key, _| {
+            let retain = services.contains_key(key);
+            if !retain {
+                self.pending.push_back(Change::Remove(key.clone()));
+            }
+            retain
+        });
+        for (key, service) in services.iter() {
+            let changed = match self.known.get_mut(key) {
+                Some(known) if (self.changed)(known, service) => {
+                    *known = service.clone();
+                    true
+                }
+                Some(_) => false,
+                None => {
+                    self.known.insert(key.clone(), service.clone());
+                    true
+                }
+            };
+            if changed {
+                self.pending
+                    .push_back(Change::Insert(key.clone(), service.clone()));
+            }
+        }
+    }
+}
+
+impl<K, S> Discover for WatchDiscover<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: Clone,
+{
+    type Key = K;
+    type Service = S;
+    type Error = Infallible;
+
+    async fn discover(&self) -> Result<Change<Self::Key, Self::Service>, Self::Error> {
+        let mut state = self.state.lock().await;
+        if !state.initialized {
+            state.initialized = true;
+            state.diff();
+        }
+        loop {
+            if let Some(change) = state.pending.pop_front() {
+                return Ok(change);
+            }
+            if state.receiver.changed().await.is_err() {
+                drop(state);
+                return std::future::pending().await;
+            }
+            state.diff();
+        }
+    }
+}
+
+impl<K, S> fmt::Debug for WatchDiscover<K, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchDiscover").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    async fn next<D: Discover>(discover: &D) -> Option<Change<D::Key, D::Service>>
+    where
+        D::Error: fmt::Debug,
+    {
+        tokio::time::timeout(Duration::from_secs(1), discover.discover())
+            .await
+            .ok()
+            .map(Result::unwrap)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn service_list() {
+        let discover: ServiceList<_> = ["a", "b"].into_iter().collect();
+        assert_eq!(next(&discover).await, Some(Change::Insert(0, "a")));
+        assert_eq!(next(&discover).await, Some(Change::Insert(1, "b")));
+        assert_eq!(next(&discover).await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watch_discover() {
+        let (tx, rx) = watch::channel(HashMap::from([(1, "a")]));
+        let discover = WatchDiscover::new(rx);
+        assert_eq!(next(&discover).await, Some(Change::Insert(1, "a")));
+        assert_eq!(next(&discover).await, None);
+
+        tx.send_replace(HashMap::from([(1, "a"), (2, "b")]));
+        assert_eq!(next(&discover).await, Some(Change::Insert(2, "b")));
+        assert_eq!(next(&discover).await, None);
+
+        // services which changed under their key are inserted again
+        tx.send_replace(HashMap::from([(1, "a2"), (2, "b")]));
+        assert_eq!(next(&discover).await, Some(Change::Insert(1, "a2")));
+        assert_eq!(next(&discover).await, None);
+
+        tx.send_replace(HashMap::from([(2, "b")]));
+        assert_eq!(next(&discover).await, Some(Change::Remove(1)));
+
+        // the key of a removed service can be inserted again
+        tx.send_replace(HashMap::from([(1, "a3"), (2, "b")]));
+        assert_eq!(next(&discover).await, Some(Change::Insert(1, "a3")));
+
+        drop(tx);
+        assert_eq!(next(&discover).await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watch_discover_by_key() {
+        let (tx, rx) = watch::channel(HashMap::from([(1, "a")]));
+        let discover = WatchDiscover::by_key(rx);
+        assert_eq!(next(&discover).await, Some(Change::Insert(1, "a")));
+
+        // services which keep their key are not inserted again
+        tx.send_replace(HashMap::from([(1, "a2"), (2, "b")]));
+        assert_eq!(next(&discover).await, Some(Change::Insert(2, "b")));
+        assert_eq!(next(&discover).await, None);
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    fmt,
+    hash::{BuildHasher, BuildHasherDefault, Hash},
+};
+
+use super::{Change, Discover};
+
+/// Discovers a deterministic subset of the services discovered by another source.
+///
+/// In large deployments, every client connecting to every backend adds up to a lot of
+/// connections. With subsetting, each client only uses `size` of the backends. The subset is
+/// chosen using rendezvous hashing: every service is scored by hashing its key together with
+/// the id of the client, and the subset holds the services with the highest scores. Clients
+/// with different ids therefore pick different subsets, spreading the load over all backends,
+/// while the subset of a client is stable: a service joining or leaving the set only replaces
+/// at most one service of the subset.
+///
+/// When a service of the subset is removed, the service with the next highest score takes its
+/// place. When a service with a higher score than a service of the subset is discovered, it
+/// replaces the service with the lowest score, which is then removed. The services are
+/// [`Clone`], as services outside of the subset are kept to be inserted later.
+///
+/// # Example
+///
+/// ```
+/// use std::{collections::HashSet, time::Duration};
+/// use tower_async::discover::{Change, Discover, ServiceList, Subset};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let backends = ServiceList::new(["a", "b", "c", "d", "e", "f"]);
+/// let discover = Subset::new(backends, "client-42", 2);
+///
+/// let mut subset = HashSet::new();
+/// let next = || tokio::time::timeout(Duration::from_millis(10), discover.discover());
+/// while let Ok(change) = next().await {
+///     match change.unwrap() {
+///         Change::Insert(index, _) => subset.insert(index),
+///         Change::Remove(index) => subset.remove(&index),
+///     };
+/// }
+///
+/// // only two of the six backends are used
+/// assert_eq!(subset.len(), 2);
+/// # }
+/// ```
+pub struct Subset<D, H = BuildHasherDefault<DefaultHasher>>
+where
+    D: Discover,
+{
+    inner: D,
+    seed: u64,
+    hasher: H,
+    size: usize,
+    state: tokio::sync::Mutex<SubsetState<D::Key, D::Service>>,
+}
+
+struct SubsetState<K, S> {
+    /// All discovered services, with their score and whether they are part of the subset.
+    services: HashMap<K, Entry<S>>,
+    members: usize,
+    pending: VecDeque<Change<K, S>>,
+}
+
+struct Entry<S> {
+    service: S,
+    score: u64,
+    member: bool,
+}
+
+impl<D> Subset<D>
+where
+    D: Discover,
+{
+    /// Create a new [`Subset`], discovering `size` of the services discovered by `inner`,
+    /// picked for the client with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn new(inner: D, client_id: impl Hash, size: usize) -> Self {
+        Self::with_hasher(inner, client_id, size, BuildHasherDefault::default())
+    }
+}
+
+impl<D, H> Subset<D, H>
+where
+    D: Discover,
+    H: BuildHasher,
+{
+    /// Create a new [`Subset`] like [`Subset::new`], scoring the services
+    /// using the given hasher.
+    ///
+    /// Clients which share the placement of their subsets with other processes
+    /// should use a hasher with a stable output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn with_hasher(inner: D, client_id: impl Hash, size: usize, hasher: H) -> Self {
+        assert!(size > 0, "size must be larger than 0");
+        Subset {
+            inner,
+            seed: hasher.hash_one(client_id),
+            hasher,
+            size,
+            state: tokio::sync::Mutex::new(SubsetState {
+                services: HashMap::new(),
+                members: 0,
+                pending: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Get a reference to the inner [`Discover`] source.
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+}
+
+impl<K, S> SubsetState<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: Clone,
+{
+    /// Applies a change of the inner source, queueing the resulting changes of the subset.
+    fn apply(&mut self, change: Change<K, S>, score: u64, size: usize) {
+        match change {
+            Change::Insert(key, service) => {
+                if let Some(entry) = self.services.get_mut(&key) {
+                    entry.service = service.clone();
+                    if entry.member {
+                        self.pending.push_back(Change::Insert(key, service));
+                    }
+                    return;
+                }
+
+                let lowest = self.lowest_member();
+                let member = match lowest {
+                    _ if self.members < size => true,
+                    Some((_, lowest)) => score > lowest,
+                    None => false,
+                };
+                self.services.insert(
+                    key.clone(),
+                    Entry {
+                        service: service.clone(),
+                        score,
+                        member,
+                    },
+                );
+                if !member {
+                    return;
+                }
+                self.members += 1;
+                self.pending.push_back(Change::Insert(key, service));
+                if self.members > size {
+                    if let Some((lowest, _)) = lowest {
+                        self.services.get_mut(&lowest).unwrap().member = false;
+                        self.members -= 1;
+                        self.pending.push_back(Change::Remove(lowest));
+                    }
+                }
+            }
+            Change::Remove(key) => {
+                let Some(entry) = self.services.remove(&key) else {
+                    return;
+                };
+                if !entry.member {
+                    return;
+                }
+                self.members -= 1;
+                if let Some(next) = self.highest_non_member() {
+                    let entry = self.services.get_mut(&next).unwrap();
+                    entry.member = true;
+                    self.members += 1;
+                    let service = entry.service.clone();
+                    self.pending.push_back(Change::Insert(next, service));
+                }
+                self.pending.push_back(Change::Remove(key));
+            }
+        }
+    }
+
+    fn lowest_member(&self) -> Option<(K, u64)> {
+        self.services
+            .iter()
+            .filter(|(_, entry)| entry.member)
+            .min_by_key(|(_, entry)| entry.score)
+            .map(|(key, entry)| (key.clone(), entry.score))
+    }
+
+    fn highest_non_member(&self) -> Option<K> {
+        self.services
+            .iter()
+            .filter(|(_, entry)| !entry.member)
+            .max_by_key(|(_, entry)| entry.score)
+            .map(|(key, _)| key.clone())
+    }
+}
+
+impl<D, H> Discover for Subset<D, H>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Clone,
+    H: BuildHasher,
+{
+    type Key = D::Key;
+    type Service = D::Service;
+    type Error = D::Error;
+
+    async fn discover(&self) -> Result<Change<Self::Key, Self::Service>, Self::Error> {
+        let mut state = self.state.lock().await;
+        loop {
+            if let Some(change) = state.pending.pop_front() {
+                return Ok(change);
+            }
+            let change = self.inner.discover().await?;
+            let score = self.hasher.hash_one((self.seed, change.key()));
+            state.apply(change, score, self.size);
+        }
+    }
+}
+
+impl<D, H> fmt::Debug for Subset<D, H>
+where
+    D: Discover + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subset")
+            .field("inner", &self.inner)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{collections::HashSet, time::Duration};
+
+    use tokio::sync::watch;
+
+    use crate::discover::{ServiceList, WatchDiscover};
+
+    /// Applies the changes discovered until none are ready within a second.
+    async fn members<D>(discover: &D, members: &mut HashSet<D::Key>)
+    where
+        D: Discover,
+        D::Key: Hash + Eq,
+        D::Error: fmt::Debug,
+    {
+        while let Ok(change) =
+            tokio::time::timeout(Duration::from_secs(1), discover.discover()).await
+        {
+            match change.unwrap() {
+                Change::Insert(key, _) => members.insert(key),
+                Change::Remove(key) => members.remove(&key),
+            };
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn picks_stable_subsets() {
+        let mut subsets = Vec::new();
+        for client in ["a", "b", "a"] {
+            let discover = Subset::new(ServiceList::new(0..10), client, 3);
+            let mut subset = HashSet::new();
+            members(&discover, &mut subset).await;
+            assert_eq!(subset.len(), 3);
+            subsets.push(subset);
+        }
+        assert_eq!(subsets[0], subsets[2]);
+        assert_ne!(subsets[0], subsets[1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spreads_clients() {
+        let mut clients = HashMap::<usize, usize>::new();
+        for client in 0..50 {
+            let discover = Subset::new(ServiceList::new(0..10), client, 3);
+            let mut subset = HashSet::new();
+            members(&discover, &mut subset).await;
+            for backend in subset {
+                *clients.entry(backend).or_default() += 1;
+            }
+        }
+        assert_eq!(clients.len(), 10);
+        assert_eq!(clients.values().sum::<usize>(), 150);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_changes() {
+        let all: HashMap<_, _> = (0..10).map(|n| (n, ())).collect();
+        let (tx, rx) = watch::channel(all.clone());
+        let discover = Subset::new(WatchDiscover::new(rx), "client", 3);
+
+        let mut subset = HashSet::new();
+        members(&discover, &mut subset).await;
+        assert_eq!(subset.len(), 3);
+
+        // a removed member is replaced, while the other members stay
+        let removed = *subset.iter().next().unwrap();
+        let mut remaining = all.clone();
+        remaining.remove(&removed);
+        tx.send_replace(remaining);
+        let before = subset.clone();
+        members(&discover, &mut subset).await;
+        assert_eq!(subset.len(), 3);
+        assert!(!subset.contains(&removed));
+        assert_eq!(subset.intersection(&before).count(), 2);
+
+        // once it returns, it replaces the member which took its place
+        tx.send_replace(all);
+        members(&discover, &mut subset).await;
+        assert_eq!(subset, before);
+    }
+}
@@ -0,0 +1,127 @@
+//! Configuration cells which can be updated while services are running.
+//!
+//! Middleware parameters, such as the duration of a [`Timeout`] or the rate of a
+//! [`RatePolicy`], are usually fixed once the service stack is built. Layers which accept a
+//! [`Dynamic`] value instead read its current value for every request, such that the
+//! parameter can be updated at runtime, for example by a task watching a configuration file,
+//! without rebuilding the service stack.
+//!
+//! Plain values convert into a [`Dynamic`] which is never updated, so layers accepting
+//! `impl Into<Dynamic<T>>` can still be configured with a fixed value.
+//!
+//! [`Timeout`]: crate::timeout::Timeout
+//! [`RatePolicy`]: crate::limit::policy::RatePolicy
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tower_async::{dynamic::Dynamic, service_fn, Service, ServiceBuilder};
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let timeout = Dynamic::new(Duration::from_secs(10));
+//!
+//! let service = ServiceBuilder::new()
+//!     .timeout(timeout.clone())
+//!     .service(service_fn(|_| async { Ok::<_, Infallible>(()) }));
+//!
+//! // all clones of the cell share the value, which applies to subsequent requests
+//! timeout.set(Duration::from_secs(1));
+//! assert_eq!(timeout.get(), Duration::from_secs(1));
+//! # service.call(()).await.unwrap();
+//! # }
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use arc_swap::ArcSwap;
+
+/// A configuration value which can be updated at runtime.
+///
+/// All clones of a [`Dynamic`] share the same value. Reading the value is cheap and never
+/// blocks, even while it is being updated.
+///
+/// See the [module docs](self) for more details.
+pub struct Dynamic<T> {
+    value: Arc<ArcSwap<T>>,
+}
+
+impl<T> Dynamic<T> {
+    /// Creates a new [`Dynamic`] holding the given value.
+    pub fn new(value: T) -> Self {
+        Dynamic {
+            value: Arc::new(ArcSwap::from_pointee(value)),
+        }
+    }
+
+    /// Returns the current value.
+    pub fn load(&self) -> Arc<T> {
+        self.value.load_full()
+    }
+
+    /// Returns a clone of the current value.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        T::clone(&self.value.load())
+    }
+
+    /// Replaces the value, returning the previous one.
+    pub fn set(&self, value: T) -> Arc<T> {
+        self.value.swap(Arc::new(value))
+    }
+
+    /// Updates the value using a function of the current value.
+    ///
+    /// The function may be called more than once if the value is updated concurrently.
+    pub fn update(&self, mut f: impl FnMut(&T) -> T) {
+        self.value.rcu(|value| f(value));
+    }
+}
+
+impl<T> From<T> for Dynamic<T> {
+    fn from(value: T) -> Self {
+        Dynamic::new(value)
+    }
+}
+
+impl<T: Default> Default for Dynamic<T> {
+    fn default() -> Self {
+        Dynamic::new(T::default())
+    }
+}
+
+impl<T> Clone for Dynamic<T> {
+    fn clone(&self) -> Self {
+        Dynamic {
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Dynamic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Dynamic").field(&self.value.load()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_between_clones() {
+        let value = Dynamic::new(1);
+        let clone = value.clone();
+
+        assert_eq!(*clone.set(2), 1);
+        assert_eq!(value.get(), 2);
+
+        value.update(|n| n * 10);
+        assert_eq!(*clone.load(), 20);
+        assert_eq!(format!("{clone:?}"), "Dynamic(20)");
+    }
+}
@@ -0,0 +1,485 @@
+//! Pre-emptively retry requests which have been outstanding for longer
+//! than a given latency percentile.
+//!
+//! [`Hedge`] records the latency of the requests completed by the inner service in a
+//! rolling histogram. Once a request has been outstanding for longer than the configured
+//! percentile of those latencies, a second, speculative, request is issued, and the response
+//! of whichever request completes first is returned. The other request is cancelled.
+//!
+//! This trades a small amount of extra load for lower tail latencies, as long as the slow
+//! requests are caused by the particular instance or attempt serving them rather than by the
+//! request itself. Only requests which the [`Policy`] allows to be retried are hedged, and
+//! no requests are hedged until the histogram holds enough data points.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{hedge::{Hedge, Policy}, service_fn, Service};
+//!
+//! #[derive(Clone)]
+//! struct Idempotent;
+//!
+//! impl Policy<String> for Idempotent {
+//!     fn clone_request(&self, req: &String) -> Option<String> {
+//!         Some(req.clone())
+//!     }
+//!
+//!     fn can_retry(&self, req: &String) -> bool {
+//!         req.starts_with("GET ")
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = Hedge::new(
+//!     service_fn(|req: String| async move { Ok::<_, Infallible>(req.len()) }),
+//!     Idempotent,
+//!     // hedge once 10 requests completed in the last period,
+//!     10,
+//!     // for requests slower than 90% of the requests in the last period,
+//!     0.9,
+//!     // where a period lasts 10 seconds.
+//!     Duration::from_secs(10),
+//! );
+//!
+//! let response = service.call("GET /".to_owned()).await.unwrap();
+//! assert_eq!(response, 5);
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+mod rotating_histogram;
+
+use self::rotating_histogram::RotatingHistogram;
+use crate::retry::budget::AttemptBudget;
+
+/// A policy which describes which requests can be hedged,
+/// and how to clone them.
+pub trait Policy<Request> {
+    /// Clone a request, for the speculative request of a hedge.
+    ///
+    /// Returns `None` if the request can't be cloned, in which case it isn't hedged.
+    fn clone_request(&self, req: &Request) -> Option<Request>;
+
+    /// Check whether the request may be hedged, such as only idempotent requests.
+    fn can_retry(&self, req: &Request) -> bool;
+}
+
+/// Issues a second request if the first one has been outstanding for longer
+/// than a given latency percentile, returning whichever completes first.
+///
+/// All clones of a [`Hedge`] share the same latency histogram.
+///
+/// See the [module docs](self) for more details.
+pub struct Hedge<S, P> {
+    inner: S,
+    policy: P,
+    config: Config,
+    attempt_budget: Option<AttemptBudget>,
+    histogram: Arc<Mutex<RotatingHistogram>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    min_data_points: u64,
+    latency_percentile: f32,
+    period: Duration,
+}
+
+impl Config {
+    fn new(min_data_points: u64, latency_percentile: f32, period: Duration) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&latency_percentile),
+            "latency percentile must be between 0.0 and 1.0"
+        );
+        assert!(
+            period > Duration::ZERO,
+            "histogram period must be greater than zero"
+        );
+        Config {
+            min_data_points,
+            latency_percentile,
+            period,
+        }
+    }
+}
+
+impl<S, P> Hedge<S, P> {
+    /// Creates a new [`Hedge`], wrapping the given service.
+    ///
+    /// Requests are hedged once they have been outstanding for longer than the
+    /// `latency_percentile` (between 0.0 and 1.0) of the latencies recorded in the last
+    /// `period`, as long as at least `min_data_points` latencies were recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `latency_percentile` is not between 0.0 and 1.0, or if `period` is zero.
+    pub fn new(
+        inner: S,
+        policy: P,
+        min_data_points: u64,
+        latency_percentile: f32,
+        period: Duration,
+    ) -> Self {
+        Self::with_config(
+            inner,
+            policy,
+            Config::new(min_data_points, latency_percentile, period),
+        )
+    }
+
+    fn with_config(inner: S, policy: P, config: Config) -> Self {
+        Hedge {
+            inner,
+            policy,
+            attempt_budget: None,
+            histogram: Arc::new(Mutex::new(RotatingHistogram::new(config.period))),
+            config,
+        }
+    }
+
+    /// Share an [`AttemptBudget`] with other middleware, such as a [`Retry`],
+    /// capping the attempts made for a single request across all of them.
+    ///
+    /// Requests are no longer hedged once the budget is used up.
+    ///
+    /// [`Retry`]: crate::retry::Retry
+    pub fn attempt_budget(mut self, budget: AttemptBudget) -> Self {
+        self.attempt_budget = Some(budget);
+        self
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns how long a request may be outstanding before it is hedged,
+    /// or `None` if not enough latencies were recorded yet.
+    fn budget(&self) -> Option<Duration> {
+        let mut histogram = self.histogram.lock().unwrap();
+        let histogram = histogram.read();
+        if histogram.len() < self.config.min_data_points {
+            return None;
+        }
+        let micros = histogram.value_at_quantile(self.config.latency_percentile.into());
+        Some(Duration::from_micros(micros))
+    }
+
+    /// Calls the inner service, recording the latency of the call once it completes.
+    async fn record<F: Future>(&self, future: F) -> F::Output {
+        let start = Instant::now();
+        let output = future.await;
+        let micros = start.elapsed().as_micros().try_into().unwrap_or(u64::MAX);
+        // The histogram resizes itself, and only rejects values too large to be tracked at all.
+        let _ = self.histogram.lock().unwrap().write().record(micros);
+        output
+    }
+}
+
+impl<S, P, Request> Service<Request> for Hedge<S, P>
+where
+    S: Service<Request>,
+    P: Policy<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        match &self.attempt_budget {
+            Some(budget) => budget.scope(self.call_with_hedge(request)).await,
+            None => self.call_with_hedge(request).await,
+        }
+    }
+}
+
+impl<S, P> Hedge<S, P> {
+    async fn call_with_hedge<Request>(&self, request: Request) -> Result<S::Response, S::Error>
+    where
+        S: Service<Request>,
+        P: Policy<Request>,
+    {
+        let hedge = self.budget().and_then(|budget| {
+            if self.policy.can_retry(&request) {
+                self.policy
+                    .clone_request(&request)
+                    .map(|request| (budget, request))
+            } else {
+                None
+            }
+        });
+
+        let original = self.record(self.inner.call(request));
+        let Some((budget, hedge_request)) = hedge else {
+            return original.await;
+        };
+
+        tokio::pin!(original);
+        tokio::select! {
+            result = &mut original => return result,
+            _ = tokio::time::sleep(budget) => {}
+        }
+
+        if matches!(&self.attempt_budget, Some(attempts) if !attempts.try_attempt()) {
+            return original.await;
+        }
+
+        let hedge = self.record(self.inner.call(hedge_request));
+        tokio::select! {
+            result = original => result,
+            result = hedge => result,
+        }
+    }
+}
+
+impl<S, P> Clone for Hedge<S, P>
+where
+    S: Clone,
+    P: Clone,
+{
+    fn clone(&self) -> Self {
+        Hedge {
+            inner: self.inner.clone(),
+            policy: self.policy.clone(),
+            config: self.config,
+            attempt_budget: self.attempt_budget.clone(),
+            histogram: self.histogram.clone(),
+        }
+    }
+}
+
+impl<S, P> fmt::Debug for Hedge<S, P>
+where
+    S: fmt::Debug,
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hedge")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .field("config", &self.config)
+            .field("attempt_budget", &self.attempt_budget)
+            .finish()
+    }
+}
+
+/// Hedges the requests of the wrapped services.
+///
+/// Each wrapped service records its own latencies. See [`Hedge::new`] for more details.
+#[derive(Debug, Clone)]
+pub struct HedgeLayer<P> {
+    policy: P,
+    config: Config,
+    attempt_budget: Option<AttemptBudget>,
+}
+
+impl<P> HedgeLayer<P> {
+    /// Creates a new [`HedgeLayer`].
+    ///
+    /// See [`Hedge::new`] for more details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `latency_percentile` is not between 0.0 and 1.0, or if `period` is zero.
+    pub fn new(policy: P, min_data_points: u64, latency_percentile: f32, period: Duration) -> Self {
+        HedgeLayer {
+            policy,
+            config: Config::new(min_data_points, latency_percentile, period),
+            attempt_budget: None,
+        }
+    }
+
+    /// Share an [`AttemptBudget`] with other middleware,
+    /// capping the attempts made for a single request across all of them.
+    ///
+    /// See [`Hedge::attempt_budget`] for more details.
+    pub fn attempt_budget(mut self, budget: AttemptBudget) -> Self {
+        self.attempt_budget = Some(budget);
+        self
+    }
+}
+
+impl<S, P> Layer<S> for HedgeLayer<P>
+where
+    P: Clone,
+{
+    type Service = Hedge<S, P>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let hedge = Hedge::with_config(service, self.policy.clone(), self.config);
+        match &self.attempt_budget {
+            Some(budget) => hedge.attempt_budget(budget.clone()),
+            None => hedge,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::service_fn;
+
+    #[derive(Clone)]
+    struct Even;
+
+    impl Policy<u64> for Even {
+        fn clone_request(&self, req: &u64) -> Option<u64> {
+            Some(*req)
+        }
+
+        fn can_retry(&self, req: &u64) -> bool {
+            req.is_multiple_of(2)
+        }
+    }
+
+    /// A service responding after the given number of milliseconds, except for the second
+    /// attempt of a request, which always responds after 10 milliseconds.
+    fn service() -> impl Service<u64, Response = (usize, Duration), Error = Infallible> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let attempts = Arc::new(Mutex::new(std::collections::HashSet::new()));
+        service_fn(move |millis: u64| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            let hedged = !attempts.lock().unwrap().insert(millis);
+            let start = Instant::now();
+            async move {
+                let delay = if hedged { 10 } else { millis };
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok((call, start.elapsed()))
+            }
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hedges_slow_requests() {
+        let hedge = Hedge::new(service(), Even, 10, 0.9, Duration::from_secs(60));
+
+        // not enough data points yet
+        let (call, elapsed) = hedge.call(10_000).await.unwrap();
+        assert_eq!((call, elapsed), (0, Duration::from_secs(10)));
+
+        for millis in 1..=10 {
+            hedge.call(millis * 10).await.unwrap();
+        }
+        // the latencies become readable once the period is over
+        tokio::time::sleep(Duration::from_secs(60)).await;
+
+        // fast requests are not hedged
+        let (call, _) = hedge.call(4).await.unwrap();
+        assert_eq!(call, 11);
+
+        // hedged after the 90th percentile of 100ms, answered by the hedge 10ms later
+        let start = Instant::now();
+        let (call, _) = hedge.call(20_000).await.unwrap();
+        assert_eq!(call, 13);
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(110) && elapsed < Duration::from_millis(115),
+            "{elapsed:?}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn respects_policy() {
+        let hedge = HedgeLayer::new(Even, 1, 0.5, Duration::from_secs(60)).layer(service());
+        hedge.call(2).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+
+        // odd requests can't be retried
+        let start = Instant::now();
+        hedge.call(1_001).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(1_001));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shares_attempt_budget_with_retry() {
+        #[derive(Clone)]
+        struct RetryErrors;
+
+        impl crate::retry::Policy<u64, (), &'static str> for RetryErrors {
+            async fn retry(&self, _: &mut u64, result: &mut Result<(), &'static str>) -> bool {
+                result.is_err()
+            }
+
+            fn clone_request(&self, req: &u64) -> Option<u64> {
+                Some(*req)
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let budget = AttemptBudget::new(3).on_attempts({
+            let attempts = attempts.clone();
+            move |n| attempts.store(n, Ordering::SeqCst)
+        });
+
+        let hedge = Hedge::new(
+            service_fn({
+                let calls = calls.clone();
+                move |millis: u64| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(millis)).await;
+                        Err::<(), _>("unavailable")
+                    }
+                }
+            }),
+            Even,
+            1,
+            0.5,
+            Duration::from_secs(60),
+        )
+        .attempt_budget(budget.clone());
+        hedge.call(2).await.unwrap_err();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // retried after being hedged, but the retry isn't hedged again
+        let svc = crate::retry::Retry::new(RetryErrors, hedge).attempt_budget(budget);
+        let start = Instant::now();
+        svc.call(100).await.unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 1 + 3);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(
+            start.elapsed() >= Duration::from_millis(200),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn histogram_rotates() {
+        let hedge = Hedge::new(service(), Even, 1, 0.5, Duration::from_secs(1));
+        hedge.call(2).await.unwrap();
+        // written to the current period, not readable yet
+        assert_eq!(hedge.budget(), None);
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let budget = hedge.budget().unwrap();
+        assert!(budget >= Duration::from_millis(2) && budget < Duration::from_millis(3));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(hedge.budget(), None);
+    }
+}
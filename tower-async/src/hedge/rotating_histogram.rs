@@ -0,0 +1,63 @@
+use hdrhistogram::Histogram;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// This represents a "rotating" histogram which stores two histogram, one which
+/// should be read and one which should be written to. Every period, the read
+/// histogram is discarded and replaced by the write histogram. The idea here
+/// is that the read histogram should always contain a full period (the previous
+/// period) of write operations.
+#[derive(Debug)]
+pub(super) struct RotatingHistogram {
+    read: Histogram<u64>,
+    write: Histogram<u64>,
+    last_rotation: Instant,
+    period: Duration,
+}
+
+impl RotatingHistogram {
+    pub(super) fn new(period: Duration) -> RotatingHistogram {
+        RotatingHistogram {
+            // Use an auto-resizing histogram to avoid choosing
+            // a maximum latency bound for all users.
+            read: Histogram::<u64>::new(3).expect("Invalid histogram params"),
+            write: Histogram::<u64>::new(3).expect("Invalid histogram params"),
+            last_rotation: Instant::now(),
+            period,
+        }
+    }
+
+    pub(super) fn read(&mut self) -> &mut Histogram<u64> {
+        self.maybe_rotate();
+        &mut self.read
+    }
+
+    pub(super) fn write(&mut self) -> &mut Histogram<u64> {
+        self.maybe_rotate();
+        &mut self.write
+    }
+
+    fn maybe_rotate(&mut self) {
+        let now = Instant::now();
+        let delta = now.saturating_duration_since(self.last_rotation);
+        let rotations = delta.as_nanos() / self.period.as_nanos();
+        if rotations >= 2 {
+            self.clear();
+        } else if rotations == 1 {
+            self.rotate();
+        }
+        // Keep rotating on period boundaries, even if no operation happened for a while.
+        let elapsed_in_period = delta.as_nanos() % self.period.as_nanos();
+        self.last_rotation = now - Duration::from_nanos(elapsed_in_period as u64);
+    }
+
+    fn rotate(&mut self) {
+        std::mem::swap(&mut self.read, &mut self.write);
+        self.write.clear();
+    }
+
+    fn clear(&mut self) {
+        self.read.clear();
+        self.write.clear();
+    }
+}
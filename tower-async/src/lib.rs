@@ -8,6 +8,7 @@
 #![allow(elided_lifetimes_in_paths, clippy::type_complexity)]
 #![cfg_attr(test, allow(clippy::float_cmp))]
 #![cfg_attr(docsrs, feature(doc_auto_cfg, doc_cfg))]
+#![cfg_attr(feature = "nightly", feature(return_type_notation))]
 // `rustdoc::broken_intra_doc_links` is checked on CI
 
 //! `async fn(Request) -> Result<Response, Error>`
@@ -195,6 +196,7 @@
 //! [`Layer`]: https://docs.rs/tower-async/latest/tower-async/trait.Layer.html
 //! [all_layers]: https://docs.rs/tower-async/latest/tower-async/#modules
 //! [timeouts]: https://docs.rs/tower-async/latest/tower-async/timeout/
+//! [load balancing]: https://docs.rs/tower-async/latest/tower-async/balance/
 //! [`ServiceBuilder`]: https://docs.rs/tower-async/latest/tower-async/struct.ServiceBuilder.html
 //! [utilities]: https://docs.rs/tower-async/latest/tower-async/trait.ServiceExt.html
 //! [`tower-async`]: https://crates.io/crates/tower
@@ -214,27 +216,61 @@
 //!
 //! ## Supported Rust Versions
 //!
-//! Tower Async requires nightly Rust for the time being and has no backwards compatibility
-//! promises for the time being.
+//! Tower Async compiles on stable Rust, now that `async fn` in traits is stabilized,
+//! and has no backwards compatibility promises for the time being.
 //!
-//! Once `async traits` are stabilized we'll start supporting stable rust once again,
-//! and we can start working towards backwards compatibility.
-//!
-//! Read <https://blog.rust-lang.org/inside-rust/2023/05/03/stabilizing-async-fn-in-trait.html> for more information
-//! on this roadmap by the Rust Language Core Team.
+//! Middleware which spawns the futures returned by a generic `Service`, and thus requires them
+//! to be `Send`, relies on the unstable `return_type_notation` feature. It is only available
+//! with the `nightly` feature enabled, which requires a nightly toolchain and is not part of `full`.
+//! In `tower-async` this is the `cache` middleware,
+//! as well as `Buffer::new` and `BufferLayer`, which spawn the worker of a `Buffer`,
+//! `Batch::new` and `BatchLayer`, which spawn the worker of a `Batch`,
+//! and `ServiceExt::spawn`, which spawns every call of a service.
 
+#[cfg(feature = "balance")]
+pub mod balance;
+#[cfg(feature = "batch")]
+pub mod batch;
+#[cfg(feature = "buffer")]
+pub mod buffer;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "circuit-breaker")]
+pub mod circuit_breaker;
+#[cfg(feature = "codec")]
+pub mod codec;
+#[cfg(feature = "discover")]
+pub mod discover;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
 #[cfg(feature = "filter")]
 pub mod filter;
+#[cfg(feature = "hedge")]
+pub mod hedge;
 
 #[cfg(feature = "limit")]
 pub mod limit;
+#[cfg(feature = "load-shed")]
+pub mod load_shed;
 
 #[cfg(feature = "make")]
 pub mod make;
+#[cfg(feature = "pool")]
+pub mod pool;
+#[cfg(feature = "reconnect")]
+pub mod reconnect;
 #[cfg(feature = "retry")]
 pub mod retry;
+#[cfg(feature = "shard")]
+pub mod shard;
+#[cfg(feature = "task-local")]
+pub mod task_local;
 #[cfg(feature = "timeout")]
 pub mod timeout;
+#[cfg(feature = "timing")]
+pub mod timing;
+#[cfg(feature = "transport")]
+pub mod transport;
 #[cfg(feature = "util")]
 pub mod util;
 
@@ -243,7 +279,7 @@ pub mod layer;
 
 #[cfg(feature = "util")]
 #[doc(inline)]
-pub use self::util::{service_fn, ServiceExt};
+pub use self::util::{service_fn, service_fn_with_state, ServiceExt};
 
 #[doc(inline)]
 pub use crate::builder::ServiceBuilder;
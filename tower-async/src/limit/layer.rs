@@ -1,16 +1,32 @@
-use super::Limit;
+use std::time::Duration;
+
+use super::{policy::RatePolicy, Limit, RateLimit};
+use crate::dynamic::Dynamic;
 use tower_async_layer::Layer;
 
 /// Limit requests based on a policy
 #[derive(Debug)]
 pub struct LimitLayer<P> {
     policy: P,
+    max_retries: Option<usize>,
 }
 
 impl<P> LimitLayer<P> {
     /// Creates a new [`LimitLayer`] from a [`crate::limit::Policy`].
     pub fn new(policy: P) -> Self {
-        LimitLayer { policy }
+        LimitLayer {
+            policy,
+            max_retries: None,
+        }
+    }
+
+    /// Set the maximum number of times the policy can ask to retry
+    /// the check of a single request.
+    ///
+    /// See [`Limit::max_retries`] for more details.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
     }
 }
 
@@ -22,6 +38,41 @@ where
 
     fn layer(&self, service: T) -> Self::Service {
         let policy = self.policy.clone();
-        Limit::new(service, policy)
+        let limit = Limit::new(service, policy);
+        match self.max_retries {
+            Some(max_retries) => limit.max_retries(max_retries),
+            None => limit,
+        }
+    }
+}
+
+/// Limit the rate at which requests are processed to `num` requests per period.
+///
+/// This is a [`LimitLayer`] using a [`RatePolicy`], see it for more details.
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    policy: RatePolicy,
+}
+
+impl RateLimitLayer {
+    /// Creates a new [`RateLimitLayer`], allowing `num` requests per `per` period.
+    ///
+    /// All services produced by the layer share the same rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero when the layer is created.
+    pub fn new(num: impl Into<Dynamic<u64>>, per: impl Into<Dynamic<Duration>>) -> Self {
+        RateLimitLayer {
+            policy: RatePolicy::new(num, per),
+        }
+    }
+}
+
+impl<T> Layer<T> for RateLimitLayer {
+    type Service = RateLimit<T>;
+
+    fn layer(&self, service: T) -> Self::Service {
+        Limit::new(service, self.policy.clone())
     }
 }
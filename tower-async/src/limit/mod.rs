@@ -1,29 +1,53 @@
-//! A middleware that limits the number of in-flight requests.
+//! A middleware that limits the number of in-flight requests,
+//! or the rate at which they are processed.
 //!
-//! See [`Limit`].
+//! See [`Limit`] and [`RateLimit`]. To reject requests when a policy is at capacity,
+//! instead of having them wait, see the `load_shed` module.
 
 use tower_async_service::Service;
 
 use crate::BoxError;
 
 pub mod policy;
-pub use policy::{Policy, PolicyOutput};
+pub use policy::{Outcome, Policy, PolicyOutput};
 
 mod layer;
-pub use layer::LimitLayer;
+pub use layer::{LimitLayer, RateLimitLayer};
+
+/// Limit the rate at which requests are processed,
+/// using a [`RatePolicy`](policy::RatePolicy).
+pub type RateLimit<T> = Limit<T, policy::RatePolicy>;
 
 /// Limit requests based on a policy
 #[derive(Debug)]
 pub struct Limit<T, P> {
     inner: T,
     policy: P,
+    max_retries: Option<usize>,
 }
 
 impl<T, P> Limit<T, P> {
     /// Creates a new [`Limit`] from a limit policy,
     /// wrapping the given service.
     pub fn new(inner: T, policy: P) -> Self {
-        Limit { inner, policy }
+        Limit {
+            inner,
+            policy,
+            max_retries: None,
+        }
+    }
+
+    /// Set the maximum number of times the policy can return [`PolicyOutput::Retry`]
+    /// for a single request.
+    ///
+    /// Once exceeded, [`Policy::retries_exceeded`] is called and the request fails with a
+    /// [`RetriesExceeded`] error, instead of checking the policy again. This protects against
+    /// policies that never let a request proceed nor abort it.
+    ///
+    /// By default the policy can be checked again any number of times.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
     }
 }
 
@@ -36,6 +60,7 @@ where
         Limit {
             inner: self.inner.clone(),
             policy: self.policy.clone(),
+            max_retries: self.max_retries,
         }
     }
 }
@@ -52,19 +77,59 @@ where
 
     async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
         let mut request = request;
+        let mut retries = 0;
         loop {
             match self.policy.check(&mut request).await {
                 policy::PolicyOutput::Ready(guard) => {
-                    let _ = guard;
-                    return self.inner.call(request).await.map_err(Into::into);
+                    let result = self.inner.call(request).await;
+                    let outcome = match result {
+                        Ok(_) => Outcome::Success,
+                        Err(_) => Outcome::Failure,
+                    };
+                    self.policy.record(guard, outcome);
+                    return result.map_err(Into::into);
                 }
                 policy::PolicyOutput::Abort(err) => return Err(err.into()),
-                policy::PolicyOutput::Retry => (),
+                policy::PolicyOutput::Retry => match self.max_retries {
+                    Some(max_retries) if retries >= max_retries => {
+                        self.policy.retries_exceeded(&request);
+                        return Err(RetriesExceeded { max_retries }.into());
+                    }
+                    _ => retries += 1,
+                },
             }
         }
     }
 }
 
+/// The error returned by [`Limit`] when its policy asked to retry
+/// the check of a request more often than the configured [maximum].
+///
+/// [maximum]: Limit::max_retries
+#[derive(Debug)]
+pub struct RetriesExceeded {
+    max_retries: usize,
+}
+
+impl RetriesExceeded {
+    /// Returns the maximum number of retries that was exceeded.
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+}
+
+impl std::fmt::Display for RetriesExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "limit policy retried more than {} times",
+            self.max_retries
+        )
+    }
+}
+
+impl std::error::Error for RetriesExceeded {}
+
 #[cfg(test)]
 mod tests {
     use std::convert::Infallible;
@@ -105,4 +170,81 @@ mod tests {
             assert!(result_2.is_err());
         }
     }
+
+    #[tokio::test]
+    async fn test_limit_max_retries() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Default)]
+        struct AlwaysRetry {
+            checks: AtomicUsize,
+            exceeded: AtomicUsize,
+        }
+
+        impl<Request> Policy<Request> for AlwaysRetry {
+            type Guard = ();
+            type Error = Infallible;
+
+            async fn check(&self, _: &mut Request) -> PolicyOutput<(), Infallible> {
+                self.checks.fetch_add(1, Ordering::SeqCst);
+                PolicyOutput::Retry
+            }
+
+            fn retries_exceeded(&self, _: &Request) {
+                self.exceeded.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let service = Limit::new(
+            service_fn(|req: &'static str| async move { Ok::<_, Infallible>(req) }),
+            AlwaysRetry::default(),
+        )
+        .max_retries(3);
+
+        let err = service.call("Hello").await.unwrap_err();
+        let err = err.downcast_ref::<RetriesExceeded>().unwrap();
+        assert_eq!(err.max_retries(), 3);
+        assert_eq!(service.policy.checks.load(Ordering::SeqCst), 4);
+        assert_eq!(service.policy.exceeded.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_and_concurrency_limit() {
+        use crate::limit::policy::RatePolicy;
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        let service = Limit::new(
+            service_fn(|req: &'static str| async move { Ok::<_, Infallible>(req) }),
+            (
+                ConcurrentPolicy::new(1),
+                RatePolicy::new(1, Duration::from_secs(1)),
+            ),
+        );
+
+        let start = Instant::now();
+        assert_eq!(service.call("Hello").await.unwrap(), "Hello");
+        assert_eq!(service.call("Hello").await.unwrap(), "Hello");
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(service.policy.0.current(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit_layer() {
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        let layer = RateLimitLayer::new(1, Duration::from_secs(1));
+        let service_1 = layer.layer(service_fn(handle));
+        let service_2 = layer.layer(service_fn(handle));
+
+        async fn handle(req: &'static str) -> Result<&'static str, Infallible> {
+            Ok(req)
+        }
+
+        // all services produced by the layer share the same rate
+        let start = Instant::now();
+        let _ = join_all(vec![service_1.call("Hello"), service_2.call("Hello")]).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,258 @@
+//! A policy that adapts the number of concurrent requests to the health of the service.
+//!
+//! See [`AdaptiveConcurrencyPolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{
+//!     limit::{Limit, policy::AdaptiveConcurrencyPolicy},
+//!     Service, service_fn,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = service_fn(|_| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//! let policy = AdaptiveConcurrencyPolicy::new(10)
+//!     .max_limit(100)
+//!     .latency_threshold(Duration::from_millis(250));
+//! let service = Limit::new(service, policy.clone());
+//!
+//! service.call(()).await.unwrap();
+//! assert_eq!(policy.limit(), 10);
+//! # }
+//! ```
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::{LimitReached, Outcome, Policy, PolicyOutput};
+
+/// A policy that limits the number of concurrent requests, adjusting the limit
+/// based on the outcome of the requests, using additive increase, multiplicative
+/// decrease (AIMD).
+///
+/// Every request which succeeded while at least half of the limit was in use raises the
+/// limit by one, up to the [maximum limit](Self::max_limit). Every request which failed,
+/// or took longer than the [latency threshold](Self::latency_threshold) when configured,
+/// multiplies the limit by the [backoff ratio](Self::backoff_ratio), down to the
+/// [minimum limit](Self::min_limit). The limit thus probes for the concurrency the service
+/// can handle, and backs off quickly once it is overloaded.
+///
+/// Requests exceeding the limit are aborted with [`LimitReached`]. Requests which are
+/// cancelled release their slot without adjusting the limit.
+///
+/// All clones of the policy share the same limit.
+#[derive(Debug, Clone)]
+pub struct AdaptiveConcurrencyPolicy {
+    min_limit: usize,
+    max_limit: usize,
+    backoff_ratio: f64,
+    latency_threshold: Option<Duration>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    in_flight: usize,
+}
+
+impl AdaptiveConcurrencyPolicy {
+    /// Create a new adaptive concurrency policy, starting with the given limit.
+    ///
+    /// The limit is adjusted between 1 and 1000 by default,
+    /// or up to the initial limit if it is larger.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `initial_limit` is 0.
+    pub fn new(initial_limit: usize) -> Self {
+        assert!(initial_limit > 0, "initial_limit must be larger than 0");
+        AdaptiveConcurrencyPolicy {
+            min_limit: 1,
+            max_limit: initial_limit.max(1000),
+            backoff_ratio: 0.9,
+            latency_threshold: None,
+            state: Arc::new(Mutex::new(State {
+                limit: initial_limit,
+                in_flight: 0,
+            })),
+        }
+    }
+
+    /// Sets the limit below which the limit is never decreased, defaults to 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_limit` is 0, or larger than the maximum limit.
+    pub fn min_limit(mut self, min_limit: usize) -> Self {
+        assert!(min_limit > 0, "min_limit must be larger than 0");
+        self.min_limit = min_limit;
+        self.clamp();
+        self
+    }
+
+    /// Sets the limit above which the limit is never increased, defaults to 1000.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_limit` is smaller than the minimum limit, and thus if it is 0.
+    pub fn max_limit(mut self, max_limit: usize) -> Self {
+        self.max_limit = max_limit;
+        self.clamp();
+        self
+    }
+
+    /// Sets the ratio by which the limit is multiplied when a request failed,
+    /// defaults to 0.9.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not larger than 0 and smaller than 1.
+    pub fn backoff_ratio(mut self, ratio: f64) -> Self {
+        assert!(
+            ratio > 0.0 && ratio < 1.0,
+            "ratio must be larger than 0 and smaller than 1"
+        );
+        self.backoff_ratio = ratio;
+        self
+    }
+
+    /// Counts requests taking longer than `latency` as failed, even if they succeeded.
+    ///
+    /// By default only requests which failed decrease the limit.
+    pub fn latency_threshold(mut self, latency: Duration) -> Self {
+        self.latency_threshold = Some(latency);
+        self
+    }
+
+    /// Returns the current limit of concurrent requests, shared by all clones of this policy.
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Returns the number of requests currently in flight,
+    /// shared by all clones of this policy.
+    pub fn current(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    fn clamp(&self) {
+        assert!(
+            self.min_limit <= self.max_limit,
+            "min_limit must be at most max_limit"
+        );
+        let mut state = self.state.lock().unwrap();
+        state.limit = state.limit.clamp(self.min_limit, self.max_limit);
+    }
+}
+
+/// The guard that releases the slot of a request admitted by an [`AdaptiveConcurrencyPolicy`].
+#[derive(Debug)]
+pub struct AdaptiveConcurrencyGuard {
+    state: Arc<Mutex<State>>,
+    start: Instant,
+}
+
+impl Drop for AdaptiveConcurrencyGuard {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().in_flight -= 1;
+    }
+}
+
+impl<Request> Policy<Request> for AdaptiveConcurrencyPolicy {
+    type Guard = AdaptiveConcurrencyGuard;
+    type Error = LimitReached;
+
+    async fn check(&self, _: &mut Request) -> PolicyOutput<Self::Guard, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight < state.limit {
+            state.in_flight += 1;
+            PolicyOutput::Ready(AdaptiveConcurrencyGuard {
+                state: self.state.clone(),
+                start: Instant::now(),
+            })
+        } else {
+            PolicyOutput::Abort(LimitReached)
+        }
+    }
+
+    fn record(&self, guard: Self::Guard, outcome: Outcome) {
+        let failed = outcome == Outcome::Failure
+            || matches!(self.latency_threshold, Some(threshold) if guard.start.elapsed() > threshold);
+        let mut state = guard.state.lock().unwrap();
+        if failed {
+            let limit = (state.limit as f64 * self.backoff_ratio) as usize;
+            state.limit = limit.max(self.min_limit);
+        } else if state.in_flight * 2 >= state.limit {
+            state.limit = (state.limit + 1).min(self.max_limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ready<G, E>(output: PolicyOutput<G, E>) -> G {
+        match output {
+            PolicyOutput::Ready(guard) => guard,
+            _ => panic!("unexpected output, expected ready"),
+        }
+    }
+
+    fn record(
+        policy: &AdaptiveConcurrencyPolicy,
+        guard: AdaptiveConcurrencyGuard,
+        outcome: Outcome,
+    ) {
+        Policy::<()>::record(policy, guard, outcome);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn adapts_limit() {
+        let policy = AdaptiveConcurrencyPolicy::new(2)
+            .min_limit(1)
+            .max_limit(3)
+            .backoff_ratio(0.7)
+            .latency_threshold(Duration::from_secs(1));
+
+        // successes while the limit is in use raise it, up to the maximum
+        for _ in 0..3 {
+            let guard = assert_ready(policy.check(&mut ()).await);
+            record(&policy, guard, Outcome::Success);
+        }
+        assert_eq!(policy.limit(), 3);
+
+        let mut guards = Vec::new();
+        for _ in 0..3 {
+            guards.push(assert_ready(policy.check(&mut ()).await));
+        }
+        assert!(matches!(
+            policy.check(&mut ()).await,
+            PolicyOutput::Abort(LimitReached)
+        ));
+        assert_eq!(policy.current(), 3);
+
+        // failed and slow requests lower it, down to the minimum
+        let mut guards = guards.into_iter();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        record(&policy, guards.next().unwrap(), Outcome::Success);
+        assert_eq!(policy.limit(), 2);
+        record(&policy, guards.next().unwrap(), Outcome::Failure);
+        assert_eq!(policy.limit(), 1);
+
+        // cancelled requests release their slot without adjusting the limit
+        drop(guards);
+        assert_eq!(policy.current(), 0);
+        assert_eq!(policy.limit(), 1);
+    }
+}
@@ -79,6 +79,19 @@ impl<B> ConcurrentPolicy<B> {
     }
 }
 
+impl<B> ConcurrentPolicy<B> {
+    /// Returns the maximum number of concurrent requests allowed by this policy.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Returns the number of requests currently in flight,
+    /// shared by all clones of this policy.
+    pub fn current(&self) -> usize {
+        *self.current.lock().unwrap()
+    }
+}
+
 /// The guard that releases the concurrent request limit.
 #[derive(Debug)]
 pub struct ConcurrentGuard {
@@ -169,10 +182,13 @@ mod tests {
 
         let guard_1 = assert_ready(policy.check(&mut ()).await);
         let guard_2 = assert_ready(policy.check(&mut ()).await);
+        assert_eq!(policy.current(), 2);
+        assert_eq!(policy.clone().current(), 2);
 
         assert_abort(policy.check(&mut ()).await);
 
         drop(guard_1);
+        assert_eq!(policy.current(), 1);
         let _guard_3 = assert_ready(policy.check(&mut ()).await);
 
         assert_abort(policy.check(&mut ()).await);
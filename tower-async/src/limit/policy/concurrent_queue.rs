@@ -0,0 +1,246 @@
+//! A policy that limits the number of concurrent requests,
+//! queueing the requests exceeding the limit.
+//!
+//! See [`ConcurrentQueuePolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use tower_async::{
+//!     limit::{Limit, policy::ConcurrentQueuePolicy},
+//!     Service, ServiceExt, service_fn,
+//! };
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//!
+//! let service = service_fn(|_| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//! // Allow 2 requests in flight, and up to 100 requests waiting for their turn.
+//! let mut service = Limit::new(service, ConcurrentQueuePolicy::new(2).max_queued(100));
+//!
+//! let response = service.oneshot(()).await;
+//! assert!(response.is_ok());
+//! # }
+//! ```
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::{LimitReached, Policy, PolicyOutput};
+
+/// A policy that limits the number of concurrent requests,
+/// making the requests exceeding the limit wait for their turn.
+///
+/// Unlike [`ConcurrentPolicy`], which aborts the requests exceeding the limit or retries
+/// them after a backoff, requests wait in a queue until a request in flight completes.
+/// The queue is first-in, first-out, such that requests are admitted in the order they
+/// arrived, and no time is spent polling for capacity.
+///
+/// The number of waiting requests is unbounded, unless [limited](Self::max_queued),
+/// in which case requests exceeding it are aborted with [`LimitReached`].
+///
+/// All clones of the policy share the same limit and queue.
+///
+/// [`ConcurrentPolicy`]: super::ConcurrentPolicy
+#[derive(Debug, Clone)]
+pub struct ConcurrentQueuePolicy {
+    max: usize,
+    max_queued: Option<usize>,
+    semaphore: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl ConcurrentQueuePolicy {
+    /// Create a new concurrent queue policy,
+    /// which allows up to `max` requests in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` exceeds [`Semaphore::MAX_PERMITS`].
+    pub fn new(max: usize) -> Self {
+        ConcurrentQueuePolicy {
+            max,
+            max_queued: None,
+            semaphore: Arc::new(Semaphore::new(max)),
+            queued: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Limit the number of requests waiting for their turn,
+    /// aborting the requests exceeding it.
+    pub fn max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = Some(max_queued);
+        self
+    }
+
+    /// Returns the maximum number of concurrent requests allowed by this policy.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Returns the number of requests currently in flight,
+    /// shared by all clones of this policy.
+    pub fn current(&self) -> usize {
+        self.max - self.semaphore.available_permits()
+    }
+
+    /// Returns the number of requests currently waiting for their turn,
+    /// shared by all clones of this policy.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    /// Reserves a place in the queue, unless it is full.
+    fn enqueue(&self) -> Option<QueuedGuard> {
+        self.queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+                match self.max_queued {
+                    Some(max_queued) if queued >= max_queued => None,
+                    _ => Some(queued + 1),
+                }
+            })
+            .ok()?;
+        Some(QueuedGuard {
+            queued: self.queued.clone(),
+        })
+    }
+}
+
+/// The guard that releases the concurrent request limit.
+#[derive(Debug)]
+pub struct ConcurrentQueueGuard {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Releases a place in the queue once the request stops waiting,
+/// including when it is cancelled while waiting.
+struct QueuedGuard {
+    queued: Arc<AtomicUsize>,
+}
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl<Request> Policy<Request> for ConcurrentQueuePolicy {
+    type Guard = ConcurrentQueueGuard;
+    type Error = LimitReached;
+
+    async fn check(&self, _: &mut Request) -> PolicyOutput<Self::Guard, Self::Error> {
+        // Released permits are handed to the waiting requests first,
+        // so this never lets a request skip the queue.
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return PolicyOutput::Ready(ConcurrentQueueGuard { _permit: permit });
+        }
+
+        let Some(_queued) = self.enqueue() else {
+            return PolicyOutput::Abort(LimitReached);
+        };
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        PolicyOutput::Ready(ConcurrentQueueGuard { _permit: permit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::{limit::Limit, service_fn, Service};
+
+    fn assert_ready<G, E>(output: PolicyOutput<G, E>) -> G {
+        match output {
+            PolicyOutput::Ready(guard) => guard,
+            _ => panic!("unexpected output, expected ready"),
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_queue_policy() {
+        let policy = ConcurrentQueuePolicy::new(1).max_queued(1);
+
+        let guard = assert_ready(policy.check(&mut ()).await);
+        assert_eq!(policy.current(), 1);
+
+        let waiting = tokio::spawn({
+            let policy = policy.clone();
+            async move { assert_ready(policy.check(&mut ()).await) }
+        });
+        while policy.queued() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // the queue is full
+        assert!(matches!(
+            policy.check(&mut ()).await,
+            PolicyOutput::Abort(LimitReached)
+        ));
+
+        drop(guard);
+        let guard = waiting.await.unwrap();
+        assert_eq!(policy.queued(), 0);
+        assert_eq!(policy.current(), 1);
+
+        drop(guard);
+        assert_eq!(policy.current(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_requests_leave_the_queue() {
+        let policy = ConcurrentQueuePolicy::new(1);
+        let _guard = assert_ready(policy.check(&mut ()).await);
+
+        let mut request = ();
+        let check = policy.check(&mut request);
+        assert!(tokio::time::timeout(Duration::from_secs(1), check)
+            .await
+            .is_err());
+        assert_eq!(policy.queued(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn admits_requests_in_order() {
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let service = Arc::new(Limit::new(
+            service_fn({
+                let order = order.clone();
+                move |n: usize| {
+                    order.lock().unwrap().push(n);
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        Ok::<_, std::convert::Infallible>(())
+                    }
+                }
+            }),
+            ConcurrentQueuePolicy::new(1),
+        ));
+
+        let mut handles = Vec::new();
+        for n in 0..5 {
+            let service = service.clone();
+            handles.push(tokio::spawn(async move { service.call(n).await }));
+            // let the request reach the queue before sending the next one
+            tokio::task::yield_now().await;
+        }
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+}
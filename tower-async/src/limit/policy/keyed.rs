@@ -0,0 +1,282 @@
+//! A policy that limits every key, such as a client, with a policy of its own.
+//!
+//! See [`KeyedPolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use std::{net::IpAddr, time::Duration};
+//! use tower_async::{
+//!     limit::{Limit, policy::{KeyedPolicy, RatePolicy}},
+//!     Service, service_fn,
+//! };
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! struct Request {
+//!     peer: IpAddr,
+//! }
+//!
+//! let service = service_fn(|_: Request| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//!
+//! // Allow every peer 10 requests per second.
+//! let policy = KeyedPolicy::new(
+//!     |req: &Request| req.peer,
+//!     |_: &IpAddr| RatePolicy::new(10, Duration::from_secs(1)),
+//! );
+//!
+//! let service = Limit::new(service, policy);
+//!
+//! let response = service.call(Request { peer: [127, 0, 0, 1].into() }).await;
+//! assert!(response.is_ok());
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::{Outcome, Policy, PolicyOutput};
+
+/// A policy that limits the requests of every key with a policy of its own.
+///
+/// Every request is assigned a key, such as the IP address of its peer or its API key, by
+/// a function given to [`KeyedPolicy::new`]. The first request of a key creates the policy
+/// of that key, using the `make` function, after which all requests of that key are checked
+/// by that policy. This allows throttling every client separately, using any limit policy,
+/// such as a [`RatePolicy`] or a [`ConcurrentPolicy`].
+///
+/// The policies of keys which had no requests in flight for the [idle timeout](Self::idle_timeout)
+/// are dropped, such that the number of policies kept doesn't grow with every key ever seen.
+/// A key seen again afterwards starts with a new policy.
+///
+/// All clones of the policy share the same policies per key.
+///
+/// [`RatePolicy`]: super::RatePolicy
+/// [`ConcurrentPolicy`]: super::ConcurrentPolicy
+pub struct KeyedPolicy<K, F, M, P> {
+    key: F,
+    make: Arc<M>,
+    idle_timeout: Duration,
+    state: Arc<Mutex<State<K, P>>>,
+}
+
+struct State<K, P> {
+    policies: HashMap<K, Entry<P>>,
+    last_sweep: Instant,
+}
+
+struct Entry<P> {
+    policy: Arc<P>,
+    last_used: Instant,
+}
+
+impl<K, F, M, P> KeyedPolicy<K, F, M, P> {
+    /// Create a new keyed policy, using `key` to determine the key of a request,
+    /// and `make` to create the policy of a key.
+    ///
+    /// Policies of idle keys are dropped after 60 seconds by default.
+    pub fn new(key: F, make: M) -> Self {
+        KeyedPolicy {
+            key,
+            make: Arc::new(make),
+            idle_timeout: Duration::from_secs(60),
+            state: Arc::new(Mutex::new(State {
+                policies: HashMap::new(),
+                last_sweep: Instant::now(),
+            })),
+        }
+    }
+
+    /// Set the duration after which the policy of a key without requests
+    /// in flight is dropped, defaults to 60 seconds.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Returns the number of keys for which a policy is kept,
+    /// shared by all clones of this policy.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().policies.len()
+    }
+
+    /// Returns whether no policy is kept for any key.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, F, M, P> KeyedPolicy<K, F, M, P>
+where
+    K: Hash + Eq,
+    M: Fn(&K) -> P,
+{
+    /// Returns the policy of the key, creating it if needed,
+    /// and drops the policies of idle keys once per idle timeout.
+    fn policy(&self, key: K) -> Arc<P> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if now.duration_since(state.last_sweep) >= self.idle_timeout {
+            state.last_sweep = now;
+            // policies referenced elsewhere have requests in flight
+            state.policies.retain(|_, entry| {
+                Arc::strong_count(&entry.policy) > 1
+                    || now.duration_since(entry.last_used) < self.idle_timeout
+            });
+        }
+
+        let entry = state.policies.entry(key).or_insert_with_key(|key| Entry {
+            policy: Arc::new((self.make)(key)),
+            last_used: now,
+        });
+        entry.last_used = now;
+        entry.policy.clone()
+    }
+}
+
+impl<K, F, M, P> Clone for KeyedPolicy<K, F, M, P>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        KeyedPolicy {
+            key: self.key.clone(),
+            make: self.make.clone(),
+            idle_timeout: self.idle_timeout,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K, F, M, P> fmt::Debug for KeyedPolicy<K, F, M, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedPolicy")
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .field("make", &format_args!("{}", std::any::type_name::<M>()))
+            .field("idle_timeout", &self.idle_timeout)
+            .field("keys", &self.len())
+            .finish()
+    }
+}
+
+/// The guard of a request allowed to proceed by a [`KeyedPolicy`],
+/// holding the guard of the policy of its key.
+pub struct KeyedGuard<P, G> {
+    guard: G,
+    policy: Arc<P>,
+}
+
+impl<P, G> fmt::Debug for KeyedGuard<P, G>
+where
+    G: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedGuard")
+            .field("guard", &self.guard)
+            .finish()
+    }
+}
+
+impl<K, F, M, P, Request> Policy<Request> for KeyedPolicy<K, F, M, P>
+where
+    K: Hash + Eq,
+    F: Fn(&Request) -> K,
+    M: Fn(&K) -> P,
+    P: Policy<Request>,
+{
+    type Guard = KeyedGuard<P, P::Guard>;
+    type Error = P::Error;
+
+    async fn check(&self, request: &mut Request) -> PolicyOutput<Self::Guard, Self::Error> {
+        let policy = self.policy((self.key)(request));
+        match policy.check(request).await {
+            PolicyOutput::Ready(guard) => PolicyOutput::Ready(KeyedGuard { guard, policy }),
+            PolicyOutput::Abort(err) => PolicyOutput::Abort(err),
+            PolicyOutput::Retry => PolicyOutput::Retry,
+        }
+    }
+
+    fn record(&self, guard: Self::Guard, outcome: Outcome) {
+        let KeyedGuard { guard, policy } = guard;
+        policy.record(guard, outcome);
+    }
+
+    fn retries_exceeded(&self, request: &Request) {
+        let key = (self.key)(request);
+        let policy = self
+            .state
+            .lock()
+            .unwrap()
+            .policies
+            .get(&key)
+            .map(|entry| entry.policy.clone());
+        if let Some(policy) = policy {
+            policy.retries_exceeded(request);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::limit::policy::{ConcurrentPolicy, LimitReached};
+
+    fn assert_ready<G, E>(output: PolicyOutput<G, E>) -> G {
+        match output {
+            PolicyOutput::Ready(guard) => guard,
+            _ => panic!("unexpected output, expected ready"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_keys_separately() {
+        let policy = KeyedPolicy::new(
+            |req: &&'static str| *req,
+            |_: &&'static str| ConcurrentPolicy::new(1),
+        );
+
+        let guard_a = assert_ready(policy.check(&mut "a").await);
+        let _guard_b = assert_ready(policy.check(&mut "b").await);
+        assert!(matches!(
+            policy.check(&mut "a").await,
+            PolicyOutput::Abort(LimitReached)
+        ));
+        assert_eq!(policy.len(), 2);
+
+        drop(guard_a);
+        assert_ready(policy.check(&mut "a").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_idle_keys() {
+        let policy = KeyedPolicy::new(
+            |req: &&'static str| *req,
+            |_: &&'static str| ConcurrentPolicy::new(1),
+        )
+        .idle_timeout(Duration::from_secs(10));
+
+        let _guard = assert_ready(policy.check(&mut "busy").await);
+        drop(assert_ready(policy.check(&mut "idle").await));
+        assert_eq!(policy.len(), 2);
+
+        // keys with requests in flight are kept
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        drop(assert_ready(policy.check(&mut "new").await));
+        assert_eq!(policy.len(), 2);
+        assert!(matches!(
+            policy.check(&mut "busy").await,
+            PolicyOutput::Abort(LimitReached)
+        ));
+    }
+}
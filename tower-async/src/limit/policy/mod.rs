@@ -2,9 +2,26 @@
 //! define how requests are handled when the limit is reached
 //! for a given request.
 
+mod adaptive;
+pub use adaptive::{AdaptiveConcurrencyGuard, AdaptiveConcurrencyPolicy};
+
 mod concurrent;
 pub use concurrent::{ConcurrentPolicy, LimitReached};
 
+mod concurrent_queue;
+pub use concurrent_queue::{ConcurrentQueueGuard, ConcurrentQueuePolicy};
+
+mod keyed;
+pub use keyed::{KeyedGuard, KeyedPolicy};
+
+mod rate;
+pub use rate::RatePolicy;
+
+mod weighted_fair;
+pub use weighted_fair::{WeightedFairGuard, WeightedFairPolicy};
+
+use crate::BoxError;
+
 /// The output of a limit policy.
 #[derive(Debug)]
 pub enum PolicyOutput<Guard, Error> {
@@ -18,8 +35,21 @@ pub enum PolicyOutput<Guard, Error> {
     Retry,
 }
 
+/// The outcome of a request which was allowed to proceed by a limit policy,
+/// reported to the policy using [`Policy::record`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The inner service returned a response.
+    Success,
+    /// The inner service returned an error.
+    Failure,
+}
+
 /// A limit policy is used to determine whether a request is allowed to proceed,
 /// and if not, how to handle it.
+///
+/// Policies can be combined as a tuple, such as a [`RatePolicy`] and a [`ConcurrentPolicy`],
+/// in which case a request only proceeds once both allow it to.
 pub trait Policy<Request> {
     /// The guard type that is returned when the request is allowed to proceed.
     ///
@@ -40,4 +70,61 @@ pub trait Policy<Request> {
         &self,
         request: &mut Request,
     ) -> impl std::future::Future<Output = PolicyOutput<Self::Guard, Self::Error>>;
+
+    /// Called once the inner service handled a request which was allowed to proceed,
+    /// with the guard of the request and the outcome of the request.
+    ///
+    /// Drops the guard by default, but can be used to adapt the limit to the health
+    /// of the service, such as the [`AdaptiveConcurrencyPolicy`]. It is not called for
+    /// requests which were cancelled, in which case only the guard is dropped.
+    fn record(&self, guard: Self::Guard, outcome: Outcome) {
+        let _ = outcome;
+        drop(guard);
+    }
+
+    /// Called when the check of a request was retried more often than allowed
+    /// by [`Limit::max_retries`], right before the request fails.
+    ///
+    /// Does nothing by default, but can be used to report overloads.
+    ///
+    /// [`Limit::max_retries`]: super::Limit::max_retries
+    fn retries_exceeded(&self, request: &Request) {
+        let _ = request;
+    }
+}
+
+impl<P1, P2, Request> Policy<Request> for (P1, P2)
+where
+    P1: Policy<Request>,
+    P2: Policy<Request>,
+    P1::Error: Into<BoxError>,
+    P2::Error: Into<BoxError>,
+{
+    type Guard = (P1::Guard, P2::Guard);
+    type Error = BoxError;
+
+    async fn check(&self, request: &mut Request) -> PolicyOutput<Self::Guard, Self::Error> {
+        let first = match self.0.check(request).await {
+            PolicyOutput::Ready(guard) => guard,
+            PolicyOutput::Abort(err) => return PolicyOutput::Abort(err.into()),
+            PolicyOutput::Retry => return PolicyOutput::Retry,
+        };
+        // the guard of the first policy is dropped, releasing its limit,
+        // unless the second policy allows the request to proceed as well
+        match self.1.check(request).await {
+            PolicyOutput::Ready(guard) => PolicyOutput::Ready((first, guard)),
+            PolicyOutput::Abort(err) => PolicyOutput::Abort(err.into()),
+            PolicyOutput::Retry => PolicyOutput::Retry,
+        }
+    }
+
+    fn record(&self, (first, second): Self::Guard, outcome: Outcome) {
+        self.0.record(first, outcome);
+        self.1.record(second, outcome);
+    }
+
+    fn retries_exceeded(&self, request: &Request) {
+        self.0.retries_exceeded(request);
+        self.1.retries_exceeded(request);
+    }
 }
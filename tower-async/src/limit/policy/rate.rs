@@ -0,0 +1,227 @@
+//! A policy that limits the rate at which requests are processed.
+//!
+//! See [`RatePolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//! use tower_async::{
+//!     limit::{Limit, policy::RatePolicy},
+//!     Service, ServiceExt, service_fn,
+//! };
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//!
+//! let service = service_fn(|_| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//! // Allow 10 requests per second.
+//! let mut service = Limit::new(service, RatePolicy::new(10, Duration::from_secs(1)));
+//!
+//! let response = service.oneshot(()).await;
+//! assert!(response.is_ok());
+//! # }
+//! ```
+
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::{Policy, PolicyOutput};
+use crate::dynamic::Dynamic;
+
+/// A policy that allows `num` requests to proceed per period of time.
+///
+/// Requests exceeding the rate wait until the current period has ended, after which the
+/// [`Limit`] middleware checks them again. Requests are never aborted.
+///
+/// All clones of the policy share the same rate.
+///
+/// The rate can be configured with [`Dynamic`] values, which can be updated while requests are
+/// processed. Updates apply from the next period on. A `num` updated to zero blocks all
+/// requests until it is updated again, while a `per` updated to zero lifts the limit right
+/// away, even if `num` is zero as well.
+///
+/// [`Limit`]: crate::limit::Limit
+#[derive(Debug, Clone)]
+pub struct RatePolicy {
+    num: Dynamic<u64>,
+    per: Dynamic<Duration>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    until: Instant,
+    remaining: u64,
+}
+
+impl RatePolicy {
+    /// Create a new rate policy, allowing `num` requests per `per` period.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero when the policy is created.
+    pub fn new(num: impl Into<Dynamic<u64>>, per: impl Into<Dynamic<Duration>>) -> Self {
+        let (num, per) = (num.into(), per.into());
+        assert!(num.get() > 0, "rate must allow at least one request");
+        assert!(
+            per.get() > Duration::ZERO,
+            "rate period must be greater than zero"
+        );
+
+        RatePolicy {
+            state: Arc::new(Mutex::new(State {
+                until: Instant::now(),
+                remaining: num.get(),
+            })),
+            num,
+            per,
+        }
+    }
+
+    /// Returns the number of requests currently allowed per period.
+    pub fn num(&self) -> u64 {
+        self.num.get()
+    }
+
+    /// Returns the current period of the rate.
+    pub fn per(&self) -> Duration {
+        self.per.get()
+    }
+
+    /// Returns the time until the current period ends, after which requests are allowed again,
+    /// or `None` if requests are still allowed in the current period.
+    ///
+    /// This can be used to tell rejected clients when to retry, such as in a `Retry-After` header.
+    pub fn available_after(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if now >= state.until || state.remaining > 0 {
+            return None;
+        }
+        Some(state.until - now)
+    }
+}
+
+impl<Request> Policy<Request> for RatePolicy {
+    type Guard = ();
+    type Error = Infallible;
+
+    async fn check(&self, _: &mut Request) -> PolicyOutput<Self::Guard, Self::Error> {
+        let until = {
+            let per = self.per.get();
+            if per.is_zero() {
+                // checked at use, as the period can be updated to zero at runtime,
+                // where waiting for the end of an empty period would spin
+                return PolicyOutput::Ready(());
+            }
+
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            if now >= state.until {
+                state.until = now + per;
+                state.remaining = self.num.get();
+            }
+            if state.remaining > 0 {
+                state.remaining -= 1;
+                return PolicyOutput::Ready(());
+            }
+            state.until
+        };
+
+        tokio::time::sleep_until(until).await;
+        PolicyOutput::Retry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{limit::Limit, service_fn};
+    use futures_util::future::join_all;
+    use tower_async_service::Service;
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_policy() {
+        let service = Limit::new(
+            service_fn(|n: u32| async move { Ok::<_, Infallible>((n, Instant::now())) }),
+            RatePolicy::new(2, Duration::from_secs(1)),
+        );
+
+        let start = Instant::now();
+        let responses = join_all((0..5).map(|n| service.call(n))).await;
+        let mut elapsed: Vec<_> = responses
+            .into_iter()
+            .map(|response| (response.unwrap().1 - start).as_secs())
+            .collect();
+        elapsed.sort();
+        assert_eq!(elapsed, [0, 0, 1, 1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_rate_updates() {
+        let num = Dynamic::new(1);
+        let per = Dynamic::new(Duration::from_secs(1));
+        let policy = RatePolicy::new(num.clone(), per.clone());
+
+        // a zero period lifts the limit, also when no requests are allowed
+        num.set(0);
+        per.set(Duration::ZERO);
+        for _ in 0..3 {
+            assert!(matches!(
+                policy.check(&mut ()).await,
+                PolicyOutput::Ready(())
+            ));
+        }
+
+        // no requests are allowed, requests wait for the end of the period
+        per.set(Duration::from_secs(1));
+        let start = Instant::now();
+        assert!(matches!(policy.check(&mut ()).await, PolicyOutput::Retry));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn available_after_period_ends() {
+        let policy = RatePolicy::new(1, Duration::from_secs(1));
+        assert_eq!(policy.available_after(), None);
+
+        assert!(matches!(
+            policy.check(&mut ()).await,
+            PolicyOutput::Ready(())
+        ));
+        tokio::time::advance(Duration::from_millis(400)).await;
+        assert_eq!(policy.available_after(), Some(Duration::from_millis(600)));
+
+        tokio::time::advance(Duration::from_millis(600)).await;
+        assert_eq!(policy.available_after(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dynamic_rate_policy() {
+        let num = Dynamic::new(1);
+        let service = Limit::new(
+            service_fn(|n: u32| async move { Ok::<_, Infallible>((n, Instant::now())) }),
+            RatePolicy::new(num.clone(), Duration::from_secs(1)),
+        );
+
+        let start = Instant::now();
+        service.call(0).await.unwrap();
+        num.set(3);
+        let responses = join_all((1..4).map(|n| service.call(n))).await;
+        let elapsed: Vec<_> = responses
+            .into_iter()
+            .map(|response| (response.unwrap().1 - start).as_secs())
+            .collect();
+        assert_eq!(elapsed, [1, 1, 1]);
+    }
+}
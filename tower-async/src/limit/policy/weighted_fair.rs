@@ -0,0 +1,463 @@
+//! A policy that shares a concurrency limit fairly between keys, such as tenants.
+//!
+//! See [`WeightedFairPolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use tower_async::{
+//!     limit::{Limit, policy::WeightedFairPolicy},
+//!     Service, ServiceExt, service_fn,
+//! };
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! struct Request {
+//!     tenant: &'static str,
+//! }
+//!
+//! let service = service_fn(|_: Request| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//!
+//! // Allow 100 requests in flight, of which "enterprise" gets three times
+//! // the share of every other tenant when they compete for capacity.
+//! let policy = WeightedFairPolicy::new(100, |req: &Request| req.tenant);
+//! policy.set_weight("enterprise", 3);
+//!
+//! let mut service = Limit::new(service, policy);
+//!
+//! let response = service.oneshot(Request { tenant: "enterprise" }).await;
+//! assert!(response.is_ok());
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::oneshot;
+
+use super::{LimitReached, Policy, PolicyOutput};
+
+/// A policy that shares a concurrency limit between keys, proportional to their weights.
+///
+/// Every request is assigned a key, such as the tenant it was sent for, by a function
+/// given to [`WeightedFairPolicy::new`]. While fewer than `max` requests are in flight,
+/// requests are allowed to proceed immediately, whatever their key, such that a single
+/// key can borrow all capacity that is not used by others.
+///
+/// Once the limit is reached, requests wait in a queue per key. Every time a request
+/// completes, its slot is given to the waiting request of the key with the fewest requests
+/// in flight relative to its weight. As a result, keys competing for capacity converge to
+/// a share of `max` proportional to their weight, which is at least
+/// `max * weight / total weight of the competing keys`. A large tenant can therefore not
+/// starve small tenants, while still using all capacity when nobody else needs it.
+///
+/// Keys have a weight of `1`, unless configured otherwise using
+/// [`WeightedFairPolicy::set_weight`]. The number of requests waiting per key is unbounded,
+/// unless [limited](WeightedFairPolicy::max_queued), in which case requests exceeding it are
+/// aborted with [`LimitReached`].
+///
+/// All clones of the policy share the same limit.
+pub struct WeightedFairPolicy<K, F> {
+    max: usize,
+    max_queued: Option<usize>,
+    key: F,
+    state: Arc<Mutex<State<K>>>,
+}
+
+struct State<K> {
+    in_flight: usize,
+    keys: HashMap<K, KeyState>,
+    weights: HashMap<K, usize>,
+}
+
+struct KeyState {
+    in_flight: usize,
+    queue: VecDeque<oneshot::Sender<()>>,
+}
+
+impl<K, F> WeightedFairPolicy<K, F> {
+    /// Create a new weighted fair policy, allowing `max` requests in flight,
+    /// using `key` to determine the key of a request.
+    pub fn new(max: usize, key: F) -> Self {
+        WeightedFairPolicy {
+            max,
+            max_queued: None,
+            key,
+            state: Arc::new(Mutex::new(State {
+                in_flight: 0,
+                keys: HashMap::new(),
+                weights: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Set the maximum number of requests that can wait for capacity per key.
+    ///
+    /// Requests exceeding it are aborted with [`LimitReached`].
+    pub fn max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = Some(max_queued);
+        self
+    }
+
+    /// Returns the maximum number of concurrent requests allowed by this policy.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Returns the number of requests currently in flight,
+    /// shared by all clones of this policy.
+    pub fn current(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+}
+
+impl<K, F> WeightedFairPolicy<K, F>
+where
+    K: Hash + Eq,
+{
+    /// Set the weight of `key`, which defaults to `1`.
+    ///
+    /// Applies to all clones of this policy, and can be changed while it is in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is zero.
+    pub fn set_weight(&self, key: K, weight: usize) {
+        assert!(weight > 0, "weight must be at least 1");
+        self.state.lock().unwrap().weights.insert(key, weight);
+    }
+
+    /// Returns the number of requests with the given key currently in flight.
+    pub fn current_for(&self, key: &K) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .keys
+            .get(key)
+            .map_or(0, |state| state.in_flight)
+    }
+}
+
+impl<K, F> Clone for WeightedFairPolicy<K, F>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        WeightedFairPolicy {
+            max: self.max,
+            max_queued: self.max_queued,
+            key: self.key.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K, F> fmt::Debug for WeightedFairPolicy<K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeightedFairPolicy")
+            .field("max", &self.max)
+            .field("max_queued", &self.max_queued)
+            .field("current", &self.current())
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<K> State<K>
+where
+    K: Hash + Eq + Clone,
+{
+    fn acquire(&mut self, key: &K) {
+        self.in_flight += 1;
+        self.key_state(key).in_flight += 1;
+    }
+
+    fn release(&mut self, key: &K) {
+        self.in_flight -= 1;
+        if let Some(state) = self.keys.get_mut(key) {
+            state.in_flight -= 1;
+        }
+
+        // Hand the slot to the waiting key with the lowest weighted usage.
+        loop {
+            let next = self
+                .keys
+                .iter()
+                .filter(|(_, state)| !state.queue.is_empty())
+                .min_by(|(a, a_state), (b, b_state)| {
+                    // compare (in_flight + 1) / weight without dividing
+                    ((a_state.in_flight + 1) * self.weight(b))
+                        .cmp(&((b_state.in_flight + 1) * self.weight(a)))
+                })
+                .map(|(key, _)| key.clone());
+            let Some(next) = next else { break };
+
+            let tx = self
+                .key_state(&next)
+                .queue
+                .pop_front()
+                .expect("selected key has a waiting request");
+            if tx.send(()).is_ok() {
+                self.acquire(&next);
+                break;
+            }
+            // the waiting request was cancelled, try the next one
+        }
+
+        self.keys
+            .retain(|_, state| state.in_flight > 0 || !state.queue.is_empty());
+    }
+
+    fn weight(&self, key: &K) -> usize {
+        self.weights.get(key).copied().unwrap_or(1)
+    }
+
+    fn key_state(&mut self, key: &K) -> &mut KeyState {
+        self.keys.entry(key.clone()).or_insert_with(|| KeyState {
+            in_flight: 0,
+            queue: VecDeque::new(),
+        })
+    }
+}
+
+/// The guard that releases the slot of a request admitted by a [`WeightedFairPolicy`].
+pub struct WeightedFairGuard<K>
+where
+    K: Hash + Eq + Clone,
+{
+    key: K,
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K> fmt::Debug for WeightedFairGuard<K>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeightedFairGuard")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl<K> Drop for WeightedFairGuard<K>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        self.state.lock().unwrap().release(&self.key);
+    }
+}
+
+/// Releases the slot handed to a waiting request that was cancelled
+/// before it could claim it.
+struct Waiting<K>
+where
+    K: Hash + Eq + Clone,
+{
+    rx: oneshot::Receiver<()>,
+    key: K,
+    state: Arc<Mutex<State<K>>>,
+}
+
+impl<K> Drop for Waiting<K>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        if self.rx.try_recv().is_ok() {
+            self.state.lock().unwrap().release(&self.key);
+        }
+    }
+}
+
+impl<K, F, Request> Policy<Request> for WeightedFairPolicy<K, F>
+where
+    K: Hash + Eq + Clone,
+    F: Fn(&Request) -> K,
+{
+    type Guard = WeightedFairGuard<K>;
+    type Error = LimitReached;
+
+    async fn check(&self, request: &mut Request) -> PolicyOutput<Self::Guard, Self::Error> {
+        let key = (self.key)(request);
+
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight < self.max {
+                state.acquire(&key);
+                drop(state);
+                return PolicyOutput::Ready(WeightedFairGuard {
+                    key,
+                    state: self.state.clone(),
+                });
+            }
+
+            let queue = &mut state.key_state(&key).queue;
+            queue.retain(|tx| !tx.is_closed());
+            if matches!(self.max_queued, Some(max_queued) if queue.len() >= max_queued) {
+                return PolicyOutput::Abort(LimitReached);
+            }
+            let (tx, rx) = oneshot::channel();
+            queue.push_back(tx);
+            rx
+        };
+
+        let mut waiting = Waiting {
+            rx,
+            key,
+            state: self.state.clone(),
+        };
+        match (&mut waiting.rx).await {
+            // the slot was acquired for this request when it was handed over
+            Ok(()) => PolicyOutput::Ready(WeightedFairGuard {
+                key: waiting.key.clone(),
+                state: self.state.clone(),
+            }),
+            Err(_) => PolicyOutput::Retry,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::FutureExt;
+    use std::{future::Future, pin::Pin};
+
+    fn assert_ready<G, E>(output: PolicyOutput<G, E>) -> G {
+        match output {
+            PolicyOutput::Ready(guard) => guard,
+            _ => panic!("unexpected output, expected ready"),
+        }
+    }
+
+    type TestPolicy = WeightedFairPolicy<&'static str, fn(&&'static str) -> &'static str>;
+
+    fn wait(
+        policy: &TestPolicy,
+        key: &'static str,
+    ) -> Pin<Box<dyn Future<Output = PolicyOutput<WeightedFairGuard<&'static str>, LimitReached>>>>
+    {
+        let policy = policy.clone();
+        Box::pin(async move {
+            let mut key = key;
+            policy.check(&mut key).await
+        })
+    }
+
+    fn policy(max: usize) -> TestPolicy {
+        WeightedFairPolicy::new(max, |key: &&'static str| *key)
+    }
+
+    #[tokio::test]
+    async fn single_key_borrows_all_capacity() {
+        let policy = policy(3);
+
+        let _guards = [
+            assert_ready(policy.check(&mut "a").await),
+            assert_ready(policy.check(&mut "a").await),
+            assert_ready(policy.check(&mut "a").await),
+        ];
+        assert_eq!(policy.current(), 3);
+        assert_eq!(policy.current_for(&"a"), 3);
+
+        // at capacity, so the next request has to wait
+        assert!(wait(&policy, "b").now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn released_slots_go_to_least_served_key() {
+        let policy = policy(2);
+
+        let guard_1 = assert_ready(policy.check(&mut "big").await);
+        let guard_2 = assert_ready(policy.check(&mut "big").await);
+
+        let mut big = wait(&policy, "big");
+        assert!(futures::poll!(&mut big).is_pending());
+        let mut small = wait(&policy, "small");
+        assert!(futures::poll!(&mut small).is_pending());
+
+        // "small" waited shorter, but has fewer requests in flight
+        drop(guard_1);
+        let small = assert_ready(small.await);
+        assert!(futures::poll!(&mut big).is_pending());
+        assert_eq!(policy.current_for(&"small"), 1);
+
+        drop(guard_2);
+        let _big = assert_ready(big.await);
+        drop(small);
+        assert_eq!(policy.current(), 1);
+    }
+
+    #[tokio::test]
+    async fn weights_determine_share() {
+        let policy = policy(4);
+        policy.set_weight("heavy", 3);
+
+        let mut guards: Vec<_> = Vec::new();
+        for _ in 0..4 {
+            guards.push(assert_ready(policy.check(&mut "light").await));
+        }
+
+        // both keys keep requests waiting, while the "light" requests complete one by one
+        let mut waiting: Vec<_> = (0..4)
+            .flat_map(|_| ["heavy", "light"])
+            .map(|key| wait(&policy, key))
+            .collect();
+        for fut in &mut waiting {
+            assert!(futures::poll!(fut).is_pending());
+        }
+
+        for _ in 0..4 {
+            guards.remove(0);
+        }
+        assert_eq!(policy.current_for(&"heavy"), 3);
+        assert_eq!(policy.current_for(&"light"), 1);
+    }
+
+    #[tokio::test]
+    async fn max_queued_aborts() {
+        let policy = policy(1).max_queued(1);
+
+        let _guard = assert_ready(policy.check(&mut "a").await);
+        let mut waiting = wait(&policy, "a");
+        assert!(futures::poll!(&mut waiting).is_pending());
+
+        assert!(matches!(
+            policy.check(&mut "a").await,
+            PolicyOutput::Abort(LimitReached)
+        ));
+        // other keys have their own queue
+        assert!(wait(&policy, "b").now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiters_do_not_leak_slots() {
+        let policy = policy(1);
+
+        let guard = assert_ready(policy.check(&mut "a").await);
+        let mut cancelled = wait(&policy, "b");
+        assert!(futures::poll!(&mut cancelled).is_pending());
+        let mut handed_over = wait(&policy, "c");
+        assert!(futures::poll!(&mut handed_over).is_pending());
+
+        // cancelled before the slot is released
+        drop(cancelled);
+        drop(guard);
+        assert_eq!(policy.current_for(&"c"), 1);
+
+        // cancelled after the slot was handed over
+        drop(handed_over);
+        assert_eq!(policy.current(), 0);
+        let _guard = assert_ready(policy.check(&mut "a").await);
+    }
+}
@@ -0,0 +1,30 @@
+use super::LoadShed;
+use tower_async_layer::Layer;
+
+/// Wraps services with a [`LoadShed`] middleware, shedding requests when
+/// the given limit policy is at capacity.
+#[derive(Debug, Clone)]
+pub struct LoadShedLayer<P> {
+    policy: P,
+}
+
+impl<P> LoadShedLayer<P> {
+    /// Creates a new [`LoadShedLayer`] from a [`crate::limit::Policy`].
+    ///
+    /// All services produced by the layer share the limit of the policy,
+    /// if the policy shares its state between its clones.
+    pub fn new(policy: P) -> Self {
+        LoadShedLayer { policy }
+    }
+}
+
+impl<S, P> Layer<S> for LoadShedLayer<P>
+where
+    P: Clone,
+{
+    type Service = LoadShed<S, P>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        LoadShed::new(service, self.policy.clone())
+    }
+}
@@ -0,0 +1,233 @@
+//! Middleware that rejects requests right away when a limit is at capacity,
+//! instead of having them wait for it.
+//!
+//! A [`LoadShed`] checks a [limit policy](crate::limit::Policy) like a [`Limit`] does,
+//! but only lets a request proceed if the policy allows it to right away. When the policy
+//! would make the request wait, such as a [`ConcurrentPolicy`] with a backoff, a
+//! [`ConcurrentQueuePolicy`] or a [`RatePolicy`], or asks to retry its check, the request
+//! fails with an [`Overloaded`] error instead. The same policy can thus either queue requests
+//! when used by a [`Limit`], or shed them when used by a [`LoadShed`].
+//!
+//! Shedding load keeps the latency of the accepted requests low when a service is overloaded,
+//! and lets callers fail over to another replica instead of waiting.
+//!
+//! Errors of a policy aborting a request, such as [`LimitReached`], are returned as is.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{
+//!     limit::policy::ConcurrentQueuePolicy, load_shed::Overloaded, service_fn, Service,
+//!     ServiceBuilder,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = ServiceBuilder::new()
+//!     .load_shed(ConcurrentQueuePolicy::new(1))
+//!     .service(service_fn(|_: ()| async {
+//!         tokio::time::sleep(Duration::from_millis(10)).await;
+//!         Ok::<_, Infallible>(())
+//!     }));
+//!
+//! // the second request would have to wait for the first one, so it is shed
+//! let (first, second) = tokio::join!(service.call(()), service.call(()));
+//! assert!(first.is_ok());
+//! assert!(second.unwrap_err().is::<Overloaded>());
+//! # }
+//! ```
+//!
+//! [`Limit`]: crate::limit::Limit
+//! [`ConcurrentPolicy`]: crate::limit::policy::ConcurrentPolicy
+//! [`ConcurrentQueuePolicy`]: crate::limit::policy::ConcurrentQueuePolicy
+//! [`RatePolicy`]: crate::limit::policy::RatePolicy
+//! [`LimitReached`]: crate::limit::policy::LimitReached
+
+use std::{fmt, time::Duration};
+
+use futures_util::FutureExt;
+use tower_async_service::Service;
+
+use crate::{
+    limit::{Outcome, Policy, PolicyOutput},
+    BoxError,
+};
+
+mod layer;
+pub use layer::LoadShedLayer;
+
+/// Rejects requests with an [`Overloaded`] error when its limit policy is at capacity.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct LoadShed<S, P> {
+    inner: S,
+    policy: P,
+}
+
+impl<S, P> LoadShed<S, P> {
+    /// Creates a new [`LoadShed`], shedding the requests which the given
+    /// limit policy doesn't allow to proceed right away.
+    pub fn new(inner: S, policy: P) -> Self {
+        LoadShed { inner, policy }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a [`LoadShed`] middleware,
+    /// sharing the given limit policy.
+    ///
+    /// [`Layer`]: crate::Layer
+    pub fn layer(policy: P) -> LoadShedLayer<P> {
+        LoadShedLayer::new(policy)
+    }
+
+    /// Get a reference to the limit policy.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, P, Request> Service<Request> for LoadShed<S, P>
+where
+    S: Service<Request>,
+    S::Error: Into<BoxError>,
+    P: Policy<Request>,
+    P::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let mut request = request;
+        // a check which doesn't complete right away is waiting for capacity,
+        // dropping it leaves the queue of the policy, if any
+        let output = self.policy.check(&mut request).now_or_never();
+        match output {
+            Some(PolicyOutput::Ready(guard)) => {
+                let result = self.inner.call(request).await;
+                let outcome = match result {
+                    Ok(_) => Outcome::Success,
+                    Err(_) => Outcome::Failure,
+                };
+                self.policy.record(guard, outcome);
+                result.map_err(Into::into)
+            }
+            Some(PolicyOutput::Abort(err)) => Err(err.into()),
+            Some(PolicyOutput::Retry) | None => Err(Overloaded::new().into()),
+        }
+    }
+}
+
+/// The error that indicates the request was shed by a [`LoadShed`],
+/// because its limit policy is at capacity.
+///
+/// Services can return it as well to signal that they are overloaded, optionally with the
+/// delay after which the request can be retried. The `Backpressure` middleware of
+/// `tower-async-http` converts it into a response with a `Retry-After` header.
+#[derive(Debug, Clone, Default)]
+pub struct Overloaded {
+    retry_after: Option<Duration>,
+}
+
+impl Overloaded {
+    /// Create a new [`Overloaded`] error.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`Overloaded`] error, asking the caller to retry after the given delay.
+    pub fn retry_after(retry_after: Duration) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+        }
+    }
+
+    /// Returns the delay after which the request can be retried, if known.
+    pub fn retry_after_hint(&self) -> Option<Duration> {
+        self.retry_after
+    }
+}
+
+impl fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("service overloaded")
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{convert::Infallible, time::Duration};
+
+    use crate::{
+        limit::policy::{ConcurrentPolicy, ConcurrentQueuePolicy, LimitReached, RatePolicy},
+        service_fn,
+        util::backoff::NoBackoff,
+    };
+
+    async fn slow(_: ()) -> Result<(), Infallible> {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sheds_instead_of_retrying() {
+        let service = LoadShed::new(
+            service_fn(slow),
+            ConcurrentPolicy::with_backoff(1, NoBackoff::new()),
+        );
+
+        let (first, second) = tokio::join!(service.call(()), service.call(()));
+        first.unwrap();
+        assert!(second.unwrap_err().is::<Overloaded>());
+
+        // the limit is released once the request completed
+        assert_eq!(service.policy().current(), 0);
+        service.call(()).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sheds_instead_of_queueing() {
+        let service = LoadShed::new(service_fn(slow), ConcurrentQueuePolicy::new(1));
+
+        let (first, second) = tokio::join!(service.call(()), service.call(()));
+        first.unwrap();
+        assert!(second.unwrap_err().is::<Overloaded>());
+        // the shed request left the queue
+        assert_eq!(service.policy().queued(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sheds_rate_limited_requests() {
+        let service = LoadShed::new(
+            service_fn(|_: ()| async { Ok::<_, Infallible>(()) }),
+            RatePolicy::new(1, Duration::from_secs(1)),
+        );
+
+        service.call(()).await.unwrap();
+        assert!(service.call(()).await.unwrap_err().is::<Overloaded>());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        service.call(()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn passes_abort_errors() {
+        let service = LoadShed::new(service_fn(slow), ConcurrentPolicy::new(0));
+
+        assert!(service.call(()).await.unwrap_err().is::<LimitReached>());
+    }
+}
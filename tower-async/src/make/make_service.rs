@@ -5,6 +5,7 @@ use std::fmt;
 use std::marker::PhantomData;
 use tower_async_service::Service;
 
+pub(crate) mod layered;
 pub(crate) mod shared;
 
 /// Creates new [`Service`] values.
@@ -0,0 +1,206 @@
+use std::fmt;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// A [`MakeService`] that applies a [`Layer`] to each service it produces,
+/// where the layer is created from the target the service is made for.
+///
+/// This allows middleware to be configured per target, such as per connection,
+/// without having to write a wrapper around the inner [`MakeService`].
+///
+/// # Example
+///
+/// ```
+/// use std::{convert::Infallible, net::SocketAddr};
+/// use tower_async::{
+///     make::{LayeredMakeService, MakeService, Shared},
+///     service_fn,
+///     util::MapRequestLayer,
+///     Service,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let make_service = Shared::new(service_fn(|req: (SocketAddr, String)| async move {
+///     Ok::<_, Infallible>(format!("{} says {}", req.0, req.1))
+/// }));
+///
+/// // tag all requests of a connection with the address of its peer
+/// let make_service = LayeredMakeService::new(make_service, |peer_addr: &SocketAddr| {
+///     let peer_addr = *peer_addr;
+///     MapRequestLayer::new(move |req: String| (peer_addr, req))
+/// });
+///
+/// let peer_addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+/// let svc = make_service.make_service(peer_addr).await.unwrap();
+///
+/// let res = svc.call("hello".to_string()).await.unwrap();
+/// assert_eq!(res, "127.0.0.1:8080 says hello");
+/// # }
+/// ```
+///
+/// [`MakeService`]: super::MakeService
+/// [`Layer`]: tower_async_layer::Layer
+#[derive(Clone)]
+pub struct LayeredMakeService<M, F> {
+    make: M,
+    f: F,
+}
+
+impl<M, F> fmt::Debug for LayeredMakeService<M, F>
+where
+    M: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayeredMakeService")
+            .field("make", &self.make)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<M, F> LayeredMakeService<M, F> {
+    /// Create a new [`LayeredMakeService`].
+    ///
+    /// `f` is called with a reference to the target for every service made,
+    /// and returns the [`Layer`] to apply to that service.
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn new(make: M, f: F) -> Self {
+        Self { make, f }
+    }
+
+    /// Returns a new [`Layer`] that produces [`LayeredMakeService`] services.
+    ///
+    /// This is a convenience function that simply calls [`LayeredMakeServiceLayer::new`].
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(f: F) -> LayeredMakeServiceLayer<F> {
+        LayeredMakeServiceLayer { f }
+    }
+
+    /// Get a reference to the inner [`MakeService`].
+    ///
+    /// [`MakeService`]: super::MakeService
+    pub fn get_ref(&self) -> &M {
+        &self.make
+    }
+
+    /// Consume `self`, returning the inner [`MakeService`].
+    ///
+    /// [`MakeService`]: super::MakeService
+    pub fn into_inner(self) -> M {
+        self.make
+    }
+}
+
+impl<M, F, L, Target> Service<Target> for LayeredMakeService<M, F>
+where
+    M: Service<Target>,
+    F: Fn(&Target) -> L,
+    L: Layer<M::Response>,
+{
+    type Response = L::Service;
+    type Error = M::Error;
+
+    async fn call(&self, target: Target) -> Result<Self::Response, Self::Error> {
+        let layer = (self.f)(&target);
+        let service = self.make.call(target).await?;
+        Ok(layer.layer(service))
+    }
+}
+
+/// A [`Layer`] that produces [`LayeredMakeService`] services.
+///
+/// [`Layer`]: tower_async_layer::Layer
+#[derive(Clone)]
+pub struct LayeredMakeServiceLayer<F> {
+    f: F,
+}
+
+impl<F> fmt::Debug for LayeredMakeServiceLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayeredMakeServiceLayer")
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<F> LayeredMakeServiceLayer<F> {
+    /// Creates a new [`LayeredMakeServiceLayer`].
+    pub fn new(f: F) -> Self {
+        Self { f }
+    }
+}
+
+impl<M, F> Layer<M> for LayeredMakeServiceLayer<F>
+where
+    F: Clone,
+{
+    type Service = LayeredMakeService<M, F>;
+
+    fn layer(&self, make: M) -> Self::Service {
+        LayeredMakeService {
+            make,
+            f: self.f.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::make::{MakeService, Shared};
+    use crate::service_fn;
+    use std::convert::Infallible;
+    use tower_async_layer::layer_fn;
+
+    #[derive(Debug, Clone)]
+    struct Prefix<S> {
+        inner: S,
+        prefix: &'static str,
+    }
+
+    impl<S> Service<String> for Prefix<S>
+    where
+        S: Service<String>,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+
+        async fn call(&self, req: String) -> Result<Self::Response, Self::Error> {
+            self.inner.call(format!("{}{}", self.prefix, req)).await
+        }
+    }
+
+    #[tokio::test]
+    async fn layer_per_target() {
+        let make = Shared::new(service_fn(
+            |req: String| async move { Ok::<_, Infallible>(req) },
+        ));
+        let make = LayeredMakeService::new(make, |target: &&'static str| {
+            let prefix = *target;
+            layer_fn(move |inner| Prefix { inner, prefix })
+        });
+
+        let foo = make.make_service("foo: ").await.unwrap();
+        let bar = make.make_service("bar: ").await.unwrap();
+
+        assert_eq!(foo.call("hello".to_owned()).await.unwrap(), "foo: hello");
+        assert_eq!(bar.call("hello".to_owned()).await.unwrap(), "bar: hello");
+    }
+
+    #[tokio::test]
+    async fn make_error_is_returned() {
+        let make = service_fn(|_: ()| async { Err::<(), _>("nope") });
+        let make = LayeredMakeServiceLayer::new(|_: &()| {
+            layer_fn(|inner| Prefix {
+                inner,
+                prefix: "unused",
+            })
+        })
+        .layer(make);
+
+        assert_eq!(make.call(()).await.unwrap_err(), "nope");
+    }
+}
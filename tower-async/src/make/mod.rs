@@ -4,5 +4,6 @@ mod make_connection;
 mod make_service;
 
 pub use self::make_connection::MakeConnection;
+pub use self::make_service::layered::{LayeredMakeService, LayeredMakeServiceLayer};
 pub use self::make_service::shared::Shared;
 pub use self::make_service::{AsService, IntoService, MakeService};
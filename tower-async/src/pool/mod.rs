@@ -0,0 +1,364 @@
+//! Middleware that shares requests among a pool of services.
+//!
+//! [`Pool`] makes up to a fixed number of services for a target, using a [`MakeService`],
+//! such as one connection-oriented client per connection to a database or an HTTP/2 server.
+//! Every request is sent to the instance with the fewest requests in flight, starting the
+//! search from a rotating position, such that idle instances take turns.
+//!
+//! The instances are made on demand, a request being sent to an instance which isn't made
+//! yet makes it first, or ahead of time using [`Pool::warm_up`], which makes all missing
+//! instances concurrently. Once a request fails with an error which the [`Policy`]
+//! classifies as a broken instance, the instance is evicted from the pool, and is made again
+//! for a later request. The failed request itself is not retried, which can be done by
+//! wrapping [`Pool`] with the [`Retry`] middleware.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{
+//!     io,
+//!     sync::atomic::{AtomicUsize, Ordering},
+//! };
+//! use tower_async::{pool::Pool, service_fn, Service};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! // Makes a new client for the given address.
+//! let connections = AtomicUsize::new(0);
+//! let connect = service_fn(|addr: &'static str| {
+//!     let connection = connections.fetch_add(1, Ordering::SeqCst);
+//!     async move {
+//!         Ok::<_, io::Error>(service_fn(move |request: String| async move {
+//!             Ok::<_, io::Error>(format!("{addr}#{connection} received {request}"))
+//!         }))
+//!     }
+//! });
+//!
+//! let pool = Pool::new(connect, "127.0.0.1:8080", 4);
+//! pool.warm_up().await?;
+//! assert_eq!(pool.ready(), 4);
+//!
+//! let response = pool.call("hello".to_owned()).await?;
+//! assert_eq!(response, "127.0.0.1:8080#0 received hello");
+//! let response = pool.call("hello".to_owned()).await?;
+//! assert_eq!(response, "127.0.0.1:8080#1 received hello");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MakeService`]: crate::MakeService
+//! [`Retry`]: crate::retry::Retry
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures_util::future::join_all;
+use tokio::sync::Mutex;
+use tower_async_service::Service;
+
+pub use crate::reconnect::{Policy, ReconnectOnError};
+use crate::BoxError;
+
+/// Shares requests among a pool of services made for a target.
+///
+/// See the [module docs](self) for more details.
+pub struct Pool<M, Target, P = ReconnectOnError>
+where
+    M: Service<Target>,
+{
+    make: M,
+    target: Target,
+    policy: P,
+    slots: Box<[Mutex<Option<Arc<M::Response>>>]>,
+    next: AtomicUsize,
+}
+
+impl<M, Target> Pool<M, Target>
+where
+    M: Service<Target>,
+{
+    /// Creates a new [`Pool`] of up to `size` services, made for `target` by `make`.
+    ///
+    /// No service is made until the first request is received,
+    /// or [`Pool::warm_up`] is called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(make: M, target: Target, size: usize) -> Self {
+        assert!(size > 0, "pool size must be greater than zero");
+        Pool {
+            make,
+            target,
+            policy: ReconnectOnError::default(),
+            slots: (0..size).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<M, Target, P> Pool<M, Target, P>
+where
+    M: Service<Target>,
+{
+    /// Set the [`Policy`] which decides whether an error means the service that returned it
+    /// is broken, such that it is evicted from the pool.
+    ///
+    /// By default services are evicted on every error.
+    pub fn policy<NewP>(self, policy: NewP) -> Pool<M, Target, NewP> {
+        Pool {
+            make: self.make,
+            target: self.target,
+            policy,
+            slots: self.slots,
+            next: self.next,
+        }
+    }
+
+    /// Returns the maximum number of services in the pool.
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the number of services in the pool which are made,
+    /// not counting the services which are being made right now.
+    pub fn ready(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| matches!(slot.try_lock(), Ok(service) if service.is_some()))
+            .count()
+    }
+
+    /// Makes all the services of the pool which aren't made yet, concurrently.
+    ///
+    /// Returns the first error if any of the services failed to be made,
+    /// the services that were made successfully are kept.
+    pub async fn warm_up(&self) -> Result<(), BoxError>
+    where
+        M::Error: Into<BoxError>,
+        Target: Clone,
+    {
+        let results = join_all((0..self.slots.len()).map(|index| self.make_at(index))).await;
+        results.into_iter().try_for_each(|result| result.map(drop))
+    }
+
+    /// Get a reference to the inner [`MakeService`]
+    ///
+    /// [`MakeService`]: crate::MakeService
+    pub fn get_ref(&self) -> &M {
+        &self.make
+    }
+
+    /// Consume `self`, returning the inner [`MakeService`]
+    ///
+    /// [`MakeService`]: crate::MakeService
+    pub fn into_inner(self) -> M {
+        self.make
+    }
+
+    /// Returns the service at `index`, making it first if needed.
+    async fn make_at(&self, index: usize) -> Result<Arc<M::Response>, BoxError>
+    where
+        M::Error: Into<BoxError>,
+        Target: Clone,
+    {
+        let mut slot = self.slots[index].lock().await;
+        if let Some(service) = &*slot {
+            return Ok(service.clone());
+        }
+        let service = Arc::new(
+            self.make
+                .call(self.target.clone())
+                .await
+                .map_err(Into::into)?,
+        );
+        *slot = Some(service.clone());
+        Ok(service)
+    }
+
+    /// Returns the service with the fewest requests in flight, starting from `start`,
+    /// skipping the services which aren't made, or are being made right now.
+    fn pick(&self, start: usize) -> Option<(usize, Arc<M::Response>)> {
+        let mut picked: Option<(usize, Arc<M::Response>, usize)> = None;
+        for offset in 0..self.slots.len() {
+            let index = (start + offset) % self.slots.len();
+            let Ok(slot) = self.slots[index].try_lock() else {
+                continue;
+            };
+            let Some(service) = &*slot else {
+                continue;
+            };
+            // every request in flight holds a reference to the service
+            let load = Arc::strong_count(service);
+            if picked.as_ref().is_none_or(|(_, _, picked)| load < *picked) {
+                picked = Some((index, service.clone(), load));
+            }
+        }
+        picked.map(|(index, service, _)| (index, service))
+    }
+
+    /// Evicts the service at `index`, unless it was replaced already.
+    async fn evict(&self, index: usize, service: &Arc<M::Response>) {
+        let mut slot = self.slots[index].lock().await;
+        if matches!(&*slot, Some(current) if Arc::ptr_eq(current, service)) {
+            *slot = None;
+        }
+    }
+}
+
+impl<M, Target, P, S, Request> Service<Request> for Pool<M, Target, P>
+where
+    M: Service<Target, Response = S>,
+    M::Error: Into<BoxError>,
+    S: Service<Request>,
+    S::Error: Into<BoxError>,
+    Target: Clone,
+    P: Policy<S::Error>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+
+        // the request landing on a missing service makes it,
+        // unless another service can take the request when that fails
+        let vacant = matches!(self.slots[start].try_lock(), Ok(slot) if slot.is_none());
+        let (index, service) = match self.pick(start) {
+            Some(picked) if !vacant => picked,
+            picked => match self.make_at(start).await {
+                Ok(service) => (start, service),
+                Err(err) => picked.or_else(|| self.pick(start)).ok_or(err)?,
+            },
+        };
+
+        match service.call(request).await {
+            Ok(response) => Ok(response),
+            Err(err) => {
+                if self.policy.should_reconnect(&err) {
+                    self.evict(index, &service).await;
+                }
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl<M, Target, P> fmt::Debug for Pool<M, Target, P>
+where
+    M: Service<Target> + fmt::Debug,
+    Target: fmt::Debug,
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("make", &self.make)
+            .field("target", &self.target)
+            .field("policy", &self.policy)
+            .field("size", &self.slots.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::service_fn;
+
+    /// Makes services responding with the index of the service, failing `"disconnect"` and
+    /// `"invalid"` requests, and waiting for `"slow"` requests to be released.
+    fn connect(
+        connects: Arc<AtomicUsize>,
+        release: Arc<tokio::sync::Semaphore>,
+    ) -> impl Service<
+        (),
+        Response = impl Service<&'static str, Response = usize, Error = &'static str>,
+        Error = BoxError,
+    > {
+        service_fn(move |_: ()| {
+            let connection = connects.fetch_add(1, Ordering::SeqCst);
+            let release = release.clone();
+            async move {
+                Ok(service_fn(move |request: &'static str| {
+                    let release = release.clone();
+                    async move {
+                        match request {
+                            "disconnect" | "invalid" => Err(request),
+                            "slow" => {
+                                let _ = release.acquire().await.unwrap();
+                                Ok(connection)
+                            }
+                            _ => Ok(connection),
+                        }
+                    }
+                }))
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn shares_requests_among_services() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(tokio::sync::Semaphore::new(0));
+        let pool = Pool::new(connect(connects.clone(), release.clone()), (), 3);
+        assert_eq!(pool.ready(), 0);
+
+        pool.warm_up().await.unwrap();
+        assert_eq!(pool.ready(), 3);
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+
+        // idle services take turns
+        let mut responses = Vec::new();
+        for _ in 0..4 {
+            responses.push(pool.call("hello").await.unwrap());
+        }
+        assert_eq!(responses, [0, 1, 2, 0]);
+
+        // busy services are skipped
+        let slow = pool.call("slow");
+        let fast = async {
+            let mut responses = Vec::new();
+            for _ in 0..4 {
+                responses.push(pool.call("hello").await.unwrap());
+            }
+            release.add_permits(1);
+            responses
+        };
+        let (slow, fast) = tokio::join!(slow, fast);
+        let slow = slow.unwrap();
+        assert!(!fast.contains(&slow), "{slow} in {fast:?}");
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn evicts_broken_services() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(tokio::sync::Semaphore::new(0));
+        let pool = Pool::new(connect(connects.clone(), release), (), 2)
+            .policy(|err: &&'static str| *err == "disconnect");
+        pool.warm_up().await.unwrap();
+
+        // not considered broken
+        pool.call("invalid").await.unwrap_err();
+        assert_eq!(pool.ready(), 2);
+
+        pool.call("disconnect").await.unwrap_err();
+        assert_eq!(pool.ready(), 1);
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        // made again by the request landing on it
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            responses.push(pool.call("hello").await.unwrap());
+        }
+        assert_eq!(responses, [0, 2]);
+        assert_eq!(pool.ready(), 2);
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,400 @@
+//! Middleware that re-establishes the inner service when its connection is broken.
+//!
+//! [`Reconnect`] wraps a [`MakeService`] and a target. The service is made for the target
+//! when the first request is received and used for subsequent requests. Once a request fails
+//! with an error that the [`Policy`] classifies as a broken connection, the service is dropped
+//! and a new one is made for the next request. The failed request itself is not retried, which
+//! can be done by wrapping [`Reconnect`] with the [`Retry`] middleware.
+//!
+//! If making the service fails, the error is returned for the request which triggered the
+//! attempt, and subsequent attempts wait for the configured [`Backoff`] first. The backoff is
+//! reset once a service has been made.
+//!
+//! Only one request at a time makes a new service, concurrent requests wait for it
+//! to be made instead.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, io, time::Duration};
+//! use tower_async::{
+//!     reconnect::Reconnect,
+//!     service_fn,
+//!     util::backoff::ExponentialBackoffMaker,
+//!     util::rng::HasherRng,
+//!     Service,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), tower_async::BoxError> {
+//! // Makes a new client for the given address.
+//! let connect = service_fn(|addr: &'static str| async move {
+//!     Ok::<_, io::Error>(service_fn(move |request: String| async move {
+//!         Ok::<_, io::Error>(format!("{addr} received {request}"))
+//!     }))
+//! });
+//!
+//! let backoff = ExponentialBackoffMaker::new(
+//!     Duration::from_millis(50),
+//!     Duration::from_secs(10),
+//!     0.5,
+//!     HasherRng::default(),
+//! )?;
+//!
+//! let svc = Reconnect::new(connect, "127.0.0.1:8080")
+//!     // only reconnect on I/O errors that indicate a broken connection
+//!     .policy(|err: &io::Error| {
+//!         matches!(
+//!             err.kind(),
+//!             io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe
+//!         )
+//!     })
+//!     .backoff(backoff);
+//!
+//! let response = svc.call("hello".to_owned()).await?;
+//! assert_eq!(response, "127.0.0.1:8080 received hello");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MakeService`]: crate::MakeService
+//! [`Retry`]: crate::retry::Retry
+//! [`Backoff`]: crate::util::backoff::Backoff
+
+use std::{fmt, sync::Arc};
+
+use tokio::sync::Mutex;
+use tower_async_service::Service;
+
+use crate::util::backoff::{Backoff, MakeBackoff, NoBackoff};
+use crate::BoxError;
+
+mod policy;
+pub use policy::{Policy, ReconnectOnError};
+
+/// Re-establishes the inner service when its connection is broken.
+///
+/// See the [module docs](self) for more details.
+pub struct Reconnect<M, Target, P = ReconnectOnError, B = NoBackoff>
+where
+    M: Service<Target>,
+    B: MakeBackoff,
+{
+    make: M,
+    target: Target,
+    policy: P,
+    make_backoff: B,
+    state: Mutex<State<M::Response, B::Backoff>>,
+}
+
+struct State<S, B> {
+    service: Option<Arc<S>>,
+    backoff: Option<B>,
+}
+
+impl<S, B> Default for State<S, B> {
+    fn default() -> Self {
+        Self {
+            service: None,
+            backoff: None,
+        }
+    }
+}
+
+impl<M, Target> Reconnect<M, Target>
+where
+    M: Service<Target>,
+{
+    /// Creates a new [`Reconnect`], making services for the given target.
+    ///
+    /// By default every error returned by the service is considered a broken connection,
+    /// and services are made again without backoff.
+    pub fn new(make: M, target: Target) -> Self {
+        Reconnect {
+            make,
+            target,
+            policy: ReconnectOnError::default(),
+            make_backoff: NoBackoff::default(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Creates a new [`Reconnect`] using an already made `service` for the first requests,
+    /// making new services for the given target once it is broken.
+    pub fn with_service(service: M::Response, make: M, target: Target) -> Self {
+        Reconnect {
+            state: Mutex::new(State {
+                service: Some(Arc::new(service)),
+                backoff: None,
+            }),
+            ..Reconnect::new(make, target)
+        }
+    }
+}
+
+impl<M, Target, P, B> Reconnect<M, Target, P, B>
+where
+    M: Service<Target>,
+    B: MakeBackoff,
+{
+    /// Set the [`Policy`] which determines what errors mean that the connection is broken.
+    pub fn policy<NewP>(self, policy: NewP) -> Reconnect<M, Target, NewP, B> {
+        Reconnect {
+            make: self.make,
+            target: self.target,
+            policy,
+            make_backoff: self.make_backoff,
+            state: self.state,
+        }
+    }
+
+    /// Set the [`Backoff`] to wait for before making a service again, after it failed.
+    ///
+    /// The current service, such as the one given to [`Reconnect::with_service`], is kept.
+    ///
+    /// [`Backoff`]: crate::util::backoff::Backoff
+    pub fn backoff<NewB>(self, make_backoff: NewB) -> Reconnect<M, Target, P, NewB>
+    where
+        NewB: MakeBackoff,
+    {
+        let State { service, .. } = self.state.into_inner();
+        Reconnect {
+            make: self.make,
+            target: self.target,
+            policy: self.policy,
+            make_backoff,
+            state: Mutex::new(State {
+                service,
+                backoff: None,
+            }),
+        }
+    }
+
+    /// Get a reference to the [`MakeService`] of this [`Reconnect`].
+    ///
+    /// [`MakeService`]: crate::MakeService
+    pub fn get_ref(&self) -> &M {
+        &self.make
+    }
+
+    /// Consume `self`, returning the [`MakeService`] of this [`Reconnect`].
+    ///
+    /// [`MakeService`]: crate::MakeService
+    pub fn into_inner(self) -> M {
+        self.make
+    }
+
+    async fn connect(&self) -> Result<Arc<M::Response>, BoxError>
+    where
+        M::Error: Into<BoxError>,
+        Target: Clone,
+    {
+        let mut state = self.state.lock().await;
+        if let Some(service) = &state.service {
+            return Ok(service.clone());
+        }
+
+        if let Some(backoff) = &state.backoff {
+            backoff.next_backoff().await;
+        }
+
+        match self.make.call(self.target.clone()).await {
+            Ok(service) => {
+                let service = Arc::new(service);
+                state.service = Some(service.clone());
+                state.backoff = None;
+                Ok(service)
+            }
+            Err(err) => {
+                if state.backoff.is_none() {
+                    state.backoff = Some(self.make_backoff.make_backoff());
+                }
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl<M, Target, P, B, S, Request> Service<Request> for Reconnect<M, Target, P, B>
+where
+    M: Service<Target, Response = S>,
+    M::Error: Into<BoxError>,
+    S: Service<Request>,
+    S::Error: Into<BoxError>,
+    Target: Clone,
+    P: Policy<S::Error>,
+    B: MakeBackoff,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let service = self.connect().await?;
+
+        match service.call(request).await {
+            Ok(response) => Ok(response),
+            Err(err) => {
+                if self.policy.should_reconnect(&err) {
+                    let mut state = self.state.lock().await;
+                    // another request may have replaced the service already
+                    if matches!(&state.service, Some(current) if Arc::ptr_eq(current, &service)) {
+                        state.service = None;
+                    }
+                }
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl<M, Target, P, B> fmt::Debug for Reconnect<M, Target, P, B>
+where
+    M: Service<Target> + fmt::Debug,
+    Target: fmt::Debug,
+    P: fmt::Debug,
+    B: MakeBackoff + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reconnect")
+            .field("make", &self.make)
+            .field("target", &self.target)
+            .field("policy", &self.policy)
+            .field("make_backoff", &self.make_backoff)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::service_fn;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default, Clone)]
+    struct CountingBackoff(Arc<AtomicUsize>);
+
+    impl MakeBackoff for CountingBackoff {
+        type Backoff = CountingBackoff;
+
+        fn make_backoff(&self) -> Self::Backoff {
+            self.clone()
+        }
+    }
+
+    impl Backoff for CountingBackoff {
+        async fn next_backoff(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_connection_errors() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let make = {
+            let connects = connects.clone();
+            service_fn(move |_: ()| {
+                let connection = connects.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    Ok::<_, BoxError>(service_fn(move |request: &'static str| async move {
+                        match request {
+                            "disconnect" | "invalid" => Err(request),
+                            _ => Ok(connection),
+                        }
+                    }))
+                }
+            })
+        };
+
+        let svc = Reconnect::new(make, ()).policy(|err: &&str| *err == "disconnect");
+
+        assert_eq!(svc.call("hello").await.unwrap(), 0);
+        assert_eq!(
+            svc.call("invalid").await.unwrap_err().to_string(),
+            "invalid"
+        );
+        assert_eq!(svc.call("hello").await.unwrap(), 0);
+        assert_eq!(
+            svc.call("disconnect").await.unwrap_err().to_string(),
+            "disconnect"
+        );
+        assert_eq!(svc.call("hello").await.unwrap(), 1);
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+    }
+
+    fn connection(
+        name: &'static str,
+    ) -> impl Service<&'static str, Response = &'static str, Error = &'static str> {
+        service_fn(move |request: &'static str| async move {
+            match request {
+                "disconnect" => Err(request),
+                _ => Ok(name),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn starts_with_given_service() {
+        let make = service_fn(|_: ()| async { Ok::<_, BoxError>(connection("reconnected")) });
+        let svc = Reconnect::with_service(connection("initial"), make, ());
+
+        assert_eq!(svc.call("hello").await.unwrap(), "initial");
+        assert!(svc.call("disconnect").await.is_err());
+        assert_eq!(svc.call("hello").await.unwrap(), "reconnected");
+    }
+
+    #[tokio::test]
+    async fn keeps_given_service_when_setting_backoff() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let make = {
+            let connects = connects.clone();
+            service_fn(move |_: ()| {
+                connects.fetch_add(1, Ordering::SeqCst);
+                async { Ok::<_, BoxError>(connection("reconnected")) }
+            })
+        };
+        let svc = Reconnect::with_service(connection("initial"), make, ())
+            .policy(|err: &&str| *err == "disconnect")
+            .backoff(CountingBackoff::default());
+
+        assert_eq!(svc.call("hello").await.unwrap(), "initial");
+        assert_eq!(connects.load(Ordering::SeqCst), 0);
+        assert!(svc.call("disconnect").await.is_err());
+        assert_eq!(svc.call("hello").await.unwrap(), "reconnected");
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn backs_off_after_failed_connects() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let make = {
+            let attempts = attempts.clone();
+            service_fn(move |_: ()| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt < 2 {
+                        return Err("connection refused");
+                    }
+                    Ok(service_fn(|request: &'static str| async move {
+                        Ok::<_, BoxError>(request)
+                    }))
+                }
+            })
+        };
+
+        let backoffs = CountingBackoff::default();
+        let svc = Reconnect::new(make, ()).backoff(backoffs.clone());
+
+        assert!(svc.call("hello").await.is_err());
+        assert_eq!(backoffs.0.load(Ordering::SeqCst), 0);
+        assert!(svc.call("hello").await.is_err());
+        assert_eq!(backoffs.0.load(Ordering::SeqCst), 1);
+        assert_eq!(svc.call("hello").await.unwrap(), "hello");
+        assert_eq!(backoffs.0.load(Ordering::SeqCst), 2);
+
+        // the backoff is reset once connected
+        assert_eq!(svc.call("hello").await.unwrap(), "hello");
+        assert_eq!(backoffs.0.load(Ordering::SeqCst), 2);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,43 @@
+/// Determines whether an error returned by the connected service means the
+/// connection is broken, such that [`Reconnect`] establishes a new one.
+///
+/// This trait is implemented for closures of the form `Fn(&E) -> bool`.
+///
+/// [`Reconnect`]: super::Reconnect
+pub trait Policy<E> {
+    /// Returns `true` if the service that returned the `error` should be dropped,
+    /// and a new one made for the next request.
+    fn should_reconnect(&self, error: &E) -> bool;
+}
+
+impl<E, F> Policy<E> for F
+where
+    F: Fn(&E) -> bool,
+{
+    fn should_reconnect(&self, error: &E) -> bool {
+        self(error)
+    }
+}
+
+/// [`Policy`] that reconnects on every error returned by the connected service.
+///
+/// This is the default policy of [`Reconnect`].
+///
+/// [`Reconnect`]: super::Reconnect
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReconnectOnError {
+    _priv: (),
+}
+
+impl ReconnectOnError {
+    /// Create a new [`ReconnectOnError`] policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<E> Policy<E> for ReconnectOnError {
+    fn should_reconnect(&self, _: &E) -> bool {
+        true
+    }
+}
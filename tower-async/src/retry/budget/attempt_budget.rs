@@ -0,0 +1,303 @@
+//! Attempt budget, shared by the retry and hedge middleware
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use super::Budget;
+
+tokio::task_local! {
+    static ATTEMPTS: Attempts;
+}
+
+/// The attempts made for the request handled by the current task.
+#[derive(Clone)]
+struct Attempts {
+    budget: u64,
+    count: Arc<AtomicUsize>,
+}
+
+/// Reports the attempts made for a request once it completes, or is cancelled.
+struct Report<'a> {
+    budget: &'a AttemptBudget,
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for Report<'_> {
+    fn drop(&mut self) {
+        if let Some(on_attempts) = &self.budget.on_attempts {
+            on_attempts(self.count.load(Ordering::Acquire));
+        }
+    }
+}
+
+/// Caps the total number of attempts made for a single request,
+/// across all the middleware sharing the budget.
+///
+/// Stacking a [`Retry`] on top of a [`Hedge`] multiplies the attempts made for a single
+/// request: every retry may be hedged, and every hedge may fail and be retried. Sharing a
+/// single [`AttemptBudget`] between them caps the attempts made for the request as a whole,
+/// the original attempt included, no matter which middleware asks for them.
+///
+/// The outermost middleware using the budget starts tracking the attempts of the request,
+/// for the duration of its `call`. Additional attempts can optionally be withdrawn from a
+/// [`Budget`] shared by all requests, such as a [`TpsBudget`], in which case every request
+/// deposits into it once.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicUsize, Ordering},
+///     Arc,
+/// };
+/// use tower_async::{
+///     retry::{budget::AttemptBudget, Policy, Retry},
+///     service_fn, Service,
+/// };
+///
+/// #[derive(Clone)]
+/// struct RetryErrors;
+///
+/// impl<E> Policy<(), (), E> for RetryErrors {
+///     async fn retry(&self, _: &mut (), result: &mut Result<(), E>) -> bool {
+///         result.is_err()
+///     }
+///
+///     fn clone_request(&self, _: &()) -> Option<()> {
+///         Some(())
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let attempts = Arc::new(AtomicUsize::new(0));
+/// let budget = AttemptBudget::new(3).on_attempts({
+///     let attempts = attempts.clone();
+///     move |n| attempts.store(n, Ordering::SeqCst)
+/// });
+///
+/// let svc = Retry::new(
+///     RetryErrors,
+///     service_fn(|_: ()| async { Err::<(), _>("unavailable") }),
+/// )
+/// .attempt_budget(budget);
+///
+/// assert!(svc.call(()).await.is_err());
+/// assert_eq!(attempts.load(Ordering::SeqCst), 3);
+/// # }
+/// ```
+///
+/// [`Retry`]: crate::retry::Retry
+/// [`Hedge`]: crate::hedge::Hedge
+/// [`TpsBudget`]: super::TpsBudget
+#[derive(Clone)]
+pub struct AttemptBudget {
+    id: u64,
+    max_attempts: usize,
+    tokens: Option<Arc<dyn Budget + Send + Sync>>,
+    on_attempts: Option<Arc<dyn Fn(usize) + Send + Sync>>,
+}
+
+impl AttemptBudget {
+    /// Create an [`AttemptBudget`] allowing up to `max_attempts` attempts for a single
+    /// request, the original attempt included.
+    ///
+    /// All clones of the [`AttemptBudget`] share the attempts of a request.
+    pub fn new(max_attempts: usize) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        AttemptBudget {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            max_attempts,
+            tokens: None,
+            on_attempts: None,
+        }
+    }
+
+    /// Withdraw every additional attempt from a [`Budget`] shared by all requests,
+    /// into which every request deposits once.
+    pub fn with_budget<B>(mut self, budget: B) -> Self
+    where
+        B: Budget + Send + Sync + 'static,
+    {
+        self.tokens = Some(Arc::new(budget));
+        self
+    }
+
+    /// Call `f` with the number of attempts made for every request,
+    /// once the request completes or is cancelled.
+    ///
+    /// This can be used to record the attempts per request as a metric.
+    pub fn on_attempts<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_attempts = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns the maximum number of attempts for a single request.
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Returns the number of attempts made so far for the request handled by the
+    /// current task, or `None` if the current task isn't tracked by this budget.
+    pub fn attempts(&self) -> Option<usize> {
+        self.current().map(|count| count.load(Ordering::Acquire))
+    }
+
+    /// Run `future`, the handling of a single request, tracking its attempts.
+    ///
+    /// The request is counted as one attempt. If the current task is already tracked by
+    /// this budget, such as by an outer middleware sharing it, the attempts of that
+    /// request are used instead.
+    pub async fn scope<F>(&self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        if self.current().is_some() {
+            return future.await;
+        }
+
+        if let Some(tokens) = &self.tokens {
+            tokens.deposit();
+        }
+        let count = Arc::new(AtomicUsize::new(1));
+        let _report = Report {
+            budget: self,
+            count: count.clone(),
+        };
+        let attempts = Attempts {
+            budget: self.id,
+            count,
+        };
+        ATTEMPTS.scope(attempts, future).await
+    }
+
+    /// Try to make an additional attempt for the request handled by the current task.
+    ///
+    /// Returns `false` if the request used up its attempts, if the shared [`Budget`] is
+    /// overdrawn, or if the current task isn't tracked by this budget.
+    pub fn try_attempt(&self) -> bool {
+        let Some(count) = self.current() else {
+            return false;
+        };
+        let reserved = count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < self.max_attempts).then_some(count + 1)
+            })
+            .is_ok();
+        if !reserved {
+            return false;
+        }
+        if let Some(tokens) = &self.tokens {
+            if !tokens.withdraw() {
+                count.fetch_sub(1, Ordering::AcqRel);
+                return false;
+            }
+        }
+        true
+    }
+
+    fn current(&self) -> Option<Arc<AtomicUsize>> {
+        ATTEMPTS
+            .try_with(|attempts| (attempts.budget == self.id).then(|| attempts.count.clone()))
+            .ok()
+            .flatten()
+    }
+}
+
+impl fmt::Debug for AttemptBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttemptBudget")
+            .field("max_attempts", &self.max_attempts)
+            .field("with_budget", &self.tokens.is_some())
+            .field("on_attempts", &self.on_attempts.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use crate::{
+        retry::{Policy, RetryLayer},
+        service_fn, Layer, Service,
+    };
+
+    #[derive(Clone)]
+    struct RetryErrors;
+
+    impl<E> Policy<(), (), E> for RetryErrors {
+        async fn retry(&self, _: &mut (), result: &mut Result<(), E>) -> bool {
+            result.is_err()
+        }
+
+        fn clone_request(&self, _: &()) -> Option<()> {
+            Some(())
+        }
+    }
+
+    #[tokio::test]
+    async fn nested_retries_share_attempts() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let budget = AttemptBudget::new(4).on_attempts({
+            let reported = reported.clone();
+            move |n| reported.lock().unwrap().push(n)
+        });
+
+        let svc = service_fn({
+            let calls = calls.clone();
+            move |_: ()| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Err::<(), _>("unavailable") }
+            }
+        });
+        let retry = RetryLayer::new(RetryErrors).attempt_budget(budget.clone());
+        let svc = retry.layer(retry.layer(svc));
+
+        assert!(svc.call(()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert!(svc.call(()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+        assert_eq!(*reported.lock().unwrap(), [4, 4]);
+
+        // not tracked outside of a request
+        assert_eq!(budget.attempts(), None);
+        assert!(!budget.try_attempt());
+    }
+
+    #[tokio::test]
+    async fn withdraws_from_shared_budget() {
+        struct Tokens(AtomicUsize);
+
+        impl Budget for Tokens {
+            fn deposit(&self) {}
+
+            fn withdraw(&self) -> bool {
+                self.0
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok()
+            }
+        }
+
+        let budget = AttemptBudget::new(10).with_budget(Tokens(AtomicUsize::new(2)));
+        let attempts = budget
+            .scope(async {
+                while budget.try_attempt() {}
+                budget.attempts()
+            })
+            .await;
+        assert_eq!(attempts, Some(3));
+    }
+}
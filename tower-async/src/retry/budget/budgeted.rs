@@ -0,0 +1,160 @@
+//! A retry policy wrapper limiting retries to a budget
+
+use std::future::Future;
+
+use super::Budget;
+use crate::retry::Policy;
+
+/// Limits the retries of a [`Policy`] to a [`Budget`].
+///
+/// Every retry the wrapped policy asks for is withdrawn from the budget, and is only made
+/// if the budget isn't overdrawn. Every successful result, which the wrapped policy doesn't
+/// retry, deposits into the budget. Sharing a single budget, such as an [`Arc<TpsBudget>`],
+/// between all clients of a service prevents retry storms, as retries can only make up a
+/// fraction of the requests sent.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use tower_async::retry::{
+///     budget::{Budgeted, TpsBudget},
+///     Policy, RetryLayer,
+/// };
+///
+/// #[derive(Clone)]
+/// struct RetryErrors;
+///
+/// impl<E> Policy<String, String, E> for RetryErrors {
+///     async fn retry(&self, _: &mut String, result: &mut Result<String, E>) -> bool {
+///         result.is_err()
+///     }
+///
+///     fn clone_request(&self, req: &String) -> Option<String> {
+///         Some(req.clone())
+///     }
+/// }
+///
+/// // allow up to 10 retries per second, and 20% of the requests sent on top
+/// let budget = Arc::new(TpsBudget::new(Duration::from_secs(10), 10, 0.2));
+/// let layer = RetryLayer::new(Budgeted::new(RetryErrors, budget));
+/// ```
+///
+/// [`Arc<TpsBudget>`]: super::TpsBudget
+#[derive(Debug, Clone)]
+pub struct Budgeted<P, B> {
+    policy: P,
+    budget: B,
+}
+
+impl<P, B> Budgeted<P, B> {
+    /// Limits the retries of `policy` to `budget`.
+    pub fn new(policy: P, budget: B) -> Self {
+        Budgeted { policy, budget }
+    }
+
+    /// Get a reference to the wrapped policy
+    pub fn get_ref(&self) -> &P {
+        &self.policy
+    }
+
+    /// Get a reference to the budget
+    pub fn budget(&self) -> &B {
+        &self.budget
+    }
+}
+
+impl<P, B, Req, Res, E> Policy<Req, Res, E> for Budgeted<P, B>
+where
+    P: Policy<Req, Res, E>,
+    B: Budget,
+{
+    async fn retry(&self, req: &mut Req, result: &mut Result<Res, E>) -> bool {
+        if !self.policy.retry(req, result).await {
+            if result.is_ok() {
+                self.budget.deposit();
+            }
+            return false;
+        }
+        self.budget.withdraw()
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        self.policy.clone_request(req)
+    }
+
+    fn retries_exceeded(&self, req: &mut Req, result: &mut Result<Res, E>) {
+        self.policy.retries_exceeded(req, result)
+    }
+
+    fn scope<F>(&self, future: F) -> impl Future<Output = F::Output>
+    where
+        F: Future,
+    {
+        self.policy.scope(future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{
+        retry::{budget::TpsBudget, RetryLayer},
+        service_fn, Layer, Service,
+    };
+
+    #[derive(Clone)]
+    struct RetryErrors;
+
+    impl<E> Policy<usize, usize, E> for RetryErrors {
+        async fn retry(&self, _: &mut usize, result: &mut Result<usize, E>) -> bool {
+            result.is_err()
+        }
+
+        fn clone_request(&self, req: &usize) -> Option<usize> {
+            Some(*req)
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_within_budget() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let budget = Arc::new(TpsBudget::new(Duration::from_secs(1), 0, 1.0));
+        let layer = RetryLayer::new(Budgeted::new(RetryErrors, budget.clone()));
+        let svc = layer.layer(service_fn({
+            let calls = calls.clone();
+            move |fails: usize| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if call < fails {
+                        Err("unavailable")
+                    } else {
+                        Ok(call)
+                    }
+                }
+            }
+        }));
+
+        // nothing deposited yet
+        assert_eq!(svc.call(1).await, Err("unavailable"));
+
+        // two successful requests, allowing two retries
+        calls.store(0, Ordering::SeqCst);
+        assert_eq!(svc.call(0).await, Ok(0));
+        assert_eq!(svc.call(0).await, Ok(1));
+
+        calls.store(0, Ordering::SeqCst);
+        assert_eq!(svc.call(10).await, Err("unavailable"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(!budget.withdraw());
+    }
+}
@@ -24,6 +24,11 @@
 //!
 //! It's generally dangerous to implement retries without some limiting factor. [`Budget`]s are that limit.
 //!
+//! [`Budgeted`] limits the retries of any [`Policy`] to a [`Budget`]. Budgets can also be
+//! consulted by hand, from within a policy, as in the example below.
+//!
+//! [`Policy`]: super::Policy
+//!
 //! # Examples
 //!
 //! ```rust
@@ -69,8 +74,12 @@
 //! }
 //! ```
 
+pub mod attempt_budget;
+pub mod budgeted;
 pub mod tps_budget;
 
+pub use attempt_budget::AttemptBudget;
+pub use budgeted::Budgeted;
 pub use tps_budget::TpsBudget;
 
 /// For more info about [`Budget`], please see the [module-level documentation].
@@ -87,3 +96,16 @@ pub trait Budget {
     /// If there is not enough, false is returned.
     fn withdraw(&self) -> bool;
 }
+
+impl<B> Budget for std::sync::Arc<B>
+where
+    B: Budget + ?Sized,
+{
+    fn deposit(&self) {
+        (**self).deposit()
+    }
+
+    fn withdraw(&self) -> bool {
+        (**self).withdraw()
+    }
+}
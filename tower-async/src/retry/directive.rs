@@ -0,0 +1,108 @@
+/// Overrides how a [`Retry`] retries an individual request.
+///
+/// Returned by a [`RetryOverride`], which is typically decided upstream of the [`Retry`],
+/// for example by a router knowing which routes are idempotent, or by a client
+/// marking the requests it sends.
+///
+/// [`Retry`]: super::Retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDirective {
+    /// Never retry the request, such as a request which is not idempotent.
+    ///
+    /// The request is not cloned and the [`Policy`] is not consulted,
+    /// the first result is returned as is.
+    ///
+    /// [`Policy`]: super::Policy
+    Never,
+    /// Retry the request at most the given number of times, replacing the
+    /// [maximum number of retries](super::Retry::max_retries) of the [`Retry`].
+    ///
+    /// The [`Policy`] still decides whether the request is retried,
+    /// and a shared [attempt budget](super::Retry::attempt_budget) still applies.
+    ///
+    /// [`Retry`]: super::Retry
+    /// [`Policy`]: super::Policy
+    MaxRetries(usize),
+}
+
+/// Decides the [`RetryDirective`] of individual requests handled by a [`Retry`].
+///
+/// Implemented for `()`, which never overrides the retries, and for closures of the form
+/// `Fn(&Request) -> Option<RetryDirective>`, such as a closure reading the directive
+/// from the extensions of an HTTP request.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicUsize, Ordering},
+///     Arc,
+/// };
+/// use tower_async::{
+///     retry::{Policy, Retry, RetryDirective},
+///     service_fn, Service,
+/// };
+///
+/// #[derive(Clone)]
+/// struct Request {
+///     method: &'static str,
+/// }
+///
+/// #[derive(Clone)]
+/// struct RetryErrors;
+///
+/// impl<Res, E> Policy<Request, Res, E> for RetryErrors {
+///     async fn retry(&self, _: &mut Request, result: &mut Result<Res, E>) -> bool {
+///         result.is_err()
+///     }
+///
+///     fn clone_request(&self, req: &Request) -> Option<Request> {
+///         Some(req.clone())
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let calls = Arc::new(AtomicUsize::new(0));
+/// let svc = Retry::new(
+///     RetryErrors,
+///     service_fn(|_: Request| {
+///         calls.fetch_add(1, Ordering::SeqCst);
+///         async { Err::<(), _>("unavailable") }
+///     }),
+/// )
+/// .max_retries(2)
+/// // creating an order twice is worse than failing to create it
+/// .with_override(|req: &Request| (req.method == "POST").then_some(RetryDirective::Never));
+///
+/// svc.call(Request { method: "POST" }).await.unwrap_err();
+/// assert_eq!(calls.load(Ordering::SeqCst), 1);
+///
+/// svc.call(Request { method: "GET" }).await.unwrap_err();
+/// assert_eq!(calls.load(Ordering::SeqCst), 4);
+/// # }
+/// ```
+///
+/// [`Retry`]: super::Retry
+pub trait RetryOverride<Request> {
+    /// Returns the directive for the request,
+    /// or `None` to retry it as configured on the [`Retry`].
+    ///
+    /// [`Retry`]: super::Retry
+    fn directive(&self, request: &Request) -> Option<RetryDirective>;
+}
+
+impl<Request> RetryOverride<Request> for () {
+    fn directive(&self, _: &Request) -> Option<RetryDirective> {
+        None
+    }
+}
+
+impl<F, Request> RetryOverride<Request> for F
+where
+    F: Fn(&Request) -> Option<RetryDirective>,
+{
+    fn directive(&self, request: &Request) -> Option<RetryDirective> {
+        self(request)
+    }
+}
@@ -1,27 +1,94 @@
-use super::Retry;
+use super::{budget::AttemptBudget, Retry};
+use std::fmt;
 use tower_async_layer::Layer;
 
 /// Retry requests based on a policy
-#[derive(Debug, Clone)]
-pub struct RetryLayer<P> {
+#[derive(Clone)]
+pub struct RetryLayer<P, O = ()> {
     policy: P,
+    max_retries: Option<usize>,
+    attempt_budget: Option<AttemptBudget>,
+    directives: O,
 }
 
 impl<P> RetryLayer<P> {
     /// Creates a new [`RetryLayer`] from a retry policy.
     pub fn new(policy: P) -> Self {
-        RetryLayer { policy }
+        RetryLayer {
+            policy,
+            max_retries: None,
+            attempt_budget: None,
+            directives: (),
+        }
     }
 }
 
-impl<P, S> Layer<S> for RetryLayer<P>
+impl<P, O> RetryLayer<P, O> {
+    /// Set the maximum number of times a single request is retried,
+    /// regardless of the retry policy.
+    ///
+    /// See [`Retry::max_retries`] for more details.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Share an [`AttemptBudget`] with other middleware,
+    /// capping the attempts made for a single request across all of them.
+    ///
+    /// See [`Retry::attempt_budget`] for more details.
+    pub fn attempt_budget(mut self, budget: AttemptBudget) -> Self {
+        self.attempt_budget = Some(budget);
+        self
+    }
+
+    /// Override the retries of individual requests using the given [`RetryOverride`].
+    ///
+    /// See [`Retry::with_override`] for more details.
+    ///
+    /// [`RetryOverride`]: super::RetryOverride
+    pub fn with_override<O2>(self, directives: O2) -> RetryLayer<P, O2> {
+        RetryLayer {
+            policy: self.policy,
+            max_retries: self.max_retries,
+            attempt_budget: self.attempt_budget,
+            directives,
+        }
+    }
+}
+
+impl<P, O, S> Layer<S> for RetryLayer<P, O>
 where
     P: Clone,
+    O: Clone,
 {
-    type Service = Retry<P, S>;
+    type Service = Retry<P, S, O>;
 
     fn layer(&self, service: S) -> Self::Service {
         let policy = self.policy.clone();
-        Retry::new(policy, service)
+        Retry {
+            policy,
+            service,
+            max_retries: self.max_retries,
+            attempt_budget: self.attempt_budget.clone(),
+            directives: self.directives.clone(),
+        }
+    }
+}
+
+impl<P, O> fmt::Debug for RetryLayer<P, O>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryLayer")
+            .field("policy", &self.policy)
+            .field("max_retries", &self.max_retries)
+            .field("attempt_budget", &self.attempt_budget)
+            .field(
+                "directives",
+                &format_args!("{}", std::any::type_name::<O>()),
+            )
+            .finish()
     }
 }
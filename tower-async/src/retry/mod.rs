@@ -1,21 +1,33 @@
 //! Middleware for retrying "failed" requests.
+//!
+//! Individual requests can opt out of retries, or be given a different maximum number of
+//! retries, using a [`RetryOverride`].
 
 pub mod budget;
+mod directive;
 mod layer;
+pub mod policies;
 mod policy;
 
+pub use self::directive::{RetryDirective, RetryOverride};
 pub use self::layer::RetryLayer;
 pub use self::policy::Policy;
 
+use self::budget::AttemptBudget;
+use std::fmt;
 use tower_async_service::Service;
 
 /// Configure retrying requests of "failed" responses.
 ///
-/// A [`Policy`] classifies what is a "failed" response.
-#[derive(Clone, Debug)]
-pub struct Retry<P, S> {
+/// A [`Policy`] classifies what is a "failed" response,
+/// and a [`RetryOverride`] can override the retries of individual requests.
+#[derive(Clone)]
+pub struct Retry<P, S, O = ()> {
     policy: P,
     service: S,
+    max_retries: Option<usize>,
+    attempt_budget: Option<AttemptBudget>,
+    directives: O,
 }
 
 // ===== impl Retry =====
@@ -23,7 +35,55 @@ pub struct Retry<P, S> {
 impl<P, S> Retry<P, S> {
     /// Retry the inner service depending on this [`Policy`].
     pub fn new(policy: P, service: S) -> Self {
-        Retry { policy, service }
+        Retry {
+            policy,
+            service,
+            max_retries: None,
+            attempt_budget: None,
+            directives: (),
+        }
+    }
+}
+
+impl<P, S, O> Retry<P, S, O> {
+    /// Set the maximum number of times a single request is retried,
+    /// regardless of the [`Policy`].
+    ///
+    /// Once the policy asks to retry a request more often, [`Policy::retries_exceeded`]
+    /// is called, which can turn the last result into an error, and that result is returned.
+    /// This protects against policies that never stop retrying a request.
+    ///
+    /// By default requests are retried for as long as the policy asks for it.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Share an [`AttemptBudget`] with other middleware, such as a [`Hedge`],
+    /// capping the attempts made for a single request across all of them.
+    ///
+    /// Once the budget is used up, [`Policy::retries_exceeded`] is called,
+    /// as if the maximum number of retries was exceeded.
+    ///
+    /// [`Hedge`]: crate::hedge::Hedge
+    pub fn attempt_budget(mut self, budget: AttemptBudget) -> Self {
+        self.attempt_budget = Some(budget);
+        self
+    }
+
+    /// Override the retries of individual requests using the given [`RetryOverride`],
+    /// such as a closure returning the [`RetryDirective`] of a request, if any.
+    ///
+    /// This allows requests which are not idempotent to opt out of retries,
+    /// or important requests to be retried more often, as decided upstream.
+    pub fn with_override<O2>(self, directives: O2) -> Retry<P, S, O2> {
+        Retry {
+            policy: self.policy,
+            service: self.service,
+            max_retries: self.max_retries,
+            attempt_budget: self.attempt_budget,
+            directives,
+        }
     }
 
     /// Get a reference to the inner service
@@ -37,15 +97,40 @@ impl<P, S> Retry<P, S> {
     }
 }
 
-impl<P, S, Request> Service<Request> for Retry<P, S>
+impl<P, S, O, Request> Service<Request> for Retry<P, S, O>
 where
     P: Policy<Request, S::Response, S::Error>,
     S: Service<Request>,
+    O: RetryOverride<Request>,
 {
     type Response = S::Response;
     type Error = S::Error;
 
-    async fn call(&self, mut request: Request) -> Result<Self::Response, Self::Error> {
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let future = self.policy.scope(self.call_with_retries(request));
+        match &self.attempt_budget {
+            Some(budget) => budget.scope(future).await,
+            None => future.await,
+        }
+    }
+}
+
+impl<P, S, O> Retry<P, S, O> {
+    async fn call_with_retries<Request>(
+        &self,
+        mut request: Request,
+    ) -> Result<S::Response, S::Error>
+    where
+        P: Policy<Request, S::Response, S::Error>,
+        S: Service<Request>,
+        O: RetryOverride<Request>,
+    {
+        let max_retries = match self.directives.directive(&request) {
+            Some(RetryDirective::Never) => return self.service.call(request).await,
+            Some(RetryDirective::MaxRetries(max_retries)) => Some(max_retries),
+            None => self.max_retries,
+        };
+        let mut retries = 0;
         loop {
             let cloned_request = self.policy.clone_request(&request);
             let mut result = self.service.call(request).await;
@@ -53,6 +138,13 @@ where
                 if !self.policy.retry(&mut req, &mut result).await {
                     return result;
                 }
+                let exceeded = matches!(max_retries, Some(max_retries) if retries >= max_retries)
+                    || matches!(&self.attempt_budget, Some(budget) if !budget.try_attempt());
+                if exceeded {
+                    self.policy.retries_exceeded(&mut req, &mut result);
+                    return result;
+                }
+                retries += 1;
                 request = req;
             } else {
                 return result;
@@ -60,3 +152,22 @@ where
         }
     }
 }
+
+impl<P, S, O> fmt::Debug for Retry<P, S, O>
+where
+    P: fmt::Debug,
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("policy", &self.policy)
+            .field("service", &self.service)
+            .field("max_retries", &self.max_retries)
+            .field("attempt_budget", &self.attempt_budget)
+            .field(
+                "directives",
+                &format_args!("{}", std::any::type_name::<O>()),
+            )
+            .finish()
+    }
+}
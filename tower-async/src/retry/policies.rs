@@ -0,0 +1,462 @@
+//! Ready-made retry policies.
+//!
+//! [`RetryPolicyBuilder`] builds a [`RetryPolicy`] for the common case of retrying a request
+//! a limited number of times, for the results matching a predicate, waiting for a
+//! [`Backoff`] in between attempts, and optionally within a retry [`Budget`].
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{
+//!     retry::policies::RetryPolicyBuilder,
+//!     util::{backoff::ExponentialBackoffMaker, rng::HasherRng},
+//!     Service, ServiceBuilder,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let backoff = ExponentialBackoffMaker::new(
+//!     Duration::from_millis(10),
+//!     Duration::from_secs(1),
+//!     0.5,
+//!     HasherRng::default(),
+//! )
+//! .unwrap();
+//!
+//! let svc = ServiceBuilder::new()
+//!     .retry(
+//!         RetryPolicyBuilder::new()
+//!             .max_attempts(3)
+//!             .backoff(backoff)
+//!             // only retry idempotent requests
+//!             .clone_request_with(|req: &String| req.starts_with("GET ").then(|| req.clone()))
+//!             .build(),
+//!     )
+//!     .service_fn(|req: String| async move { Ok::<_, Infallible>(req.len()) });
+//!
+//! let response = svc.call("GET /".to_owned()).await.unwrap();
+//! assert_eq!(response, 5);
+//! # }
+//! ```
+//!
+//! [`Backoff`]: crate::util::backoff::Backoff
+
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use super::{
+    budget::{AttemptBudget, Budget},
+    Policy,
+};
+use crate::util::backoff::{Backoff, MakeBackoff, NoBackoff};
+
+tokio::task_local! {
+    static SESSION: Session;
+}
+
+/// The backoff session of the request handled by the current task.
+struct Session {
+    policy: u64,
+    backoff: Arc<dyn Any + Send + Sync>,
+}
+
+/// Decides whether the result of an attempt is retried by a [`RetryPolicy`].
+///
+/// Implemented for `()`, which retries all errors, and for closures of the form
+/// `Fn(&Result<Res, E>) -> bool`.
+pub trait RetryPredicate<Res, E> {
+    /// Returns `true` if the request should be retried for this result.
+    fn should_retry(&self, result: &Result<Res, E>) -> bool;
+}
+
+impl<Res, E> RetryPredicate<Res, E> for () {
+    fn should_retry(&self, result: &Result<Res, E>) -> bool {
+        result.is_err()
+    }
+}
+
+impl<F, Res, E> RetryPredicate<Res, E> for F
+where
+    F: Fn(&Result<Res, E>) -> bool,
+{
+    fn should_retry(&self, result: &Result<Res, E>) -> bool {
+        self(result)
+    }
+}
+
+/// Clones the requests retried by a [`RetryPolicy`].
+///
+/// Implemented for `()`, which clones all requests implementing [`Clone`], and for closures
+/// of the form `Fn(&Request) -> Option<Request>`. Requests which aren't cloned aren't retried.
+pub trait RequestCloner<Request> {
+    /// Clones the request, or returns `None` if it can't or shouldn't be retried.
+    fn clone_request(&self, request: &Request) -> Option<Request>;
+}
+
+impl<Request> RequestCloner<Request> for ()
+where
+    Request: Clone,
+{
+    fn clone_request(&self, request: &Request) -> Option<Request> {
+        Some(request.clone())
+    }
+}
+
+impl<F, Request> RequestCloner<Request> for F
+where
+    F: Fn(&Request) -> Option<Request>,
+{
+    fn clone_request(&self, request: &Request) -> Option<Request> {
+        self(request)
+    }
+}
+
+/// Builds a [`RetryPolicy`].
+///
+/// By default, errors are retried right away, for up to 3 attempts in total,
+/// and requests are cloned using [`Clone`].
+///
+/// See the [module docs](self) for an example.
+#[derive(Clone)]
+pub struct RetryPolicyBuilder<M = NoBackoff, P = (), C = ()> {
+    max_attempts: usize,
+    backoff: M,
+    predicate: P,
+    cloner: C,
+    budget: Option<Arc<dyn Budget + Send + Sync>>,
+    attempt_budget: Option<AttemptBudget>,
+}
+
+impl RetryPolicyBuilder {
+    /// Creates a new [`RetryPolicyBuilder`] with the default configuration.
+    pub fn new() -> Self {
+        RetryPolicyBuilder {
+            max_attempts: 3,
+            backoff: NoBackoff::new(),
+            predicate: (),
+            cloner: (),
+            budget: None,
+            attempt_budget: None,
+        }
+    }
+}
+
+impl Default for RetryPolicyBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, P, C> RetryPolicyBuilder<M, P, C> {
+    /// Set the maximum number of attempts for a single request, the original attempt included.
+    ///
+    /// Defaults to 3.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Wait for a backoff, made for every request, before every retry.
+    ///
+    /// Retries are made right away by default.
+    pub fn backoff<M2>(self, backoff: M2) -> RetryPolicyBuilder<M2, P, C>
+    where
+        M2: MakeBackoff,
+    {
+        RetryPolicyBuilder {
+            max_attempts: self.max_attempts,
+            backoff,
+            predicate: self.predicate,
+            cloner: self.cloner,
+            budget: self.budget,
+            attempt_budget: self.attempt_budget,
+        }
+    }
+
+    /// Only retry the results for which the [`RetryPredicate`] returns `true`.
+    ///
+    /// All errors, and only errors, are retried by default.
+    pub fn retry_if<P2>(self, predicate: P2) -> RetryPolicyBuilder<M, P2, C> {
+        RetryPolicyBuilder {
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            predicate,
+            cloner: self.cloner,
+            budget: self.budget,
+            attempt_budget: self.attempt_budget,
+        }
+    }
+
+    /// Clone requests using the given [`RequestCloner`], such as to only retry
+    /// idempotent requests, or requests which don't implement [`Clone`].
+    pub fn clone_request_with<C2>(self, cloner: C2) -> RetryPolicyBuilder<M, P, C2> {
+        RetryPolicyBuilder {
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            predicate: self.predicate,
+            cloner,
+            budget: self.budget,
+            attempt_budget: self.attempt_budget,
+        }
+    }
+
+    /// Withdraw every retry from a [`Budget`] shared by all requests,
+    /// such as a [`TpsBudget`], into which every request deposits once.
+    ///
+    /// [`TpsBudget`]: super::budget::TpsBudget
+    pub fn budget<B>(mut self, budget: B) -> Self
+    where
+        B: Budget + Send + Sync + 'static,
+    {
+        self.budget = Some(Arc::new(budget));
+        self
+    }
+
+    /// Track the attempts of a request in an [`AttemptBudget`] shared with other
+    /// middleware, such as a [`Hedge`], instead of the maximum number of attempts
+    /// and the [`Budget`] configured on this builder.
+    ///
+    /// [`Hedge`]: crate::hedge::Hedge
+    pub fn attempt_budget(mut self, budget: AttemptBudget) -> Self {
+        self.attempt_budget = Some(budget);
+        self
+    }
+
+    /// Builds the [`RetryPolicy`].
+    pub fn build(self) -> RetryPolicy<M, P, C> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let attempts = self.attempt_budget.unwrap_or_else(|| {
+            let attempts = AttemptBudget::new(self.max_attempts);
+            match self.budget {
+                Some(budget) => attempts.with_budget(budget),
+                None => attempts,
+            }
+        });
+        RetryPolicy {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            attempts,
+            backoff: self.backoff,
+            predicate: self.predicate,
+            cloner: self.cloner,
+        }
+    }
+}
+
+impl<M, P, C> fmt::Debug for RetryPolicyBuilder<M, P, C>
+where
+    M: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicyBuilder")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .field("predicate", &format_args!("{}", std::any::type_name::<P>()))
+            .field("cloner", &format_args!("{}", std::any::type_name::<C>()))
+            .field("budget", &self.budget.is_some())
+            .field("attempt_budget", &self.attempt_budget)
+            .finish()
+    }
+}
+
+/// A ready-made retry [`Policy`], built by a [`RetryPolicyBuilder`].
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct RetryPolicy<M = NoBackoff, P = (), C = ()> {
+    id: u64,
+    attempts: AttemptBudget,
+    backoff: M,
+    predicate: P,
+    cloner: C,
+}
+
+impl RetryPolicy {
+    /// Returns a new [`RetryPolicyBuilder`].
+    pub fn builder() -> RetryPolicyBuilder {
+        RetryPolicyBuilder::new()
+    }
+}
+
+impl<M, P, C> RetryPolicy<M, P, C> {
+    /// Returns the backoff session of the request handled by the current task.
+    fn session<B>(&self) -> Option<Arc<B>>
+    where
+        B: Send + Sync + 'static,
+    {
+        SESSION
+            .try_with(|session| {
+                (session.policy == self.id).then(|| session.backoff.clone().downcast().ok())
+            })
+            .ok()
+            .flatten()
+            .flatten()
+    }
+}
+
+impl<M, P, C, Req, Res, E> Policy<Req, Res, E> for RetryPolicy<M, P, C>
+where
+    M: MakeBackoff,
+    M::Backoff: Send + Sync + 'static,
+    P: RetryPredicate<Res, E>,
+    C: RequestCloner<Req>,
+{
+    async fn retry(&self, _: &mut Req, result: &mut Result<Res, E>) -> bool {
+        if !self.predicate.should_retry(result) || !self.attempts.try_attempt() {
+            return false;
+        }
+        if let Some(backoff) = self.session::<M::Backoff>() {
+            backoff.next_backoff().await;
+        }
+        true
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        self.cloner.clone_request(req)
+    }
+
+    async fn scope<F>(&self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        if self.session::<M::Backoff>().is_some() {
+            return future.await;
+        }
+        let session = Session {
+            policy: self.id,
+            backoff: Arc::new(self.backoff.make_backoff()),
+        };
+        self.attempts.scope(SESSION.scope(session, future)).await
+    }
+}
+
+impl<M, P, C> fmt::Debug for RetryPolicy<M, P, C>
+where
+    M: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("attempts", &self.attempts)
+            .field("backoff", &self.backoff)
+            .field("predicate", &format_args!("{}", std::any::type_name::<P>()))
+            .field("cloner", &format_args!("{}", std::any::type_name::<C>()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::atomic::AtomicUsize, time::Duration};
+    use tokio::time::Instant;
+
+    use crate::{
+        retry::{budget::TpsBudget, RetryLayer},
+        service_fn,
+        util::{backoff::ExponentialBackoffMaker, rng::HasherRng},
+        Layer, Service,
+    };
+
+    fn service(
+        calls: Arc<AtomicUsize>,
+    ) -> impl Service<u32, Response = u32, Error = &'static str> + Clone {
+        service_fn(move |fails: u32| {
+            let call = calls.fetch_add(1, Ordering::SeqCst) as u32;
+            async move {
+                if call < fails {
+                    Err("unavailable")
+                } else {
+                    Ok(call)
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn retries_errors_up_to_max_attempts() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(RetryPolicy::builder().max_attempts(3).build())
+            .layer(service(calls.clone()));
+
+        assert_eq!(svc.call(2).await, Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // attempts are counted per request
+        calls.store(0, Ordering::SeqCst);
+        assert_eq!(svc.call(10).await, Err("unavailable"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_matching_results() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let policy = RetryPolicyBuilder::new()
+            .max_attempts(10)
+            .retry_if(|result: &Result<u32, &'static str>| matches!(result, Ok(n) if *n < 4))
+            .clone_request_with(|fails: &u32| (*fails < 100).then_some(*fails))
+            .build();
+        let svc = RetryLayer::new(policy).layer(service(calls.clone()));
+
+        // errors are not retried
+        assert_eq!(svc.call(1).await, Err("unavailable"));
+        assert_eq!(svc.call(0).await, Ok(4));
+
+        // requests which aren't cloned are not retried
+        assert_eq!(svc.call(100).await, Err("unavailable"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_backoff() {
+        let backoff = ExponentialBackoffMaker::new(
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+            0.0,
+            HasherRng::default(),
+        )
+        .unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(
+            RetryPolicyBuilder::new()
+                .max_attempts(4)
+                .backoff(backoff)
+                .build(),
+        )
+        .layer(service(calls.clone()));
+
+        for _ in 0..2 {
+            // every request starts a new backoff session
+            calls.store(0, Ordering::SeqCst);
+            let start = Instant::now();
+            assert_eq!(svc.call(3).await, Ok(3));
+            assert_eq!(start.elapsed(), Duration::from_millis(100 + 200 + 400));
+        }
+    }
+
+    #[tokio::test]
+    async fn withdraws_from_budget() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let budget = TpsBudget::new(Duration::from_secs(1), 0, 1.0);
+        let svc = RetryLayer::new(
+            RetryPolicyBuilder::new()
+                .max_attempts(10)
+                .budget(budget)
+                .build(),
+        )
+        .layer(service(calls.clone()));
+
+        // every request deposits a single retry
+        assert_eq!(svc.call(0).await, Ok(0));
+        calls.store(0, Ordering::SeqCst);
+        assert_eq!(svc.call(3).await, Err("unavailable"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -85,4 +85,29 @@ pub trait Policy<Req, Res, E> {
     /// If the request cannot be cloned, return [`None`]. Moreover, the retry
     /// function will not be called if the [`None`] is returned.
     fn clone_request(&self, req: &Req) -> Option<Req>;
+
+    /// Called when the policy asked to retry a request more often than allowed
+    /// by [`Retry::max_retries`], right before the last result is returned.
+    ///
+    /// Does nothing by default. The policy MAY mutate the result, for example
+    /// to turn a response into an error signaling that the retries were exhausted.
+    ///
+    /// [`Retry::max_retries`]: super::Retry::max_retries
+    fn retries_exceeded(&self, req: &mut Req, result: &mut Result<Res, E>) {
+        let _ = (req, result);
+    }
+
+    /// Wraps the handling of a single request by [`Retry`], its retries included.
+    ///
+    /// As the policy is shared by all requests, this allows it to keep state for the
+    /// duration of a single request, such as in a task-local, for example to track its
+    /// attempts or a backoff session. Returns `future` as is by default.
+    ///
+    /// [`Retry`]: super::Retry
+    fn scope<F>(&self, future: F) -> impl std::future::Future<Output = F::Output>
+    where
+        F: std::future::Future,
+    {
+        future
+    }
 }
@@ -0,0 +1,355 @@
+//! Route requests to one of many services based on a key of the request.
+//!
+//! [`Shard`] extracts a key from each request, such as the key of a cache or database
+//! command, and routes the request to the service owning that key. The owner is found using
+//! consistent hashing, which places every shard at many points of a hash ring. Adding or
+//! removing a shard therefore only moves the keys of that shard, while all other keys stay
+//! on the same shard, which makes it a good fit for cache and database client stacks.
+//!
+//! Shards can be added and removed while requests are served using a [`ShardHandle`],
+//! for example when the members of a cluster change.
+//!
+//! The hash function can be configured using [`Shard::with_hasher`]. By default the hasher of
+//! the standard library is used, whose output is not guaranteed to be stable across Rust
+//! releases. Clients which share the placement of keys with other processes should use a
+//! hasher with a stable output instead.
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use tower_async::{service_fn, shard::Shard, Service};
+//!
+//! struct Get {
+//!     key: String,
+//! }
+//!
+//! fn cache(name: &'static str) -> impl Service<Get, Response = String, Error = Infallible> {
+//!     service_fn(move |get: Get| async move { Ok(format!("{} from {name}", get.key)) })
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let shard = Shard::new(
+//!     [("cache-a", cache("cache-a")), ("cache-b", cache("cache-b"))],
+//!     |get: &Get| get.key.clone(),
+//! );
+//!
+//! // requests with the same key are always routed to the same shard
+//! let first = shard.call(Get { key: "user:42".to_owned() }).await.unwrap();
+//! let second = shard.call(Get { key: "user:42".to_owned() }).await.unwrap();
+//! assert_eq!(first, second);
+//!
+//! // once a shard is removed, its keys move to the remaining shards
+//! let handle = shard.handle();
+//! handle.remove(&"cache-a");
+//! let response = shard.call(Get { key: "user:42".to_owned() }).await.unwrap();
+//! assert_eq!(response, "user:42 from cache-b");
+//! # }
+//! ```
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    error, fmt,
+    hash::{BuildHasher, BuildHasherDefault, Hash},
+    sync::{Arc, RwLock},
+};
+
+use tower_async_service::Service;
+
+use crate::BoxError;
+
+mod ring;
+
+use self::ring::Ring;
+
+/// The default [`BuildHasher`] used by [`Shard`].
+pub type DefaultHashBuilder = BuildHasherDefault<DefaultHasher>;
+
+const DEFAULT_REPLICAS: usize = 160;
+
+/// Routes requests to one of many services, based on a consistent hash of a key
+/// extracted from the request.
+///
+/// See the [module docs](self) for more details.
+pub struct Shard<Id, S, F, H = DefaultHashBuilder> {
+    ring: Arc<RwLock<Ring<Id, S, H>>>,
+    key_fn: F,
+}
+
+impl<Id, S, F> Shard<Id, S, F>
+where
+    Id: Hash + Eq,
+{
+    /// Creates a new [`Shard`] routing requests to the given services, identified by their id,
+    /// using the key returned by `key_fn`.
+    pub fn new(shards: impl IntoIterator<Item = (Id, S)>, key_fn: F) -> Self {
+        Self::with_hasher(shards, key_fn, DefaultHashBuilder::default())
+    }
+}
+
+impl<Id, S, F, H> Shard<Id, S, F, H>
+where
+    Id: Hash + Eq,
+    H: BuildHasher,
+{
+    /// Creates a new [`Shard`] like [`Shard::new`], hashing the keys and the ids
+    /// of the shards using the given hasher.
+    pub fn with_hasher(shards: impl IntoIterator<Item = (Id, S)>, key_fn: F, hasher: H) -> Self {
+        let mut ring = Ring::new(DEFAULT_REPLICAS, hasher);
+        for (id, service) in shards {
+            ring.insert(id, service);
+        }
+        Shard {
+            ring: Arc::new(RwLock::new(ring)),
+            key_fn,
+        }
+    }
+
+    /// Sets the number of points at which each shard is placed on the hash ring,
+    /// defaults to 160.
+    ///
+    /// More points spread the keys more evenly over the shards,
+    /// at the cost of memory and slower re-sharding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `replicas` is 0.
+    pub fn replicas(self, replicas: usize) -> Self {
+        assert!(replicas > 0, "replicas must be larger than 0");
+        self.ring.write().unwrap().set_replicas(replicas);
+        self
+    }
+
+    /// Returns a [`ShardHandle`] to add and remove shards while requests are served.
+    pub fn handle(&self) -> ShardHandle<Id, S, H> {
+        ShardHandle {
+            ring: self.ring.clone(),
+        }
+    }
+}
+
+impl<Id, S, F, H, Request, K> Service<Request> for Shard<Id, S, F, H>
+where
+    Id: Hash + Eq,
+    S: Service<Request>,
+    S::Error: Into<BoxError>,
+    F: Fn(&Request) -> K,
+    K: Hash,
+    H: BuildHasher,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let key = (self.key_fn)(&request);
+        let service = self
+            .ring
+            .read()
+            .unwrap()
+            .get(&key)
+            .map(|(_, service)| service.clone())
+            .ok_or(NoShards(()))?;
+        service.call(request).await.map_err(Into::into)
+    }
+}
+
+impl<Id, S, F, H> Clone for Shard<Id, S, F, H>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        Shard {
+            ring: self.ring.clone(),
+            key_fn: self.key_fn.clone(),
+        }
+    }
+}
+
+impl<Id, S, F, H> fmt::Debug for Shard<Id, S, F, H>
+where
+    Id: fmt::Debug,
+    S: fmt::Debug,
+    H: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shard")
+            .field("ring", &self.ring)
+            .field("key_fn", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// A handle to add and remove the shards of a [`Shard`] while it serves requests.
+///
+/// Requests which were already routed to a shard are not affected.
+pub struct ShardHandle<Id, S, H = DefaultHashBuilder> {
+    ring: Arc<RwLock<Ring<Id, S, H>>>,
+}
+
+impl<Id, S, H> ShardHandle<Id, S, H>
+where
+    Id: Hash + Eq,
+    H: BuildHasher,
+{
+    /// Adds a shard, moving the keys of its points on the hash ring from the other shards.
+    ///
+    /// If a shard with the same id already exists, its service is replaced and returned,
+    /// while the placement of the keys is not affected.
+    pub fn insert(&self, id: Id, service: S) -> Option<Arc<S>> {
+        self.ring.write().unwrap().insert(id, service)
+    }
+
+    /// Removes a shard, moving its keys to the remaining shards,
+    /// and returns its service if it existed.
+    pub fn remove(&self, id: &Id) -> Option<Arc<S>> {
+        self.ring.write().unwrap().remove(id)
+    }
+
+    /// Returns the number of shards.
+    pub fn len(&self) -> usize {
+        self.ring.read().unwrap().len()
+    }
+
+    /// Returns `true` if there are no shards.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the ids of the shards.
+    pub fn ids(&self) -> Vec<Id>
+    where
+        Id: Clone,
+    {
+        self.ring.read().unwrap().ids().cloned().collect()
+    }
+
+    /// Returns the id of the shard owning the given key, if there are any shards.
+    pub fn owner<K: Hash>(&self, key: &K) -> Option<Id>
+    where
+        Id: Clone,
+    {
+        self.ring.read().unwrap().get(key).map(|(id, _)| id.clone())
+    }
+
+    /// Applies a change discovered by a [`Discover`] source, inserting or removing a shard.
+    ///
+    /// Returns the service of the replaced or removed shard, if any.
+    ///
+    /// [`Discover`]: crate::discover::Discover
+    #[cfg(feature = "discover")]
+    pub fn apply(&self, change: crate::discover::Change<Id, S>) -> Option<Arc<S>> {
+        match change {
+            crate::discover::Change::Insert(id, service) => self.insert(id, service),
+            crate::discover::Change::Remove(id) => self.remove(&id),
+        }
+    }
+}
+
+impl<Id, S, H> Clone for ShardHandle<Id, S, H> {
+    fn clone(&self) -> Self {
+        ShardHandle {
+            ring: self.ring.clone(),
+        }
+    }
+}
+
+impl<Id, S, H> fmt::Debug for ShardHandle<Id, S, H>
+where
+    Id: fmt::Debug,
+    S: fmt::Debug,
+    H: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardHandle")
+            .field("ring", &self.ring)
+            .finish()
+    }
+}
+
+/// Error returned by [`Shard`] when there are no shards to route the request to.
+#[derive(Debug, Default)]
+pub struct NoShards(pub(super) ());
+
+impl NoShards {
+    /// Construct a new no shards error.
+    pub fn new() -> Self {
+        NoShards(())
+    }
+}
+
+impl fmt::Display for NoShards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("no shards available")
+    }
+}
+
+impl error::Error for NoShards {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::service_fn;
+
+    fn shard_service(
+        id: usize,
+    ) -> impl Service<u64, Response = usize, Error = Infallible> + Send + Sync {
+        service_fn(move |_: u64| async move { Ok(id) })
+    }
+
+    #[tokio::test]
+    async fn same_key_same_shard() {
+        let shard = Shard::new((0..4).map(|id| (id, shard_service(id))), |key: &u64| *key);
+        for key in 0..100 {
+            let owner = shard.call(key).await.unwrap();
+            assert_eq!(shard.call(key).await.unwrap(), owner);
+            assert_eq!(shard.handle().owner(&key), Some(owner));
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_are_spread_over_shards() {
+        let shard = Shard::new((0..4).map(|id| (id, shard_service(id))), |key: &u64| *key);
+        let mut counts = [0; 4];
+        for key in 0..4000 {
+            counts[shard.call(key).await.unwrap()] += 1;
+        }
+        for count in counts {
+            assert!((500..1500).contains(&count), "{counts:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn resharding_only_moves_affected_keys() {
+        let shard = Shard::new((0..4).map(|id| (id, shard_service(id))), |key: &u64| *key);
+        let handle = shard.handle();
+        let before: Vec<_> = (0..1000u64)
+            .map(|key| handle.owner(&key).unwrap())
+            .collect();
+
+        handle.insert(4, shard_service(4));
+        assert_eq!(handle.len(), 5);
+        for (key, before) in before.iter().enumerate() {
+            let after = shard.call(key as u64).await.unwrap();
+            assert!(after == *before || after == 4);
+        }
+
+        handle.remove(&4);
+        for (key, before) in before.iter().enumerate() {
+            assert_eq!(shard.call(key as u64).await.unwrap(), *before);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_shards() {
+        let shard = Shard::new([(0, shard_service(0))], |key: &u64| *key);
+        shard.handle().remove(&0);
+        assert!(shard.handle().is_empty());
+
+        let err = shard.call(1).await.unwrap_err();
+        assert!(err.is::<NoShards>());
+    }
+}
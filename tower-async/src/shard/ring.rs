@@ -0,0 +1,81 @@
+use std::{
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+/// A consistent hash ring, placing each shard on the ring at `replicas` points.
+///
+/// A key is owned by the shard of the first point at or after the hash of the key,
+/// such that adding or removing a shard only moves the keys of the affected points.
+#[derive(Debug)]
+pub(super) struct Ring<Id, S, H> {
+    shards: Vec<(Id, Arc<S>)>,
+    points: Vec<(u64, usize)>,
+    replicas: usize,
+    hasher: H,
+}
+
+impl<Id, S, H> Ring<Id, S, H>
+where
+    Id: Hash + Eq,
+    H: BuildHasher,
+{
+    pub(super) fn new(replicas: usize, hasher: H) -> Self {
+        Ring {
+            shards: Vec::new(),
+            points: Vec::new(),
+            replicas,
+            hasher,
+        }
+    }
+
+    pub(super) fn set_replicas(&mut self, replicas: usize) {
+        self.replicas = replicas;
+        self.rebuild();
+    }
+
+    pub(super) fn insert(&mut self, id: Id, service: S) -> Option<Arc<S>> {
+        let service = Arc::new(service);
+        if let Some((_, existing)) = self.shards.iter_mut().find(|(other, _)| *other == id) {
+            return Some(std::mem::replace(existing, service));
+        }
+        self.shards.push((id, service));
+        self.rebuild();
+        None
+    }
+
+    pub(super) fn remove(&mut self, id: &Id) -> Option<Arc<S>> {
+        let index = self.shards.iter().position(|(other, _)| other == id)?;
+        let (_, service) = self.shards.swap_remove(index);
+        self.rebuild();
+        Some(service)
+    }
+
+    pub(super) fn get<K: Hash>(&self, key: &K) -> Option<&(Id, Arc<S>)> {
+        let hash = self.hasher.hash_one(key);
+        let index = match self.points.binary_search_by_key(&hash, |(point, _)| *point) {
+            Ok(index) | Err(index) => index,
+        };
+        let (_, shard) = self.points.get(index).or_else(|| self.points.first())?;
+        self.shards.get(*shard)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub(super) fn ids(&self) -> impl Iterator<Item = &Id> {
+        self.shards.iter().map(|(id, _)| id)
+    }
+
+    fn rebuild(&mut self) {
+        self.points.clear();
+        for (index, (id, _)) in self.shards.iter().enumerate() {
+            for replica in 0..self.replicas {
+                let point = self.hasher.hash_one((id, replica as u64));
+                self.points.push((point, index));
+            }
+        }
+        self.points.sort_unstable();
+    }
+}
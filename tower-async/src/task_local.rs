@@ -0,0 +1,212 @@
+//! Middleware that makes request context available as a task-local value.
+//!
+//! [`TaskLocal`] derives a value from each request, such as the id of the request or the
+//! tenant it was sent for, and sets it as the value of a Tokio [task-local] for the duration
+//! of the inner service's `call`. Code deep inside the call tree of a handler, such as logging
+//! or metrics helpers, can then access it without the value being passed through every
+//! function signature.
+//!
+//! The value is only set for the future returned by the inner service's `call`. It is not
+//! available to tasks spawned by the service, nor to response bodies that are streamed after
+//! `call` returned. Spawned tasks can set it again using [`LocalKey::scope`].
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use tower_async::{service_fn, Service, ServiceBuilder};
+//!
+//! tokio::task_local! {
+//!     static REQUEST_ID: u64;
+//! }
+//!
+//! struct Request {
+//!     id: u64,
+//!     body: String,
+//! }
+//!
+//! // somewhere deep inside the handler
+//! fn log(message: &str) -> String {
+//!     let id = REQUEST_ID.try_with(|id| *id).unwrap_or_default();
+//!     format!("[request {id}] {message}")
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let svc = ServiceBuilder::new()
+//!     .task_local(&REQUEST_ID, |req: &Request| req.id)
+//!     .service_fn(|req: Request| async move { Ok::<_, Infallible>(log(&req.body)) });
+//!
+//! let response = svc.call(Request { id: 42, body: "hello".to_owned() }).await.unwrap();
+//! assert_eq!(response, "[request 42] hello");
+//! # }
+//! ```
+//!
+//! [task-local]: tokio::task_local
+//! [`LocalKey::scope`]: tokio::task::LocalKey::scope
+
+use std::fmt;
+
+use tokio::task::LocalKey;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Sets a task-local value, derived from the request,
+/// for the duration of the inner service's `call`.
+///
+/// See the [module docs](self) for more details.
+pub struct TaskLocal<S, T: 'static, F> {
+    inner: S,
+    key: &'static LocalKey<T>,
+    f: F,
+}
+
+impl<S, T, F> TaskLocal<S, T, F> {
+    /// Creates a new [`TaskLocal`] service, setting `key` to the value returned by `f`.
+    pub fn new(inner: S, key: &'static LocalKey<T>, f: F) -> Self {
+        TaskLocal { inner, key, f }
+    }
+
+    /// Returns a new [`Layer`] that produces [`TaskLocal`] services.
+    ///
+    /// This is a convenience function that simply calls [`TaskLocalLayer::new`].
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(key: &'static LocalKey<T>, f: F) -> TaskLocalLayer<T, F> {
+        TaskLocalLayer::new(key, f)
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, F> Clone for TaskLocal<S, T, F>
+where
+    S: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        TaskLocal {
+            inner: self.inner.clone(),
+            key: self.key,
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<S, T, F> fmt::Debug for TaskLocal<S, T, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskLocal")
+            .field("inner", &self.inner)
+            .field("key", &self.key)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, T, F, Request> Service<Request> for TaskLocal<S, T, F>
+where
+    S: Service<Request>,
+    F: Fn(&Request) -> T,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let value = (self.f)(&request);
+        self.key.scope(value, self.inner.call(request)).await
+    }
+}
+
+/// A [`Layer`] that produces [`TaskLocal`] services.
+///
+/// [`Layer`]: tower_async_layer::Layer
+pub struct TaskLocalLayer<T: 'static, F> {
+    key: &'static LocalKey<T>,
+    f: F,
+}
+
+impl<T, F> TaskLocalLayer<T, F> {
+    /// Creates a new [`TaskLocalLayer`], setting `key` to the value returned by `f`.
+    pub fn new(key: &'static LocalKey<T>, f: F) -> Self {
+        TaskLocalLayer { key, f }
+    }
+}
+
+impl<T, F> Clone for TaskLocalLayer<T, F>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        TaskLocalLayer {
+            key: self.key,
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<T, F> fmt::Debug for TaskLocalLayer<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskLocalLayer")
+            .field("key", &self.key)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, T, F> Layer<S> for TaskLocalLayer<T, F>
+where
+    F: Clone,
+{
+    type Service = TaskLocal<S, T, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TaskLocal {
+            inner,
+            key: self.key,
+            f: self.f.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::service_fn;
+    use std::convert::Infallible;
+
+    tokio::task_local! {
+        static TENANT: &'static str;
+    }
+
+    async fn nested() -> &'static str {
+        tokio::task::yield_now().await;
+        TENANT.get()
+    }
+
+    #[tokio::test]
+    async fn sets_task_local_for_call() {
+        let svc = TaskLocalLayer::new(&TENANT, |req: &(&'static str, u32)| req.0).layer(
+            service_fn(|(_, n): (&'static str, u32)| async move {
+                Ok::<_, Infallible>((nested().await, n))
+            }),
+        );
+
+        let (a, b) = tokio::join!(svc.call(("acme", 1)), svc.call(("globex", 2)));
+        assert_eq!(a.unwrap(), ("acme", 1));
+        assert_eq!(b.unwrap(), ("globex", 2));
+
+        assert!(TENANT.try_with(|_| ()).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+/// Overrides the timeout of a [`Timeout`] for individual requests.
+///
+/// This allows a single [`Timeout`] to honor per-route or client supplied deadlines,
+/// such as a deadline sent along in a header, instead of stacking multiple timeouts.
+///
+/// Implemented for `()`, which never overrides the timeout, and for closures of the form
+/// `Fn(&Request) -> Option<Duration>`.
+///
+/// # Example
+///
+/// ```
+/// use std::{convert::Infallible, time::Duration};
+/// use tower_async::{service_fn, timeout::Timeout, Service};
+///
+/// struct Request {
+///     path: &'static str,
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let svc = Timeout::new(
+///     service_fn(|_: Request| async { Ok::<_, Infallible>(()) }),
+///     Duration::from_secs(1),
+/// )
+/// // exports take a while, give them more time
+/// .with_budget(|req: &Request| {
+///     req.path.starts_with("/export").then(|| Duration::from_secs(60))
+/// });
+///
+/// svc.call(Request { path: "/export/orders" }).await.unwrap();
+/// # }
+/// ```
+///
+/// [`Timeout`]: super::Timeout
+pub trait TimeoutBudget<Request> {
+    /// Returns the timeout to apply to the request,
+    /// or `None` to apply the timeout configured on the [`Timeout`].
+    ///
+    /// [`Timeout`]: super::Timeout
+    fn timeout(&self, request: &Request) -> Option<Duration>;
+}
+
+impl<Request> TimeoutBudget<Request> for () {
+    fn timeout(&self, _: &Request) -> Option<Duration> {
+        None
+    }
+}
+
+impl<F, Request> TimeoutBudget<Request> for F
+where
+    F: Fn(&Request) -> Option<Duration>,
+{
+    fn timeout(&self, request: &Request) -> Option<Duration> {
+        self(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+
+    use crate::{
+        service_fn,
+        timeout::{error::Elapsed, Deadline, TimeoutLayer},
+        BoxError, Layer, Service,
+    };
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn budget_overrides_timeout() {
+        let svc = TimeoutLayer::new(Duration::from_secs(5))
+            .with_budget(|(budget, _): &(Option<Duration>, Duration)| *budget)
+            .layer(service_fn(
+                |(_, delay): (Option<Duration>, Duration)| async move {
+                    let remaining = Deadline::current().unwrap().remaining();
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, BoxError>(remaining)
+                },
+            ));
+
+        let remaining = svc.call((None, Duration::ZERO)).await.unwrap();
+        assert_eq!(remaining, Duration::from_secs(5));
+
+        // a longer budget than the configured timeout
+        let remaining = svc
+            .call((Some(Duration::from_secs(30)), Duration::from_secs(10)))
+            .await
+            .unwrap();
+        assert_eq!(remaining, Duration::from_secs(30));
+
+        // a shorter budget than the configured timeout
+        let start = Instant::now();
+        let err = svc
+            .call((Some(Duration::from_secs(1)), Duration::from_secs(3)))
+            .await
+            .unwrap_err();
+        assert!(err.is::<Elapsed>());
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,244 @@
+use super::error::Elapsed;
+use std::{future::Future, time::Duration};
+use tokio::time::Instant;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Deadline used for budgets too long to be represented, roughly 30 years from now,
+/// the same as used by [`tokio::time::sleep`].
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
+}
+
+/// The point in time by which a request has to be handled.
+///
+/// [`Timeout`] makes the deadline of the request it handles available to the
+/// inner service, for the duration of the call. Nested client calls made from
+/// within a handler can use [`Deadline::current`] to learn the budget they
+/// have left, or be capped to it using [`RemainingTimeout`].
+///
+/// Nested deadlines never extend an outer deadline:
+/// the earliest deadline always applies.
+///
+/// # Example
+///
+/// ```
+/// use std::{convert::Infallible, time::Duration};
+/// use tower_async::{
+///     service_fn,
+///     timeout::{Deadline, TimeoutLayer},
+///     Service, ServiceBuilder,
+/// };
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let svc = ServiceBuilder::new()
+///     .layer(TimeoutLayer::new(Duration::from_secs(10)))
+///     .service_fn(|_: ()| async {
+///         let remaining = Deadline::current().unwrap().remaining();
+///         Ok::<_, Infallible>(remaining)
+///     });
+///
+/// let remaining = svc.call(()).await.unwrap();
+/// assert!(remaining <= Duration::from_secs(10));
+/// # }
+/// ```
+///
+/// [`Timeout`]: super::Timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl Deadline {
+    /// Create a new [`Deadline`] at the given instant.
+    pub fn new(at: Instant) -> Self {
+        Self { at }
+    }
+
+    /// Create a new [`Deadline`] that expires after the given budget.
+    ///
+    /// Budgets too long to be represented, such as [`Duration::MAX`],
+    /// expire in the far future instead.
+    pub fn after(budget: Duration) -> Self {
+        let now = Instant::now();
+        Self::new(now.checked_add(budget).unwrap_or_else(|| now + FAR_FUTURE))
+    }
+
+    /// Returns the deadline of the current task, if any.
+    pub fn current() -> Option<Self> {
+        DEADLINE.try_with(|deadline| *deadline).ok()
+    }
+
+    /// Run `future` with this deadline as the deadline of the current task.
+    ///
+    /// If the current task already has an earlier deadline, that deadline is kept.
+    pub async fn scope<F>(self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        let deadline = match Self::current() {
+            Some(current) => current.min(self),
+            None => self,
+        };
+        DEADLINE.scope(deadline, future).await
+    }
+
+    /// Returns the instant at which the deadline expires.
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+
+    /// Returns the budget left before the deadline expires.
+    ///
+    /// Returns [`Duration::ZERO`] once the deadline has expired.
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns `true` if the deadline has expired.
+    pub fn is_expired(&self) -> bool {
+        self.at <= Instant::now()
+    }
+}
+
+/// Caps requests to the [`Deadline`] of the current task.
+///
+/// Requests are failed with an [`Elapsed`] error once the deadline set by
+/// an outer [`Timeout`] expires, or immediately if it already expired.
+/// Requests made outside of a deadline are not limited.
+///
+/// This is typically used to wrap the clients used by a handler,
+/// such that they don't outlive the request they are made for.
+///
+/// [`Timeout`]: super::Timeout
+#[derive(Debug, Clone)]
+pub struct RemainingTimeout<T> {
+    inner: T,
+}
+
+impl<T> RemainingTimeout<T> {
+    /// Creates a new [`RemainingTimeout`].
+    pub fn new(inner: T) -> Self {
+        RemainingTimeout { inner }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<S, Request> Service<Request> for RemainingTimeout<S>
+where
+    S: Service<Request>,
+    S::Error: Into<crate::BoxError>,
+{
+    type Response = S::Response;
+    type Error = crate::BoxError;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let deadline = match Deadline::current() {
+            Some(deadline) => deadline,
+            None => return self.inner.call(request).await.map_err(Into::into),
+        };
+        if deadline.is_expired() {
+            return Err(Elapsed(()).into());
+        }
+
+        tokio::select! {
+            res = self.inner.call(request) => res.map_err(Into::into),
+            _ = tokio::time::sleep_until(deadline.at()) => Err(Elapsed(()).into()),
+        }
+    }
+}
+
+/// Caps requests to the [`Deadline`] of the current task via the supplied inner service.
+#[derive(Debug, Clone, Default)]
+pub struct RemainingTimeoutLayer {
+    _priv: (),
+}
+
+impl RemainingTimeoutLayer {
+    /// Creates a new [`RemainingTimeoutLayer`].
+    pub fn new() -> Self {
+        RemainingTimeoutLayer { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for RemainingTimeoutLayer {
+    type Service = RemainingTimeout<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RemainingTimeout::new(service)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, timeout::Timeout, BoxError};
+
+    #[tokio::test(start_paused = true)]
+    async fn nested_deadline_does_not_extend_outer() {
+        let svc = Timeout::new(
+            Timeout::new(
+                service_fn(|_: ()| async { Ok::<_, BoxError>(Deadline::current().unwrap()) }),
+                Duration::from_secs(10),
+            ),
+            Duration::from_secs(5),
+        );
+
+        let deadline = svc.call(()).await.unwrap();
+        assert_eq!(deadline.remaining(), Duration::from_secs(5));
+        assert_eq!(Deadline::current(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn max_duration_does_not_overflow() {
+        let svc = Timeout::new(
+            service_fn(|_: ()| async { Ok::<_, BoxError>(Deadline::current().unwrap()) }),
+            Duration::MAX,
+        );
+
+        let deadline = svc.call(()).await.unwrap();
+        assert!(deadline.remaining() >= FAR_FUTURE);
+        assert!(!Deadline::after(Duration::MAX).is_expired());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_calls_are_capped_to_remaining_budget() {
+        let client = RemainingTimeout::new(service_fn(|delay: Duration| async move {
+            tokio::time::sleep(delay).await;
+            Ok::<_, BoxError>(())
+        }));
+
+        // outside of a deadline calls are not limited
+        client.call(Duration::from_secs(60)).await.unwrap();
+
+        let handler = Timeout::new(
+            service_fn(|delay: Duration| {
+                let client = client.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_secs(3)).await;
+                    client.call(delay).await
+                }
+            }),
+            Duration::from_secs(5),
+        );
+
+        handler.call(Duration::from_secs(1)).await.unwrap();
+
+        let start = Instant::now();
+        let err = handler.call(Duration::from_secs(60)).await.unwrap_err();
+        assert!(err.is::<Elapsed>());
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+}
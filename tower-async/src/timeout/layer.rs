@@ -1,24 +1,56 @@
 use super::Timeout;
-use std::time::Duration;
+use crate::dynamic::Dynamic;
+use std::{fmt, time::Duration};
 use tower_async_layer::Layer;
 
 /// Applies a timeout to requests via the supplied inner service.
-#[derive(Debug, Clone)]
-pub struct TimeoutLayer {
-    timeout: Duration,
+#[derive(Clone)]
+pub struct TimeoutLayer<B = ()> {
+    timeout: Dynamic<Duration>,
+    budget: B,
 }
 
 impl TimeoutLayer {
-    /// Create a timeout from a duration
-    pub fn new(timeout: Duration) -> Self {
-        TimeoutLayer { timeout }
+    /// Create a timeout from a duration, or a [`Dynamic`] duration which can be updated
+    /// while the wrapped services are running.
+    pub fn new(timeout: impl Into<Dynamic<Duration>>) -> Self {
+        TimeoutLayer {
+            timeout: timeout.into(),
+            budget: (),
+        }
     }
 }
 
-impl<S> Layer<S> for TimeoutLayer {
-    type Service = Timeout<S>;
+impl<B> TimeoutLayer<B> {
+    /// Override the timeout for individual requests using the given [`TimeoutBudget`].
+    ///
+    /// See [`Timeout::with_budget`] for more details.
+    ///
+    /// [`TimeoutBudget`]: super::TimeoutBudget
+    pub fn with_budget<B2>(self, budget: B2) -> TimeoutLayer<B2> {
+        TimeoutLayer {
+            timeout: self.timeout,
+            budget,
+        }
+    }
+}
+
+impl<S, B> Layer<S> for TimeoutLayer<B>
+where
+    B: Clone,
+{
+    type Service = Timeout<S, B>;
 
     fn layer(&self, service: S) -> Self::Service {
-        Timeout::new(service, self.timeout)
+        Timeout::new(service, self.timeout.clone()).with_budget(self.budget.clone())
+    }
+}
+
+impl<B> fmt::Debug for TimeoutLayer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutLayer")
+            .field("timeout", &self.timeout)
+            .field("budget", &format_args!("{}", std::any::type_name::<B>()))
+            .finish()
     }
 }
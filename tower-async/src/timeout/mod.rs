@@ -2,30 +2,66 @@
 //!
 //! If the response does not complete within the specified timeout, the response
 //! will be aborted.
+//!
+//! The [`Deadline`] of a request is made available to the inner service, such that
+//! nested client calls can be capped to the remaining budget using [`RemainingTimeout`].
+//!
+//! The timeout can be overridden for individual requests using a [`TimeoutBudget`].
 
+mod budget;
+mod deadline;
 pub mod error;
 mod layer;
 
-pub use self::layer::TimeoutLayer;
+pub use self::{
+    budget::TimeoutBudget,
+    deadline::{Deadline, RemainingTimeout, RemainingTimeoutLayer},
+    layer::TimeoutLayer,
+};
 
 use error::Elapsed;
 
-use std::time::Duration;
+use std::{fmt, time::Duration};
 use tower_async_service::Service;
 
+use crate::dynamic::Dynamic;
+
 /// Applies a timeout to requests.
-#[derive(Debug, Clone)]
-pub struct Timeout<T> {
+///
+/// The inner service is called with the [`Deadline`] of the request set
+/// as the deadline of the current task.
+///
+/// The timeout can be a [`Dynamic`] value, in which case updates apply to subsequent requests,
+/// and can be overridden for individual requests using a [`TimeoutBudget`].
+#[derive(Clone)]
+pub struct Timeout<T, B = ()> {
     inner: T,
-    timeout: Duration,
+    timeout: Dynamic<Duration>,
+    budget: B,
 }
 
 // ===== impl Timeout =====
 
 impl<T> Timeout<T> {
     /// Creates a new [`Timeout`]
-    pub fn new(inner: T, timeout: Duration) -> Self {
-        Timeout { inner, timeout }
+    pub fn new(inner: T, timeout: impl Into<Dynamic<Duration>>) -> Self {
+        Timeout {
+            inner,
+            timeout: timeout.into(),
+            budget: (),
+        }
+    }
+}
+
+impl<T, B> Timeout<T, B> {
+    /// Override the timeout for individual requests using the given [`TimeoutBudget`],
+    /// such as a closure returning the timeout of a request, if any.
+    pub fn with_budget<B2>(self, budget: B2) -> Timeout<T, B2> {
+        Timeout {
+            inner: self.inner,
+            timeout: self.timeout,
+            budget,
+        }
     }
 
     /// Get a reference to the inner service
@@ -39,18 +75,37 @@ impl<T> Timeout<T> {
     }
 }
 
-impl<S, Request> Service<Request> for Timeout<S>
+impl<S, B, Request> Service<Request> for Timeout<S, B>
 where
     S: Service<Request>,
     S::Error: Into<crate::BoxError>,
+    B: TimeoutBudget<Request>,
 {
     type Response = S::Response;
     type Error = crate::BoxError;
 
     async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let timeout = self
+            .budget
+            .timeout(&request)
+            .unwrap_or_else(|| self.timeout.get());
+        let deadline = Deadline::after(timeout);
         tokio::select! {
-            res = self.inner.call(request) => res.map_err(Into::into),
-            _ = tokio::time::sleep(self.timeout) => Err(Elapsed(()).into()),
+            res = deadline.scope(self.inner.call(request)) => res.map_err(Into::into),
+            _ = tokio::time::sleep(timeout) => Err(Elapsed(()).into()),
         }
     }
 }
+
+impl<T, B> fmt::Debug for Timeout<T, B>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("inner", &self.inner)
+            .field("timeout", &self.timeout)
+            .field("budget", &format_args!("{}", std::any::type_name::<B>()))
+            .finish()
+    }
+}
@@ -0,0 +1,184 @@
+use super::report::{OnReport, TimingReport};
+use tokio::time::Instant;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Wraps a layer to record the time spent in the service it produces.
+///
+/// The service produced by the wrapped layer is given a [`TimedInner`] service,
+/// which records the time spent below the layer.
+///
+/// See the [module docs](super) for more details.
+#[derive(Debug, Clone)]
+pub struct TimedLayer<L> {
+    layer: L,
+    name: &'static str,
+    index: usize,
+}
+
+impl<L> TimedLayer<L> {
+    /// Creates a new [`TimedLayer`].
+    ///
+    /// `index` identifies the layer in the [`TimingReport`],
+    /// and has to be unique among the timed layers of a stack.
+    pub fn new(layer: L, name: &'static str, index: usize) -> Self {
+        TimedLayer { layer, name, index }
+    }
+}
+
+impl<S, L> Layer<S> for TimedLayer<L>
+where
+    L: Layer<TimedInner<S>>,
+{
+    type Service = Timed<L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let inner = TimedInner {
+            inner,
+            name: self.name,
+            index: self.index,
+        };
+        Timed {
+            inner: self.layer.layer(inner),
+            name: self.name,
+            index: self.index,
+        }
+    }
+}
+
+/// Records the time spent in the service produced by a [`TimedLayer`].
+#[derive(Debug, Clone)]
+pub struct Timed<S> {
+    inner: S,
+    name: &'static str,
+    index: usize,
+}
+
+impl<S> Timed<S> {
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, Request> Service<Request> for Timed<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let result = self.inner.call(request).await;
+        TimingReport::record_call(self.index, self.name, start.elapsed());
+        result
+    }
+}
+
+/// Records the time spent below the service produced by a [`TimedLayer`].
+#[derive(Debug, Clone)]
+pub struct TimedInner<S> {
+    inner: S,
+    name: &'static str,
+    index: usize,
+}
+
+impl<S> TimedInner<S> {
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, Request> Service<Request> for TimedInner<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let result = self.inner.call(request).await;
+        TimingReport::record_inner_call(self.index, self.name, start.elapsed());
+        result
+    }
+}
+
+/// Collects the [`TimingReport`] of each request via the supplied inner service.
+#[derive(Debug, Clone)]
+pub struct TimingReportLayer<F> {
+    on_report: F,
+}
+
+impl<F> TimingReportLayer<F> {
+    /// Creates a new [`TimingReportLayer`].
+    pub fn new(on_report: F) -> Self {
+        TimingReportLayer { on_report }
+    }
+}
+
+impl<S, F> Layer<S> for TimingReportLayer<F>
+where
+    F: Clone,
+{
+    type Service = WithTimingReport<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WithTimingReport {
+            inner,
+            on_report: self.on_report.clone(),
+        }
+    }
+}
+
+/// Collects the [`TimingReport`] of each request,
+/// and passes it to an [`OnReport`] callback once the request completed.
+#[derive(Debug, Clone)]
+pub struct WithTimingReport<S, F> {
+    inner: S,
+    on_report: F,
+}
+
+impl<S, F> WithTimingReport<S, F> {
+    /// Creates a new [`WithTimingReport`].
+    pub fn new(inner: S, on_report: F) -> Self {
+        WithTimingReport { inner, on_report }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, Request> Service<Request> for WithTimingReport<S, F>
+where
+    S: Service<Request>,
+    F: OnReport,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let (result, report) = TimingReport::collect(self.inner.call(request)).await;
+        self.on_report.on_report(report);
+        result
+    }
+}
@@ -0,0 +1,139 @@
+//! Middleware that records how much time each layer of a stack spends on a request.
+//!
+//! Stacks built with [`ServiceBuilder::timed`] wrap every layer added afterwards in a
+//! [`TimedLayer`], which measures the time spent in the service produced by that layer.
+//! Time spent in the services below it is measured separately, such that the [own] time
+//! of a layer is the overhead of that middleware alone, making it possible to see which
+//! middleware dominates the latency of a request.
+//!
+//! The measurements of a request are collected into a [`TimingReport`], which is passed
+//! to an [`OnReport`] callback once the request completed. By default the report is
+//! emitted as a `DEBUG` level [`tracing`] event.
+//!
+//! Measurements are collected in a task-local, so inner services that call their
+//! inner service concurrently, or on another task, are not measured accurately.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//! use tower_async::{timing::TimingReport, Service, ServiceBuilder};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let svc = ServiceBuilder::new()
+//!     .timed()
+//!     .on_report(|report: TimingReport| {
+//!         for layer in report.layers() {
+//!             println!("{}: {:?}", layer.name(), layer.own());
+//!         }
+//!     })
+//!     .named_layer("timeout", tower_async::timeout::TimeoutLayer::new(Duration::from_secs(10)))
+//!     .service_fn(|request: &'static str| async move { Ok::<_, Infallible>(request) });
+//!
+//! svc.call("foo").await.unwrap();
+//! # }
+//! ```
+//!
+//! [`ServiceBuilder::timed`]: crate::ServiceBuilder::timed
+//! [own]: LayerTiming::own
+//! [`tracing`]: https://docs.rs/tracing
+
+mod layer;
+mod report;
+
+pub use self::{
+    layer::{Timed, TimedInner, TimedLayer, TimingReportLayer, WithTimingReport},
+    report::{DefaultOnReport, LayerTiming, OnReport, TimingReport},
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Layer, Service, ServiceBuilder};
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    #[derive(Clone)]
+    struct Delay<S> {
+        inner: S,
+        delay: Duration,
+    }
+
+    impl<S, Request> Service<Request> for Delay<S>
+    where
+        S: Service<Request>,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+
+        async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.call(request).await
+        }
+    }
+
+    fn delay<S>(delay: Duration) -> impl Layer<S, Service = Delay<S>> + Clone {
+        crate::layer::layer_fn(move |inner| Delay { inner, delay })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_own_time_of_each_layer() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let svc = ServiceBuilder::new()
+            .timed()
+            .on_report({
+                let reports = reports.clone();
+                move |report: TimingReport| reports.lock().unwrap().push(report)
+            })
+            .named_layer("slow", delay(Duration::from_secs(2)))
+            .named_layer("fast", delay(Duration::from_millis(10)))
+            .service_fn(|request: u64| async move {
+                tokio::time::sleep(Duration::from_secs(request)).await;
+                Ok::<_, Infallible>(request)
+            });
+
+        svc.call(3).await.unwrap();
+
+        let report = reports.lock().unwrap().pop().unwrap();
+        let layers = report.layers();
+        assert_eq!(layers.len(), 2);
+
+        assert_eq!(layers[0].name(), "slow");
+        assert_eq!(layers[0].calls(), 1);
+        assert_eq!(layers[0].own(), Duration::from_secs(2));
+        assert_eq!(layers[0].total(), Duration::from_millis(5010));
+
+        assert_eq!(layers[1].name(), "fast");
+        assert_eq!(layers[1].own(), Duration::from_millis(10));
+        assert_eq!(layers[1].total(), Duration::from_millis(3010));
+
+        assert_eq!(report.slowest().unwrap().name(), "slow");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn layers_before_timed_are_not_recorded() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let svc = ServiceBuilder::new()
+            .layer(delay(Duration::from_secs(1)))
+            .timed()
+            .on_report({
+                let reports = reports.clone();
+                move |report: TimingReport| reports.lock().unwrap().push(report)
+            })
+            .layer(delay(Duration::from_secs(1)))
+            .service_fn(|_: ()| async { Ok::<_, Infallible>(()) });
+
+        svc.call(()).await.unwrap();
+
+        let report = reports.lock().unwrap().pop().unwrap();
+        assert_eq!(report.layers().len(), 1);
+        assert!(report.layers()[0].name().contains("LayerFn"));
+        assert_eq!(report.layers()[0].own(), Duration::from_secs(1));
+    }
+}
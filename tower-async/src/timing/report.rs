@@ -0,0 +1,193 @@
+use std::{cell::RefCell, fmt, time::Duration};
+
+tokio::task_local! {
+    static REPORT: RefCell<Vec<LayerTiming>>;
+}
+
+/// The time a single layer spent on a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerTiming {
+    index: usize,
+    name: &'static str,
+    calls: usize,
+    total: Duration,
+    inner: Duration,
+}
+
+impl LayerTiming {
+    /// Returns the name of the layer.
+    ///
+    /// Defaults to the type name of the layer.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns how often the service of the layer was called for the request.
+    pub fn calls(&self) -> usize {
+        self.calls
+    }
+
+    /// Returns the time spent in the service of the layer,
+    /// including the time spent in the services below it.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Returns the time spent in the service of the layer,
+    /// excluding the time spent in the services below it.
+    pub fn own(&self) -> Duration {
+        self.total.saturating_sub(self.inner)
+    }
+}
+
+/// The time each timed layer spent on a request.
+///
+/// See the [module docs](super) for more details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimingReport {
+    layers: Vec<LayerTiming>,
+}
+
+impl TimingReport {
+    /// Returns the timings of the layers called for the request,
+    /// ordered from the outermost to the innermost layer.
+    pub fn layers(&self) -> &[LayerTiming] {
+        &self.layers
+    }
+
+    /// Returns the layer with the largest [own] time, if any layer was called.
+    ///
+    /// [own]: LayerTiming::own
+    pub fn slowest(&self) -> Option<&LayerTiming> {
+        self.layers.iter().max_by_key(|layer| layer.own())
+    }
+
+    /// Returns the timings recorded so far for the request handled by the current task,
+    /// or `None` if the current task isn't collecting a report.
+    ///
+    /// Only the layers whose service already returned are included, such that a middleware
+    /// calling this after its inner service returned gets the timings of the layers below it.
+    pub fn current() -> Option<Self> {
+        REPORT
+            .try_with(|report| {
+                let mut layers: Vec<_> = report
+                    .borrow()
+                    .iter()
+                    .filter(|layer| layer.calls > 0)
+                    .cloned()
+                    .collect();
+                layers.sort_by_key(|layer| layer.index);
+                Self { layers }
+            })
+            .ok()
+    }
+
+    /// Run `future`, collecting the timings recorded by timed layers into a report.
+    pub(crate) async fn collect<F>(future: F) -> (F::Output, Self)
+    where
+        F: std::future::Future,
+    {
+        REPORT
+            .scope(RefCell::new(Vec::new()), async move {
+                let output = future.await;
+                let mut layers = REPORT.with(|report| report.take());
+                layers.sort_by_key(|layer| layer.index);
+                (output, Self { layers })
+            })
+            .await
+    }
+
+    /// Record a call to the service of the layer at `index`.
+    ///
+    /// Does nothing when called outside of [`TimingReport::collect`].
+    pub(crate) fn record_call(index: usize, name: &'static str, elapsed: Duration) {
+        Self::record(index, name, |layer| {
+            layer.calls += 1;
+            layer.total += elapsed;
+        });
+    }
+
+    /// Record a call to the service below the layer at `index`.
+    ///
+    /// Does nothing when called outside of [`TimingReport::collect`].
+    pub(crate) fn record_inner_call(index: usize, name: &'static str, elapsed: Duration) {
+        Self::record(index, name, |layer| layer.inner += elapsed);
+    }
+
+    fn record(index: usize, name: &'static str, f: impl FnOnce(&mut LayerTiming)) {
+        let _ = REPORT.try_with(|report| {
+            let mut report = report.borrow_mut();
+            let layer = match report.iter().position(|layer| layer.index == index) {
+                Some(position) => &mut report[position],
+                None => {
+                    report.push(LayerTiming {
+                        index,
+                        name,
+                        calls: 0,
+                        total: Duration::ZERO,
+                        inner: Duration::ZERO,
+                    });
+                    report.last_mut().unwrap()
+                }
+            };
+            f(layer);
+        });
+    }
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, layer) in self.layers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}={:?}", layer.name, layer.own())?;
+        }
+        Ok(())
+    }
+}
+
+/// Trait used to tell [`WithTimingReport`] what to do with the [`TimingReport`] of a request.
+///
+/// This trait is implemented for closures taking a [`TimingReport`].
+///
+/// [`WithTimingReport`]: super::WithTimingReport
+pub trait OnReport {
+    /// Do something with the [`TimingReport`] of a completed request.
+    fn on_report(&self, report: TimingReport);
+}
+
+impl<F> OnReport for F
+where
+    F: Fn(TimingReport),
+{
+    fn on_report(&self, report: TimingReport) {
+        self(report)
+    }
+}
+
+/// The default [`OnReport`] implementation,
+/// emitting the report as a `DEBUG` level [`tracing`] event.
+///
+/// [`tracing`]: https://docs.rs/tracing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultOnReport {
+    _priv: (),
+}
+
+impl DefaultOnReport {
+    /// Create a new [`DefaultOnReport`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OnReport for DefaultOnReport {
+    fn on_report(&self, report: TimingReport) {
+        tracing::debug!(
+            slowest = report.slowest().map(LayerTiming::name),
+            %report,
+            "layer timing report"
+        );
+    }
+}
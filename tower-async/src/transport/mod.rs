@@ -0,0 +1,96 @@
+//! Drive services over framed transports.
+//!
+//! A transport is a [`Stream`] of incoming frames combined with a [`Sink`] of outgoing frames,
+//! such as a [`Framed`] connection. This module provides the drivers which connect such
+//! transports to tower-async services, on either side of the connection:
+//!
+//! - [`pipeline`]: responses are sent in the order of the requests, such that they can be
+//!   matched without any additional information;
+//! - [`multiplex`]: requests and responses carry a tag, such that responses can be sent as soon
+//!   as they are ready, regardless of the order of the requests.
+//!
+//! Both offer a `Server`, which reads requests from a transport and answers them by calling a
+//! service, and a `Client`, which is a service sending its requests over a transport.
+//!
+//! The [`codec`] module builds on these drivers to serve connections framed by a codec.
+//!
+//! [`Stream`]: futures_core::Stream
+//! [`Sink`]: futures_util::Sink
+//! [`Framed`]: https://docs.rs/tokio-util/latest/tokio_util/codec/struct.Framed.html
+//! [`codec`]: crate::codec
+
+use std::{error, fmt, sync::Arc};
+
+use crate::BoxError;
+
+pub mod multiplex;
+pub mod pipeline;
+
+/// Error returned by the `Server` drivers.
+#[derive(Debug)]
+pub enum ServeError<T, S> {
+    /// The transport failed to receive a request or to send a response.
+    Transport(T),
+    /// The service failed to handle a request.
+    Service(S),
+}
+
+impl<T, S> fmt::Display for ServeError<T, S>
+where
+    T: fmt::Display,
+    S: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServeError::Transport(err) => write!(f, "transport error: {err}"),
+            ServeError::Service(err) => write!(f, "service error: {err}"),
+        }
+    }
+}
+
+impl<T, S> error::Error for ServeError<T, S>
+where
+    T: error::Error + 'static,
+    S: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ServeError::Transport(err) => Some(err),
+            ServeError::Service(err) => Some(err),
+        }
+    }
+}
+
+/// Error returned by the `Client` drivers once their transport is closed.
+///
+/// The transport is closed once the peer closed it, or once it failed,
+/// in which case the error of the transport is the [source](error::Error::source) of this error.
+#[derive(Debug, Clone)]
+pub struct Closed {
+    source: Option<Arc<BoxError>>,
+}
+
+impl Closed {
+    pub(crate) fn new(source: Option<BoxError>) -> Self {
+        Closed {
+            source: source.map(Arc::new),
+        }
+    }
+}
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "transport closed: {source}"),
+            None => f.pad("transport closed"),
+        }
+    }
+}
+
+impl error::Error for Closed {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| &**source as &(dyn error::Error + 'static))
+    }
+}
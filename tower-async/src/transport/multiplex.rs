@@ -0,0 +1,330 @@
+//! Multiplexed transports, where requests and responses carry a tag to match them.
+//!
+//! # Example
+//!
+//! ```
+//! use tokio_util::{
+//!     bytes::{Bytes, BytesMut},
+//!     codec::{Framed, LengthDelimitedCodec},
+//! };
+//! use tower_async::{codec::Tagged, service_fn, transport::multiplex, Service};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let (client, server) = tokio::io::duplex(64);
+//!
+//! tokio::spawn(async move {
+//!     let service = service_fn(|mut payload: BytesMut| async move {
+//!         payload.reverse();
+//!         Ok::<_, std::io::Error>(payload.freeze())
+//!     });
+//!     let transport = Framed::new(server, Tagged::new(LengthDelimitedCodec::new()));
+//!     multiplex::Server::new(service).serve(transport).await.unwrap();
+//! });
+//!
+//! let transport = Framed::new(client, Tagged::new(LengthDelimitedCodec::new()));
+//! let client = multiplex::Client::new(transport);
+//! let response = client.call(Bytes::from_static(b"ping")).await.unwrap();
+//! assert_eq!(&response[..], b"gnip");
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+use futures_core::Stream;
+use futures_util::{stream::FuturesUnordered, Sink, SinkExt, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tower_async_service::Service;
+
+use super::{Closed, ServeError};
+use crate::BoxError;
+
+/// Serves a multiplexed transport using a [`Service`].
+///
+/// Requests are received together with their tag, which is sent back together with the
+/// response. Up to [`max_in_flight`] requests are handled concurrently and their responses
+/// are sent as soon as they are ready.
+///
+/// [`max_in_flight`]: Server::max_in_flight
+#[derive(Debug, Clone)]
+pub struct Server<S> {
+    service: S,
+    max_in_flight: usize,
+}
+
+impl<S> Server<S> {
+    /// Creates a new [`Server`] calling the given service for each request.
+    pub fn new(service: S) -> Self {
+        Server {
+            service,
+            max_in_flight: 32,
+        }
+    }
+
+    /// Sets the maximum number of requests handled concurrently, defaults to 32.
+    ///
+    /// No more requests are received from the transport while this many requests are in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be larger than 0");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Consume `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+
+    /// Serves the transport until the peer closed it, or until the transport or the service
+    /// failed.
+    ///
+    /// Once the peer closed the transport, the responses of the requests in flight are sent
+    /// and the transport is closed.
+    ///
+    /// The requests are handled on the task calling this method, such that the service
+    /// does not need to be `Send` nor `'static`.
+    pub async fn serve<T, E, Tag, Request>(
+        &self,
+        transport: T,
+    ) -> Result<(), ServeError<E, S::Error>>
+    where
+        T: Stream<Item = Result<(Tag, Request), E>> + Sink<(Tag, S::Response), Error = E>,
+        S: Service<Request>,
+    {
+        serve(&self.service, self.max_in_flight, transport).await
+    }
+}
+
+pub(crate) async fn serve<S, T, E, Tag, Request>(
+    service: &S,
+    max_in_flight: usize,
+    transport: T,
+) -> Result<(), ServeError<E, S::Error>>
+where
+    T: Stream<Item = Result<(Tag, Request), E>> + Sink<(Tag, S::Response), Error = E>,
+    S: Service<Request>,
+{
+    let mut transport = std::pin::pin!(transport);
+    let mut in_flight = FuturesUnordered::new();
+    let mut receiving = true;
+
+    loop {
+        tokio::select! {
+            request = transport.next(), if receiving && in_flight.len() < max_in_flight => {
+                match request {
+                    Some(Ok((tag, request))) => in_flight.push(async move {
+                        (tag, service.call(request).await)
+                    }),
+                    Some(Err(err)) => return Err(ServeError::Transport(err)),
+                    None => receiving = false,
+                }
+            }
+            Some((tag, result)) = in_flight.next(), if !in_flight.is_empty() => {
+                let response = result.map_err(ServeError::Service)?;
+                transport.send((tag, response)).await.map_err(ServeError::Transport)?;
+            }
+            else => break,
+        }
+    }
+
+    transport.close().await.map_err(ServeError::Transport)
+}
+
+type Message<Request, Response> = (Request, oneshot::Sender<Result<Response, Closed>>);
+
+/// A [`Service`] sending its requests over a multiplexed transport.
+///
+/// Each request is sent together with a unique `u64` tag, and matched with the response
+/// received with the same tag. Responses with an unknown tag are ignored.
+///
+/// The transport is driven by a background task. Clones of the client share the same
+/// transport, which is closed once all of them are dropped and all responses received.
+pub struct Client<Request, Response> {
+    requests: mpsc::Sender<Message<Request, Response>>,
+    closed: Arc<OnceLock<Closed>>,
+}
+
+impl<Request, Response> Client<Request, Response>
+where
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    /// Creates a new [`Client`], spawning a task which drives the transport.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new<T, E>(transport: T) -> Self
+    where
+        T: Stream<Item = Result<(u64, Response), E>>
+            + Sink<(u64, Request), Error = E>
+            + Send
+            + 'static,
+        E: Into<BoxError> + Send + 'static,
+    {
+        let (requests, rx) = mpsc::channel(32);
+        let closed = Arc::new(OnceLock::new());
+        tokio::spawn(drive(transport, rx, closed.clone()));
+        Client { requests, closed }
+    }
+}
+
+async fn drive<T, E, Request, Response>(
+    transport: T,
+    mut requests: mpsc::Receiver<Message<Request, Response>>,
+    closed: Arc<OnceLock<Closed>>,
+) where
+    T: Stream<Item = Result<(u64, Response), E>> + Sink<(u64, Request), Error = E>,
+    E: Into<BoxError>,
+{
+    let mut transport = std::pin::pin!(transport);
+    let mut pending = HashMap::new();
+    let mut next_tag = 0u64;
+    let mut receiving = true;
+
+    let result: Result<(), BoxError> = loop {
+        if !receiving && pending.is_empty() {
+            break transport.close().await.map_err(Into::into);
+        }
+
+        tokio::select! {
+            message = requests.recv(), if receiving => match message {
+                Some((request, tx)) => {
+                    let tag = next_tag;
+                    next_tag = next_tag.wrapping_add(1);
+                    pending.insert(tag, tx);
+                    if let Err(err) = transport.send((tag, request)).await {
+                        break Err(err.into());
+                    }
+                }
+                None => receiving = false,
+            },
+            response = transport.next() => match response {
+                Some(Ok((tag, response))) => {
+                    if let Some(tx) = pending.remove(&tag) {
+                        let _ = tx.send(Ok(response));
+                    }
+                }
+                Some(Err(err)) => break Err(err.into()),
+                None => break Ok(()),
+            },
+        }
+    };
+
+    let error = closed.get_or_init(|| Closed::new(result.err()));
+    requests.close();
+    for (_, tx) in pending {
+        let _ = tx.send(Err(error.clone()));
+    }
+}
+
+impl<Request, Response> Client<Request, Response> {
+    fn closed(&self) -> Closed {
+        self.closed
+            .get()
+            .cloned()
+            .unwrap_or_else(|| Closed::new(None))
+    }
+}
+
+impl<Request, Response> Service<Request> for Client<Request, Response> {
+    type Response = Response;
+    type Error = Closed;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let (tx, rx) = oneshot::channel();
+        if self.requests.send((request, tx)).await.is_err() {
+            return Err(self.closed());
+        }
+        rx.await.unwrap_or_else(|_| Err(self.closed()))
+    }
+}
+
+impl<Request, Response> Clone for Client<Request, Response> {
+    fn clone(&self) -> Self {
+        Client {
+            requests: self.requests.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<Request, Response> fmt::Debug for Client<Request, Response> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("closed", &self.closed.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{io, time::Duration};
+    use tokio_util::{
+        bytes::{Bytes, BytesMut},
+        codec::{Framed, LengthDelimitedCodec},
+    };
+
+    use crate::{codec::Tagged, service_fn};
+
+    #[tokio::test(start_paused = true)]
+    async fn responses_are_matched_by_tag() {
+        let (client, server) = tokio::io::duplex(256);
+
+        tokio::spawn(async move {
+            let service = service_fn(|payload: BytesMut| async move {
+                let delay = payload[0] as u64;
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok::<_, io::Error>(payload.freeze())
+            });
+            let transport = Framed::new(server, Tagged::new(LengthDelimitedCodec::new()));
+            Server::new(service).serve(transport).await.unwrap();
+        });
+
+        let client = Client::new(Framed::new(
+            client,
+            Tagged::new(LengthDelimitedCodec::new()),
+        ));
+        let start = tokio::time::Instant::now();
+        let responses = futures_util::future::join_all(
+            [30u8, 10, 20].map(|delay| client.call(Bytes::from(vec![delay]))),
+        )
+        .await;
+        let responses: Vec<_> = responses.into_iter().map(|r| r.unwrap()[0]).collect();
+        assert_eq!(responses, [30, 10, 20]);
+        // handled concurrently
+        assert_eq!(start.elapsed(), Duration::from_millis(30));
+    }
+
+    #[tokio::test]
+    async fn transport_errors_close_the_client() {
+        let (client, server) = tokio::io::duplex(64);
+        let client = Client::new(Framed::new(
+            client,
+            Tagged::new(LengthDelimitedCodec::new()),
+        ));
+
+        // a frame which is too short to contain a tag
+        let mut server = Framed::new(server, LengthDelimitedCodec::new());
+        server.send(Bytes::from_static(b"oops")).await.unwrap();
+
+        let err = client.call(Bytes::from_static(b"ping")).await.unwrap_err();
+        assert_eq!(err.to_string(), "transport closed: frame is missing its id");
+        assert!(client.call(Bytes::from_static(b"ping")).await.is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Pipelined transports, where responses are sent in the order of the requests.
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use tokio_util::codec::{Framed, LinesCodec};
+//! use tower_async::{service_fn, transport::pipeline, Service};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let (client, server) = tokio::io::duplex(64);
+//!
+//! tokio::spawn(async move {
+//!     let service = service_fn(|line: String| async move {
+//!         Ok::<_, Infallible>(line.to_uppercase())
+//!     });
+//!     pipeline::Server::new(service)
+//!         .serve(Framed::new(server, LinesCodec::new()))
+//!         .await
+//!         .unwrap();
+//! });
+//!
+//! let client = pipeline::Client::new(Framed::new(client, LinesCodec::new()));
+//! let (hello, world) = tokio::join!(
+//!     client.call("hello".to_owned()),
+//!     client.call("world".to_owned()),
+//! );
+//! assert_eq!(hello.unwrap(), "HELLO");
+//! assert_eq!(world.unwrap(), "WORLD");
+//! # }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+use futures_core::Stream;
+use futures_util::{stream::FuturesOrdered, Sink, SinkExt, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tower_async_service::Service;
+
+use super::{Closed, ServeError};
+use crate::BoxError;
+
+/// Serves a pipelined transport using a [`Service`].
+///
+/// Up to [`max_in_flight`] requests are handled concurrently,
+/// while their responses are sent in the order of the requests.
+///
+/// [`max_in_flight`]: Server::max_in_flight
+#[derive(Debug, Clone)]
+pub struct Server<S> {
+    service: S,
+    max_in_flight: usize,
+}
+
+impl<S> Server<S> {
+    /// Creates a new [`Server`] calling the given service for each request.
+    pub fn new(service: S) -> Self {
+        Server {
+            service,
+            max_in_flight: 32,
+        }
+    }
+
+    /// Sets the maximum number of requests handled concurrently, defaults to 32.
+    ///
+    /// No more requests are received from the transport while this many requests are in
+    /// flight, a value of 1 handles one request at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be larger than 0");
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Consume `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+
+    /// Serves the transport until the peer closed it, or until the transport or the service
+    /// failed.
+    ///
+    /// Once the peer closed the transport, the responses of the requests in flight are sent
+    /// and the transport is closed.
+    pub async fn serve<T, E, Request>(&self, transport: T) -> Result<(), ServeError<E, S::Error>>
+    where
+        T: Stream<Item = Result<Request, E>> + Sink<S::Response, Error = E>,
+        S: Service<Request>,
+    {
+        serve(&self.service, self.max_in_flight, transport).await
+    }
+}
+
+pub(crate) async fn serve<S, T, E, Request>(
+    service: &S,
+    max_in_flight: usize,
+    transport: T,
+) -> Result<(), ServeError<E, S::Error>>
+where
+    T: Stream<Item = Result<Request, E>> + Sink<S::Response, Error = E>,
+    S: Service<Request>,
+{
+    let mut transport = std::pin::pin!(transport);
+    let mut in_flight = FuturesOrdered::new();
+    let mut receiving = true;
+
+    loop {
+        tokio::select! {
+            request = transport.next(), if receiving && in_flight.len() < max_in_flight => {
+                match request {
+                    Some(Ok(request)) => in_flight.push_back(service.call(request)),
+                    Some(Err(err)) => return Err(ServeError::Transport(err)),
+                    None => receiving = false,
+                }
+            }
+            Some(result) = in_flight.next(), if !in_flight.is_empty() => {
+                let response = result.map_err(ServeError::Service)?;
+                transport.send(response).await.map_err(ServeError::Transport)?;
+            }
+            else => break,
+        }
+    }
+
+    transport.close().await.map_err(ServeError::Transport)
+}
+
+type Message<Request, Response> = (Request, oneshot::Sender<Result<Response, Closed>>);
+
+/// A [`Service`] sending its requests over a pipelined transport.
+///
+/// The transport is driven by a background task, which matches the responses to the
+/// requests in the order in which they were sent. Clones of the client share the same
+/// transport, which is closed once all of them are dropped and all responses received.
+pub struct Client<Request, Response> {
+    requests: mpsc::Sender<Message<Request, Response>>,
+    closed: Arc<OnceLock<Closed>>,
+}
+
+impl<Request, Response> Client<Request, Response>
+where
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    /// Creates a new [`Client`], spawning a task which drives the transport.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new<T, E>(transport: T) -> Self
+    where
+        T: Stream<Item = Result<Response, E>> + Sink<Request, Error = E> + Send + 'static,
+        E: Into<BoxError> + Send + 'static,
+    {
+        let (requests, rx) = mpsc::channel(32);
+        let closed = Arc::new(OnceLock::new());
+        tokio::spawn(drive(transport, rx, closed.clone()));
+        Client { requests, closed }
+    }
+}
+
+async fn drive<T, E, Request, Response>(
+    transport: T,
+    mut requests: mpsc::Receiver<Message<Request, Response>>,
+    closed: Arc<OnceLock<Closed>>,
+) where
+    T: Stream<Item = Result<Response, E>> + Sink<Request, Error = E>,
+    E: Into<BoxError>,
+{
+    let mut transport = std::pin::pin!(transport);
+    let mut pending = VecDeque::new();
+    let mut receiving = true;
+
+    let result: Result<(), BoxError> = loop {
+        if !receiving && pending.is_empty() {
+            break transport.close().await.map_err(Into::into);
+        }
+
+        tokio::select! {
+            message = requests.recv(), if receiving => match message {
+                Some((request, tx)) => {
+                    pending.push_back(tx);
+                    if let Err(err) = transport.send(request).await {
+                        break Err(err.into());
+                    }
+                }
+                None => receiving = false,
+            },
+            response = transport.next() => match response {
+                Some(Ok(response)) => match pending.pop_front() {
+                    Some(tx) => {
+                        let _ = tx.send(Ok(response));
+                    }
+                    None => break Err("received a response without a request".into()),
+                },
+                Some(Err(err)) => break Err(err.into()),
+                None => break Ok(()),
+            },
+        }
+    };
+
+    let error = closed.get_or_init(|| Closed::new(result.err()));
+    requests.close();
+    for tx in pending {
+        let _ = tx.send(Err(error.clone()));
+    }
+}
+
+impl<Request, Response> Client<Request, Response> {
+    fn closed(&self) -> Closed {
+        self.closed
+            .get()
+            .cloned()
+            .unwrap_or_else(|| Closed::new(None))
+    }
+}
+
+impl<Request, Response> Service<Request> for Client<Request, Response> {
+    type Response = Response;
+    type Error = Closed;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let (tx, rx) = oneshot::channel();
+        if self.requests.send((request, tx)).await.is_err() {
+            return Err(self.closed());
+        }
+        rx.await.unwrap_or_else(|_| Err(self.closed()))
+    }
+}
+
+impl<Request, Response> Clone for Client<Request, Response> {
+    fn clone(&self) -> Self {
+        Client {
+            requests: self.requests.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<Request, Response> fmt::Debug for Client<Request, Response> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("closed", &self.closed.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{convert::Infallible, time::Duration};
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    use crate::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn responses_are_sent_in_order() {
+        let (client, server) = tokio::io::duplex(64);
+
+        tokio::spawn(async move {
+            let service = service_fn(|line: String| async move {
+                let delay = line.parse().unwrap();
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok::<_, Infallible>(line)
+            });
+            Server::new(service)
+                .serve(Framed::new(server, LinesCodec::new()))
+                .await
+                .unwrap();
+        });
+
+        let client = Client::new(Framed::new(client, LinesCodec::new()));
+        let responses = futures_util::future::join_all(
+            ["30", "10", "20"].map(|delay| client.call(delay.to_owned())),
+        )
+        .await;
+        let responses: Vec<_> = responses.into_iter().map(Result::unwrap).collect();
+        assert_eq!(responses, ["30", "10", "20"]);
+    }
+
+    #[tokio::test]
+    async fn pending_requests_fail_once_closed() {
+        let (client, server) = tokio::io::duplex(64);
+        let client = Client::<String, String>::new(Framed::new(client, LinesCodec::new()));
+
+        let call = client.call("never answered".to_owned());
+        let close = async {
+            tokio::task::yield_now().await;
+            drop(server);
+        };
+        let (result, _) = tokio::join!(call, close);
+        assert!(result.is_err());
+
+        let err = client.call("closed".to_owned()).await.unwrap_err();
+        assert!(err.to_string().starts_with("transport closed"));
+    }
+}
@@ -27,6 +27,31 @@ pub trait Backoff {
     fn next_backoff(&self) -> impl std::future::Future<Output = ()>;
 }
 
+/// A backoff, and maker of it, which resumes operations right away.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoBackoff {
+    _priv: (),
+}
+
+impl NoBackoff {
+    /// Create a new [`NoBackoff`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MakeBackoff for NoBackoff {
+    type Backoff = NoBackoff;
+
+    fn make_backoff(&self) -> Self::Backoff {
+        *self
+    }
+}
+
+impl Backoff for NoBackoff {
+    async fn next_backoff(&self) {}
+}
+
 #[cfg(feature = "util-tokio")]
 mod exponential;
 #[cfg(feature = "util-tokio")]
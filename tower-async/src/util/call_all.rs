@@ -0,0 +1,338 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use futures_util::{
+    stream::{FuturesOrdered, FuturesUnordered},
+    StreamExt,
+};
+use tower_async_service::Service;
+
+use super::DynFuture;
+
+type CallFuture<S, Request> =
+    DynFuture<'static, Result<<S as Service<Request>>::Response, <S as Service<Request>>::Error>>;
+
+/// A stream of responses, calling a service with each request of a stream of requests.
+///
+/// Up to [`max_in_flight`] calls are made concurrently, and their responses are yielded in
+/// the order of the requests. Use [`CallAll::unordered`] to yield the responses as soon as
+/// they are ready instead. No more requests are taken from the request stream while this
+/// many calls are in flight, such that the stream is consumed at the pace of the service.
+///
+/// The service is shared by the calls through an [`Arc`], such that each call owns its
+/// future. As with [`Oneshot`], these futures are boxed, and thus the stream is not `Send`.
+///
+/// Errors of the service are yielded like any other response, without ending the stream.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use futures_util::{stream, StreamExt};
+/// use tower_async::{service_fn, ServiceExt};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let svc = service_fn(|n: u32| async move { Ok::<_, Infallible>(n * 2) });
+///
+/// let responses: Vec<_> = svc
+///     .call_all(stream::iter(1..=3))
+///     .max_in_flight(2)
+///     .collect()
+///     .await;
+/// assert_eq!(responses, [Ok(2), Ok(4), Ok(6)]);
+/// # }
+/// ```
+///
+/// [`max_in_flight`]: CallAll::max_in_flight
+/// [`Oneshot`]: super::Oneshot
+pub struct CallAll<S, St>
+where
+    St: Stream,
+    S: Service<St::Item>,
+{
+    driver: Driver<S, St, FuturesOrdered<CallFuture<S, St::Item>>>,
+}
+
+impl<S, St> CallAll<S, St>
+where
+    St: Stream,
+    S: Service<St::Item>,
+{
+    /// Creates a new [`CallAll`], calling the service with each request of the stream.
+    pub fn new(service: S, requests: St) -> Self {
+        CallAll {
+            driver: Driver {
+                service: Arc::new(service),
+                requests: Box::pin(requests),
+                requests_done: false,
+                in_flight: FuturesOrdered::new(),
+                max_in_flight: 32,
+            },
+        }
+    }
+
+    /// Sets the maximum number of concurrent calls, defaults to 32.
+    ///
+    /// A value of 1 calls the service with one request at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is 0.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be larger than 0");
+        self.driver.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Yields the responses as soon as they are ready, rather than in the order of the requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if calls are in flight, which is the case once the stream is polled.
+    pub fn unordered(self) -> CallAllUnordered<S, St> {
+        assert!(
+            self.driver.in_flight.is_empty(),
+            "CallAll::unordered called with calls in flight"
+        );
+        let driver = self.driver;
+        CallAllUnordered {
+            driver: Driver {
+                service: driver.service,
+                requests: driver.requests,
+                requests_done: driver.requests_done,
+                in_flight: FuturesUnordered::new(),
+                max_in_flight: driver.max_in_flight,
+            },
+        }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.driver.service
+    }
+}
+
+impl<S, St> Stream for CallAll<S, St>
+where
+    St: Stream,
+    St::Item: 'static,
+    S: Service<St::Item> + 'static,
+{
+    type Item = Result<S::Response, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().driver.poll_next(cx)
+    }
+}
+
+impl<S, St> fmt::Debug for CallAll<S, St>
+where
+    St: Stream,
+    S: Service<St::Item>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.driver.fmt("CallAll", f)
+    }
+}
+
+/// A stream of responses, calling a service with each request of a stream of requests,
+/// yielding the responses as soon as they are ready.
+///
+/// Created with [`CallAll::unordered`], see [`CallAll`] for more details.
+pub struct CallAllUnordered<S, St>
+where
+    St: Stream,
+    S: Service<St::Item>,
+{
+    driver: Driver<S, St, FuturesUnordered<CallFuture<S, St::Item>>>,
+}
+
+impl<S, St> CallAllUnordered<S, St>
+where
+    St: Stream,
+    S: Service<St::Item>,
+{
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.driver.service
+    }
+}
+
+impl<S, St> Stream for CallAllUnordered<S, St>
+where
+    St: Stream,
+    St::Item: 'static,
+    S: Service<St::Item> + 'static,
+{
+    type Item = Result<S::Response, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().driver.poll_next(cx)
+    }
+}
+
+impl<S, St> fmt::Debug for CallAllUnordered<S, St>
+where
+    St: Stream,
+    S: Service<St::Item>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.driver.fmt("CallAllUnordered", f)
+    }
+}
+
+/// The queue of calls in flight, either ordered or unordered.
+trait Queue: Stream + Unpin {
+    type Future;
+
+    fn push(&mut self, future: Self::Future);
+
+    fn len(&self) -> usize;
+}
+
+impl<T> Queue for FuturesOrdered<DynFuture<'static, T>> {
+    type Future = DynFuture<'static, T>;
+
+    fn push(&mut self, future: Self::Future) {
+        self.push_back(future);
+    }
+
+    fn len(&self) -> usize {
+        FuturesOrdered::len(self)
+    }
+}
+
+impl<T> Queue for FuturesUnordered<DynFuture<'static, T>> {
+    type Future = DynFuture<'static, T>;
+
+    fn push(&mut self, future: Self::Future) {
+        FuturesUnordered::push(self, future);
+    }
+
+    fn len(&self) -> usize {
+        FuturesUnordered::len(self)
+    }
+}
+
+struct Driver<S, St, Q> {
+    service: Arc<S>,
+    requests: Pin<Box<St>>,
+    requests_done: bool,
+    in_flight: Q,
+    max_in_flight: usize,
+}
+
+impl<S, St, Q> Driver<S, St, Q>
+where
+    St: Stream,
+    St::Item: 'static,
+    S: Service<St::Item> + 'static,
+    Q: Queue<Future = CallFuture<S, St::Item>, Item = Result<S::Response, S::Error>>,
+{
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Q::Item>> {
+        while !self.requests_done && self.in_flight.len() < self.max_in_flight {
+            match self.requests.as_mut().poll_next(cx) {
+                Poll::Ready(Some(request)) => {
+                    let service = self.service.clone();
+                    self.in_flight
+                        .push(Box::pin(async move { service.call(request).await }));
+                }
+                Poll::Ready(None) => self.requests_done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        match self.in_flight.poll_next_unpin(cx) {
+            Poll::Ready(Some(result)) => Poll::Ready(Some(result)),
+            // no calls in flight, while the request stream is pending
+            Poll::Ready(None) if !self.requests_done => Poll::Pending,
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S, St, Q: Queue> Driver<S, St, Q> {
+    fn fmt(&self, name: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(name)
+            .field("service", &format_args!("{}", std::any::type_name::<S>()))
+            .field("in_flight", &self.in_flight.len())
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use futures_util::stream;
+
+    use crate::{service_fn, ServiceExt};
+
+    fn sleepy() -> impl Service<u64, Response = u64, Error = Infallible> {
+        service_fn(|ms: u64| async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(ms)
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ordered() {
+        let responses: Vec<_> = sleepy()
+            .call_all(stream::iter([30, 10, 20]))
+            .collect()
+            .await;
+        assert_eq!(responses, [Ok(30), Ok(10), Ok(20)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unordered() {
+        let responses: Vec<_> = sleepy()
+            .call_all(stream::iter([30, 10, 20]))
+            .unordered()
+            .collect()
+            .await;
+        assert_eq!(responses, [Ok(10), Ok(20), Ok(30)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_calls_in_flight() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let svc = service_fn({
+            let (in_flight, max) = (in_flight.clone(), max.clone());
+            move |n: u32| {
+                let (in_flight, max) = (in_flight.clone(), max.clone());
+                async move {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Ok::<_, Infallible>(n)
+                }
+            }
+        });
+
+        let responses: Vec<_> = svc
+            .call_all(stream::iter(0..10))
+            .max_in_flight(3)
+            .unordered()
+            .collect()
+            .await;
+        assert_eq!(responses.len(), 10);
+        assert_eq!(max.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,206 @@
+//! Type erased services whose futures are `Send`.
+//!
+//! See [`BoxSendService`] for more details.
+
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// A boxed `Send` future, as returned by the services erased by [`BoxSendService`].
+type DynSendFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// An object-safe version of [`Service`], for services whose futures are `Send`.
+trait DynSendService<Request>: Send + Sync {
+    type Response;
+    type Error;
+
+    fn call_dyn_send<'a>(
+        &'a self,
+        request: Request,
+    ) -> DynSendFuture<'a, Result<Self::Response, Self::Error>>
+    where
+        Request: 'a;
+}
+
+impl<S, Request> DynSendService<Request> for S
+where
+    S: Service<Request, call(): Send> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call_dyn_send<'a>(
+        &'a self,
+        request: Request,
+    ) -> DynSendFuture<'a, Result<Self::Response, Self::Error>>
+    where
+        Request: 'a,
+    {
+        Box::pin(self.call(request))
+    }
+}
+
+/// A type erased [`Service`], whose futures are `Send`.
+///
+/// Like [`BoxService`], but only for services whose futures are `Send`, such that the futures
+/// of the [`BoxSendService`] are `Send` as well, as long as the request is. This allows serving
+/// type erased services where their calls are spawned, such as with `tower-async-hyper`.
+///
+/// Clones of a [`BoxSendService`] share the same service.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tower_async::{service_fn, util::BoxSendService, BoxError, Service, ServiceBuilder};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let service: BoxSendService<u32, u32, BoxError> = BoxSendService::new(
+///     ServiceBuilder::new()
+///         .timeout(Duration::from_secs(1))
+///         .service_fn(|n: u32| async move { Ok::<_, BoxError>(n * 2) }),
+/// );
+///
+/// // the call can be spawned
+/// let response = tokio::spawn(async move { service.call(3).await }).await.unwrap();
+/// assert_eq!(response.unwrap(), 6);
+/// # }
+/// ```
+///
+/// [`Service`]: crate::Service
+/// [`BoxService`]: crate::util::BoxService
+pub struct BoxSendService<Request, Response, Error> {
+    inner: Arc<dyn DynSendService<Request, Response = Response, Error = Error>>,
+}
+
+impl<Request, Response, Error> BoxSendService<Request, Response, Error> {
+    /// Create a new [`BoxSendService`], erasing the type of the given service.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<Request, Response = Response, Error = Error, call(): Send>
+            + Send
+            + Sync
+            + 'static,
+    {
+        BoxSendService {
+            inner: Arc::new(service),
+        }
+    }
+
+    /// Returns a [`BoxSendLayer`] that wraps services in a [`BoxSendService`].
+    pub fn layer<S>() -> BoxSendLayer<S, Request, Response, Error>
+    where
+        S: Service<Request, Response = Response, Error = Error, call(): Send>
+            + Send
+            + Sync
+            + 'static,
+        Request: Send + 'static,
+        Response: 'static,
+        Error: 'static,
+    {
+        BoxSendLayer::new(tower_async_layer::layer_fn(Self::new))
+    }
+}
+
+impl<Request, Response, Error> Service<Request> for BoxSendService<Request, Response, Error> {
+    type Response = Response;
+    type Error = Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        self.inner.call_dyn_send(request).await
+    }
+}
+
+impl<Request, Response, Error> Clone for BoxSendService<Request, Response, Error> {
+    fn clone(&self) -> Self {
+        BoxSendService {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<Request, Response, Error> fmt::Debug for BoxSendService<Request, Response, Error> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxSendService").finish_non_exhaustive()
+    }
+}
+
+/// A type erased [`Layer`], producing [`BoxSendService`]s.
+///
+/// Like [`BoxLayer`], but for layers producing services whose futures are `Send`.
+///
+/// [`Layer`]: crate::Layer
+/// [`BoxLayer`]: crate::util::BoxLayer
+pub struct BoxSendLayer<In, Request, Response, Error> {
+    boxed: Arc<dyn Fn(In) -> BoxSendService<Request, Response, Error> + Send + Sync>,
+}
+
+impl<In, Request, Response, Error> BoxSendLayer<In, Request, Response, Error> {
+    /// Create a new [`BoxSendLayer`], erasing the type of the given layer.
+    pub fn new<L>(inner_layer: L) -> Self
+    where
+        L: Layer<In> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Response, Error = Error, call(): Send>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let layer = move |inner: In| BoxSendService::new(inner_layer.layer(inner));
+        BoxSendLayer {
+            boxed: Arc::new(layer),
+        }
+    }
+}
+
+impl<In, Request, Response, Error> Layer<In> for BoxSendLayer<In, Request, Response, Error> {
+    type Service = BoxSendService<Request, Response, Error>;
+
+    fn layer(&self, inner: In) -> Self::Service {
+        (self.boxed)(inner)
+    }
+}
+
+impl<In, Request, Response, Error> Clone for BoxSendLayer<In, Request, Response, Error> {
+    fn clone(&self) -> Self {
+        BoxSendLayer {
+            boxed: self.boxed.clone(),
+        }
+    }
+}
+
+impl<In, Request, Response, Error> fmt::Debug for BoxSendLayer<In, Request, Response, Error> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxSendLayer").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::{service_fn, ServiceExt};
+
+    #[tokio::test]
+    async fn box_send_service_can_be_spawned() {
+        type Inner = BoxSendService<u32, u32, Infallible>;
+
+        let layer = BoxSendLayer::new(tower_async_layer::layer_fn(|inner: Inner| {
+            inner.map_request(|n: u32| n * 2)
+        }));
+        let service = layer.layer(BoxSendService::new(service_fn(|n: u32| async move {
+            Ok::<_, Infallible>(n + 1)
+        })));
+
+        let response = tokio::spawn({
+            let service = service.clone();
+            async move { service.call(1).await }
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.unwrap(), 3);
+    }
+}
@@ -0,0 +1,297 @@
+//! Object-safe services, to use services as trait objects.
+//!
+//! [`Service::call`] is an `async fn`, which makes the [`Service`] trait not object safe:
+//! `Box<dyn Service<Request>>` can't be used to store services of different types.
+//! [`DynService`] is an object-safe companion trait, implemented for every [`Service`],
+//! which boxes the future returned by the service.
+//!
+//! [`BoxService`] wraps a `dyn DynService` into a [`Service`] again, such that type erased
+//! services can be layered, cloned and stored like any other service. [`BoxLayer`] does the
+//! same for layers, which erases the type of a stack of layers.
+//!
+//! The boxed futures are not `Send`, as [`Service`] doesn't require the futures of its
+//! implementations to be `Send` either. Such services can't be used where their calls are
+//! spawned, such as when serving them with `tower-async-hyper`. With the `nightly` feature
+//! enabled, [`BoxSendService`] and [`BoxSendLayer`] erase the type of services whose futures
+//! are `Send`, and box them as `Send` futures.
+//!
+//! [`BoxSendService`]: crate::util::BoxSendService
+//! [`BoxSendLayer`]: crate::util::BoxSendLayer
+//! [`Service`]: crate::Service
+//! [`Service::call`]: crate::Service::call
+
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// A boxed future, as returned by [`DynService::call_dyn`].
+pub type DynFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// An object-safe version of [`Service`], implemented for every [`Service`].
+///
+/// See the [module docs](self) for more details.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use tower_async::{service_fn, util::DynService};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let services: Vec<Box<dyn DynService<u32, Response = u32, Error = Infallible>>> = vec![
+///     Box::new(service_fn(|n: u32| async move { Ok(n + 1) })),
+///     Box::new(service_fn(|n: u32| async move { Ok(n * 2) })),
+/// ];
+///
+/// let mut n = 3;
+/// for service in &services {
+///     n = service.call_dyn(n).await.unwrap();
+/// }
+/// assert_eq!(n, 8);
+/// # }
+/// ```
+///
+/// [`Service`]: crate::Service
+pub trait DynService<Request> {
+    /// Responses given by the service.
+    type Response;
+
+    /// Errors produced by the service.
+    type Error;
+
+    /// Process the request and return the response asynchronously, as a boxed future.
+    fn call_dyn<'a>(
+        &'a self,
+        request: Request,
+    ) -> DynFuture<'a, Result<Self::Response, Self::Error>>
+    where
+        Request: 'a;
+}
+
+impl<S, Request> DynService<Request> for S
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call_dyn<'a>(
+        &'a self,
+        request: Request,
+    ) -> DynFuture<'a, Result<Self::Response, Self::Error>>
+    where
+        Request: 'a,
+    {
+        Box::pin(self.call(request))
+    }
+}
+
+/// A type erased [`Service`].
+///
+/// Clones of a [`BoxService`] share the same service.
+///
+/// See the [module docs](self) for more details.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tower_async::{service_fn, util::BoxService, BoxError, Service, ServiceBuilder, ServiceExt};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// // services of different types, stored in a single collection
+/// let services: Vec<BoxService<u32, u32, BoxError>> = vec![
+///     service_fn(|n: u32| async move { Ok(n + 1) }).into_dyn(),
+///     ServiceBuilder::new()
+///         .timeout(Duration::from_secs(1))
+///         .service_fn(|n: u32| async move { Ok::<_, BoxError>(n * 2) })
+///         .into_dyn(),
+/// ];
+///
+/// assert_eq!(services[0].call(3).await.unwrap(), 4);
+/// assert_eq!(services[1].call(3).await.unwrap(), 6);
+/// # }
+/// ```
+///
+/// [`Service`]: crate::Service
+pub struct BoxService<Request, Response, Error> {
+    inner: Arc<dyn DynService<Request, Response = Response, Error = Error> + Send + Sync>,
+}
+
+impl<Request, Response, Error> BoxService<Request, Response, Error> {
+    /// Create a new [`BoxService`], erasing the type of the given service.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<Request, Response = Response, Error = Error> + Send + Sync + 'static,
+    {
+        BoxService {
+            inner: Arc::new(service),
+        }
+    }
+
+    /// Returns a [`BoxLayer`] that wraps services in a [`BoxService`].
+    pub fn layer<S>() -> BoxLayer<S, Request, Response, Error>
+    where
+        S: Service<Request, Response = Response, Error = Error> + Send + Sync + 'static,
+        Request: 'static,
+        Response: 'static,
+        Error: 'static,
+    {
+        BoxLayer::new(tower_async_layer::layer_fn(Self::new))
+    }
+}
+
+impl<Request, Response, Error> Service<Request> for BoxService<Request, Response, Error> {
+    type Response = Response;
+    type Error = Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        self.inner.call_dyn(request).await
+    }
+}
+
+impl<Request, Response, Error> Clone for BoxService<Request, Response, Error> {
+    fn clone(&self) -> Self {
+        BoxService {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<Request, Response, Error> fmt::Debug for BoxService<Request, Response, Error> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxService").finish_non_exhaustive()
+    }
+}
+
+/// A type erased [`Layer`], producing [`BoxService`]s.
+///
+/// This erases the type of a stack of layers, such as the middleware applied to the
+/// services of a [`MakeService`], which can then be selected or configured at runtime.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tower_async::{
+///     layer::util::Identity, service_fn, timeout::TimeoutLayer, util::{BoxLayer, BoxService},
+///     BoxError, Layer, Service, ServiceExt,
+/// };
+///
+/// type Handler = BoxService<u32, u32, BoxError>;
+///
+/// fn middleware(timeout: Option<Duration>) -> BoxLayer<Handler, u32, u32, BoxError> {
+///     match timeout {
+///         Some(timeout) => BoxLayer::new(TimeoutLayer::new(timeout)),
+///         None => BoxLayer::new(Identity::new()),
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let handler = service_fn(|n: u32| async move { Ok::<_, BoxError>(n + 1) }).into_dyn();
+/// let service = middleware(Some(Duration::from_secs(1))).layer(handler);
+/// assert_eq!(service.call(1).await.unwrap(), 2);
+/// # }
+/// ```
+///
+/// [`Layer`]: crate::Layer
+/// [`MakeService`]: crate::MakeService
+pub struct BoxLayer<In, Request, Response, Error> {
+    boxed: Arc<dyn Fn(In) -> BoxService<Request, Response, Error> + Send + Sync>,
+}
+
+impl<In, Request, Response, Error> BoxLayer<In, Request, Response, Error> {
+    /// Create a new [`BoxLayer`], erasing the type of the given layer.
+    pub fn new<L>(inner_layer: L) -> Self
+    where
+        L: Layer<In> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Response, Error = Error> + Send + Sync + 'static,
+    {
+        let layer = move |inner: In| BoxService::new(inner_layer.layer(inner));
+        BoxLayer {
+            boxed: Arc::new(layer),
+        }
+    }
+}
+
+impl<In, Request, Response, Error> Layer<In> for BoxLayer<In, Request, Response, Error> {
+    type Service = BoxService<Request, Response, Error>;
+
+    fn layer(&self, inner: In) -> Self::Service {
+        (self.boxed)(inner)
+    }
+}
+
+impl<In, Request, Response, Error> Clone for BoxLayer<In, Request, Response, Error> {
+    fn clone(&self) -> Self {
+        BoxLayer {
+            boxed: self.boxed.clone(),
+        }
+    }
+}
+
+impl<In, Request, Response, Error> fmt::Debug for BoxLayer<In, Request, Response, Error> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxLayer").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::{service_fn, MakeService, ServiceExt};
+
+    #[tokio::test]
+    async fn box_service_is_a_service() {
+        let service = service_fn(|n: u32| async move { Ok::<_, Infallible>(n + 1) })
+            .into_dyn()
+            .map_response(|n| n * 10);
+        assert_eq!(service.call(1).await.unwrap(), 20);
+
+        let cloned = BoxService::new(service);
+        assert_eq!(cloned.clone().call(2).await.unwrap(), 30);
+    }
+
+    #[tokio::test]
+    async fn boxed_make_service() {
+        // a make service producing type erased services,
+        // which is itself type erased as well
+        let make: BoxService<u32, BoxService<u32, u32, Infallible>, Infallible> =
+            service_fn(|offset: u32| async move {
+                Ok::<_, Infallible>(
+                    service_fn(move |n: u32| async move { Ok::<_, Infallible>(n + offset) })
+                        .into_dyn(),
+                )
+            })
+            .into_dyn();
+
+        let service = make.make_service(10).await.unwrap();
+        assert_eq!(service.call(1).await.unwrap(), 11);
+    }
+
+    #[tokio::test]
+    async fn box_layer() {
+        type Inner = BoxService<u32, u32, Infallible>;
+
+        let layer = BoxLayer::new(tower_async_layer::layer_fn(|inner: Inner| {
+            inner.map_request(|n: u32| n * 2)
+        }));
+        let service = layer
+            .clone()
+            .layer(service_fn(|n: u32| async move { Ok::<_, Infallible>(n + 1) }).into_dyn());
+        assert_eq!(service.call(1).await.unwrap(), 3);
+
+        let service =
+            BoxService::layer().layer(service_fn(
+                |n: u32| async move { Ok::<_, Infallible>(n + 1) },
+            ));
+        assert_eq!(service.call(1).await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Focuses on a single field of a request or response, such as the payload of an envelope.
+///
+/// Used by the [`map_request_payload`] and [`map_response_payload`] combinators to
+/// transform that field, while leaving the rest of the value untouched.
+///
+/// A lens is usually created by [`field_lens`], for fields with a [`Default`] value, or by
+/// [`lens`], for other fields.
+///
+/// [`map_request_payload`]: crate::util::ServiceExt::map_request_payload
+/// [`map_response_payload`]: crate::util::ServiceExt::map_response_payload
+pub trait Lens<T> {
+    /// The type of the field.
+    type Field;
+
+    /// Takes the field out of `target`.
+    ///
+    /// It is followed by a call to [`Lens::set`], so `target` can be left in any state.
+    fn get(&self, target: &mut T) -> Self::Field;
+
+    /// Sets the field of `target`.
+    fn set(&self, target: &mut T, field: Self::Field);
+}
+
+/// Creates a [`Lens`] from a function returning a mutable reference to a field.
+///
+/// The [`Default`] value of the field is used as a placeholder while it is transformed.
+///
+/// # Example
+///
+/// ```
+/// use tower_async::util::{field_lens, Lens};
+///
+/// struct Envelope {
+///     id: u64,
+///     payload: String,
+/// }
+///
+/// let payload = field_lens(|envelope: &mut Envelope| &mut envelope.payload);
+///
+/// let mut envelope = Envelope { id: 1, payload: "ping".to_owned() };
+/// let field = payload.get(&mut envelope);
+/// payload.set(&mut envelope, field.to_uppercase());
+/// assert_eq!(envelope.payload, "PING");
+/// ```
+pub fn field_lens<T, Field, F>(f: F) -> FieldLens<F>
+where
+    F: Fn(&mut T) -> &mut Field,
+    Field: Default,
+{
+    FieldLens { f }
+}
+
+/// A [`Lens`] created from a function returning a mutable reference to a field
+/// by [`field_lens`].
+#[derive(Clone, Copy)]
+pub struct FieldLens<F> {
+    f: F,
+}
+
+impl<F> fmt::Debug for FieldLens<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldLens")
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<T, Field, F> Lens<T> for FieldLens<F>
+where
+    F: Fn(&mut T) -> &mut Field,
+    Field: Default,
+{
+    type Field = Field;
+
+    fn get(&self, target: &mut T) -> Field {
+        std::mem::take((self.f)(target))
+    }
+
+    fn set(&self, target: &mut T, field: Field) {
+        *(self.f)(target) = field;
+    }
+}
+
+/// Creates a [`Lens`] from a pair of functions, taking the field out and setting it again.
+///
+/// # Example
+///
+/// ```
+/// use tower_async::util::{lens, Lens};
+///
+/// struct Envelope {
+///     id: u64,
+///     payload: Option<Vec<u8>>,
+/// }
+///
+/// let payload = lens(
+///     |envelope: &mut Envelope| envelope.payload.take().unwrap(),
+///     |envelope: &mut Envelope, payload| envelope.payload = Some(payload),
+/// );
+///
+/// let mut envelope = Envelope { id: 1, payload: Some(b"ping".to_vec()) };
+/// let mut field = payload.get(&mut envelope);
+/// field.reverse();
+/// payload.set(&mut envelope, field);
+/// assert_eq!(envelope.payload.unwrap(), b"gnip");
+/// ```
+pub fn lens<T, Field, G, S>(get: G, set: S) -> FnLens<G, S>
+where
+    G: Fn(&mut T) -> Field,
+    S: Fn(&mut T, Field),
+{
+    FnLens { get, set }
+}
+
+/// A [`Lens`] created from a pair of functions by [`lens`].
+#[derive(Clone, Copy)]
+pub struct FnLens<G, S> {
+    get: G,
+    set: S,
+}
+
+impl<G, S> fmt::Debug for FnLens<G, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FnLens")
+            .field("get", &format_args!("{}", std::any::type_name::<G>()))
+            .field("set", &format_args!("{}", std::any::type_name::<S>()))
+            .finish()
+    }
+}
+
+impl<T, Field, G, S> Lens<T> for FnLens<G, S>
+where
+    G: Fn(&mut T) -> Field,
+    S: Fn(&mut T, Field),
+{
+    type Field = Field;
+
+    fn get(&self, target: &mut T) -> Field {
+        (self.get)(target)
+    }
+
+    fn set(&self, target: &mut T, field: Field) {
+        (self.set)(target, field)
+    }
+}
+
+/// Service returned by the [`map_request_payload`] combinator.
+///
+/// [`map_request_payload`]: crate::util::ServiceExt::map_request_payload
+#[derive(Clone)]
+pub struct MapRequestPayload<S, L, F> {
+    inner: S,
+    lens: L,
+    f: F,
+}
+
+impl<S, L, F> fmt::Debug for MapRequestPayload<S, L, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapRequestPayload")
+            .field("inner", &self.inner)
+            .field("lens", &format_args!("{}", std::any::type_name::<L>()))
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, L, F> MapRequestPayload<S, L, F> {
+    /// Creates a new [`MapRequestPayload`] service.
+    pub fn new(inner: S, lens: L, f: F) -> Self {
+        MapRequestPayload { inner, lens, f }
+    }
+
+    /// Returns a new [`Layer`] that produces [`MapRequestPayload`] services.
+    ///
+    /// This is a convenience function that simply calls [`MapRequestPayloadLayer::new`].
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(lens: L, f: F) -> MapRequestPayloadLayer<L, F> {
+        MapRequestPayloadLayer { lens, f }
+    }
+}
+
+impl<S, L, F, Request> Service<Request> for MapRequestPayload<S, L, F>
+where
+    S: Service<Request>,
+    L: Lens<Request>,
+    F: Fn(L::Field) -> L::Field,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    #[inline]
+    async fn call(&self, mut request: Request) -> Result<Self::Response, Self::Error> {
+        let field = self.lens.get(&mut request);
+        self.lens.set(&mut request, (self.f)(field));
+        self.inner.call(request).await
+    }
+}
+
+/// A [`Layer`] that produces [`MapRequestPayload`] services.
+///
+/// [`Layer`]: tower_async_layer::Layer
+#[derive(Clone)]
+pub struct MapRequestPayloadLayer<L, F> {
+    lens: L,
+    f: F,
+}
+
+impl<L, F> fmt::Debug for MapRequestPayloadLayer<L, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapRequestPayloadLayer")
+            .field("lens", &format_args!("{}", std::any::type_name::<L>()))
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<L, F> MapRequestPayloadLayer<L, F> {
+    /// Creates a new [`MapRequestPayloadLayer`].
+    pub fn new(lens: L, f: F) -> Self {
+        MapRequestPayloadLayer { lens, f }
+    }
+}
+
+impl<S, L, F> Layer<S> for MapRequestPayloadLayer<L, F>
+where
+    L: Clone,
+    F: Clone,
+{
+    type Service = MapRequestPayload<S, L, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapRequestPayload {
+            inner,
+            lens: self.lens.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
+
+/// Service returned by the [`map_response_payload`] combinator.
+///
+/// [`map_response_payload`]: crate::util::ServiceExt::map_response_payload
+#[derive(Clone)]
+pub struct MapResponsePayload<S, L, F> {
+    inner: S,
+    lens: L,
+    f: F,
+}
+
+impl<S, L, F> fmt::Debug for MapResponsePayload<S, L, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapResponsePayload")
+            .field("inner", &self.inner)
+            .field("lens", &format_args!("{}", std::any::type_name::<L>()))
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, L, F> MapResponsePayload<S, L, F> {
+    /// Creates a new [`MapResponsePayload`] service.
+    pub fn new(inner: S, lens: L, f: F) -> Self {
+        MapResponsePayload { inner, lens, f }
+    }
+
+    /// Returns a new [`Layer`] that produces [`MapResponsePayload`] services.
+    ///
+    /// This is a convenience function that simply calls [`MapResponsePayloadLayer::new`].
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer(lens: L, f: F) -> MapResponsePayloadLayer<L, F> {
+        MapResponsePayloadLayer { lens, f }
+    }
+}
+
+impl<S, L, F, Request> Service<Request> for MapResponsePayload<S, L, F>
+where
+    S: Service<Request>,
+    L: Lens<S::Response>,
+    F: Fn(L::Field) -> L::Field,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    #[inline]
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let mut response = self.inner.call(request).await?;
+        let field = self.lens.get(&mut response);
+        self.lens.set(&mut response, (self.f)(field));
+        Ok(response)
+    }
+}
+
+/// A [`Layer`] that produces [`MapResponsePayload`] services.
+///
+/// [`Layer`]: tower_async_layer::Layer
+#[derive(Clone)]
+pub struct MapResponsePayloadLayer<L, F> {
+    lens: L,
+    f: F,
+}
+
+impl<L, F> fmt::Debug for MapResponsePayloadLayer<L, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapResponsePayloadLayer")
+            .field("lens", &format_args!("{}", std::any::type_name::<L>()))
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<L, F> MapResponsePayloadLayer<L, F> {
+    /// Creates a new [`MapResponsePayloadLayer`].
+    pub fn new(lens: L, f: F) -> Self {
+        MapResponsePayloadLayer { lens, f }
+    }
+}
+
+impl<S, L, F> Layer<S> for MapResponsePayloadLayer<L, F>
+where
+    L: Clone,
+    F: Clone,
+{
+    type Service = MapResponsePayload<S, L, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapResponsePayload {
+            inner,
+            lens: self.lens.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
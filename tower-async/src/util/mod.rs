@@ -1,14 +1,24 @@
 //! Various utility types and functions that are generally used with Tower.
 
 mod and_then;
+mod call_all;
+#[cfg(feature = "nightly")]
+mod dyn_send_service;
+mod dyn_service;
 mod either;
 
 mod map_err;
+mod map_payload;
 mod map_request;
 mod map_response;
 mod map_result;
+mod oneshot;
 
 mod service_fn;
+mod service_ref;
+#[cfg(all(feature = "util-tokio", feature = "nightly"))]
+mod spawn;
+mod steer;
 mod then;
 
 pub mod backoff;
@@ -16,16 +26,31 @@ pub mod rng;
 
 pub use self::{
     and_then::{AndThen, AndThenLayer},
+    call_all::{CallAll, CallAllUnordered},
+    dyn_service::{BoxLayer, BoxService, DynFuture, DynService},
     either::Either,
     map_err::{MapErr, MapErrLayer},
+    map_payload::{
+        field_lens, lens, FieldLens, FnLens, Lens, MapRequestPayload, MapRequestPayloadLayer,
+        MapResponsePayload, MapResponsePayloadLayer,
+    },
     map_request::{MapRequest, MapRequestLayer},
     map_response::{MapResponse, MapResponseLayer},
     map_result::{MapResult, MapResultLayer},
-    service_fn::{service_fn, ServiceFn},
+    oneshot::Oneshot,
+    service_fn::{service_fn, service_fn_with_state, ServiceFn, ServiceFnWithState},
+    service_ref::{ByRef, CloneRequest, ServiceRef},
+    steer::{Picker, Steer},
     then::{Then, ThenLayer},
 };
 
-use std::future::Future;
+#[cfg(feature = "nightly")]
+pub use self::dyn_send_service::{BoxSendLayer, BoxSendService};
+
+#[cfg(all(feature = "util-tokio", feature = "nightly"))]
+pub use self::spawn::{Spawn, SpawnLayer};
+
+use std::{future::Future, sync::Arc};
 
 use crate::layer::util::Identity;
 
@@ -43,6 +68,44 @@ pub trait ServiceExt<Request>: tower_async_service::Service<Request> {
         async move { self.call(req).await }
     }
 
+    /// Call a service shared through an [`Arc`] with the provided request.
+    ///
+    /// The returned future owns the [`Arc`], and therefore doesn't borrow the service,
+    /// such that it can be spawned onto a runtime, as long as it is `Send`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{convert::Infallible, sync::Arc};
+    /// use tower_async::{service_fn, ServiceExt};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let service = Arc::new(service_fn(|n: u32| async move { Ok::<_, Infallible>(n * 2) }));
+    ///
+    /// let handle = tokio::spawn(service.clone().call_owned(21));
+    /// assert_eq!(handle.await.unwrap(), Ok(42));
+    /// # }
+    /// ```
+    fn call_owned(
+        self: Arc<Self>,
+        req: Request,
+    ) -> impl std::future::Future<Output = Result<Self::Response, Self::Error>> {
+        async move { self.call(req).await }
+    }
+
+    /// Consume this `Service`, calling it with each request of the given stream.
+    ///
+    /// Returns a stream of the responses, in the order of the requests, calling the
+    /// service concurrently. See [`CallAll`] for more details.
+    fn call_all<S>(self, requests: S) -> CallAll<Self, S>
+    where
+        Self: Sized,
+        S: futures_core::Stream<Item = Request>,
+    {
+        CallAll::new(self, requests)
+    }
+
     /// Executes a new future after this service's future resolves.
     ///
     /// This method can be used to change the [`Response`] type of the service
@@ -471,6 +534,73 @@ pub trait ServiceExt<Request>: tower_async_service::Service<Request> {
         MapRequest::new(self, f)
     }
 
+    /// Transforms a single field of each request, such as the payload of an envelope,
+    /// before sending it to `self`.
+    ///
+    /// The field is selected by a [`Lens`], which is usually created using [`field_lens`]
+    /// or [`lens`]. Unlike [`map_request`], the function `f` only needs to know about the
+    /// field it transforms, which allows to reuse it for different request types.
+    ///
+    /// # Example
+    /// ```
+    /// use std::convert::Infallible;
+    /// use tower_async::{service_fn, Service, ServiceExt};
+    /// use tower_async::util::field_lens;
+    ///
+    /// struct Envelope {
+    ///     method: &'static str,
+    ///     payload: Vec<u8>,
+    /// }
+    ///
+    /// fn decrypt(payload: Vec<u8>) -> Vec<u8> {
+    ///     payload.into_iter().map(|b| b ^ 0x20).collect()
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let service = service_fn(|envelope: Envelope| async move {
+    ///     Ok::<_, Infallible>(String::from_utf8(envelope.payload).unwrap())
+    /// });
+    ///
+    /// let service = service.map_request_payload(
+    ///     field_lens(|envelope: &mut Envelope| &mut envelope.payload),
+    ///     decrypt,
+    /// );
+    ///
+    /// let response = service
+    ///     .call(Envelope { method: "echo", payload: b"HELLO".to_vec() })
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(response, "hello");
+    /// # }
+    /// ```
+    ///
+    /// [`map_request`]: ServiceExt::map_request
+    fn map_request_payload<L, F>(self, lens: L, f: F) -> MapRequestPayload<Self, L, F>
+    where
+        Self: Sized,
+        L: Lens<Request>,
+        F: Fn(L::Field) -> L::Field,
+    {
+        MapRequestPayload::new(self, lens, f)
+    }
+
+    /// Transforms a single field of each response of this service,
+    /// such as the payload of an envelope.
+    ///
+    /// The field is selected by a [`Lens`], which is usually created using [`field_lens`]
+    /// or [`lens`]. See [`map_request_payload`] for more details.
+    ///
+    /// [`map_request_payload`]: ServiceExt::map_request_payload
+    fn map_response_payload<L, F>(self, lens: L, f: F) -> MapResponsePayload<Self, L, F>
+    where
+        Self: Sized,
+        L: Lens<Self::Response>,
+        F: Fn(L::Field) -> L::Field,
+    {
+        MapResponsePayload::new(self, lens, f)
+    }
+
     /// Composes this service with a [`Filter`] that conditionally accepts or
     /// rejects requests based on a [predicate].
     ///
@@ -706,6 +836,30 @@ pub trait ServiceExt<Request>: tower_async_service::Service<Request> {
     {
         Then::new(self, f)
     }
+
+    /// Convert the service into a [`BoxService`], erasing its type.
+    ///
+    /// See [`BoxService`] for more details.
+    fn into_dyn(self) -> BoxService<Request, Self::Response, Self::Error>
+    where
+        Self: Sized + Send + Sync + 'static,
+    {
+        BoxService::new(self)
+    }
+
+    /// Run every call of the service on its own task, spawned on the Tokio runtime.
+    ///
+    /// The call runs to completion even if the caller stops polling it. The future of the
+    /// service must already be `Send` to be spawned.
+    ///
+    /// See [`Spawn`] for more details.
+    #[cfg(all(feature = "util-tokio", feature = "nightly"))]
+    fn spawn(self) -> Spawn<Self>
+    where
+        Self: Sized,
+    {
+        Spawn::new(self)
+    }
 }
 
 impl<T: ?Sized, Request> ServiceExt<Request> for T where T: tower_async_service::Service<Request> {}
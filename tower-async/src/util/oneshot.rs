@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tower_async_service::Service;
+
+use super::DynFuture;
+
+/// A future which owns a service, calling it once with a request.
+///
+/// Unlike the future returned by [`ServiceExt::oneshot`], [`Oneshot`] is a named type, such
+/// that it can be stored in a struct field. The call is boxed to make that possible, and as
+/// with [`DynFuture`], the boxed future is not `Send`. Use [`ServiceExt::oneshot`] or
+/// [`ServiceExt::call_owned`] instead to call a service from a spawned task.
+///
+/// Services shared through an [`Arc`] or [`Rc`] are services too, and can be called once
+/// using an `Oneshot<Arc<S>, Request>`, without giving up ownership of the service.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use tower_async::{service_fn, util::Oneshot, Service};
+///
+/// async fn double(n: u32) -> Result<u32, Infallible> {
+///     Ok(n * 2)
+/// }
+///
+/// struct Pending<S: Service<u32>> {
+///     response: Oneshot<S, u32>,
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let pending = Pending {
+///     response: Oneshot::new(service_fn(double), 21),
+/// };
+/// assert_eq!(pending.response.await, Ok(42));
+/// # }
+/// ```
+///
+/// [`ServiceExt::oneshot`]: crate::ServiceExt::oneshot
+/// [`ServiceExt::call_owned`]: crate::ServiceExt::call_owned
+/// [`Arc`]: std::sync::Arc
+/// [`Rc`]: std::rc::Rc
+pub struct Oneshot<S, Request>
+where
+    S: Service<Request>,
+{
+    future: DynFuture<'static, Result<S::Response, S::Error>>,
+    _marker: PhantomData<fn(S, Request)>,
+}
+
+impl<S, Request> Oneshot<S, Request>
+where
+    S: Service<Request> + 'static,
+    Request: 'static,
+{
+    /// Creates a new [`Oneshot`], calling the service with the request once polled.
+    pub fn new(service: S, request: Request) -> Self {
+        Oneshot {
+            future: Box::pin(async move { service.call(request).await }),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, Request> Future for Oneshot<S, Request>
+where
+    S: Service<Request>,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.future.as_mut().poll(cx)
+    }
+}
+
+impl<S, Request> fmt::Debug for Oneshot<S, Request>
+where
+    S: Service<Request>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Oneshot")
+            .field("service", &format_args!("{}", std::any::type_name::<S>()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        cell::Cell,
+        convert::Infallible,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{service_fn, ServiceBuilder};
+
+    #[tokio::test]
+    async fn shared_services() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let service = Arc::new(service_fn({
+            let calls = calls.clone();
+            move |n: u32| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move { Ok::<_, Infallible>(n * 2) }
+            }
+        }));
+        let layered = ServiceBuilder::new()
+            .map_response(|n: u32| n + 1)
+            .service(service.clone());
+
+        assert_eq!(Oneshot::new(service.clone(), 1).await, Ok(2));
+        assert_eq!(layered.call(2).await, Ok(5));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // services which aren't `Send` can be shared on the current thread
+        let count = Rc::new(Cell::new(0));
+        let service = Rc::new(service_fn({
+            let count = count.clone();
+            move |()| {
+                count.set(count.get() + 1);
+                async { Ok::<_, Infallible>(()) }
+            }
+        }));
+        Oneshot::new(service.clone(), ()).await.unwrap();
+        service.call(()).await.unwrap();
+        assert_eq!(count.get(), 2);
+    }
+}
@@ -72,3 +72,85 @@ where
         (self.f)(req).await
     }
 }
+
+/// Returns a new [`ServiceFnWithState`] with the given state and closure.
+///
+/// This lets you build a [`Service`] from an async function that takes a state
+/// and a request, and returns a [`Result`]. The state is cloned for every request,
+/// so it is typically cheap to clone, such as an [`Arc`] or a connection pool handle.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicUsize, Ordering},
+///     Arc,
+/// };
+/// use tower_async::{service_fn_with_state, BoxError, Service};
+///
+/// #[derive(Clone, Default)]
+/// struct AppState {
+///     hits: Arc<AtomicUsize>,
+/// }
+///
+/// async fn handle(state: AppState, name: &'static str) -> Result<String, BoxError> {
+///     let hits = state.hits.fetch_add(1, Ordering::SeqCst) + 1;
+///     Ok(format!("Hello, {name}! (#{hits})"))
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), BoxError> {
+/// let service = service_fn_with_state(AppState::default(), handle);
+///
+/// assert_eq!(service.call("World").await?, "Hello, World! (#1)");
+/// assert_eq!(service.call("Tower").await?, "Hello, Tower! (#2)");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Arc`]: std::sync::Arc
+pub fn service_fn_with_state<S, T>(state: S, f: T) -> ServiceFnWithState<S, T> {
+    ServiceFnWithState { state, f }
+}
+
+/// A [`Service`] implemented by a closure, which is called with a clone of a state.
+///
+/// See [`service_fn_with_state`] for more details.
+#[derive(Copy, Clone)]
+pub struct ServiceFnWithState<S, T> {
+    state: S,
+    f: T,
+}
+
+impl<S, T> ServiceFnWithState<S, T> {
+    /// Get a reference to the state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+}
+
+impl<S, T> fmt::Debug for ServiceFnWithState<S, T>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceFnWithState")
+            .field("state", &self.state)
+            .field("f", &format_args!("{}", std::any::type_name::<T>()))
+            .finish()
+    }
+}
+
+impl<S, T, F, Request, R, E> Service<Request> for ServiceFnWithState<S, T>
+where
+    S: Clone,
+    T: Fn(S, Request) -> F,
+    F: Future<Output = Result<R, E>>,
+{
+    type Response = R;
+    type Error = E;
+
+    async fn call(&self, req: Request) -> Result<Self::Response, Self::Error> {
+        (self.f)(self.state.clone(), req).await
+    }
+}
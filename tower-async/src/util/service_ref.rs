@@ -0,0 +1,252 @@
+//! Services which borrow their requests.
+//!
+//! A [`Service`] takes ownership of its requests, which forces pipelines that only inspect
+//! requests, such as validation, to clone them before every call. [`ServiceRef`] is the
+//! borrowing counterpart of [`Service`]: it is called with a reference to the request.
+//!
+//! [`ByRef`] bridges a [`ServiceRef`] into a [`Service`] of borrowed requests (`&Request`),
+//! or of [`Cow`] requests, such that the regular middleware can be layered on top of it.
+//! [`CloneRequest`] goes the other way, and turns a [`Service`] into a [`ServiceRef`] by
+//! cloning the requests, for the few services in a pipeline which need ownership.
+//!
+//! [`Service`]: crate::Service
+
+use std::{borrow::Cow, future::Future, sync::Arc};
+
+use tower_async_service::Service;
+
+/// A service called with a reference to its request.
+///
+/// See the [module docs](self) for more details.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tower_async::{util::{ByRef, ServiceRef}, BoxError, Service, ServiceBuilder};
+///
+/// struct Order {
+///     quantity: u32,
+/// }
+///
+/// struct ValidateQuantity;
+///
+/// impl ServiceRef<Order> for ValidateQuantity {
+///     type Response = ();
+///     type Error = BoxError;
+///
+///     async fn call_ref(&self, order: &Order) -> Result<(), BoxError> {
+///         if order.quantity == 0 {
+///             return Err("quantity must be positive".into());
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let validate = ServiceBuilder::new()
+///     .timeout(Duration::from_secs(1))
+///     .service(ByRef::new(ValidateQuantity));
+///
+/// // the order is only borrowed, and used afterwards
+/// let order = Order { quantity: 0 };
+/// assert!(validate.call(&order).await.is_err());
+/// assert_eq!(order.quantity, 0);
+/// # }
+/// ```
+pub trait ServiceRef<Request: ?Sized> {
+    /// Responses given by the service.
+    type Response;
+
+    /// Errors produced by the service.
+    type Error;
+
+    /// Process a borrowed request and return the response asynchronously.
+    fn call_ref(
+        &self,
+        request: &Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>>;
+}
+
+impl<S, Request> ServiceRef<Request> for &S
+where
+    S: ServiceRef<Request> + ?Sized,
+    Request: ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call_ref(
+        &self,
+        request: &Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        (**self).call_ref(request)
+    }
+}
+
+impl<S, Request> ServiceRef<Request> for Box<S>
+where
+    S: ServiceRef<Request> + ?Sized,
+    Request: ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call_ref(
+        &self,
+        request: &Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        (**self).call_ref(request)
+    }
+}
+
+impl<S, Request> ServiceRef<Request> for Arc<S>
+where
+    S: ServiceRef<Request> + ?Sized,
+    Request: ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn call_ref(
+        &self,
+        request: &Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        (**self).call_ref(request)
+    }
+}
+
+/// A [`Service`] of borrowed requests, calling a [`ServiceRef`].
+///
+/// Implements `Service<&Request>`, as well as `Service<Cow<Request>>` for requests which
+/// are borrowed by some callers and owned by others.
+///
+/// See the [module docs](self) for more details.
+///
+/// [`Service`]: crate::Service
+#[derive(Debug, Clone, Copy)]
+pub struct ByRef<S> {
+    inner: S,
+}
+
+impl<S> ByRef<S> {
+    /// Creates a new [`ByRef`], wrapping the given [`ServiceRef`].
+    pub fn new(inner: S) -> Self {
+        ByRef { inner }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<'a, S, Request> Service<&'a Request> for ByRef<S>
+where
+    S: ServiceRef<Request>,
+    Request: ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: &'a Request) -> Result<Self::Response, Self::Error> {
+        self.inner.call_ref(request).await
+    }
+}
+
+impl<'a, S, Request> Service<Cow<'a, Request>> for ByRef<S>
+where
+    S: ServiceRef<Request>,
+    Request: ToOwned + ?Sized,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Cow<'a, Request>) -> Result<Self::Response, Self::Error> {
+        self.inner.call_ref(&request).await
+    }
+}
+
+/// A [`ServiceRef`] calling a [`Service`] with a clone of the borrowed request.
+///
+/// See the [module docs](self) for more details.
+///
+/// [`Service`]: crate::Service
+#[derive(Debug, Clone, Copy)]
+pub struct CloneRequest<S> {
+    inner: S,
+}
+
+impl<S> CloneRequest<S> {
+    /// Creates a new [`CloneRequest`], wrapping the given [`Service`].
+    ///
+    /// [`Service`]: crate::Service
+    pub fn new(inner: S) -> Self {
+        CloneRequest { inner }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, Request> ServiceRef<Request> for CloneRequest<S>
+where
+    S: Service<Request>,
+    Request: Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call_ref(&self, request: &Request) -> Result<Self::Response, Self::Error> {
+        self.inner.call(request.clone()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::service_fn;
+
+    struct Len;
+
+    impl ServiceRef<str> for Len {
+        type Response = usize;
+        type Error = Infallible;
+
+        async fn call_ref(&self, request: &str) -> Result<usize, Infallible> {
+            Ok(request.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn borrowed_requests() {
+        let service = ByRef::new(Len);
+        let request = String::from("hello");
+        assert_eq!(service.call(request.as_str()).await, Ok(5));
+        assert_eq!(service.call(Cow::Borrowed("hi")).await, Ok(2));
+        assert_eq!(service.call(Cow::Owned(request)).await, Ok(5));
+
+        let service = ByRef::new(CloneRequest::new(service_fn(
+            |request: String| async move { Ok::<_, Infallible>(request + "!") },
+        )));
+        let request = String::from("hello");
+        assert_eq!(service.call(&request).await.unwrap(), "hello!");
+        assert_eq!(request, "hello");
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fmt, sync::Arc};
+
+use tower_async_layer::Layer;
+use tower_async_service::Service;
+
+/// Service returned by the [`spawn`] combinator.
+///
+/// Every call of the inner service runs on its own task, spawned on the Tokio runtime, while
+/// the caller waits for its result. The call is detached from the task of the caller: it makes
+/// progress on the runtime's worker threads, whether or not the caller polls it, and runs to
+/// completion even if the caller stops waiting for it, such as when a request is cancelled.
+///
+/// As the call is spawned with [`tokio::spawn`], the future returned by the inner service
+/// must already be `Send`, which is expressed with return type notation and therefore
+/// requires the `nightly` feature. [`Spawn`] doesn't make a service with a `!Send` future
+/// usable from multi-threaded code.
+///
+/// Panics of the inner service are propagated to the caller.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use tower_async::{service_fn, Service, ServiceExt};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let svc = service_fn(|n: u64| async move { Ok::<_, Infallible>(n * 2) }).spawn();
+///
+/// let handle = tokio::spawn(async move { svc.call(21).await });
+/// assert_eq!(handle.await.unwrap(), Ok(42));
+/// # }
+/// ```
+///
+/// [`spawn`]: crate::util::ServiceExt::spawn
+pub struct Spawn<S> {
+    inner: Arc<S>,
+}
+
+impl<S> Spawn<S> {
+    /// Creates a new [`Spawn`] service.
+    pub fn new(inner: S) -> Self {
+        Spawn {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Returns a new [`Layer`] that produces [`Spawn`] services.
+    ///
+    /// This is a convenience function that simply calls [`SpawnLayer::new`].
+    ///
+    /// [`Layer`]: tower_async_layer::Layer
+    pub fn layer() -> SpawnLayer {
+        SpawnLayer::new()
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, Request> Service<Request> for Spawn<S>
+where
+    S: Service<Request, call(): Send> + Send + Sync + 'static,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+    Request: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let inner = self.inner.clone();
+        match tokio::spawn(async move { inner.call(request).await }).await {
+            Ok(result) => result,
+            Err(err) => match err.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                // tasks are only cancelled by the runtime shutting down
+                Err(err) => panic!("spawned call did not complete: {err}"),
+            },
+        }
+    }
+}
+
+impl<S> Clone for Spawn<S> {
+    fn clone(&self) -> Self {
+        Spawn {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S> fmt::Debug for Spawn<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawn").field("inner", &self.inner).finish()
+    }
+}
+
+/// A [`Layer`] that produces [`Spawn`] services.
+///
+/// [`Layer`]: tower_async_layer::Layer
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpawnLayer {
+    _priv: (),
+}
+
+impl SpawnLayer {
+    /// Creates a new [`SpawnLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for SpawnLayer {
+    type Service = Spawn<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Spawn::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use crate::{service_fn, ServiceExt};
+
+    #[tokio::test]
+    async fn runs_calls_to_completion() {
+        let completed = Arc::new(AtomicBool::new(false));
+        let svc = service_fn({
+            let completed = completed.clone();
+            move |_: ()| {
+                let completed = completed.clone();
+                async move {
+                    tokio::task::yield_now().await;
+                    completed.store(true, Ordering::SeqCst);
+                    Ok::<_, Infallible>(())
+                }
+            }
+        })
+        .spawn();
+
+        // the caller stops waiting right away
+        let call = svc.call(());
+        let _ = futures_util::poll!(std::pin::pin!(call));
+
+        for _ in 0..10 {
+            if completed.load(Ordering::SeqCst) {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+        panic!("call was not completed");
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "inner panic")]
+    async fn propagates_panics() {
+        let svc = Spawn::new(service_fn(|_: ()| async {
+            panic!("inner panic");
+            #[allow(unreachable_code)]
+            Ok::<(), Infallible>(())
+        }));
+        let _ = svc.call(()).await;
+    }
+}
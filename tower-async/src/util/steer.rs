@@ -0,0 +1,116 @@
+use std::fmt;
+
+use tower_async_service::Service;
+
+/// Picks the service of a [`Steer`] that a request is dispatched to.
+///
+/// Implemented for closures of the form `Fn(&Request, &[S]) -> usize`.
+pub trait Picker<S, Request> {
+    /// Returns the index of the service in `services` to dispatch the request to.
+    fn pick(&self, request: &Request, services: &[S]) -> usize;
+}
+
+impl<S, F, Request> Picker<S, Request> for F
+where
+    F: Fn(&Request, &[S]) -> usize,
+{
+    fn pick(&self, request: &Request, services: &[S]) -> usize {
+        self(request, services)
+    }
+}
+
+/// Dispatches each request to one of many services, chosen by a [`Picker`].
+///
+/// This makes simple routers possible, such as routing requests by their path, without
+/// writing a dedicated service. All services must have the same request, response and error
+/// types, which can be achieved by type erasing them using [`BoxService`].
+///
+/// # Panics
+///
+/// Calling the service panics if the picker returns an index which is out of bounds.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use tower_async::{service_fn, util::{BoxService, Steer}, Service, ServiceExt};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let root = service_fn(|_: String| async { Ok::<_, Infallible>("root".to_owned()) });
+/// let users = service_fn(|path: String| async move { Ok(format!("user {}", &path[7..])) });
+/// let not_found = service_fn(|_: String| async { Ok("not found".to_owned()) });
+///
+/// let router = Steer::new(
+///     [root.into_dyn(), users.into_dyn(), not_found.into_dyn()],
+///     |path: &String, _: &[BoxService<String, String, Infallible>]| match path.as_str() {
+///         "/" => 0,
+///         path if path.starts_with("/users/") => 1,
+///         _ => 2,
+///     },
+/// );
+///
+/// assert_eq!(router.call("/".to_owned()).await.unwrap(), "root");
+/// assert_eq!(router.call("/users/42".to_owned()).await.unwrap(), "user 42");
+/// assert_eq!(router.call("/about".to_owned()).await.unwrap(), "not found");
+/// # }
+/// ```
+///
+/// [`BoxService`]: super::BoxService
+#[derive(Clone)]
+pub struct Steer<S, F> {
+    services: Vec<S>,
+    picker: F,
+}
+
+impl<S, F> Steer<S, F> {
+    /// Creates a new [`Steer`], dispatching requests to the given services using the picker.
+    pub fn new(services: impl IntoIterator<Item = S>, picker: F) -> Self {
+        Steer {
+            services: services.into_iter().collect(),
+            picker,
+        }
+    }
+
+    /// Returns the services requests are dispatched to.
+    pub fn services(&self) -> &[S] {
+        &self.services
+    }
+
+    /// Consume `self`, returning the services requests are dispatched to.
+    pub fn into_services(self) -> Vec<S> {
+        self.services
+    }
+}
+
+impl<S, F, Request> Service<Request> for Steer<S, F>
+where
+    S: Service<Request>,
+    F: Picker<S, Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, request: Request) -> Result<Self::Response, Self::Error> {
+        let index = self.picker.pick(&request, &self.services);
+        let service = self.services.get(index).unwrap_or_else(|| {
+            panic!(
+                "picker returned index {index} for {} services",
+                self.services.len()
+            )
+        });
+        service.call(request).await
+    }
+}
+
+impl<S, F> fmt::Debug for Steer<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Steer")
+            .field("services", &self.services)
+            .field("picker", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
@@ -4,7 +4,7 @@ mod support;
 
 use std::sync::{Arc, Mutex};
 
-use tower_async::retry::{Policy, RetryLayer};
+use tower_async::retry::{Policy, RetryDirective, RetryLayer};
 use tower_async_test::Builder;
 
 #[tokio::test(flavor = "current_thread")]
@@ -93,6 +93,60 @@ async fn retry_mutating_policy() {
         .expect_error("out of retries");
 }
 
+#[tokio::test(flavor = "current_thread")]
+async fn retry_max_retries() {
+    let _t = support::trace_init();
+
+    Builder::new("hello")
+        .send_error("retry 1")
+        .expect_request("hello")
+        .send_response("world")
+        .expect_request("hello")
+        .send_error("retry 2")
+        .expect_request("hello")
+        .test(RetryLayer::new(RetryForever).max_retries(2))
+        .await
+        .expect_error("max retries exceeded");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn retry_directive_never() {
+    let _t = support::trace_init();
+
+    Builder::new("create")
+        .send_error("retry me")
+        .expect_request("create")
+        .test(
+            RetryLayer::new(RetryErrors).with_override(|req: &&'static str| {
+                (*req == "create").then_some(RetryDirective::Never)
+            }),
+        )
+        .await
+        .expect_error("retry me");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn retry_directive_max_retries() {
+    let _t = support::trace_init();
+
+    Builder::new("hello")
+        .send_error("retry 1")
+        .expect_request("hello")
+        .send_error("retry 2")
+        .expect_request("hello")
+        .send_error("retry 3")
+        .expect_request("hello")
+        .send_error("retry 4")
+        .expect_request("hello")
+        .test(
+            RetryLayer::new(RetryForever)
+                .max_retries(1)
+                .with_override(|_: &&'static str| Some(RetryDirective::MaxRetries(3))),
+        )
+        .await
+        .expect_error("max retries exceeded");
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct RetryErrors;
 
@@ -198,3 +252,26 @@ where
         Some(*req)
     }
 }
+
+/// Test policy that never stops retrying, and turns the result into `"max retries exceeded"`
+/// once the retries are capped.
+#[derive(Debug, Clone)]
+struct RetryForever;
+
+impl<Req, Res, Error> Policy<Req, Res, Error> for RetryForever
+where
+    Req: Copy,
+    Error: From<&'static str>,
+{
+    async fn retry(&self, _: &mut Req, _: &mut Result<Res, Error>) -> bool {
+        true
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        Some(*req)
+    }
+
+    fn retries_exceeded(&self, _: &mut Req, result: &mut Result<Res, Error>) {
+        *result = Err("max retries exceeded".into());
+    }
+}